@@ -8,6 +8,79 @@ use core::fmt;
 use crate::pr_debug;
 use intrusive_linked_list::IntrusiveLinkedList;
 
+// the length of `FragmentationReport::orders` is this literal, not `MAX_ORDERS`: rustc ICEs
+// laying out an array sized by a const item of this generic_const_exprs crate in another crate
+macro_rules! max_orders {
+    () => {
+        16
+    };
+}
+
+/// orders a `FragmentationReport` holds, enough for MAX_ALLOCATABLE_BYTES up to 256 KiB
+pub const MAX_ORDERS: usize = max_orders!();
+
+/// Blocks of one order of the buddy allocator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrderStats {
+    pub block_size: usize,
+    pub free: usize,
+    pub used: usize,
+}
+
+/// Usage of the buddy allocator, to tune MAX_ALLOCATABLE_BYTES and the slab sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentationReport {
+    orders: [OrderStats; max_orders!()],
+    order_count: usize,
+    /// bytes taken from the range list allocator
+    pub total_bytes: usize,
+    /// bytes of the allocated blocks, rounded up to their order
+    pub allocated_bytes: usize,
+    pub free_bytes: usize,
+    /// largest block allocatable without taking more memory from the range list allocator
+    pub largest_free_block: usize,
+}
+
+impl FragmentationReport {
+    /// the orders, smallest block first
+    pub fn orders(&self) -> &[OrderStats] {
+        &self.orders[..self.order_count]
+    }
+
+    /// percentage of the free bytes outside of the largest free block, 0 without free bytes
+    pub fn external_fragmentation(&self) -> usize {
+        if self.free_bytes == 0 {
+            return 0;
+        }
+        (self.free_bytes - self.largest_free_block) * 100 / self.free_bytes
+    }
+}
+
+impl fmt::Display for FragmentationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "total: {:#x}, allocated: {:#x}, free: {:#x}",
+            self.total_bytes, self.allocated_bytes, self.free_bytes
+        )?;
+        writeln!(
+            f,
+            "largest free block: {:#x}, external fragmentation: {}%",
+            self.largest_free_block,
+            self.external_fragmentation()
+        )?;
+        writeln!(f, "{:>8} {:>8} {:>8}", "size", "free", "used")?;
+        for order in self.orders() {
+            writeln!(
+                f,
+                "{:>8} {:>8} {:>8}",
+                order.block_size, order.free, order.used
+            )?;
+        }
+        Ok(())
+    }
+}
+
 // Assumes that MAX_ALLOCATABLE_BYTES is a power of 2.
 pub(crate) struct BuddyAllocator<const MAX_ALLOCATABLE_BYTES: usize>
 where
@@ -18,6 +91,8 @@ where
     alloc_heap: Option<&'static (dyn Fn() -> Option<usize> + 'static)>,
     total_size: usize,
     allocated: usize,
+    // allocated blocks of each level
+    used: [usize; levels!(MAX_ALLOCATABLE_BYTES)],
 }
 
 impl<const MAX_ALLOCATABLE_BYTES: usize> fmt::Debug for BuddyAllocator<MAX_ALLOCATABLE_BYTES>
@@ -29,6 +104,7 @@ where
             .field("free_list", &self.free_list)
             .field("total_size", &self.total_size)
             .field("allocated", &self.allocated)
+            .field("used", &self.used)
             .finish()
     }
 }
@@ -57,6 +133,11 @@ where
         size.trailing_zeros() as usize - Self::MINIMUM_ALLOCATABLE_BYTES_LEVELS
     }
 
+    // the same for alloc and dealloc, so a block returns to the level it came from
+    fn required_size(layout: Layout) -> usize {
+        max(layout.size(), layout.align()).max(MINIMUM_ALLOCATABLE_BYTES)
+    }
+
     pub(crate) fn new(
         heap_allocator: Option<&'static (dyn Fn() -> Option<usize> + 'static)>,
     ) -> Self {
@@ -66,6 +147,7 @@ where
             alloc_heap: heap_allocator,
             total_size: 0,
             allocated: 0,
+            used: [0; levels!(MAX_ALLOCATABLE_BYTES)],
         }
     }
 
@@ -95,7 +177,7 @@ where
     // According to the Layout specification, align must be greater than 0 and a power of 2.
     pub(crate) fn alloc(&mut self, layout: Layout) -> Result<usize, &'static str> {
         pr_debug!("buddy_allocator: alloc before: {:#?}", self);
-        let required_size = max(layout.size(), layout.align()).max(MINIMUM_ALLOCATABLE_BYTES);
+        let required_size = Self::required_size(layout);
         let level = Self::size2level_next_power(required_size);
 
         let mut free_level = level;
@@ -126,6 +208,7 @@ where
 
         let ptr = self.free_list[level].pop().unwrap();
         self.allocated += required_size.next_power_of_two();
+        self.used[level] += 1;
         pr_debug!("buddy_allocator: alloc after: {:#?}", self);
         Ok(ptr)
     }
@@ -140,10 +223,11 @@ where
             layout.size()
         );
         let mut ptr = ptr;
-        let required_size = layout.size().max(MINIMUM_ALLOCATABLE_BYTES);
+        let required_size = Self::required_size(layout);
         let mut level = Self::size2level_next_power(required_size);
 
         self.allocated -= required_size.next_power_of_two();
+        self.used[level] -= 1;
 
         while level + 1 < Self::LEVELS {
             let block_size = Self::level2size(level);
@@ -165,6 +249,30 @@ where
         unsafe { self.free_list[level].push_back(ptr) };
         pr_debug!("buddy_allocator: dealloc after: {:#?}", self);
     }
+
+    pub(crate) fn fragmentation_report(&self) -> FragmentationReport {
+        let mut report = FragmentationReport {
+            orders: [OrderStats::default(); MAX_ORDERS],
+            order_count: Self::LEVELS,
+            total_bytes: self.total_size,
+            allocated_bytes: self.allocated,
+            free_bytes: 0,
+            largest_free_block: 0,
+        };
+        for level in 0..Self::LEVELS {
+            let order = OrderStats {
+                block_size: Self::level2size(level),
+                free: self.free_list[level].size(),
+                used: self.used[level],
+            };
+            report.free_bytes += order.free * order.block_size;
+            if order.free != 0 {
+                report.largest_free_block = order.block_size;
+            }
+            report.orders[level] = order;
+        }
+        report
+    }
 }
 
 #[cfg(test)]
@@ -446,4 +554,42 @@ mod tests {
         allocator.dealloc(ptr, layout);
         assert_eq!(allocator.allocated, 0);
     }
+
+    #[test]
+    fn test_fragmentation_report() {
+        let mut heap = AlignedHeap([0; HEAP_SIZE]);
+        let heap_addr = &mut heap.0 as *mut _ as usize;
+
+        let mut allocator = BuddyAllocator::<MAX_ALLOC>::new(None);
+        allocator.set_memory(heap_addr, HEAP_SIZE);
+        let report = allocator.fragmentation_report();
+        assert_eq!(report.free_bytes, HEAP_SIZE);
+        assert_eq!(report.largest_free_block, MAX_ALLOC);
+        assert_eq!(report.external_fragmentation(), 75);
+
+        // aligned beyond its size, taken from and returned to the 512 byte order
+        let layout = Layout::from_size_align(64, 512).unwrap();
+        let ptr = allocator.alloc(layout).unwrap();
+        let report = allocator.fragmentation_report();
+        let orders = report.orders();
+        let top_level = BuddyAllocator::<MAX_ALLOC>::LEVELS - 1;
+        assert_eq!(orders.len(), BuddyAllocator::<MAX_ALLOC>::LEVELS);
+        assert_eq!(orders[top_level].block_size, MAX_ALLOC);
+        assert_eq!(orders[top_level].free, 3);
+        assert_eq!(orders[top_level - 1].free, 1);
+        assert_eq!(orders[top_level - 2].free, 1);
+        assert_eq!(orders[top_level - 3].free, 1);
+        assert_eq!(orders[top_level - 3].used, 1);
+        assert_eq!(report.allocated_bytes, 512);
+        assert_eq!(report.free_bytes, HEAP_SIZE - 512);
+        assert_eq!(
+            report.external_fragmentation(),
+            (HEAP_SIZE - 512 - MAX_ALLOC) * 100 / (HEAP_SIZE - 512)
+        );
+
+        allocator.dealloc(ptr, layout);
+        let report = allocator.fragmentation_report();
+        assert!(report.orders().iter().all(|order| order.used == 0));
+        assert_eq!(report.orders()[top_level].free, 4);
+    }
 }
@@ -18,7 +18,10 @@ use crate::buddy_allocator::BuddyAllocator;
 use crate::range_list_allocator::MemoryBlock;
 use crate::range_list_allocator::MemoryRegions;
 
-#[cfg(all(not(feature = "debug-assertions"), not(test)))]
+pub use crate::buddy_allocator::FragmentationReport;
+pub use crate::buddy_allocator::OrderStats;
+
+#[cfg(all(not(feature = "log"), not(test)))]
 #[macro_export]
 macro_rules! pr_debug {
     ($($arg:tt)*) => {};
@@ -30,10 +33,11 @@ macro_rules! pr_debug {
     ($($arg:tt)*) => (std::println!("[info] (alloc) {} ({}:{})", format_args!($($arg)*), file!(), line!()));
 }
 
-#[cfg(all(feature = "debug-assertions", not(test)))]
+// trace rather than debug: the allocator state is dumped on every allocation
+#[cfg(all(feature = "log", not(test)))]
 #[macro_export]
 macro_rules! pr_debug {
-    ($($arg:tt)*) => {};
+    ($($arg:tt)*) => (::log::trace!(target: "alloc", $($arg)*));
 }
 
 #[macro_export]
@@ -153,8 +157,12 @@ where
 #[cfg(not(test))]
 #[alloc_error_handler]
 fn panic(layout: Layout) -> ! {
-    pr_debug!("allocator panicked!!: {:?}", layout);
-    loop {}
+    // the panic handler of the binary reports it and resets the system
+    panic!(
+        "memory allocation of {} bytes (align {}) failed",
+        layout.size(),
+        layout.align()
+    );
 }
 
 /// Initialize the global allocator state. Safe to call multiple times.
@@ -203,6 +211,68 @@ pub fn allocate_dynamic_reserved_region(
     }
 }
 
+/// Allocate the fixed range `[address, address + size)` after finalization,
+/// e.g. for payloads linked to run at a physical address.
+/// Release it with `dealloc` and a layout of `size`.
+pub fn allocate_at(address: usize, size: usize) -> Result<(), &'static str> {
+    let mut guard = GLOBAL_ALLOCATOR.range_list_allocator.lock();
+    let Some(block) = guard.get_mut() else {
+        return Err("allocator not initialized");
+    };
+    if !block.is_finalized() {
+        return Err("allocator not finalized");
+    }
+    block
+        .allocate_region_at(address, size)
+        .map(|_| ())
+        .ok_or("requested region is not free")
+}
+
+/// Allocate `size` bytes at the lowest free address `a` with `(a - offset) % align == 0`
+/// which does not overlap the `avoid` ranges, e.g. for a kernel with a load offset.
+/// Release it with `dealloc` and a layout of `size`.
+pub fn allocate_placed(
+    size: usize,
+    align: usize,
+    offset: usize,
+    avoid: &[(usize, usize)],
+) -> Result<usize, &'static str> {
+    if !align.is_power_of_two() {
+        return Err("alignment is not a power of two");
+    }
+    let mut guard = GLOBAL_ALLOCATOR.range_list_allocator.lock();
+    let Some(block) = guard.get_mut() else {
+        return Err("allocator not initialized");
+    };
+    if !block.is_finalized() {
+        return Err("allocator not finalized");
+    }
+    block
+        .allocate_region_placed(size, align, offset, avoid)
+        .ok_or("no suitable free region")
+}
+
+/// Copy the free regions as (address, size) into `regions` after finalization, e.g. to test
+/// them. Returns the number of regions, which may exceed `regions.len()`.
+/// Nothing may allocate while the copied regions are in use.
+pub fn free_regions(regions: &mut [(usize, usize)]) -> Result<usize, &'static str> {
+    let guard = GLOBAL_ALLOCATOR.range_list_allocator.lock();
+    let Some(block) = guard.get() else {
+        return Err("allocator not initialized");
+    };
+    if !block.is_finalized() {
+        return Err("allocator not finalized");
+    }
+    let mut count = 0;
+    for region in block.free_regions() {
+        if let Some(slot) = regions.get_mut(count) {
+            *slot = region;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
 /// Finalize the allocator by subtracting reserved regions and enabling allocation.
 /// Safe to call multiple times; after the first success, it’s a no-op.
 pub fn finalize() -> Result<(), &'static str> {
@@ -231,3 +301,12 @@ pub fn trim_for_boot(reserve_bytes: usize) -> Result<Vec<(usize, usize)>, &'stat
     }
     block.trim_for_boot(reserve_bytes)
 }
+
+/// Reports the usage of the buddy allocator per order and its fragmentation.
+pub fn fragmentation_report() -> Result<FragmentationReport, &'static str> {
+    let guard = GLOBAL_ALLOCATOR.buddy_allocator.lock();
+    let Some(buddy_allocator) = guard.get() else {
+        return Err("allocator not initialized");
+    };
+    Ok(buddy_allocator.fragmentation_report())
+}
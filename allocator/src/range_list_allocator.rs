@@ -500,6 +500,39 @@ impl MemoryBlock {
         None
     }
 
+    fn allocate_region_at_internal(&mut self, address: usize, size: usize) -> Option<usize> {
+        let end = address.checked_add(size)?;
+        let regions = &mut self.regions;
+        let i = (0..self.region_size as usize)
+            .find(|&i| regions[i].address <= address && end <= regions[i].end())?;
+        let before = address - regions[i].address;
+        let after = regions[i].end() - end;
+        match (before, after) {
+            (0, 0) => {
+                regions.copy_within(i + 1..self.region_size as usize, i);
+                self.region_size -= 1;
+            }
+            (0, _) => {
+                regions[i] = MemoryRegions {
+                    address: end,
+                    size: after,
+                }
+            }
+            (_, 0) => regions[i].size = before,
+            _ => {
+                regions.copy_within(i..self.region_size as usize, i + 1);
+                regions[i].size = before;
+                regions[i + 1] = MemoryRegions {
+                    address: end,
+                    size: after,
+                };
+                self.region_size += 1;
+            }
+        }
+        self.add_reserved_alloc_record(address, size);
+        Some(address)
+    }
+
     fn ensure_overflow_headroom(&mut self) {
         if self.region_size + 10 > self.region_capacity
             || self.reserved_region_size + 10 > self.reserved_region_capacity
@@ -661,6 +694,71 @@ impl MemoryBlock {
         self.allocate_region_internal(layout.size(), layout.align())
     }
 
+    /// allocate exactly `[address, address + size)`, `None` if any part of it is not free
+    pub fn allocate_region_at(&mut self, address: usize, size: usize) -> Option<usize> {
+        if !self.allocatable || size == 0 {
+            return None;
+        }
+        self.ensure_overflow_headroom();
+        self.allocate_region_at_internal(address, size)
+    }
+
+    /// Allocate `size` bytes at the lowest address `a` with `(a - offset) % align == 0`
+    /// which does not overlap any of the `avoid` ranges, given as (address, size).
+    /// `align` must be a power of two.
+    pub fn allocate_region_placed(
+        &mut self,
+        size: usize,
+        align: usize,
+        offset: usize,
+        avoid: &[(usize, usize)],
+    ) -> Option<usize> {
+        if !self.allocatable || size == 0 {
+            return None;
+        }
+        // the lowest placed address at or above `address`
+        let placed = |address: usize| {
+            address
+                .saturating_sub(offset)
+                .checked_next_multiple_of(align)?
+                .checked_add(offset)
+        };
+        let mut found = None;
+        'regions: for region in &self.regions[..self.region_size as usize] {
+            let Some(mut address) = placed(region.address) else {
+                break;
+            };
+            loop {
+                let Some(end) = address.checked_add(size).filter(|&end| end <= region.end()) else {
+                    continue 'regions;
+                };
+                let overlap = avoid
+                    .iter()
+                    .filter(|&&(a, s)| a < end && address < a.saturating_add(s))
+                    .map(|&(a, s)| a.saturating_add(s))
+                    .max();
+                let Some(overlap_end) = overlap else {
+                    found = Some(address);
+                    break 'regions;
+                };
+                // retry past the overlapping ranges
+                let Some(next) = placed(overlap_end) else {
+                    continue 'regions;
+                };
+                address = next;
+            }
+        }
+        self.ensure_overflow_headroom();
+        self.allocate_region_at_internal(found?, size)
+    }
+
+    /// the free regions as (address, size), in address order
+    pub fn free_regions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.regions[..self.region_size as usize]
+            .iter()
+            .map(|region| (region.address, region.size))
+    }
+
     pub fn deallocate_region(&mut self, ptr: usize, layout: Layout) {
         if !self.allocatable {
             return;
@@ -1256,6 +1354,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_allocate_region_placed() {
+        let mut allocator = MemoryBlock::init();
+        allocator
+            .add_region(&MemoryRegions {
+                address: 0x1000,
+                size: 0x400,
+            })
+            .unwrap();
+        allocator
+            .add_region(&MemoryRegions {
+                address: 0x4000,
+                size: 0x4000,
+            })
+            .unwrap();
+        allocator.check_regions().unwrap();
+
+        // the first region is too small once aligned
+        assert_eq!(
+            allocator.allocate_region_placed(0x400, 0x1000, 0x80, &[]),
+            Some(0x4080)
+        );
+        // the next aligned slot overlaps the avoided range
+        assert_eq!(
+            allocator.allocate_region_placed(0x400, 0x1000, 0x80, &[(0x5200, 0x100)]),
+            Some(0x6080)
+        );
+        // nothing fits
+        assert_eq!(
+            allocator.allocate_region_placed(0x2000, 0x1000, 0x80, &[]),
+            None
+        );
+        assert_eq!(
+            allocator.allocate_region_placed(0x100, 0x100, 0, &[]),
+            Some(0x1000)
+        );
+        allocator.deallocate_region(0x4080, Layout::from_size_align(0x400, 1).unwrap());
+        assert_eq!(
+            allocator.allocate_region_placed(0x400, 0x1000, 0x80, &[]),
+            Some(0x4080)
+        );
+        assert_eq!(
+            allocator.free_regions().collect::<Vec<_>>(),
+            [
+                (0x1100, 0x300),
+                (0x4000, 0x80),
+                (0x4480, 0x1c00),
+                (0x6480, 0x1b80)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allocate_region_at() {
+        let mut allocator = MemoryBlock::init();
+        allocator
+            .add_region(&MemoryRegions {
+                address: 0x1000,
+                size: 0x1000,
+            })
+            .unwrap();
+        allocator.check_regions().unwrap();
+
+        assert_eq!(allocator.allocate_region_at(0x1400, 0x200), Some(0x1400));
+        assert_eq!(allocator.region_size, 2);
+        assert_eq!(
+            allocator.regions[0],
+            MemoryRegions {
+                address: 0x1000,
+                size: 0x400
+            }
+        );
+        assert_eq!(
+            allocator.regions[1],
+            MemoryRegions {
+                address: 0x1600,
+                size: 0xA00
+            }
+        );
+        // overlaps the allocation above
+        assert_eq!(allocator.allocate_region_at(0x1500, 0x200), None);
+        assert_eq!(allocator.allocate_region_at(0x1000, 0x400), Some(0x1000));
+        assert_eq!(allocator.region_size, 1);
+
+        // merged with the free region after it
+        allocator.deallocate_region(0x1400, Layout::from_size_align(0x200, 1).unwrap());
+        assert_eq!(
+            allocator.regions[0],
+            MemoryRegions {
+                address: 0x1400,
+                size: 0xC00
+            }
+        );
+    }
+
     #[test]
     fn test_allocate_region_with_alignment() {
         let mut allocator = MemoryBlock::init();
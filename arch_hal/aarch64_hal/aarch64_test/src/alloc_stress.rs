@@ -0,0 +1,343 @@
+//! Randomized alloc/free/realloc sequences against an allocator on the target
+//!
+//! Every live block is filled with a pattern of its own. Each operation checks that a new
+//! block is aligned and does not overlap a live one, and that the pattern of a block is
+//! intact before it is freed or reallocated, which catches a block handed out twice and a
+//! write past the end of a neighbour. The sequence only depends on the seed, so a failure
+//! is reproduced by running the same seed again.
+//!
+//! ```ignore
+//! #[test_case]
+//! fn global_allocator_survives_stress() -> Result<(), StressError> {
+//!     let report = alloc_stress::run(&GlobalAllocator, &StressConfig::new(0x1234))?;
+//!     println!("{:?}", report);
+//!     Ok(())
+//! }
+//! ```
+
+use core::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::fmt;
+
+use crate::rng::XorShift64;
+
+const MAX_LIVE: usize = 256;
+
+/// `GlobalAlloc` of the `alloc` crate: the `#[global_allocator]` of the binary
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalAllocator;
+
+unsafe impl GlobalAlloc for GlobalAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc::alloc::alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { alloc::alloc::dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        unsafe { alloc::alloc::realloc(ptr, layout, new_size) }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StressConfig {
+    pub seed: u64,
+    pub operations: usize,
+    /// blocks alive at the same time, at most 256
+    pub max_live: usize,
+    /// block sizes are picked from 1 to `max_size`, small ones more often
+    pub max_size: usize,
+    /// alignments are picked from 1 to `max_align`, a power of two
+    pub max_align: usize,
+    /// an allocation which fails is an error instead of being skipped
+    pub fail_on_oom: bool,
+}
+
+impl StressConfig {
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            operations: 10_000,
+            max_live: 64,
+            max_size: 64 * 1024,
+            max_align: 4096,
+            fail_on_oom: false,
+        }
+    }
+}
+
+/// What a run did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StressReport {
+    pub allocations: usize,
+    pub deallocations: usize,
+    pub reallocations: usize,
+    /// allocations the allocator refused
+    pub out_of_memory: usize,
+    /// most bytes alive at the same time
+    pub peak_bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StressErrorKind {
+    /// the allocator returned a pointer which is not aligned for the layout
+    Misaligned {
+        address: usize,
+        align: usize,
+    },
+    /// the new block overlaps the live block at `other`
+    Overlap {
+        address: usize,
+        size: usize,
+        other: usize,
+        other_size: usize,
+    },
+    /// the pattern of the block changed at `offset` while it was alive
+    Corrupted {
+        address: usize,
+        offset: usize,
+    },
+    OutOfMemory {
+        size: usize,
+        align: usize,
+    },
+}
+
+/// A failed check, with the seed and the operation to reproduce it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StressError {
+    pub seed: u64,
+    pub operation: usize,
+    pub kind: StressErrorKind,
+}
+
+impl fmt::Display for StressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at operation {} of seed {:#x}",
+            self.kind, self.operation, self.seed
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Block {
+    address: usize,
+    layout: Layout,
+    // first byte of the pattern
+    tag: u8,
+}
+
+impl Block {
+    fn bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.address as *const u8, self.layout.size()) }
+    }
+
+    fn fill(&self, from: usize) {
+        let bytes =
+            unsafe { core::slice::from_raw_parts_mut(self.address as *mut u8, self.layout.size()) };
+        for (i, byte) in bytes.iter_mut().enumerate().skip(from) {
+            *byte = pattern(self.tag, i);
+        }
+    }
+
+    // offset of the first byte below `len` which does not match the pattern
+    fn corrupted(&self, len: usize) -> Option<usize> {
+        self.bytes()[..len]
+            .iter()
+            .enumerate()
+            .find(|&(i, &byte)| byte != pattern(self.tag, i))
+            .map(|(i, _)| i)
+    }
+}
+
+fn pattern(tag: u8, offset: usize) -> u8 {
+    tag.wrapping_add((offset as u8).wrapping_mul(31))
+}
+
+struct Stress<'a, A: GlobalAlloc> {
+    allocator: &'a A,
+    config: &'a StressConfig,
+    rng: XorShift64,
+    live: [Option<Block>; MAX_LIVE],
+    live_bytes: usize,
+    report: StressReport,
+    operation: usize,
+}
+
+impl<A: GlobalAlloc> Stress<'_, A> {
+    fn error(&self, kind: StressErrorKind) -> StressError {
+        StressError {
+            seed: self.config.seed,
+            operation: self.operation,
+            kind,
+        }
+    }
+
+    // sizes spread over the powers of two, so small blocks are as frequent as large ones
+    fn random_size(&mut self) -> usize {
+        let max_shift = usize::BITS - self.config.max_size.max(1).leading_zeros();
+        let shift = self.rng.range(0, max_shift as usize);
+        self.rng
+            .range(1 << shift, (2 << shift).min(self.config.max_size + 1))
+            .max(1)
+    }
+
+    fn random_align(&mut self) -> usize {
+        let max_shift = self.config.max_align.max(1).trailing_zeros() as usize;
+        1 << self.rng.range(0, max_shift + 1)
+    }
+
+    fn check_new(&self, address: usize, layout: Layout, skip: usize) -> Result<(), StressError> {
+        if !address.is_multiple_of(layout.align()) {
+            return Err(self.error(StressErrorKind::Misaligned {
+                address,
+                align: layout.align(),
+            }));
+        }
+        let end = address + layout.size();
+        for (i, other) in self.live.iter().enumerate() {
+            let Some(other) = other.filter(|_| i != skip) else {
+                continue;
+            };
+            if address < other.address + other.layout.size() && other.address < end {
+                return Err(self.error(StressErrorKind::Overlap {
+                    address,
+                    size: layout.size(),
+                    other: other.address,
+                    other_size: other.layout.size(),
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_pattern(&self, block: &Block, len: usize) -> Result<(), StressError> {
+        match block.corrupted(len) {
+            Some(offset) => Err(self.error(StressErrorKind::Corrupted {
+                address: block.address,
+                offset,
+            })),
+            None => Ok(()),
+        }
+    }
+
+    fn out_of_memory(&mut self, layout: Layout) -> Result<(), StressError> {
+        if self.config.fail_on_oom {
+            return Err(self.error(StressErrorKind::OutOfMemory {
+                size: layout.size(),
+                align: layout.align(),
+            }));
+        }
+        self.report.out_of_memory += 1;
+        Ok(())
+    }
+
+    fn allocate(&mut self, slot: usize) -> Result<(), StressError> {
+        let size = self.random_size();
+        let align = self.random_align();
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let address = unsafe { self.allocator.alloc(layout) } as usize;
+        if address == 0 {
+            return self.out_of_memory(layout);
+        }
+        self.check_new(address, layout, MAX_LIVE)?;
+        let block = Block {
+            address,
+            layout,
+            tag: self.rng.next_u32() as u8,
+        };
+        block.fill(0);
+        self.live[slot] = Some(block);
+        self.live_bytes += size;
+        self.report.allocations += 1;
+        Ok(())
+    }
+
+    fn deallocate(&mut self, slot: usize) -> Result<(), StressError> {
+        let block = self.live[slot].take().unwrap();
+        self.check_pattern(&block, block.layout.size())?;
+        unsafe {
+            self.allocator
+                .dealloc(block.address as *mut u8, block.layout)
+        };
+        self.live_bytes -= block.layout.size();
+        self.report.deallocations += 1;
+        Ok(())
+    }
+
+    fn reallocate(&mut self, slot: usize) -> Result<(), StressError> {
+        let block = self.live[slot].unwrap();
+        self.check_pattern(&block, block.layout.size())?;
+        let new_size = self.random_size();
+        let address = unsafe {
+            self.allocator
+                .realloc(block.address as *mut u8, block.layout, new_size)
+        } as usize;
+        let layout = Layout::from_size_align(new_size, block.layout.align()).unwrap();
+        if address == 0 {
+            // the old block is still alive
+            return self.out_of_memory(layout);
+        }
+        self.check_new(address, layout, slot)?;
+        let moved = Block {
+            address,
+            layout,
+            tag: block.tag,
+        };
+        let kept = block.layout.size().min(new_size);
+        self.check_pattern(&moved, kept)?;
+        moved.fill(kept);
+        self.live[slot] = Some(moved);
+        self.live_bytes = self.live_bytes - block.layout.size() + new_size;
+        self.report.reallocations += 1;
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<(), StressError> {
+        let slot = self.rng.range(0, self.config.max_live.clamp(1, MAX_LIVE));
+        match self.live[slot] {
+            None => self.allocate(slot)?,
+            Some(_) if self.rng.chance(30) => self.reallocate(slot)?,
+            Some(_) => self.deallocate(slot)?,
+        }
+        self.report.peak_bytes = self.report.peak_bytes.max(self.live_bytes);
+        Ok(())
+    }
+
+    // free what is left, checking it once more
+    fn release(&mut self) -> Result<(), StressError> {
+        for slot in 0..MAX_LIVE {
+            if self.live[slot].is_some() {
+                self.deallocate(slot)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run `config.operations` random operations on `allocator`. Every block is freed again,
+/// unless a check failed and the allocator state is suspect anyway
+pub fn run<A: GlobalAlloc>(
+    allocator: &A,
+    config: &StressConfig,
+) -> Result<StressReport, StressError> {
+    let mut stress = Stress {
+        allocator,
+        config,
+        rng: XorShift64::new(config.seed),
+        live: [None; MAX_LIVE],
+        live_bytes: 0,
+        report: StressReport::default(),
+        operation: 0,
+    };
+    while stress.operation < config.operations {
+        stress.step()?;
+        stress.operation += 1;
+    }
+    stress.release()?;
+    Ok(stress.report)
+}
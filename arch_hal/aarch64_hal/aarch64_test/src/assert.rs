@@ -0,0 +1,209 @@
+//! Assertions for on-target tests which report the machine state on failure
+//!
+//! `assert_hw!`, `assert_eq_hw!` and `assert_ne_hw!` behave like their `core` counterparts,
+//! but a failure prints the failing expression, its location, a snapshot of the registers
+//! at the assertion and, inside an exception handler, the syndrome, fault address and
+//! registers of the exception. The run then ends with `EXIT_ASSERTION_FAILED` instead of
+//! going through the panic handler, so the host can tell an assertion from a panic.
+//!
+//! ```ignore
+//! assert_hw!(status & READY != 0, "device not ready: {:#x}", status);
+//! assert_eq_hw!(block[510..], [0x55, 0xAA]);
+//! ```
+
+use core::arch::asm;
+use core::fmt;
+use core::fmt::Write;
+
+use crate::semihosting::HostConsole;
+use crate::test_runner::test_aborted;
+use cpu::exception::TrapFrame;
+
+/// Exit code of a run ended by a failed assertion
+pub const EXIT_ASSERTION_FAILED: u32 = 3;
+
+/// Registers at the failed assertion
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterSnapshot {
+    /// x19 to x30, the callee-saved registers, frame pointer and link register
+    pub x19_x30: [u64; 12],
+    pub sp: u64,
+    pub nzcv: u64,
+    pub daif: u64,
+    pub current_el: u64,
+}
+
+impl RegisterSnapshot {
+    /// The registers of the caller, which this is inlined into
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut x19_x30 = [0u64; 12];
+        let (sp, nzcv, daif, current_el): (u64, u64, u64, u64);
+        unsafe {
+            asm!(
+                "stp x19, x20, [{regs}, #0]",
+                "stp x21, x22, [{regs}, #16]",
+                "stp x23, x24, [{regs}, #32]",
+                "stp x25, x26, [{regs}, #48]",
+                "stp x27, x28, [{regs}, #64]",
+                "stp x29, x30, [{regs}, #80]",
+                "mov {sp}, sp",
+                "mrs {nzcv}, nzcv",
+                "mrs {daif}, daif",
+                "mrs {el}, currentel",
+                regs = in(reg) x19_x30.as_mut_ptr(),
+                sp = out(reg) sp,
+                nzcv = out(reg) nzcv,
+                daif = out(reg) daif,
+                el = out(reg) current_el,
+                options(nostack, preserves_flags)
+            );
+        }
+        Self {
+            x19_x30,
+            sp,
+            nzcv,
+            daif,
+            current_el: current_el >> 2,
+        }
+    }
+}
+
+impl fmt::Display for RegisterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "registers: EL{}, sp: {:#x}, nzcv: {:#x}, daif: {:#x}",
+            self.current_el, self.sp, self.nzcv, self.daif
+        )?;
+        for (i, chunk) in self.x19_x30.chunks(4).enumerate() {
+            for (j, x) in chunk.iter().enumerate() {
+                write!(f, "x{:<2}: {:#018x}  ", 19 + i * 4 + j, x)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_frame(w: &mut impl Write, frame: &TrapFrame) -> fmt::Result {
+    writeln!(
+        w,
+        "in exception: esr: {:#x} ({:?}), far: {:#x}, elr: {:#x}, spsr: {:#x}",
+        frame.esr,
+        frame.exception_class(),
+        frame.far,
+        frame.elr,
+        frame.spsr
+    )?;
+    for (i, chunk) in frame.x.chunks(4).enumerate() {
+        for (j, x) in chunk.iter().enumerate() {
+            write!(w, "x{:<2}: {:#018x}  ", i * 4 + j, x)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[doc(hidden)]
+#[cold]
+pub fn assertion_failed(
+    message: fmt::Arguments,
+    file: &str,
+    line: u32,
+    registers: &RegisterSnapshot,
+) -> ! {
+    let mut console = HostConsole;
+    let _ = writeln!(
+        console,
+        "\nassertion failed at {}:{}: {}",
+        file, line, message
+    );
+    let _ = write!(console, "{}", registers);
+    if let Some(frame) = cpu::exception::current_frame() {
+        let _ = write_frame(&mut console, &frame);
+    }
+    test_aborted("assertion failed", EXIT_ASSERTION_FAILED)
+}
+
+/// `assert!` reporting the registers, and the exception being handled, on failure
+#[macro_export]
+macro_rules! assert_hw {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::assert::assertion_failed(
+                format_args!("{}", stringify!($cond)),
+                file!(),
+                line!(),
+                &$crate::assert::RegisterSnapshot::capture(),
+            );
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::assert::assertion_failed(
+                format_args!("{}: {}", stringify!($cond), format_args!($($arg)+)),
+                file!(),
+                line!(),
+                &$crate::assert::RegisterSnapshot::capture(),
+            );
+        }
+    };
+}
+
+/// `assert_eq!` reporting the registers, and the exception being handled, on failure
+#[macro_export]
+macro_rules! assert_eq_hw {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::assert_eq_hw!($left, $right, "")
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left == *right) {
+                    $crate::assert::assertion_failed(
+                        format_args!(
+                            "{} == {}\n  left: {:?}\n right: {:?}\n{}",
+                            stringify!($left),
+                            stringify!($right),
+                            left,
+                            right,
+                            format_args!($($arg)+)
+                        ),
+                        file!(),
+                        line!(),
+                        &$crate::assert::RegisterSnapshot::capture(),
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// `assert_ne!` reporting the registers, and the exception being handled, on failure
+#[macro_export]
+macro_rules! assert_ne_hw {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::assert_ne_hw!($left, $right, "")
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left == *right {
+                    $crate::assert::assertion_failed(
+                        format_args!(
+                            "{} != {}\n  both: {:?}\n{}",
+                            stringify!($left),
+                            stringify!($right),
+                            left,
+                            format_args!($($arg)+)
+                        ),
+                        file!(),
+                        line!(),
+                        &$crate::assert::RegisterSnapshot::capture(),
+                    );
+                }
+            }
+        }
+    };
+}
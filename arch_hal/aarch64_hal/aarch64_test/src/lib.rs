@@ -4,6 +4,18 @@ extern crate alloc;
 
 use core::arch::asm;
 
+pub mod alloc_stress;
+pub mod assert;
+pub mod rng;
+pub mod semihosting;
+mod test_runner;
+pub mod watchdog;
+
+pub use test_runner::TestCase;
+pub use test_runner::TestResult;
+pub use test_runner::test_panicked;
+pub use test_runner::test_runner;
+
 mod allocator {
     use core::alloc::GlobalAlloc;
     use core::alloc::Layout;
@@ -105,4 +117,4 @@ pub extern "C" fn exit_with_code(code: u32) -> ! {
             options(noreturn)
         );
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,60 @@
+//! Deterministic pseudo random numbers for randomized tests
+//!
+//! A failing run is reproduced by running it again with the seed it printed.
+
+/// xorshift64 (Marsaglia), not for anything which needs real randomness
+#[derive(Debug, Clone)]
+pub struct XorShift64 {
+    state: u64,
+}
+
+impl XorShift64 {
+    /// A zero seed, which xorshift cannot leave, is replaced by a fixed one
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    /// Seed from the physical count, print it to reproduce the run
+    pub fn from_counter() -> Self {
+        Self::new(cpu::timer::counter())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// A value in `low..high`, `low` when the range is empty
+    pub fn range(&mut self, low: usize, high: usize) -> usize {
+        if high <= low {
+            return low;
+        }
+        low + (self.next_u64() % (high - low) as u64) as usize
+    }
+
+    /// true with a probability of `percent` / 100
+    pub fn chance(&mut self, percent: u32) -> bool {
+        self.next_u64() % 100 < u64::from(percent)
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Semihosting calls to the QEMU host, which is run with `-semihosting-config enable=on`
+//!
+//! Besides the console, tests load their fixtures (DTBs, ELF files, disk images) from the
+//! host with `HostFile` instead of baking them into the binary. Relative paths are resolved
+//! against the directory QEMU was started in.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::fmt;
+
+const SYS_OPEN: u64 = 0x01;
+const SYS_CLOSE: u64 = 0x02;
+const SYS_WRITE0: u64 = 0x04;
+const SYS_WRITE: u64 = 0x05;
+const SYS_READ: u64 = 0x06;
+const SYS_SEEK: u64 = 0x0A;
+const SYS_FLEN: u64 = 0x0C;
+const SYS_ERRNO: u64 = 0x13;
+
+/// Perform semihosting operation `op` with the parameter block at `param`
+///
+/// # Safety
+/// `param` is the parameter block `op` expects, or the value itself for the few operations
+/// which take one
+pub unsafe fn call(op: u64, param: usize) -> isize {
+    let result: isize;
+    unsafe {
+        asm!(
+            "hlt #0xf000",
+            inout("x0") op as isize => result,
+            in("x1") param,
+            options(nostack)
+        );
+    }
+    result
+}
+
+/// Write `s` to the host console with SYS_WRITE0, a NUL in `s` is dropped
+pub fn write_str(s: &str) {
+    let mut chunk = [0u8; 64];
+    let mut bytes = s.bytes().filter(|&byte| byte != 0).peekable();
+    while bytes.peek().is_some() {
+        let mut len = 0;
+        for byte in bytes.by_ref().take(chunk.len() - 1) {
+            chunk[len] = byte;
+            len += 1;
+        }
+        chunk[len] = 0;
+        unsafe { call(SYS_WRITE0, chunk.as_ptr() as usize) };
+    }
+}
+
+/// `fmt::Write` to the host console
+pub struct HostConsole;
+
+impl fmt::Write for HostConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_str(s);
+        Ok(())
+    }
+}
+
+/// A failed file operation, with the host `errno`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostError {
+    pub errno: isize,
+}
+
+impl HostError {
+    fn last() -> Self {
+        Self {
+            errno: unsafe { call(SYS_ERRNO, 0) },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    Read,
+    /// create or truncate
+    Write,
+    /// create or append
+    Append,
+}
+
+impl OpenMode {
+    // the binary `fopen` modes "rb", "wb" and "ab"
+    fn raw(self) -> usize {
+        match self {
+            Self::Read => 1,
+            Self::Write => 5,
+            Self::Append => 9,
+        }
+    }
+}
+
+/// A file of the host, closed on drop
+#[derive(Debug)]
+pub struct HostFile {
+    handle: usize,
+}
+
+impl HostFile {
+    pub fn open(path: &str, mode: OpenMode) -> Result<Self, HostError> {
+        let mut name = Vec::with_capacity(path.len() + 1);
+        name.extend_from_slice(path.as_bytes());
+        name.push(0);
+        let params = [name.as_ptr() as usize, mode.raw(), path.len()];
+        match unsafe { call(SYS_OPEN, params.as_ptr() as usize) } {
+            -1 => Err(HostError::last()),
+            handle => Ok(Self {
+                handle: handle as usize,
+            }),
+        }
+    }
+
+    pub fn len(&self) -> Result<usize, HostError> {
+        let params = [self.handle];
+        match unsafe { call(SYS_FLEN, params.as_ptr() as usize) } {
+            -1 => Err(HostError::last()),
+            len => Ok(len as usize),
+        }
+    }
+
+    pub fn is_empty(&self) -> Result<bool, HostError> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Move to the absolute position `offset`
+    pub fn seek(&self, offset: usize) -> Result<(), HostError> {
+        let params = [self.handle, offset];
+        match unsafe { call(SYS_SEEK, params.as_ptr() as usize) } {
+            0 => Ok(()),
+            _ => Err(HostError::last()),
+        }
+    }
+
+    /// Read up to `buf.len()` bytes, returns the bytes read, 0 at the end of the file
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, HostError> {
+        let params = [self.handle, buf.as_mut_ptr() as usize, buf.len()];
+        // the result is the number of bytes *not* read
+        match unsafe { call(SYS_READ, params.as_ptr() as usize) } {
+            not_read if (0..=buf.len() as isize).contains(&not_read) => {
+                Ok(buf.len() - not_read as usize)
+            }
+            _ => Err(HostError::last()),
+        }
+    }
+
+    /// The rest of the file
+    pub fn read_to_end(&self) -> Result<Vec<u8>, HostError> {
+        let mut data = vec![0u8; self.len()?];
+        let mut filled = 0;
+        while filled < data.len() {
+            match self.read(&mut data[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        data.truncate(filled);
+        Ok(data)
+    }
+
+    pub fn write_all(&self, mut buf: &[u8]) -> Result<(), HostError> {
+        while !buf.is_empty() {
+            let params = [self.handle, buf.as_ptr() as usize, buf.len()];
+            // the result is the number of bytes *not* written
+            match unsafe { call(SYS_WRITE, params.as_ptr() as usize) } {
+                not_written if (0..buf.len() as isize).contains(&not_written) => {
+                    buf = &buf[buf.len() - not_written as usize..];
+                }
+                _ => return Err(HostError::last()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for HostFile {
+    fn drop(&mut self) {
+        let params = [self.handle];
+        unsafe { call(SYS_CLOSE, params.as_ptr() as usize) };
+    }
+}
+
+/// The content of the host file `path`
+pub fn read_file(path: &str) -> Result<Vec<u8>, HostError> {
+    HostFile::open(path, OpenMode::Read)?.read_to_end()
+}
+
+/// Write `data` to the host file `path`, replacing it
+pub fn write_file(path: &str, data: &[u8]) -> Result<(), HostError> {
+    HostFile::open(path, OpenMode::Write)?.write_all(data)
+}
@@ -0,0 +1,155 @@
+//! Runner of `#[test_case]` functions for on-target tests
+//!
+//! A test binary built with the default harness collects its `#[test_case]` functions and
+//! passes them to `test_runner`, which prints each name and result to the host console and
+//! exits QEMU with 0 when every test passed, 1 otherwise. A test fails by returning `Err`
+//! or by panicking; the panic handler reports the panic and calls `test_panicked`, which
+//! ends the run since a panic cannot be recovered from. A test which hangs fails once the
+//! `watchdog`, when enabled, ends it, and the run continues with the next test.
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//! #![feature(custom_test_frameworks)]
+//! #![test_runner(arch_hal::test_runner)]
+//! #![reexport_test_harness_main = "test_main"]
+//!
+//! #[unsafe(no_mangle)]
+//! extern "C" fn efi_main() -> ! {
+//!     test_main();
+//!     arch_hal::exit_failure()
+//! }
+//!
+//! #[panic_handler]
+//! fn panic_handler(info: &core::panic::PanicInfo<'_>) -> ! {
+//!     let mut console = panic_report::console(UartConfig::pl011(0x900_0000, None), 115200);
+//!     panic_report::report(&mut console, info);
+//!     arch_hal::test_panicked()
+//! }
+//!
+//! #[test_case]
+//! fn reads_the_first_block() -> Result<(), IoError> { ... }
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use core::any::type_name;
+use core::fmt;
+use core::fmt::Write;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+use crate::exit_success;
+use crate::exit_with_code;
+use crate::semihosting::HostConsole;
+use crate::watchdog;
+
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// What a test returns: `()`, or a `Result` failing the test with its `Err`
+pub trait TestResult {
+    /// Why the test failed, `None` when it passed
+    fn failure(self) -> Option<String>;
+}
+
+impl TestResult for () {
+    fn failure(self) -> Option<String> {
+        None
+    }
+}
+
+impl<E: fmt::Debug> TestResult for Result<(), E> {
+    fn failure(self) -> Option<String> {
+        self.err().map(|e| format!("Err({:?})", e))
+    }
+}
+
+/// A `#[test_case]`
+pub trait TestCase {
+    fn name(&self) -> &'static str;
+    fn run(&self) -> Option<String>;
+}
+
+impl<F, R> TestCase for F
+where
+    F: Fn() -> R,
+    R: TestResult,
+{
+    fn name(&self) -> &'static str {
+        type_name::<F>()
+    }
+
+    fn run(&self) -> Option<String> {
+        self().failure()
+    }
+}
+
+/// The `#![test_runner]`: run `tests` in order and exit with the result
+pub fn test_runner(tests: &[&dyn TestCase]) -> ! {
+    let mut console = HostConsole;
+    TOTAL.store(tests.len(), Ordering::Relaxed);
+    let _ = writeln!(console, "\nrunning {} tests", tests.len());
+    for test in tests {
+        let _ = write!(console, "test {} ... ", test.name());
+        match watchdog::guard(|| test.run()) {
+            Some(None) => {
+                PASSED.fetch_add(1, Ordering::Relaxed);
+                let _ = writeln!(console, "ok");
+            }
+            Some(Some(failure)) => {
+                FAILED.fetch_add(1, Ordering::Relaxed);
+                let _ = writeln!(console, "FAILED\n    {}", failure);
+            }
+            None => {
+                FAILED.fetch_add(1, Ordering::Relaxed);
+                let _ = writeln!(
+                    console,
+                    "FAILED (timed out)\n    no result after {:?}, ended by the watchdog",
+                    watchdog::timeout().unwrap_or_default()
+                );
+            }
+        }
+    }
+    finish()
+}
+
+/// End the run from the panic handler, the running test is counted as failed
+pub fn test_panicked() -> ! {
+    test_aborted("panicked", 1)
+}
+
+/// End the run because of `reason`, the running test is counted as failed and the run exits
+/// with `code`
+pub(crate) fn test_aborted(reason: &str, code: u32) -> ! {
+    let mut console = HostConsole;
+    let _ = writeln!(console, "FAILED ({})", reason);
+    FAILED.fetch_add(1, Ordering::Relaxed);
+    finish_with(code)
+}
+
+fn finish() -> ! {
+    finish_with(1)
+}
+
+// exit with 0 when every test passed, `failure_code` otherwise
+fn finish_with(failure_code: u32) -> ! {
+    let mut console = HostConsole;
+    let passed = PASSED.load(Ordering::Relaxed);
+    let failed = FAILED.load(Ordering::Relaxed);
+    let not_run = TOTAL.load(Ordering::Relaxed) - passed - failed;
+    let _ = writeln!(
+        console,
+        "\ntest result: {}. {} passed; {} failed; {} not run",
+        if failed == 0 { "ok" } else { "FAILED" },
+        passed,
+        failed,
+        not_run
+    );
+    if failed == 0 && not_run == 0 {
+        exit_success()
+    } else {
+        exit_with_code(failure_code)
+    }
+}
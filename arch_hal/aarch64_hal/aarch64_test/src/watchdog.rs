@@ -0,0 +1,161 @@
+//! Watchdog which ends a hung `#[test_case]` so the runner can continue with the next one
+//!
+//! The watchdog uses the EL1 physical timer, which the loader and its tests leave unused.
+//! Each test runs with a saved context of the runner, and the timer interrupt, taken from
+//! the hung test, resumes the runner from that context instead of returning to the test.
+//! The stack of the test is abandoned: locks it holds stay locked and its values are
+//! leaked, which is fine for the few tests left to run.
+//!
+//! The test binary installs the exception vectors, initializes the GIC and routes the timer
+//! interrupt to `handle_interrupt`, e.g. with `arch_hal::timer::init_test_watchdog`, before
+//! calling `enable`. IRQs must be unmasked while a test runs. Without `enable` tests run
+//! unguarded.
+
+use core::arch::global_asm;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use cpu::timer;
+
+/// EL1 physical timer PPI on QEMU virt and most boards
+pub const DEFAULT_WATCHDOG_INTID: u32 = 30;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TIMEOUT: AtomicU64 = AtomicU64::new(0);
+// `Context` of the guarded test, 0 while none runs
+static CONTEXT: AtomicUsize = AtomicUsize::new(0);
+
+// x19-x30, sp and d8-d15: the registers a call preserves
+#[repr(C)]
+struct Context {
+    x: [u64; 12],
+    sp: u64,
+    d: [u64; 8],
+}
+
+const _: () = assert!(size_of::<Context>() == 0xA8);
+
+/// Guard every following test with `timeout`
+pub fn enable(timeout: Duration) {
+    TIMEOUT.store(timeout.as_nanos() as u64, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Release);
+}
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::Release);
+    timer::disable_physical_timer();
+}
+
+/// The timeout of the tests while the watchdog is enabled
+pub fn timeout() -> Option<Duration> {
+    ENABLED
+        .load(Ordering::Acquire)
+        .then(|| Duration::from_nanos(TIMEOUT.load(Ordering::Relaxed)))
+}
+
+/// The watchdog timer interrupt: resume the runner if a guarded test is running
+pub fn handle_interrupt() {
+    timer::disable_physical_timer();
+    let context = CONTEXT.swap(0, Ordering::AcqRel);
+    if context == 0 {
+        return;
+    }
+    let resumed = cpu::exception::with_current_frame(|frame| {
+        // eret to `aarch64_test_guarded_resume(context)` at the interrupted level
+        frame.elr = aarch64_test_guarded_resume as *const () as u64;
+        frame.x[0] = context as u64;
+    });
+    if resumed.is_none() {
+        CONTEXT.store(context, Ordering::Release);
+    }
+}
+
+unsafe extern "C" {
+    fn aarch64_test_guarded_call(
+        context: *mut Context,
+        f: extern "C" fn(*mut ()),
+        data: *mut (),
+    ) -> u64;
+    fn aarch64_test_guarded_resume(context: *const Context) -> !;
+}
+
+/// Call `f`, `None` if the watchdog ended it. `f` runs unguarded while it is disabled
+pub(crate) fn guard<F: FnOnce() -> R, R>(f: F) -> Option<R> {
+    let Some(timeout) = timeout() else {
+        return Some(f());
+    };
+
+    extern "C" fn trampoline<G: FnOnce() -> T, T>(data: *mut ()) {
+        let (f, result) = unsafe { &mut *(data as *mut (Option<G>, Option<T>)) };
+        *result = f.take().map(|f| f());
+    }
+
+    let mut data: (Option<F>, Option<R>) = (Some(f), None);
+    let mut context = Context {
+        x: [0; 12],
+        sp: 0,
+        d: [0; 8],
+    };
+    let ticks = (timeout.as_nanos() * u128::from(timer::frequency()) / 1_000_000_000) as u64;
+    CONTEXT.store(&mut context as *mut Context as usize, Ordering::Release);
+    timer::set_physical_timer_compare(timer::counter().saturating_add(ticks));
+    timer::enable_physical_timer();
+    let timed_out = unsafe {
+        aarch64_test_guarded_call(
+            &mut context,
+            trampoline::<F, R>,
+            &mut data as *mut _ as *mut (),
+        )
+    } != 0;
+    timer::disable_physical_timer();
+    CONTEXT.store(0, Ordering::Release);
+    if timed_out { None } else { data.1 }
+}
+
+global_asm!(
+    r#"
+// x0: Context, x1: f, x2: argument of f. returns 0 when f returned, 1 when resumed
+.global aarch64_test_guarded_call
+aarch64_test_guarded_call:
+    stp x19, x20, [x0, #0x00]
+    stp x21, x22, [x0, #0x10]
+    stp x23, x24, [x0, #0x20]
+    stp x25, x26, [x0, #0x30]
+    stp x27, x28, [x0, #0x40]
+    stp x29, x30, [x0, #0x50]
+    mov x9, sp
+    str x9, [x0, #0x60]
+    stp d8, d9, [x0, #0x68]
+    stp d10, d11, [x0, #0x78]
+    stp d12, d13, [x0, #0x88]
+    stp d14, d15, [x0, #0x98]
+    stp x29, x30, [sp, #-16]!
+    mov x29, sp
+    mov x0, x2
+    blr x1
+    ldp x29, x30, [sp], #16
+    mov x0, #0
+    ret
+
+// x0: Context saved by aarch64_test_guarded_call, which returns 1
+.global aarch64_test_guarded_resume
+aarch64_test_guarded_resume:
+    ldp x19, x20, [x0, #0x00]
+    ldp x21, x22, [x0, #0x10]
+    ldp x23, x24, [x0, #0x20]
+    ldp x25, x26, [x0, #0x30]
+    ldp x27, x28, [x0, #0x40]
+    ldp x29, x30, [x0, #0x50]
+    ldr x9, [x0, #0x60]
+    mov sp, x9
+    ldp d8, d9, [x0, #0x68]
+    ldp d10, d11, [x0, #0x78]
+    ldp d12, d13, [x0, #0x88]
+    ldp d14, d15, [x0, #0x98]
+    mov x0, #1
+    ret
+"#
+);
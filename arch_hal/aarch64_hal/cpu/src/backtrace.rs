@@ -0,0 +1,32 @@
+use core::arch::asm;
+
+/// x29 of the caller
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { asm!("mov {}, x29", out(reg) fp) };
+    fp
+}
+
+/// Walk the AAPCS64 frame records ({previous x29, x30}) starting at `fp`
+/// and call `f` with each return address, at most `max_depth` times.
+/// The walk stops at a null, misaligned or non-increasing frame pointer,
+/// so the code must be built with frame pointers (`-C force-frame-pointers=yes`).
+pub fn walk(mut fp: usize, max_depth: usize, mut f: impl FnMut(usize)) {
+    for _ in 0..max_depth {
+        if fp == 0 || !fp.is_multiple_of(16) {
+            return;
+        }
+        let record = fp as *const [usize; 2];
+        let [next_fp, return_address] = unsafe { record.read_volatile() };
+        if return_address == 0 {
+            return;
+        }
+        f(return_address);
+        // the stack grows down, so callers' records are at higher addresses
+        if next_fp <= fp {
+            return;
+        }
+        fp = next_fp;
+    }
+}
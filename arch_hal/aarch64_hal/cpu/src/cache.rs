@@ -0,0 +1,79 @@
+use core::arch::asm;
+
+/// smallest data cache line size in bytes (CTR_EL0.DminLine)
+pub fn dcache_line_size() -> usize {
+    let ctr: usize;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr) };
+    4 << ((ctr >> 16) & 0xF)
+}
+
+/// smallest instruction cache line size in bytes (CTR_EL0.IminLine)
+pub fn icache_line_size() -> usize {
+    let ctr: usize;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr) };
+    4 << (ctr & 0xF)
+}
+
+// (start, end, line size) of the cache lines covering the range. end is exclusive
+fn align_range(ptr: *const u8, len: usize) -> (usize, usize, usize) {
+    let line = dcache_line_size();
+    let start = (ptr as usize) & !(line - 1);
+    let end = (ptr as usize + len).next_multiple_of(line);
+    (start, end, line)
+}
+
+/// write back the range to the point of coherency (DC CVAC)
+pub fn clean_dcache_range(ptr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let (start, end, line) = align_range(ptr, len);
+    for address in (start..end).step_by(line) {
+        unsafe { asm!("dc cvac, {}", in(reg) address) };
+    }
+    unsafe { asm!("dsb sy") };
+}
+
+/// discard the cached copy of the range (DC IVAC).
+/// dirty data sharing a line with the range is lost
+pub fn invalidate_dcache_range(ptr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let (start, end, line) = align_range(ptr, len);
+    for address in (start..end).step_by(line) {
+        unsafe { asm!("dc ivac, {}", in(reg) address) };
+    }
+    unsafe { asm!("dsb sy") };
+}
+
+/// write back and discard the range (DC CIVAC)
+pub fn clean_invalidate_dcache_range(ptr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let (start, end, line) = align_range(ptr, len);
+    for address in (start..end).step_by(line) {
+        unsafe { asm!("dc civac, {}", in(reg) address) };
+    }
+    unsafe { asm!("dsb sy") };
+}
+
+/// make instructions written to the range visible to instruction fetch
+/// (DC CVAU + IC IVAU). call after copying executable code
+pub fn sync_icache(ptr: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let (start, end, line) = align_range(ptr, len);
+    for address in (start..end).step_by(line) {
+        unsafe { asm!("dc cvau, {}", in(reg) address) };
+    }
+    unsafe { asm!("dsb ish") };
+    let line = icache_line_size();
+    let start = (ptr as usize) & !(line - 1);
+    for address in (start..end).step_by(line) {
+        unsafe { asm!("ic ivau, {}", in(reg) address) };
+    }
+    unsafe { asm!("dsb ish", "isb") };
+}
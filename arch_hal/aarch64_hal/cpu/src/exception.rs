@@ -0,0 +1,360 @@
+use core::arch::asm;
+use core::arch::global_asm;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+/// General purpose registers and exception syndrome saved on EL2 exception entry
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct TrapFrame {
+    pub x: [u64; 31],
+    pub elr: u64,
+    pub spsr: u64,
+    pub esr: u64,
+    pub far: u64,
+    _reserved: u64,
+}
+
+const _: () = assert!(size_of::<TrapFrame>() == 0x120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionSource {
+    CurrentElSp0,
+    CurrentElSpx,
+    LowerElAarch64,
+    LowerElAarch32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionType {
+    Synchronous,
+    Irq,
+    Fiq,
+    SError,
+}
+
+/// ESR_EL2.EC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionClass {
+    Unknown,
+    WfiWfe,
+    SimdFp,
+    Hvc64,
+    Smc64,
+    SysReg64,
+    InstructionAbortLower,
+    InstructionAbortCurrent,
+    PcAlignment,
+    DataAbortLower,
+    DataAbortCurrent,
+    SpAlignment,
+    SError,
+    Brk,
+    Other(u8),
+}
+
+impl ExceptionClass {
+    pub fn from_esr(esr: u64) -> Self {
+        match ((esr >> 26) & 0x3F) as u8 {
+            0x00 => Self::Unknown,
+            0x01 => Self::WfiWfe,
+            0x07 => Self::SimdFp,
+            0x16 => Self::Hvc64,
+            0x17 => Self::Smc64,
+            0x18 => Self::SysReg64,
+            0x20 => Self::InstructionAbortLower,
+            0x21 => Self::InstructionAbortCurrent,
+            0x22 => Self::PcAlignment,
+            0x24 => Self::DataAbortLower,
+            0x25 => Self::DataAbortCurrent,
+            0x26 => Self::SpAlignment,
+            0x2F => Self::SError,
+            0x3C => Self::Brk,
+            ec => Self::Other(ec),
+        }
+    }
+}
+
+impl TrapFrame {
+    pub fn exception_class(&self) -> ExceptionClass {
+        ExceptionClass::from_esr(self.esr)
+    }
+
+    /// ESR_EL2.ISS
+    pub fn iss(&self) -> u32 {
+        (self.esr & 0x1FF_FFFF) as u32
+    }
+
+    /// skip the trapped instruction (ELR_EL2 points to it for SMC, sysreg and abort traps)
+    pub fn advance_pc(&mut self) {
+        const ESR_EL2_IL: u64 = 1 << 25;
+        self.elr += if self.esr & ESR_EL2_IL != 0 { 4 } else { 2 };
+    }
+}
+
+impl TrapFrame {
+    /// initial state of a guest which starts at `entry_point` with `spsr`
+    pub const fn new(entry_point: u64, spsr: u64) -> Self {
+        Self {
+            x: [0; 31],
+            elr: entry_point,
+            spsr,
+            esr: 0,
+            far: 0,
+            _reserved: 0,
+        }
+    }
+}
+
+pub type ExceptionHandler = fn(&mut TrapFrame, ExceptionSource, ExceptionType);
+
+static HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+const MAX_PES: usize = 8;
+
+// MPIDR_EL1 (never 0, bit 31 is RES1) and the frame of the exception being handled on that PE
+static CURRENT_FRAMES: [(AtomicU64, AtomicUsize); MAX_PES] =
+    [const { (AtomicU64::new(0), AtomicUsize::new(0)) }; MAX_PES];
+
+fn current_frame_slot() -> Option<&'static AtomicUsize> {
+    let mpidr = crate::get_mpidr();
+    CURRENT_FRAMES.iter().find_map(|(owner, frame)| {
+        match owner.compare_exchange(0, mpidr, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => Some(frame),
+            Err(current) if current == mpidr => Some(frame),
+            Err(_) => None,
+        }
+    })
+}
+
+/// Copy of the frame of the innermost exception being handled on the calling PE.
+/// None outside of the exception handler, e.g. for a panic in the boot flow
+pub fn current_frame() -> Option<TrapFrame> {
+    let frame = current_frame_slot()?.load(Ordering::Relaxed);
+    if frame == 0 {
+        return None;
+    }
+    Some(unsafe { &*(frame as *const TrapFrame) }.clone())
+}
+
+/// Call `f` with the frame of the innermost exception being handled on the calling PE, which
+/// is restored on return from the exception, e.g. to resume somewhere else.
+/// None outside of the exception handler
+pub fn with_current_frame<R>(f: impl FnOnce(&mut TrapFrame) -> R) -> Option<R> {
+    let frame = current_frame_slot()?.load(Ordering::Relaxed);
+    if frame == 0 {
+        return None;
+    }
+    Some(f(unsafe { &mut *(frame as *mut TrapFrame) }))
+}
+
+unsafe extern "C" {
+    static el2_exception_vector: u8;
+}
+
+/// install the EL2 exception vector table and the handler called from every vector
+pub fn init(handler: ExceptionHandler) {
+    HANDLER.store(handler as usize, Ordering::Release);
+    let vector = unsafe { &el2_exception_vector } as *const u8 as usize;
+    // no guest is running in enter_guest yet (TPIDR_EL2 resets to an UNKNOWN value)
+    unsafe { asm!("msr tpidr_el2, xzr", "msr vbar_el2, {}", "isb", in(reg) vector) };
+}
+
+// index of the vector table entry
+fn decode_kind(kind: u64) -> (ExceptionSource, ExceptionType) {
+    let source = match kind >> 2 {
+        0 => ExceptionSource::CurrentElSp0,
+        1 => ExceptionSource::CurrentElSpx,
+        2 => ExceptionSource::LowerElAarch64,
+        _ => ExceptionSource::LowerElAarch32,
+    };
+    let exception_type = match kind & 0b11 {
+        0 => ExceptionType::Synchronous,
+        1 => ExceptionType::Irq,
+        2 => ExceptionType::Fiq,
+        _ => ExceptionType::SError,
+    };
+    (source, exception_type)
+}
+
+/// Run the guest described by `frame` on this PE until it takes an exception to EL2.
+/// `frame` is updated with the guest state at the exit, and the exception is not handled.
+/// Exceptions from the current EL are still dispatched to the handler passed to `init`.
+pub fn enter_guest(frame: &mut TrapFrame) -> (ExceptionSource, ExceptionType) {
+    let kind = unsafe { el2_enter_guest(frame) };
+    decode_kind(kind)
+}
+
+unsafe extern "C" {
+    fn el2_enter_guest(frame: *mut TrapFrame) -> u64;
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn el2_exception_handler(frame: &mut TrapFrame, kind: u64) {
+    let (source, exception_type) = decode_kind(kind);
+    let slot = current_frame_slot();
+    let outer_frame = slot.map(|slot| slot.swap(frame as *mut _ as usize, Ordering::Relaxed));
+    let handler = HANDLER.load(Ordering::Acquire);
+    if handler == 0 {
+        panic!(
+            "unhandled exception: {:?} {:?}, esr: {:#x}, elr: {:#x}, far: {:#x}",
+            source, exception_type, frame.esr, frame.elr, frame.far
+        );
+    }
+    let handler = unsafe { core::mem::transmute::<usize, ExceptionHandler>(handler) };
+    handler(frame, source, exception_type);
+    if let (Some(slot), Some(outer_frame)) = (slot, outer_frame) {
+        slot.store(outer_frame, Ordering::Relaxed);
+    }
+}
+
+global_asm!(
+    r#"
+.macro EXCEPTION_VECTOR kind
+    .balign 0x80
+    sub sp, sp, #0x120
+    stp x0, x1, [sp, #0x00]
+    mov x1, #\kind
+    b el2_exception_entry
+.endm
+
+.pushsection .text.el2_exception_vector, "ax"
+.balign 0x800
+.global el2_exception_vector
+el2_exception_vector:
+    EXCEPTION_VECTOR 0
+    EXCEPTION_VECTOR 1
+    EXCEPTION_VECTOR 2
+    EXCEPTION_VECTOR 3
+    EXCEPTION_VECTOR 4
+    EXCEPTION_VECTOR 5
+    EXCEPTION_VECTOR 6
+    EXCEPTION_VECTOR 7
+    EXCEPTION_VECTOR 8
+    EXCEPTION_VECTOR 9
+    EXCEPTION_VECTOR 10
+    EXCEPTION_VECTOR 11
+    EXCEPTION_VECTOR 12
+    EXCEPTION_VECTOR 13
+    EXCEPTION_VECTOR 14
+    EXCEPTION_VECTOR 15
+
+el2_exception_entry:
+    stp x2, x3, [sp, #0x10]
+    stp x4, x5, [sp, #0x20]
+    stp x6, x7, [sp, #0x30]
+    stp x8, x9, [sp, #0x40]
+    stp x10, x11, [sp, #0x50]
+    stp x12, x13, [sp, #0x60]
+    stp x14, x15, [sp, #0x70]
+    stp x16, x17, [sp, #0x80]
+    stp x18, x19, [sp, #0x90]
+    stp x20, x21, [sp, #0xA0]
+    stp x22, x23, [sp, #0xB0]
+    stp x24, x25, [sp, #0xC0]
+    stp x26, x27, [sp, #0xD0]
+    stp x28, x29, [sp, #0xE0]
+    mrs x2, elr_el2
+    stp x30, x2, [sp, #0xF0]
+    mrs x2, spsr_el2
+    mrs x3, esr_el2
+    stp x2, x3, [sp, #0x100]
+    mrs x2, far_el2
+    str x2, [sp, #0x110]
+    // TPIDR_EL2 holds the host context while a guest runs in el2_enter_guest
+    mrs x2, tpidr_el2
+    cbz x2, 1f
+    cmp x1, #8
+    b.hs el2_guest_exit
+1:
+    cmp x1, #8
+    b.lo 3f
+    // x29 holds the guest frame pointer, end the backtrace of the handler here
+    mov x29, xzr
+3:
+    mov x0, sp
+    bl el2_exception_handler
+    ldp x30, x2, [sp, #0xF0]
+    msr elr_el2, x2
+    ldr x2, [sp, #0x100]
+    msr spsr_el2, x2
+    ldp x28, x29, [sp, #0xE0]
+    ldp x26, x27, [sp, #0xD0]
+    ldp x24, x25, [sp, #0xC0]
+    ldp x22, x23, [sp, #0xB0]
+    ldp x20, x21, [sp, #0xA0]
+    ldp x18, x19, [sp, #0x90]
+    ldp x16, x17, [sp, #0x80]
+    ldp x14, x15, [sp, #0x70]
+    ldp x12, x13, [sp, #0x60]
+    ldp x10, x11, [sp, #0x50]
+    ldp x8, x9, [sp, #0x40]
+    ldp x6, x7, [sp, #0x30]
+    ldp x4, x5, [sp, #0x20]
+    ldp x2, x3, [sp, #0x10]
+    ldp x0, x1, [sp, #0x00]
+    add sp, sp, #0x120
+    eret
+
+// x0: guest TrapFrame, returns the vector table entry of the exit
+// host context: x19-x30 and the TrapFrame pointer, 0x70 bytes
+.global el2_enter_guest
+el2_enter_guest:
+    sub sp, sp, #0x70
+    stp x19, x20, [sp, #0x00]
+    stp x21, x22, [sp, #0x10]
+    stp x23, x24, [sp, #0x20]
+    stp x25, x26, [sp, #0x30]
+    stp x27, x28, [sp, #0x40]
+    stp x29, x30, [sp, #0x50]
+    str x0, [sp, #0x60]
+    mov x1, sp
+    msr tpidr_el2, x1
+    ldp x2, x3, [x0, #0xF8]
+    msr elr_el2, x2
+    msr spsr_el2, x3
+    ldp x2, x3, [x0, #0x10]
+    ldp x4, x5, [x0, #0x20]
+    ldp x6, x7, [x0, #0x30]
+    ldp x8, x9, [x0, #0x40]
+    ldp x10, x11, [x0, #0x50]
+    ldp x12, x13, [x0, #0x60]
+    ldp x14, x15, [x0, #0x70]
+    ldp x16, x17, [x0, #0x80]
+    ldp x18, x19, [x0, #0x90]
+    ldp x20, x21, [x0, #0xA0]
+    ldp x22, x23, [x0, #0xB0]
+    ldp x24, x25, [x0, #0xC0]
+    ldp x26, x27, [x0, #0xD0]
+    ldp x28, x29, [x0, #0xE0]
+    ldr x30, [x0, #0xF0]
+    ldp x0, x1, [x0, #0x00]
+    eret
+
+// sp: TrapFrame of the exit, x1: vector table entry, x2: host context (sp + 0x120)
+el2_guest_exit:
+    // ESB: defer an SError still pending from the guest into DISR_EL1 (FEAT_RAS, NOP otherwise)
+    hint #16
+    msr tpidr_el2, xzr
+    ldr x3, [x2, #0x60]
+    mov x4, sp
+    mov x5, #(0x120 / 16)
+2:
+    ldp x6, x7, [x4], #16
+    stp x6, x7, [x3], #16
+    subs x5, x5, #1
+    b.ne 2b
+    mov sp, x2
+    mov x0, x1
+    ldp x19, x20, [sp, #0x00]
+    ldp x21, x22, [sp, #0x10]
+    ldp x23, x24, [sp, #0x20]
+    ldp x25, x26, [sp, #0x30]
+    ldp x27, x28, [sp, #0x40]
+    ldp x29, x30, [sp, #0x50]
+    add sp, sp, #0x70
+    ret
+.popsection
+"#
+);
@@ -0,0 +1,122 @@
+use core::arch::asm;
+
+// CPTR_EL2 (HCR_EL2.E2H == 0)
+const CPTR_EL2_TFP: u64 = 1 << 10;
+
+/// FP/SIMD register file (V0-V31, FPCR and FPSR)
+#[repr(C, align(16))]
+#[derive(Debug, Clone)]
+pub struct FpState {
+    pub q: [u128; 32],
+    pub fpcr: u64,
+    pub fpsr: u64,
+}
+
+const _: () = assert!(size_of::<FpState>() == 0x210);
+
+impl FpState {
+    /// all registers zero, FPCR at its reset value (round to nearest, no traps)
+    pub const fn new() -> Self {
+        Self {
+            q: [0; 32],
+            fpcr: 0,
+            fpsr: 0,
+        }
+    }
+}
+
+impl Default for FpState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// trap FP/SIMD accesses from EL0, EL1 and EL2 to EL2 (ESR_EL2.EC == 0x07)
+pub fn set_trap(trap: bool) {
+    let mut cptr_el2: u64;
+    unsafe { asm!("mrs {}, cptr_el2", out(reg) cptr_el2) };
+    if trap {
+        cptr_el2 |= CPTR_EL2_TFP;
+    } else {
+        cptr_el2 &= !CPTR_EL2_TFP;
+    }
+    unsafe { asm!("msr cptr_el2, {}", "isb", in(reg) cptr_el2) };
+}
+
+pub fn is_trapped() -> bool {
+    let cptr_el2: u64;
+    unsafe { asm!("mrs {}, cptr_el2", out(reg) cptr_el2) };
+    cptr_el2 & CPTR_EL2_TFP != 0
+}
+
+/// store the FP/SIMD registers of this PE to `state`.
+/// FP/SIMD must not be trapped
+pub fn save(state: &mut FpState) {
+    unsafe {
+        asm!(
+            "stp q0, q1, [{state}, #0x000]",
+            "stp q2, q3, [{state}, #0x020]",
+            "stp q4, q5, [{state}, #0x040]",
+            "stp q6, q7, [{state}, #0x060]",
+            "stp q8, q9, [{state}, #0x080]",
+            "stp q10, q11, [{state}, #0x0A0]",
+            "stp q12, q13, [{state}, #0x0C0]",
+            "stp q14, q15, [{state}, #0x0E0]",
+            "stp q16, q17, [{state}, #0x100]",
+            "stp q18, q19, [{state}, #0x120]",
+            "stp q20, q21, [{state}, #0x140]",
+            "stp q22, q23, [{state}, #0x160]",
+            "stp q24, q25, [{state}, #0x180]",
+            "stp q26, q27, [{state}, #0x1A0]",
+            "stp q28, q29, [{state}, #0x1C0]",
+            "stp q30, q31, [{state}, #0x1E0]",
+            "mrs {tmp}, fpcr",
+            "str {tmp}, [{state}, #0x200]",
+            "mrs {tmp}, fpsr",
+            "str {tmp}, [{state}, #0x208]",
+            state = in(reg) state as *mut FpState,
+            tmp = out(reg) _,
+            options(nostack)
+        )
+    };
+}
+
+/// load the FP/SIMD registers of this PE from `state`.
+/// FP/SIMD must not be trapped
+pub fn restore(state: &FpState) {
+    unsafe {
+        asm!(
+            "ldp q0, q1, [{state}, #0x000]",
+            "ldp q2, q3, [{state}, #0x020]",
+            "ldp q4, q5, [{state}, #0x040]",
+            "ldp q6, q7, [{state}, #0x060]",
+            "ldp q8, q9, [{state}, #0x080]",
+            "ldp q10, q11, [{state}, #0x0A0]",
+            "ldp q12, q13, [{state}, #0x0C0]",
+            "ldp q14, q15, [{state}, #0x0E0]",
+            "ldp q16, q17, [{state}, #0x100]",
+            "ldp q18, q19, [{state}, #0x120]",
+            "ldp q20, q21, [{state}, #0x140]",
+            "ldp q22, q23, [{state}, #0x160]",
+            "ldp q24, q25, [{state}, #0x180]",
+            "ldp q26, q27, [{state}, #0x1A0]",
+            "ldp q28, q29, [{state}, #0x1C0]",
+            "ldp q30, q31, [{state}, #0x1E0]",
+            "ldr {tmp}, [{state}, #0x200]",
+            "msr fpcr, {tmp}",
+            "ldr {tmp}, [{state}, #0x208]",
+            "msr fpsr, {tmp}",
+            state = in(reg) state as *const FpState,
+            tmp = out(reg) _,
+            out("v0") _, out("v1") _, out("v2") _, out("v3") _,
+            out("v4") _, out("v5") _, out("v6") _, out("v7") _,
+            out("v8") _, out("v9") _, out("v10") _, out("v11") _,
+            out("v12") _, out("v13") _, out("v14") _, out("v15") _,
+            out("v16") _, out("v17") _, out("v18") _, out("v19") _,
+            out("v20") _, out("v21") _, out("v22") _, out("v23") _,
+            out("v24") _, out("v25") _, out("v26") _, out("v27") _,
+            out("v28") _, out("v29") _, out("v30") _, out("v31") _,
+            options(nostack)
+        )
+    };
+}
@@ -2,15 +2,107 @@
 
 use core::arch::asm;
 
+pub mod backtrace;
+pub mod cache;
+pub mod exception;
+pub mod fpsimd;
+pub mod psci;
+pub mod registers;
+pub mod timer;
+
 pub fn get_current_el() -> u64 {
     let current_el: u64;
     unsafe { asm!("mrs {}, currentel", out(reg) current_el) };
     current_el >> 2
 }
 
+/// Aff3, Aff2, Aff1 and Aff0 fields of MPIDR_EL1
+pub const MPIDR_AFFINITY_MASK: u64 = 0xFF_00FF_FFFF;
+
+pub fn get_mpidr() -> u64 {
+    let mpidr: u64;
+    unsafe { asm!("mrs {}, mpidr_el1", out(reg) mpidr) };
+    mpidr
+}
+
+pub fn get_mpidr_affinity() -> u64 {
+    get_mpidr() & MPIDR_AFFINITY_MASK
+}
+
+pub fn get_id_aa64mmfr0_el1() -> u64 {
+    let mmfr0: u64;
+    unsafe { asm!("mrs {}, id_aa64mmfr0_el1", out(reg) mmfr0) };
+    mmfr0
+}
+
+/// whether stage 1 translation supports `size` byte pages (4KiB, 16KiB or 64KiB)
+pub fn is_granule_supported(size: usize) -> bool {
+    let mmfr0 = get_id_aa64mmfr0_el1();
+    match size {
+        // TGran4: 0b1111 is not implemented
+        0x1000 => (mmfr0 >> 28) & 0xF != 0xF,
+        // TGran16: 0b0000 is not implemented
+        0x4000 => (mmfr0 >> 20) & 0xF != 0,
+        // TGran64: 0b1111 is not implemented
+        0x1_0000 => (mmfr0 >> 24) & 0xF != 0xF,
+        _ => false,
+    }
+}
+
+/// whether EL1 can run big-endian (ID_AA64MMFR0_EL1.BigEnd)
+pub fn is_big_endian_supported() -> bool {
+    (get_id_aa64mmfr0_el1() >> 8) & 0xF != 0
+}
+
+/// select the endianness of EL1 data accesses (SCTLR_EL1.EE), used before entering a kernel
+pub fn set_el1_big_endian(big_endian: bool) {
+    const SCTLR_EL1_EE: u64 = 1 << 25;
+    let mut sctlr: u64;
+    unsafe { asm!("mrs {}, sctlr_el1", out(reg) sctlr) };
+    if big_endian {
+        sctlr |= SCTLR_EL1_EE;
+    } else {
+        sctlr &= !SCTLR_EL1_EE;
+    }
+    unsafe { asm!("msr sctlr_el1, {}", "isb", in(reg) sctlr) };
+}
+
+/// turn off the EL1 MMU and caches (SCTLR_EL1.M, C and I), the state a kernel is entered in
+pub fn disable_el1_mmu() {
+    const SCTLR_EL1_M: u64 = 1 << 0;
+    const SCTLR_EL1_C: u64 = 1 << 2;
+    const SCTLR_EL1_I: u64 = 1 << 12;
+    let mut sctlr: u64;
+    unsafe { asm!("mrs {}, sctlr_el1", out(reg) sctlr) };
+    sctlr &= !(SCTLR_EL1_M | SCTLR_EL1_C | SCTLR_EL1_I);
+    unsafe { asm!("msr sctlr_el1, {}", "isb", in(reg) sctlr) };
+}
+
+/// unmask IRQs at the current EL
+pub fn enable_irq() {
+    unsafe { asm!("msr daifclr, #2") };
+}
+
+/// mask IRQs at the current EL
+pub fn disable_irq() {
+    unsafe { asm!("msr daifset, #2") };
+}
+
+pub fn get_hcr_el2() -> u64 {
+    let hcr_el2: u64;
+    unsafe { asm!("mrs {}, hcr_el2", out(reg) hcr_el2) };
+    hcr_el2
+}
+
+pub fn set_hcr_el2(hcr_el2: u64) {
+    unsafe { asm!("msr hcr_el2, {}", "isb", in(reg) hcr_el2) };
+}
+
 pub fn setup_hypervisor_registers() {
     const HCR_EL2_RW: u64 = 1 << 31;
     const HCR_EL2_API: u64 = 1 << 41;
-    let hcr_el2 = HCR_EL2_RW | HCR_EL2_API;
+    // trap guest SMC (PSCI calls) to EL2
+    const HCR_EL2_TSC: u64 = 1 << 19;
+    let hcr_el2 = HCR_EL2_RW | HCR_EL2_API | HCR_EL2_TSC;
     unsafe { asm!("msr hcr_el2, {}", in(reg) hcr_el2) };
 }
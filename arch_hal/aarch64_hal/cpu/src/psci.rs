@@ -0,0 +1,206 @@
+use core::arch::asm;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+/// compatible strings of the `/psci` node which use the standard (PSCI 0.2+) function IDs
+pub const COMPATIBLE: &[&str] = &["arm,psci-1.0", "arm,psci-0.2"];
+
+pub const PSCI_VERSION: u32 = 0x8400_0000;
+pub const CPU_SUSPEND_32: u32 = 0x8400_0001;
+pub const CPU_SUSPEND_64: u32 = 0xC400_0001;
+pub const CPU_OFF: u32 = 0x8400_0002;
+pub const CPU_ON_32: u32 = 0x8400_0003;
+pub const CPU_ON_64: u32 = 0xC400_0003;
+pub const AFFINITY_INFO_32: u32 = 0x8400_0004;
+pub const AFFINITY_INFO_64: u32 = 0xC400_0004;
+pub const MIGRATE_INFO_TYPE: u32 = 0x8400_0006;
+pub const SYSTEM_OFF: u32 = 0x8400_0008;
+pub const SYSTEM_RESET: u32 = 0x8400_0009;
+pub const PSCI_FEATURES: u32 = 0x8400_000A;
+
+const CONDUIT_NONE: u8 = 0;
+const CONDUIT_SMC: u8 = 1;
+const CONDUIT_HVC: u8 = 2;
+
+static CONDUIT: AtomicU8 = AtomicU8::new(CONDUIT_NONE);
+
+/// Instruction used to call the PSCI firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsciConduit {
+    Smc,
+    Hvc,
+}
+
+impl PsciConduit {
+    /// parse the `method` property of the `/psci` node
+    pub fn from_method(method: &str) -> Option<Self> {
+        match method {
+            "smc" => Some(Self::Smc),
+            "hvc" => Some(Self::Hvc),
+            _ => None,
+        }
+    }
+}
+
+/// power state returned by AFFINITY_INFO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AffinityState {
+    On = 0,
+    Off = 1,
+    OnPending = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsciErr {
+    NotSupported,
+    InvalidParameters,
+    Denied,
+    AlreadyOn,
+    OnPending,
+    InternalFailure,
+    NotPresent,
+    Disabled,
+    InvalidAddress,
+    /// `init` has not been called
+    NotInitialized,
+    UnknownError(i32),
+}
+
+impl PsciErr {
+    fn from_return_value(value: i32) -> Self {
+        match value {
+            -1 => Self::NotSupported,
+            -2 => Self::InvalidParameters,
+            -3 => Self::Denied,
+            -4 => Self::AlreadyOn,
+            -5 => Self::OnPending,
+            -6 => Self::InternalFailure,
+            -7 => Self::NotPresent,
+            -8 => Self::Disabled,
+            -9 => Self::InvalidAddress,
+            _ => Self::UnknownError(value),
+        }
+    }
+
+    /// raw PSCI return value of this error
+    pub fn to_return_value(self) -> i32 {
+        match self {
+            Self::NotSupported | Self::NotInitialized => -1,
+            Self::InvalidParameters => -2,
+            Self::Denied => -3,
+            Self::AlreadyOn => -4,
+            Self::OnPending => -5,
+            Self::InternalFailure => -6,
+            Self::NotPresent => -7,
+            Self::Disabled => -8,
+            Self::InvalidAddress => -9,
+            Self::UnknownError(value) => value,
+        }
+    }
+}
+
+pub fn init(conduit: PsciConduit) {
+    let conduit = match conduit {
+        PsciConduit::Smc => CONDUIT_SMC,
+        PsciConduit::Hvc => CONDUIT_HVC,
+    };
+    CONDUIT.store(conduit, Ordering::Release);
+}
+
+pub fn conduit() -> Option<PsciConduit> {
+    match CONDUIT.load(Ordering::Acquire) {
+        CONDUIT_SMC => Some(PsciConduit::Smc),
+        CONDUIT_HVC => Some(PsciConduit::Hvc),
+        _ => None,
+    }
+}
+
+pub fn is_available() -> bool {
+    conduit().is_some()
+}
+
+/// Issue a raw PSCI call and return x0
+pub fn call(function_id: u32, arg0: u64, arg1: u64, arg2: u64) -> Result<u64, PsciErr> {
+    let mut x0 = function_id as u64;
+    // SMCCC v1.0 allows x4-x17 to be corrupted
+    match conduit().ok_or(PsciErr::NotInitialized)? {
+        PsciConduit::Smc => unsafe {
+            asm!(
+                "smc #0",
+                inout("x0") x0,
+                inout("x1") arg0 => _,
+                inout("x2") arg1 => _,
+                inout("x3") arg2 => _,
+                out("x4") _, out("x5") _, out("x6") _, out("x7") _,
+                out("x8") _, out("x9") _, out("x10") _, out("x11") _,
+                out("x12") _, out("x13") _, out("x14") _, out("x15") _,
+                out("x16") _, out("x17") _,
+                options(nostack)
+            )
+        },
+        PsciConduit::Hvc => unsafe {
+            asm!(
+                "hvc #0",
+                inout("x0") x0,
+                inout("x1") arg0 => _,
+                inout("x2") arg1 => _,
+                inout("x3") arg2 => _,
+                out("x4") _, out("x5") _, out("x6") _, out("x7") _,
+                out("x8") _, out("x9") _, out("x10") _, out("x11") _,
+                out("x12") _, out("x13") _, out("x14") _, out("x15") _,
+                out("x16") _, out("x17") _,
+                options(nostack)
+            )
+        },
+    }
+    Ok(x0)
+}
+
+fn call_status(function_id: u32, arg0: u64, arg1: u64, arg2: u64) -> Result<u64, PsciErr> {
+    let ret = call(function_id, arg0, arg1, arg2)?;
+    if (ret as i32) < 0 {
+        return Err(PsciErr::from_return_value(ret as i32));
+    }
+    Ok(ret)
+}
+
+/// returns (major, minor)
+pub fn version() -> Result<(u16, u16), PsciErr> {
+    let version = call_status(PSCI_VERSION, 0, 0, 0)? as u32;
+    Ok(((version >> 16) as u16, version as u16))
+}
+
+/// returns the feature flags of `function_id` (PSCI 1.0 or later)
+pub fn features(function_id: u32) -> Result<u32, PsciErr> {
+    call_status(PSCI_FEATURES, function_id as u64, 0, 0).map(|f| f as u32)
+}
+
+/// Power up the core `target_mpidr`.
+/// The core starts at `entry_point` with the MMU off and `context_id` in x0.
+pub fn cpu_on(target_mpidr: u64, entry_point: usize, context_id: u64) -> Result<(), PsciErr> {
+    call_status(CPU_ON_64, target_mpidr, entry_point as u64, context_id).map(|_| ())
+}
+
+/// Power down the calling core. Returns only on failure.
+pub fn cpu_off() -> PsciErr {
+    match call_status(CPU_OFF, 0, 0, 0) {
+        Ok(_) => PsciErr::InternalFailure,
+        Err(e) => e,
+    }
+}
+
+/// Power off the system. Returns only on failure.
+pub fn system_off() -> PsciErr {
+    match call_status(SYSTEM_OFF, 0, 0, 0) {
+        Ok(_) => PsciErr::InternalFailure,
+        Err(e) => e,
+    }
+}
+
+/// Reset the system. Returns only on failure.
+pub fn system_reset() -> PsciErr {
+    match call_status(SYSTEM_RESET, 0, 0, 0) {
+        Ok(_) => PsciErr::InternalFailure,
+        Err(e) => e,
+    }
+}
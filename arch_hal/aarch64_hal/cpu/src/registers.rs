@@ -0,0 +1,34 @@
+#![allow(non_camel_case_types)]
+
+use typestate::bitregs;
+
+bitregs! {
+    /// SCTLR_EL2 — System Control Register (EL2)
+    /// Purpose:
+    ///     Top level control of the EL2 regime, only the bits the loader changes are named
+    pub struct SCTLR_EL2: u64 {
+        // stage 1 MMU enable of the EL2 regime
+        pub m@[0:0],
+        reserved@[1:1] [ignore],
+        // cacheability of the EL2 data accesses
+        pub c@[2:2],
+        reserved@[11:3] [ignore],
+        // cacheability of the EL2 instruction fetches
+        pub i@[12:12],
+        reserved@[63:13] [ignore],
+    }
+}
+
+bitregs! {
+    /// HCR_EL2 — Hypervisor Configuration Register
+    /// Purpose:
+    ///     Controls virtualization of the EL1&0 regime, only the bits the loader changes are named
+    pub struct HCR_EL2: u64 {
+        // stage 2 translation of the EL1&0 regime
+        pub vm@[0:0],
+        reserved@[30:1] [ignore],
+        // EL1 executes in AArch64
+        pub rw@[31:31],
+        reserved@[63:32] [ignore],
+    }
+}
@@ -0,0 +1,145 @@
+use core::arch::asm;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+// CNTHP_CTL_EL2
+const CNTHP_CTL_ENABLE: u64 = 1 << 0;
+const CNTHP_CTL_IMASK: u64 = 1 << 1;
+const CNTHP_CTL_ISTATUS: u64 = 1 << 2;
+// CNTHCTL_EL2 (HCR_EL2.E2H == 0)
+const CNTHCTL_EL1PCTEN: u64 = 1 << 0;
+const CNTHCTL_EL1PCEN: u64 = 1 << 1;
+
+/// physical count (CNTPCT_EL0)
+pub fn counter() -> u64 {
+    let counter: u64;
+    unsafe { asm!("isb", "mrs {}, cntpct_el0", out(reg) counter) };
+    counter
+}
+
+// replaces CNTFRQ_EL0 when not zero
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// counter frequency in Hz used for time math: the value of `set_frequency`, else CNTFRQ_EL0
+pub fn frequency() -> u64 {
+    match FREQUENCY.load(Ordering::Relaxed) {
+        0 => counter_frequency(),
+        frequency => frequency,
+    }
+}
+
+/// CNTFRQ_EL0 as programmed by the firmware
+pub fn counter_frequency() -> u64 {
+    let frequency: u64;
+    unsafe { asm!("mrs {}, cntfrq_el0", out(reg) frequency) };
+    frequency
+}
+
+/// use `frequency` instead of a wrong CNTFRQ_EL0, which only EL3 can write. Zero clears it
+pub fn set_frequency(frequency: u64) {
+    FREQUENCY.store(frequency, Ordering::Relaxed);
+}
+
+/// busy-wait at least `us` microseconds on the physical count
+pub fn delay_us(us: u64) {
+    let ticks = (u128::from(us) * u128::from(frequency()) / 1_000_000) as u64;
+    let start = counter();
+    while counter().wrapping_sub(start) < ticks {
+        core::hint::spin_loop();
+    }
+}
+
+/// busy-wait at least `ms` milliseconds on the physical count
+pub fn delay_ms(ms: u64) {
+    delay_us(ms.saturating_mul(1000));
+}
+
+/// the operation did not complete within the time given to `with_timeout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+/// Call `poll` until it returns `Some`, or fail once `timeout` has passed.
+/// `poll` is called at least once, so a zero timeout still checks the condition
+pub fn with_timeout<T>(
+    timeout: Duration,
+    mut poll: impl FnMut() -> Option<T>,
+) -> Result<T, Timeout> {
+    let ticks = (timeout.as_nanos() * u128::from(frequency()) / 1_000_000_000) as u64;
+    let start = counter();
+    loop {
+        if let Some(value) = poll() {
+            return Ok(value);
+        }
+        if counter().wrapping_sub(start) >= ticks {
+            return Err(Timeout);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// fire the EL2 physical timer when the physical count reaches `compare_value`
+pub fn set_hyp_timer_compare(compare_value: u64) {
+    unsafe { asm!("msr cnthp_cval_el2, {}", "isb", in(reg) compare_value) };
+}
+
+pub fn enable_hyp_timer() {
+    unsafe { asm!("msr cnthp_ctl_el2, {}", "isb", in(reg) CNTHP_CTL_ENABLE) };
+}
+
+pub fn disable_hyp_timer() {
+    unsafe { asm!("msr cnthp_ctl_el2, {}", "isb", in(reg) CNTHP_CTL_IMASK) };
+}
+
+/// whether the EL2 physical timer condition is met
+pub fn hyp_timer_fired() -> bool {
+    let ctl: u64;
+    unsafe { asm!("mrs {}, cnthp_ctl_el2", out(reg) ctl) };
+    ctl & CNTHP_CTL_ISTATUS != 0
+}
+
+/// fire the EL1 physical timer when the physical count reaches `compare_value`
+pub fn set_physical_timer_compare(compare_value: u64) {
+    unsafe { asm!("msr cntp_cval_el0, {}", "isb", in(reg) compare_value) };
+}
+
+pub fn enable_physical_timer() {
+    unsafe { asm!("msr cntp_ctl_el0, {}", "isb", in(reg) CNTHP_CTL_ENABLE) };
+}
+
+pub fn disable_physical_timer() {
+    unsafe { asm!("msr cntp_ctl_el0, {}", "isb", in(reg) CNTHP_CTL_IMASK) };
+}
+
+/// compare value of the EL1 virtual timer (CNTV_CVAL_EL0) when it is enabled and unmasked
+pub fn virtual_timer_compare() -> Option<u64> {
+    let (ctl, cval): (u64, u64);
+    unsafe { asm!("mrs {}, cntv_ctl_el0", "mrs {}, cntv_cval_el0", out(reg) ctl, out(reg) cval) };
+    (ctl & (CNTHP_CTL_ENABLE | CNTHP_CTL_IMASK) == CNTHP_CTL_ENABLE).then_some(cval)
+}
+
+/// CNTVCT_EL0 = CNTPCT_EL0 - `offset` for EL1/EL0
+pub fn set_virtual_offset(offset: u64) {
+    unsafe { asm!("msr cntvoff_el2, {}", "isb", in(reg) offset) };
+}
+
+pub fn virtual_offset() -> u64 {
+    let offset: u64;
+    unsafe { asm!("mrs {}, cntvoff_el2", out(reg) offset) };
+    offset
+}
+
+/// allow EL1/EL0 to access the physical counter (CNTPCT_EL0) and the physical timer (CNTP_*).
+/// disallowed accesses are trapped to EL2
+pub fn set_el1_physical_access(counter: bool, timer: bool) {
+    let mut cnthctl: u64;
+    unsafe { asm!("mrs {}, cnthctl_el2", out(reg) cnthctl) };
+    cnthctl &= !(CNTHCTL_EL1PCTEN | CNTHCTL_EL1PCEN);
+    if counter {
+        cnthctl |= CNTHCTL_EL1PCTEN;
+    }
+    if timer {
+        cnthctl |= CNTHCTL_EL1PCEN;
+    }
+    unsafe { asm!("msr cnthctl_el2, {}", "isb", in(reg) cnthctl) };
+}
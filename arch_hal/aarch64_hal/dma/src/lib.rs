@@ -0,0 +1,40 @@
+#![no_std]
+
+pub mod pl080;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaErr {
+    /// a transfer is still running on the channel
+    Busy,
+    /// the length is zero or above `DmaEngine::max_transfer`
+    InvalidLength,
+    /// the controller reported an error, e.g. a bus error on an address
+    Transfer,
+    /// the transfer did not finish in time and was aborted
+    Timeout,
+}
+
+/// a DMA channel copying memory into a peripheral FIFO, paced by the request line of the
+/// peripheral
+pub trait DmaEngine: Sync {
+    /// most bytes one transfer copies
+    fn max_transfer(&self) -> usize;
+
+    /// start copying `len` bytes at the bus address `src` to the peripheral register at the
+    /// bus address `dst`, one byte per access. `request` is the request line of the
+    /// peripheral on the controller. The caller cleans `src` from the data cache first and
+    /// keeps it alive until the transfer finished
+    fn start_to_peripheral(
+        &self,
+        src: usize,
+        len: usize,
+        dst: usize,
+        request: u32,
+    ) -> Result<(), DmaErr>;
+
+    /// `Ok(true)` once the transfer started last finished, `Ok(false)` while it runs
+    fn poll(&self) -> Result<bool, DmaErr>;
+
+    /// stop the running transfer, the bytes not copied yet are dropped
+    fn abort(&self);
+}
@@ -0,0 +1,177 @@
+use crate::DmaEngine;
+use crate::DmaErr;
+use typestate::ReadOnly;
+use typestate::ReadWrite;
+use typestate::Readable;
+use typestate::Writable;
+use typestate::WriteOnly;
+
+/// PL080 register frame
+#[repr(C)]
+#[derive(Debug)]
+pub struct Pl080Registers {
+    pub interrupt_status: ReadOnly<u32>,     // 0x000 DMACIntStatus
+    pub tc_status: ReadOnly<u32>,            // 0x004 DMACIntTCStatus
+    pub tc_clear: WriteOnly<u32>,            // 0x008 DMACIntTCClear
+    pub error_status: ReadOnly<u32>,         // 0x00C DMACIntErrorStatus
+    pub error_clear: WriteOnly<u32>,         // 0x010 DMACIntErrClr
+    pub raw_tc_status: ReadOnly<u32>,        // 0x014 DMACRawIntTCStatus
+    pub raw_error_status: ReadOnly<u32>,     // 0x018 DMACRawIntErrorStatus
+    pub enabled_channels: ReadOnly<u32>,     // 0x01C DMACEnbldChns
+    pub soft_burst_request: ReadWrite<u32>,  // 0x020 DMACSoftBReq
+    pub soft_single_request: ReadWrite<u32>, // 0x024 DMACSoftSReq
+    pub soft_last_burst: ReadWrite<u32>,     // 0x028 DMACSoftLBReq
+    pub soft_last_single: ReadWrite<u32>,    // 0x02C DMACSoftLSReq
+    pub configuration: ReadWrite<u32>,       // 0x030 DMACConfiguration
+    pub synchronization: ReadWrite<u32>,     // 0x034 DMACSync
+    _reserved038: [u8; 0xC8],                // 0x038..0x100
+    pub channels: [Pl080Channel; 8],         // 0x100..0x200
+    _reserved200: [u8; 0xDE0],               // 0x200..0xFE0
+    pub peripheral_id: [ReadOnly<u32>; 4],   // 0xFE0..0xFF0
+    pub pcell_id: [ReadOnly<u32>; 4],        // 0xFF0..0x1000
+                                             // @END (0x1000)
+}
+
+/// registers of one channel
+#[repr(C)]
+#[derive(Debug)]
+pub struct Pl080Channel {
+    pub source: ReadWrite<u32>,        // 0x00 DMACCxSrcAddr
+    pub destination: ReadWrite<u32>,   // 0x04 DMACCxDestAddr
+    pub linked_list: ReadWrite<u32>,   // 0x08 DMACCxLLI
+    pub control: ReadWrite<u32>,       // 0x0C DMACCxControl
+    pub configuration: ReadWrite<u32>, // 0x10 DMACCxConfiguration
+    _reserved14: [u8; 0x0C],           // 0x14..0x20
+}
+
+const _: () = assert!(size_of::<Pl080Channel>() == 0x20);
+const _: () = assert!(size_of::<Pl080Registers>() == 0x1000);
+
+// DMACConfiguration
+const CONTROLLER_ENABLE: u32 = 1 << 0;
+
+// DMACCxControl
+const TRANSFER_SIZE_MASK: u32 = 0xFFF;
+const SOURCE_BURST_OFFSET: u32 = 12;
+const DESTINATION_BURST_OFFSET: u32 = 15;
+// 4 transfers per burst, a quarter of a 16 byte FIFO
+const BURST_4: u32 = 0b001;
+const SOURCE_INCREMENT: u32 = 1 << 26;
+const TERMINAL_COUNT_INTERRUPT: u32 = 1 << 31;
+
+// DMACCxConfiguration
+const CHANNEL_ENABLE: u32 = 1 << 0;
+const DESTINATION_PERIPHERAL_OFFSET: u32 = 6;
+const FLOW_CONTROL_OFFSET: u32 = 11;
+// memory to peripheral, the DMAC is the flow controller
+const FLOW_MEMORY_TO_PERIPHERAL: u32 = 0b001;
+const ACTIVE: u32 = 1 << 17;
+const HALT: u32 = 1 << 18;
+
+/// one channel of an ARM PrimeCell PL080 DMA controller
+#[derive(Debug)]
+pub struct Pl080 {
+    registers: &'static Pl080Registers,
+    channel: usize,
+}
+
+impl Pl080 {
+    pub const COMPATIBLE: &'static str = "arm,pl080";
+    pub const CHANNELS: usize = 8;
+    const REQUEST_LINES: u32 = 16;
+
+    /// `channel` of the controller at `base`, which is enabled
+    pub fn new(base: usize, channel: usize) -> Self {
+        assert!(channel < Self::CHANNELS);
+        let registers = unsafe { &*(base as *const Pl080Registers) };
+        let configuration = registers.configuration.read();
+        registers
+            .configuration
+            .write(configuration | CONTROLLER_ENABLE);
+        Self { registers, channel }
+    }
+
+    fn channel(&self) -> &Pl080Channel {
+        &self.registers.channels[self.channel]
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.registers.enabled_channels.read() & (1 << self.channel) != 0
+    }
+}
+
+impl DmaEngine for Pl080 {
+    fn max_transfer(&self) -> usize {
+        TRANSFER_SIZE_MASK as usize
+    }
+
+    fn start_to_peripheral(
+        &self,
+        src: usize,
+        len: usize,
+        dst: usize,
+        request: u32,
+    ) -> Result<(), DmaErr> {
+        if len == 0 || len > self.max_transfer() {
+            return Err(DmaErr::InvalidLength);
+        }
+        assert!(request < Self::REQUEST_LINES);
+        if self.is_enabled() {
+            return Err(DmaErr::Busy);
+        }
+        let bit = 1 << self.channel;
+        self.registers.tc_clear.write(bit);
+        self.registers.error_clear.write(bit);
+        let channel = self.channel();
+        // the PL080 masters have a 32-bit address bus
+        channel.source.write(src as u32);
+        channel.destination.write(dst as u32);
+        channel.linked_list.write(0);
+        channel.control.write(
+            len as u32
+                | BURST_4 << SOURCE_BURST_OFFSET
+                | BURST_4 << DESTINATION_BURST_OFFSET
+                | SOURCE_INCREMENT
+                | TERMINAL_COUNT_INTERRUPT,
+        );
+        channel.configuration.write(
+            request << DESTINATION_PERIPHERAL_OFFSET
+                | FLOW_MEMORY_TO_PERIPHERAL << FLOW_CONTROL_OFFSET
+                | CHANNEL_ENABLE,
+        );
+        Ok(())
+    }
+
+    fn poll(&self) -> Result<bool, DmaErr> {
+        let bit = 1 << self.channel;
+        if self.registers.raw_error_status.read() & bit != 0 {
+            self.registers.error_clear.write(bit);
+            return Err(DmaErr::Transfer);
+        }
+        Ok(!self.is_enabled())
+    }
+
+    fn abort(&self) {
+        let channel = self.channel();
+        let configuration = channel.configuration.read();
+        // halt, let the FIFO of the channel drain, then disable it
+        channel.configuration.write(configuration | HALT);
+        for _ in 0..1000 {
+            if channel.configuration.read() & ACTIVE == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        channel
+            .configuration
+            .write(channel.configuration.read() & !(CHANNEL_ENABLE | HALT));
+        let bit = 1 << self.channel;
+        self.registers.tc_clear.write(bit);
+        self.registers.error_clear.write(bit);
+    }
+}
+
+// the channel registers are only reached through its `Pl080`, the shared ones are written
+// one channel bit at a time
+unsafe impl Send for Pl080 {}
+unsafe impl Sync for Pl080 {}
@@ -0,0 +1,331 @@
+#![no_std]
+
+mod registers;
+mod vgic;
+
+use core::time::Duration;
+
+use cpu::timer::with_timeout;
+use typestate::Readable;
+use typestate::Writable;
+
+use crate::registers::GICD_CTLR;
+use crate::registers::GICD_TYPER;
+use crate::registers::GICR_TYPER;
+use crate::registers::GICR_WAKER;
+use crate::registers::GicDistributor;
+use crate::registers::GicRedistributor;
+
+pub use vgic::DEFAULT_MAINTENANCE_INTID;
+pub use vgic::VGic;
+pub use vgic::VirtualInterrupt;
+
+/// Default priority assigned to every interrupt (lower value is higher priority)
+pub const DEFAULT_PRIORITY: u8 = 0xA0;
+/// INTID returned by the CPU interface when no interrupt is pending
+pub const SPURIOUS_INTID: u32 = 1023;
+
+const SGI_PPI_COUNT: u32 = 32;
+const REDISTRIBUTOR_FRAME_SIZE: usize = 0x1_0000;
+const WAKE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GicErr {
+    /// no redistributor in the region matches the affinity of the current PE
+    RedistributorNotFound,
+    /// INTID is out of range for this operation
+    InvalidIntId,
+    /// no free list register and the pending queue of the virtual interface is full
+    QueueFull,
+    /// the redistributor did not wake up
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Level,
+    Edge,
+}
+
+/// GICv3 driver
+///
+/// Interrupts are configured as Non-secure Group 1 with affinity routing enabled,
+/// and the CPU interface is accessed through the ICC_* system registers.
+#[derive(Debug)]
+pub struct Gicv3 {
+    distributor: &'static GicDistributor,
+    redistributor_base: usize,
+    redistributor_size: usize,
+}
+
+impl Gicv3 {
+    /// `compatible` strings which can be driven by this driver
+    pub const COMPATIBLE: &'static [&'static str] = &["arm,gic-v3"];
+
+    /// `gicd_base` and `gicr_base`/`gicr_size` are the first and second `reg` entries
+    /// of the interrupt controller node
+    pub fn new(gicd_base: usize, gicr_base: usize, gicr_size: usize) -> Self {
+        Self {
+            distributor: unsafe { &*(gicd_base as *const GicDistributor) },
+            redistributor_base: gicr_base,
+            redistributor_size: gicr_size,
+        }
+    }
+
+    /// number of INTIDs supported by the distributor (including SGI/PPI)
+    pub fn max_intid(&self) -> u32 {
+        let it_lines = (self.distributor.typer.read() & GICD_TYPER::IT_LINES_NUMBER_MASK).0
+            >> GICD_TYPER::IT_LINES_NUMBER_OFFSET;
+        (32 * (it_lines + 1)).min(SPURIOUS_INTID - 3)
+    }
+
+    fn wait_for_distributor_rwp(&self) {
+        while self.distributor.ctlr.read() & GICD_CTLR::RWP_MASK != GICD_CTLR(0) {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Initialize the distributor. Must be called once before `init_cpu_interface`.
+    pub fn init_distributor(&self) {
+        // disable distributor
+        self.distributor.ctlr.write(GICD_CTLR(0));
+        self.wait_for_distributor_rwp();
+
+        let max_intid = self.max_intid();
+        // SPIs: Non-secure Group 1, disabled, not pending, level triggered, default priority
+        for i in (SGI_PPI_COUNT / 32) as usize..max_intid.div_ceil(32) as usize {
+            self.distributor.icenabler[i].write(u32::MAX);
+            self.distributor.icpendr[i].write(u32::MAX);
+            self.distributor.icactiver[i].write(u32::MAX);
+            self.distributor.igroupr[i].write(u32::MAX);
+            self.distributor.igrpmodr[i].write(0);
+        }
+        for i in (SGI_PPI_COUNT / 16) as usize..max_intid.div_ceil(16) as usize {
+            self.distributor.icfgr[i].write(0);
+        }
+        for i in SGI_PPI_COUNT as usize..max_intid as usize {
+            self.distributor.ipriorityr[i].write(DEFAULT_PRIORITY);
+        }
+        self.wait_for_distributor_rwp();
+
+        // enable affinity routing first, then enable Group 1
+        self.distributor.ctlr.write(GICD_CTLR::ARE_NS_MASK);
+        self.wait_for_distributor_rwp();
+
+        // route every SPI to the current PE
+        let affinity = cpu::get_mpidr_affinity();
+        for i in SGI_PPI_COUNT as usize..max_intid as usize {
+            self.distributor.irouter[i].write(affinity);
+        }
+
+        self.distributor.ctlr.write(
+            GICD_CTLR::ARE_NS_MASK + GICD_CTLR::ENABLE_GRP1A_MASK + GICD_CTLR::ENABLE_GRP1_MASK,
+        );
+        self.wait_for_distributor_rwp();
+    }
+
+    /// find the redistributor of the current PE
+    fn redistributor(&self) -> Result<&'static GicRedistributor, GicErr> {
+        let affinity = cpu::get_mpidr_affinity();
+        // GICR_TYPER.Affinity uses the Aff3.Aff2.Aff1.Aff0 layout
+        let affinity = (((affinity >> 32) & 0xFF) << 24) | (affinity & 0xFF_FFFF);
+        let mut address = self.redistributor_base;
+        while address + 2 * REDISTRIBUTOR_FRAME_SIZE
+            <= self.redistributor_base + self.redistributor_size
+        {
+            let redistributor = unsafe { &*(address as *const GicRedistributor) };
+            let typer = redistributor.rd.typer.read();
+            if (typer & GICR_TYPER::AFFINITY_MASK).0 >> GICR_TYPER::AFFINITY_OFFSET == affinity {
+                return Ok(redistributor);
+            }
+            if typer & GICR_TYPER::LAST_MASK != GICR_TYPER(0) {
+                break;
+            }
+            address += if typer & GICR_TYPER::VLPIS_MASK != GICR_TYPER(0) {
+                4 * REDISTRIBUTOR_FRAME_SIZE
+            } else {
+                2 * REDISTRIBUTOR_FRAME_SIZE
+            };
+        }
+        Err(GicErr::RedistributorNotFound)
+    }
+
+    /// Wake up the redistributor of the current PE and enable the ICC_* system register interface.
+    /// Must be called on every PE which handles interrupts.
+    pub fn init_cpu_interface(&self) -> Result<(), GicErr> {
+        let redistributor = self.redistributor()?;
+
+        // wake up redistributor
+        redistributor
+            .rd
+            .waker
+            .clear_bits(GICR_WAKER::PROCESSOR_SLEEP_MASK);
+        with_timeout(WAKE_TIMEOUT, || {
+            (redistributor.rd.waker.read() & GICR_WAKER::CHILDREN_ASLEEP_MASK == GICR_WAKER(0))
+                .then_some(())
+        })
+        .map_err(|_| GicErr::Timeout)?;
+
+        // SGIs/PPIs: Non-secure Group 1, disabled, default priority
+        redistributor.sgi.icenabler0.write(u32::MAX);
+        redistributor.sgi.icpendr0.write(u32::MAX);
+        redistributor.sgi.icactiver0.write(u32::MAX);
+        redistributor.sgi.igroupr0.write(u32::MAX);
+        redistributor.sgi.igrpmodr0.write(0);
+        for priority in redistributor.sgi.ipriorityr.iter() {
+            priority.write(DEFAULT_PRIORITY);
+        }
+
+        icc::enable_system_register_interface();
+        icc::set_priority_mask(0xFF);
+        icc::set_binary_point(0);
+        icc::set_eoi_mode(false);
+        icc::enable_group1(true);
+        Ok(())
+    }
+
+    fn check_intid(&self, intid: u32) -> Result<(), GicErr> {
+        if intid >= self.max_intid() {
+            return Err(GicErr::InvalidIntId);
+        }
+        Ok(())
+    }
+
+    pub fn enable_interrupt(&self, intid: u32) -> Result<(), GicErr> {
+        self.check_intid(intid)?;
+        let bit = 1 << (intid % 32);
+        if intid < SGI_PPI_COUNT {
+            self.redistributor()?.sgi.isenabler0.write(bit);
+        } else {
+            self.distributor.isenabler[(intid / 32) as usize].write(bit);
+        }
+        Ok(())
+    }
+
+    pub fn disable_interrupt(&self, intid: u32) -> Result<(), GicErr> {
+        self.check_intid(intid)?;
+        let bit = 1 << (intid % 32);
+        if intid < SGI_PPI_COUNT {
+            self.redistributor()?.sgi.icenabler0.write(bit);
+        } else {
+            self.distributor.icenabler[(intid / 32) as usize].write(bit);
+            self.wait_for_distributor_rwp();
+        }
+        Ok(())
+    }
+
+    pub fn set_priority(&self, intid: u32, priority: u8) -> Result<(), GicErr> {
+        self.check_intid(intid)?;
+        if intid < SGI_PPI_COUNT {
+            self.redistributor()?.sgi.ipriorityr[intid as usize].write(priority);
+        } else {
+            self.distributor.ipriorityr[intid as usize].write(priority);
+        }
+        Ok(())
+    }
+
+    /// SGIs are always edge triggered, so only PPIs and SPIs can be configured
+    pub fn set_trigger_mode(&self, intid: u32, mode: TriggerMode) -> Result<(), GicErr> {
+        self.check_intid(intid)?;
+        if intid < 16 {
+            return Err(GicErr::InvalidIntId);
+        }
+        let shift = (intid % 16) * 2 + 1;
+        let icfgr = if intid < SGI_PPI_COUNT {
+            &self.redistributor()?.sgi.icfgr[1]
+        } else {
+            &self.distributor.icfgr[(intid / 16) as usize]
+        };
+        let value = match mode {
+            TriggerMode::Level => icfgr.read() & !(1 << shift),
+            TriggerMode::Edge => icfgr.read() | (1 << shift),
+        };
+        icfgr.write(value);
+        Ok(())
+    }
+
+    /// route SPI to the PE identified by `mpidr` (Aff3:Aff2:Aff1:Aff0 layout of MPIDR_EL1)
+    pub fn set_spi_route(&self, intid: u32, mpidr: u64) -> Result<(), GicErr> {
+        self.check_intid(intid)?;
+        if intid < SGI_PPI_COUNT {
+            return Err(GicErr::InvalidIntId);
+        }
+        self.distributor.irouter[intid as usize].write(mpidr & cpu::MPIDR_AFFINITY_MASK);
+        Ok(())
+    }
+}
+
+unsafe impl Send for Gicv3 {}
+
+/// GICv3 CPU interface (ICC_* system registers)
+pub mod icc {
+    use core::arch::asm;
+
+    const ICC_SRE_SRE: u64 = 1 << 0;
+    const ICC_SRE_ENABLE: u64 = 1 << 3;
+    const ICC_CTLR_EOIMODE: u64 = 1 << 1;
+
+    /// enable the system register interface at EL2 and allow EL1 to use it
+    pub fn enable_system_register_interface() {
+        let mut sre: u64;
+        unsafe { asm!("mrs {}, icc_sre_el2", out(reg) sre) };
+        sre |= ICC_SRE_SRE | ICC_SRE_ENABLE;
+        unsafe { asm!("msr icc_sre_el2, {}", "isb", in(reg) sre) };
+    }
+
+    /// interrupts with priority higher (numerically lower) than `mask` are signaled
+    pub fn set_priority_mask(mask: u8) {
+        unsafe { asm!("msr icc_pmr_el1, {}", in(reg) mask as u64) };
+    }
+
+    pub fn set_binary_point(binary_point: u8) {
+        unsafe { asm!("msr icc_bpr1_el1, {}", in(reg) binary_point as u64) };
+    }
+
+    /// when `split` is true, EOIR only drops the priority and DIR deactivates the interrupt
+    pub fn set_eoi_mode(split: bool) {
+        let mut ctlr: u64;
+        unsafe { asm!("mrs {}, icc_ctlr_el1", out(reg) ctlr) };
+        if split {
+            ctlr |= ICC_CTLR_EOIMODE;
+        } else {
+            ctlr &= !ICC_CTLR_EOIMODE;
+        }
+        unsafe { asm!("msr icc_ctlr_el1, {}", "isb", in(reg) ctlr) };
+    }
+
+    pub fn enable_group1(enable: bool) {
+        unsafe { asm!("msr icc_igrpen1_el1, {}", "isb", in(reg) enable as u64) };
+    }
+
+    /// acknowledge the highest priority pending Group 1 interrupt.
+    /// returns None on spurious interrupt
+    pub fn acknowledge() -> Option<u32> {
+        let iar: u64;
+        unsafe { asm!("mrs {}, icc_iar1_el1", out(reg) iar) };
+        let intid = (iar & 0xFF_FFFF) as u32;
+        if (super::SPURIOUS_INTID - 3..=super::SPURIOUS_INTID).contains(&intid) {
+            None
+        } else {
+            Some(intid)
+        }
+    }
+
+    pub fn end_of_interrupt(intid: u32) {
+        unsafe { asm!("msr icc_eoir1_el1, {}", "isb", in(reg) intid as u64) };
+    }
+
+    pub fn deactivate_interrupt(intid: u32) {
+        unsafe { asm!("msr icc_dir_el1, {}", "isb", in(reg) intid as u64) };
+    }
+
+    /// send SGI `intid` to the PEs in `target_list` (bit n = Aff0 n) of the cluster `mpidr`
+    pub fn send_sgi(intid: u32, mpidr: u64, target_list: u16) {
+        let sgi1r = (((mpidr >> 32) & 0xFF) << 48) // Aff3
+            | (((mpidr >> 16) & 0xFF) << 32) // Aff2
+            | (((intid as u64) & 0xF) << 24)
+            | (((mpidr >> 8) & 0xFF) << 16) // Aff1
+            | target_list as u64;
+        unsafe { asm!("msr icc_sgi1r_el1, {}", "isb", in(reg) sgi1r) };
+    }
+}
@@ -0,0 +1,174 @@
+#![allow(non_camel_case_types)]
+
+use typestate::ReadOnly;
+use typestate::ReadWrite;
+use typestate::WriteOnly;
+use typestate_macro::RawReg;
+
+const _: () = assert!(size_of::<GicDistributor>() == 0x1_0000);
+const _: () = assert!(size_of::<GicRedistributor>() == 0x2_0000);
+
+#[inline(always)]
+const fn mask(width: u32) -> u32 {
+    (1u32 << width) - 1
+}
+
+/// GICv3 Distributor (GICD_*) register frame
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct GicDistributor {
+    pub ctlr: ReadWrite<GICD_CTLR>,        // 0x0000
+    pub typer: ReadOnly<GICD_TYPER>,       // 0x0004
+    pub iidr: ReadOnly<u32>,               // 0x0008
+    pub typer2: ReadOnly<u32>,             // 0x000C
+    pub statusr: ReadWrite<u32>,           // 0x0010
+    _reserved0014: [u8; 0x2C],             // 0x0014..0x0040
+    pub setspi_nsr: WriteOnly<u32>,        // 0x0040
+    _reserved0044: [u8; 0x04],             // 0x0044..0x0048
+    pub clrspi_nsr: WriteOnly<u32>,        // 0x0048
+    _reserved004c: [u8; 0x04],             // 0x004C..0x0050
+    pub setspi_sr: WriteOnly<u32>,         // 0x0050
+    _reserved0054: [u8; 0x04],             // 0x0054..0x0058
+    pub clrspi_sr: WriteOnly<u32>,         // 0x0058
+    _reserved005c: [u8; 0x24],             // 0x005C..0x0080
+    pub igroupr: [ReadWrite<u32>; 32],     // 0x0080..0x0100
+    pub isenabler: [ReadWrite<u32>; 32],   // 0x0100..0x0180
+    pub icenabler: [ReadWrite<u32>; 32],   // 0x0180..0x0200
+    pub ispendr: [ReadWrite<u32>; 32],     // 0x0200..0x0280
+    pub icpendr: [ReadWrite<u32>; 32],     // 0x0280..0x0300
+    pub isactiver: [ReadWrite<u32>; 32],   // 0x0300..0x0380
+    pub icactiver: [ReadWrite<u32>; 32],   // 0x0380..0x0400
+    pub ipriorityr: [ReadWrite<u8>; 1024], // 0x0400..0x0800
+    pub itargetsr: [ReadWrite<u8>; 1024],  // 0x0800..0x0C00 (legacy, ARE=0 only)
+    pub icfgr: [ReadWrite<u32>; 64],       // 0x0C00..0x0D00
+    pub igrpmodr: [ReadWrite<u32>; 32],    // 0x0D00..0x0D80
+    _reserved0d80: [u8; 0x80],             // 0x0D80..0x0E00
+    pub nsacr: [ReadWrite<u32>; 64],       // 0x0E00..0x0F00
+    pub sgir: WriteOnly<u32>,              // 0x0F00 (legacy, ARE=0 only)
+    _reserved0f04: [u8; 0x0C],             // 0x0F04..0x0F10
+    pub cpendsgir: [ReadWrite<u8>; 16],    // 0x0F10..0x0F20
+    pub spendsgir: [ReadWrite<u8>; 16],    // 0x0F20..0x0F30
+    _reserved0f30: [u8; 0x50D0],           // 0x0F30..0x6000
+    pub irouter: [ReadWrite<u64>; 1024],   // 0x6000..0x8000 (INTID 0..31 are reserved)
+    _reserved8000: [u8; 0x7FD0],           // 0x8000..0xFFD0
+    pub id: [ReadOnly<u32>; 12],           // 0xFFD0..0x10000
+                                           // @END (0x10000)
+}
+
+/// GICv3 Redistributor RD_base frame (GICR_*)
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct GicRedistributorRd {
+    pub ctlr: ReadWrite<u32>,         // 0x0000
+    pub iidr: ReadOnly<u32>,          // 0x0004
+    pub typer: ReadOnly<GICR_TYPER>,  // 0x0008
+    pub statusr: ReadWrite<u32>,      // 0x0010
+    pub waker: ReadWrite<GICR_WAKER>, // 0x0014
+    _reserved0018: [u8; 0xFFB8],      // 0x0018..0xFFD0
+    pub id: [ReadOnly<u32>; 12],      // 0xFFD0..0x10000
+                                      // @END (0x10000)
+}
+
+/// GICv3 Redistributor SGI_base frame (banked SGI/PPI configuration)
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct GicRedistributorSgi {
+    _reserved0000: [u8; 0x80],           // 0x0000..0x0080
+    pub igroupr0: ReadWrite<u32>,        // 0x0080
+    _reserved0084: [u8; 0x7C],           // 0x0084..0x0100
+    pub isenabler0: ReadWrite<u32>,      // 0x0100
+    _reserved0104: [u8; 0x7C],           // 0x0104..0x0180
+    pub icenabler0: ReadWrite<u32>,      // 0x0180
+    _reserved0184: [u8; 0x7C],           // 0x0184..0x0200
+    pub ispendr0: ReadWrite<u32>,        // 0x0200
+    _reserved0204: [u8; 0x7C],           // 0x0204..0x0280
+    pub icpendr0: ReadWrite<u32>,        // 0x0280
+    _reserved0284: [u8; 0x7C],           // 0x0284..0x0300
+    pub isactiver0: ReadWrite<u32>,      // 0x0300
+    _reserved0304: [u8; 0x7C],           // 0x0304..0x0380
+    pub icactiver0: ReadWrite<u32>,      // 0x0380
+    _reserved0384: [u8; 0x7C],           // 0x0384..0x0400
+    pub ipriorityr: [ReadWrite<u8>; 32], // 0x0400..0x0420
+    _reserved0420: [u8; 0x7E0],          // 0x0420..0x0C00
+    pub icfgr: [ReadWrite<u32>; 2],      // 0x0C00..0x0C08
+    _reserved0c08: [u8; 0xF8],           // 0x0C08..0x0D00
+    pub igrpmodr0: ReadWrite<u32>,       // 0x0D00
+    _reserved0d04: [u8; 0xFC],           // 0x0D04..0x0E00
+    pub nsacr: ReadWrite<u32>,           // 0x0E00
+    _reserved0e04: [u8; 0xF1FC],         // 0x0E04..0x10000
+                                         // @END (0x10000)
+}
+
+/// One GICv3 redistributor (RD_base + SGI_base)
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct GicRedistributor {
+    pub rd: GicRedistributorRd,
+    pub sgi: GicRedistributorSgi,
+}
+
+/// Distributor Control Register
+#[repr(transparent)]
+#[derive(Clone, Copy, RawReg, PartialEq, Eq, Debug)]
+pub(crate) struct GICD_CTLR(pub u32);
+
+impl GICD_CTLR {
+    // Non-secure view (or DS=1: EnableGrp0)
+    pub const ENABLE_GRP1_OFFSET: u32 = 0;
+    pub const ENABLE_GRP1_MASK: Self = Self(1 << Self::ENABLE_GRP1_OFFSET);
+
+    // Non-secure view (or DS=1: EnableGrp1)
+    pub const ENABLE_GRP1A_OFFSET: u32 = 1;
+    pub const ENABLE_GRP1A_MASK: Self = Self(1 << Self::ENABLE_GRP1A_OFFSET);
+
+    // affinity routing enable (ARE_NS, or ARE when DS=1)
+    pub const ARE_NS_OFFSET: u32 = 4;
+    pub const ARE_NS_MASK: Self = Self(1 << Self::ARE_NS_OFFSET);
+
+    // register write pending
+    pub const RWP_OFFSET: u32 = 31;
+    pub const RWP_MASK: Self = Self(1 << Self::RWP_OFFSET);
+}
+
+/// Interrupt Controller Type Register
+#[repr(transparent)]
+#[derive(Clone, Copy, RawReg, PartialEq, Eq, Debug)]
+pub(crate) struct GICD_TYPER(pub u32);
+
+impl GICD_TYPER {
+    // supported INTID = 32 * (ITLinesNumber + 1)
+    pub const IT_LINES_NUMBER_OFFSET: u32 = 0; // NUMBITS(5)
+    pub const IT_LINES_NUMBER_MASK: Self = Self(mask(5) << Self::IT_LINES_NUMBER_OFFSET);
+}
+
+/// Redistributor Type Register
+#[repr(transparent)]
+#[derive(Clone, Copy, RawReg, PartialEq, Eq, Debug)]
+pub(crate) struct GICR_TYPER(pub u64);
+
+impl GICR_TYPER {
+    // virtual LPI supported (GICv4: the redistributor has two more 64KiB frames)
+    pub const VLPIS_OFFSET: u32 = 1;
+    pub const VLPIS_MASK: Self = Self(1 << Self::VLPIS_OFFSET);
+
+    // last redistributor in this region
+    pub const LAST_OFFSET: u32 = 4;
+    pub const LAST_MASK: Self = Self(1 << Self::LAST_OFFSET);
+
+    // Aff3.Aff2.Aff1.Aff0 of the PE handled by this redistributor
+    pub const AFFINITY_OFFSET: u32 = 32; // NUMBITS(32)
+    pub const AFFINITY_MASK: Self = Self((u32::MAX as u64) << Self::AFFINITY_OFFSET);
+}
+
+/// Redistributor Wake Register
+#[repr(transparent)]
+#[derive(Clone, Copy, RawReg, PartialEq, Eq, Debug)]
+pub(crate) struct GICR_WAKER(pub u32);
+
+impl GICR_WAKER {
+    pub const PROCESSOR_SLEEP_OFFSET: u32 = 1;
+    pub const PROCESSOR_SLEEP_MASK: Self = Self(1 << Self::PROCESSOR_SLEEP_OFFSET);
+
+    pub const CHILDREN_ASLEEP_OFFSET: u32 = 2;
+    pub const CHILDREN_ASLEEP_MASK: Self = Self(1 << Self::CHILDREN_ASLEEP_OFFSET);
+}
@@ -0,0 +1,295 @@
+#![allow(non_camel_case_types)]
+
+use core::arch::asm;
+
+use typestate::bitregs;
+
+use crate::GicErr;
+
+/// maintenance interrupt which QEMU virt and most boards wire to PPI 9
+pub const DEFAULT_MAINTENANCE_INTID: u32 = 25;
+/// number of virtual interrupts which can wait for a free list register
+const PENDING_QUEUE_SIZE: usize = 64;
+
+bitregs! {
+    /// ICH_HCR_EL2 — Interrupt Controller Hyp Control Register
+    pub(crate) struct ICH_HCR_EL2: u64 {
+        // global enable of the virtual CPU interface
+        pub(crate) en@[0:0],
+        // underflow maintenance interrupt enable (zero or one valid LR)
+        pub(crate) uie@[1:1],
+        // list register entry not present maintenance interrupt enable
+        pub(crate) lrenpie@[2:2],
+        // no pending maintenance interrupt enable (no LR in the pending state)
+        pub(crate) npie@[3:3],
+        pub(crate) vgrp0eie@[4:4],
+        pub(crate) vgrp0die@[5:5],
+        pub(crate) vgrp1eie@[6:6],
+        pub(crate) vgrp1die@[7:7],
+        // FEAT_GICv4p1
+        pub(crate) vsgieoicount@[8:8],
+        reserved@[9:9] [res0],
+        // trap common EL1 ICC_* accesses
+        pub(crate) tc@[10:10],
+        pub(crate) tall0@[11:11],
+        pub(crate) tall1@[12:12],
+        pub(crate) tsei@[13:13],
+        pub(crate) tdir@[14:14],
+        pub(crate) dvim@[15:15],
+        reserved@[26:16] [res0],
+        // number of EOIs that could not be matched to a list register
+        pub(crate) eoicount@[31:27],
+        reserved@[63:32] [res0],
+    }
+}
+
+bitregs! {
+    /// ICH_VTR_EL2 — Interrupt Controller VGIC Type Register
+    /// # Safety
+    ///     all field is ReadOnly
+    pub(crate) struct ICH_VTR_EL2: u64 {
+        // number of implemented list registers minus one
+        pub(crate) list_regs@[4:0],
+        reserved@[17:5] [res0],
+        pub(crate) dvim@[18:18],
+        pub(crate) tds@[19:19],
+        pub(crate) nv4@[20:20],
+        pub(crate) a3v@[21:21],
+        pub(crate) seis@[22:22],
+        pub(crate) id_bits@[25:23],
+        pub(crate) pre_bits@[28:26],
+        pub(crate) pri_bits@[31:29],
+        reserved@[63:32] [res0],
+    }
+}
+
+bitregs! {
+    /// ICH_MISR_EL2 — Interrupt Controller Maintenance Interrupt State Register
+    /// # Safety
+    ///     all field is ReadOnly
+    pub(crate) struct ICH_MISR_EL2: u64 {
+        pub(crate) eoi@[0:0],
+        pub(crate) u@[1:1],
+        pub(crate) lrenp@[2:2],
+        pub(crate) np@[3:3],
+        pub(crate) vgrp0e@[4:4],
+        pub(crate) vgrp0d@[5:5],
+        pub(crate) vgrp1e@[6:6],
+        pub(crate) vgrp1d@[7:7],
+        reserved@[63:8] [res0],
+    }
+}
+
+bitregs! {
+    /// ICH_LR<n>_EL2 — Interrupt Controller List Registers
+    pub(crate) struct ICH_LR_EL2: u64 {
+        pub(crate) vintid@[31:0],
+        // physical INTID when HW == 1.
+        // when HW == 0, bit[41] requests a maintenance interrupt on EOI
+        pub(crate) pintid@[44:32],
+        reserved@[47:45] [res0],
+        pub(crate) priority@[55:48],
+        reserved@[59:56] [res0],
+        pub(crate) group@[60:60],
+        pub(crate) hw@[61:61],
+        pub(crate) state@[63:62] as LrState {
+            Invalid = 0b00,
+            Pending = 0b01,
+            Active = 0b10,
+            PendingActive = 0b11,
+        },
+    }
+}
+
+const ICH_LR_EOI: u64 = 1 << 9; // bit[41] of the LR, relative to pINTID
+
+// ICH_LR<n>_EL2 can only be accessed with an immediate register number
+macro_rules! ich_lr_accessors {
+    ($($n:literal),*) => {
+        fn read_lr(index: usize) -> ICH_LR_EL2 {
+            let lr: u64;
+            match index {
+                $($n => unsafe { asm!(concat!("mrs {}, ich_lr", stringify!($n), "_el2"), out(reg) lr) },)*
+                _ => unreachable!(),
+            }
+            ICH_LR_EL2::from_bits(lr)
+        }
+
+        fn write_lr(index: usize, lr: ICH_LR_EL2) {
+            match index {
+                // the isb makes ICH_ELRSR_EL2 reflect the new LR before the next free one is picked
+                $($n => unsafe { asm!(concat!("msr ich_lr", stringify!($n), "_el2, {}"), "isb", in(reg) lr.bits()) },)*
+                _ => unreachable!(),
+            }
+        }
+    };
+}
+
+ich_lr_accessors!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+
+fn read_hcr() -> ICH_HCR_EL2 {
+    let hcr: u64;
+    unsafe { asm!("mrs {}, ich_hcr_el2", out(reg) hcr) };
+    ICH_HCR_EL2::from_bits(hcr)
+}
+
+fn write_hcr(hcr: ICH_HCR_EL2) {
+    unsafe { asm!("msr ich_hcr_el2, {}", "isb", in(reg) hcr.bits()) };
+}
+
+/// Virtual interrupt to be delivered to the guest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualInterrupt {
+    /// INTID seen by the guest
+    pub vintid: u32,
+    pub priority: u8,
+    /// physical INTID which is deactivated when the guest deactivates `vintid`.
+    /// the physical interrupt must have been acknowledged and dropped with split EOI mode
+    pub pintid: Option<u32>,
+}
+
+/// GICv3 virtual CPU interface (ICH_* system registers)
+///
+/// The caller is responsible for routing physical IRQ/FIQ to EL2 (HCR_EL2.IMO/FMO)
+/// while the guest is running and for calling `handle_maintenance_interrupt`
+/// when the maintenance interrupt fires.
+#[derive(Debug)]
+pub struct VGic {
+    num_list_registers: usize,
+    pending: [Option<VirtualInterrupt>; PENDING_QUEUE_SIZE],
+}
+
+impl VGic {
+    pub fn new() -> Self {
+        let vtr: u64;
+        unsafe { asm!("mrs {}, ich_vtr_el2", out(reg) vtr) };
+        let vtr = ICH_VTR_EL2::from_bits(vtr);
+        Self {
+            num_list_registers: vtr.get(ICH_VTR_EL2::list_regs) as usize + 1,
+            pending: [None; PENDING_QUEUE_SIZE],
+        }
+    }
+
+    pub fn num_list_registers(&self) -> usize {
+        self.num_list_registers
+    }
+
+    /// clear all list registers and enable the virtual CPU interface
+    pub fn init(&mut self) {
+        for i in 0..self.num_list_registers {
+            write_lr(i, ICH_LR_EL2::new());
+        }
+        self.pending = [None; PENDING_QUEUE_SIZE];
+        unsafe { asm!("msr ich_vmcr_el2, xzr") };
+        write_hcr(ICH_HCR_EL2::new().set(ICH_HCR_EL2::en, 1));
+    }
+
+    fn empty_list_register(&self) -> Option<usize> {
+        let elrsr: u64;
+        unsafe { asm!("mrs {}, ich_elrsr_el2", out(reg) elrsr) };
+        let index = elrsr.trailing_zeros() as usize;
+        (index < self.num_list_registers).then_some(index)
+    }
+
+    fn is_in_list_register(&self, vintid: u32) -> bool {
+        (0..self.num_list_registers).any(|i| {
+            let lr = read_lr(i);
+            lr.get_enum::<_, LrState>(ICH_LR_EL2::state) != Some(LrState::Invalid)
+                && lr.get(ICH_LR_EL2::vintid) == vintid as u64
+        })
+    }
+
+    fn load_list_register(index: usize, irq: &VirtualInterrupt) {
+        let mut lr = ICH_LR_EL2::new()
+            .set(ICH_LR_EL2::vintid, irq.vintid as u64)
+            .set(ICH_LR_EL2::priority, irq.priority as u64)
+            .set(ICH_LR_EL2::group, 1)
+            .set_enum(ICH_LR_EL2::state, LrState::Pending);
+        lr = match irq.pintid {
+            Some(pintid) => lr
+                .set(ICH_LR_EL2::hw, 1)
+                .set(ICH_LR_EL2::pintid, pintid as u64),
+            None => lr.set(ICH_LR_EL2::pintid, ICH_LR_EOI),
+        };
+        write_lr(index, lr);
+    }
+
+    /// whether an interrupt is pending in a list register or waiting for one
+    pub fn has_pending(&self) -> bool {
+        self.pending.iter().any(|p| p.is_some())
+            || (0..self.num_list_registers).any(|i| {
+                matches!(
+                    read_lr(i).get_enum::<_, LrState>(ICH_LR_EL2::state),
+                    Some(LrState::Pending | LrState::PendingActive)
+                )
+            })
+    }
+
+    /// Make `irq` pending for the guest.
+    /// When every list register is in use, `irq` is queued and loaded from the maintenance interrupt.
+    pub fn inject(&mut self, irq: VirtualInterrupt) -> Result<(), GicErr> {
+        if irq.vintid >= crate::SPURIOUS_INTID - 3 {
+            return Err(GicErr::InvalidIntId);
+        }
+        if self.is_in_list_register(irq.vintid)
+            || self
+                .pending
+                .iter()
+                .flatten()
+                .any(|p| p.vintid == irq.vintid)
+        {
+            // already pending, interrupts are not counted
+            return Ok(());
+        }
+        if let Some(index) = self.empty_list_register() {
+            Self::load_list_register(index, &irq);
+            return Ok(());
+        }
+        let slot = self
+            .pending
+            .iter_mut()
+            .find(|p| p.is_none())
+            .ok_or(GicErr::QueueFull)?;
+        *slot = Some(irq);
+        // get a maintenance interrupt once no list register is pending
+        write_hcr(read_hcr().set(ICH_HCR_EL2::npie, 1));
+        Ok(())
+    }
+
+    /// Handle the maintenance interrupt: release completed list registers
+    /// and refill them with queued interrupts (highest priority first).
+    pub fn handle_maintenance_interrupt(&mut self) {
+        let misr: u64;
+        unsafe { asm!("mrs {}, ich_misr_el2", out(reg) misr) };
+        let misr = ICH_MISR_EL2::from_bits(misr);
+        if misr.get(ICH_MISR_EL2::eoi) != 0 {
+            let eisr: u64;
+            unsafe { asm!("mrs {}, ich_eisr_el2", out(reg) eisr) };
+            for i in (0..self.num_list_registers).filter(|i| eisr & (1 << i) != 0) {
+                write_lr(i, ICH_LR_EL2::new());
+            }
+        }
+
+        while let Some(index) = self.empty_list_register() {
+            let Some(next) = self
+                .pending
+                .iter_mut()
+                .filter(|p| p.is_some())
+                .min_by_key(|p| p.unwrap().priority)
+            else {
+                break;
+            };
+            Self::load_list_register(index, &next.take().unwrap());
+        }
+
+        if self.pending.iter().all(|p| p.is_none()) {
+            write_hcr(read_hcr().set(ICH_HCR_EL2::npie, 0));
+        }
+    }
+}
+
+impl Default for VGic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
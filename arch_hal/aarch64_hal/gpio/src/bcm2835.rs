@@ -0,0 +1,62 @@
+use crate::Gpio;
+use typestate::ReadWrite;
+use typestate::Readable;
+use typestate::Writable;
+use typestate::WriteOnly;
+
+/// BCM2835 GPIO registers up to the pin levels
+#[repr(C)]
+#[derive(Debug)]
+pub struct Bcm2835GpioRegisters {
+    pub function_select: [ReadWrite<u32>; 6], // 0x00 GPFSEL0..5
+    _reserved18: [u8; 0x04],                  // 0x18..0x1C
+    pub set: [WriteOnly<u32>; 2],             // 0x1C GPSET0..1
+    _reserved24: [u8; 0x04],                  // 0x24..0x28
+    pub clear: [WriteOnly<u32>; 2],           // 0x28 GPCLR0..1
+    _reserved30: [u8; 0x04],                  // 0x30..0x34
+    pub level: [ReadWrite<u32>; 2],           // 0x34 GPLEV0..1
+                                              // @END (0x3C)
+}
+
+const _: () = assert!(size_of::<Bcm2835GpioRegisters>() == 0x3C);
+
+/// GPIO of the BCM2835 and the BCM2711, 54 pins with ten 3-bit function fields per register
+#[derive(Debug)]
+pub struct Bcm2835Gpio {
+    registers: &'static Bcm2835GpioRegisters,
+}
+
+impl Bcm2835Gpio {
+    pub const COMPATIBLE: &'static str = "brcm,bcm2835-gpio";
+    pub const COMPATIBLE_BCM2711: &'static str = "brcm,bcm2711-gpio";
+    const PINS: u32 = 54;
+    const FUNCTION_OUTPUT: u32 = 0b001;
+
+    pub fn new(base: usize) -> Self {
+        Self {
+            registers: unsafe { &*(base as *const Bcm2835GpioRegisters) },
+        }
+    }
+}
+
+impl Gpio for Bcm2835Gpio {
+    fn pin_count(&self) -> u32 {
+        Self::PINS
+    }
+
+    fn set_output(&self, pin: u32) {
+        let register = &self.registers.function_select[(pin / 10) as usize];
+        let shift = (pin % 10) * 3;
+        let value = register.read() & !(0b111 << shift);
+        register.write(value | (Self::FUNCTION_OUTPUT << shift));
+    }
+
+    fn write(&self, pin: u32, high: bool) {
+        let registers = if high {
+            &self.registers.set
+        } else {
+            &self.registers.clear
+        };
+        registers[(pin / 32) as usize].write(1 << (pin % 32));
+    }
+}
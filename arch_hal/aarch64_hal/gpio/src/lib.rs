@@ -0,0 +1,16 @@
+#![no_std]
+
+pub mod bcm2835;
+pub mod pl061;
+
+/// output pins of a GPIO controller
+pub trait Gpio {
+    /// number of pins the controller drives
+    fn pin_count(&self) -> u32;
+
+    /// make `pin` a GPIO output
+    fn set_output(&self, pin: u32);
+
+    /// drive `pin` high or low
+    fn write(&self, pin: u32, high: bool);
+}
@@ -0,0 +1,61 @@
+use crate::Gpio;
+use typestate::ReadWrite;
+use typestate::Readable;
+use typestate::Writable;
+
+/// PL061 register frame
+#[repr(C)]
+#[derive(Debug)]
+pub struct Pl061Registers {
+    // 0x000..0x400 GPIODATA, address bits [9:2] mask the pins accessed
+    pub data: [ReadWrite<u32>; 0x100],
+    pub direction: ReadWrite<u32>,               // 0x400 GPIODIR
+    pub interrupt_sense: ReadWrite<u32>,         // 0x404 GPIOIS
+    pub interrupt_both_edges: ReadWrite<u32>,    // 0x408 GPIOIBE
+    pub interrupt_event: ReadWrite<u32>,         // 0x40C GPIOIEV
+    pub interrupt_mask: ReadWrite<u32>,          // 0x410 GPIOIE
+    pub raw_interrupt_status: ReadWrite<u32>,    // 0x414 GPIORIS
+    pub masked_interrupt_status: ReadWrite<u32>, // 0x418 GPIOMIS
+    pub interrupt_clear: ReadWrite<u32>,         // 0x41C GPIOIC
+    pub alternate_function: ReadWrite<u32>,      // 0x420 GPIOAFSEL
+    _reserved0424: [u8; 0xBDC],                  // 0x424..0x1000
+                                                 // @END (0x1000)
+}
+
+const _: () = assert!(size_of::<Pl061Registers>() == 0x1000);
+
+/// ARM PrimeCell GPIO, eight pins
+#[derive(Debug)]
+pub struct Pl061 {
+    registers: &'static Pl061Registers,
+}
+
+impl Pl061 {
+    pub const COMPATIBLE: &'static str = "arm,pl061";
+    const PINS: u32 = 8;
+
+    pub fn new(base: usize) -> Self {
+        Self {
+            registers: unsafe { &*(base as *const Pl061Registers) },
+        }
+    }
+}
+
+impl Gpio for Pl061 {
+    fn pin_count(&self) -> u32 {
+        Self::PINS
+    }
+
+    fn set_output(&self, pin: u32) {
+        let mask = 1 << pin;
+        let afsel = self.registers.alternate_function.read();
+        self.registers.alternate_function.write(afsel & !mask);
+        let direction = self.registers.direction.read();
+        self.registers.direction.write(direction | mask);
+    }
+
+    fn write(&self, pin: u32, high: bool) {
+        // only the pin selected by the address is written
+        self.registers.data[1 << pin].write(if high { 1 << pin } else { 0 });
+    }
+}
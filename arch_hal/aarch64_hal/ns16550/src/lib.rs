@@ -0,0 +1,125 @@
+#![no_std]
+
+//! NS16550 compatible UART, the 8250 family found on most non-Arm SoCs
+//!
+//! The register stride and access width vary between SoCs, they come from the `reg-shift`
+//! and `reg-io-width` properties of the dtb node.
+
+use core::fmt;
+use core::ptr;
+use core::time::Duration;
+
+use cpu::timer::with_timeout;
+
+// a 16-byte FIFO drains in about 17ms at 9600 baud
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+// register indexes, scaled by `reg-shift`
+const RBR_THR_DLL: usize = 0;
+const IER_DLM: usize = 1;
+const FCR: usize = 2;
+const LCR: usize = 3;
+const MCR: usize = 4;
+const LSR: usize = 5;
+
+const FCR_ENABLE: u32 = 1 << 0;
+const FCR_CLEAR_RX: u32 = 1 << 1;
+const FCR_CLEAR_TX: u32 = 1 << 2;
+const LCR_WLEN8: u32 = 0b11;
+const LCR_DLAB: u32 = 1 << 7;
+const MCR_DTR: u32 = 1 << 0;
+const MCR_RTS: u32 = 1 << 1;
+const LSR_DR: u32 = 1 << 0;
+const LSR_THRE: u32 = 1 << 5;
+const LSR_TEMT: u32 = 1 << 6;
+
+#[derive(Debug)]
+pub struct Ns16550Uart {
+    base_address: usize,
+    reg_shift: u32,
+    // bytes per access, 1 or 4
+    reg_io_width: u32,
+}
+
+impl Ns16550Uart {
+    pub const COMPATIBLES: &'static [&'static str] = &["ns16550a", "ns16550", "snps,dw-apb-uart"];
+
+    /// `reg_shift` and `reg_io_width` as in the dtb, 0 and 1 when the node has none
+    pub fn new(base_address: usize, reg_shift: u32, reg_io_width: u32) -> Self {
+        Self {
+            base_address,
+            reg_shift,
+            reg_io_width,
+        }
+    }
+
+    fn read_reg(&self, index: usize) -> u32 {
+        let address = self.base_address + (index << self.reg_shift);
+        match self.reg_io_width {
+            4 => unsafe { ptr::read_volatile(address as *const u32) },
+            _ => unsafe { ptr::read_volatile(address as *const u8) }.into(),
+        }
+    }
+
+    fn write_reg(&self, index: usize, value: u32) {
+        let address = self.base_address + (index << self.reg_shift);
+        match self.reg_io_width {
+            4 => unsafe { ptr::write_volatile(address as *mut u32, value) },
+            _ => unsafe { ptr::write_volatile(address as *mut u8, value as u8) },
+        }
+    }
+
+    /// Wait until every queued byte has been transmitted. Gives up after `FLUSH_TIMEOUT`,
+    /// a UART held off by flow control never drains
+    pub fn flush(&self) {
+        let _ = with_timeout(FLUSH_TIMEOUT, || {
+            (self.read_reg(LSR) & LSR_TEMT != 0).then_some(())
+        });
+    }
+
+    /// program 8N1 at `baudrate` with the FIFOs on and the interrupts off
+    pub fn init(&self, uart_clk: u32, baudrate: u32) {
+        self.flush();
+        self.write_reg(IER_DLM, 0);
+        let divisor = (uart_clk + 8 * baudrate) / (16 * baudrate);
+        self.write_reg(LCR, LCR_DLAB);
+        self.write_reg(RBR_THR_DLL, divisor & 0xff);
+        self.write_reg(IER_DLM, (divisor >> 8) & 0xff);
+        self.write_reg(LCR, LCR_WLEN8);
+        self.write_reg(FCR, FCR_ENABLE | FCR_CLEAR_RX | FCR_CLEAR_TX);
+        self.write_reg(MCR, MCR_DTR | MCR_RTS);
+    }
+
+    fn pushb(&self, ch: u8) {
+        while self.read_reg(LSR) & LSR_THRE == 0 {
+            core::hint::spin_loop();
+        }
+        self.write_reg(RBR_THR_DLL, ch.into());
+    }
+
+    pub fn write(&self, char: &str) {
+        for &i in char.as_bytes() {
+            if i == b'\n' {
+                self.pushb(b'\r');
+            }
+            self.pushb(i);
+        }
+    }
+
+    /// send `byte` as is, without the LF to CRLF conversion of `write`
+    pub fn write_byte(&self, byte: u8) {
+        self.pushb(byte);
+    }
+
+    /// returns `None` instead of waiting when nothing was received
+    pub fn try_read_char(&self) -> Option<u8> {
+        (self.read_reg(LSR) & LSR_DR != 0).then(|| self.read_reg(RBR_THR_DLL) as u8)
+    }
+}
+
+impl fmt::Write for Ns16550Uart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s);
+        Ok(())
+    }
+}
@@ -83,6 +83,10 @@ bitregs! {
         //   OA[49:48] live in descriptor bits[49:48], and OA[51:50] live in bits[9:8].
         pub(crate) oab@[47:21],
 
+        // OA[20:17] and [15:12] of smaller blocks, unused by the 2MiB and larger blocks we map
+        reserved@[20:17] [res0],
+        reserved@[15:12] [res0],
+
         // nT — “No-translate” hint for size-change sequences.
         //   Requires FEAT_BBML1. When set, implementation may avoid caching this
         //   translation and can fault instead of caching to avoid TLB conflicts.
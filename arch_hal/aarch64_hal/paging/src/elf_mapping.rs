@@ -0,0 +1,98 @@
+//! Stage 2 mappings of the segments of a guest kernel loaded from an ELF file
+//!
+//! The segments are loaded at their physical address, so the IPA of a mapping equals its PA.
+//! Segments are widened to whole pages, a page shared by two segments gets the permissions
+//! of both, and device regions are left out so they can be mapped, or trapped, on their own.
+
+use alloc::vec::Vec;
+
+use elf::ElfPermissions;
+use elf::ProgramHeaderData;
+
+use crate::PagingErr;
+use crate::Stage2PagingSetting;
+
+const PAGE_SIZE: usize = 0x1000;
+
+fn permissions_from_elf(permission: ElfPermissions) -> u8 {
+    let mut permissions = 0;
+    if permission & ElfPermissions::READABLE == ElfPermissions::READABLE {
+        permissions |= Stage2PagingSetting::READ;
+    }
+    if permission & ElfPermissions::WRITABLE == ElfPermissions::WRITABLE {
+        permissions |= Stage2PagingSetting::WRITE;
+    }
+    if permission & ElfPermissions::EXECUTABLE == ElfPermissions::EXECUTABLE {
+        permissions |= Stage2PagingSetting::EXECUTE;
+    }
+    permissions
+}
+
+/// Mappings of `segments`, from `Elf64::iterate_program_header`, in ascending order as
+/// `Stage2Paging::set_stage2paging` expects. `device_regions` are (address, size) pairs
+/// which are not mapped even where a segment covers them. Empty segments are skipped
+pub fn stage2_settings_from_elf(
+    segments: &[ProgramHeaderData],
+    device_regions: &[(usize, usize)],
+) -> Result<Vec<Stage2PagingSetting>, PagingErr> {
+    // page aligned [start, end) and permissions of every segment
+    let mut ranges = Vec::with_capacity(segments.len());
+    for segment in segments.iter().filter(|s| s.mem_len() != 0) {
+        let start = usize::try_from(segment.address()).map_err(|_| PagingErr::AddressOverflow)?;
+        let end = usize::try_from(segment.mem_len())
+            .ok()
+            .and_then(|len| start.checked_add(len))
+            .and_then(|end| end.checked_next_multiple_of(PAGE_SIZE))
+            .ok_or(PagingErr::AddressOverflow)?;
+        let start = start & !(PAGE_SIZE - 1);
+        ranges.push((start, end, permissions_from_elf(segment.permission())));
+    }
+
+    // split at every boundary of a segment or a device region, then merge the pieces back
+    let mut boundaries = Vec::with_capacity(ranges.len() * 2 + device_regions.len() * 2);
+    for &(start, end, _) in &ranges {
+        boundaries.push(start);
+        boundaries.push(end);
+    }
+    for &(address, size) in device_regions {
+        let end = address
+            .checked_add(size)
+            .ok_or(PagingErr::AddressOverflow)?;
+        boundaries.push(address & !(PAGE_SIZE - 1));
+        boundaries.push(end.next_multiple_of(PAGE_SIZE));
+    }
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut settings: Vec<Stage2PagingSetting> = Vec::new();
+    for piece in boundaries.windows(2) {
+        let (start, end) = (piece[0], piece[1]);
+        let is_device = device_regions
+            .iter()
+            .any(|&(address, size)| start < address + size && address < end);
+        if is_device {
+            continue;
+        }
+        let permissions = ranges
+            .iter()
+            .filter(|&&(s, e, _)| s <= start && end <= e)
+            .fold(None, |acc: Option<u8>, &(_, _, p)| {
+                Some(acc.unwrap_or(0) | p)
+            });
+        let Some(permissions) = permissions else {
+            continue;
+        };
+        match settings.last_mut() {
+            Some(last) if last.ipa + last.size == start && last.permissions == permissions => {
+                last.size += end - start;
+            }
+            _ => settings.push(Stage2PagingSetting {
+                ipa: start,
+                pa: start,
+                size: end - start,
+                permissions,
+            }),
+        }
+    }
+    Ok(settings)
+}
@@ -4,29 +4,40 @@
 //! TODO
 //! Stage 2 Pagingをとりあえず作成する
 //! とりあえずメモリサイズ48bit、4KiB pagingで大きなサイズの対応は無し
-//! 
+//!
 //! memo:
 //! - VTCR_EL2 virtualization translation control register
-//!     - 
+//!     -
 
 use alloc::boxed::Box;
 
 extern crate alloc;
 
 mod descriptor;
+mod elf_mapping;
 mod registers;
 
+pub use elf_mapping::stage2_settings_from_elf;
+
 pub struct Stage2Paging {
     before: Box<[Stage2PagingSetting]>,
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub struct Stage2PagingSetting {
     pub ipa: usize,
     pub pa: usize,
     pub size: usize,
+    /// `READ`, `WRITE` and `EXECUTE`
     pub permissions: u8,
 }
 
+impl Stage2PagingSetting {
+    pub const READ: u8 = 1 << 0;
+    pub const WRITE: u8 = 1 << 1;
+    pub const EXECUTE: u8 = 1 << 2;
+}
+
 impl Stage2Paging {
     pub fn activate(&self) {
         todo!()
@@ -34,9 +45,7 @@ impl Stage2Paging {
 
     /// # Safety
     ///     dataは必ず昇順
-    pub fn set_stage2paging(
-        data: &[Stage2PagingSetting],
-    ) -> Result<Self, PagingErr> {
+    pub fn set_stage2paging(data: &[Stage2PagingSetting]) -> Result<Self, PagingErr> {
         let data = Box::from(data);
         todo!()
     }
@@ -44,5 +53,6 @@ impl Stage2Paging {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PagingErr {
-
+    /// an address or size does not fit the address space
+    AddressOverflow,
 }
@@ -1,7 +1,13 @@
 #![no_std]
 
+use core::cell::Cell;
 use core::fmt;
+use core::time::Duration;
 
+use cpu::cache::clean_dcache_range;
+use cpu::timer::with_timeout;
+use dma::DmaEngine;
+use dma::DmaErr;
 use typestate::ReadOnly;
 use typestate::ReadWrite;
 use typestate::Readable;
@@ -9,6 +15,13 @@ use typestate::Writable;
 use typestate::WriteOnly;
 use typestate_macro::RawReg;
 
+// a 32-byte FIFO drains in about 35ms at 9600 baud
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+// a byte takes about 1ms at 9600 baud
+const DMA_BYTE_TIMEOUT: Duration = Duration::from_millis(2);
+// output shorter than the FIFO is written by the CPU, it would not wait for the FIFO anyway
+const DMA_MIN_LEN: usize = 32;
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct Pl011Peripherals {
@@ -27,7 +40,7 @@ pub struct Pl011Peripherals {
     pub raw_interrupt_status: ReadOnly<u32>,         // 0x003C
     pub masked_interrupt_status: ReadOnly<u32>,      // 0x0040
     pub interrupt_clear: WriteOnly<UARTICR>,         // 0x0044
-    pub dma_control: ReadWrite<u32>,                 // 0x0048
+    pub dma_control: ReadWrite<UARTDMACR>,           // 0x0048
     _reserved004c: [u8; 3988],                       // 0x004C..0x0FE0
     pub peripheral_id: [ReadOnly<u32>; 4],           // 0x0FE0..0x0FF0
     pub pcell_id: [ReadOnly<u32>; 4],                // 0x0FF0..0x1000
@@ -219,29 +232,197 @@ impl UARTICR {
     pub const ALL_MASK: Self = Self(mask(11) << Self::ALL_OFFSET);
 }
 
+/// UART DMA Control Register
+#[repr(transparent)]
+#[derive(Clone, Copy, RawReg, PartialEq, Eq, Debug)]
+pub struct UARTDMACR(pub u32);
+
+impl UARTDMACR {
+    pub const RXDMAE_OFFSET: u32 = 0; // receive DMA enable
+    pub const RXDMAE_MASK: Self = Self(1 << Self::RXDMAE_OFFSET);
+
+    pub const TXDMAE_OFFSET: u32 = 1; // transmit DMA enable
+    pub const TXDMAE_MASK: Self = Self(1 << Self::TXDMAE_OFFSET);
+
+    pub const DMAONERR_OFFSET: u32 = 2; // DMA on error
+    pub const DMAONERR_MASK: Self = Self(1 << Self::DMAONERR_OFFSET);
+}
+
+/// Transmit DMA of a `Pl011Uart`: the output is converted to CRLF into `buffer` and handed
+/// to `engine`, the CPU only waits for a transfer when the next one needs the buffer
+pub struct Pl011Dma {
+    engine: &'static dyn DmaEngine,
+    /// request line of the transmit FIFO on `engine`
+    request: u32,
+    /// bus address of UARTDR
+    data_register: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+    /// length of the running transfer, 0 if none
+    in_flight: Cell<usize>,
+    /// a transfer failed, the UART is written by the CPU from then on
+    failed: Cell<bool>,
+}
+
+impl Pl011Dma {
+    /// `buffer` must be reachable by `engine` at its address, e.g. identity mapped.
+    /// `data_register` is the bus address of UARTDR, the base address of the UART
+    pub fn new(
+        engine: &'static dyn DmaEngine,
+        request: u32,
+        data_register: usize,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            engine,
+            request,
+            data_register,
+            buffer_len: buffer.len().min(engine.max_transfer()),
+            buffer: buffer.as_mut_ptr(),
+            in_flight: Cell::new(0),
+            failed: Cell::new(false),
+        }
+    }
+
+    fn is_usable(&self) -> bool {
+        !self.failed.get() && self.buffer_len >= 2
+    }
+
+    /// wait for the running transfer. A failed or stuck one is aborted and the DMA is not
+    /// used again
+    fn wait(&self) -> Result<(), DmaErr> {
+        let len = self.in_flight.replace(0);
+        if len == 0 {
+            return Ok(());
+        }
+        let timeout = FLUSH_TIMEOUT + DMA_BYTE_TIMEOUT * len as u32;
+        let result = match with_timeout(timeout, || match self.engine.poll() {
+            Ok(true) => Some(Ok(())),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }) {
+            Ok(result) => result,
+            Err(_) => Err(DmaErr::Timeout),
+        };
+        if result.is_err() {
+            self.engine.abort();
+            self.failed.set(true);
+        }
+        result
+    }
+
+    // convert the head of `bytes` to CRLF into the buffer, returns (bytes taken, buffer length)
+    fn fill(&self, bytes: &[u8]) -> (usize, usize) {
+        let buffer = unsafe { core::slice::from_raw_parts_mut(self.buffer, self.buffer_len) };
+        let mut len = 0;
+        for (taken, &byte) in bytes.iter().enumerate() {
+            let need = if byte == b'\n' { 2 } else { 1 };
+            if len + need > buffer.len() {
+                return (taken, len);
+            }
+            if byte == b'\n' {
+                buffer[len] = b'\r';
+                len += 1;
+            }
+            buffer[len] = byte;
+            len += 1;
+        }
+        (bytes.len(), len)
+    }
+}
+
+impl fmt::Debug for Pl011Dma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pl011Dma")
+            .field("request", &self.request)
+            .field("data_register", &self.data_register)
+            .field("buffer", &self.buffer)
+            .field("buffer_len", &self.buffer_len)
+            .field("in_flight", &self.in_flight.get())
+            .field("failed", &self.failed.get())
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub struct Pl011Uart {
     registers: &'static Pl011Peripherals,
+    dma: Option<Pl011Dma>,
 }
 
 impl Pl011Uart {
+    pub const COMPATIBLES: &'static [&'static str] = &["arm,pl011"];
+
     pub fn new(base_address: usize) -> Self {
         Self {
             registers: unsafe { &mut *(base_address as *mut Pl011Peripherals) },
+            dma: None,
         }
     }
 
-    pub fn flush(&self) {
-        while (self.registers.flags.read() & UARTFR::BUSY_MASK) != UARTFR(0) {
-            core::hint::spin_loop();
+    /// Transmit through `dma` from now on, output shorter than the FIFO is still written by
+    /// the CPU. The UART is written by the CPU again once a transfer fails
+    pub fn enable_dma(&mut self, dma: Pl011Dma) {
+        self.disable_dma();
+        self.registers.dma_control.set_bits(UARTDMACR::TXDMAE_MASK);
+        self.dma = Some(dma);
+    }
+
+    /// Wait for the running transfer and write by the CPU from now on
+    pub fn disable_dma(&mut self) {
+        if let Some(dma) = self.dma.take() {
+            let _ = dma.wait();
         }
-        while (self.registers.flags.read() & UARTFR::TXFE_MASK) != UARTFR(0) {
-            core::hint::spin_loop();
+        self.registers
+            .dma_control
+            .clear_bits(UARTDMACR::TXDMAE_MASK);
+    }
+
+    /// wait for the running transfer before the CPU writes to the FIFO
+    fn wait_dma(&self) {
+        if let Some(dma) = &self.dma {
+            let _ = dma.wait();
+        }
+    }
+
+    // hand `bytes` to the DMA, returns the bytes left to write when a transfer failed
+    fn write_dma<'a>(&self, dma: &Pl011Dma, mut bytes: &'a [u8]) -> &'a [u8] {
+        while !bytes.is_empty() {
+            if dma.wait().is_err() {
+                return bytes;
+            }
+            let (taken, len) = dma.fill(bytes);
+            clean_dcache_range(dma.buffer, len);
+            if dma
+                .engine
+                .start_to_peripheral(dma.buffer as usize, len, dma.data_register, dma.request)
+                .is_err()
+            {
+                dma.failed.set(true);
+                return bytes;
+            }
+            dma.in_flight.set(len);
+            bytes = &bytes[taken..];
         }
+        bytes
+    }
+
+    /// Wait until every queued byte has been transmitted. Gives up after `FLUSH_TIMEOUT`,
+    /// a UART held off by flow control never drains
+    pub fn flush(&self) {
+        self.wait_dma();
+        let _ = with_timeout(FLUSH_TIMEOUT, || {
+            let flags = self.registers.flags.read();
+            (flags & UARTFR::TXFE_MASK != UARTFR(0) && flags & UARTFR::BUSY_MASK == UARTFR(0))
+                .then_some(())
+        });
     }
 
     pub fn disabled(&self) {
         self.flush();
+        self.registers
+            .dma_control
+            .clear_bits(UARTDMACR::TXDMAE_MASK);
         // disable pl011
         self.registers
             .control
@@ -271,6 +452,9 @@ impl Pl011Uart {
         self.registers
             .control
             .set_bits(UARTCR::UARTEN_MASK + UARTCR::TXE_MASK + UARTCR::RXE_MASK);
+        if self.dma.is_some() {
+            self.registers.dma_control.set_bits(UARTDMACR::TXDMAE_MASK);
+        }
     }
 
     fn pushb(&self, ch: u32) {
@@ -281,7 +465,14 @@ impl Pl011Uart {
     }
 
     pub fn write(&self, char: &str) {
-        for &i in char.as_bytes() {
+        let mut bytes = char.as_bytes();
+        match &self.dma {
+            Some(dma) if dma.is_usable() && bytes.len() >= DMA_MIN_LEN => {
+                bytes = self.write_dma(dma, bytes);
+            }
+            _ => self.wait_dma(),
+        }
+        for &i in bytes {
             if i == b'\n' {
                 self.pushb('\r' as u32);
             }
@@ -289,17 +480,33 @@ impl Pl011Uart {
         }
     }
 
+    /// send `byte` as is, without the LF to CRLF conversion of `write`
+    pub fn write_byte(&self, byte: u8) {
+        self.wait_dma();
+        self.pushb(byte as u32);
+    }
+
     pub fn read_char(&self) -> u8 {
-        while self.registers.flags.read() & UARTFR::RXFE_MASK != UARTFR(0) {
+        loop {
+            if let Some(c) = self.try_read_char() {
+                return c;
+            }
             core::hint::spin_loop();
         }
+    }
+
+    /// returns `None` instead of waiting when the receive FIFO is empty
+    pub fn try_read_char(&self) -> Option<u8> {
+        if self.registers.flags.read() & UARTFR::RXFE_MASK != UARTFR(0) {
+            return None;
+        }
         let read = self.registers.data.read();
         if (read & (UARTDR::FE_MASK + UARTDR::PE_MASK + UARTDR::BE_MASK + UARTDR::OE_MASK))
             != UARTDR(0)
         {
             self.registers.error_status.write(0);
         }
-        (read & UARTDR::DATA_MASK).0 as u8
+        Some((read & UARTDR::DATA_MASK).0 as u8)
     }
 }
 
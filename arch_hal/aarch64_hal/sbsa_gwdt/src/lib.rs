@@ -0,0 +1,100 @@
+#![no_std]
+
+use core::time::Duration;
+
+use typestate::ReadOnly;
+use typestate::ReadWrite;
+use typestate::Readable;
+use typestate::Writable;
+use typestate::WriteOnly;
+
+/// Watchdog control frame (WDOG_CF)
+#[repr(C)]
+#[derive(Debug)]
+pub struct GwdtControlFrame {
+    pub control_status: ReadWrite<u32>, // 0x0000 WCS
+    _reserved0004: [u8; 0x04],          // 0x0004..0x0008
+    pub offset: ReadWrite<u32>,         // 0x0008 WOR
+    _reserved000c: [u8; 0x04],          // 0x000C..0x0010
+    pub compare_value: ReadWrite<u64>,  // 0x0010 WCV
+    _reserved0018: [u8; 0xFB4],         // 0x0018..0x0FCC
+    pub interface_id: ReadOnly<u32>,    // 0x0FCC W_IIDR
+    _reserved0fd0: [u8; 0x30],          // 0x0FD0..0x1000
+                                        // @END (0x1000)
+}
+
+/// Watchdog refresh frame (WDOG_RF)
+#[repr(C)]
+#[derive(Debug)]
+pub struct GwdtRefreshFrame {
+    pub refresh: WriteOnly<u32>,     // 0x0000 WRR
+    _reserved0004: [u8; 0xFC8],      // 0x0004..0x0FCC
+    pub interface_id: ReadOnly<u32>, // 0x0FCC W_IIDR
+    _reserved0fd0: [u8; 0x30],       // 0x0FD0..0x1000
+                                     // @END (0x1000)
+}
+
+const _: () = assert!(size_of::<GwdtControlFrame>() == 0x1000);
+const _: () = assert!(size_of::<GwdtRefreshFrame>() == 0x1000);
+
+const WCS_EN: u32 = 1 << 0;
+const WCS_WS0: u32 = 1 << 1;
+const WCS_WS1: u32 = 1 << 2;
+
+/// SBSA generic watchdog
+///
+/// The first expiry raises WS0 (an interrupt) and the second one WS1, which resets the
+/// system, so `start` programs half of the timeout as the watchdog offset.
+#[derive(Debug)]
+pub struct SbsaGwdt {
+    control: &'static GwdtControlFrame,
+    refresh: &'static GwdtRefreshFrame,
+    /// system counter frequency (CNTFRQ_EL0)
+    frequency: u64,
+}
+
+impl SbsaGwdt {
+    pub const COMPATIBLE: &'static str = "arm,sbsa-gwdt";
+
+    /// `control_base` and `refresh_base` are the first and second `reg` entries of the DTB node
+    pub fn new(control_base: usize, refresh_base: usize, frequency: u64) -> Self {
+        Self {
+            control: unsafe { &*(control_base as *const GwdtControlFrame) },
+            refresh: unsafe { &*(refresh_base as *const GwdtRefreshFrame) },
+            frequency,
+        }
+    }
+
+    /// longest timeout `start` can program
+    pub fn max_timeout(&self) -> Duration {
+        Duration::from_nanos((u32::MAX as u128 * 2 * 1_000_000_000 / self.frequency as u128) as u64)
+    }
+
+    /// reset the system if `refresh` is not called within `timeout`
+    pub fn start(&self, timeout: Duration) {
+        let ticks = (timeout.as_nanos() * self.frequency as u128 / 1_000_000_000 / 2)
+            .clamp(1, u32::MAX as u128) as u32;
+        self.stop();
+        self.control.offset.write(ticks);
+        self.pet();
+        self.control.control_status.write(WCS_EN);
+    }
+
+    pub fn stop(&self) {
+        self.control.control_status.write(0);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.control.control_status.read() & WCS_EN != 0
+    }
+
+    /// the first expiry (WS0) happened since the last refresh
+    pub fn is_warning(&self) -> bool {
+        self.control.control_status.read() & (WCS_WS0 | WCS_WS1) != 0
+    }
+
+    /// restart the countdown and clear WS0
+    pub fn pet(&self) {
+        self.refresh.refresh.write(0);
+    }
+}
@@ -0,0 +1,55 @@
+//! Console sinks mirroring the debug UART output
+//!
+//! Everything printed or logged goes to the `log_buffer`, the debug UART and every sink
+//! registered here, such as `semihosting::write_str`. The sinks are kept in atomics, so the
+//! panic report reaches them without a lock.
+
+use core::fmt;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+const MAX_SINKS: usize = 4;
+
+/// receives the console output, must not print
+pub type Sink = fn(&str);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleErr {
+    NoFreeSlot,
+}
+
+static SINKS: [AtomicUsize; MAX_SINKS] = [const { AtomicUsize::new(0) }; MAX_SINKS];
+
+/// mirror the console output to `sink`, a sink registered twice is called once
+pub fn register(sink: Sink) -> Result<(), ConsoleErr> {
+    let sink = sink as usize;
+    for slot in &SINKS {
+        match slot.compare_exchange(0, sink, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return Ok(()),
+            Err(current) if current == sink => return Ok(()),
+            Err(_) => {}
+        }
+    }
+    Err(ConsoleErr::NoFreeSlot)
+}
+
+/// write `s` to the registered sinks
+pub fn write_str(s: &str) {
+    for slot in &SINKS {
+        let sink = slot.load(Ordering::Acquire);
+        if sink != 0 {
+            let sink = unsafe { core::mem::transmute::<usize, Sink>(sink) };
+            sink(s);
+        }
+    }
+}
+
+/// `fmt::Write` to the registered sinks
+pub struct Writer;
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_str(s);
+        Ok(())
+    }
+}
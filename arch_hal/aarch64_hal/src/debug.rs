@@ -0,0 +1,90 @@
+//! Guest access to the debug and PMU registers (MDCR_EL2)
+
+use core::arch::asm;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+use crate::sysreg::SysReg;
+
+const MDCR_EL2_HPMN_MASK: u64 = 0b1_1111;
+const MDCR_EL2_TPMCR: u64 = 1 << 5;
+const MDCR_EL2_TPM: u64 = 1 << 6;
+const MDCR_EL2_TDA: u64 = 1 << 9;
+const MDCR_EL2_TDOSA: u64 = 1 << 10;
+const MDCR_EL2_TDRA: u64 = 1 << 11;
+
+/// How the guest sees a group of debug registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugPolicy {
+    /// trap the accesses, reads return zero and writes are ignored
+    Hide,
+    /// the guest owns the hardware registers
+    Passthrough,
+    /// trap the accesses to the handlers registered with `sysreg::register_handler`,
+    /// registers without a handler are read as zero and writes are ignored
+    Emulate,
+}
+
+impl DebugPolicy {
+    const fn to_u8(self) -> u8 {
+        match self {
+            Self::Hide => 0,
+            Self::Passthrough => 1,
+            Self::Emulate => 2,
+        }
+    }
+
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Hide,
+            2 => Self::Emulate,
+            _ => Self::Passthrough,
+        }
+    }
+}
+
+static DEBUG_POLICY: AtomicU8 = AtomicU8::new(DebugPolicy::Passthrough.to_u8());
+static PMU_POLICY: AtomicU8 = AtomicU8::new(DebugPolicy::Passthrough.to_u8());
+
+/// self-hosted debug registers (op0 == 2): breakpoints, watchpoints, OS lock, DCC, ...
+fn is_debug_register(reg: &SysReg) -> bool {
+    reg.op0 == 2
+}
+
+/// PMUv3 registers: PMCR_EL0 ... PMOVSSET_EL0, PMINTEN{SET,CLR}_EL1 and PMEV{CNTR,TYPER}<n>_EL0
+fn is_pmu_register(reg: &SysReg) -> bool {
+    reg.op0 == 3
+        && ((reg.crn == 9 && reg.op1 == 3 && (12..=14).contains(&reg.crm))
+            || (reg.crn == 9 && reg.op1 == 0 && reg.crm == 14)
+            || (reg.crn == 14 && reg.op1 == 3 && reg.crm >= 8))
+}
+
+/// policy which applies to `reg`, None if it is neither a debug nor a PMU register
+pub fn policy_of(reg: &SysReg) -> Option<DebugPolicy> {
+    if is_debug_register(reg) {
+        Some(DebugPolicy::from_u8(DEBUG_POLICY.load(Ordering::Relaxed)))
+    } else if is_pmu_register(reg) {
+        Some(DebugPolicy::from_u8(PMU_POLICY.load(Ordering::Relaxed)))
+    } else {
+        None
+    }
+}
+
+/// Program MDCR_EL2 of the calling PE for `debug` and `pmu`.
+/// Debug exceptions are always taken by the guest (MDCR_EL2.TDE == 0).
+pub fn configure_traps(debug: DebugPolicy, pmu: DebugPolicy) {
+    DEBUG_POLICY.store(debug.to_u8(), Ordering::Relaxed);
+    PMU_POLICY.store(pmu.to_u8(), Ordering::Relaxed);
+
+    let pmcr: u64;
+    unsafe { asm!("mrs {}, pmcr_el0", out(reg) pmcr) };
+    // HPMN: every implemented counter is accessible from EL1, HPME/TDE stay 0
+    let mut mdcr_el2 = (pmcr >> 11) & MDCR_EL2_HPMN_MASK;
+    if debug != DebugPolicy::Passthrough {
+        mdcr_el2 |= MDCR_EL2_TDA | MDCR_EL2_TDOSA | MDCR_EL2_TDRA;
+    }
+    if pmu != DebugPolicy::Passthrough {
+        mdcr_el2 |= MDCR_EL2_TPM | MDCR_EL2_TPMCR;
+    }
+    unsafe { asm!("msr mdcr_el2, {}", "isb", in(reg) mdcr_el2) };
+}
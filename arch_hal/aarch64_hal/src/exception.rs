@@ -0,0 +1,58 @@
+//! EL2 exception dispatcher
+
+use cpu::exception::ExceptionClass;
+use cpu::exception::ExceptionSource;
+use cpu::exception::ExceptionType;
+use cpu::exception::TrapFrame;
+
+use crate::fpsimd;
+use crate::interrupt;
+use crate::serror;
+use crate::vcpu;
+use crate::vcpu::ExitReason;
+
+/// install the EL2 exception vector table of this PE
+pub fn init() {
+    cpu::exception::init(handle_exception);
+}
+
+// lower EL exceptions only reach here for guests entered by a plain ERET,
+// `vcpu::Vcpu::run` returns the exits of its guest to the caller
+fn handle_exception(frame: &mut TrapFrame, source: ExceptionSource, exception_type: ExceptionType) {
+    if source == ExceptionSource::LowerElAarch64 {
+        fpsimd::on_guest_exit();
+    }
+    match (source, exception_type) {
+        (ExceptionSource::LowerElAarch64, ExceptionType::Synchronous | ExceptionType::SError) => {
+            handle_guest_exit(frame, exception_type)
+        }
+        (ExceptionSource::CurrentElSpx, ExceptionType::SError) => {
+            serror::handle_hypervisor(frame.iss() as u64)
+        }
+        (ExceptionSource::CurrentElSpx, ExceptionType::Synchronous)
+            if frame.exception_class() == ExceptionClass::SimdFp =>
+        {
+            fpsimd::handle_hypervisor_trap()
+        }
+        (ExceptionSource::CurrentElSpx | ExceptionSource::LowerElAarch64, ExceptionType::Irq) => {
+            interrupt::handle_irq()
+        }
+        _ => panic!(
+            "unhandled exception: {:?} {:?}, esr: {:#x}, elr: {:#x}, far: {:#x}",
+            source, exception_type, frame.esr, frame.elr, frame.far
+        ),
+    }
+    if source == ExceptionSource::LowerElAarch64 {
+        fpsimd::prepare_guest_entry();
+    }
+}
+
+fn handle_guest_exit(frame: &mut TrapFrame, exception_type: ExceptionType) {
+    let reason = ExitReason::new(frame, exception_type);
+    if !vcpu::dispatch(frame, reason) {
+        panic!(
+            "unhandled guest exception: {:?}, esr: {:#x}, elr: {:#x}, far: {:#x}",
+            reason, frame.esr, frame.elr, frame.far
+        );
+    }
+}
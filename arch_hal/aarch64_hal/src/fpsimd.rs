@@ -0,0 +1,101 @@
+//! Lazy FP/SIMD context switching between the guest and the hypervisor
+//!
+//! The FP/SIMD registers are owned by either the guest or the hypervisor. Accesses from
+//! the side which does not own them are trapped by CPTR_EL2.TFP and switch the owner, so
+//! the 0x210 byte register file is only saved when both sides actually use FP/SIMD.
+//! An FP-free hypervisor never saves or restores it on an exit.
+
+use cpu::fpsimd::FpState;
+use mutex::SpinLock;
+
+const MAX_PES: usize = 8;
+
+struct FpContext {
+    mpidr: u64,
+    guest: FpState,
+    host: FpState,
+    // the guest owns the registers
+    guest_live: bool,
+}
+
+static CONTEXTS: SpinLock<[Option<FpContext>; MAX_PES]> = SpinLock::new([const { None }; MAX_PES]);
+
+fn with_context<R>(f: impl FnOnce(Option<&mut FpContext>) -> R) -> R {
+    let mpidr = cpu::get_mpidr_affinity();
+    let mut contexts = CONTEXTS.lock();
+    f(contexts.iter_mut().flatten().find(|c| c.mpidr == mpidr))
+}
+
+/// Start lazy FP/SIMD switching on the calling PE with a zeroed guest context.
+/// Returns false when every slot is in use; FP/SIMD is never trapped on this PE in that case.
+pub fn init() -> bool {
+    let mpidr = cpu::get_mpidr_affinity();
+    let mut contexts = CONTEXTS.lock();
+    let slot = match contexts
+        .iter()
+        .position(|c| c.as_ref().is_some_and(|c| c.mpidr == mpidr))
+    {
+        Some(index) => Some(index),
+        None => contexts.iter().position(|c| c.is_none()),
+    };
+    cpu::fpsimd::set_trap(false);
+    let Some(slot) = slot else {
+        return false;
+    };
+    contexts[slot] = Some(FpContext {
+        mpidr,
+        guest: FpState::new(),
+        host: FpState::new(),
+        guest_live: false,
+    });
+    true
+}
+
+/// FP/SIMD access from the guest while the hypervisor owns the registers:
+/// hand them to the guest and retry the instruction
+pub fn handle_guest_trap() {
+    cpu::fpsimd::set_trap(false);
+    with_context(|context| {
+        if let Some(context) = context
+            && !context.guest_live
+        {
+            cpu::fpsimd::save(&mut context.host);
+            cpu::fpsimd::restore(&context.guest);
+            context.guest_live = true;
+        }
+    });
+}
+
+/// FP/SIMD access from EL2 while the guest owns the registers: hand them back to the hypervisor
+pub fn handle_hypervisor_trap() {
+    cpu::fpsimd::set_trap(false);
+    with_context(|context| {
+        if let Some(context) = context
+            && context.guest_live
+        {
+            cpu::fpsimd::save(&mut context.guest);
+            cpu::fpsimd::restore(&context.host);
+            context.guest_live = false;
+        }
+    });
+}
+
+/// trap the guest FP/SIMD accesses unless the guest owns the registers.
+/// called on every entry to the guest
+pub fn prepare_guest_entry() {
+    with_context(|context| {
+        if let Some(context) = context {
+            cpu::fpsimd::set_trap(!context.guest_live);
+        }
+    });
+}
+
+/// trap the hypervisor FP/SIMD accesses if the guest owns the registers.
+/// called on every exit from the guest
+pub fn on_guest_exit() {
+    with_context(|context| {
+        if context.is_some_and(|c| c.guest_live) {
+            cpu::fpsimd::set_trap(true);
+        }
+    });
+}
@@ -0,0 +1,122 @@
+//! Hypercall ABI for para-virtualized payloads
+//!
+//! Calls follow the SMCCC register convention in the Vendor Specific Hypervisor Service
+//! range (owner 6): HVC #0 with the function ID in x0 and arguments in x1-x3.
+//! x0 returns `SUCCESS` or a negative error, results are returned in x1-x3.
+
+use cpu::exception::TrapFrame;
+use cpu::psci;
+use mutex::SpinLock;
+
+use crate::debug_uart;
+use crate::log_buffer;
+
+/// returns the ABI version in x1 ((major << 16) | minor)
+pub const HYP_VERSION: u32 = 0x8600_0000;
+/// write the byte in x1 to the hypervisor console
+pub const HYP_CONSOLE_PUTCHAR: u32 = 0x8600_0001;
+/// returns the DTB address in x1, the kernel image base in x2 and its size in x3
+pub const HYP_GET_BOOT_INFO: u32 = 0xC600_0002;
+/// power off the system through PSCI, returns only on failure
+pub const HYP_POWER_OFF: u32 = 0x8600_0003;
+/// returns the counter at the start of the boot phase numbered x1 in x1, at its end in x2
+/// and the counter frequency in x3. Phases are numbered by the loader, 0 if not recorded
+pub const HYP_GET_BOOT_PHASE: u32 = 0xC600_0004;
+/// returns up to 16 bytes of the hypervisor log buffer from the byte offset x1, oldest first,
+/// in x1 and x2 (little-endian, zero padded) and the number of bytes in the buffer in x3
+pub const HYP_READ_LOG: u32 = 0xC600_0005;
+
+pub const VERSION_MAJOR: u16 = 0;
+pub const VERSION_MINOR: u16 = 1;
+
+pub const SUCCESS: i64 = 0;
+pub const NOT_SUPPORTED: i64 = -1;
+pub const INVALID_PARAMETERS: i64 = -2;
+pub const NOT_AVAILABLE: i64 = -3;
+
+/// values returned by `HYP_GET_BOOT_INFO`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootInfo {
+    pub dtb_address: u64,
+    pub image_base: u64,
+    pub image_size: u64,
+}
+
+static BOOT_INFO: SpinLock<Option<BootInfo>> = SpinLock::new(None);
+
+/// number of phases `HYP_GET_BOOT_PHASE` can report
+pub const MAX_BOOT_PHASES: usize = 16;
+
+/// (start, end) counter values of each boot phase
+static BOOT_PHASES: SpinLock<Option<[(u64, u64); MAX_BOOT_PHASES]>> = SpinLock::new(None);
+
+pub fn set_boot_info(info: BootInfo) {
+    *BOOT_INFO.lock() = Some(info);
+}
+
+/// Publish the boot phase marks, phases past `MAX_BOOT_PHASES` are dropped
+pub fn set_boot_phases(phases: &[(u64, u64)]) {
+    let mut marks = [(0, 0); MAX_BOOT_PHASES];
+    let len = phases.len().min(MAX_BOOT_PHASES);
+    marks[..len].copy_from_slice(&phases[..len]);
+    *BOOT_PHASES.lock() = Some(marks);
+}
+
+/// Handle a guest HVC whose function ID is in x0.
+/// Returns false when the call is not in the hypervisor service range.
+pub fn handle(frame: &mut TrapFrame) -> bool {
+    let function_id = frame.x[0] as u32;
+    // fast calls of the Vendor Specific Hypervisor Service, SMC32 or SMC64
+    if function_id & 0xBF00_0000 != 0x8600_0000 {
+        return false;
+    }
+    let result = match function_id {
+        HYP_VERSION => Ok([((VERSION_MAJOR as u64) << 16) | VERSION_MINOR as u64, 0, 0]),
+        HYP_CONSOLE_PUTCHAR => match u8::try_from(frame.x[1]) {
+            Ok(byte) => {
+                debug_uart::write_byte(byte);
+                Ok([0; 3])
+            }
+            Err(_) => Err(INVALID_PARAMETERS),
+        },
+        HYP_GET_BOOT_INFO => match *BOOT_INFO.lock() {
+            Some(info) => Ok([info.dtb_address, info.image_base, info.image_size]),
+            None => Err(NOT_AVAILABLE),
+        },
+        HYP_GET_BOOT_PHASE => match (*BOOT_PHASES.lock(), usize::try_from(frame.x[1])) {
+            (None, _) => Err(NOT_AVAILABLE),
+            (Some(marks), Ok(phase)) if phase < MAX_BOOT_PHASES => {
+                let (start, end) = marks[phase];
+                Ok([start, end, cpu::timer::frequency()])
+            }
+            _ => Err(INVALID_PARAMETERS),
+        },
+        HYP_READ_LOG => match usize::try_from(frame.x[1]) {
+            Ok(offset) => {
+                let mut bytes = [0u8; 16];
+                log_buffer::read(offset, &mut bytes);
+                Ok([
+                    u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+                    u64::from_le_bytes(bytes[8..].try_into().unwrap()),
+                    log_buffer::len() as u64,
+                ])
+            }
+            Err(_) => Err(INVALID_PARAMETERS),
+        },
+        HYP_POWER_OFF => {
+            crate::println!("power off requested by the guest");
+            let e = psci::system_off();
+            crate::println!("power off failed: {:?}", e);
+            Err(NOT_AVAILABLE)
+        }
+        _ => Err(NOT_SUPPORTED),
+    };
+    match result {
+        Ok(values) => {
+            frame.x[0] = SUCCESS as u64;
+            frame.x[1..4].copy_from_slice(&values);
+        }
+        Err(e) => frame.x[0] = e as u64,
+    }
+    true
+}
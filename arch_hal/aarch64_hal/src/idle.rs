@@ -0,0 +1,63 @@
+//! Guest WFI/WFE trapping (HCR_EL2.TWI/TWE) and idle accounting
+
+use core::arch::asm;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use cpu::exception::TrapFrame;
+
+use crate::interrupt;
+use crate::timer;
+
+const HCR_EL2_TWI: u64 = 1 << 13;
+const HCR_EL2_TWE: u64 = 1 << 14;
+// ESR_EL2.ISS.TI: 0b00 WFI, 0b01 WFE, 0b10 WFIT, 0b11 WFET
+const ISS_TI_WFE: u32 = 1 << 0;
+
+static IDLE_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Trap guest WFI and/or WFE on the calling PE.
+/// a trapped WFI halts the physical CPU until an interrupt or the guest timer deadline,
+/// a trapped WFE returns to the guest immediately.
+/// must be called after `cpu::setup_hypervisor_registers`
+pub fn configure_traps(trap_wfi: bool, trap_wfe: bool) {
+    let mut hcr_el2 = cpu::get_hcr_el2() & !(HCR_EL2_TWI | HCR_EL2_TWE);
+    if trap_wfi {
+        hcr_el2 |= HCR_EL2_TWI;
+    }
+    if trap_wfe {
+        hcr_el2 |= HCR_EL2_TWE;
+    }
+    cpu::set_hcr_el2(hcr_el2);
+}
+
+/// total time spent halted on behalf of guests (all PEs)
+pub fn idle_time() -> Duration {
+    timer::ticks_to_duration(IDLE_TICKS.load(Ordering::Relaxed))
+}
+
+fn wake() {}
+
+/// handle a trapped WFI/WFE (ESR_EL2.EC == 0x01)
+pub fn handle(frame: &mut TrapFrame) {
+    frame.advance_pc();
+    if frame.iss() & ISS_TI_WFE != 0 || interrupt::has_pending_virtual_interrupt() {
+        return;
+    }
+
+    // the guest virtual timer may not be routed to this PE, so wake up at its deadline
+    let wake_up = cpu::timer::virtual_timer_compare()
+        .map(|cval| cval.wrapping_add(cpu::timer::virtual_offset()))
+        .and_then(|deadline| timer::add_oneshot_at(deadline, wake).ok());
+    let start = timer::now();
+    unsafe { asm!("dsb sy", "wfi") };
+    IDLE_TICKS.fetch_add(timer::now() - start, Ordering::Relaxed);
+
+    // take interrupts owned by the hypervisor (e.g. the wake-up timer) before returning
+    cpu::enable_irq();
+    cpu::disable_irq();
+    if let Some(id) = wake_up {
+        let _ = timer::cancel(id);
+    }
+}
@@ -3,18 +3,48 @@
 use core::cell::OnceCell;
 use core::fmt::Write;
 use core::fmt::{self};
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
 
 #[cfg(feature = "uefi-test")]
 pub use aarch64_test::*;
 
 pub use cpu;
+pub use dma;
+pub use gic;
+pub use gpio;
+pub use ns16550;
 pub use paging;
 pub use pl011;
+pub use sbsa_gwdt;
 
+pub mod console;
+pub mod debug;
+pub mod exception;
+pub mod fpsimd;
+pub mod hypercall;
+pub mod idle;
+pub mod log_buffer;
+pub mod logging;
+pub mod mmio;
+pub mod panic_report;
+pub mod psci_proxy;
+pub mod semihosting;
+pub mod serror;
+pub mod sysreg;
+pub mod timer;
+pub mod uart;
+pub mod vcpu;
+
+use gic::Gicv3;
+use gic::VGic;
 use mutex::SpinLock;
-use pl011::Pl011Uart;
+use uart::Uart;
 
-pub static DEBUG_UART: SpinLock<OnceCell<Pl011Uart>> = SpinLock::new(OnceCell::new());
+pub static DEBUG_UART: SpinLock<OnceCell<Uart>> = SpinLock::new(OnceCell::new());
+pub static GIC: SpinLock<OnceCell<Gicv3>> = SpinLock::new(OnceCell::new());
+pub static VGIC: SpinLock<OnceCell<VGic>> = SpinLock::new(OnceCell::new());
 
 #[macro_export]
 macro_rules! print {
@@ -36,19 +66,288 @@ macro_rules! pr_trace {
 }
 
 pub mod debug_uart {
-    use pl011::Pl011Uart;
+    use core::fmt;
+    use core::fmt::Write;
+    use core::sync::atomic::AtomicU32;
+    use core::sync::atomic::AtomicUsize;
+    use core::sync::atomic::Ordering;
+
+    use pl011::Pl011Dma;
 
     use crate::DEBUG_UART;
+    use crate::uart::Uart;
+    use crate::uart::UartConfig;
+    use crate::uart::UartKind;
+
+    // copy of the console for panic handlers, which must not wait for `DEBUG_UART`
+    static PANIC_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+    // `UartKind`, see `encode_kind`
+    static PANIC_KIND: AtomicU32 = AtomicU32::new(0);
+    // 0 if unknown
+    static PANIC_CLOCK: AtomicU32 = AtomicU32::new(0);
+    static PANIC_BAUDRATE: AtomicU32 = AtomicU32::new(0);
+
+    fn encode_kind(kind: UartKind) -> u32 {
+        match kind {
+            UartKind::Pl011 => 0,
+            UartKind::Ns16550 {
+                reg_shift,
+                reg_io_width,
+            } => 1 | u32::from(reg_shift) << 8 | u32::from(reg_io_width) << 16,
+        }
+    }
+
+    fn decode_kind(kind: u32) -> UartKind {
+        match kind & 0xff {
+            0 => UartKind::Pl011,
+            _ => UartKind::Ns16550 {
+                reg_shift: (kind >> 8) as u8,
+                reg_io_width: (kind >> 16) as u8,
+            },
+        }
+    }
 
-    pub fn init(base_address: usize) {
-        let uart = Pl011Uart::new(base_address);
+    /// PL011 console, `clock` is the reference clock of the UART in Hz, if the firmware
+    /// describes it
+    pub fn init(base_address: usize, clock: Option<u32>) {
+        init_uart(UartConfig::pl011(base_address, clock));
+    }
+
+    /// Use the UART of `config` as the console. It is reprogrammed when both its clock and
+    /// baud rate are known, the firmware setup is kept otherwise
+    pub fn init_uart(config: UartConfig) {
+        let uart = Uart::new(&config);
+        if let (Some(clock), Some(baudrate)) = (config.clock, config.baudrate) {
+            uart.init(clock, baudrate);
+        }
         let debug_uart = DEBUG_UART.lock();
         debug_uart.set(uart).unwrap();
+        PANIC_KIND.store(encode_kind(config.kind), Ordering::Relaxed);
+        PANIC_CLOCK.store(config.clock.unwrap_or(0), Ordering::Relaxed);
+        PANIC_BAUDRATE.store(config.baudrate.unwrap_or(0), Ordering::Relaxed);
+        PANIC_ADDRESS.store(config.base, Ordering::Release);
+    }
+
+    /// Point the console at `new_base`, the address the same UART is reached at now, such as
+    /// its virtual address once the stage-1 MMU is on or another UART after the hypervisor
+    /// took this one over for a guest. The UART is not reprogrammed, queued bytes are flushed
+    /// through the old address first. Does nothing before `init`
+    pub fn rebind(new_base: usize) {
+        let mut debug_uart = DEBUG_UART.lock();
+        let (Some(uart), Some(config)) = (debug_uart.get_mut(), panic_console()) else {
+            return;
+        };
+        // the DMA targets the bus address of the old UART
+        uart.disable_dma();
+        uart.flush();
+        *uart = Uart::new(&UartConfig {
+            base: new_base,
+            ..config
+        });
+        PANIC_ADDRESS.store(new_base, Ordering::Release);
+    }
+
+    /// Transmit the console output of a PL011 console through `dma`, see `Pl011Uart::enable_dma`.
+    /// Returns false, and leaves the console as it is, before `init` or for another UART.
+    /// `rebind` goes back to writing by the CPU
+    pub fn enable_dma(dma: Pl011Dma) -> bool {
+        let mut debug_uart = DEBUG_UART.lock();
+        match debug_uart.get_mut() {
+            Some(Uart::Pl011(uart)) => {
+                uart.enable_dma(dma);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// the console passed to `init` or `rebind`, `None` before `init`.
+    /// Does not take the console lock, so it is usable while panicking
+    pub fn panic_console() -> Option<UartConfig> {
+        let address = PANIC_ADDRESS.load(Ordering::Acquire);
+        let clock = PANIC_CLOCK.load(Ordering::Relaxed);
+        let baudrate = PANIC_BAUDRATE.load(Ordering::Relaxed);
+        (address != 0).then(|| UartConfig {
+            kind: decode_kind(PANIC_KIND.load(Ordering::Relaxed)),
+            base: address,
+            clock: (clock != 0).then_some(clock),
+            baudrate: (baudrate != 0).then_some(baudrate),
+        })
+    }
+
+    /// Write `args` to the console without taking its lock, for exception and panic paths
+    /// which may have interrupted its owner. Does nothing before `init`
+    pub fn emergency_write(args: fmt::Arguments) {
+        if let Some(config) = panic_console() {
+            let _ = Uart::new(&config).write_fmt(args);
+        }
+    }
+
+    /// write a raw byte, does nothing before `init`
+    pub fn write_byte(byte: u8) {
+        if let Some(uart) = DEBUG_UART.lock().get() {
+            uart.write_byte(byte);
+        }
+    }
+
+    /// read a received byte without waiting, `None` if nothing arrived or before `init`
+    pub fn try_read_byte() -> Option<u8> {
+        DEBUG_UART
+            .lock()
+            .get()
+            .and_then(|uart| uart.try_read_char())
+    }
+}
+
+pub mod interrupt {
+    use gic::GicErr;
+    use gic::Gicv3;
+    use gic::VGic;
+    use gic::VirtualInterrupt;
+    use gic::icc;
+    use mutex::SpinLock;
+
+    use crate::GIC;
+    use crate::VGIC;
+
+    const MAX_IRQ_HANDLERS: usize = 32;
+
+    /// called with the acknowledged INTID, before the end of interrupt
+    pub type IrqHandler = fn(u32);
+
+    static IRQ_HANDLERS: SpinLock<[Option<(u32, IrqHandler)>; MAX_IRQ_HANDLERS]> =
+        SpinLock::new([None; MAX_IRQ_HANDLERS]);
+
+    /// initialize GICv3 distributor and the CPU interface of the boot PE
+    pub fn init_gicv3(gicd_base: usize, gicr_base: usize, gicr_size: usize) -> Result<(), GicErr> {
+        let gic = Gicv3::new(gicd_base, gicr_base, gicr_size);
+        gic.init_distributor();
+        gic.init_cpu_interface()?;
+        let global_gic = GIC.lock();
+        global_gic.set(gic).unwrap();
+        Ok(())
+    }
+
+    /// initialize the redistributor and CPU interface of the calling PE (secondary CPUs).
+    /// does nothing when the GIC has not been initialized
+    pub fn init_cpu_interface() -> Result<(), GicErr> {
+        let gic = GIC.lock();
+        match gic.get() {
+            Some(gic) => gic.init_cpu_interface(),
+            None => Ok(()),
+        }
+    }
+
+    /// enable the virtual CPU interface and its maintenance interrupt.
+    /// `init_gicv3` must be called before this function
+    pub fn init_vgic(maintenance_intid: u32) -> Result<(), GicErr> {
+        let mut vgic = VGic::new();
+        vgic.init();
+        let gic = GIC.lock();
+        let gic = gic.get().unwrap();
+        gic.set_trigger_mode(maintenance_intid, gic::TriggerMode::Level)?;
+        gic.enable_interrupt(maintenance_intid)?;
+        let global_vgic = VGIC.lock();
+        global_vgic.set(vgic).unwrap();
+        register_irq_handler(maintenance_intid, |_| handle_maintenance_interrupt())
+    }
+
+    /// register `handler` for `intid`, replacing the previous one
+    pub fn register_irq_handler(intid: u32, handler: IrqHandler) -> Result<(), GicErr> {
+        let mut handlers = IRQ_HANDLERS.lock();
+        if let Some(entry) = handlers.iter_mut().flatten().find(|(i, _)| *i == intid) {
+            entry.1 = handler;
+            return Ok(());
+        }
+        let slot = handlers
+            .iter_mut()
+            .find(|h| h.is_none())
+            .ok_or(GicErr::QueueFull)?;
+        *slot = Some((intid, handler));
+        Ok(())
+    }
+
+    /// acknowledge and dispatch every pending physical interrupt of this PE
+    pub fn handle_irq() {
+        while let Some(intid) = icc::acknowledge() {
+            let handler = IRQ_HANDLERS
+                .lock()
+                .iter()
+                .flatten()
+                .find(|(i, _)| *i == intid)
+                .map(|(_, handler)| *handler);
+            match handler {
+                Some(handler) => handler(intid),
+                None => crate::println!("unhandled interrupt: {}", intid),
+            }
+            icc::end_of_interrupt(intid);
+        }
+    }
+
+    /// inject a virtual interrupt into the guest running on this PE
+    pub fn inject_virtual_interrupt(irq: VirtualInterrupt) -> Result<(), GicErr> {
+        let mut vgic = VGIC.lock();
+        vgic.get_mut().unwrap().inject(irq)
+    }
+
+    /// whether a virtual interrupt is waiting to be taken by the guest on this PE
+    pub fn has_pending_virtual_interrupt() -> bool {
+        VGIC.lock().get().is_some_and(|vgic| vgic.has_pending())
+    }
+
+    pub fn handle_maintenance_interrupt() {
+        let mut vgic = VGIC.lock();
+        if let Some(vgic) = vgic.get_mut() {
+            vgic.handle_maintenance_interrupt();
+        }
     }
 }
 
+// MPIDR affinity + 1 of the PE writing the console, 0 if none. An exception handler which
+// prints while its PE is printing must not wait for itself
+static PRINTING: AtomicU64 = AtomicU64::new(0);
+// wait for another PE to finish its line, longer only if it died while printing
+const PRINT_TIMEOUT: Duration = Duration::from_millis(100);
+
 pub fn _print(args: fmt::Arguments) {
-    let mut debug_uart = DEBUG_UART.lock();
-    let uart = debug_uart.get_mut().unwrap();
-    uart.write_fmt(args).unwrap();
+    write_console(args, true);
+}
+
+/// Write `args` to the log buffer, and to the console sinks and the debug UART if `show`.
+/// A print nested in another one on the same PE, or one which finds the UART locked, is
+/// written with `debug_uart::emergency_write` and skips the log buffer if its lock may be
+/// held, so it neither deadlocks nor panics
+pub(crate) fn write_console(args: fmt::Arguments, show: bool) {
+    let pe = cpu::get_mpidr_affinity() + 1;
+    if PRINTING.load(Ordering::Relaxed) == pe {
+        if show {
+            let _ = console::Writer.write_fmt(args);
+            debug_uart::emergency_write(args);
+        }
+        return;
+    }
+    let owned = cpu::timer::with_timeout(PRINT_TIMEOUT, || {
+        PRINTING
+            .compare_exchange(0, pe, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+    })
+    .is_ok();
+    if owned {
+        let _ = log_buffer::Writer.write_fmt(args);
+    }
+    if show {
+        let _ = console::Writer.write_fmt(args);
+        // before `debug_uart::init` the output is only kept in the log buffer and the sinks
+        match DEBUG_UART.try_lock() {
+            Some(mut debug_uart) => {
+                if let Some(uart) = debug_uart.get_mut() {
+                    let _ = uart.write_fmt(args);
+                }
+            }
+            None => debug_uart::emergency_write(args),
+        }
+    }
+    if owned {
+        PRINTING.store(0, Ordering::Release);
+    }
 }
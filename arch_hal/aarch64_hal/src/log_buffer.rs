@@ -0,0 +1,96 @@
+//! Ring buffer of the recent console output
+//!
+//! Everything printed and every log message up to `CAPTURE_LEVEL` is kept here, whether or
+//! not the UART printed it, so output from before `debug_uart::init` or below the console
+//! level can be dumped later. The oldest bytes are overwritten once it is full.
+
+use core::fmt;
+
+use mutex::SpinLock;
+
+use crate::logging::Level;
+
+/// bytes kept
+pub const SIZE: usize = 16 * 1024;
+/// least severe log level kept regardless of the console level
+pub const CAPTURE_LEVEL: Level = Level::Debug;
+
+struct Ring {
+    data: [u8; SIZE],
+    // index of the next byte written
+    head: usize,
+    len: usize,
+}
+
+impl Ring {
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.data[self.head] = byte;
+            self.head = (self.head + 1) % SIZE;
+        }
+        self.len = (self.len + bytes.len()).min(SIZE);
+    }
+
+    // the content as two slices, oldest first
+    fn slices(&self) -> (&[u8], &[u8]) {
+        let start = (self.head + SIZE - self.len) % SIZE;
+        if start + self.len <= SIZE {
+            (&self.data[start..start + self.len], &[])
+        } else {
+            (&self.data[start..], &self.data[..self.head])
+        }
+    }
+}
+
+static BUFFER: SpinLock<Ring> = SpinLock::new(Ring {
+    data: [0; SIZE],
+    head: 0,
+    len: 0,
+});
+
+pub fn write(bytes: &[u8]) {
+    BUFFER.lock().push(bytes);
+}
+
+/// `fmt::Write` into the buffer
+pub struct Writer;
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// number of bytes kept
+pub fn len() -> usize {
+    BUFFER.lock().len
+}
+
+/// Copy the content from `offset` bytes after the oldest one into `buf`.
+/// Returns the number of bytes copied
+pub fn read(offset: usize, buf: &mut [u8]) -> usize {
+    let ring = BUFFER.lock();
+    let (first, second) = ring.slices();
+    let mut copied = 0;
+    for (i, &byte) in first.iter().chain(second).skip(offset).enumerate() {
+        let Some(slot) = buf.get_mut(i) else {
+            break;
+        };
+        *slot = byte;
+        copied += 1;
+    }
+    copied
+}
+
+/// Pass the content to `f`, oldest first, in at most two chunks.
+/// The buffer stays locked, so `f` must not print
+pub fn for_each_chunk(f: &mut dyn FnMut(&[u8])) {
+    let ring = BUFFER.lock();
+    let (first, second) = ring.slices();
+    for chunk in [first, second] {
+        if !chunk.is_empty() {
+            f(chunk);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Leveled console logging with a runtime level filter
+//!
+//! `log_error!` .. `log_trace!` print to the debug UART when their level is enabled.
+//! Before `debug_uart::init` messages only go to the `log_buffer`, so crates which run
+//! before the console exists (the dtb parser) can log too.
+//! Each line starts with the uptime, the level and the module which logged it:
+//! `[    1.024310] debug dtb: ...`
+//! With the `log` feature, `init_log` routes the `log` crate macros of other crates through
+//! the same filter, prefixed with their target.
+
+use core::fmt;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+use crate::log_buffer;
+use crate::timer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    const ALL: [Self; 5] = [
+        Self::Error,
+        Self::Warn,
+        Self::Info,
+        Self::Debug,
+        Self::Trace,
+    ];
+
+    /// parse `error`, `warn`, `info`, `debug` or `trace`
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| level.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// print messages up to `level`, the default is `Level::Info`
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+    // the `log` macros skip formatting below their own filter
+    #[cfg(feature = "log")]
+    log::set_max_level(level.max(log_buffer::CAPTURE_LEVEL).into());
+}
+
+pub fn max_level() -> Level {
+    Level::ALL[usize::from(MAX_LEVEL.load(Ordering::Relaxed)) - 1]
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "log")]
+impl From<log::Level> for Level {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Self::Error,
+            log::Level::Warn => Self::Warn,
+            log::Level::Info => Self::Info,
+            log::Level::Debug => Self::Debug,
+            log::Level::Trace => Self::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<Level> for log::LevelFilter {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => Self::Error,
+            Level::Warn => Self::Warn,
+            Level::Info => Self::Info,
+            Level::Debug => Self::Debug,
+            Level::Trace => Self::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+struct Console;
+
+#[cfg(feature = "log")]
+impl log::Log for Console {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        let level = Level::from(metadata.level());
+        enabled(level) || level <= log_buffer::CAPTURE_LEVEL
+    }
+
+    fn log(&self, record: &log::Record) {
+        _log(record.level().into(), record.target(), *record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the console as the logger of the `log` crate. Fails if another logger was set
+#[cfg(feature = "log")]
+pub fn init_log() -> Result<(), log::SetLoggerError> {
+    static CONSOLE: Console = Console;
+    log::set_logger(&CONSOLE)?;
+    log::set_max_level(max_level().max(log_buffer::CAPTURE_LEVEL).into());
+    Ok(())
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, module: &str, args: fmt::Arguments) {
+    let print = enabled(level);
+    if !print && level > log_buffer::CAPTURE_LEVEL {
+        return;
+    }
+    let uptime = timer::uptime();
+    let (secs, micros, name) = (uptime.as_secs(), uptime.subsec_micros(), level.name());
+    let line = format_args!(
+        "[{:5}.{:06}] {:<5} {}: {}\n",
+        secs, micros, name, module, args
+    );
+    crate::write_console(line, print);
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => ($crate::logging::_log($crate::logging::Level::Error, module_path!(), format_args!($($arg)+)));
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => ($crate::logging::_log($crate::logging::Level::Warn, module_path!(), format_args!($($arg)+)));
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => ($crate::logging::_log($crate::logging::Level::Info, module_path!(), format_args!($($arg)+)));
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => ($crate::logging::_log($crate::logging::Level::Debug, module_path!(), format_args!($($arg)+)));
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)+) => ($crate::logging::_log($crate::logging::Level::Trace, module_path!(), format_args!($($arg)+)));
+}
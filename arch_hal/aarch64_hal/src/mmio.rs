@@ -0,0 +1,122 @@
+//! Trap-and-emulate of guest MMIO accesses reported as stage-2 data aborts
+
+use core::arch::asm;
+
+use cpu::exception::TrapFrame;
+use mutex::SpinLock;
+
+const MAX_MMIO_DEVICES: usize = 16;
+
+// ISS of data aborts
+const ISS_ISV: u32 = 1 << 24;
+const ISS_SSE: u32 = 1 << 21;
+const ISS_SF: u32 = 1 << 15;
+const ISS_WNR: u32 = 1 << 6;
+
+/// device emulated by the hypervisor. `offset` is relative to the registered base IPA
+/// and `size` is the access width in bytes (1, 2, 4 or 8)
+pub trait MmioDevice: Sync {
+    fn read(&self, offset: usize, size: usize) -> u64;
+    fn write(&self, offset: usize, size: usize, value: u64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmioErr {
+    NoFreeSlot,
+    Overlapped,
+}
+
+#[derive(Clone, Copy)]
+struct MmioRegion {
+    base: usize,
+    size: usize,
+    device: &'static dyn MmioDevice,
+}
+
+static DEVICES: SpinLock<[Option<MmioRegion>; MAX_MMIO_DEVICES]> =
+    SpinLock::new([None; MAX_MMIO_DEVICES]);
+
+/// emulate guest accesses to [`base`, `base + size`) with `device`.
+/// the range must not be mapped in the stage-2 translation table
+pub fn register_device(
+    base: usize,
+    size: usize,
+    device: &'static dyn MmioDevice,
+) -> Result<(), MmioErr> {
+    let mut devices = DEVICES.lock();
+    if devices
+        .iter()
+        .flatten()
+        .any(|r| base < r.base + r.size && r.base < base + size)
+    {
+        return Err(MmioErr::Overlapped);
+    }
+    let slot = devices
+        .iter_mut()
+        .find(|d| d.is_none())
+        .ok_or(MmioErr::NoFreeSlot)?;
+    *slot = Some(MmioRegion { base, size, device });
+    Ok(())
+}
+
+pub fn unregister_device(base: usize) {
+    let mut devices = DEVICES.lock();
+    if let Some(slot) = devices
+        .iter_mut()
+        .find(|d| d.is_some_and(|r| r.base == base))
+    {
+        *slot = None;
+    }
+}
+
+// IPA of the faulting access (HPFAR_EL2.FIPA + page offset of FAR_EL2)
+fn fault_ipa(far: u64) -> usize {
+    let hpfar: u64;
+    unsafe { asm!("mrs {}, hpfar_el2", out(reg) hpfar) };
+    ((((hpfar >> 4) & 0xFF_FFFF_FFFF) << 12) | (far & 0xFFF)) as usize
+}
+
+/// Emulate the load/store which caused the stage-2 data abort in `frame`.
+/// Returns false when the syndrome is not valid or no device covers the address.
+pub fn handle(frame: &mut TrapFrame) -> bool {
+    let iss = frame.iss();
+    if iss & ISS_ISV == 0 {
+        return false;
+    }
+    let ipa = fault_ipa(frame.far);
+    let size = 1 << ((iss >> 22) & 0b11);
+    let rt = ((iss >> 16) & 0b1_1111) as usize;
+    let Some(region) = DEVICES
+        .lock()
+        .iter()
+        .flatten()
+        .find(|r| r.base <= ipa && ipa + size <= r.base + r.size)
+        .copied()
+    else {
+        return false;
+    };
+    let mask = if size == 8 {
+        u64::MAX
+    } else {
+        (1 << (size * 8)) - 1
+    };
+    let offset = ipa - region.base;
+    if iss & ISS_WNR != 0 {
+        let value = if rt == 31 { 0 } else { frame.x[rt] };
+        region.device.write(offset, size, value & mask);
+    } else {
+        let mut value = region.device.read(offset, size) & mask;
+        if iss & ISS_SSE != 0 && size != 8 {
+            let shift = 64 - size * 8;
+            value = (((value << shift) as i64) >> shift) as u64;
+        }
+        if iss & ISS_SF == 0 {
+            value &= u32::MAX as u64;
+        }
+        if rt != 31 {
+            frame.x[rt] = value;
+        }
+    }
+    frame.advance_pc();
+    true
+}
@@ -0,0 +1,95 @@
+//! Panic report: the panic message, the registers of the exception being handled
+//! and a frame pointer backtrace
+//!
+//! Panic handlers open the debug UART with `console` and write the report with `report`.
+//! Neither takes a lock, so a panic from an exception handler or while printing is reported
+//! the same way. The report is mirrored to the `console` sinks, and to semihosting with
+//! `uefi-test`.
+
+use core::fmt;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+
+use crate::console;
+use crate::debug_uart;
+use crate::uart::Uart;
+use crate::uart::UartConfig;
+use cpu::backtrace;
+use cpu::exception::TrapFrame;
+
+const MAX_BACKTRACE_DEPTH: usize = 32;
+
+// a fault while walking the stack panics again, skip the walk then
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// The debug UART without its lock: the one given to `debug_uart::init`, else `fallback`.
+/// It is reprogrammed at its own baud rate, else `baudrate`, when its clock is known, the
+/// firmware setup is kept otherwise
+pub fn console(fallback: UartConfig, baudrate: u32) -> Uart {
+    let config = debug_uart::panic_console().unwrap_or(fallback);
+    let uart = Uart::new(&config);
+    if let Some(clock) = config.clock {
+        uart.init(clock, config.baudrate.unwrap_or(baudrate));
+    }
+    uart
+}
+
+// every panic sink
+struct Sinks<'a> {
+    console: &'a mut Uart,
+}
+
+impl Write for Sinks<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        #[cfg(feature = "uefi-test")]
+        crate::semihosting::write_str(s);
+        console::write_str(s);
+        self.console.write_str(s)
+    }
+}
+
+/// write the report of `info` on the calling PE to `console` and the other panic sinks
+pub fn report(console: &mut Uart, info: &PanicInfo) {
+    let _ = write_report(&mut Sinks { console }, info);
+}
+
+fn write_frame(w: &mut impl Write, frame: &TrapFrame) -> fmt::Result {
+    writeln!(
+        w,
+        "exception: esr: {:#x}, elr: {:#x}, far: {:#x}, spsr: {:#x}",
+        frame.esr, frame.elr, frame.far, frame.spsr
+    )?;
+    for (i, chunk) in frame.x.chunks(4).enumerate() {
+        for (j, x) in chunk.iter().enumerate() {
+            write!(w, "x{:<2}: {:#018x}  ", i * 4 + j, x)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// write the report of `info` on the calling PE to `w`
+pub fn write_report(w: &mut impl Write, info: &PanicInfo) -> fmt::Result {
+    writeln!(w, "PANIC on cpu {:#x}: {}", cpu::get_mpidr_affinity(), info)?;
+    if let Some(frame) = cpu::exception::current_frame() {
+        write_frame(w, &frame)?;
+    }
+    if REPORTING.swap(true, Ordering::Acquire) {
+        return writeln!(w, "panicked while reporting a panic, backtrace skipped");
+    }
+    writeln!(w, "backtrace:")?;
+    let mut result = Ok(());
+    let mut depth = 0;
+    backtrace::walk(
+        backtrace::frame_pointer(),
+        MAX_BACKTRACE_DEPTH,
+        |return_address| {
+            result = result.and_then(|_| writeln!(w, "  #{:<2} {:#x}", depth, return_address));
+            depth += 1;
+        },
+    );
+    REPORTING.store(false, Ordering::Release);
+    result
+}
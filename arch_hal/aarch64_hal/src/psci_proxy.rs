@@ -0,0 +1,115 @@
+//! PSCI calls issued by the guest (SMC trapped by HCR_EL2.TSC or HVC)
+
+use cpu::exception::TrapFrame;
+use cpu::psci;
+use cpu::psci::AffinityState;
+use cpu::psci::PsciErr;
+use mutex::SpinLock;
+
+/// Start the vCPU `target_mpidr` at `entry_point` in EL1 with `context_id` in x0
+pub type CpuOnHandler =
+    fn(target_mpidr: u64, entry_point: u64, context_id: u64) -> Result<(), PsciErr>;
+
+/// Power state of the vCPU `target_mpidr`
+pub type AffinityInfoHandler = fn(target_mpidr: u64) -> Result<AffinityState, PsciErr>;
+
+static CPU_ON_HANDLER: SpinLock<Option<CpuOnHandler>> = SpinLock::new(None);
+static AFFINITY_INFO_HANDLER: SpinLock<Option<AffinityInfoHandler>> = SpinLock::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsciPolicy {
+    /// pass the call through to the firmware unchanged
+    Forward,
+    /// emulate the call in the hypervisor
+    Virtualize,
+    /// return NOT_SUPPORTED to the guest
+    Deny,
+}
+
+pub fn policy(function_id: u32) -> PsciPolicy {
+    match function_id {
+        psci::PSCI_VERSION | psci::MIGRATE_INFO_TYPE | psci::SYSTEM_OFF | psci::SYSTEM_RESET => {
+            PsciPolicy::Forward
+        }
+        psci::CPU_ON_32
+        | psci::CPU_ON_64
+        | psci::CPU_OFF
+        | psci::AFFINITY_INFO_32
+        | psci::AFFINITY_INFO_64
+        | psci::PSCI_FEATURES => PsciPolicy::Virtualize,
+        // CPU_SUSPEND would resume at EL2 on the guest entry point
+        _ => PsciPolicy::Deny,
+    }
+}
+
+pub fn set_cpu_on_handler(handler: CpuOnHandler) {
+    *CPU_ON_HANDLER.lock() = Some(handler);
+}
+
+/// without a handler AFFINITY_INFO is passed through to the firmware
+pub fn set_affinity_info_handler(handler: AffinityInfoHandler) {
+    *AFFINITY_INFO_HANDLER.lock() = Some(handler);
+}
+
+/// Whether the guest call in `frame` is CPU_OFF.
+/// CPU_OFF does not return to the caller, `vcpu::Vcpu::run_loop` stops the vCPU instead
+pub fn is_cpu_off(frame: &TrapFrame) -> bool {
+    frame.x[0] as u32 == psci::CPU_OFF
+}
+
+/// Handle a guest SMC/HVC whose function ID is in x0.
+/// Returns false when the call is not a PSCI call.
+pub fn handle(frame: &mut TrapFrame) -> bool {
+    let function_id = frame.x[0] as u32;
+    // PSCI owns 0x8400_0000..=0x8400_001F and its SMC64 variants
+    if function_id & 0xBFFF_FFE0 != 0x8400_0000 {
+        return false;
+    }
+    let result = match policy(function_id) {
+        PsciPolicy::Forward => psci::call(function_id, frame.x[1], frame.x[2], frame.x[3]),
+        PsciPolicy::Virtualize => virtualize(function_id, frame),
+        PsciPolicy::Deny => Err(PsciErr::NotSupported),
+    };
+    frame.x[0] = match result {
+        Ok(value) => value,
+        Err(e) => e.to_return_value() as i64 as u64,
+    };
+    true
+}
+
+fn virtualize(function_id: u32, frame: &TrapFrame) -> Result<u64, PsciErr> {
+    match function_id {
+        psci::CPU_ON_32 | psci::CPU_ON_64 => {
+            let handler = (*CPU_ON_HANDLER.lock()).ok_or(PsciErr::InternalFailure)?;
+            let (mut entry_point, mut context_id) = (frame.x[2], frame.x[3]);
+            if function_id == psci::CPU_ON_32 {
+                entry_point &= u32::MAX as u64;
+                context_id &= u32::MAX as u64;
+            }
+            handler(
+                frame.x[1] & cpu::MPIDR_AFFINITY_MASK,
+                entry_point,
+                context_id,
+            )
+            .map(|_| 0)
+        }
+        // only reached by a guest which is not run by `vcpu::Vcpu::run_loop`
+        psci::CPU_OFF => Err(PsciErr::Denied),
+        psci::AFFINITY_INFO_32 | psci::AFFINITY_INFO_64 => {
+            let Some(handler) = *AFFINITY_INFO_HANDLER.lock() else {
+                return psci::call(function_id, frame.x[1], frame.x[2], 0);
+            };
+            // only the state of a single core is tracked
+            if frame.x[2] != 0 {
+                return Err(PsciErr::InvalidParameters);
+            }
+            handler(frame.x[1] & cpu::MPIDR_AFFINITY_MASK).map(|state| state as u64)
+        }
+        psci::PSCI_FEATURES => match policy(frame.x[1] as u32) {
+            PsciPolicy::Forward => psci::call(function_id, frame.x[1], 0, 0),
+            PsciPolicy::Virtualize => Ok(0),
+            PsciPolicy::Deny => Err(PsciErr::NotSupported),
+        },
+        _ => Err(PsciErr::NotSupported),
+    }
+}
@@ -0,0 +1,41 @@
+//! Console output through semihosting
+//!
+//! Shown by QEMU run with `-semihosting` even when the UART path is broken.
+//! Without a semihosting host the `HLT` traps, so only use it under such a host.
+
+use core::arch::asm;
+use core::fmt;
+
+const SYS_WRITE0: u64 = 0x04;
+
+/// write `s` with SYS_WRITE0, a NUL in `s` would end the output early and is dropped
+pub fn write_str(s: &str) {
+    let mut chunk = [0u8; 64];
+    let mut bytes = s.bytes().filter(|&byte| byte != 0).peekable();
+    while bytes.peek().is_some() {
+        let mut len = 0;
+        for byte in bytes.by_ref().take(chunk.len() - 1) {
+            chunk[len] = byte;
+            len += 1;
+        }
+        chunk[len] = 0;
+        unsafe {
+            asm!(
+                "hlt #0xf000",
+                inout("x0") SYS_WRITE0 => _,
+                in("x1") chunk.as_ptr(),
+                options(nostack)
+            );
+        }
+    }
+}
+
+/// `fmt::Write` through semihosting
+pub struct Writer;
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_str(s);
+        Ok(())
+    }
+}
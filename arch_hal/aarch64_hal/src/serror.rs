@@ -0,0 +1,174 @@
+//! SError (asynchronous external abort) and RAS error handling
+
+use core::arch::asm;
+use core::fmt;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+
+const HCR_EL2_AMO: u64 = 1 << 5;
+const HCR_EL2_VSE: u64 = 1 << 8;
+
+// DISR_EL1.A: an SError was deferred by ESB
+const DISR_A: u64 = 1 << 31;
+
+/// What to do with an SError taken while a guest is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SErrorPolicy {
+    /// inject a virtual SError into the guest unless the error is uncontainable
+    Inject,
+    /// treat every SError as fatal
+    Panic,
+}
+
+const POLICY_INJECT: u8 = 0;
+const POLICY_PANIC: u8 = 1;
+
+static POLICY: AtomicU8 = AtomicU8::new(POLICY_INJECT);
+
+/// ESR_EL2.AET / DISR_EL1.AET (FEAT_RAS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorType {
+    Uncontainable,
+    Unrecoverable,
+    Restartable,
+    Recoverable,
+    Corrected,
+    Unknown(u8),
+}
+
+/// Decoded SError syndrome (ESR_EL2.ISS with EC == 0x2F, or DISR_EL1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SErrorSyndrome {
+    /// raw ISS / DISR_EL1 value
+    pub raw: u64,
+    pub from_guest: bool,
+    /// the syndrome is IMPLEMENTATION DEFINED and the fields below are not valid
+    pub implementation_defined: bool,
+    pub error_type: ErrorType,
+    /// external abort type (ExT)
+    pub external_abort_type: bool,
+    /// DFSC: 0x00 uncategorized, 0x11 asynchronous SError
+    pub fault_status: u8,
+}
+
+impl SErrorSyndrome {
+    pub fn new(raw: u64, from_guest: bool) -> Self {
+        let error_type = match ((raw >> 10) & 0b111) as u8 {
+            0b000 => ErrorType::Uncontainable,
+            0b001 => ErrorType::Unrecoverable,
+            0b010 => ErrorType::Restartable,
+            0b011 => ErrorType::Recoverable,
+            0b110 => ErrorType::Corrected,
+            aet => ErrorType::Unknown(aet),
+        };
+        Self {
+            raw,
+            from_guest,
+            implementation_defined: raw & (1 << 24) != 0,
+            error_type,
+            external_abort_type: raw & (1 << 9) != 0,
+            fault_status: (raw & 0x3F) as u8,
+        }
+    }
+
+    /// the error may be reported to the guest and the system keeps running
+    pub fn is_containable(&self) -> bool {
+        self.implementation_defined
+            || !matches!(
+                self.error_type,
+                ErrorType::Uncontainable | ErrorType::Unrecoverable
+            )
+    }
+}
+
+impl fmt::Display for SErrorSyndrome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SError from {}: syndrome {:#x}",
+            if self.from_guest {
+                "guest"
+            } else {
+                "hypervisor"
+            },
+            self.raw
+        )?;
+        if self.implementation_defined {
+            write!(f, " (IMPLEMENTATION DEFINED)")
+        } else {
+            write!(
+                f,
+                ", type: {:?}, ExT: {}, DFSC: {:#x}",
+                self.error_type, self.external_abort_type as u8, self.fault_status
+            )
+        }
+    }
+}
+
+pub fn set_policy(policy: SErrorPolicy) {
+    let policy = match policy {
+        SErrorPolicy::Inject => POLICY_INJECT,
+        SErrorPolicy::Panic => POLICY_PANIC,
+    };
+    POLICY.store(policy, Ordering::Relaxed);
+}
+
+pub fn policy() -> SErrorPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        POLICY_INJECT => SErrorPolicy::Inject,
+        _ => SErrorPolicy::Panic,
+    }
+}
+
+fn has_ras() -> bool {
+    let pfr0: u64;
+    unsafe { asm!("mrs {}, id_aa64pfr0_el1", out(reg) pfr0) };
+    (pfr0 >> 28) & 0xF != 0
+}
+
+/// Route guest SErrors to EL2 (HCR_EL2.AMO) and unmask SError at EL2 on the calling PE.
+/// must be called after `cpu::setup_hypervisor_registers`
+pub fn init() {
+    cpu::set_hcr_el2(cpu::get_hcr_el2() | HCR_EL2_AMO);
+    unsafe { asm!("msr daifclr, #4") };
+}
+
+/// Read and clear the SError deferred by the ESB on the guest exit path (FEAT_RAS)
+pub fn take_deferred() -> Option<u64> {
+    if !has_ras() {
+        return None;
+    }
+    let disr: u64;
+    unsafe { asm!("mrs {}, s3_0_c12_c1_1", out(reg) disr) };
+    if disr & DISR_A == 0 {
+        return None;
+    }
+    unsafe { asm!("msr s3_0_c12_c1_1, xzr") };
+    Some(disr)
+}
+
+/// make a virtual SError with `syndrome` pending for the guest
+fn inject(syndrome: &SErrorSyndrome) {
+    if has_ras() {
+        // VSESR_EL2
+        let vsesr = syndrome.raw & 0x1FF_FFFF;
+        unsafe { asm!("msr s3_4_c5_c2_3, {}", in(reg) vsesr) };
+    }
+    cpu::set_hcr_el2(cpu::get_hcr_el2() | HCR_EL2_VSE);
+}
+
+/// Report an SError taken from the guest and forward it according to `policy`.
+/// Panics when the error can not be contained in the guest.
+pub fn handle_guest(raw: u64) {
+    let syndrome = SErrorSyndrome::new(raw, true);
+    if policy() == SErrorPolicy::Panic || !syndrome.is_containable() {
+        panic!("{}", syndrome);
+    }
+    crate::println!("{}, injected into the guest", syndrome);
+    inject(&syndrome);
+}
+
+/// SError taken from EL2: the hypervisor state can not be trusted anymore
+pub fn handle_hypervisor(raw: u64) -> ! {
+    panic!("{}", SErrorSyndrome::new(raw, false));
+}
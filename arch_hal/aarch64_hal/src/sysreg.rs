@@ -0,0 +1,257 @@
+//! Trap-and-emulate of guest (EL1) system register accesses (ESR_EL2.EC == 0x18)
+
+use core::arch::asm;
+
+use cpu::exception::TrapFrame;
+use mutex::SpinLock;
+
+use crate::debug;
+use crate::debug::DebugPolicy;
+
+const MAX_SYSREG_HANDLERS: usize = 32;
+
+// HCR_EL2 trap controls
+const HCR_EL2_TID3: u64 = 1 << 18;
+const HCR_EL2_TACR: u64 = 1 << 21;
+const HCR_EL2_TVM: u64 = 1 << 26;
+const HCR_EL2_TRVM: u64 = 1 << 30;
+
+/// system register encoding (op0, op1, CRn, CRm, op2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SysReg {
+    pub op0: u8,
+    pub op1: u8,
+    pub crn: u8,
+    pub crm: u8,
+    pub op2: u8,
+}
+
+impl SysReg {
+    pub const fn new(op0: u8, op1: u8, crn: u8, crm: u8, op2: u8) -> Self {
+        Self {
+            op0,
+            op1,
+            crn,
+            crm,
+            op2,
+        }
+    }
+
+    pub const MIDR_EL1: Self = Self::new(3, 0, 0, 0, 0);
+    pub const MPIDR_EL1: Self = Self::new(3, 0, 0, 0, 5);
+    pub const ID_AA64PFR0_EL1: Self = Self::new(3, 0, 0, 4, 0);
+    pub const ID_AA64PFR1_EL1: Self = Self::new(3, 0, 0, 4, 1);
+    pub const ID_AA64DFR0_EL1: Self = Self::new(3, 0, 0, 5, 0);
+    pub const ID_AA64ISAR0_EL1: Self = Self::new(3, 0, 0, 6, 0);
+    pub const ID_AA64ISAR1_EL1: Self = Self::new(3, 0, 0, 6, 1);
+    pub const ID_AA64MMFR0_EL1: Self = Self::new(3, 0, 0, 7, 0);
+    pub const ID_AA64MMFR1_EL1: Self = Self::new(3, 0, 0, 7, 1);
+    pub const ID_AA64MMFR2_EL1: Self = Self::new(3, 0, 0, 7, 2);
+    pub const SCTLR_EL1: Self = Self::new(3, 0, 1, 0, 0);
+    pub const ACTLR_EL1: Self = Self::new(3, 0, 1, 0, 1);
+    pub const TTBR0_EL1: Self = Self::new(3, 0, 2, 0, 0);
+    pub const TTBR1_EL1: Self = Self::new(3, 0, 2, 0, 1);
+    pub const TCR_EL1: Self = Self::new(3, 0, 2, 0, 2);
+    pub const AFSR0_EL1: Self = Self::new(3, 0, 5, 1, 0);
+    pub const AFSR1_EL1: Self = Self::new(3, 0, 5, 1, 1);
+    pub const ESR_EL1: Self = Self::new(3, 0, 5, 2, 0);
+    pub const FAR_EL1: Self = Self::new(3, 0, 6, 0, 0);
+    pub const MAIR_EL1: Self = Self::new(3, 0, 10, 2, 0);
+    pub const AMAIR_EL1: Self = Self::new(3, 0, 10, 3, 0);
+    pub const CONTEXTIDR_EL1: Self = Self::new(3, 0, 13, 0, 1);
+
+    /// feature ID register space (op0 == 3, op1 == 0, CRn == 0, CRm == 1..=7) trapped by TID3
+    pub fn is_feature_id(&self) -> bool {
+        self.op0 == 3 && self.op1 == 0 && self.crn == 0 && (1..=7).contains(&self.crm)
+    }
+}
+
+/// decoded ISS of a trapped MSR/MRS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SysRegAccess {
+    pub reg: SysReg,
+    /// transfer register, 31 is XZR
+    pub rt: usize,
+    /// MRS (guest reads the register)
+    pub is_read: bool,
+}
+
+impl SysRegAccess {
+    pub fn from_iss(iss: u32) -> Self {
+        Self {
+            reg: SysReg::new(
+                ((iss >> 20) & 0b11) as u8,
+                ((iss >> 14) & 0b111) as u8,
+                ((iss >> 10) & 0b1111) as u8,
+                ((iss >> 1) & 0b1111) as u8,
+                ((iss >> 17) & 0b111) as u8,
+            ),
+            rt: ((iss >> 5) & 0b1_1111) as usize,
+            is_read: iss & 1 != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysRegErr {
+    NoFreeSlot,
+    /// the register cannot be accessed by `access_hardware`
+    Unsupported,
+}
+
+/// Emulate `access`. `value` holds the written value on MSR
+/// and the value returned to the guest must be stored on MRS.
+pub type SysRegHandler = fn(access: &SysRegAccess, value: &mut u64);
+
+static HANDLERS: SpinLock<[Option<(SysReg, SysRegHandler)>; MAX_SYSREG_HANDLERS]> =
+    SpinLock::new([None; MAX_SYSREG_HANDLERS]);
+
+/// EL1 register accesses trapped to EL2 (HCR_EL2)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SysRegTraps {
+    /// TVM: writes to the virtual memory control registers (SCTLR, TTBRn, TCR, MAIR, ...)
+    pub vm_write: bool,
+    /// TRVM: reads of the virtual memory control registers
+    pub vm_read: bool,
+    /// TACR: ACTLR_EL1
+    pub actlr: bool,
+    /// TID3: feature ID registers
+    pub feature_id: bool,
+}
+
+/// configure which registers are trapped on the calling PE.
+/// must be called after `cpu::setup_hypervisor_registers`
+pub fn configure_traps(traps: SysRegTraps) {
+    let mut hcr_el2 =
+        cpu::get_hcr_el2() & !(HCR_EL2_TVM | HCR_EL2_TRVM | HCR_EL2_TACR | HCR_EL2_TID3);
+    for (enable, bit) in [
+        (traps.vm_write, HCR_EL2_TVM),
+        (traps.vm_read, HCR_EL2_TRVM),
+        (traps.actlr, HCR_EL2_TACR),
+        (traps.feature_id, HCR_EL2_TID3),
+    ] {
+        if enable {
+            hcr_el2 |= bit;
+        }
+    }
+    cpu::set_hcr_el2(hcr_el2);
+}
+
+/// register `handler` for `reg`, replacing the previous one.
+/// trapped registers without a handler are passed through to the hardware
+pub fn register_handler(reg: SysReg, handler: SysRegHandler) -> Result<(), SysRegErr> {
+    let mut handlers = HANDLERS.lock();
+    if let Some(entry) = handlers.iter_mut().flatten().find(|(r, _)| *r == reg) {
+        entry.1 = handler;
+        return Ok(());
+    }
+    let slot = handlers
+        .iter_mut()
+        .find(|h| h.is_none())
+        .ok_or(SysRegErr::NoFreeSlot)?;
+    *slot = Some((reg, handler));
+    Ok(())
+}
+
+macro_rules! feature_id_reads {
+    ($reg:expr, $value:expr, $($crm:literal),*) => {
+        match ($reg.crm, $reg.op2) {
+            $(
+                ($crm, 0) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_0"), out(reg) *$value) },
+                ($crm, 1) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_1"), out(reg) *$value) },
+                ($crm, 2) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_2"), out(reg) *$value) },
+                ($crm, 3) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_3"), out(reg) *$value) },
+                ($crm, 4) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_4"), out(reg) *$value) },
+                ($crm, 5) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_5"), out(reg) *$value) },
+                ($crm, 6) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_6"), out(reg) *$value) },
+                ($crm, 7) => unsafe { asm!(concat!("mrs {}, s3_0_c0_c", $crm, "_7"), out(reg) *$value) },
+            )*
+            _ => unreachable!(),
+        }
+    };
+}
+
+macro_rules! el1_register_accesses {
+    ($reg:expr, $is_read:expr, $value:expr, $($name:ident => $asm_name:literal),*) => {
+        $(
+            if $reg == SysReg::$name {
+                if $is_read {
+                    unsafe { asm!(concat!("mrs {}, ", $asm_name), out(reg) *$value) };
+                } else {
+                    unsafe { asm!(concat!("msr ", $asm_name, ", {}"), in(reg) *$value) };
+                }
+                return Ok(());
+            }
+        )*
+    };
+}
+
+/// perform the access on the real EL1 register
+pub fn access_hardware(reg: SysReg, is_read: bool, value: &mut u64) -> Result<(), SysRegErr> {
+    if reg.is_feature_id() {
+        if !is_read {
+            return Err(SysRegErr::Unsupported);
+        }
+        feature_id_reads!(reg, value, 1, 2, 3, 4, 5, 6, 7);
+        return Ok(());
+    }
+    el1_register_accesses!(
+        reg, is_read, value,
+        SCTLR_EL1 => "sctlr_el1",
+        ACTLR_EL1 => "actlr_el1",
+        TTBR0_EL1 => "ttbr0_el1",
+        TTBR1_EL1 => "ttbr1_el1",
+        TCR_EL1 => "tcr_el1",
+        AFSR0_EL1 => "afsr0_el1",
+        AFSR1_EL1 => "afsr1_el1",
+        ESR_EL1 => "esr_el1",
+        FAR_EL1 => "far_el1",
+        MAIR_EL1 => "mair_el1",
+        AMAIR_EL1 => "amair_el1",
+        CONTEXTIDR_EL1 => "contextidr_el1"
+    );
+    if is_read {
+        if reg == SysReg::MIDR_EL1 {
+            unsafe { asm!("mrs {}, midr_el1", out(reg) * value) };
+            return Ok(());
+        }
+        if reg == SysReg::MPIDR_EL1 {
+            unsafe { asm!("mrs {}, mpidr_el1", out(reg) * value) };
+            return Ok(());
+        }
+    }
+    Err(SysRegErr::Unsupported)
+}
+
+/// Emulate the trapped MSR/MRS in `frame`.
+/// Debug and PMU registers follow `debug::configure_traps`.
+/// Returns false when the register is neither handled nor passed through.
+pub fn handle(frame: &mut TrapFrame) -> bool {
+    let access = SysRegAccess::from_iss(frame.iss());
+    let mut value = if access.is_read || access.rt == 31 {
+        0
+    } else {
+        frame.x[access.rt]
+    };
+    let handler = HANDLERS
+        .lock()
+        .iter()
+        .flatten()
+        .find(|(r, _)| *r == access.reg)
+        .map(|(_, handler)| *handler);
+    match (debug::policy_of(&access.reg), handler) {
+        // read as zero, write ignored
+        (Some(DebugPolicy::Hide), _) | (Some(DebugPolicy::Emulate), None) => value = 0,
+        (_, Some(handler)) => handler(&access, &mut value),
+        (_, None) => {
+            if access_hardware(access.reg, access.is_read, &mut value).is_err() {
+                return false;
+            }
+        }
+    }
+    if access.is_read && access.rt != 31 {
+        frame.x[access.rt] = value;
+    }
+    frame.advance_pc();
+    true
+}
@@ -0,0 +1,247 @@
+//! EL2 physical timer (CNTHP_*) with one-shot and periodic callbacks
+
+use core::arch::asm;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use cpu::timer;
+use gic::GicErr;
+use gic::TriggerMode;
+use mutex::SpinLock;
+
+use crate::GIC;
+use crate::interrupt;
+
+/// EL2 physical timer PPI on QEMU virt and most boards
+pub const DEFAULT_HYP_TIMER_INTID: u32 = 26;
+const MAX_TIMERS: usize = 16;
+
+pub type TimerCallback = fn();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerErr {
+    NoFreeSlot,
+    InvalidPeriod,
+    InvalidTimerId,
+    /// `init` has not routed the timer interrupt
+    NotInitialized,
+}
+
+/// guest access to the EL1 physical counter and timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicalTimerAccess {
+    Allow,
+    /// trap CNTP_* but allow reading CNTPCT_EL0
+    TrapTimer,
+    /// trap CNTP_* and CNTPCT_EL0
+    TrapAll,
+}
+
+#[derive(Clone, Copy)]
+struct TimerEntry {
+    deadline: u64,
+    period: Option<u64>,
+    callback: TimerCallback,
+}
+
+static TIMERS: SpinLock<[Option<TimerEntry>; MAX_TIMERS]> = SpinLock::new([None; MAX_TIMERS]);
+// set by `init`, the EL2 timer registers are not accessible before
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// current physical count in ticks
+pub fn now() -> u64 {
+    timer::counter()
+}
+
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    (duration.as_nanos() * timer::frequency() as u128 / 1_000_000_000) as u64
+}
+
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_nanos((ticks as u128 * 1_000_000_000 / timer::frequency() as u128) as u64)
+}
+
+/// time since the physical count started, normally since reset
+pub fn uptime() -> Duration {
+    ticks_to_duration(now())
+}
+
+/// deadline in ticks which expires `duration` from now
+pub fn deadline_after(duration: Duration) -> u64 {
+    now().saturating_add(duration_to_ticks(duration))
+}
+
+pub fn is_expired(deadline: u64) -> bool {
+    now() >= deadline
+}
+
+/// CNTVOFF_EL2 value which makes the guest virtual counter read `epoch` now
+pub fn counter_offset_for_epoch(epoch: u64) -> u64 {
+    now().wrapping_sub(epoch)
+}
+
+/// CNTVOFF_EL2 of the calling PE
+pub fn guest_counter_offset() -> u64 {
+    timer::virtual_offset()
+}
+
+/// program CNTVOFF_EL2 and CNTHCTL_EL2 of the calling PE before entering the guest.
+/// every PE of a guest must use the same `counter_offset`
+pub fn configure_guest_timer(counter_offset: u64, access: PhysicalTimerAccess) {
+    timer::set_virtual_offset(counter_offset);
+    match access {
+        PhysicalTimerAccess::Allow => timer::set_el1_physical_access(true, true),
+        PhysicalTimerAccess::TrapTimer => timer::set_el1_physical_access(true, false),
+        PhysicalTimerAccess::TrapAll => timer::set_el1_physical_access(false, false),
+    }
+}
+
+/// route the EL2 physical timer interrupt `intid` to the callback dispatcher.
+/// the GIC must have been initialized
+pub fn init(intid: u32) -> Result<(), GicErr> {
+    timer::disable_hyp_timer();
+    {
+        let gic = GIC.lock();
+        let gic = gic.get().ok_or(GicErr::RedistributorNotFound)?;
+        gic.set_trigger_mode(intid, TriggerMode::Level)?;
+        gic.enable_interrupt(intid)?;
+    }
+    interrupt::register_irq_handler(intid, |_| handle_interrupt())?;
+    INITIALIZED.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// route the EL1 physical timer interrupt `intid` to the test watchdog and guard the
+/// following `#[test_case]`s with `timeout`. The GIC must have been initialized
+#[cfg(feature = "uefi-test")]
+pub fn init_test_watchdog(intid: u32, timeout: Duration) -> Result<(), GicErr> {
+    timer::disable_physical_timer();
+    {
+        let gic = GIC.lock();
+        let gic = gic.get().ok_or(GicErr::RedistributorNotFound)?;
+        gic.set_trigger_mode(intid, TriggerMode::Level)?;
+        gic.enable_interrupt(intid)?;
+    }
+    interrupt::register_irq_handler(intid, |_| aarch64_test::watchdog::handle_interrupt())?;
+    aarch64_test::watchdog::enable(timeout);
+    Ok(())
+}
+
+/// Run the callbacks whose deadline has passed. For loops which run with IRQs masked,
+/// such as the loader before the handoff. Does nothing before `init`
+pub fn poll() {
+    if INITIALIZED.load(Ordering::Acquire) && timer::hyp_timer_fired() {
+        handle_interrupt();
+    }
+}
+
+fn wake() {}
+
+/// Halt the calling PE until the physical count reaches `deadline`, instead of spinning.
+/// `init` must have routed the timer interrupt and IRQs must be masked, interrupts which
+/// wake the PE early are dispatched before it halts again.
+pub fn wfi_until(deadline: u64) -> Result<(), TimerErr> {
+    if is_expired(deadline) {
+        return Ok(());
+    }
+    // not cancelled: once it fired the slot may belong to a timer a callback added
+    add_oneshot_at(deadline, wake)?;
+    while !is_expired(deadline) {
+        unsafe { asm!("dsb sy", "wfi") };
+        cpu::enable_irq();
+        cpu::disable_irq();
+    }
+    Ok(())
+}
+
+fn add(deadline: u64, period: Option<u64>, callback: TimerCallback) -> Result<TimerId, TimerErr> {
+    if !INITIALIZED.load(Ordering::Acquire) {
+        return Err(TimerErr::NotInitialized);
+    }
+    let mut timers = TIMERS.lock();
+    let (index, slot) = timers
+        .iter_mut()
+        .enumerate()
+        .find(|(_, t)| t.is_none())
+        .ok_or(TimerErr::NoFreeSlot)?;
+    *slot = Some(TimerEntry {
+        deadline,
+        period,
+        callback,
+    });
+    program(&timers);
+    Ok(TimerId(index))
+}
+
+/// call `callback` once from the timer interrupt after `after`
+pub fn add_oneshot(after: Duration, callback: TimerCallback) -> Result<TimerId, TimerErr> {
+    add(deadline_after(after), None, callback)
+}
+
+/// call `callback` once from the timer interrupt when the physical count reaches `deadline`
+pub fn add_oneshot_at(deadline: u64, callback: TimerCallback) -> Result<TimerId, TimerErr> {
+    add(deadline, None, callback)
+}
+
+/// call `callback` from the timer interrupt every `period`
+pub fn add_periodic(period: Duration, callback: TimerCallback) -> Result<TimerId, TimerErr> {
+    let period = duration_to_ticks(period);
+    if period == 0 {
+        return Err(TimerErr::InvalidPeriod);
+    }
+    add(now().saturating_add(period), Some(period), callback)
+}
+
+pub fn cancel(id: TimerId) -> Result<(), TimerErr> {
+    let mut timers = TIMERS.lock();
+    timers
+        .get_mut(id.0)
+        .and_then(|t| t.take())
+        .ok_or(TimerErr::InvalidTimerId)?;
+    program(&timers);
+    Ok(())
+}
+
+// program the compare value with the earliest deadline
+fn program(timers: &[Option<TimerEntry>; MAX_TIMERS]) {
+    match timers.iter().flatten().map(|t| t.deadline).min() {
+        Some(deadline) => {
+            timer::set_hyp_timer_compare(deadline);
+            timer::enable_hyp_timer();
+        }
+        None => timer::disable_hyp_timer(),
+    }
+}
+
+fn handle_interrupt() {
+    let mut expired = [None; MAX_TIMERS];
+    {
+        let mut timers = TIMERS.lock();
+        let now = now();
+        for (slot, callback) in timers.iter_mut().zip(expired.iter_mut()) {
+            let Some(entry) = slot else {
+                continue;
+            };
+            if entry.deadline > now {
+                continue;
+            }
+            *callback = Some(entry.callback);
+            match entry.period {
+                // skip periods which were missed while the interrupt was masked
+                Some(period) => {
+                    entry.deadline += period * ((now - entry.deadline) / period + 1);
+                }
+                None => *slot = None,
+            }
+        }
+        program(&timers);
+    }
+    // callbacks may add or cancel timers
+    for callback in expired.into_iter().flatten() {
+        callback();
+    }
+}
@@ -0,0 +1,126 @@
+//! The serial ports the debug console can drive
+//!
+//! `UartConfig` describes one, as found in the firmware dtb, and `Uart` drives it.
+
+use core::fmt;
+
+use ns16550::Ns16550Uart;
+use pl011::Pl011Uart;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartKind {
+    Pl011,
+    /// `reg-shift` and `reg-io-width` of the dtb node
+    Ns16550 {
+        reg_shift: u8,
+        reg_io_width: u8,
+    },
+}
+
+impl UartKind {
+    /// the kind of a node `compatible` with, the `Ns16550` register layout is the default one
+    pub fn from_compatible(compatible: &str) -> Option<Self> {
+        if Pl011Uart::COMPATIBLES.contains(&compatible) {
+            Some(Self::Pl011)
+        } else if Ns16550Uart::COMPATIBLES.contains(&compatible) {
+            Some(Self::Ns16550 {
+                reg_shift: 0,
+                reg_io_width: 1,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartConfig {
+    pub kind: UartKind,
+    pub base: usize,
+    /// reference clock in Hz, if the firmware describes it
+    pub clock: Option<u32>,
+    /// line speed to program, the firmware setup is kept without it or the clock
+    pub baudrate: Option<u32>,
+}
+
+impl UartConfig {
+    pub const fn pl011(base: usize, clock: Option<u32>) -> Self {
+        Self {
+            kind: UartKind::Pl011,
+            base,
+            clock,
+            baudrate: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Uart {
+    Pl011(Pl011Uart),
+    Ns16550(Ns16550Uart),
+}
+
+impl Uart {
+    /// the UART of `config`, as the firmware left it
+    pub fn new(config: &UartConfig) -> Self {
+        match config.kind {
+            UartKind::Pl011 => Self::Pl011(Pl011Uart::new(config.base)),
+            UartKind::Ns16550 {
+                reg_shift,
+                reg_io_width,
+            } => Self::Ns16550(Ns16550Uart::new(
+                config.base,
+                reg_shift.into(),
+                reg_io_width.into(),
+            )),
+        }
+    }
+
+    pub fn init(&self, uart_clk: u32, baudrate: u32) {
+        match self {
+            Self::Pl011(uart) => uart.init(uart_clk, baudrate),
+            Self::Ns16550(uart) => uart.init(uart_clk, baudrate),
+        }
+    }
+
+    pub fn flush(&self) {
+        match self {
+            Self::Pl011(uart) => uart.flush(),
+            Self::Ns16550(uart) => uart.flush(),
+        }
+    }
+
+    /// wait for the running DMA transfer and write by the CPU from now on
+    pub fn disable_dma(&mut self) {
+        match self {
+            Self::Pl011(uart) => uart.disable_dma(),
+            Self::Ns16550(_) => {}
+        }
+    }
+
+    /// send `byte` as is, without the LF to CRLF conversion of `fmt::Write`
+    pub fn write_byte(&self, byte: u8) {
+        match self {
+            Self::Pl011(uart) => uart.write_byte(byte),
+            Self::Ns16550(uart) => uart.write_byte(byte),
+        }
+    }
+
+    /// returns `None` instead of waiting when nothing was received
+    pub fn try_read_char(&self) -> Option<u8> {
+        match self {
+            Self::Pl011(uart) => uart.try_read_char(),
+            Self::Ns16550(uart) => uart.try_read_char(),
+        }
+    }
+}
+
+impl fmt::Write for Uart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self {
+            Self::Pl011(uart) => uart.write(s),
+            Self::Ns16550(uart) => uart.write(s),
+        }
+        Ok(())
+    }
+}
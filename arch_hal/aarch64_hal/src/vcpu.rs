@@ -0,0 +1,163 @@
+//! Guest run loop: enter the guest, decode why it exited and dispatch to the emulation
+
+use cpu::exception::ExceptionClass;
+use cpu::exception::ExceptionSource;
+use cpu::exception::ExceptionType;
+use cpu::exception::TrapFrame;
+use cpu::psci::PsciErr;
+
+use crate::fpsimd;
+use crate::hypercall;
+use crate::idle;
+use crate::interrupt;
+use crate::mmio;
+use crate::psci_proxy;
+use crate::serror;
+use crate::sysreg;
+
+/// EL1h with DAIF masked
+pub const SPSR_EL1H_DAIF_MASKED: u64 = 0x3C5;
+
+// ESR_EL2.ISS.TI bit 0: 0 WFI, 1 WFE
+const ISS_TI_WFE: u32 = 1 << 0;
+
+/// Why the guest returned to EL2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// HVC with the immediate
+    Hvc(u16),
+    /// SMC trapped by HCR_EL2.TSC with the immediate
+    Smc(u16),
+    /// MSR/MRS trapped by the HCR_EL2 trap controls
+    SysReg,
+    /// stage-2 data abort, emulated as MMIO
+    Mmio,
+    Wfi,
+    Wfe,
+    /// first FP/SIMD access since the hypervisor took the registers
+    FpSimd,
+    Irq,
+    Fiq,
+    SError,
+    /// any other synchronous exception
+    Fault(ExceptionClass),
+}
+
+impl ExitReason {
+    /// decode an exception from the guest
+    pub fn new(frame: &TrapFrame, exception_type: ExceptionType) -> Self {
+        match exception_type {
+            ExceptionType::Irq => return Self::Irq,
+            ExceptionType::Fiq => return Self::Fiq,
+            ExceptionType::SError => return Self::SError,
+            ExceptionType::Synchronous => {}
+        }
+        let imm16 = frame.iss() as u16;
+        match frame.exception_class() {
+            ExceptionClass::Hvc64 => Self::Hvc(imm16),
+            ExceptionClass::Smc64 => Self::Smc(imm16),
+            ExceptionClass::SysReg64 => Self::SysReg,
+            ExceptionClass::DataAbortLower => Self::Mmio,
+            ExceptionClass::WfiWfe if frame.iss() & ISS_TI_WFE != 0 => Self::Wfe,
+            ExceptionClass::WfiWfe => Self::Wfi,
+            ExceptionClass::SimdFp => Self::FpSimd,
+            ec => Self::Fault(ec),
+        }
+    }
+}
+
+/// Emulate the exit `reason` on `frame`.
+/// Returns false when the hypervisor has no handler for it.
+pub fn dispatch(frame: &mut TrapFrame, reason: ExitReason) -> bool {
+    match reason {
+        ExitReason::Hvc(_) | ExitReason::Smc(_) => {
+            // ELR_EL2 points to the trapped SMC, but past HVC
+            if matches!(reason, ExitReason::Smc(_)) {
+                frame.advance_pc();
+            }
+            let handled = psci_proxy::handle(frame)
+                || (matches!(reason, ExitReason::Hvc(_)) && hypercall::handle(frame));
+            if !handled {
+                frame.x[0] = PsciErr::NotSupported.to_return_value() as i64 as u64;
+            }
+            true
+        }
+        ExitReason::SysReg => sysreg::handle(frame),
+        ExitReason::Mmio => mmio::handle(frame),
+        ExitReason::Wfi | ExitReason::Wfe => {
+            idle::handle(frame);
+            true
+        }
+        // the trapped instruction is retried with the guest registers loaded
+        ExitReason::FpSimd => {
+            fpsimd::handle_guest_trap();
+            true
+        }
+        ExitReason::Irq => {
+            interrupt::handle_irq();
+            true
+        }
+        // panics unless the error is injected into the guest
+        ExitReason::SError => {
+            serror::handle_guest(frame.iss() as u64);
+            true
+        }
+        ExitReason::Fiq | ExitReason::Fault(_) => false,
+    }
+}
+
+/// A virtual CPU bound to the PE which calls `run`
+pub struct Vcpu {
+    frame: TrapFrame,
+}
+
+impl Vcpu {
+    /// a vCPU which starts at `entry_point` in EL1h with `x0` as its first argument
+    pub fn new(entry_point: u64, x0: u64) -> Self {
+        let mut frame = TrapFrame::new(entry_point, SPSR_EL1H_DAIF_MASKED);
+        frame.x[0] = x0;
+        Self { frame }
+    }
+
+    pub fn frame(&self) -> &TrapFrame {
+        &self.frame
+    }
+
+    pub fn frame_mut(&mut self) -> &mut TrapFrame {
+        &mut self.frame
+    }
+
+    /// Enter the guest via ERET and return at its next exit without handling it
+    pub fn run(&mut self) -> ExitReason {
+        fpsimd::prepare_guest_entry();
+        let (source, exception_type) = cpu::exception::enter_guest(&mut self.frame);
+        fpsimd::on_guest_exit();
+        if let Some(disr) = serror::take_deferred() {
+            serror::handle_guest(disr);
+        }
+        if source != ExceptionSource::LowerElAarch64 {
+            panic!("guest exited from {:?}", source);
+        }
+        ExitReason::new(&self.frame, exception_type)
+    }
+
+    /// Run the guest and dispatch every exit until the guest turns this vCPU off by PSCI CPU_OFF.
+    /// Panics on an exit which the hypervisor can not handle.
+    pub fn run_loop(&mut self) {
+        loop {
+            let reason = self.run();
+            if matches!(reason, ExitReason::Hvc(_) | ExitReason::Smc(_))
+                && psci_proxy::is_cpu_off(&self.frame)
+            {
+                return;
+            }
+            if !dispatch(&mut self.frame, reason) {
+                let frame = &self.frame;
+                panic!(
+                    "unhandled guest exit: {:?}, esr: {:#x}, elr: {:#x}, far: {:#x}",
+                    reason, frame.esr, frame.elr, frame.far
+                );
+            }
+        }
+    }
+}
@@ -7,6 +7,10 @@ fn main() {
     println!("cargo:rerun-if-changed=aarch64.lds");
     println!("cargo:rerun-if-env-changed=XTASK_BUILD");
 
+    // a UEFI application is a PE image placed by the firmware, it has no linker script
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("uefi") {
+        return;
+    }
     println!("cargo:rustc-link-search={}", crate_dir);
     println!("cargo:rustc-link-arg=-Taarch64.lds");
 }
@@ -0,0 +1,233 @@
+// interactive boot menu on the debug UART
+
+use crate::chainload::Target;
+use crate::config::BootConfig;
+use crate::guest::GuestConfig;
+use crate::payload;
+use crate::payload::PayloadKind;
+use crate::shell;
+use crate::systimer::SystemTimer;
+use alloc::format;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use arch_hal::debug_uart;
+use arch_hal::print;
+use arch_hal::println;
+use core::ops::ControlFlow;
+use core::time::Duration;
+use dtb::DtbParser;
+use file::OpenOptions;
+use file::StorageDevice;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+// kernel loaded when neither the config file nor the directory scan finds one
+const FALLBACK_KERNEL_PATH: &str = "/image";
+const SCAN_DIRECTORY: &str = "/";
+
+const KEY_BACKSPACE: u8 = 0x08;
+const KEY_DELETE: u8 = 0x7F;
+const KEY_CTRL_U: u8 = 0x15;
+const KEY_ESCAPE: u8 = 0x1B;
+
+#[derive(Debug, Clone)]
+pub struct BootEntry {
+    pub label: String,
+    pub path: String,
+    /// chainload the file instead of booting it as a kernel
+    pub chain: Option<Target>,
+    /// boot the file as this guest
+    pub guest: Option<GuestConfig>,
+}
+
+/// guests and entries of the config file followed by the kernel images and ELF files found in
+/// `SCAN_DIRECTORY`
+pub fn collect_entries(config: &BootConfig, storage: &StorageDevice) -> Vec<BootEntry> {
+    let mut entries: Vec<BootEntry> = config
+        .guests
+        .iter()
+        .map(|guest| BootEntry {
+            label: guest.label.clone(),
+            path: guest.kernel.clone(),
+            chain: None,
+            guest: Some(guest.clone()),
+        })
+        .chain(config.entries.iter().map(|(label, path, chain)| BootEntry {
+            label: label.clone(),
+            path: path.clone(),
+            chain: *chain,
+            guest: None,
+        }))
+        .collect();
+    let mut found = Vec::new();
+    let _ = storage.read_dir(0, SCAN_DIRECTORY, &mut |entry| {
+        if !entry.is_dir && entry.size as usize >= PayloadKind::HEADER_SIZE {
+            found.push(format!("{}{}", SCAN_DIRECTORY, entry.name));
+        }
+        ControlFlow::Continue(())
+    });
+    for path in found {
+        if entries.iter().any(|e| e.path.eq_ignore_ascii_case(&path))
+            || !is_bootable(storage, &path)
+        {
+            continue;
+        }
+        entries.push(BootEntry {
+            label: path[SCAN_DIRECTORY.len()..].to_string(),
+            path,
+            chain: None,
+            guest: None,
+        });
+    }
+    if entries.is_empty() {
+        entries.push(BootEntry {
+            label: "default".to_string(),
+            path: FALLBACK_KERNEL_PATH.to_string(),
+            chain: None,
+            guest: None,
+        });
+    }
+    entries
+}
+
+fn is_bootable(storage: &StorageDevice, path: &str) -> bool {
+    storage
+        .open(0, path, &OpenOptions::Read)
+        .is_ok_and(|file| payload::detect(&file).is_some())
+}
+
+/// Show `entries` and return the index of the entry to boot.
+/// The first entry boots unless a key is pressed within `timeout`.
+/// `bootargs` can be edited from the menu and `pet` is called while waiting for input.
+/// The debug shell reads files from `storage` and nodes from `dtb`.
+pub fn run(
+    storage: &StorageDevice,
+    dtb: &DtbParser,
+    entries: &[BootEntry],
+    bootargs: &mut Option<String>,
+    timeout: Option<Duration>,
+    timer: &SystemTimer,
+    pet: &dyn Fn(),
+) -> usize {
+    print_entries(entries, bootargs);
+    if !wait_for_key(timer, timeout.unwrap_or(DEFAULT_TIMEOUT), &entries[0], pet) {
+        return 0;
+    }
+    loop {
+        print!(
+            "select 0-{}, e: edit command line, s: shell, enter: boot 0> ",
+            entries.len() - 1
+        );
+        let key = read_key(pet);
+        println!();
+        match key {
+            b'\r' | b'\n' => return 0,
+            b'0'..=b'9' if ((key - b'0') as usize) < entries.len() => {
+                return (key - b'0') as usize;
+            }
+            b'e' => {
+                print!("bootargs: ");
+                if let Some(line) = read_line(bootargs.as_deref().unwrap_or(""), pet) {
+                    *bootargs = (!line.is_empty()).then_some(line);
+                }
+            }
+            b's' => {
+                if let Some(index) = shell::run(storage, dtb, entries, pet) {
+                    return index;
+                }
+                print_entries(entries, bootargs);
+            }
+            _ => println!("unknown key"),
+        }
+    }
+}
+
+fn print_entries(entries: &[BootEntry], bootargs: &Option<String>) {
+    println!("boot menu:");
+    for (i, entry) in entries.iter().enumerate() {
+        match entry.chain {
+            Some(target) => println!(
+                "  [{}] {} ({} chainloaded at {})",
+                i, entry.label, entry.path, target
+            ),
+            None => println!("  [{}] {} ({})", i, entry.label, entry.path),
+        }
+    }
+    println!("  bootargs: {}", bootargs.as_deref().unwrap_or("(none)"));
+}
+
+// returns whether a key was pressed before `timeout`
+fn wait_for_key(
+    timer: &SystemTimer,
+    timeout: Duration,
+    default: &BootEntry,
+    pet: &dyn Fn(),
+) -> bool {
+    let deadline = timer.deadline(timeout);
+    let mut shown = None;
+    loop {
+        if debug_uart::try_read_byte().is_some() {
+            println!();
+            return true;
+        }
+        let remaining = timer.remaining(deadline);
+        if remaining.is_zero() {
+            println!();
+            return false;
+        }
+        // round up so the countdown ends at 1
+        let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() != 0);
+        if shown != Some(seconds) {
+            print!(
+                "\rbooting '{}' in {}s, press any key for the menu ",
+                default.label, seconds
+            );
+            shown = Some(seconds);
+        }
+        pet();
+        core::hint::spin_loop();
+    }
+}
+
+pub(crate) fn read_key(pet: &dyn Fn()) -> u8 {
+    loop {
+        if let Some(key) = debug_uart::try_read_byte() {
+            return key;
+        }
+        pet();
+        core::hint::spin_loop();
+    }
+}
+
+/// Read a line with `initial` already typed. Returns `None` when cancelled with escape.
+pub(crate) fn read_line(initial: &str, pet: &dyn Fn()) -> Option<String> {
+    let mut line = String::from(initial);
+    print!("{}", line);
+    loop {
+        match read_key(pet) {
+            b'\r' | b'\n' => {
+                println!();
+                return Some(line);
+            }
+            KEY_ESCAPE => {
+                println!();
+                return None;
+            }
+            KEY_BACKSPACE | KEY_DELETE if !line.is_empty() => {
+                line.pop();
+                print!("\x08 \x08");
+            }
+            KEY_CTRL_U => {
+                for _ in 0..line.len() {
+                    print!("\x08 \x08");
+                }
+                line.clear();
+            }
+            key if key.is_ascii_graphic() || key == b' ' => {
+                line.push(key as char);
+                debug_uart::write_byte(key);
+            }
+            _ => {}
+        }
+    }
+}
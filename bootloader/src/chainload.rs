@@ -0,0 +1,222 @@
+// chainloading another boot loader (U-Boot, a vendor second stage) as a raw binary
+//
+// The binary is copied to its configured address and entered like the previous boot stage
+// would enter it: MMU and caches off, interrupts masked, the firmware dtb in x0 and zero in
+// x1-x3. The hypervisor does not stay resident. EFI applications need UEFI boot services,
+// which are not provided, and are refused.
+
+use crate::payload::PayloadKind;
+use arch_hal::cpu;
+use arch_hal::cpu::cache;
+use core::arch::asm;
+use core::fmt;
+use core::ptr::slice_from_raw_parts_mut;
+use crypto::sha256;
+use crypto::sha256::DIGEST_SIZE;
+use file::FileHandle;
+
+const PAGE_SIZE: usize = 0x1000;
+// PE/COFF images start with the MS-DOS header
+const PE_MAGIC: [u8; 2] = *b"MZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionLevel {
+    El1,
+    El2,
+}
+
+impl ExceptionLevel {
+    fn number(self) -> u64 {
+        match self {
+            Self::El1 => 1,
+            Self::El2 => 2,
+        }
+    }
+}
+
+/// where and how the binary is entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    /// load address, also the entry point
+    pub address: usize,
+    /// `None` enters at the EL the loader runs at
+    pub el: Option<ExceptionLevel>,
+}
+
+impl Target {
+    /// parse `address[,el1|el2]`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (address, el) = match s.split_once(',') {
+            Some((address, el)) => (
+                address,
+                Some(match el.trim() {
+                    "el1" => ExceptionLevel::El1,
+                    "el2" => ExceptionLevel::El2,
+                    _ => return None,
+                }),
+            ),
+            None => (s, None),
+        };
+        let address = crate::str_to_usize(address.trim())?;
+        (address % PAGE_SIZE == 0).then_some(Self { address, el })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.address)?;
+        match self.el {
+            Some(el) => write!(f, ",el{}", el.number()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainErr {
+    ReadFailed,
+    /// the configured range is not free memory
+    RegionNotFree,
+    /// the file is a PE/COFF application which needs UEFI boot services
+    EfiApplication,
+    /// EL2 was requested but the loader runs at EL1
+    UnsupportedEl,
+    /// the file failed the signature check
+    Rejected,
+}
+
+/// a binary loaded at its target address
+#[derive(Debug, Clone, Copy)]
+pub struct Chained {
+    pub target: Target,
+    pub size: usize,
+    /// SHA-256 of the file
+    pub digest: [u8; DIGEST_SIZE],
+}
+
+/// Copy `file` to `target.address`.
+/// `accept` sees the file contents once they are read and rejects the binary by returning false.
+pub fn load(
+    file: &FileHandle,
+    target: Target,
+    accept: &dyn Fn(&[u8]) -> bool,
+) -> Result<Chained, ChainErr> {
+    if target.el == Some(ExceptionLevel::El2) && cpu::get_current_el() != 2 {
+        return Err(ChainErr::UnsupportedEl);
+    }
+    let header = file
+        .read_struct::<[u8; PayloadKind::HEADER_SIZE]>(0)
+        .map_err(|_| ChainErr::ReadFailed)?;
+    // a Linux Image with the EFI stub is a PE image too, but it does not need the firmware
+    if header.starts_with(&PE_MAGIC)
+        && PayloadKind::detect(&header) != Some(PayloadKind::LinuxImage)
+    {
+        return Err(ChainErr::EfiApplication);
+    }
+
+    let size = file.size().map_err(|_| ChainErr::ReadFailed)? as usize;
+    allocator::allocate_at(target.address, size.next_multiple_of(PAGE_SIZE))
+        .map_err(|_| ChainErr::RegionNotFree)?;
+    let data = unsafe { &mut *slice_from_raw_parts_mut(target.address as *mut u8, size) };
+    file.read_exact_at(0, data)
+        .map_err(|_| ChainErr::ReadFailed)?;
+    if !accept(data) {
+        return Err(ChainErr::Rejected);
+    }
+    Ok(Chained {
+        target,
+        size,
+        digest: sha256::digest(data),
+    })
+}
+
+/// Enter `chained` with `dtb` in x0. Secondary CPUs must be off and devices quiesced.
+pub fn jump(chained: &Chained, dtb: usize, dtb_size: usize) -> ! {
+    let entry = chained.target.address;
+    // the next stage starts with the MMU and caches off, so write back everything it reads
+    cache::clean_dcache_range(entry as *const u8, chained.size);
+    cache::sync_icache(entry as *const u8, chained.size);
+    cache::clean_dcache_range(dtb as *const u8, dtb_size);
+
+    let current = cpu::get_current_el();
+    match chained.target.el.map_or(current, ExceptionLevel::number) {
+        1 if current == 2 => unsafe { enter_el1_from_el2(entry, dtb) },
+        2 => unsafe {
+            asm!(
+                "msr daifset, #0xf",
+                "mrs x9, SCTLR_EL2",
+                "bic x9, x9, #(1 << 0)",  // M = 0 (MMU off)
+                "bic x9, x9, #(1 << 2)",  // C = 0 (D-cache disable)
+                "bic x9, x9, #(1 << 12)", // I = 0 (I-cache disable)
+                "msr SCTLR_EL2, x9",
+                "tlbi alle2",
+                "dsb sy",
+                "isb",
+                "mov x1, xzr",
+                "mov x2, xzr",
+                "mov x3, xzr",
+                "br x4",
+                in("x0") dtb,
+                in("x4") entry,
+                options(noreturn)
+            )
+        },
+        _ => unsafe {
+            asm!(
+                "msr daifset, #0xf",
+                "mrs x9, SCTLR_EL1",
+                "bic x9, x9, #(1 << 0)",  // M = 0 (MMU off)
+                "bic x9, x9, #(1 << 2)",  // C = 0 (D-cache disable)
+                "bic x9, x9, #(1 << 12)", // I = 0 (I-cache disable)
+                "msr SCTLR_EL1, x9",
+                "tlbi vmalle1",
+                "dsb sy",
+                "isb",
+                "mov x1, xzr",
+                "mov x2, xzr",
+                "mov x3, xzr",
+                "br x4",
+                in("x0") dtb,
+                in("x4") entry,
+                options(noreturn)
+            )
+        },
+    }
+}
+
+// drop to EL1h with a reset-like EL1 state and no EL2 traps
+unsafe fn enter_el1_from_el2(entry: usize, dtb: usize) -> ! {
+    const HCR_EL2_RW: u64 = 1 << 31;
+    // EL1 physical counter and timer access
+    const CNTHCTL_EL2_EL1PCTEN: u64 = 1 << 0;
+    const CNTHCTL_EL2_EL1PCEN: u64 = 1 << 1;
+    // RES1 bits, MMU and caches off, little-endian
+    const SCTLR_EL1_MMU_OFF: u64 = 0x30D0_0800;
+    // EL1h with D, A, I and F masked
+    const SPSR_EL1H_MASKED: u64 = 0x3C5;
+    unsafe {
+        asm!(
+            "msr daifset, #0xf",
+            "msr HCR_EL2, {hcr}",
+            "msr CNTHCTL_EL2, {cnthctl}",
+            "msr CNTVOFF_EL2, xzr",
+            "msr SCTLR_EL1, {sctlr}",
+            "msr SPSR_EL2, {spsr}",
+            "msr ELR_EL2, x4",
+            "tlbi alle1",
+            "dsb sy",
+            "isb",
+            "mov x1, xzr",
+            "mov x2, xzr",
+            "mov x3, xzr",
+            "eret",
+            hcr = in(reg) HCR_EL2_RW,
+            cnthctl = in(reg) CNTHCTL_EL2_EL1PCTEN | CNTHCTL_EL2_EL1PCEN,
+            sctlr = in(reg) SCTLR_EL1_MMU_OFF,
+            spsr = in(reg) SPSR_EL1H_MASKED,
+            in("x0") dtb,
+            in("x4") entry,
+            options(noreturn)
+        )
+    }
+}
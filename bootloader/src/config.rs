@@ -0,0 +1,293 @@
+// boot configuration file
+//
+// `/boot.cfg` is a list of `key=value` lines. Blank lines and lines starting
+// with `#` are ignored, and a value may be wrapped in double quotes.
+//
+//     # kernel command line
+//     bootargs="console=ttyAMA0 root=/dev/vda"
+//     # boot menu: seconds before the first entry boots, then `label:path` entries
+//     timeout=3
+//     entry=Linux:/image
+//     # guest dtb, falls back to /qemu.dtb and then the firmware dtb
+//     dtb=/board.dtb
+//     # overlay applied to the guest dtb before it is handed to the kernel
+//     overlay=/board-overlay.dtbo
+//     # another loader copied to an address and entered at EL1 or EL2 (default: current EL)
+//     chain=U-Boot:/u-boot.bin@0x60000000,el2
+//     # guest with its own dtb, memory size and vCPU count, see `guest`
+//     guest=linux:/image,dtb=/linux.dtb,memory=512M,vcpus=2
+//     # kernel signature check: off, warn or enforce
+//     verify=enforce
+//     # fetch the kernel (and a dtb and initrd) over DHCP and TFTP, see `netboot`
+//     boot=net,kernel=Image,initrd=rootfs.cpio
+//     # test the free memory before loading the kernel: fast or full, see `memtest`
+//     memtest=fast
+//     # memory kept for a crash capture kernel: size[@offset], see `crashkernel`
+//     crashkernel=256M
+//     # console messages shown: error, warn, info (default), debug or trace
+//     loglevel=debug
+//     # disk errors: retries of a request[,device re-inits in total] (default 1,4), or off
+//     io_retry=2,8
+//     # watchdog once the kernel is entered: a timeout in seconds, or off to stop it
+//     kernel_watchdog=120
+//     # size /boot.log is rotated at (default 64K), or off, see `journal`
+//     boot_log=16K
+
+use crate::chainload::Target;
+use crate::crashkernel::CrashKernel;
+use crate::guest::GuestConfig;
+use crate::memtest;
+use crate::netboot::NetBootConfig;
+use crate::parse_size;
+use crate::verify::Policy;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use arch_hal::logging::Level;
+use arch_hal::println;
+use core::time::Duration;
+use crypto::sha256;
+use crypto::sha256::DIGEST_SIZE;
+use dtb::DtbParser;
+use file::OpenOptions;
+use file::RetryPolicy;
+use file::StorageDevice;
+
+pub const CONFIG_PATH: &str = "/boot.cfg";
+
+#[derive(Debug, Default)]
+pub struct BootConfig {
+    /// kernel command line, emitted as `/chosen/bootargs` of the guest dtb
+    pub bootargs: Option<String>,
+    /// seconds the boot menu waits for a key
+    pub timeout: Option<u64>,
+    /// boot menu entries as (label, path, chainload target), the target is `None` for kernels
+    pub entries: Vec<(String, String, Option<Target>)>,
+    /// guests, listed in the boot menu before `entries`
+    pub guests: Vec<GuestConfig>,
+    /// kernel signature policy, see `verify`
+    pub verify: Option<Policy>,
+    /// boot from the network instead of the disk
+    pub net: Option<NetBootConfig>,
+    /// guest dtb for entries which do not name their own
+    pub dtb: Option<String>,
+    /// dtb overlay applied to the guest dtb of disk boots
+    pub overlay: Option<String>,
+    /// memory test run before the kernel is loaded
+    pub memtest: Option<memtest::Mode>,
+    /// region reserved for a crash capture kernel
+    pub crashkernel: Option<CrashKernel>,
+    /// most verbose console messages shown
+    pub log_level: Option<Level>,
+    /// recovery from disk errors
+    pub io_retry: Option<RetryPolicy>,
+    /// watchdog timeout once the kernel is entered, `Some(None)` stops the watchdog
+    pub kernel_watchdog: Option<Option<Duration>>,
+    /// size the boot log is rotated at, `Some(None)` turns it off
+    pub boot_log: Option<Option<usize>>,
+    /// SHA-256 of the file, `None` if it was not read
+    pub digest: Option<[u8; DIGEST_SIZE]>,
+}
+
+impl BootConfig {
+    /// Read `CONFIG_PATH`. A missing or unreadable file gives the default configuration.
+    pub fn load(storage: &StorageDevice) -> Self {
+        let Ok(file) = storage.open(0, CONFIG_PATH, &OpenOptions::Read) else {
+            return Self::default();
+        };
+        let Ok(data) = file.read(1) else {
+            println!("{}: failed to read, ignored", CONFIG_PATH);
+            return Self::default();
+        };
+        let mut config = match core::str::from_utf8(&data) {
+            Ok(text) => Self::parse(text),
+            Err(_) => {
+                println!("{}: not UTF-8, ignored", CONFIG_PATH);
+                Self::default()
+            }
+        };
+        config.digest = Some(sha256::digest(&data));
+        config
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut config = Self::default();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                println!("{}:{}: expected key=value", CONFIG_PATH, line_number + 1);
+                continue;
+            };
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            match key.trim() {
+                "bootargs" => config.bootargs = Some(value.to_string()),
+                "timeout" => match value.parse() {
+                    Ok(timeout) => config.timeout = Some(timeout),
+                    Err(_) => println!("{}:{}: invalid timeout", CONFIG_PATH, line_number + 1),
+                },
+                "verify" => match Policy::parse(value) {
+                    Some(policy) => config.verify = Some(policy),
+                    None => println!(
+                        "{}:{}: expected verify=off|warn|enforce",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "boot" => match NetBootConfig::parse(value) {
+                    Some(net) => config.net = Some(net),
+                    None => println!(
+                        "{}:{}: expected boot=net[,kernel=name][,dtb=name][,initrd=name]",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "dtb" if value.starts_with('/') => config.dtb = Some(value.to_string()),
+                "dtb" => println!("{}:{}: expected dtb=/path", CONFIG_PATH, line_number + 1),
+                "overlay" if value.starts_with('/') => config.overlay = Some(value.to_string()),
+                "overlay" => {
+                    println!(
+                        "{}:{}: expected overlay=/path",
+                        CONFIG_PATH,
+                        line_number + 1
+                    )
+                }
+                "memtest" => match memtest::Mode::parse(value) {
+                    Some(mode) => config.memtest = Some(mode),
+                    None => println!(
+                        "{}:{}: expected memtest=fast|full",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "crashkernel" => match CrashKernel::parse(value) {
+                    Some(crash) => config.crashkernel = Some(crash),
+                    None => println!(
+                        "{}:{}: expected crashkernel=size[@offset], offset 2 MiB aligned",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "loglevel" => match Level::parse(value) {
+                    Some(level) => config.log_level = Some(level),
+                    None => println!(
+                        "{}:{}: expected loglevel=error|warn|info|debug|trace",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "io_retry" => match parse_io_retry(value) {
+                    Some(policy) => config.io_retry = Some(policy),
+                    None => println!(
+                        "{}:{}: expected io_retry=retries[,reinits] or io_retry=off",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "kernel_watchdog" => match parse_watchdog(value) {
+                    Some(timeout) => config.kernel_watchdog = Some(timeout),
+                    None => println!(
+                        "{}:{}: expected kernel_watchdog=seconds or kernel_watchdog=off",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "boot_log" => match parse_boot_log(value) {
+                    Some(max_size) => config.boot_log = Some(max_size),
+                    None => println!(
+                        "{}:{}: expected boot_log=size or boot_log=off",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                "entry" => {
+                    match value.split_once(':') {
+                        Some((label, path)) if path.trim().starts_with('/') => config
+                            .entries
+                            .push((label.trim().to_string(), path.trim().to_string(), None)),
+                        _ => println!(
+                            "{}:{}: expected entry=label:/path",
+                            CONFIG_PATH,
+                            line_number + 1
+                        ),
+                    }
+                }
+                "chain" => {
+                    match value.split_once(':').and_then(|(label, rest)| {
+                        let (path, target) = rest.rsplit_once('@')?;
+                        Some((label, path.trim(), Target::parse(target)?))
+                    }) {
+                        Some((label, path, target)) if path.starts_with('/') => config
+                            .entries
+                            .push((label.trim().to_string(), path.to_string(), Some(target))),
+                        _ => println!(
+                            "{}:{}: expected chain=label:/path@address[,el1|el2]",
+                            CONFIG_PATH,
+                            line_number + 1
+                        ),
+                    }
+                }
+                "guest" => match GuestConfig::parse(value) {
+                    Some(guest) => config.guests.push(guest),
+                    None => println!(
+                        "{}:{}: expected guest=label:/kernel[,dtb=/path][,memory=size][,vcpus=count]",
+                        CONFIG_PATH,
+                        line_number + 1
+                    ),
+                },
+                key => println!("{}:{}: unknown key '{}'", CONFIG_PATH, line_number + 1, key),
+            }
+        }
+        config
+    }
+
+    /// Take `bootargs` from the `/chosen` node of the firmware dtb unless the file set it
+    pub fn fill_from_firmware(&mut self, dtb: &DtbParser) {
+        if self.bootargs.is_some() {
+            return;
+        }
+        let _ = dtb.find_chosen_property("bootargs", &mut |value| {
+            let value = value.split(|b| *b == 0).next().unwrap_or(value);
+            if let Ok(value) = core::str::from_utf8(value) {
+                self.bootargs = Some(value.to_string());
+            }
+        });
+    }
+}
+
+// `retries[,reinits]` or `off`
+// `off`, or a timeout of at least a second
+fn parse_watchdog(value: &str) -> Option<Option<Duration>> {
+    if value == "off" {
+        return Some(None);
+    }
+    let seconds = value.parse().ok().filter(|&seconds| seconds != 0)?;
+    Some(Some(Duration::from_secs(seconds)))
+}
+
+fn parse_boot_log(value: &str) -> Option<Option<usize>> {
+    if value == "off" {
+        return Some(None);
+    }
+    Some(Some(parse_size(value)?))
+}
+
+fn parse_io_retry(value: &str) -> Option<RetryPolicy> {
+    if value == "off" {
+        return Some(RetryPolicy::NONE);
+    }
+    let (retries, max_reinits) = match value.split_once(',') {
+        Some((retries, reinits)) => (retries, reinits.trim().parse().ok()?),
+        None => (value, RetryPolicy::DEFAULT.max_reinits),
+    };
+    Some(RetryPolicy {
+        retries: retries.trim().parse().ok()?,
+        max_reinits,
+    })
+}
@@ -0,0 +1,55 @@
+// crash kernel region: `crashkernel=size[@offset]` in the config file
+//
+// The region is allocated once the kernel is placed, so a fixed offset must not overlap it,
+// and described to the guest as `/reserved-memory/crashkernel@<address>`. Its range is also
+// emitted as `/chosen/elf-hypervisor,crashkernel`, encoded like `linux,usable-memory-range`,
+// for the capture kernel loaded into it later.
+
+use crate::parse_size;
+use crate::str_to_usize;
+
+// arm64 kernels are placed on 2 MiB boundaries
+const ALIGN: usize = 0x20_0000;
+pub const NODE_NAME: &str = "crashkernel";
+pub const PROPERTY_NAME: &str = "elf-hypervisor,crashkernel";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrashKernel {
+    pub size: usize,
+    /// physical address, the lowest free one when `None`
+    pub offset: Option<usize>,
+}
+
+impl CrashKernel {
+    /// parse `size[@offset]`, the size with an optional K, M or G suffix
+    pub fn parse(value: &str) -> Option<Self> {
+        let (size, offset) = match value.split_once('@') {
+            Some((size, offset)) => (size, Some(str_to_usize(offset.trim())?)),
+            None => (value, None),
+        };
+        let size = parse_size(size.trim())?;
+        if offset.is_some_and(|offset| offset % ALIGN != 0) {
+            return None;
+        }
+        Some(Self { size, offset })
+    }
+
+    /// Allocate the region, away from the `avoid` ranges without an offset.
+    /// Returns (address, size).
+    pub fn reserve(&self, avoid: &[(usize, usize)]) -> Result<(usize, usize), &'static str> {
+        let size = self.size.next_multiple_of(ALIGN);
+        let address = match self.offset {
+            Some(offset) => allocator::allocate_at(offset, size).map(|_| offset)?,
+            None => allocator::allocate_placed(size, ALIGN, 0, avoid)?,
+        };
+        Ok((address, size))
+    }
+}
+
+/// `(address, size)` as the two 64-bit cells of `linux,usable-memory-range`
+pub fn range_cells((address, size): (usize, usize)) -> [u8; 16] {
+    let mut cells = [0; 16];
+    cells[..8].copy_from_slice(&(address as u64).to_be_bytes());
+    cells[8..].copy_from_slice(&(size as u64).to_be_bytes());
+    cells
+}
@@ -0,0 +1,147 @@
+// console selection from the firmware dtb
+//
+// The UART named by `/chosen/stdout-path` is the console, at the baud rate of its options
+// (`serial0:115200n8`), PL011 and NS16550 compatibles are supported. Only a dtb without
+// `/chosen` falls back to the first serial node of a supported kind: when `/chosen` names
+// no usable console the output stays in the log buffer and the console sinks.
+
+use arch_hal::ns16550::Ns16550Uart;
+use arch_hal::pl011::Pl011Uart;
+use arch_hal::println;
+use arch_hal::uart::UartConfig;
+use arch_hal::uart::UartKind;
+use core::ops::ControlFlow;
+use dtb::DtbParser;
+use dtb::Property;
+
+// properties of a UART node read by `node_config`, in this order
+const NODE_PROPERTIES: [&str; 5] = [
+    "compatible",
+    "clock-frequency",
+    "clocks",
+    "reg-shift",
+    "reg-io-width",
+];
+
+/// The console described by `dtb`, `None` if it names none this loader can drive
+pub fn find(dtb: &DtbParser) -> Option<UartConfig> {
+    match dtb.stdout_path() {
+        Ok(Some((path, options))) => {
+            let config = from_path(dtb, path, options);
+            if config.is_none() {
+                println!("earlycon: {}: not a supported UART", path);
+            }
+            config
+        }
+        Ok(None) if has_chosen(dtb) => {
+            println!("earlycon: /chosen names no console");
+            None
+        }
+        Ok(None) => first_serial(dtb),
+        Err(e) => {
+            println!("earlycon: {}", e);
+            None
+        }
+    }
+}
+
+fn from_path(dtb: &DtbParser, path: &str, options: Option<&str>) -> Option<UartConfig> {
+    let mut config = None;
+    dtb.find_node_by_path(path, &NODE_PROPERTIES, &mut |reg, values| {
+        config = reg.and_then(|(base, _size)| node_config(dtb, base, values));
+    })
+    .ok()?;
+    let mut config = config?;
+    config.baudrate = options.and_then(parse_baudrate);
+    Some(config)
+}
+
+fn has_chosen(dtb: &DtbParser) -> bool {
+    let mut found = false;
+    let _ = dtb.for_each_node(&mut |depth, name| {
+        found = depth == 1 && name == "chosen";
+        if found {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    found
+}
+
+// the first PL011, else the first NS16550, at the baud rate the firmware left
+fn first_serial(dtb: &DtbParser) -> Option<UartConfig> {
+    for compatible in Pl011Uart::COMPATIBLES
+        .iter()
+        .chain(Ns16550Uart::COMPATIBLES)
+    {
+        let mut base = None;
+        let _ = dtb.find_node(None, Some(compatible), &mut |address, _size| {
+            base = Some(address);
+            ControlFlow::Break(())
+        });
+        let Some(base) = base else {
+            continue;
+        };
+        let mut config = None;
+        let _ = dtb.find_node_properties(None, Some(compatible), &NODE_PROPERTIES, &mut |values| {
+            config = node_config(dtb, base, values);
+            ControlFlow::Break(())
+        });
+        if config.is_some() {
+            return config;
+        }
+    }
+    None
+}
+
+// the UART at `base` whose `NODE_PROPERTIES` are `values`
+fn node_config(
+    dtb: &DtbParser,
+    base: usize,
+    values: &[Option<&'static [u8]>],
+) -> Option<UartConfig> {
+    let &[compatible, clock_frequency, clocks, reg_shift, reg_io_width] = values else {
+        return None;
+    };
+    let kind = compatible?
+        .split(|b| *b == 0)
+        .filter_map(|name| core::str::from_utf8(name).ok())
+        .find_map(UartKind::from_compatible)?;
+    let kind = match kind {
+        UartKind::Ns16550 { .. } => UartKind::Ns16550 {
+            reg_shift: reg_shift.and_then(cell).unwrap_or(0) as u8,
+            reg_io_width: reg_io_width.and_then(cell).unwrap_or(1) as u8,
+        },
+        kind => kind,
+    };
+    // the clock of the node, else the fixed clock its `clocks` points at
+    let clock = clock_frequency.and_then(cell).or_else(|| {
+        dtb.clock_frequency(Property::new(clocks?))
+            .unwrap_or_else(|e| {
+                println!("earlycon: {}", e);
+                None
+            })
+    });
+    Some(UartConfig {
+        kind,
+        base,
+        clock,
+        baudrate: None,
+    })
+}
+
+fn cell(value: &[u8]) -> Option<u32> {
+    value.try_into().ok().map(u32::from_be_bytes)
+}
+
+// the leading baud rate of `115200n8`
+fn parse_baudrate(options: &str) -> Option<u32> {
+    let end = options
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(options.len());
+    options[..end]
+        .parse()
+        .ok()
+        .filter(|&baudrate| baudrate != 0)
+}
@@ -0,0 +1,102 @@
+// machine-readable boot events
+//
+// With the `structured-output` feature each boot event is also printed as one line of
+// `key=value` fields after a fixed tag, so the xtask integration tests can assert on them
+// instead of the free-form text:
+//
+//   @@event phase name="dtb parse" us=1532
+//   @@event digest component=kernel sha256=9f86d0... path=/Image
+//   @@event handoff kind=Linux entry=0x40280000
+//   @@event error message="dtb: bad magic" location=bootloader/src/main.rs:153
+//
+// A value with a space, a quote or a backslash is quoted with `"`, escaping `"`, `\` and
+// newlines with a backslash.
+
+use arch_hal::print;
+use core::fmt;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+const TAG: &str = "@@event";
+
+pub const ENABLED: bool = cfg!(feature = "structured-output");
+
+pub type Field<'a> = (&'a str, &'a dyn fmt::Display);
+
+/// print the event `kind` with `fields`, nothing without `structured-output`
+pub fn emit(kind: &str, fields: &[Field]) {
+    if ENABLED {
+        let _ = write(&mut Console, kind, fields);
+    }
+}
+
+/// write the event `kind` with `fields` to `w`, for the panic handler which cannot print
+pub fn write(w: &mut dyn Write, kind: &str, fields: &[Field]) -> fmt::Result {
+    if !ENABLED {
+        return Ok(());
+    }
+    write!(w, "{} ", TAG)?;
+    write_line(w, kind, fields)
+}
+
+/// write the event `kind` with `fields` as one line without the tag, also without
+/// `structured-output`, for the boot log
+pub fn write_line(w: &mut dyn Write, kind: &str, fields: &[Field]) -> fmt::Result {
+    w.write_str(kind)?;
+    for (key, value) in fields {
+        write!(w, " {}=", key)?;
+        let mut check = NeedsQuotes(false);
+        let _ = write!(check, "{}", value);
+        if check.0 {
+            w.write_char('"')?;
+            write!(Escaped(w), "{}", value)?;
+            w.write_char('"')?;
+        } else {
+            write!(w, "{}", value)?;
+        }
+    }
+    w.write_char('\n')
+}
+
+/// write the `error` event of a panic to `w`
+pub fn write_panic(w: &mut dyn Write, info: &PanicInfo) -> fmt::Result {
+    let message = info.message();
+    match info.location() {
+        Some(location) => write(w, "error", &[("message", &message), ("location", location)]),
+        None => write(w, "error", &[("message", &message)]),
+    }
+}
+
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        print!("{}", s);
+        Ok(())
+    }
+}
+
+// whether a value has a character which must be quoted
+struct NeedsQuotes(bool);
+
+impl Write for NeedsQuotes {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 |= s.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\');
+        Ok(())
+    }
+}
+
+struct Escaped<'a>(&'a mut dyn Write);
+
+impl Write for Escaped<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' | '\\' => write!(self.0, "\\{}", c)?,
+                '\n' => self.0.write_str("\\n")?,
+                c => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+// guests described by `guest=` lines of the boot configuration
+//
+//     guest=linux:/image,dtb=/linux.dtb,memory=512M,vcpus=2
+//
+// `dtb`, `memory` and `vcpus` are optional. Isolating guests from each other needs
+// stage-2 translation, which the hypervisor does not implement yet (`Stage2Paging` is a
+// stub), so a single guest runs at a time: the one picked in the boot menu. Its vCPU count
+// bounds the CPUs it can start through PSCI, and the others are removed from its dtb.
+// Without stage-2 nothing enforces its memory size, which is passed to the kernel as `mem=`.
+
+use crate::parse_size;
+use alloc::format;
+use alloc::string::String;
+use alloc::string::ToString;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestConfig {
+    pub label: String,
+    pub kernel: String,
+    /// dtb the guest dtb is generated from, `None` for the default
+    pub dtb: Option<String>,
+    /// RAM size in bytes
+    pub memory: Option<usize>,
+    pub vcpus: Option<usize>,
+}
+
+impl GuestConfig {
+    /// parse `label:/kernel[,dtb=/path][,memory=size][,vcpus=count]`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (label, rest) = value.split_once(':')?;
+        let mut options = rest.split(',');
+        let kernel = options.next()?.trim();
+        if !kernel.starts_with('/') {
+            return None;
+        }
+        let mut guest = Self {
+            label: label.trim().to_string(),
+            kernel: kernel.to_string(),
+            dtb: None,
+            memory: None,
+            vcpus: None,
+        };
+        for option in options {
+            let (key, value) = option.split_once('=')?;
+            let value = value.trim();
+            match key.trim() {
+                "dtb" if value.starts_with('/') => guest.dtb = Some(value.to_string()),
+                "memory" => guest.memory = Some(parse_size(value)?),
+                "vcpus" => guest.vcpus = Some(value.parse().ok().filter(|&n| n > 0)?),
+                _ => return None,
+            }
+        }
+        Some(guest)
+    }
+
+    /// kernel command line option limiting the guest to its memory size
+    pub fn memory_option(&self) -> Option<String> {
+        self.memory.map(|size| format!("mem={}", size))
+    }
+}
@@ -0,0 +1,119 @@
+// persistent boot log
+//
+// Each boot appends its events to `/boot.log` on the first partition of the boot disk, so
+// the boots of a headless device can be diagnosed afterwards. A line is an `event` line
+// without the tag, behind the uptime in seconds:
+//
+//   0.052113 start version=0.1.0
+//   0.843077 phase name="disk probe" us=612004
+//   0.843102 boot entry=Linux path=/Image
+//   1.532012 phase name="kernel read" us=688850
+//   1.532090 digest component=kernel sha256=9f86d0... path=/Image
+//   1.532154 loaded kind=Linux entry=0x40280000
+//
+// The lines are written while the disk is open: before the kernel is read, when reading it
+// fails and before the file system is closed. A boot which stopped in between ends with the
+// lines of the last write, and with an `error` line when the loader caught the failure.
+// A write which would grow the log past its size first renames it to `/boot.log.1`,
+// replacing the older log. A network boot without a disk keeps no log.
+
+use crate::event;
+use crate::event::Field;
+use crate::systimer;
+use alloc::string::String;
+use arch_hal::println;
+use core::fmt;
+use core::fmt::Write;
+use file::FileSystemErr;
+use file::OpenOptions;
+use file::StorageDevice;
+use file::StorageDeviceErr;
+
+pub const LOG_PATH: &str = "/boot.log";
+pub const OLD_LOG_PATH: &str = "/boot.log.1";
+/// size the log is rotated at without `boot_log` in the configuration
+pub const DEFAULT_MAX_SIZE: usize = 64 * 1024;
+
+const NOT_FOUND: StorageDeviceErr = StorageDeviceErr::FileSystemErr(FileSystemErr::NotFound);
+
+pub struct Journal {
+    // lines not written yet
+    pending: String,
+    // size the log is rotated at, `None` once it is off or could not be written
+    max_size: Option<usize>,
+}
+
+impl Journal {
+    /// start the lines of this boot with a `start` event
+    pub fn new() -> Self {
+        let mut journal = Self {
+            pending: String::new(),
+            max_size: Some(DEFAULT_MAX_SIZE),
+        };
+        journal.record("start", &[("version", &env!("CARGO_PKG_VERSION"))]);
+        journal
+    }
+
+    /// set the size the log is rotated at, `None` drops the lines instead
+    pub fn set_max_size(&mut self, max_size: Option<usize>) {
+        self.max_size = max_size;
+    }
+
+    /// keep the event `kind` with `fields` for the next `flush`, stamped with the uptime
+    pub fn record(&mut self, kind: &str, fields: &[Field]) {
+        let uptime = systimer::uptime();
+        let _ = write!(
+            self.pending,
+            "{}.{:06} ",
+            uptime.as_secs(),
+            uptime.subsec_micros()
+        );
+        let _ = event::write_line(&mut self.pending, kind, fields);
+    }
+
+    /// append the kept lines to `LOG_PATH`. A failure is printed and turns the log off
+    pub fn flush(&mut self, storage: &StorageDevice) {
+        let Some(max_size) = self.max_size.filter(|_| !self.pending.is_empty()) else {
+            self.pending.clear();
+            return;
+        };
+        if let Err(e) = append(storage, self.pending.as_bytes(), max_size) {
+            println!("{}: {:?}, the boot log is not written", LOG_PATH, e);
+            self.max_size = None;
+        }
+        self.pending.clear();
+    }
+
+    /// record `message` as an `error`, write the log and panic with it
+    pub fn fail(&mut self, storage: &StorageDevice, message: fmt::Arguments) -> ! {
+        self.record("error", &[("message", &message)]);
+        self.flush(storage);
+        panic!("{}", message);
+    }
+}
+
+// append `data` to `LOG_PATH`, rotating it first when it would grow past `max_size`
+fn append(storage: &StorageDevice, data: &[u8], max_size: usize) -> Result<(), StorageDeviceErr> {
+    let mut size = match storage.open(0, LOG_PATH, &OpenOptions::Read) {
+        Ok(file) => Some(file.size().map_err(StorageDeviceErr::FileSystemErr)?),
+        Err(NOT_FOUND) => None,
+        Err(e) => return Err(e),
+    };
+    if size.is_some_and(|size| size != 0 && size as usize + data.len() > max_size) {
+        match storage.remove_file(0, OLD_LOG_PATH) {
+            Ok(()) | Err(NOT_FOUND) => {}
+            Err(e) => return Err(e),
+        }
+        storage.rename(0, LOG_PATH, OLD_LOG_PATH)?;
+        size = None;
+    }
+    let offset = match size {
+        Some(size) => size,
+        None => {
+            storage.create(0, LOG_PATH)?;
+            0
+        }
+    };
+    storage.write(0, LOG_PATH, offset, data)?;
+    Ok(())
+}
@@ -4,31 +4,75 @@
 #![recursion_limit = "256"]
 
 extern crate alloc;
+mod boot_menu;
+mod chainload;
+mod config;
+mod crashkernel;
+mod earlycon;
+mod event;
+mod guest;
+mod journal;
+mod measure;
+mod memtest;
+mod netboot;
+mod payload;
+mod profile;
+mod progress;
+mod sanitize;
+mod shell;
+mod smp;
 mod systimer;
+#[cfg(target_os = "uefi")]
+mod uefi;
+mod verify;
+use crate::profile::Phase;
 use crate::systimer::SystemTimer;
-use alloc::alloc::alloc;
+use arch_hal::console;
 use arch_hal::cpu;
+use arch_hal::cpu::cache;
+use arch_hal::cpu::psci;
+use arch_hal::cpu::psci::PsciConduit;
+use arch_hal::cpu::registers::HCR_EL2;
+use arch_hal::cpu::registers::SCTLR_EL2;
+use arch_hal::debug;
 use arch_hal::debug_uart;
-use arch_hal::pl011::Pl011Uart;
+use arch_hal::exception;
+use arch_hal::fpsimd;
+use arch_hal::gic;
+use arch_hal::gic::Gicv3;
+use arch_hal::hypercall;
+use arch_hal::idle;
+use arch_hal::interrupt;
+use arch_hal::logging;
+use arch_hal::panic_report;
 use arch_hal::println;
+use arch_hal::sbsa_gwdt::SbsaGwdt;
+use arch_hal::semihosting;
+use arch_hal::serror;
+use arch_hal::timer;
+use arch_hal::uart::Uart;
+use arch_hal::uart::UartConfig;
 use core::alloc::Layout;
+#[cfg(not(target_os = "uefi"))]
 use core::arch::naked_asm;
 use core::ffi::CStr;
+#[cfg(all(not(target_os = "uefi"), feature = "argv-boot"))]
 use core::ffi::c_char;
 use core::fmt::Write;
-use core::mem::MaybeUninit;
 use core::ops::ControlFlow;
 use core::panic::PanicInfo;
 use core::ptr;
+use core::ptr::slice_from_raw_parts;
 use core::ptr::slice_from_raw_parts_mut;
+#[cfg(all(not(target_os = "uefi"), feature = "argv-boot"))]
 use core::slice;
 use core::time::Duration;
 use dtb::DtbGenerator;
 use dtb::DtbParser;
 use file::OpenOptions;
 use file::StorageDevice;
-use typestate::Le;
 
+#[cfg(not(target_os = "uefi"))]
 unsafe extern "C" {
     static mut _BSS_START: usize;
     static mut _BSS_END: usize;
@@ -37,66 +81,178 @@ unsafe extern "C" {
     static mut _STACK_TOP: usize;
 }
 
-static PL011_UART_ADDR: usize = 0x900_0000;
-
-#[repr(C)]
-struct LinuxHeader {
-    code0: u32,
-    code1: u32,
-    text_offset: Le<u64>,
-    image_size: Le<u64>,
-    flags: Le<u64>,
-    res2: u64,
-    res3: u64,
-    res4: u64,
-    magic: [u8; 4],
-    res5: u32,
-}
-
-impl LinuxHeader {
-    const MAGIC: [u8; 4] = [b'A', b'R', b'M', 0x64];
-}
+// console of the panic handler when none was found in the dtb
+const FALLBACK_UART_ADDR: usize = 0x900_0000;
+const FALLBACK_UART_CLOCK: u32 = 4400_0000;
+const PANIC_UART_BAUDRATE: u32 = 115200;
+// the guest uses the virtual timer, the EL1 physical timer is not used by the hypervisor
+const GUEST_PHYSICAL_TIMER_ACCESS: timer::PhysicalTimerAccess = timer::PhysicalTimerAccess::Allow;
+// let the guest run perf and kgdb on the real hardware
+const GUEST_DEBUG_POLICY: debug::DebugPolicy = debug::DebugPolicy::Passthrough;
+const GUEST_PMU_POLICY: debug::DebugPolicy = debug::DebugPolicy::Passthrough;
+// reset the board when loading hangs
+const BOOT_WATCHDOG_TIMEOUT: Duration = Duration::from_secs(60);
+// None: stop the watchdog before jumping to the kernel, Some: re-arm it for the kernel.
+// The default of `kernel_watchdog=` in the config file
+const KERNEL_WATCHDOG_TIMEOUT: Option<Duration> = None;
+// counter frequency in Hz when CNTFRQ_EL0 and the dtb timer node are both wrong,
+// see `systimer::calibrate`. Only the loader and the hypervisor use it, the kernel takes
+// the `clock-frequency` of its dtb timer node or CNTFRQ_EL0
+const COUNTER_FREQUENCY: Option<u64> = None;
+// mirror the console to semihosting, only for QEMU run with `-semihosting`:
+// the semihosting call traps without a host
+const SEMIHOSTING_CONSOLE: bool = false;
+// GPIO LED showing the boot phases, see `progress`
+const STATUS_LED: Option<progress::StatusLed> = None;
+// the guest dtb is generated from this file unless the config names one,
+// and from the firmware dtb when neither can be used
+const GUEST_DTB_PATH: &str = "/qemu.dtb";
 
+// arm64 boot protocol: x0 holds the dtb address and is passed to `main` untouched
+#[cfg(not(target_os = "uefi"))]
 #[unsafe(naked)]
 #[unsafe(no_mangle)]
 extern "C" fn _start() {
     naked_asm!("ldr x9, =_STACK_TOP\n", "mov sp, x9\n", "b main\n",)
 }
 
+#[cfg(all(not(target_os = "uefi"), not(feature = "argv-boot")))]
 #[unsafe(no_mangle)]
-extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
-    let program_start = unsafe { &raw mut _PROGRAM_START } as *const _ as usize;
-    let stack_start = unsafe { &raw mut _STACK_TOP } as *const _ as usize;
+extern "C" fn main(dtb_ptr: usize) -> ! {
+    boot(dtb_ptr, None, Some(program_range()))
+}
 
+// U-Boot `bootelf` calls the entry with argc/argv, the dtb address is the first argument
+#[cfg(all(not(target_os = "uefi"), feature = "argv-boot"))]
+#[unsafe(no_mangle)]
+extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
     let args = unsafe { slice::from_raw_parts(argv, argc) };
     let dtb_ptr =
         str_to_usize(unsafe { CStr::from_ptr(args[0] as *const c_char).to_str().unwrap() })
             .unwrap();
+    boot(dtb_ptr, None, Some(program_range()))
+}
+
+// the loader image and its stack, as (address, size)
+#[cfg(not(target_os = "uefi"))]
+fn program_range() -> (usize, usize) {
+    let program_start = unsafe { &raw mut _PROGRAM_START } as *const _ as usize;
+    let stack_start = unsafe { &raw mut _STACK_TOP } as *const _ as usize;
+    (program_start, stack_start - program_start)
+}
+
+/// Boot from the dtb at `dtb_ptr`.
+/// `memory_map` is the RAM the firmware left free, `None` takes `/memory` and the
+/// reservations from the dtb. `program` is the loader image when it lies in that RAM.
+fn boot(
+    dtb_ptr: usize,
+    memory_map: Option<&[(usize, usize)]>,
+    program: Option<(usize, usize)>,
+) -> ! {
+    if SEMIHOSTING_CONSOLE {
+        console::register(semihosting::write_str).unwrap();
+    }
+    let mut profile = profile::BootProfile::new();
+    profile.begin(Phase::DtbParse);
     let dtb = DtbParser::init(dtb_ptr).unwrap();
-    dtb.find_node(None, Some("arm,pl011"), &mut |addr, _size| {
-        debug_uart::init(addr);
-        ControlFlow::Break(())
+    profile.end();
+    if let Some(uart) = earlycon::find(&dtb) {
+        debug_uart::init_uart(uart);
+    }
+    println!("debug uart starting...\r\n");
+    logging::init_log().unwrap();
+    // some firmware drops the loader at EL1: boot the kernel without the hypervisor there
+    let hypervisor = match cpu::get_current_el() {
+        2 => true,
+        1 => {
+            println!("started at EL1, the hypervisor is disabled");
+            false
+        }
+        el => panic!("unsupported exception level: EL{}", el),
+    };
+    systimer::calibrate(&dtb, COUNTER_FREQUENCY);
+    if let Some(led) = STATUS_LED {
+        progress::init(&dtb, led);
+    }
+    let mut watchdog_frames = [0; 2];
+    let mut watchdog_frame_num = 0;
+    dtb.find_node(None, Some(SbsaGwdt::COMPATIBLE), &mut |addr, _size| {
+        watchdog_frames[watchdog_frame_num] = addr;
+        watchdog_frame_num += 1;
+        if watchdog_frame_num == watchdog_frames.len() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
     })
     .unwrap();
-    println!("debug uart starting...\r\n");
-    assert_eq!(cpu::get_current_el(), 2);
+    let watchdog = (watchdog_frame_num == watchdog_frames.len()).then(|| {
+        let watchdog = SbsaGwdt::new(
+            watchdog_frames[0],
+            watchdog_frames[1],
+            cpu::timer::frequency(),
+        );
+        watchdog.start(BOOT_WATCHDOG_TIMEOUT);
+        println!("watchdog armed: {:?}", BOOT_WATCHDOG_TIMEOUT);
+        watchdog
+    });
+    // every wait loop pets the watchdog, IRQs are masked so timer callbacks are run here too
+    let pet_watchdog = || {
+        if let Some(watchdog) = &watchdog {
+            watchdog.pet();
+        }
+        timer::poll();
+    };
+    for compatible in psci::COMPATIBLE {
+        dtb.find_node_property(None, Some(compatible), "method", &mut |method| {
+            if let Some(conduit) = CStr::from_bytes_until_nul(method)
+                .ok()
+                .and_then(|method| method.to_str().ok())
+                .and_then(PsciConduit::from_method)
+            {
+                psci::init(conduit);
+            }
+            ControlFlow::Break(())
+        })
+        .unwrap();
+        if psci::is_available() {
+            break;
+        }
+    }
+    match psci::version() {
+        Ok((major, minor)) => println!("PSCI version {}.{}", major, minor),
+        Err(e) => println!("PSCI is not available: {:?}", e),
+    }
 
     let mut systimer = SystemTimer::new();
     systimer.init();
     println!("setup allocator");
+    profile.begin(Phase::AllocatorInit);
     allocator::init();
-    dtb.find_node(Some("memory"), None, &mut |addr, size| {
-        allocator::add_available_region(addr, size).unwrap();
-        ControlFlow::Continue(())
-    })
-    .unwrap();
-    dtb.find_memory_reservation_block(&mut |addr, size| {
-        allocator::add_reserved_region(addr, size).unwrap();
-        ControlFlow::Continue(())
-    });
+    let mut dram_base = usize::MAX;
+    if let Some(memory_map) = memory_map {
+        // the firmware map already leaves out its reservations and the dtb
+        for &(addr, size) in memory_map {
+            dram_base = dram_base.min(addr);
+            allocator::add_available_region(addr, size).unwrap();
+        }
+    } else {
+        let mut memory = [(0, 0); DtbParser::MAX_MEMORY_REGIONS];
+        let count = dtb.memory_regions(&mut memory).unwrap();
+        for &(addr, size) in &memory[..count] {
+            dram_base = dram_base.min(addr);
+            allocator::add_available_region(addr, size).unwrap();
+        }
+        dtb.find_memory_reservation_block(&mut |addr, size| {
+            allocator::add_reserved_region(addr, size).unwrap();
+            ControlFlow::Continue(())
+        });
+    }
     dtb.find_reserved_memory_node(
         &mut |addr, size| {
-            allocator::add_reserved_region(addr, size).unwrap();
+            if memory_map.is_none() {
+                allocator::add_reserved_region(addr, size).unwrap();
+            }
             ControlFlow::Continue(())
         },
         &mut |size, align, alloc_range| -> Result<ControlFlow<()>, ()> {
@@ -111,79 +267,490 @@ extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
         },
     )
     .unwrap();
-    allocator::add_reserved_region(program_start, stack_start - program_start).unwrap();
-    allocator::add_reserved_region(dtb_ptr, dtb.get_size()).unwrap();
+    if let Some((program_start, program_size)) = program {
+        allocator::add_reserved_region(program_start, program_size).unwrap();
+    }
+    if memory_map.is_none() {
+        allocator::add_reserved_region(dtb_ptr, dtb.get_size()).unwrap();
+    }
     allocator::finalize().unwrap();
+    profile.end();
+    let mut journal = journal::Journal::new();
     println!("allocator setup success!!!");
-    let mut file_driver = None;
-    dtb.find_node(None, Some("virtio,mmio"), &mut |addr, size| {
-        if let Ok(driver) = StorageDevice::new_virtio(addr) {
-            file_driver = Some(driver);
-            // workaround
-            return ControlFlow::Break(());
-        }
-        ControlFlow::Continue(())
-    })
-    .unwrap();
-    let file_driver = file_driver.unwrap();
-    let linux = file_driver
-        .open(0, "/image", &file::OpenOptions::Read)
-        .unwrap();
-    println!("get linux header");
-    let mut linux_header: MaybeUninit<LinuxHeader> = MaybeUninit::uninit();
-    linux
-        .read_at(0, unsafe {
-            &mut *slice_from_raw_parts_mut(
-                &mut linux_header as *mut _ as *mut MaybeUninit<u8>,
-                size_of::<LinuxHeader>(),
-            )
+    pet_watchdog();
+    let mut gic_regs = [(0, 0); 2];
+    let mut gic_reg_num = 0;
+    for compatible in Gicv3::COMPATIBLE {
+        dtb.find_node(None, Some(compatible), &mut |addr, size| {
+            gic_regs[gic_reg_num] = (addr, size);
+            gic_reg_num += 1;
+            if gic_reg_num == gic_regs.len() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
         })
         .unwrap();
-    let linux_header = unsafe { linux_header.assume_init() };
-    // check
-    if linux_header.magic != LinuxHeader::MAGIC {
-        panic!("invalid linux header");
-    }
-    let image_size = linux_header.image_size.read() as usize;
-    let text_offset = linux_header.text_offset.read() as usize;
-    let linux_image = unsafe {
-        alloc(
-            Layout::from_size_align(
-                image_size + text_offset,
-                0x2 * 0x1000 * 0x1000, /* 2MiB */
-            )
-            .unwrap(),
-        )
+        if gic_reg_num == gic_regs.len() {
+            break;
+        }
+        gic_reg_num = 0;
+    }
+    if !hypervisor {
+        // the CPU interface is configured through ICC_SRE_EL2, leave the GIC to the kernel
+    } else if gic_reg_num == gic_regs.len() {
+        interrupt::init_gicv3(gic_regs[0].0, gic_regs[1].0, gic_regs[1].1).unwrap();
+        interrupt::init_vgic(gic::DEFAULT_MAINTENANCE_INTID).unwrap();
+        timer::init(timer::DEFAULT_HYP_TIMER_INTID).unwrap();
+        println!("GICv3 setup success!!!");
+    } else {
+        println!("GICv3 is not found");
+    }
+    if hypervisor {
+        let cpus = smp::start_secondary_cpus(&dtb);
+        println!("{} CPUs online", cpus);
+    }
+    pet_watchdog();
+    profile.begin(Phase::DiskProbe);
+    let disks = StorageDevice::probe_all(&dtb);
+    // the boot disk is the first one holding a kernel its config names, the others are reset
+    let disk_count = disks.len();
+    let mut file_driver = None;
+    let mut disk_address = None;
+    let mut disk_config = None;
+    for storage in disks {
+        let addr = storage.address();
+        if file_driver.is_some() {
+            continue;
+        }
+        let config = config::BootConfig::load(&storage);
+        let entries = boot_menu::collect_entries(&config, &storage);
+        // a config booting from the network is taken as it is
+        if config.net.is_some()
+            || entries
+                .iter()
+                .any(|entry| storage.open(0, &entry.path, &OpenOptions::Read).is_ok())
+        {
+            println!("boot disk: {} at {:#x}", storage.kind(), addr);
+            for partition in storage.partitions().iter().filter(|p| p.dirty) {
+                println!(
+                    "warning: partition {} was not cleanly unmounted, it is not written to",
+                    partition.index
+                );
+            }
+            file_driver = Some(storage);
+            disk_address = Some(addr);
+            disk_config = Some(config);
+        } else {
+            let paths: alloc::vec::Vec<_> = entries.iter().map(|e| e.path.as_str()).collect();
+            println!(
+                "{} at {:#x}: none of {} found",
+                storage.kind(),
+                addr,
+                paths.join(", ")
+            );
+        }
+    }
+    profile.end();
+    let mut boot_log = measure::BootLog::new();
+    let mut boot_config = match disk_config {
+        Some(config) => config,
+        None if disk_count == 0 => {
+            println!("no boot disk found");
+            config::BootConfig::default()
+        }
+        None => {
+            println!(
+                "no boot disk found: {} block devices, none holds a kernel",
+                disk_count
+            );
+            config::BootConfig::default()
+        }
     };
-    if linux_image.is_null() {
-        panic!("allocation failed");
-    }
-    println!("load linux image");
-    linux
-        .read_at(0, unsafe {
-            &mut *slice_from_raw_parts_mut(
-                linux_image.add(text_offset) as *mut MaybeUninit<u8>,
-                linux.size().unwrap() as usize,
-            )
+    if let Some(digest) = boot_config.digest {
+        boot_log.record_digest(measure::Component::Config, config::CONFIG_PATH, digest);
+    }
+    if let Some(level) = boot_config.log_level {
+        logging::set_max_level(level);
+    }
+    if let Some(policy) = boot_config.io_retry {
+        file::set_retry_policy(policy);
+    }
+    if let Some(max_size) = boot_config.boot_log {
+        journal.set_max_size(max_size);
+    }
+    boot_config.fill_from_firmware(&dtb);
+    let policy = verify::Policy::effective(boot_config.verify);
+    if let Some(mode) = boot_config.memtest {
+        memtest::run(mode, &pet_watchdog);
+    }
+    let kernel_watchdog_timeout = boot_config
+        .kernel_watchdog
+        .unwrap_or(KERNEL_WATCHDOG_TIMEOUT);
+    let hand_off_watchdog = || {
+        if let Some(watchdog) = &watchdog {
+            match kernel_watchdog_timeout {
+                Some(timeout) => watchdog.start(timeout),
+                None => watchdog.stop(),
+            }
+        }
+    };
+    // the firmware dtb and the loader stay in place for the whole boot, a downloaded initrd
+    // is allocated before the kernel and out of the way already
+    let loader_ranges: alloc::vec::Vec<_> = program
+        .into_iter()
+        .chain([(dtb_ptr, dtb.get_size())])
+        .collect();
+    let placement = payload::Placement {
+        dram_base,
+        avoid: &loader_ranges,
+    };
+    let firmware_dtb = unsafe {
+        slice_from_raw_parts(dtb_ptr as *const u8, dtb.get_size())
+            .as_ref()
+            .unwrap()
+    };
+    // the guest dtb base when it is read from the disk or downloaded
+    let disk_dtb;
+    let mut disk_overlay = None;
+    let net_dtb;
+    let mut initrd = None;
+    // the CPUs a guest limited by `vcpus` may start, the others are removed from its dtb
+    let mut guest_cpus = None;
+    let disk = file_driver.as_ref().filter(|_| boot_config.net.is_none());
+    let (payload, guest_dtb) = if let Some(storage) = disk {
+        let boot_entries = boot_menu::collect_entries(&boot_config, storage);
+        let boot_entry = &boot_entries[boot_menu::run(
+            storage,
+            &dtb,
+            &boot_entries,
+            &mut boot_config.bootargs,
+            boot_config.timeout.map(Duration::from_secs),
+            &systimer,
+            &pet_watchdog,
+        )];
+        println!("booting '{}' ({})", boot_entry.label, boot_entry.path);
+        if let Some(guest) = &boot_entry.guest {
+            for other in boot_config.guests.iter().filter(|g| g.label != guest.label) {
+                println!(
+                    "guest '{}' is not started: running several guests needs stage-2 translation",
+                    other.label
+                );
+            }
+            if let Some(option) = guest.memory_option() {
+                let bootargs = boot_config.bootargs.get_or_insert_default();
+                if !bootargs.is_empty() {
+                    bootargs.push(' ');
+                }
+                bootargs.push_str(&option);
+            }
+            if let Some(vcpus) = guest.vcpus {
+                smp::set_guest_cpu_limit(vcpus);
+                guest_cpus = hypervisor.then(smp::guest_cpus);
+            }
+        }
+        let configured_dtb = boot_entry
+            .guest
+            .as_ref()
+            .and_then(|guest| guest.dtb.as_deref())
+            .or(boot_config.dtb.as_deref());
+        if let Some(bootargs) = &boot_config.bootargs {
+            println!("bootargs: {}", bootargs);
+        }
+        profile.add_to_journal(&mut journal);
+        journal.record(
+            "boot",
+            &[("entry", &boot_entry.label), ("path", &boot_entry.path)],
+        );
+        journal.flush(storage);
+        profile.begin(Phase::KernelRead);
+        let linux = storage
+            .open(0, &boot_entry.path, &file::OpenOptions::Read)
+            .unwrap_or_else(|e| {
+                journal.fail(storage, format_args!("{}: {:?}", boot_entry.path, e))
+            });
+        let signature = match policy {
+            verify::Policy::Off => None,
+            _ => verify::load_signature(storage, &boot_entry.path),
+        };
+        if let Some(target) = boot_entry.chain {
+            let chained = chainload::load(&linux, target, &|data| {
+                verify::check(policy, "kernel", data, signature.as_ref())
+            })
+            .unwrap_or_else(|e| {
+                journal.fail(storage, format_args!("{}: {:?}", boot_entry.path, e))
+            });
+            profile.begin(Phase::JumpPrep);
+            boot_log.record_digest(measure::Component::Kernel, &boot_entry.path, chained.digest);
+            boot_log.print();
+            profile.add_to_journal(&mut journal);
+            boot_log.add_to_journal(&mut journal);
+            journal.record(
+                "loaded",
+                &[
+                    ("kind", &"chainload"),
+                    ("entry", &format_args!("{:#x}", target.address)),
+                ],
+            );
+            journal.flush(storage);
+            drop(file_driver);
+            if hypervisor {
+                println!(
+                    "{} secondary CPUs powered off",
+                    smp::power_off_secondary_cpus()
+                );
+            }
+            hand_off_watchdog();
+            profile.finish();
+            event::emit(
+                "handoff",
+                &[
+                    ("kind", &"chainload"),
+                    ("entry", &format_args!("{:#x}", target.address)),
+                ],
+            );
+            println!("chainloading {} at {}...", boot_entry.path, target);
+            chainload::jump(&chained, dtb_ptr, dtb.get_size());
+        }
+        let payload = payload::load(&linux, &placement, &|data| {
+            verify::check(policy, "kernel", data, signature.as_ref())
         })
+        .unwrap_or_else(|e| journal.fail(storage, format_args!("{}: {}", boot_entry.path, e)));
+        boot_log.record_digest(measure::Component::Kernel, &boot_entry.path, payload.digest);
+        profile.end();
+        pet_watchdog();
+        // the configured dtb, then the default one, then the firmware dtb
+        disk_dtb = configured_dtb
+            .into_iter()
+            .chain([GUEST_DTB_PATH])
+            .find_map(|path| {
+                let Ok(file) = storage.open(0, path, &OpenOptions::Read) else {
+                    if configured_dtb == Some(path) {
+                        println!("{}: not found, skipped", path);
+                    }
+                    return None;
+                };
+                let Ok(data) = file.read(8) else {
+                    println!("{}: read failed, skipped", path);
+                    return None;
+                };
+                match check_dtb(&data) {
+                    Ok(()) => Some((path, data)),
+                    Err(e) => {
+                        println!("{}: invalid dtb ({}), skipped", path, e);
+                        None
+                    }
+                }
+            });
+        disk_overlay = boot_config.overlay.as_deref().and_then(|path| {
+            let Ok(file) = storage.open(0, path, &OpenOptions::Read) else {
+                println!("{}: not found, skipped", path);
+                return None;
+            };
+            let Ok(data) = file.read(8) else {
+                println!("{}: read failed, skipped", path);
+                return None;
+            };
+            match check_dtb(&data) {
+                Ok(()) => {
+                    boot_log.record(measure::Component::Dtb, path, &data);
+                    Some((path, data))
+                }
+                Err(e) => {
+                    println!("{}: invalid overlay ({}), skipped", path, e);
+                    None
+                }
+            }
+        });
+        let guest_dtb = match &disk_dtb {
+            Some((path, data)) => {
+                println!("guest dtb: {}", path);
+                boot_log.record(measure::Component::Dtb, path, data);
+                &data[..]
+            }
+            None => {
+                println!("guest dtb: firmware");
+                firmware_dtb
+            }
+        };
+        (payload, guest_dtb)
+    } else {
+        let net_config = boot_config.net.clone().unwrap_or_default();
+        if let Some(bootargs) = &boot_config.bootargs {
+            println!("bootargs: {}", bootargs);
+        }
+        profile.begin(Phase::KernelRead);
+        let files = netboot::fetch(
+            &dtb,
+            &net_config,
+            policy != verify::Policy::Off,
+            &pet_watchdog,
+        )
         .unwrap();
-    let jump_addr = unsafe { linux_image.add(text_offset) };
-    let modified = file_driver
-        .open(0, "/qemu.dtb", &OpenOptions::Read)
-        .unwrap()
-        .read(8)
-        .unwrap();
-    let dtb_modified = DtbParser::init(modified.as_ptr() as usize).unwrap();
+        let payload = payload::load_from_memory(&files.kernel, &placement, &|data| {
+            verify::check(policy, "kernel", data, files.signature.as_ref())
+        })
+        .unwrap_or_else(|e| panic!("{}: {}", files.kernel_name, e));
+        boot_log.record_digest(
+            measure::Component::Kernel,
+            &files.kernel_name,
+            payload.digest,
+        );
+        profile.end();
+        pet_watchdog();
+        net_dtb = match (&files.dtb, &net_config.dtb) {
+            (Some(data), Some(name)) => {
+                let data = netboot::AlignedBytes::copy_from(data);
+                match check_dtb(&data) {
+                    Ok(()) => {
+                        boot_log.record(measure::Component::Dtb, name, &data);
+                        Some(data)
+                    }
+                    Err(e) => {
+                        println!("{}: invalid dtb ({}), using the firmware dtb", name, e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        if let (Some(data), Some(name)) = (&files.initrd, &net_config.initrd) {
+            if !verify::check(policy, "initrd", data, files.initrd_signature.as_ref()) {
+                panic!("{}: rejected by the signature check", name);
+            }
+            boot_log.record(measure::Component::Initrd, name, data);
+        }
+        initrd = files.initrd;
+        (payload, net_dtb.as_deref().unwrap_or(firmware_dtb))
+    };
+    if let Some(compression) = payload.compression {
+        println!("decompressed {:?} payload", compression);
+    }
+    println!(
+        "{:?} loaded at {:#x}, entry {:#x}",
+        payload.kind, payload.base, payload.entry
+    );
+    cache::sync_icache(payload.base as *const u8, payload.size);
+    boot_log.print();
+    let crash_region =
+        boot_config
+            .crashkernel
+            .and_then(|crash| match crash.reserve(&loader_ranges) {
+                Ok((start, size)) => {
+                    println!("crashkernel: {:#x}-{:#x}", start, start + size);
+                    Some((start, size))
+                }
+                Err(e) => {
+                    println!("crashkernel: {}, not reserved", e);
+                    None
+                }
+            });
+    // the dtbs read from a file passed `check_dtb` already
+    let guest_parser = DtbParser::from_slice(guest_dtb);
+    let dtb_modified = match &guest_parser {
+        Ok(parser) => parser,
+        Err(e) => {
+            println!("guest dtb: {}, using the firmware dtb", e);
+            &dtb
+        }
+    };
+    let overlay = disk_overlay.as_ref().and_then(|(path, data)| {
+        DtbParser::from_slice(data)
+            .inspect_err(|e| println!("{}: invalid overlay ({}), skipped", path, e))
+            .ok()
+            .map(|parser| (*path, parser))
+    });
+    profile.add_to_journal(&mut journal);
+    boot_log.add_to_journal(&mut journal);
+    journal.record(
+        "loaded",
+        &[
+            ("kind", &format_args!("{:?}", payload.kind)),
+            ("entry", &format_args!("{:#x}", payload.entry)),
+        ],
+    );
+    if let Some(storage) = &file_driver {
+        journal.flush(storage);
+    }
 
     drop(file_driver);
     println!("file system closed");
-    // setup HCR_EL2
-    cpu::setup_hypervisor_registers();
+    if hypervisor {
+        exception::init();
+        // the guest virtual counter starts at zero
+        timer::configure_guest_timer(
+            timer::counter_offset_for_epoch(0),
+            GUEST_PHYSICAL_TIMER_ACCESS,
+        );
+        // setup HCR_EL2
+        cpu::setup_hypervisor_registers();
+        idle::configure_traps(true, false);
+        fpsimd::init();
+        serror::init();
+        debug::configure_traps(GUEST_DEBUG_POLICY, GUEST_PMU_POLICY);
+    }
     let mut reserved_memory = allocator::trim_for_boot(0x1000 * 0x1000 * 128).unwrap();
     println!("allocator closed");
-    reserved_memory.push((program_start, stack_start));
+    assert!(
+        payload.is_retained(&reserved_memory),
+        "the kernel overlaps the memory kept by the hypervisor"
+    );
+    reserved_memory.extend(program);
+    let initrd_range = initrd
+        .as_ref()
+        .map(|initrd| (initrd.as_ptr() as usize, initrd.len()));
+    // the initrd is handed over in the heap and the crash kernel region is described by
+    // `/reserved-memory`, keep both in the guest memory
+    let guest_memory = sanitize::guest_memory(
+        &dtb,
+        memory_map,
+        &reserved_memory,
+        &[(payload.base, payload.size)]
+            .into_iter()
+            .chain(initrd_range)
+            .chain(crash_region)
+            .collect::<alloc::vec::Vec<_>>(),
+    );
+    let crash_cells = crash_region.map(crashkernel::range_cells);
+    let initrd_cells = initrd_range.map(|(start, size)| {
+        [
+            (start as u64).to_be_bytes(),
+            ((start + size) as u64).to_be_bytes(),
+        ]
+    });
 
-    let new_dtb = DtbGenerator::new(&dtb_modified);
+    profile.begin(Phase::DtbRegen);
+    let mut new_dtb = DtbGenerator::new(dtb_modified);
+    if let Some((path, overlay)) = &overlay {
+        match new_dtb.apply_overlay(overlay) {
+            Ok(()) => println!("dtb overlay: {}", path),
+            Err(e) => println!("{}: overlay not applied ({})", path, e),
+        }
+    }
+    if let Some(bootargs) = &boot_config.bootargs {
+        new_dtb.set_bootargs(bootargs);
+    }
+    if let Some(cpus) = &guest_cpus {
+        new_dtb.keep_cpus(cpus);
+    }
+    boot_log.add_to_dtb(&mut new_dtb).unwrap();
+    if let Some([start, end]) = &initrd_cells {
+        new_dtb
+            .set_chosen_property("linux,initrd-start", start)
+            .unwrap();
+        new_dtb
+            .set_chosen_property("linux,initrd-end", end)
+            .unwrap();
+    }
+    if let (Some((start, size)), Some(cells)) = (crash_region, &crash_cells) {
+        new_dtb
+            .add_reserved_memory(crashkernel::NODE_NAME, start, size)
+            .unwrap();
+        new_dtb
+            .set_chosen_property(crashkernel::PROPERTY_NAME, cells)
+            .unwrap();
+    }
+    sanitize::apply(&mut new_dtb, disk_address, &guest_memory).unwrap();
     let dtb_size = new_dtb.get_required_size(reserved_memory.len());
     let dtb_data = unsafe {
         &mut *slice_from_raw_parts_mut(
@@ -194,14 +761,54 @@ extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
     new_dtb
         .make_dtb(dtb_data, reserved_memory.as_ref())
         .unwrap();
-    let base = (jump_addr as usize) - text_offset;
+    profile.begin(Phase::JumpPrep);
+    pet_watchdog();
+    hypercall::set_boot_info(hypercall::BootInfo {
+        dtb_address: dtb_data.as_ptr() as u64,
+        image_base: payload.base as u64,
+        image_size: payload.size as u64,
+    });
+    // the kernel starts with the MMU and caches off, so write back everything it reads
+    cache::clean_dcache_range(payload.base as *const u8, payload.size);
+    cache::clean_dcache_range(dtb_data.as_ptr(), dtb_data.len());
+    hand_off_watchdog();
+    if hypervisor {
+        // the guest starts with EL1 data accesses in the kernel's endianness.
+        // at EL1 this would break the loader itself, the kernel switches on its own there
+        cpu::set_el1_big_endian(payload.big_endian);
+    }
+    profile.finish();
+    event::emit(
+        "handoff",
+        &[
+            ("kind", &format_args!("{:?}", payload.kind)),
+            ("entry", &format_args!("{:#x}", payload.entry)),
+            ("hypervisor", &hypervisor),
+        ],
+    );
+    if !hypervisor {
+        unsafe { core::arch::asm!("msr daifset, #0xf", options(nostack, preserves_flags)) };
+        cpu::disable_el1_mmu();
+        unsafe {
+            core::arch::asm!(
+                "tlbi vmalle1",
+                "dsb sy",
+                "isb",
+                options(nostack, preserves_flags)
+            );
+        }
+        println!("jumping linux without the hypervisor...");
+        jump_to_kernel(payload.entry, dtb_data.as_ptr() as usize);
+    }
     unsafe {
         core::arch::asm!(
             "mrs x9, HCR_EL2",
-            "bic x9, x9, #(1 << 0)",
-            "orr x9, x9, #(1 << 31)",
+            "bic x9, x9, #{vm}",
+            "orr x9, x9, #{rw}",
             "msr HCR_EL2, x9",
             "isb",
+            vm = const HCR_EL2::VM_MASK,
+            rw = const HCR_EL2::RW_MASK,
             options(nostack, preserves_flags)
         );
 
@@ -214,16 +821,21 @@ extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
 
         core::arch::asm!(
             "mrs x9, SCTLR_EL2",
-            "bic x9, x9, #(1 << 0)",  // M = 0 (MMU off)
-            "bic x9, x9, #(1 << 2)",  // C = 0 (D-cache disable)
-            "bic x9, x9, #(1 << 12)", // I = 0 (I-cache disable)
+            "bic x9, x9, #{m}", // MMU off
+            "bic x9, x9, #{c}", // D-cache disable
+            "bic x9, x9, #{i}", // I-cache disable
             "msr SCTLR_EL2, x9",
             "dsb sy",
             "isb",
+            m = const SCTLR_EL2::M_MASK,
+            c = const SCTLR_EL2::C_MASK,
+            i = const SCTLR_EL2::I_MASK,
             options(nostack, preserves_flags)
         );
     }
 
+    let cpus = smp::rendezvous();
+    println!("{} CPUs ready for the guest", cpus);
     println!("jumping linux...");
 
     unsafe {
@@ -231,31 +843,45 @@ extern "C" fn main(argc: usize, argv: *const *const u8) -> ! {
         core::arch::asm!("dsb sy");
     }
 
-    const SPSR_EL2_M_EL1H: u64 = 0b0101; // EL1 with SP_EL1(EL1h)
-    unsafe {
-        core::arch::asm!("msr spsr_el2, {}", in(reg)SPSR_EL2_M_EL1H);
-        core::arch::asm!("msr elr_el2, {}", in(reg)el1_main as *const fn() as usize as u64);
-        core::arch::asm!("eret", options(noreturn));
-    }
+    // the guest enters the kernel like the native path: MMU and caches off, x0 the dtb
+    cpu::disable_el1_mmu();
+    smp::run_boot_cpu(payload.entry as u64, dtb_data.as_ptr() as u64)
 }
 
-fn el1_main() -> ! {
-    loop {
-        unsafe { core::arch::asm!("wfi") };
+// arm64 boot protocol: x0 the dtb, x1-x3 zero, interrupts masked, MMU and D-cache off
+fn jump_to_kernel(entry: usize, dtb: usize) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "msr daifset, #0xf",
+            "mov x1, xzr",
+            "mov x2, xzr",
+            "mov x3, xzr",
+            "br x4",
+            in("x0") dtb,
+            in("x4") entry,
+            options(noreturn, nostack)
+        )
     }
+}
 
-    // TODO
-    // // jump linux
-    // unsafe {
-    //     core::arch::asm!("msr daifset, #0xf", options(nostack, preserves_flags));
+// a dtb read from a file: its header, `totalsize` within `data` and its blocks
+fn check_dtb(data: &[u8]) -> Result<(), &'static str> {
+    DtbParser::from_slice(data)?.validate()
+}
 
-    //     core::mem::transmute::<usize, extern "C" fn(usize, usize, usize, usize)>(
-    //         jump_addr as usize,
-    //     )(dtb_data.as_ptr() as usize, 0, 0, 0);
-    // }
+// a byte count with an optional K, M or G suffix
+pub(crate) fn parse_size(s: &str) -> Option<usize> {
+    let (number, shift) = match s.as_bytes().last()? {
+        b'K' | b'k' => (&s[..s.len() - 1], 10),
+        b'M' | b'm' => (&s[..s.len() - 1], 20),
+        b'G' | b'g' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let size = str_to_usize(number)?.checked_mul(1 << shift)?;
+    (size != 0).then_some(size)
 }
 
-fn str_to_usize(s: &str) -> Option<usize> {
+pub(crate) fn str_to_usize(s: &str) -> Option<usize> {
     let radix;
     let start;
     match s.get(0..2) {
@@ -281,9 +907,26 @@ fn str_to_usize(s: &str) -> Option<usize> {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    let mut debug_uart = Pl011Uart::new(PL011_UART_ADDR);
-    debug_uart.init(4400_0000, 115200);
-    debug_uart.write("core 0 panicked!!!\r\n");
-    debug_uart.write_fmt(format_args!("PANIC: {}", info));
-    loop {}
+    let mut console = panic_report::console(
+        UartConfig::pl011(FALLBACK_UART_ADDR, Some(FALLBACK_UART_CLOCK)),
+        PANIC_UART_BAUDRATE,
+    );
+    panic_report::report(&mut console, info);
+    let _ = event::write_panic(&mut console, info);
+    progress::fatal();
+    reset_after_fatal_error(&mut console)
+}
+
+// reset so CI runs and headless boards do not hang on a failure, spin if PSCI is absent
+fn reset_after_fatal_error(uart: &mut Uart) -> ! {
+    if psci::is_available() {
+        let _ = writeln!(uart, "resetting the system");
+        // the reset would cut off the report still in the FIFO
+        uart.flush();
+        let e = psci::system_reset();
+        let _ = writeln!(uart, "PSCI SYSTEM_RESET failed: {:?}", e);
+    }
+    loop {
+        unsafe { core::arch::asm!("wfe") };
+    }
 }
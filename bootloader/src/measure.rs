@@ -0,0 +1,126 @@
+// measured boot: SHA-256 digests of everything handed to the kernel
+//
+// The digests are printed to the console and emitted as `/chosen` properties of
+// the guest dtb, e.g. `elf-hypervisor,kernel-sha256`, so the booted system can
+// check what it was started from.
+
+use crate::event;
+use crate::journal::Journal;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use arch_hal::println;
+use core::fmt;
+use crypto::sha256;
+use crypto::sha256::DIGEST_SIZE;
+use dtb::DtbGenerator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Kernel,
+    /// the dtb the guest dtb is generated from
+    Dtb,
+    Config,
+    /// the initrd downloaded by the network boot
+    Initrd,
+}
+
+impl Component {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Kernel => "kernel",
+            Self::Dtb => "dtb",
+            Self::Config => "config",
+            Self::Initrd => "initrd",
+        }
+    }
+
+    fn property_name(self) -> &'static str {
+        match self {
+            Self::Kernel => "elf-hypervisor,kernel-sha256",
+            Self::Dtb => "elf-hypervisor,dtb-sha256",
+            Self::Config => "elf-hypervisor,config-sha256",
+            Self::Initrd => "elf-hypervisor,initrd-sha256",
+        }
+    }
+}
+
+pub struct Measurement {
+    pub component: Component,
+    pub path: String,
+    pub digest: [u8; DIGEST_SIZE],
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct BootLog {
+    measurements: Vec<Measurement>,
+}
+
+impl BootLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `digest` of `path`, replacing an earlier record of `component`
+    pub fn record_digest(&mut self, component: Component, path: &str, digest: [u8; DIGEST_SIZE]) {
+        self.measurements.retain(|m| m.component != component);
+        self.measurements.push(Measurement {
+            component,
+            path: path.to_string(),
+            digest,
+        });
+    }
+
+    pub fn record(&mut self, component: Component, path: &str, data: &[u8]) {
+        self.record_digest(component, path, sha256::digest(data));
+    }
+
+    /// print in the `sha256sum` format with the component in front
+    pub fn print(&self) {
+        println!("boot measurements (sha256):");
+        for m in &self.measurements {
+            println!("  {:<7}{}  {}", m.component.name(), Hex(&m.digest), m.path);
+            event::emit(
+                "digest",
+                &[
+                    ("component", &m.component.name()),
+                    ("sha256", &Hex(&m.digest)),
+                    ("path", &m.path),
+                ],
+            );
+        }
+    }
+
+    /// Record every digest in the boot log
+    pub fn add_to_journal(&self, journal: &mut Journal) {
+        for m in &self.measurements {
+            journal.record(
+                "digest",
+                &[
+                    ("component", &m.component.name()),
+                    ("sha256", &Hex(&m.digest)),
+                    ("path", &m.path),
+                ],
+            );
+        }
+    }
+
+    /// Emit every digest as a 32-byte `/chosen` property of the generated dtb
+    pub fn add_to_dtb<'a>(&'a self, generator: &mut DtbGenerator<'a>) -> Result<(), &'static str> {
+        for m in &self.measurements {
+            generator.set_chosen_property(m.component.property_name(), &m.digest)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+// boot-time memory test over the free memory
+//
+// Enabled with `memtest=fast|full` in the config file. It runs once the config is read,
+// before the kernel is placed, over every region the allocator has free. Each pattern is
+// written to a whole region and read back from DRAM. Failing pages are allocated and never
+// released, so they are handed over to the kernel as reserved memory.
+
+use arch_hal::cpu::cache;
+use arch_hal::println;
+
+const PAGE_SIZE: usize = 0x1000;
+// free regions tested, the rest are skipped with a note
+const MAX_REGIONS: usize = 128;
+// failing ranges kept, later failures extend the last one
+const MAX_BAD_RANGES: usize = 32;
+// bytes between watchdog pets
+const PET_INTERVAL: usize = 0x10_0000;
+
+// name and the value written at each address
+type Pattern = (&'static str, fn(usize) -> u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// address and inverted address patterns, finds stuck and shorted address lines
+    Fast,
+    /// the fast patterns followed by solid and alternating bit patterns
+    Full,
+}
+
+impl Mode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "fast" => Some(Self::Fast),
+            "full" => Some(Self::Full),
+            _ => None,
+        }
+    }
+
+    fn patterns(self) -> &'static [Pattern] {
+        const PATTERNS: &[Pattern] = &[
+            ("address", |address| address as u64),
+            ("inverted address", |address| !(address as u64)),
+            ("zeros", |_| 0),
+            ("ones", |_| u64::MAX),
+            ("checkerboard", |_| 0x5555_5555_5555_5555),
+            ("inverted checkerboard", |_| 0xAAAA_AAAA_AAAA_AAAA),
+        ];
+        match self {
+            Self::Fast => &PATTERNS[..2],
+            Self::Full => PATTERNS,
+        }
+    }
+}
+
+// failing memory as page-aligned (start, end) ranges
+struct BadRanges {
+    ranges: [(usize, usize); MAX_BAD_RANGES],
+    len: usize,
+}
+
+impl BadRanges {
+    fn add_page(&mut self, page: usize) {
+        let end = page + PAGE_SIZE;
+        if let Some(last) = self.ranges[..self.len].last_mut()
+            && (last.0..=last.1).contains(&page)
+        {
+            last.1 = last.1.max(end);
+            return;
+        }
+        if self.len == MAX_BAD_RANGES {
+            // out of slots, exclude everything up to this page
+            self.ranges[self.len - 1].1 = end;
+            return;
+        }
+        self.ranges[self.len] = (page, end);
+        self.len += 1;
+    }
+}
+
+/// Test the free memory with the patterns of `mode` and take the failing pages out of the
+/// allocator. `pet` is called while testing.
+pub fn run(mode: Mode, pet: &dyn Fn()) {
+    // nothing may allocate from here until the bad pages are taken
+    let mut regions = [(0, 0); MAX_REGIONS];
+    let count = match allocator::free_regions(&mut regions) {
+        Ok(count) => count,
+        Err(e) => {
+            println!("memtest: {}", e);
+            return;
+        }
+    };
+    if count > MAX_REGIONS {
+        println!(
+            "memtest: {} free regions, only the first {} are tested",
+            count, MAX_REGIONS
+        );
+    }
+    let mut bad = BadRanges {
+        ranges: [(0, 0); MAX_BAD_RANGES],
+        len: 0,
+    };
+    let mut tested = 0;
+    for &(address, size) in &regions[..count.min(MAX_REGIONS)] {
+        let start = address.next_multiple_of(PAGE_SIZE);
+        let end = (address + size) & !(PAGE_SIZE - 1);
+        if start >= end {
+            continue;
+        }
+        println!("memtest: {:#x}-{:#x}", start, end);
+        for &(name, pattern) in mode.patterns() {
+            if !test_range(start, end, pattern, &mut bad, pet) {
+                println!("memtest:   {} pattern failed", name);
+            }
+        }
+        tested += end - start;
+    }
+
+    let mut failed = 0;
+    for &(start, end) in &bad.ranges[..bad.len] {
+        println!("memtest: excluding {:#x}-{:#x}", start, end);
+        if let Err(e) = allocator::allocate_at(start, end - start) {
+            println!("memtest: {:#x}-{:#x} not excluded: {}", start, end, e);
+        }
+        failed += end - start;
+    }
+    println!(
+        "memtest: {} MiB tested, {} KiB excluded",
+        tested >> 20,
+        failed >> 10
+    );
+}
+
+// write `pattern` over [start, end), read it back from DRAM and record the failing pages.
+// Returns whether every word matched.
+fn test_range(
+    start: usize,
+    end: usize,
+    pattern: fn(usize) -> u64,
+    bad: &mut BadRanges,
+    pet: &dyn Fn(),
+) -> bool {
+    for address in (start..end).step_by(size_of::<u64>()) {
+        unsafe { core::ptr::write_volatile(address as *mut u64, pattern(address)) };
+        if address % PET_INTERVAL == 0 {
+            pet();
+        }
+    }
+    // the reads below must not hit the lines just written
+    cache::clean_invalidate_dcache_range(start as *const u8, end - start);
+    let mut passed = true;
+    for page in (start..end).step_by(PAGE_SIZE) {
+        let matched = (page..page + PAGE_SIZE)
+            .step_by(size_of::<u64>())
+            .all(|address| unsafe {
+                core::ptr::read_volatile(address as *const u64) == pattern(address)
+            });
+        if !matched {
+            bad.add_page(page);
+            passed = false;
+        }
+        if page % PET_INTERVAL == 0 {
+            pet();
+        }
+    }
+    passed
+}
@@ -0,0 +1,184 @@
+// network boot: the kernel, dtb and initrd from the TFTP server named by DHCP
+//
+// Selected by `boot=net` in the config file, or when no boot disk is found. The kernel
+// defaults to the DHCP boot file, then `Image`. The dtb and initrd are only fetched when
+// named, the firmware dtb is used otherwise. The NIC is reset before the handoff, so the
+// guest can drive it again.
+
+use crate::systimer;
+use crate::verify;
+use alloc::format;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use arch_hal::println;
+use core::ops::ControlFlow;
+use core::ops::Deref;
+use crypto::ed25519::SIGNATURE_SIZE;
+use dtb::DtbParser;
+use net::Interface;
+use net::Ipv4Addr;
+use net::NetDevice;
+use net::NetErr;
+use net::dhcp;
+use net::tftp;
+use net::virtio_net::VirtIoNet;
+
+const DEFAULT_KERNEL: &str = "Image";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetBootConfig {
+    /// kernel file name, `None` takes the DHCP boot file
+    pub kernel: Option<String>,
+    pub dtb: Option<String>,
+    pub initrd: Option<String>,
+}
+
+impl NetBootConfig {
+    /// parse `net[,kernel=name][,dtb=name][,initrd=name]`
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split(',').map(str::trim);
+        if fields.next()? != "net" {
+            return None;
+        }
+        let mut config = Self::default();
+        for field in fields {
+            let (key, name) = field.split_once('=')?;
+            let name = Some(name.trim().to_string()).filter(|name| !name.is_empty())?;
+            match key.trim() {
+                "kernel" => config.kernel = Some(name),
+                "dtb" => config.dtb = Some(name),
+                "initrd" => config.initrd = Some(name),
+                _ => return None,
+            }
+        }
+        Some(config)
+    }
+}
+
+pub struct NetBoot {
+    /// kernel file name on the server
+    pub kernel_name: String,
+    pub kernel: Vec<u8>,
+    pub signature: Option<[u8; SIGNATURE_SIZE]>,
+    pub dtb: Option<Vec<u8>>,
+    pub initrd: Option<Vec<u8>>,
+    pub initrd_signature: Option<[u8; SIGNATURE_SIZE]>,
+}
+
+/// Fetch the files named by `config` through the first virtio-mmio NIC of `dtb`.
+/// The signatures of the kernel and the initrd are fetched too when `signatures` is set.
+pub fn fetch(
+    dtb: &DtbParser,
+    config: &NetBootConfig,
+    signatures: bool,
+    pet_watchdog: &dyn Fn(),
+) -> Result<NetBoot, NetErr> {
+    let mut nic = None;
+    dtb.find_node(
+        None,
+        Some("virtio,mmio"),
+        &mut |addr, _size| match VirtIoNet::new(addr) {
+            Ok(device) => {
+                nic = Some(device);
+                ControlFlow::Break(())
+            }
+            Err(_) => ControlFlow::Continue(()),
+        },
+    )
+    .map_err(|_| NetErr::Device)?;
+    let nic = nic.ok_or(NetErr::Device)?;
+    println!("network: {}", nic.mac());
+    let clock = || systimer::uptime().as_millis() as u64;
+    let mut interface = Interface::new(nic, &clock);
+
+    let lease = dhcp::request(&mut interface)?;
+    println!(
+        "DHCP: {}/{} gateway {}, boot server {}",
+        lease.address,
+        lease.netmask,
+        lease
+            .gateway
+            .map_or_else(|| "none".to_string(), |gateway| gateway.to_string()),
+        lease.boot_server
+    );
+    pet_watchdog();
+    let kernel_name = config
+        .kernel
+        .clone()
+        .or(lease.boot_file)
+        .unwrap_or_else(|| DEFAULT_KERNEL.to_string());
+    let server = lease.boot_server;
+    let mut read = |name: &str| read_file(&mut interface, server, name, pet_watchdog);
+
+    let kernel = read(&kernel_name)?;
+    let signature = signatures
+        .then(|| read_signature(&mut read, &kernel_name))
+        .flatten();
+    let dtb = config.dtb.as_deref().map(&mut read).transpose()?;
+    let initrd = config.initrd.as_deref().map(&mut read).transpose()?;
+    let initrd_signature = config
+        .initrd
+        .as_deref()
+        .filter(|_| signatures)
+        .and_then(|name| read_signature(&mut read, name));
+    Ok(NetBoot {
+        kernel_name,
+        kernel,
+        signature,
+        dtb,
+        initrd,
+        initrd_signature,
+    })
+}
+
+// a missing signature is reported by the check under the policy
+fn read_signature(
+    read: &mut dyn FnMut(&str) -> Result<Vec<u8>, NetErr>,
+    name: &str,
+) -> Option<[u8; SIGNATURE_SIZE]> {
+    let name = format!("{}{}", name, verify::SIGNATURE_SUFFIX);
+    read(&name).ok().and_then(|data| data[..].try_into().ok())
+}
+
+fn read_file<D: NetDevice>(
+    interface: &mut Interface<D>,
+    server: Ipv4Addr,
+    name: &str,
+    pet_watchdog: &dyn Fn(),
+) -> Result<Vec<u8>, NetErr> {
+    println!("TFTP: {}:{}", server, name);
+    let data = tftp::read(interface, server, name, &mut |_| pet_watchdog()).inspect_err(|e| {
+        println!("TFTP: {} failed: {:?}", name, e);
+    })?;
+    println!("TFTP: {} bytes", data.len());
+    Ok(data)
+}
+
+/// bytes in an 8-byte aligned buffer, as the dtb parser needs
+pub struct AlignedBytes {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl AlignedBytes {
+    pub fn copy_from(data: &[u8]) -> Self {
+        let mut words = vec![0u64; data.len().div_ceil(size_of::<u64>())];
+        let bytes =
+            unsafe { core::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, data.len()) };
+        bytes.copy_from_slice(data);
+        Self {
+            words,
+            len: data.len(),
+        }
+    }
+}
+
+impl Deref for AlignedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.words.as_ptr() as *const u8, self.len) }
+    }
+}
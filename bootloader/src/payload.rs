@@ -0,0 +1,616 @@
+// kernel payload loader: arm64 Linux `Image` or ELF, optionally gzip or zstd compressed
+
+use alloc::alloc::dealloc;
+use alloc::vec;
+use arch_hal::cpu;
+use arch_hal::println;
+use core::alloc::Layout;
+use core::fmt;
+use core::ptr::slice_from_raw_parts_mut;
+use crypto::sha256;
+use crypto::sha256::DIGEST_SIZE;
+use decompress::DecompressErr;
+use decompress::Format;
+use elf::Elf64;
+use elf::ElfErr;
+use file::FileHandle;
+use typestate::Le;
+
+const PAGE_SIZE: usize = 0x1000;
+// arm64 Image must be placed at a 2MiB aligned base
+const IMAGE_ALIGN: usize = 0x2 * 0x1000 * 0x1000;
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+// compressed bytes read to find the format of a compressed file, a zstd block is up to 128KiB
+const COMPRESSED_PEEK_SIZE: usize = 132 * 1024;
+
+#[repr(C)]
+struct LinuxHeader {
+    code0: u32,
+    code1: u32,
+    text_offset: Le<u64>,
+    image_size: Le<u64>,
+    flags: Le<u64>,
+    res2: u64,
+    res3: u64,
+    res4: u64,
+    magic: [u8; 4],
+    res5: u32,
+}
+
+impl LinuxHeader {
+    const MAGIC: [u8; 4] = [b'A', b'R', b'M', 0x64];
+    // kernels before v3.17 leave image_size zero and are linked at this offset
+    const LEGACY_TEXT_OFFSET: usize = 0x8_0000;
+    // bits 4-63 of flags are reserved
+    const RESERVED_FLAGS: u64 = !0b1111;
+
+    // the start of a file read into a byte buffer, which is not aligned for the header
+    fn from_bytes(bytes: &[u8; PayloadKind::HEADER_SIZE]) -> Self {
+        unsafe { bytes.as_ptr().cast::<Self>().read_unaligned() }
+    }
+
+    // check the fields the boot protocol fixes, independent of the file size
+    fn validate(&self) -> Result<(), ImageHeaderErr> {
+        if self.magic != Self::MAGIC {
+            return Err(ImageHeaderErr::Magic(u32::from_le_bytes(self.magic)));
+        }
+        for (name, value) in [
+            ("res2", self.res2),
+            ("res3", self.res3),
+            ("res4", self.res4),
+        ] {
+            if value != 0 {
+                return Err(ImageHeaderErr::Reserved { name, value });
+            }
+        }
+        if self.image_size.read() == 0 {
+            return Ok(());
+        }
+        let text_offset = self.text_offset.read();
+        if text_offset >= IMAGE_ALIGN as u64 || !text_offset.is_multiple_of(PAGE_SIZE as u64) {
+            return Err(ImageHeaderErr::TextOffset(text_offset));
+        }
+        let flags = self.flags.read();
+        if flags & Self::RESERVED_FLAGS != 0 {
+            return Err(ImageHeaderErr::Flags(flags));
+        }
+        Ok(())
+    }
+}
+
+/// field of the Image header that is not acceptable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageHeaderErr {
+    /// `magic` is not "ARM\x64"
+    Magic(u32),
+    /// `text_offset` is not a page aligned offset below 2MiB
+    TextOffset(u64),
+    /// `image_size` is smaller than the file
+    ImageSize { image_size: u64, file_size: u64 },
+    /// reserved bits of `flags` are set
+    Flags(u64),
+    /// a reserved field is not zero
+    Reserved { name: &'static str, value: u64 },
+}
+
+impl fmt::Display for ImageHeaderErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Magic(magic) => write!(f, "bad magic {:#010x}", magic),
+            Self::TextOffset(offset) => write!(
+                f,
+                "text_offset {:#x} is not page aligned below {:#x}",
+                offset, IMAGE_ALIGN
+            ),
+            Self::ImageSize {
+                image_size,
+                file_size,
+            } => write!(
+                f,
+                "image_size {:#x} is smaller than the {:#x} byte file",
+                image_size, file_size
+            ),
+            Self::Flags(flags) => write!(f, "reserved bits set in flags {:#x}", flags),
+            Self::Reserved { name, value } => write!(f, "reserved field {} is {:#x}", name, value),
+        }
+    }
+}
+
+/// decoded `flags` field of the Image header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageFlags {
+    pub big_endian: bool,
+    /// kernel page size, `None` if unspecified
+    pub page_size: Option<usize>,
+    /// the 2MiB aligned base may be anywhere in physical memory.
+    /// otherwise it should be as close as possible to the base of DRAM,
+    /// memory below it is not usable by the kernel
+    pub place_anywhere: bool,
+}
+
+impl ImageFlags {
+    const BIG_ENDIAN: u64 = 1 << 0;
+    const PAGE_SIZE_SHIFT: u64 = 1;
+    const PAGE_SIZE_MASK: u64 = 0b11;
+    const PHYS_PLACEMENT: u64 = 1 << 3;
+
+    pub fn new(flags: u64) -> Self {
+        Self {
+            big_endian: flags & Self::BIG_ENDIAN != 0,
+            page_size: match (flags >> Self::PAGE_SIZE_SHIFT) & Self::PAGE_SIZE_MASK {
+                1 => Some(0x1000),
+                2 => Some(0x4000),
+                3 => Some(0x1_0000),
+                _ => None,
+            },
+            place_anywhere: flags & Self::PHYS_PLACEMENT != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// arm64 Linux `Image`
+    LinuxImage,
+    /// ELF executable (vmlinux, bare-metal application)
+    Elf,
+}
+
+impl PayloadKind {
+    /// number of bytes `detect` looks at
+    pub const HEADER_SIZE: usize = size_of::<LinuxHeader>();
+
+    /// detect the format from the first `HEADER_SIZE` bytes of the file
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.get(..ELF_MAGIC.len()) == Some(&ELF_MAGIC) {
+            return Some(Self::Elf);
+        }
+        if let Some(header) = header.first_chunk::<{ Self::HEADER_SIZE }>()
+            && LinuxHeader::from_bytes(header).magic == LinuxHeader::MAGIC
+        {
+            return Some(Self::LinuxImage);
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadErr {
+    ReadFailed,
+    UnknownFormat,
+    Elf(ElfErr),
+    /// the ELF has no loadable segment
+    EmptyElf,
+    /// the physical range an ELF is linked at is not free memory
+    RegionNotFree,
+    /// the ELF is linked over the range of `Placement::avoid` at `address`,
+    /// an executable which is not position independent cannot be moved out of the way
+    Overlaps {
+        address: usize,
+        size: usize,
+    },
+    OutOfMemory,
+    /// a field of the Image header is not acceptable
+    InvalidHeader(ImageHeaderErr),
+    /// the CPU does not implement the page size of the kernel
+    UnsupportedPageSize,
+    /// the kernel is big-endian and EL1 cannot run big-endian
+    UnsupportedEndianness,
+    /// the file failed the signature check
+    Rejected,
+    Decompress(DecompressErr),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Payload {
+    pub kind: PayloadKind,
+    pub entry: usize,
+    /// the kernel expects big-endian data accesses at EL1
+    pub big_endian: bool,
+    /// memory range occupied by the loaded payload
+    pub base: usize,
+    pub size: usize,
+    /// SHA-256 of the file
+    pub digest: [u8; DIGEST_SIZE],
+    /// compression of the file, `None` if it is stored as is
+    pub compression: Option<Format>,
+}
+
+impl fmt::Display for PayloadErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadFailed => write!(f, "read failed"),
+            Self::UnknownFormat => write!(f, "neither an arm64 Image nor an ELF"),
+            Self::Elf(e) => write!(f, "invalid ELF: {:?}", e),
+            Self::EmptyElf => write!(f, "the ELF has no loadable segment"),
+            Self::RegionNotFree => write!(f, "the ELF is linked at memory which is not free"),
+            Self::Overlaps { address, size } => write!(
+                f,
+                "the ELF is linked over the loader or the dtb at {:#x}-{:#x}",
+                address,
+                address + size
+            ),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::InvalidHeader(e) => write!(f, "invalid Image header: {}", e),
+            Self::UnsupportedPageSize => write!(f, "the CPU does not support the kernel page size"),
+            Self::UnsupportedEndianness => write!(f, "EL1 cannot run a big-endian kernel"),
+            Self::Rejected => write!(f, "rejected by the signature check"),
+            Self::Decompress(e) => write!(f, "decompression failed: {:?}", e),
+        }
+    }
+}
+
+/// format of `file`, `None` if it is neither an Image nor an ELF or cannot be read.
+/// Only the start of a compressed file is read and decompressed.
+pub fn detect(file: &FileHandle) -> Option<PayloadKind> {
+    let mut header = file.read_struct::<[u8; PayloadKind::HEADER_SIZE]>(0).ok()?;
+    if let Some(format) = Format::detect(&header) {
+        let len = (file.size().ok()? as usize).min(COMPRESSED_PEEK_SIZE);
+        let mut data = vec![0u8; len];
+        file.read_exact_at(0, &mut data).ok()?;
+        header = decompress_header(format, &data).ok()?;
+    }
+    PayloadKind::detect(&header)
+}
+
+fn decompress_header(
+    format: Format,
+    data: &[u8],
+) -> Result<[u8; PayloadKind::HEADER_SIZE], PayloadErr> {
+    let mut header = [0u8; PayloadKind::HEADER_SIZE];
+    match format.decompress(data, &mut header) {
+        // the payload is larger than the header
+        Err(DecompressErr::OutputTooSmall) => Ok(header),
+        Ok(_) => Err(PayloadErr::UnknownFormat),
+        Err(e) => Err(PayloadErr::Decompress(e)),
+    }
+}
+
+/// where an Image may be placed, the lowest suitable free memory is taken
+pub struct Placement<'a> {
+    /// the lowest RAM address, used to report memory the kernel cannot use
+    pub dram_base: usize,
+    /// ranges the kernel must not overlap even if they are free, as (address, size)
+    pub avoid: &'a [(usize, usize)],
+}
+
+// where the payload bytes come from
+enum Source<'a> {
+    File(&'a FileHandle),
+    Memory(&'a [u8]),
+    Compressed {
+        format: Format,
+        data: &'a [u8],
+        size: usize,
+    },
+}
+
+impl Source<'_> {
+    fn size(&self) -> Result<usize, PayloadErr> {
+        match self {
+            Self::File(file) => file
+                .size()
+                .map(|size| size as usize)
+                .map_err(|_| PayloadErr::ReadFailed),
+            Self::Memory(data) => Ok(data.len()),
+            Self::Compressed { size, .. } => Ok(*size),
+        }
+    }
+
+    // fill `buf` with the whole content
+    fn read(&self, buf: &mut [u8]) -> Result<(), PayloadErr> {
+        match self {
+            Self::File(file) => file
+                .read_exact_at(0, buf)
+                .map_err(|_| PayloadErr::ReadFailed),
+            Self::Memory(data) => {
+                buf.copy_from_slice(data);
+                Ok(())
+            }
+            Self::Compressed { format, data, .. } => match format.decompress(data, buf) {
+                Ok(len) if len == buf.len() => Ok(()),
+                Ok(_) => Err(PayloadErr::Decompress(DecompressErr::Corrupted)),
+                Err(e) => Err(PayloadErr::Decompress(e)),
+            },
+        }
+    }
+}
+
+// checks the file contents and returns their digest
+type Inspect<'a> = dyn FnMut(&[u8]) -> Result<[u8; DIGEST_SIZE], PayloadErr> + 'a;
+
+// check a file with `accept` and return its digest
+fn inspect(accept: &dyn Fn(&[u8]) -> bool, data: &[u8]) -> Result<[u8; DIGEST_SIZE], PayloadErr> {
+    if !accept(data) {
+        return Err(PayloadErr::Rejected);
+    }
+    Ok(sha256::digest(data))
+}
+
+/// Load `file` into memory. The caller synchronizes the caches before jumping to `entry`.
+/// `accept` sees the file contents once they are read and rejects the payload by returning false.
+/// A compressed file is checked before it is decompressed into place.
+pub fn load(
+    file: &FileHandle,
+    placement: &Placement,
+    accept: &dyn Fn(&[u8]) -> bool,
+) -> Result<Payload, PayloadErr> {
+    let header = file
+        .read_struct::<[u8; PayloadKind::HEADER_SIZE]>(0)
+        .map_err(|_| PayloadErr::ReadFailed)?;
+    let Some(format) = Format::detect(&header) else {
+        return load_source(&Source::File(file), &header, placement, &mut |data| {
+            inspect(accept, data)
+        });
+    };
+
+    let data = file.read(1).map_err(|_| PayloadErr::ReadFailed)?;
+    load_compressed(format, &data, placement, accept)
+}
+
+/// Load a payload that is already in memory, such as a downloaded file, like [`load`]
+pub fn load_from_memory(
+    data: &[u8],
+    placement: &Placement,
+    accept: &dyn Fn(&[u8]) -> bool,
+) -> Result<Payload, PayloadErr> {
+    let header: [u8; PayloadKind::HEADER_SIZE] = data
+        .get(..PayloadKind::HEADER_SIZE)
+        .and_then(|header| header.try_into().ok())
+        .ok_or(PayloadErr::UnknownFormat)?;
+    match Format::detect(&header) {
+        Some(format) => load_compressed(format, data, placement, accept),
+        None => load_source(&Source::Memory(data), &header, placement, &mut |data| {
+            inspect(accept, data)
+        }),
+    }
+}
+
+fn load_compressed(
+    format: Format,
+    data: &[u8],
+    placement: &Placement,
+    accept: &dyn Fn(&[u8]) -> bool,
+) -> Result<Payload, PayloadErr> {
+    let digest = inspect(accept, data)?;
+    let size = format
+        .decompressed_size(data)
+        .map_err(PayloadErr::Decompress)?;
+    let header = decompress_header(format, data)?;
+    let source = Source::Compressed { format, data, size };
+    let payload = load_source(&source, &header, placement, &mut |_| Ok(digest))?;
+    Ok(Payload {
+        compression: Some(format),
+        ..payload
+    })
+}
+
+// `inspect` is called once with the file contents
+fn load_source(
+    source: &Source,
+    header: &[u8; PayloadKind::HEADER_SIZE],
+    placement: &Placement,
+    inspect: &mut Inspect,
+) -> Result<Payload, PayloadErr> {
+    match PayloadKind::detect(header).ok_or(PayloadErr::UnknownFormat)? {
+        PayloadKind::LinuxImage => {
+            load_linux_image(source, &LinuxHeader::from_bytes(header), placement, inspect)
+        }
+        PayloadKind::Elf => load_elf(source, placement, inspect),
+    }
+}
+
+fn load_linux_image(
+    source: &Source,
+    header: &LinuxHeader,
+    placement: &Placement,
+    inspect: &mut Inspect,
+) -> Result<Payload, PayloadErr> {
+    let file_size = source.size()?;
+    header.validate().map_err(PayloadErr::InvalidHeader)?;
+    let image_size = header.image_size.read() as usize;
+    let (text_offset, image_size, flags) = if image_size == 0 {
+        // text_offset had no defined endianness and flags did not exist either,
+        // the kernel was linked at the fixed offset
+        let text_offset = header.text_offset.read();
+        if text_offset != LinuxHeader::LEGACY_TEXT_OFFSET as u64
+            && text_offset.swap_bytes() != LinuxHeader::LEGACY_TEXT_OFFSET as u64
+        {
+            println!(
+                "Image header: image_size is zero, text_offset {:#x} ignored for {:#x}",
+                text_offset,
+                LinuxHeader::LEGACY_TEXT_OFFSET
+            );
+        }
+        (
+            LinuxHeader::LEGACY_TEXT_OFFSET,
+            file_size,
+            ImageFlags::new(0),
+        )
+    } else {
+        (
+            header.text_offset.read() as usize,
+            image_size,
+            ImageFlags::new(header.flags.read()),
+        )
+    };
+    // image_size covers the file and the zero-initialized memory after it
+    if image_size < file_size {
+        return Err(PayloadErr::InvalidHeader(ImageHeaderErr::ImageSize {
+            image_size: image_size as u64,
+            file_size: file_size as u64,
+        }));
+    }
+    if let Some(page_size) = flags.page_size
+        && !cpu::is_granule_supported(page_size)
+    {
+        return Err(PayloadErr::UnsupportedPageSize);
+    }
+    if flags.big_endian && !cpu::is_big_endian_supported() {
+        return Err(PayloadErr::UnsupportedEndianness);
+    }
+
+    // the kernel occupies `image_size` bytes from `text_offset` above a 2MiB aligned base,
+    // the memory between the base and the image is not used by it
+    let entry = allocator::allocate_placed(image_size, IMAGE_ALIGN, text_offset, placement.avoid)
+        .map_err(|_| PayloadErr::OutOfMemory)?;
+    let image_base = entry - text_offset;
+    if !flags.place_anywhere && image_base > placement.dram_base {
+        println!(
+            "the kernel cannot use {:#x} bytes of memory below {:#x}",
+            image_base - placement.dram_base,
+            image_base
+        );
+    }
+    let image = entry as *mut u8;
+    let data = unsafe { &mut *slice_from_raw_parts_mut(image, file_size) };
+    let digest = match source.read(data).and_then(|_| inspect(data)) {
+        Ok(digest) => digest,
+        Err(e) => {
+            // an alignment above the page size releases it to the range allocator
+            unsafe {
+                dealloc(
+                    image,
+                    Layout::from_size_align_unchecked(image_size, IMAGE_ALIGN),
+                )
+            };
+            return Err(e);
+        }
+    };
+    // .bss is cleared by the kernel, but give it a defined state
+    unsafe { core::ptr::write_bytes(image.add(file_size), 0, image_size - file_size) };
+    Ok(Payload {
+        kind: PayloadKind::LinuxImage,
+        entry,
+        big_endian: flags.big_endian,
+        base: entry,
+        size: image_size,
+        digest,
+        compression: None,
+    })
+}
+
+impl Payload {
+    /// whether `reserved`, the regions `allocator::trim_for_boot` kept, covers the payload,
+    /// so the memory left to the hypervisor does not overlap it
+    pub fn is_retained(&self, reserved: &[(usize, usize)]) -> bool {
+        reserved
+            .iter()
+            .any(|&(address, size)| address <= self.base && self.base + self.size <= address + size)
+    }
+}
+
+fn load_elf(
+    source: &Source,
+    placement: &Placement,
+    inspect: &mut Inspect,
+) -> Result<Payload, PayloadErr> {
+    // the whole file is needed to walk the program headers
+    let size = source.size()?;
+    // u64 backing for the alignment of the headers
+    const { assert!(Elf64::elf_header_size().1 <= align_of::<u64>()) };
+    let mut buffer = vec![0u64; size.div_ceil(size_of::<u64>())];
+    let data = unsafe { &mut *slice_from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size) };
+    source.read(data)?;
+    let digest = inspect(data)?;
+    let elf = unsafe { Elf64::new(data) }.map_err(PayloadErr::Elf)?;
+    if elf.is_big_endian() && !cpu::is_big_endian_supported() {
+        return Err(PayloadErr::UnsupportedEndianness);
+    }
+
+    let mut start = usize::MAX;
+    let mut end = 0;
+    let mut align = PAGE_SIZE;
+    elf.iterate_program_header(|segment| {
+        if segment.mem_len() == 0 {
+            return;
+        }
+        start = start.min(segment.address() as usize);
+        end = end.max((segment.address() + segment.mem_len()) as usize);
+        align = align.max(segment.align() as usize);
+    })
+    .map_err(PayloadErr::Elf)?;
+    if start >= end {
+        return Err(PayloadErr::EmptyElf);
+    }
+    // segments are placed at their physical address (p_paddr)
+    let base = start & !(PAGE_SIZE - 1);
+    let size = end.next_multiple_of(PAGE_SIZE) - base;
+    // the allocator does not know every range the loader still needs, such as the firmware dtb
+    // when the memory map came from the firmware
+    let overlap = placement
+        .avoid
+        .iter()
+        .find(|&&(address, avoid_size)| address < base + size && base < address + avoid_size);
+    let load_base = if elf.is_position_independent() {
+        // every relocated word must lie in the loaded range
+        let mut outside = false;
+        elf.iterate_relocations(|address, _| {
+            outside |= (address as usize) < base
+                || (address as usize).saturating_add(size_of::<u64>()) > base + size;
+        })
+        .map_err(PayloadErr::Elf)?;
+        if outside || !align.is_power_of_two() {
+            return Err(PayloadErr::Elf(ElfErr::Invalid));
+        }
+        // a PIE stays at the address it is linked at when it can, otherwise it is moved
+        // to free memory clear of the `avoid` ranges, keeping the alignment of its segments
+        if overlap.is_none() && allocator::allocate_at(base, size).is_ok() {
+            base
+        } else {
+            let load_base = allocator::allocate_placed(size, align, base % align, placement.avoid)
+                .map_err(|_| PayloadErr::OutOfMemory)?;
+            println!(
+                "the ELF linked at {:#x} is loaded at {:#x}",
+                base, load_base
+            );
+            load_base
+        }
+    } else {
+        if let Some(&(address, size)) = overlap {
+            return Err(PayloadErr::Overlaps { address, size });
+        }
+        allocator::allocate_at(base, size).map_err(|_| PayloadErr::RegionNotFree)?;
+        base
+    };
+    let bias = load_base.wrapping_sub(base);
+
+    elf.iterate_program_header(|segment| {
+        let address = (segment.address() as usize).wrapping_add(bias);
+        let file_len = segment.file_len() as usize;
+        let offset = segment.offset() as usize;
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr().add(offset), address as *mut u8, file_len);
+            // .bss
+            core::ptr::write_bytes(
+                (address + file_len) as *mut u8,
+                0,
+                segment.mem_len() as usize - file_len,
+            );
+        }
+    })
+    .map_err(PayloadErr::Elf)?;
+    if bias != 0 {
+        let big_endian = elf.is_big_endian();
+        elf.iterate_relocations(|address, addend| {
+            let value = addend.wrapping_add(bias as u64);
+            let value = if big_endian {
+                value.to_be()
+            } else {
+                value.to_le()
+            };
+            unsafe { ((address as usize).wrapping_add(bias) as *mut u64).write_unaligned(value) };
+        })
+        .map_err(PayloadErr::Elf)?;
+    }
+
+    Ok(Payload {
+        kind: PayloadKind::Elf,
+        entry: (elf.entry() as usize).wrapping_add(bias),
+        big_endian: elf.is_big_endian(),
+        base: load_base,
+        size,
+        digest,
+        compression: None,
+    })
+}
@@ -0,0 +1,117 @@
+// boot phase profiling
+//
+// Each phase records the system counter at its start and end. The summary is printed
+// before the handoff and the raw marks are published through `HYP_GET_BOOT_PHASE`,
+// indexed by the `Phase` discriminant.
+
+use crate::event;
+use crate::journal::Journal;
+use crate::progress;
+use crate::systimer;
+use arch_hal::hypercall;
+use arch_hal::println;
+use arch_hal::timer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    DtbParse = 0,
+    AllocatorInit = 1,
+    DiskProbe = 2,
+    KernelRead = 3,
+    DtbRegen = 4,
+    JumpPrep = 5,
+}
+
+impl Phase {
+    const COUNT: usize = 6;
+    const ALL: [Self; Self::COUNT] = [
+        Self::DtbParse,
+        Self::AllocatorInit,
+        Self::DiskProbe,
+        Self::KernelRead,
+        Self::DtbRegen,
+        Self::JumpPrep,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::DtbParse => "dtb parse",
+            Self::AllocatorInit => "allocator init",
+            Self::DiskProbe => "disk probe",
+            Self::KernelRead => "kernel read",
+            Self::DtbRegen => "dtb regen",
+            Self::JumpPrep => "jump prep",
+        }
+    }
+}
+
+const _: () = assert!(Phase::COUNT <= hypercall::MAX_BOOT_PHASES);
+
+pub struct BootProfile {
+    // counter when the loader started
+    start: u64,
+    // (start, end) counter values, (0, 0) until the phase ends
+    marks: [(u64, u64); Phase::COUNT],
+    // phase which began and has not ended, with its start
+    current: Option<(Phase, u64)>,
+    // phases already recorded in the boot log
+    journaled: [bool; Phase::COUNT],
+}
+
+impl BootProfile {
+    pub fn new() -> Self {
+        Self {
+            start: systimer::ticks(),
+            marks: [(0, 0); Phase::COUNT],
+            current: None,
+            journaled: [false; Phase::COUNT],
+        }
+    }
+
+    /// start timing `phase`, ending the current one, and show it on the status LED
+    pub fn begin(&mut self, phase: Phase) {
+        self.end();
+        progress::phase(phase);
+        self.current = Some((phase, systimer::ticks()));
+    }
+
+    /// end the current phase
+    pub fn end(&mut self) {
+        if let Some((phase, start)) = self.current.take() {
+            self.marks[phase as usize] = (start, systimer::ticks());
+        }
+    }
+
+    /// record the phases which ended since the last call in the boot log
+    pub fn add_to_journal(&mut self, journal: &mut Journal) {
+        for phase in Phase::ALL {
+            let (start, end) = self.marks[phase as usize];
+            if (start, end) != (0, 0) && !self.journaled[phase as usize] {
+                let us = timer::ticks_to_duration(end - start).as_micros();
+                journal.record("phase", &[("name", &phase.name()), ("us", &us)]);
+                self.journaled[phase as usize] = true;
+            }
+        }
+    }
+
+    /// print the duration of each phase and publish the marks to the guest
+    pub fn finish(&mut self) {
+        self.end();
+        let micros = |ticks: u64| timer::ticks_to_duration(ticks).as_micros();
+        println!("boot profile:");
+        for phase in Phase::ALL {
+            match self.marks[phase as usize] {
+                (0, 0) => println!("  {:<16}       -", phase.name()),
+                (start, end) => {
+                    let us = micros(end - start);
+                    println!("  {:<16}{:>8} us", phase.name(), us);
+                    event::emit("phase", &[("name", &phase.name()), ("us", &us)]);
+                }
+            }
+        }
+        let total = micros(systimer::ticks() - self.start);
+        println!("  {:<16}{:>8} us", "total", total);
+        event::emit("phase", &[("name", &"total"), ("us", &total)]);
+        hypercall::set_boot_phases(&self.marks);
+    }
+}
@@ -0,0 +1,143 @@
+// boot progress on a GPIO LED, for boards where the UART is not wired up
+//
+// `STATUS_LED` in main names the pin, the controller is the first PL061 or BCM2835 GPIO of
+// the dtb. Each boot phase starts with as many short blinks as its number (see `Phase`),
+// then the LED stays on. A panic blinks the number of the phase it happened in with long
+// blinks, a few times before the reset.
+//
+// The state is kept in atomics so the panic handler can reach the LED without a lock.
+
+use crate::profile::Phase;
+use arch_hal::cpu::timer::delay_ms;
+use arch_hal::gpio::Gpio;
+use arch_hal::gpio::bcm2835::Bcm2835Gpio;
+use arch_hal::gpio::pl061::Pl061;
+use arch_hal::println;
+use core::ops::ControlFlow;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use dtb::DtbParser;
+
+const SHORT_BLINK_MS: u64 = 50;
+const LONG_BLINK_MS: u64 = 400;
+// pause between two codes
+const CODE_GAP_MS: u64 = 1500;
+// times the panic code is shown before the reset
+const PANIC_REPEAT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusLed {
+    pub pin: u32,
+    /// the LED is lit when the pin is low
+    pub active_low: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Controller {
+    None = 0,
+    Pl061 = 1,
+    Bcm2835 = 2,
+}
+
+static CONTROLLER: AtomicU8 = AtomicU8::new(Controller::None as u8);
+static BASE: AtomicUsize = AtomicUsize::new(0);
+static PIN: AtomicU32 = AtomicU32::new(0);
+static ACTIVE_LOW: AtomicBool = AtomicBool::new(false);
+// code of the current phase, 0 before the first one
+static PHASE_CODE: AtomicU8 = AtomicU8::new(0);
+
+/// Find the GPIO controller of `led` in `dtb` and turn the LED on
+pub fn init(dtb: &DtbParser, led: StatusLed) {
+    let mut found = None;
+    for (compatible, controller) in [
+        (Pl061::COMPATIBLE, Controller::Pl061),
+        (Bcm2835Gpio::COMPATIBLE, Controller::Bcm2835),
+        (Bcm2835Gpio::COMPATIBLE_BCM2711, Controller::Bcm2835),
+    ] {
+        let _ = dtb.find_node(None, Some(compatible), &mut |addr, _size| {
+            found = Some((controller, addr));
+            ControlFlow::Break(())
+        });
+        if found.is_some() {
+            break;
+        }
+    }
+    let Some((controller, base)) = found else {
+        println!("status LED: no GPIO controller found");
+        return;
+    };
+    let pin_count = match controller {
+        Controller::Pl061 => Pl061::new(base).pin_count(),
+        _ => Bcm2835Gpio::new(base).pin_count(),
+    };
+    if led.pin >= pin_count {
+        println!("status LED: pin {} out of range", led.pin);
+        return;
+    }
+    BASE.store(base, Ordering::Relaxed);
+    PIN.store(led.pin, Ordering::Relaxed);
+    ACTIVE_LOW.store(led.active_low, Ordering::Relaxed);
+    CONTROLLER.store(controller as u8, Ordering::Release);
+    with_gpio(|gpio, pin| gpio.set_output(pin));
+    set(true);
+    println!("status LED: GPIO {} at {:#x}", led.pin, base);
+}
+
+/// blink the code of `phase`, then leave the LED on
+pub fn phase(phase: Phase) {
+    let code = phase as u8 + 1;
+    PHASE_CODE.store(code, Ordering::Relaxed);
+    if !is_enabled() {
+        return;
+    }
+    set(false);
+    delay_ms(SHORT_BLINK_MS);
+    blink(code, SHORT_BLINK_MS);
+    set(true);
+}
+
+/// Blink the code of the phase which failed. Called by the panic handler, returns so it can
+/// reset the system.
+pub fn fatal() {
+    if !is_enabled() {
+        return;
+    }
+    let code = PHASE_CODE.load(Ordering::Relaxed).max(1);
+    for _ in 0..PANIC_REPEAT {
+        set(false);
+        delay_ms(CODE_GAP_MS);
+        blink(code, LONG_BLINK_MS);
+    }
+}
+
+fn is_enabled() -> bool {
+    CONTROLLER.load(Ordering::Acquire) != Controller::None as u8
+}
+
+fn blink(count: u8, duration_ms: u64) {
+    for _ in 0..count {
+        set(true);
+        delay_ms(duration_ms);
+        set(false);
+        delay_ms(duration_ms);
+    }
+}
+
+fn set(lit: bool) {
+    let high = lit != ACTIVE_LOW.load(Ordering::Relaxed);
+    with_gpio(|gpio, pin| gpio.write(pin, high));
+}
+
+fn with_gpio(f: impl FnOnce(&dyn Gpio, u32)) {
+    let base = BASE.load(Ordering::Relaxed);
+    let pin = PIN.load(Ordering::Relaxed);
+    match CONTROLLER.load(Ordering::Acquire) {
+        x if x == Controller::Pl061 as u8 => f(&Pl061::new(base), pin),
+        x if x == Controller::Bcm2835 as u8 => f(&Bcm2835Gpio::new(base), pin),
+        _ => {}
+    }
+}
@@ -0,0 +1,82 @@
+// guest dtb sanitization
+//
+// The guest must not touch what the hypervisor keeps: the virtio-mmio disk the loader
+// claimed is disabled and the RAM the hypervisor retains is removed from `/memory`.
+// The debug UART stays with the guest, the hypervisor does not emulate it.
+
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+use dtb::DtbGenerator;
+use dtb::DtbParser;
+
+/// RAM the guest may use: `memory_map`, or the `/memory` regions of `dtb` without one, without
+/// the `reserved` regions the hypervisor keeps. `guest` regions (the kernel) and the firmware
+/// reservations, which the guest dtb describes itself, stay in memory even if they are reserved
+pub fn guest_memory(
+    dtb: &DtbParser,
+    memory_map: Option<&[(usize, usize)]>,
+    reserved: &[(usize, usize)],
+    guest: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    let mut memory = Vec::new();
+    match memory_map {
+        Some(memory_map) => memory.extend_from_slice(memory_map),
+        None => {
+            let mut regions = [(0, 0); DtbParser::MAX_MEMORY_REGIONS];
+            if let Ok(count) = dtb.memory_regions(&mut regions) {
+                memory.extend_from_slice(&regions[..count]);
+            }
+        }
+    }
+    let mut visible = guest.to_vec();
+    dtb.find_memory_reservation_block(&mut |address, size| {
+        visible.push((address, size));
+        ControlFlow::Continue(())
+    });
+    let _ = dtb.find_reserved_memory_node(
+        &mut |address, size| {
+            visible.push((address, size));
+            ControlFlow::Continue(())
+        },
+        &mut |_, _, _| -> Result<ControlFlow<()>, ()> { Ok(ControlFlow::Continue(())) },
+    );
+    let retained = subtract(reserved, &visible);
+    let mut memory = subtract(&memory, &retained);
+    memory.sort_unstable();
+    memory
+}
+
+/// Disable the claimed disk at `disk` and limit `/memory` to `memory`
+pub fn apply<'a>(
+    generator: &mut DtbGenerator<'a>,
+    disk: Option<usize>,
+    memory: &'a [(usize, usize)],
+) -> Result<(), &'static str> {
+    if let Some(disk) = disk {
+        generator.disable_node(disk)?;
+    }
+    if !memory.is_empty() {
+        generator.set_memory(memory);
+    }
+    Ok(())
+}
+
+// `regions` without the parts covered by `holes`, both as (address, size)
+fn subtract(regions: &[(usize, usize)], holes: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut result: Vec<(usize, usize)> = regions.to_vec();
+    for &(hole, hole_size) in holes {
+        let hole_end = hole.saturating_add(hole_size);
+        result = result
+            .into_iter()
+            .flat_map(|(address, size)| {
+                let end = address + size;
+                let before = (address, hole.min(end).saturating_sub(address));
+                let after_start = hole_end.max(address);
+                let after = (after_start, end.saturating_sub(after_start));
+                [before, after]
+            })
+            .filter(|&(_, size)| size != 0)
+            .collect();
+    }
+    result
+}
@@ -0,0 +1,271 @@
+// debug shell on the debug UART, entered from the boot menu
+
+use crate::boot_menu;
+use crate::boot_menu::BootEntry;
+use crate::str_to_usize;
+use alloc::format;
+use alloc::string::ToString;
+use arch_hal::debug_uart;
+use arch_hal::log_buffer;
+use arch_hal::logging;
+use arch_hal::logging::Level;
+use arch_hal::print;
+use arch_hal::println;
+use core::ops::ControlFlow;
+use dtb::DtbParser;
+use file::FileSystemKind;
+use file::OpenOptions;
+use file::PartitionKind;
+use file::StorageDevice;
+
+const PROMPT: &str = "elf> ";
+// bytes shown by `hexdump` and `md` without a length
+const DEFAULT_DUMP_LEN: usize = 256;
+const DUMP_LINE: usize = 16;
+
+/// Run the shell until `exit` or `boot`. Returns the index of the entry to boot, if any.
+/// `pet` is called while waiting for input.
+pub fn run(
+    storage: &StorageDevice,
+    dtb: &DtbParser,
+    entries: &[BootEntry],
+    pet: &dyn Fn(),
+) -> Option<usize> {
+    println!("debug shell, type 'help' for commands");
+    loop {
+        print!("{}", PROMPT);
+        let Some(line) = boot_menu::read_line("", pet) else {
+            continue;
+        };
+        let mut args = line.split_ascii_whitespace();
+        match args.next() {
+            None => {}
+            Some("help") => {
+                println!("parts                      list the partitions of the disk");
+                println!("ls [path]                  list a directory");
+                println!("cat <path>                 print a file");
+                println!("hexdump <path> [off [len]] dump a file");
+                println!("md <addr> [len]            dump memory");
+                println!("mw <addr> <value>          write a 32-bit word");
+                println!("dtb                        list the firmware dtb nodes");
+                println!("loglevel [level]           show or set the console log level");
+                println!("dmesg                      print the log buffer");
+                println!("allocstat                  show the buddy allocator usage");
+                println!("boot <n|label>             boot a menu entry");
+                println!("exit                       return to the boot menu");
+            }
+            Some("parts") => partitions(storage),
+            Some("ls") => list(storage, args.next().unwrap_or("/")),
+            Some("cat") => match args.next() {
+                Some(path) => cat(storage, path, pet),
+                None => println!("usage: cat <path>"),
+            },
+            Some("hexdump") => {
+                let path = args.next();
+                let offset = args.next().map(str_to_usize);
+                let len = args.next().map(str_to_usize);
+                match (
+                    path,
+                    offset.unwrap_or(Some(0)),
+                    len.unwrap_or(Some(DEFAULT_DUMP_LEN)),
+                ) {
+                    (Some(path), Some(offset), Some(len)) => hexdump(storage, path, offset, len),
+                    _ => println!("usage: hexdump <path> [offset [len]]"),
+                }
+            }
+            Some("md") => {
+                let addr = args.next().and_then(str_to_usize);
+                let len = args.next().map(str_to_usize);
+                match (addr, len.unwrap_or(Some(DEFAULT_DUMP_LEN))) {
+                    (Some(addr), Some(len)) => memory_dump(addr, len),
+                    _ => println!("usage: md <addr> [len]"),
+                }
+            }
+            Some("mw") => {
+                let addr = args.next().and_then(str_to_usize);
+                let value = args.next().and_then(str_to_usize);
+                match (addr, value.and_then(|v| u32::try_from(v).ok())) {
+                    (Some(addr), Some(value)) if addr % align_of::<u32>() == 0 => unsafe {
+                        core::ptr::write_volatile(addr as *mut u32, value);
+                    },
+                    _ => println!("usage: mw <addr> <value>, addr 4-byte aligned"),
+                }
+            }
+            Some("dtb") => {
+                let result = dtb.for_each_node(&mut |depth, name| {
+                    let name = if depth == 0 { "/" } else { name };
+                    println!("{:width$}{}", "", name, width = depth * 2);
+                    ControlFlow::Continue(())
+                });
+                if let Err(e) = result {
+                    println!("dtb: {}", e);
+                }
+            }
+            Some("loglevel") => match args.next() {
+                None => println!("{}", logging::max_level()),
+                Some(name) => match Level::parse(name) {
+                    Some(level) => logging::set_max_level(level),
+                    None => println!("usage: loglevel [error|warn|info|debug|trace]"),
+                },
+            },
+            Some("dmesg") => {
+                // the buffer is locked while it is written out, bypass `print!`
+                log_buffer::for_each_chunk(&mut |chunk| {
+                    for &byte in chunk {
+                        if byte == b'\n' {
+                            debug_uart::write_byte(b'\r');
+                        }
+                        debug_uart::write_byte(byte);
+                    }
+                });
+            }
+            Some("allocstat") => match allocator::fragmentation_report() {
+                Ok(report) => print!("{}", report),
+                Err(e) => println!("allocstat: {}", e),
+            },
+            Some("boot") => {
+                let Some(target) = args.next() else {
+                    println!("usage: boot <n|label>");
+                    continue;
+                };
+                let index = target
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&i| i < entries.len())
+                    .or_else(|| entries.iter().position(|e| e.label == target));
+                match index {
+                    Some(index) => return Some(index),
+                    None => println!("no entry '{}'", target),
+                }
+            }
+            Some("exit") => return None,
+            Some(command) => println!("unknown command: {}", command),
+        }
+    }
+}
+
+fn partitions(storage: &StorageDevice) {
+    for partition in storage.partitions() {
+        let kind = match &partition.kind {
+            PartitionKind::Mbr(kind) => format!("mbr {:#04x}", kind),
+            PartitionKind::Gpt { unique_guid, .. } => format!("gpt {}", unique_guid),
+            PartitionKind::Whole => "whole disk".to_string(),
+        };
+        let filesystem = match partition.filesystem {
+            Some(FileSystemKind::Fat32) => "fat32",
+            None => "-",
+        };
+        println!(
+            "{:>3}  {:>10}  {:>8} KiB  {:<6} {} {}{}",
+            partition.index,
+            partition.start_sector,
+            partition.size() / 1024,
+            filesystem,
+            kind,
+            partition.label().unwrap_or(""),
+            if partition.dirty { " (dirty)" } else { "" }
+        );
+    }
+}
+
+fn list(storage: &StorageDevice, path: &str) {
+    let result = storage.read_dir(0, path, &mut |entry| {
+        if entry.is_dir {
+            println!("{:>10}  {}/", "", entry.name);
+        } else {
+            println!("{:>10}  {}", entry.size, entry.name);
+        }
+        ControlFlow::Continue(())
+    });
+    if let Err(e) = result {
+        println!("ls: {}: {:?}", path, e);
+    }
+}
+
+fn cat(storage: &StorageDevice, path: &str, pet: &dyn Fn()) {
+    let file = match storage.open(0, path, &OpenOptions::Read) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("cat: {}: {:?}", path, e);
+            return;
+        }
+    };
+    let size = file.size().unwrap_or(0) as usize;
+    let mut buf = [0u8; 512];
+    let mut offset = 0;
+    while offset < size {
+        let len = buf.len().min(size - offset);
+        if file.read_exact_at(offset as u64, &mut buf[..len]).is_err() {
+            println!();
+            println!("cat: {}: read failed at {:#x}", path, offset);
+            return;
+        }
+        for &byte in &buf[..len] {
+            if byte == b'\n' {
+                debug_uart::write_byte(b'\r');
+            }
+            debug_uart::write_byte(byte);
+        }
+        offset += len;
+        pet();
+    }
+    println!();
+}
+
+fn hexdump(storage: &StorageDevice, path: &str, offset: usize, len: usize) {
+    let file = match storage.open(0, path, &OpenOptions::Read) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("hexdump: {}: {:?}", path, e);
+            return;
+        }
+    };
+    let size = file.size().unwrap_or(0) as usize;
+    let end = offset.saturating_add(len).min(size);
+    let mut line = [0u8; DUMP_LINE];
+    let mut at = offset;
+    while at < end {
+        let count = DUMP_LINE.min(end - at);
+        if file.read_exact_at(at as u64, &mut line[..count]).is_err() {
+            println!("hexdump: {}: read failed at {:#x}", path, at);
+            return;
+        }
+        print_line(at, &line[..count]);
+        at += count;
+    }
+}
+
+fn memory_dump(addr: usize, len: usize) {
+    let mut line = [0u8; DUMP_LINE];
+    let end = addr.saturating_add(len);
+    let mut at = addr;
+    while at < end {
+        let count = DUMP_LINE.min(end - at);
+        for (i, byte) in line[..count].iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((at + i) as *const u8) };
+        }
+        print_line(at, &line[..count]);
+        at += count;
+    }
+}
+
+// `address: hex bytes |ascii|`
+fn print_line(address: usize, bytes: &[u8]) {
+    print!("{:016x}: ", address);
+    for i in 0..DUMP_LINE {
+        match bytes.get(i) {
+            Some(byte) => print!("{:02x} ", byte),
+            None => print!("   "),
+        }
+    }
+    print!("|");
+    for &byte in bytes {
+        let c = if byte.is_ascii_graphic() || byte == b' ' {
+            byte as char
+        } else {
+            '.'
+        };
+        print!("{}", c);
+    }
+    println!("|");
+}
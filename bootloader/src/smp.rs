@@ -0,0 +1,434 @@
+// secondary CPU startup
+
+use crate::println;
+use alloc::alloc::alloc;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use arch_hal::cpu;
+use arch_hal::cpu::cache;
+use arch_hal::cpu::psci;
+use arch_hal::cpu::psci::AffinityState;
+use arch_hal::cpu::psci::PsciErr;
+use arch_hal::debug;
+use arch_hal::exception;
+use arch_hal::fpsimd;
+use arch_hal::idle;
+use arch_hal::interrupt;
+use arch_hal::psci_proxy;
+use arch_hal::serror;
+use arch_hal::timer;
+use arch_hal::vcpu::Vcpu;
+use core::alloc::Layout;
+use core::arch::asm;
+use core::arch::naked_asm;
+use core::ops::ControlFlow;
+use core::ptr;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use dtb::DtbParser;
+use mutex::Barrier;
+
+const MAX_CPUS: usize = 8;
+const SECONDARY_STACK_SIZE: usize = 0x10000;
+const STARTUP_TIMEOUT_LOOPS: usize = 0x100_0000;
+
+enum EnableMethod {
+    Psci,
+    SpinTable(usize),
+}
+
+// secondary CPUs start with the MMU and caches off,
+// so every field is cleaned to PoC after the boot CPU writes it
+#[repr(C, align(64))]
+struct CpuMailbox {
+    mpidr: AtomicU64,
+    // running at EL2, either parked or running the guest
+    online: AtomicBool,
+    // guest entry point requested through the virtual PSCI CPU_ON, 0 while parked
+    entry_point: AtomicU64,
+    // the host asks a parked CPU to power itself off
+    power_off: AtomicBool,
+    context_id: AtomicU64,
+    // CNTVOFF_EL2 shared by every vCPU
+    counter_offset: AtomicU64,
+    // a parked CPU waits on the barrier once, see `rendezvous`
+    rendezvous: AtomicPtr<Barrier>,
+}
+
+impl CpuMailbox {
+    const fn new() -> Self {
+        Self {
+            mpidr: AtomicU64::new(0),
+            online: AtomicBool::new(false),
+            entry_point: AtomicU64::new(0),
+            power_off: AtomicBool::new(false),
+            context_id: AtomicU64::new(0),
+            counter_offset: AtomicU64::new(0),
+            rendezvous: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+// MAILBOXES[0] is the boot CPU
+static MAILBOXES: [CpuMailbox; MAX_CPUS] = [const { CpuMailbox::new() }; MAX_CPUS];
+static NUM_MAILBOXES: AtomicUsize = AtomicUsize::new(0);
+const BOOT_CPU_INDEX: usize = 0;
+// stack and mailbox of the secondary CPU which is being started
+static SECONDARY_STACK_TOP: AtomicUsize = AtomicUsize::new(0);
+static SECONDARY_INDEX: AtomicUsize = AtomicUsize::new(0);
+// vCPUs the guest may run, the boot CPU included
+static GUEST_CPU_LIMIT: AtomicUsize = AtomicUsize::new(MAX_CPUS);
+
+fn clean_mailbox(mailbox: &CpuMailbox) {
+    cache::clean_dcache_range(mailbox as *const _ as *const u8, size_of::<CpuMailbox>());
+}
+
+fn read_be_cells(value: &[u8]) -> Option<u64> {
+    match value.len() {
+        4 => Some(u32::from_be_bytes(value.try_into().unwrap()) as u64),
+        8 => Some(u64::from_be_bytes(value.try_into().unwrap())),
+        _ => None,
+    }
+}
+
+/// Start every secondary CPU listed in the DTB, one after the other, each once the previous
+/// one is online at EL2. Once parked, they all meet the boot CPU at a barrier in `rendezvous`,
+/// and are then handed to the guest through the virtual PSCI CPU_ON.
+/// Returns the number of CPUs online including the boot CPU.
+pub fn start_secondary_cpus(dtb: &DtbParser) -> usize {
+    let boot_mpidr = cpu::get_mpidr_affinity();
+    let boot_mailbox = &MAILBOXES[BOOT_CPU_INDEX];
+    boot_mailbox.mpidr.store(boot_mpidr, Ordering::Relaxed);
+    boot_mailbox.online.store(true, Ordering::Relaxed);
+    NUM_MAILBOXES.store(BOOT_CPU_INDEX + 1, Ordering::Relaxed);
+    let mut cpus = Vec::new();
+    dtb.find_node_properties(
+        Some("cpu"),
+        None,
+        &["reg", "enable-method", "cpu-release-addr"],
+        &mut |values| {
+            let Some(mpidr) = values[0].and_then(read_be_cells) else {
+                return ControlFlow::Continue(());
+            };
+            let method = match values[1] {
+                Some(b"psci\0") => Some(EnableMethod::Psci),
+                Some(b"spin-table\0") => values[2]
+                    .and_then(read_be_cells)
+                    .map(|addr| EnableMethod::SpinTable(addr as usize)),
+                _ => None,
+            };
+            if mpidr & cpu::MPIDR_AFFINITY_MASK != boot_mpidr {
+                cpus.push((mpidr & cpu::MPIDR_AFFINITY_MASK, method));
+            }
+            ControlFlow::Continue(())
+        },
+    )
+    .unwrap();
+
+    psci_proxy::set_cpu_on_handler(guest_cpu_on);
+    psci_proxy::set_affinity_info_handler(guest_affinity_info);
+    let mut online = 1;
+    for (mpidr, method) in cpus {
+        let index = NUM_MAILBOXES.load(Ordering::Relaxed);
+        if index == MAX_CPUS {
+            println!("cpu {:#x}: too many CPUs, skipped", mpidr);
+            continue;
+        }
+        let Some(method) = method else {
+            println!("cpu {:#x}: unsupported enable-method", mpidr);
+            continue;
+        };
+        match start_secondary_cpu(index, mpidr, method) {
+            Startup::Online => {
+                NUM_MAILBOXES.store(index + 1, Ordering::Relaxed);
+                online += 1;
+            }
+            Startup::Failed => {}
+            Startup::TimedOut => {
+                // it may still come up and read SECONDARY_STACK_TOP and SECONDARY_INDEX,
+                // which would be the ones of the next CPU
+                println!("cpu {:#x}: timed out, no other CPU is started", mpidr);
+                break;
+            }
+        }
+    }
+    online
+}
+
+enum Startup {
+    Online,
+    // the CPU was not started
+    Failed,
+    // the CPU was started but did not report online
+    TimedOut,
+}
+
+fn start_secondary_cpu(index: usize, mpidr: u64, method: EnableMethod) -> Startup {
+    let stack = unsafe { alloc(Layout::from_size_align(SECONDARY_STACK_SIZE, 0x1000).unwrap()) };
+    if stack.is_null() {
+        println!("cpu {:#x}: failed to allocate the stack", mpidr);
+        return Startup::Failed;
+    }
+    let mailbox = &MAILBOXES[index];
+    mailbox.mpidr.store(mpidr, Ordering::Relaxed);
+    mailbox.online.store(false, Ordering::Relaxed);
+    mailbox.entry_point.store(0, Ordering::Relaxed);
+    mailbox.power_off.store(false, Ordering::Relaxed);
+    clean_mailbox(mailbox);
+    SECONDARY_STACK_TOP.store(stack as usize + SECONDARY_STACK_SIZE, Ordering::Relaxed);
+    SECONDARY_INDEX.store(index, Ordering::Release);
+    cache::clean_dcache_range(
+        &SECONDARY_STACK_TOP as *const _ as *const u8,
+        size_of::<usize>(),
+    );
+    cache::clean_dcache_range(
+        &SECONDARY_INDEX as *const _ as *const u8,
+        size_of::<usize>(),
+    );
+
+    let entry = secondary_entry as *const fn() as usize;
+    match method {
+        EnableMethod::Psci => {
+            if let Err(e) = psci::cpu_on(mpidr, entry, 0) {
+                println!("cpu {:#x}: PSCI CPU_ON failed: {:?}", mpidr, e);
+                return Startup::Failed;
+            }
+        }
+        EnableMethod::SpinTable(release_address) => {
+            unsafe { (release_address as *mut u64).write_volatile(entry as u64) };
+            cache::clean_dcache_range(release_address as *const u8, size_of::<u64>());
+            unsafe { asm!("sev") };
+        }
+    }
+
+    // handshake: the next CPU reuses SECONDARY_STACK_TOP, so wait until this one is online
+    for _ in 0..STARTUP_TIMEOUT_LOOPS {
+        cache::clean_invalidate_dcache_range(
+            mailbox as *const _ as *const u8,
+            size_of::<CpuMailbox>(),
+        );
+        if mailbox.online.load(Ordering::Acquire) {
+            println!("cpu {:#x} is online", mpidr);
+            return Startup::Online;
+        }
+        core::hint::spin_loop();
+    }
+    Startup::TimedOut
+}
+
+#[unsafe(naked)]
+extern "C" fn secondary_entry() -> ! {
+    naked_asm!(
+        "ldr x9, ={stack_top}",
+        "ldr x9, [x9]",
+        "mov sp, x9",
+        "ldr x9, ={index}",
+        "ldr x0, [x9]",
+        "b {secondary_main}",
+        stack_top = sym SECONDARY_STACK_TOP,
+        index = sym SECONDARY_INDEX,
+        secondary_main = sym secondary_main,
+    )
+}
+
+extern "C" fn secondary_main(index: usize) -> ! {
+    exception::init();
+    cpu::setup_hypervisor_registers();
+    idle::configure_traps(true, false);
+    fpsimd::init();
+    serror::init();
+    debug::configure_traps(crate::GUEST_DEBUG_POLICY, crate::GUEST_PMU_POLICY);
+    interrupt::init_cpu_interface().unwrap();
+
+    let mailbox = &MAILBOXES[index];
+    mailbox.online.store(true, Ordering::Release);
+    unsafe { asm!("dsb sy", "sev") };
+    run_vcpus(index)
+}
+
+/// Enter the guest on the boot CPU at `entry_point` with `context_id` in x0.
+/// The boot CPU is parked like the secondary CPUs when the guest turns it off.
+pub fn run_boot_cpu(entry_point: u64, context_id: u64) -> ! {
+    let mailbox = &MAILBOXES[BOOT_CPU_INDEX];
+    mailbox.context_id.store(context_id, Ordering::Relaxed);
+    mailbox
+        .counter_offset
+        .store(timer::guest_counter_offset(), Ordering::Relaxed);
+    mailbox.entry_point.store(entry_point, Ordering::Release);
+    run_vcpus(BOOT_CPU_INDEX)
+}
+
+// park until the guest starts this CPU, run it until it calls CPU_OFF, repeat
+fn run_vcpus(index: usize) -> ! {
+    let mailbox = &MAILBOXES[index];
+    loop {
+        let entry_point = park(mailbox);
+        let context_id = mailbox.context_id.load(Ordering::Relaxed);
+        timer::configure_guest_timer(
+            mailbox.counter_offset.load(Ordering::Relaxed),
+            crate::GUEST_PHYSICAL_TIMER_ACCESS,
+        );
+        Vcpu::new(entry_point, context_id).run_loop();
+        mailbox.entry_point.store(0, Ordering::Release);
+        clean_mailbox(mailbox);
+    }
+}
+
+fn park(mailbox: &CpuMailbox) -> u64 {
+    loop {
+        let barrier = mailbox.rendezvous.swap(ptr::null_mut(), Ordering::AcqRel);
+        if let Some(barrier) = unsafe { barrier.as_ref() } {
+            barrier.wait();
+        }
+        let entry_point = mailbox.entry_point.load(Ordering::Acquire);
+        if entry_point != 0 {
+            return entry_point;
+        }
+        if mailbox.power_off.load(Ordering::Acquire) {
+            power_off(mailbox);
+        }
+        unsafe { asm!("wfe") };
+    }
+}
+
+fn power_off(mailbox: &CpuMailbox) -> ! {
+    mailbox.online.store(false, Ordering::Release);
+    clean_mailbox(mailbox);
+    unsafe { asm!("dsb sy", "sev") };
+    if psci::is_available() {
+        let e = psci::cpu_off();
+        println!(
+            "cpu {:#x}: PSCI CPU_OFF failed: {:?}",
+            mailbox.mpidr.load(Ordering::Relaxed),
+            e
+        );
+    }
+    // spin-table CPUs can not be powered off, keep them quiesced
+    loop {
+        unsafe { asm!("wfe") };
+    }
+}
+
+/// Power off every parked secondary CPU before handing the whole machine to a bare-metal
+/// payload, which then starts them by itself. Returns the number of CPUs which went offline.
+pub fn power_off_secondary_cpus() -> usize {
+    let mut parked = Vec::new();
+    for mailbox in &MAILBOXES[BOOT_CPU_INDEX + 1..NUM_MAILBOXES.load(Ordering::Relaxed)] {
+        if mailbox.online.load(Ordering::Acquire)
+            && mailbox.entry_point.load(Ordering::Acquire) == 0
+        {
+            mailbox.power_off.store(true, Ordering::Release);
+            clean_mailbox(mailbox);
+            parked.push(mailbox);
+        }
+    }
+    unsafe { asm!("sev") };
+    parked
+        .into_iter()
+        .filter(|mailbox| {
+            (0..STARTUP_TIMEOUT_LOOPS).any(|_| {
+                cache::clean_invalidate_dcache_range(
+                    *mailbox as *const _ as *const u8,
+                    size_of::<CpuMailbox>(),
+                );
+                let offline = !mailbox.online.load(Ordering::Acquire);
+                core::hint::spin_loop();
+                offline
+            })
+        })
+        .count()
+}
+
+/// Meet every parked secondary CPU at a barrier before the guest is entered, so that none of
+/// them is still starting up when the guest boots them. The D-cache of the boot CPU must be
+/// off by now, like the ones of the secondary CPUs, so that all of them see the barrier in
+/// memory. Returns the number of CPUs which met, the boot CPU included
+pub fn rendezvous() -> usize {
+    let parked: Vec<&CpuMailbox> = MAILBOXES
+        [BOOT_CPU_INDEX + 1..NUM_MAILBOXES.load(Ordering::Relaxed)]
+        .iter()
+        .filter(|mailbox| {
+            mailbox.online.load(Ordering::Acquire)
+                && mailbox.entry_point.load(Ordering::Acquire) == 0
+        })
+        .collect();
+    let mut barrier = Box::<Barrier>::new_uninit();
+    // the heap may still hold lines from before the D-cache was turned off, drop them before
+    // the barrier is written to memory
+    cache::clean_invalidate_dcache_range(barrier.as_ptr() as *const u8, size_of::<Barrier>());
+    barrier.write(Barrier::new(parked.len() + 1));
+    let barrier: &'static Barrier = Box::leak(unsafe { barrier.assume_init() });
+    for mailbox in &parked {
+        mailbox
+            .rendezvous
+            .store(barrier as *const Barrier as *mut Barrier, Ordering::Release);
+        clean_mailbox(mailbox);
+    }
+    unsafe { asm!("dsb sy", "sev") };
+    barrier.wait();
+    parked.len() + 1
+}
+
+/// Limit the guest to the first `count` CPUs, the boot CPU included.
+/// The virtual PSCI CPU_ON of the others is denied
+pub fn set_guest_cpu_limit(count: usize) {
+    GUEST_CPU_LIMIT.store(count, Ordering::Relaxed);
+}
+
+/// MPIDR affinity of the CPUs the guest may start: the boot CPU and the first secondaries
+/// online, up to the `set_guest_cpu_limit` count
+pub fn guest_cpus() -> Vec<u64> {
+    let count = NUM_MAILBOXES
+        .load(Ordering::Relaxed)
+        .min(GUEST_CPU_LIMIT.load(Ordering::Relaxed));
+    MAILBOXES[..count]
+        .iter()
+        .map(|mailbox| mailbox.mpidr.load(Ordering::Relaxed))
+        .collect()
+}
+
+fn find_mailbox_index(mpidr: u64) -> Option<usize> {
+    MAILBOXES[..NUM_MAILBOXES.load(Ordering::Relaxed)]
+        .iter()
+        .position(|m| m.mpidr.load(Ordering::Relaxed) == mpidr)
+}
+
+fn find_mailbox(mpidr: u64) -> Option<&'static CpuMailbox> {
+    find_mailbox_index(mpidr).map(|index| &MAILBOXES[index])
+}
+
+/// virtual PSCI CPU_ON: release a parked CPU into the guest
+fn guest_cpu_on(target_mpidr: u64, entry_point: u64, context_id: u64) -> Result<(), PsciErr> {
+    let index = find_mailbox_index(target_mpidr).ok_or(PsciErr::InvalidParameters)?;
+    if index >= GUEST_CPU_LIMIT.load(Ordering::Relaxed) {
+        return Err(PsciErr::Denied);
+    }
+    let mailbox = &MAILBOXES[index];
+    if entry_point == 0 {
+        return Err(PsciErr::InvalidAddress);
+    }
+    if mailbox.entry_point.load(Ordering::Acquire) != 0 {
+        return Err(PsciErr::AlreadyOn);
+    }
+    mailbox.context_id.store(context_id, Ordering::Relaxed);
+    mailbox
+        .counter_offset
+        .store(timer::guest_counter_offset(), Ordering::Relaxed);
+    mailbox.entry_point.store(entry_point, Ordering::Release);
+    clean_mailbox(mailbox);
+    unsafe { asm!("sev") };
+    Ok(())
+}
+
+/// virtual PSCI AFFINITY_INFO: a vCPU is on from CPU_ON until it calls CPU_OFF
+fn guest_affinity_info(target_mpidr: u64) -> Result<AffinityState, PsciErr> {
+    let mailbox = find_mailbox(target_mpidr).ok_or(PsciErr::InvalidParameters)?;
+    if mailbox.entry_point.load(Ordering::Acquire) != 0 {
+        Ok(AffinityState::On)
+    } else {
+        Ok(AffinityState::Off)
+    }
+}
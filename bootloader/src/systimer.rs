@@ -1,9 +1,64 @@
 // system timer
+//
+// `ticks` and `uptime` are the time source of logs, profiling and timeouts.
+// Periodic callbacks are registered with `arch_hal::timer::add_periodic` once the GIC is set
+// up. The loader runs with IRQs masked, so `main` dispatches them from its wait loops.
 
 use crate::println;
-use core::arch::asm;
+use arch_hal::cpu;
+use arch_hal::timer;
 use core::num::NonZero;
 use core::num::NonZeroU64;
+use core::ops::ControlFlow;
+use core::time::Duration;
+use dtb::DtbParser;
+
+// architected timer nodes which may carry `clock-frequency`
+const TIMER_COMPATIBLE: [&str; 2] = ["arm,armv8-timer", "arm,armv7-timer"];
+
+/// physical count (CNTPCT_EL0)
+pub fn ticks() -> u64 {
+    timer::now()
+}
+
+/// time since the physical count started, normally since reset
+pub fn uptime() -> Duration {
+    timer::uptime()
+}
+
+/// Pick the counter frequency for time math: `configured`, else the `clock-frequency` of the
+/// dtb timer node, which firmware sets when CNTFRQ_EL0 is wrong, else CNTFRQ_EL0.
+/// A mismatch with CNTFRQ_EL0 is reported
+pub fn calibrate(dtb: &DtbParser, configured: Option<u64>) {
+    let counter = cpu::timer::counter_frequency();
+    let mut described = None;
+    for compatible in TIMER_COMPATIBLE {
+        let _ = dtb.find_property(None, Some(compatible), "clock-frequency", &mut |value| {
+            described = value.u32().map(u64::from);
+            ControlFlow::Break(())
+        });
+        if described.is_some() {
+            break;
+        }
+    }
+    if let Some(described) = described
+        && described != counter
+    {
+        println!(
+            "timer: CNTFRQ_EL0 is {}Hz, the dtb timer node says {}Hz",
+            counter, described
+        );
+    }
+    match configured.or(described) {
+        Some(frequency) if frequency != counter => {
+            println!("timer: using {}Hz", frequency);
+            cpu::timer::set_frequency(frequency);
+        }
+        Some(_) => {}
+        None if counter == 0 => println!("timer: CNTFRQ_EL0 is not programmed"),
+        None => {}
+    }
+}
 
 pub struct SystemTimer {
     counter_frequency: Option<NonZeroU64>,
@@ -19,35 +74,32 @@ impl SystemTimer {
         self.counter_frequency = Some(NonZero::new(Self::get_timer_frequency()).unwrap());
     }
     pub fn wait(&self, duration: core::time::Duration) {
-        let micros = duration.as_micros();
-        let start = Self::get_timer_counter();
-        let wait_time = u128::from(
-            self.counter_frequency
-                .expect("before calling wait function call init")
-                .get()
-                / 1000
-                / 1000,
-        ) * micros;
-        while u128::from(Self::get_timer_counter() - start) < wait_time {
-            core::hint::spin_loop();
-        }
+        cpu::timer::delay_us(duration.as_micros().try_into().unwrap_or(u64::MAX));
+    }
+    /// counter value `duration` from now
+    pub fn deadline(&self, duration: core::time::Duration) -> u64 {
+        let frequency = self.frequency();
+        let ticks = duration.as_nanos() * u128::from(frequency) / 1_000_000_000;
+        Self::get_timer_counter().saturating_add(ticks.try_into().unwrap_or(u64::MAX))
+    }
+    /// time left until `deadline`, zero once it has passed
+    pub fn remaining(&self, deadline: u64) -> core::time::Duration {
+        let ticks = deadline.saturating_sub(Self::get_timer_counter());
+        core::time::Duration::from_nanos(
+            (u128::from(ticks) * 1_000_000_000 / u128::from(self.frequency())) as u64,
+        )
+    }
+    fn frequency(&self) -> u64 {
+        self.counter_frequency
+            .expect("before calling timer functions call init")
+            .get()
     }
     fn get_timer_frequency() -> u64 {
-        let current_frequency;
-        unsafe {
-            asm!("mrs {current_frequency}, CNTFRQ_EL0", current_frequency = out(reg)current_frequency);
-        }
+        let current_frequency = cpu::timer::frequency();
         println!("system counter frequency: {}Hz", current_frequency);
         current_frequency
     }
     fn get_timer_counter() -> u64 {
-        let counter;
-        unsafe {
-            asm!("
-        isb
-        mrs {counter}, CNTPCT_EL0
-        ", counter = out(reg)counter)
-        };
-        counter
+        ticks()
     }
 }
@@ -0,0 +1,279 @@
+// entry as an `aarch64-unknown-uefi` application
+//
+// The firmware console prints until ExitBootServices. The dtb comes from the configuration
+// table and RAM from the UEFI memory map: only EfiConventionalMemory is handed to the
+// allocator, everything else (the loader image, runtime services, the dtb itself) stays
+// with the firmware. ACPI-only firmware is refused, the loader finds its devices in the dtb.
+
+use core::ffi::c_void;
+use core::fmt;
+use core::fmt::Write;
+use core::ptr;
+
+type Handle = *mut c_void;
+type Status = usize;
+
+const EFI_SUCCESS: Status = 0;
+const EFI_BUFFER_TOO_SMALL: Status = (1 << 63) | 5;
+const EFI_INVALID_PARAMETER: Status = (1 << 63) | 2;
+const EFI_UNSUPPORTED: Status = (1 << 63) | 3;
+const EFI_LOAD_ERROR: Status = (1 << 63) | 1;
+
+const EFI_CONVENTIONAL_MEMORY: u32 = 7;
+const EFI_PAGE_SIZE: usize = 0x1000;
+
+// the memory map is read into static storage, the heap does not exist yet
+const MEMORY_MAP_SIZE: usize = 0x4000;
+// conventional regions after merging adjacent descriptors
+const MAX_REGIONS: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Guid(u32, u16, u16, [u8; 8]);
+
+const DTB_TABLE_GUID: Guid = Guid(
+    0xb1b6_21d5,
+    0xf19c,
+    0x41a5,
+    [0x83, 0x0b, 0xd9, 0x15, 0x2c, 0x69, 0xaa, 0xe0],
+);
+const ACPI_20_TABLE_GUID: Guid = Guid(
+    0x8868_e871,
+    0xe4f1,
+    0x11d3,
+    [0xbc, 0x22, 0x00, 0x80, 0xc7, 0x3c, 0x88, 0x81],
+);
+
+#[repr(C)]
+struct TableHeader {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+struct SimpleTextOutput {
+    reset: usize,
+    output_string: extern "efiapi" fn(*mut SimpleTextOutput, *const u16) -> Status,
+}
+
+#[repr(C)]
+struct ConfigurationTable {
+    vendor_guid: Guid,
+    vendor_table: *const c_void,
+}
+
+#[repr(C)]
+struct SystemTable {
+    header: TableHeader,
+    firmware_vendor: *const u16,
+    firmware_revision: u32,
+    console_in_handle: Handle,
+    console_in: *mut c_void,
+    console_out_handle: Handle,
+    console_out: *mut SimpleTextOutput,
+    standard_error_handle: Handle,
+    standard_error: *mut SimpleTextOutput,
+    runtime_services: *mut c_void,
+    boot_services: *mut BootServices,
+    number_of_table_entries: usize,
+    configuration_table: *const ConfigurationTable,
+}
+
+#[repr(C)]
+struct MemoryDescriptor {
+    memory_type: u32,
+    physical_start: u64,
+    virtual_start: u64,
+    number_of_pages: u64,
+    attribute: u64,
+}
+
+// only the services the loader calls are typed, the others keep their slots
+#[repr(C)]
+struct BootServices {
+    header: TableHeader,
+    raise_tpl: usize,
+    restore_tpl: usize,
+    allocate_pages: usize,
+    free_pages: usize,
+    get_memory_map: extern "efiapi" fn(
+        *mut usize,
+        *mut MemoryDescriptor,
+        *mut usize,
+        *mut usize,
+        *mut u32,
+    ) -> Status,
+    allocate_pool: usize,
+    free_pool: usize,
+    create_event: usize,
+    set_timer: usize,
+    wait_for_event: usize,
+    signal_event: usize,
+    close_event: usize,
+    check_event: usize,
+    install_protocol_interface: usize,
+    reinstall_protocol_interface: usize,
+    uninstall_protocol_interface: usize,
+    handle_protocol: usize,
+    reserved: usize,
+    register_protocol_notify: usize,
+    locate_handle: usize,
+    locate_device_path: usize,
+    install_configuration_table: usize,
+    load_image: usize,
+    start_image: usize,
+    exit: usize,
+    unload_image: usize,
+    exit_boot_services: extern "efiapi" fn(Handle, usize) -> Status,
+    get_next_monotonic_count: usize,
+    stall: usize,
+    set_watchdog_timer: extern "efiapi" fn(usize, u64, usize, *const u16) -> Status,
+}
+
+// Boot Services console, UTF-16 through a small buffer with "\n" expanded to "\r\n"
+struct Console(*mut SimpleTextOutput);
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut buffer = [0u16; 64];
+        let mut len = 0;
+        for c in s.chars() {
+            if len + 4 >= buffer.len() {
+                self.flush(&mut buffer, &mut len)?;
+            }
+            if c == '\n' {
+                buffer[len] = u16::from(b'\r');
+                len += 1;
+            }
+            len += c.encode_utf16(&mut buffer[len..]).len();
+        }
+        self.flush(&mut buffer, &mut len)
+    }
+}
+
+impl Console {
+    fn flush(&mut self, buffer: &mut [u16], len: &mut usize) -> fmt::Result {
+        buffer[*len] = 0;
+        *len = 0;
+        match unsafe { ((*self.0).output_string)(self.0, buffer.as_ptr()) } {
+            EFI_SUCCESS => Ok(()),
+            _ => Err(fmt::Error),
+        }
+    }
+}
+
+static mut MEMORY_MAP: [u64; MEMORY_MAP_SIZE / 8] = [0; MEMORY_MAP_SIZE / 8];
+
+#[unsafe(no_mangle)]
+extern "efiapi" fn efi_main(image: Handle, system_table: *mut SystemTable) -> Status {
+    let system_table = unsafe { &*system_table };
+    let boot_services = unsafe { &*system_table.boot_services };
+    let mut console = Console(system_table.console_out);
+    let _ = writeln!(console, "elf-hypervisor: started as a UEFI application");
+    // the firmware resets the board 5 minutes into the application otherwise
+    (boot_services.set_watchdog_timer)(0, 0, 0, ptr::null());
+
+    let tables = unsafe {
+        core::slice::from_raw_parts(
+            system_table.configuration_table,
+            system_table.number_of_table_entries,
+        )
+    };
+    let find_table = |guid| {
+        tables
+            .iter()
+            .find(|table| table.vendor_guid == guid)
+            .map(|table| table.vendor_table as usize)
+    };
+    let Some(dtb_ptr) = find_table(DTB_TABLE_GUID) else {
+        if find_table(ACPI_20_TABLE_GUID).is_some() {
+            let _ = writeln!(console, "only ACPI tables are provided, a dtb is required");
+        } else {
+            let _ = writeln!(console, "no dtb in the configuration table");
+        }
+        return EFI_UNSUPPORTED;
+    };
+    let _ = writeln!(console, "dtb at {:#x}", dtb_ptr);
+
+    // the map key changes whenever the map does, so ExitBootServices may need a fresh map
+    let mut exited = false;
+    let mut map_size = 0;
+    let mut descriptor_size = 0;
+    for _ in 0..2 {
+        map_size = MEMORY_MAP_SIZE;
+        let mut map_key = 0;
+        let mut descriptor_version = 0;
+        let status = (boot_services.get_memory_map)(
+            &mut map_size,
+            (&raw mut MEMORY_MAP).cast(),
+            &mut map_key,
+            &mut descriptor_size,
+            &mut descriptor_version,
+        );
+        if status != EFI_SUCCESS {
+            let _ = writeln!(
+                console,
+                "GetMemoryMap failed: {:#x}{}",
+                status,
+                if status == EFI_BUFFER_TOO_SMALL {
+                    " (memory map too large)"
+                } else {
+                    ""
+                }
+            );
+            return EFI_LOAD_ERROR;
+        }
+        // no Boot Services call, printing included, may run between GetMemoryMap and this
+        match (boot_services.exit_boot_services)(image, map_key) {
+            EFI_SUCCESS => {
+                exited = true;
+                break;
+            }
+            EFI_INVALID_PARAMETER => continue,
+            status => return status,
+        }
+    }
+    // Boot Services, the console included, may already be gone after a failed exit
+    if !exited {
+        return EFI_LOAD_ERROR;
+    }
+
+    // efi_main does not return, so the regions can live on its stack
+    let mut regions = [(0, 0); MAX_REGIONS];
+    let count = unsafe { conventional_memory(map_size, descriptor_size, &mut regions) };
+    crate::boot(dtb_ptr, Some(&regions[..count]), None)
+}
+
+// EfiConventionalMemory regions of the map read into `MEMORY_MAP`, adjacent ones merged.
+// returns how many entries of `regions` were filled
+unsafe fn conventional_memory(
+    map_size: usize,
+    descriptor_size: usize,
+    regions: &mut [(usize, usize)],
+) -> usize {
+    let map = (&raw const MEMORY_MAP).cast::<u8>();
+    let mut count = 0;
+    for offset in (0..map_size).step_by(descriptor_size) {
+        let descriptor = unsafe { ptr::read_unaligned(map.add(offset).cast::<MemoryDescriptor>()) };
+        if descriptor.memory_type != EFI_CONVENTIONAL_MEMORY {
+            continue;
+        }
+        let address = descriptor.physical_start as usize;
+        let size = descriptor.number_of_pages as usize * EFI_PAGE_SIZE;
+        if let Some((last, last_size)) = regions[..count].last_mut()
+            && *last + *last_size == address
+        {
+            *last_size += size;
+        } else if count < regions.len() {
+            regions[count] = (address, size);
+            count += 1;
+        } else {
+            // the rest of RAM is left unused rather than failing the boot
+            break;
+        }
+    }
+    count
+}
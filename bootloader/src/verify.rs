@@ -0,0 +1,125 @@
+// kernel signature verification
+//
+// `<kernel path>.sig` holds the raw 64-byte ed25519 signature of the kernel file, and
+// `<initrd name>.sig` the one of an initrd fetched by the network boot.
+// The public key is embedded at build time from the hex string in `BOOT_PUBLIC_KEY`.
+// `BOOT_VERIFY_POLICY` (off, warn or enforce) is the lowest policy allowed, so
+// `verify=` in the config file on the same untrusted media can only make it stricter.
+
+use alloc::format;
+use arch_hal::println;
+use crypto::ed25519;
+use crypto::ed25519::PUBLIC_KEY_SIZE;
+use crypto::ed25519::SIGNATURE_SIZE;
+use file::OpenOptions;
+use file::StorageDevice;
+
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+const PUBLIC_KEY: Option<[u8; PUBLIC_KEY_SIZE]> = match option_env!("BOOT_PUBLIC_KEY") {
+    Some(hex) => Some(parse_key(hex)),
+    None => None,
+};
+
+// evaluated at compile time, a malformed key fails the build
+const fn parse_key(hex: &str) -> [u8; PUBLIC_KEY_SIZE] {
+    const fn nibble(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => panic!("BOOT_PUBLIC_KEY is not hex"),
+        }
+    }
+    let hex = hex.as_bytes();
+    assert!(
+        hex.len() == PUBLIC_KEY_SIZE * 2,
+        "BOOT_PUBLIC_KEY must be 32 bytes"
+    );
+    let mut key = [0; PUBLIC_KEY_SIZE];
+    let mut i = 0;
+    while i < PUBLIC_KEY_SIZE {
+        key[i] = (nibble(hex[2 * i]) << 4) | nibble(hex[2 * i + 1]);
+        i += 1;
+    }
+    key
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Policy {
+    /// do not check signatures
+    Off,
+    /// boot anyway and print why the kernel is not verified
+    #[default]
+    Warn,
+    /// refuse kernels without a valid signature
+    Enforce,
+}
+
+impl Policy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "warn" => Some(Self::Warn),
+            "enforce" => Some(Self::Enforce),
+            _ => None,
+        }
+    }
+
+    /// `configured` raised to the build time minimum
+    pub fn effective(configured: Option<Self>) -> Self {
+        let minimum = option_env!("BOOT_VERIFY_POLICY")
+            .and_then(Self::parse)
+            .unwrap_or(Self::Off);
+        configured.unwrap_or_default().max(minimum)
+    }
+}
+
+/// read the signature of the kernel at `kernel_path`
+pub fn load_signature(storage: &StorageDevice, kernel_path: &str) -> Option<[u8; SIGNATURE_SIZE]> {
+    let path = format!("{}{}", kernel_path, SIGNATURE_SUFFIX);
+    let data = storage
+        .open(0, &path, &OpenOptions::Read)
+        .ok()?
+        .read(1)
+        .ok()?;
+    data[..].try_into().ok()
+}
+
+/// Check `data` of `what`, e.g. `kernel`, against `signature` under `policy`.
+/// Returns whether it may be booted.
+pub fn check(
+    policy: Policy,
+    what: &str,
+    data: &[u8],
+    signature: Option<&[u8; SIGNATURE_SIZE]>,
+) -> bool {
+    if policy == Policy::Off {
+        return true;
+    }
+    let result = match (&PUBLIC_KEY, signature) {
+        (None, _) => Err("no public key is built in"),
+        (_, None) => Err("no valid signature file"),
+        (Some(key), Some(signature)) => {
+            if ed25519::verify(key, data, signature) {
+                Ok(())
+            } else {
+                Err("signature mismatch")
+            }
+        }
+    };
+    match result {
+        Ok(()) => {
+            println!("{} signature verified", what);
+            true
+        }
+        Err(reason) if policy == Policy::Enforce => {
+            println!("{} rejected: {}", what, reason);
+            false
+        }
+        Err(reason) => {
+            println!("warning: {} is not verified: {}", what, reason);
+            true
+        }
+    }
+}
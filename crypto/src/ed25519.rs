@@ -0,0 +1,371 @@
+// Ed25519 signature verification (RFC 8032)
+//
+// Field elements are 16 limbs of 16 bits in i64, as in TweetNaCl. Only public
+// data is handled here, so nothing needs to be constant time, but the ladder is
+// kept branch free anyway.
+
+use crate::sha512::Sha512;
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+
+type Fe = [i64; 16];
+// extended coordinates (X, Y, Z, T)
+type Point = [Fe; 4];
+
+const FE_ZERO: Fe = [0; 16];
+const FE_ONE: Fe = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+// -121665/121666
+const D: Fe = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070, 0xe898, 0x7779, 0x4079, 0x8cc7,
+    0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+// 2 * D
+const D2: Fe = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0, 0xd130, 0xeef3, 0x80f2, 0x198e,
+    0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+// base point
+const BASE_X: Fe = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c, 0xdc5c, 0xfdd6, 0xe231, 0xc0a4,
+    0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+const BASE_Y: Fe = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666,
+];
+// sqrt(-1)
+const SQRT_M1: Fe = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43, 0xd7a7, 0x3dfb, 0x0099, 0x2b4d,
+    0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+// group order, little-endian
+const L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// Check `signature` of `message` made with the private key of `public_key`.
+/// Non-canonical `S` and public keys which are not on the curve are rejected.
+pub fn verify(
+    public_key: &[u8; PUBLIC_KEY_SIZE],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_SIZE],
+) -> bool {
+    let (r, s) = signature.split_at(32);
+    if !is_canonical_scalar(s.try_into().unwrap()) {
+        return false;
+    }
+    let Some(mut minus_a) = unpack_negative(public_key) else {
+        return false;
+    };
+
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    let h = reduce(&hasher.finalize());
+
+    // R == [S]B - [h]A
+    let mut p = scalar_mult(&mut minus_a, &h);
+    let mut base = [BASE_X, BASE_Y, FE_ONE, mul(&BASE_X, &BASE_Y)];
+    let q = scalar_mult(&mut base, s.try_into().unwrap());
+    add(&mut p, &q);
+    pack(&p) == *r
+}
+
+fn is_canonical_scalar(s: &[u8; 32]) -> bool {
+    // s < L, compared from the most significant byte
+    for i in (0..32).rev() {
+        match (s[i] as i64).cmp(&L[i]) {
+            core::cmp::Ordering::Less => return true,
+            core::cmp::Ordering::Greater => return false,
+            core::cmp::Ordering::Equal => {}
+        }
+    }
+    false
+}
+
+fn carry(o: &mut Fe) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            // 2^256 = 38 mod p
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+// swap `p` and `q` if `b` is 1
+fn select(p: &mut Fe, q: &mut Fe, b: i64) {
+    let mask = !(b - 1);
+    for i in 0..16 {
+        let t = mask & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+    }
+}
+
+fn pack_fe(n: &Fe) -> [u8; 32] {
+    let mut t = *n;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+    // subtract p at most twice to get the canonical value
+    for _ in 0..2 {
+        let mut m = FE_ZERO;
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let borrow = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - borrow);
+    }
+    let mut o = [0; 32];
+    for i in 0..16 {
+        o[2 * i] = t[i] as u8;
+        o[2 * i + 1] = (t[i] >> 8) as u8;
+    }
+    o
+}
+
+fn unpack_fe(n: &[u8; 32]) -> Fe {
+    let mut o = FE_ZERO;
+    for i in 0..16 {
+        o[i] = n[2 * i] as i64 + ((n[2 * i + 1] as i64) << 8);
+    }
+    o[15] &= 0x7fff;
+    o
+}
+
+fn parity(a: &Fe) -> u8 {
+    pack_fe(a)[0] & 1
+}
+
+fn fe_add(a: &Fe, b: &Fe) -> Fe {
+    core::array::from_fn(|i| a[i] + b[i])
+}
+
+fn fe_sub(a: &Fe, b: &Fe) -> Fe {
+    core::array::from_fn(|i| a[i] - b[i])
+}
+
+fn mul(a: &Fe, b: &Fe) -> Fe {
+    let mut t = [0i64; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+    let mut o: Fe = t[..16].try_into().unwrap();
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn square(a: &Fe) -> Fe {
+    mul(a, a)
+}
+
+// a^(2^252 - 3), used for the square root
+fn pow2523(a: &Fe) -> Fe {
+    let mut c = *a;
+    for i in (0..=250).rev() {
+        c = square(&c);
+        if i != 1 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+// a^(p - 2)
+fn invert(a: &Fe) -> Fe {
+    let mut c = *a;
+    for i in (0..=253).rev() {
+        c = square(&c);
+        if i != 2 && i != 4 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+fn add(p: &mut Point, q: &Point) {
+    let a = mul(&fe_sub(&p[1], &p[0]), &fe_sub(&q[1], &q[0]));
+    let b = mul(&fe_add(&p[0], &p[1]), &fe_add(&q[0], &q[1]));
+    let c = mul(&mul(&p[3], &q[3]), &D2);
+    let d = mul(&p[2], &q[2]);
+    let d = fe_add(&d, &d);
+    let e = fe_sub(&b, &a);
+    let f = fe_sub(&d, &c);
+    let g = fe_add(&d, &c);
+    let h = fe_add(&b, &a);
+    p[0] = mul(&e, &f);
+    p[1] = mul(&h, &g);
+    p[2] = mul(&g, &f);
+    p[3] = mul(&e, &h);
+}
+
+fn swap(p: &mut Point, q: &mut Point, b: i64) {
+    for (p, q) in p.iter_mut().zip(q.iter_mut()) {
+        select(p, q, b);
+    }
+}
+
+fn pack(p: &Point) -> [u8; 32] {
+    let z = invert(&p[2]);
+    let x = mul(&p[0], &z);
+    let y = mul(&p[1], &z);
+    let mut r = pack_fe(&y);
+    r[31] ^= parity(&x) << 7;
+    r
+}
+
+// [s]q, `q` is clobbered
+fn scalar_mult(q: &mut Point, s: &[u8; 32]) -> Point {
+    let mut p = [FE_ZERO, FE_ONE, FE_ONE, FE_ZERO];
+    for i in (0..256).rev() {
+        let b = ((s[i / 8] >> (i & 7)) & 1) as i64;
+        swap(&mut p, q, b);
+        add(q, &p);
+        let p2 = p;
+        add(&mut p, &p2);
+        swap(&mut p, q, b);
+    }
+    p
+}
+
+// decode `p` and negate it, `None` if it is not a point on the curve
+fn unpack_negative(p: &[u8; 32]) -> Option<Point> {
+    let y = unpack_fe(p);
+    let num = square(&y);
+    let den = mul(&num, &D);
+    let num = fe_sub(&num, &FE_ONE);
+    let den = fe_add(&FE_ONE, &den);
+
+    // x = sqrt(num / den) = num * den^3 * (num * den^7)^((p - 5) / 8)
+    let den2 = square(&den);
+    let den4 = square(&den2);
+    let den6 = mul(&den4, &den2);
+    let mut t = mul(&mul(&den6, &num), &den);
+    t = pow2523(&t);
+    t = mul(&mul(&mul(&t, &num), &den), &den);
+    let mut x = mul(&t, &den);
+
+    let check = |x: &Fe| pack_fe(&mul(&square(x), &den)) == pack_fe(&num);
+    if !check(&x) {
+        x = mul(&x, &SQRT_M1);
+    }
+    if !check(&x) {
+        return None;
+    }
+    if parity(&x) == p[31] >> 7 {
+        x = fe_sub(&FE_ZERO, &x);
+    }
+    Some([x, y, FE_ONE, mul(&x, &y)])
+}
+
+// x mod L
+fn reduce(r: &[u8; 64]) -> [u8; 32] {
+    let mut x: [i64; 64] = core::array::from_fn(|i| r[i] as i64);
+    for i in (32..64).rev() {
+        let mut c = 0;
+        for j in (i - 32)..(i - 12) {
+            x[j] += c - 16 * x[i] * L[j - (i - 32)];
+            c = (x[j] + 128) >> 8;
+            x[j] -= c << 8;
+        }
+        x[i - 12] += c;
+        x[i] = 0;
+    }
+    let mut c = 0;
+    for j in 0..32 {
+        x[j] += c - (x[31] >> 4) * L[j];
+        c = x[j] >> 8;
+        x[j] &= 255;
+    }
+    for j in 0..32 {
+        x[j] -= c * L[j];
+    }
+    let mut o = [0; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        o[i] = x[i] as u8;
+    }
+    o
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex<const N: usize>(hex: &str) -> [u8; N] {
+        core::array::from_fn(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+    }
+
+    // RFC 8032 7.1 TEST 1-3
+    const VECTORS: [(&str, &[u8], &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            &[0x72],
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            &[0xaf, 0x82],
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn rfc8032_vectors() {
+        for (public_key, message, signature) in VECTORS {
+            assert!(verify(&from_hex(public_key), message, &from_hex(signature)));
+        }
+    }
+
+    #[test]
+    fn tampered_signature_rejected() {
+        let (public_key, message, signature) = VECTORS[2];
+        let public_key = from_hex(public_key);
+        let signature: [u8; SIGNATURE_SIZE] = from_hex(signature);
+        assert!(!verify(&public_key, &[0xaf, 0x83], &signature));
+        for byte in [0, 31, 32, 63] {
+            let mut bad = signature;
+            bad[byte] ^= 1;
+            assert!(!verify(&public_key, message, &bad));
+        }
+        let (other_key, _, _) = VECTORS[1];
+        assert!(!verify(&from_hex(other_key), message, &signature));
+    }
+
+    #[test]
+    fn non_canonical_s_rejected() {
+        let (public_key, message, signature) = VECTORS[0];
+        let mut signature: [u8; SIGNATURE_SIZE] = from_hex(signature);
+        // S + L verifies the same equation but is malleable
+        let mut c = 0;
+        for i in 0..32 {
+            let sum = signature[32 + i] as i64 + L[i] + c;
+            signature[32 + i] = sum as u8;
+            c = sum >> 8;
+        }
+        assert!(!verify(&from_hex(public_key), message, &signature));
+    }
+}
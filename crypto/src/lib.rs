@@ -0,0 +1,8 @@
+#![cfg_attr(not(test), no_std)]
+
+pub mod ed25519;
+pub mod sha256;
+pub mod sha512;
+
+pub use sha256::Sha256;
+pub use sha512::Sha512;
@@ -0,0 +1,86 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+// test fixtures: a sample file and its gzip/zstd compressed variants in OUT_DIR
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let sample = out_dir.join("sample.bin");
+    fs::write(&sample, sample_data()).unwrap();
+
+    let fixtures: [(&str, &str, &[&str]); 6] = [
+        ("gzip", "sample.gz", &["-9"]),
+        ("gzip", "sample_fast.gz", &["-1"]),
+        ("zstd", "sample.zst", &["-3"]),
+        ("zstd", "sample_19.zst", &["-19"]),
+        ("zstd", "sample_nocheck.zst", &["-1", "--no-check"]),
+        ("zstd", "sample_nosize.zst", &["-3", "--no-content-size"]),
+    ];
+    for (tool, name, args) in fixtures {
+        compress(tool, args, &sample, &out_dir.join(name));
+    }
+}
+
+fn compress(tool: &str, args: &[&str], input: &Path, output: &Path) {
+    // the tools are only needed by the tests, do not break the build without them
+    let result = Command::new(tool).args(args).arg("-c").arg(input).output();
+    match result {
+        Ok(o) if o.status.success() => fs::write(output, o.stdout).unwrap(),
+        Ok(o) => println!(
+            "cargo:warning={} failed (exit: {}), {} is not generated",
+            tool,
+            o.status,
+            output.display()
+        ),
+        Err(e) => println!(
+            "cargo:warning=failed to run {}: {}, {} is not generated",
+            tool,
+            e,
+            output.display()
+        ),
+    }
+}
+
+// text-like data with runs, around an incompressible middle part
+fn sample_data() -> Vec<u8> {
+    const WORDS: [&str; 12] = [
+        "kernel ",
+        "hypervisor ",
+        "bootloader ",
+        "aarch64 ",
+        "memory ",
+        "page ",
+        "table ",
+        "exception ",
+        "level ",
+        "\n",
+        "timer ",
+        "interrupt ",
+    ];
+    let mut seed: u32 = 0x1234_5678;
+    let mut next = move || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        seed >> 8
+    };
+    let mut data = Vec::new();
+    let text = |data: &mut Vec<u8>, len: usize, next: &mut dyn FnMut() -> u32| {
+        while data.len() < len {
+            let r = next();
+            match r % 32 {
+                0 => data.extend(core::iter::repeat_n(r as u8, (r % 300) as usize)),
+                1 => data.extend((0..(r % 64)).map(|_| next() as u8)),
+                _ => data.extend_from_slice(WORDS[(r as usize / 32) % WORDS.len()].as_bytes()),
+            }
+        }
+    };
+    text(&mut data, 100 * 1024, &mut next);
+    let random = data.len() + 70 * 1024;
+    while data.len() < random {
+        data.push(next() as u8);
+    }
+    text(&mut data, 300 * 1024, &mut next);
+    data
+}
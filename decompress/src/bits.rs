@@ -0,0 +1,134 @@
+// bit readers shared by the decoders
+
+use crate::DecompressErr;
+
+/// LSB-first reader (DEFLATE, FSE table descriptions)
+pub(crate) struct ForwardBits<'a> {
+    data: &'a [u8],
+    // next byte to load into `buffer`
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> ForwardBits<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        while self.count <= 56 && self.pos < self.data.len() {
+            self.buffer |= (self.data[self.pos] as u64) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+    }
+
+    /// next `n` (<= 32) bits without consuming them, zero past the end of the input
+    pub(crate) fn peek(&mut self, n: u32) -> u32 {
+        if self.count < n {
+            self.refill();
+        }
+        (self.buffer & ((1u64 << n) - 1)) as u32
+    }
+
+    pub(crate) fn consume(&mut self, n: u32) -> Result<(), DecompressErr> {
+        if self.count < n {
+            self.refill();
+            if self.count < n {
+                return Err(DecompressErr::Truncated);
+            }
+        }
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    pub(crate) fn bits(&mut self, n: u32) -> Result<u32, DecompressErr> {
+        let value = self.peek(n);
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    /// drop the bits up to the next byte boundary
+    pub(crate) fn align(&mut self) {
+        let drop = self.count % 8;
+        self.buffer >>= drop;
+        self.count -= drop;
+    }
+
+    /// number of whole bytes consumed so far, after `align`
+    pub(crate) fn byte_position(&self) -> usize {
+        self.pos - (self.count / 8) as usize
+    }
+
+    /// take `len` bytes at a byte boundary
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecompressErr> {
+        debug_assert_eq!(self.count % 8, 0);
+        let start = self.byte_position();
+        let bytes = self
+            .data
+            .get(start..start + len)
+            .ok_or(DecompressErr::Truncated)?;
+        self.pos = start + len;
+        self.buffer = 0;
+        self.count = 0;
+        Ok(bytes)
+    }
+}
+
+/// Reader of the zstd backward bitstreams. The stream is read from its last bit
+/// towards the first, starting below the highest set bit of the last byte.
+pub(crate) struct BackwardBits<'a> {
+    data: &'a [u8],
+    // bits below `offset` are still unread, it goes negative when reading past the start
+    offset: i64,
+}
+
+impl<'a> BackwardBits<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, DecompressErr> {
+        let last = *data.last().ok_or(DecompressErr::Corrupted)?;
+        if last == 0 {
+            return Err(DecompressErr::Corrupted);
+        }
+        // the highest set bit is padding
+        let padding = last.leading_zeros() as i64 + 1;
+        Ok(Self {
+            data,
+            offset: data.len() as i64 * 8 - padding,
+        })
+    }
+
+    /// read `n` (<= 56) bits, zero past the start of the stream
+    pub(crate) fn bits(&mut self, n: u32) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        self.offset -= n as i64;
+        let (start, n, shift) = if self.offset >= 0 {
+            (self.offset as usize, n, 0)
+        } else if self.offset + (n as i64) > 0 {
+            // the low bits are past the start
+            let missing = (-self.offset) as u32;
+            (0, n - missing, missing)
+        } else {
+            return 0;
+        };
+        let byte = start / 8;
+        let mut word = [0u8; 8];
+        let available = (self.data.len() - byte).min(8);
+        word[..available].copy_from_slice(&self.data[byte..byte + available]);
+        let value = (u64::from_le_bytes(word) >> (start % 8)) & ((1u64 << n) - 1);
+        value << shift
+    }
+
+    /// bits left to read, negative after reading past the start
+    pub(crate) fn remaining(&self) -> i64 {
+        self.offset
+    }
+}
@@ -0,0 +1,148 @@
+// gzip container (RFC 1952), a single member as in `Image.gz`
+
+use crate::DecompressErr;
+use crate::inflate::inflate;
+use crate::output::Output;
+
+const MAGIC: [u8; 2] = [0x1F, 0x8B];
+const METHOD_DEFLATE: u8 = 8;
+const HEADER_SIZE: usize = 10;
+const TRAILER_SIZE: usize = 8;
+
+const FLAG_HCRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const FLAG_RESERVED: u8 = 0b1110_0000;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+pub fn is_gzip(header: &[u8]) -> bool {
+    header.len() >= 3 && header[..2] == MAGIC && header[2] == METHOD_DEFLATE
+}
+
+/// ISIZE of the trailer, the size modulo 2^32
+pub fn decompressed_size(input: &[u8]) -> Result<usize, DecompressErr> {
+    if input.len() < HEADER_SIZE + TRAILER_SIZE {
+        return Err(DecompressErr::Truncated);
+    }
+    let size = &input[input.len() - 4..];
+    Ok(u32::from_le_bytes(size.try_into().unwrap()) as usize)
+}
+
+// returns the offset of the DEFLATE stream
+fn skip_header(input: &[u8]) -> Result<usize, DecompressErr> {
+    if input.len() < HEADER_SIZE {
+        return Err(DecompressErr::Truncated);
+    }
+    if !is_gzip(input) || input[3] & FLAG_RESERVED != 0 {
+        return Err(DecompressErr::Corrupted);
+    }
+    let flags = input[3];
+    let mut pos = HEADER_SIZE;
+    if flags & FLAG_EXTRA != 0 {
+        let len = input.get(pos..pos + 2).ok_or(DecompressErr::Truncated)?;
+        pos += 2 + u16::from_le_bytes(len.try_into().unwrap()) as usize;
+    }
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let len = input
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or(DecompressErr::Truncated)?;
+            pos += len + 1;
+        }
+    }
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+    if pos > input.len() {
+        return Err(DecompressErr::Truncated);
+    }
+    Ok(pos)
+}
+
+/// Decompress `input` into `output` and check CRC32 and ISIZE
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, DecompressErr> {
+    let start = skip_header(input)?;
+    let mut output = Output::new(output);
+    let len = inflate(&input[start..], &mut output)?;
+    let trailer = input
+        .get(start + len..start + len + TRAILER_SIZE)
+        .ok_or(DecompressErr::Truncated)?;
+    let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+    if crc != crc32(output.written()) || size != output.pos as u32 {
+        return Err(DecompressErr::ChecksumMismatch);
+    }
+    Ok(output.pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fixture;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn decompress_fixtures() {
+        let sample = fixture("sample.bin");
+        for name in ["sample.gz", "sample_fast.gz"] {
+            let input = fixture(name);
+            let mut output = vec![0; decompressed_size(&input).unwrap()];
+            assert_eq!(
+                decompress(&input, &mut output),
+                Ok(sample.len()),
+                "{}",
+                name
+            );
+            assert!(output == sample, "{} differs", name);
+        }
+    }
+
+    #[test]
+    fn corrupted_input_detected() {
+        let input = fixture("sample.gz");
+        let mut output = vec![0; decompressed_size(&input).unwrap()];
+        assert_eq!(
+            decompress(&input[..input.len() / 2], &mut output),
+            Err(DecompressErr::Truncated)
+        );
+        // the stored CRC32
+        let mut bad = input.clone();
+        let crc = bad.len() - TRAILER_SIZE;
+        bad[crc] ^= 1;
+        assert_eq!(
+            decompress(&bad, &mut output),
+            Err(DecompressErr::ChecksumMismatch)
+        );
+    }
+}
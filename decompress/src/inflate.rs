@@ -0,0 +1,237 @@
+// DEFLATE decoder (RFC 1951)
+
+use crate::DecompressErr;
+use crate::bits::ForwardBits;
+use crate::output::Output;
+
+const MAX_BITS: usize = 15;
+const MAX_LITERAL_LENGTH_CODES: usize = 288;
+const MAX_DISTANCE_CODES: usize = 30;
+// codes up to this length are decoded with one table lookup
+const FAST_BITS: u32 = 9;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// order of the code length code lengths in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const END_OF_BLOCK: u16 = 256;
+
+/// canonical Huffman code
+struct Huffman {
+    // number of codes of each length
+    counts: [u16; MAX_BITS + 1],
+    // symbols ordered by code
+    symbols: [u16; MAX_LITERAL_LENGTH_CODES],
+    // (symbol << 4) | length for codes up to FAST_BITS long indexed by the next bits, 0 if longer
+    fast: [u16; 1 << FAST_BITS],
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, DecompressErr> {
+        let mut huffman = Self {
+            counts: [0; MAX_BITS + 1],
+            symbols: [0; MAX_LITERAL_LENGTH_CODES],
+            fast: [0; 1 << FAST_BITS],
+        };
+        for &length in lengths {
+            huffman.counts[length as usize] += 1;
+        }
+        huffman.counts[0] = 0;
+        // over-subscribed codes are invalid, incomplete ones are allowed (a single distance code)
+        let mut left: i32 = 1;
+        for &count in &huffman.counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(DecompressErr::Corrupted);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        let mut next_code = [0u32; MAX_BITS + 1];
+        let mut code = 0;
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + huffman.counts[length];
+            code = (code + huffman.counts[length - 1] as u32) << 1;
+            next_code[length] = code;
+        }
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let length = length as usize;
+            huffman.symbols[offsets[length] as usize] = symbol as u16;
+            offsets[length] += 1;
+
+            let code = next_code[length];
+            next_code[length] += 1;
+            if length as u32 <= FAST_BITS {
+                // codes are stored from their most significant bit
+                let reversed = code.reverse_bits() >> (32 - length);
+                let mut index = reversed as usize;
+                while index < huffman.fast.len() {
+                    huffman.fast[index] = ((symbol as u16) << 4) | length as u16;
+                    index += 1 << length;
+                }
+            }
+        }
+        Ok(huffman)
+    }
+
+    fn decode(&self, bits: &mut ForwardBits) -> Result<u16, DecompressErr> {
+        let entry = self.fast[bits.peek(FAST_BITS) as usize];
+        if entry != 0 {
+            bits.consume((entry & 0xF) as u32)?;
+            return Ok(entry >> 4);
+        }
+        // walk the code bit by bit
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = count as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecompressErr::Corrupted)
+    }
+}
+
+/// Decompress the raw DEFLATE stream at the start of `input`.
+/// Returns the number of input bytes the stream occupies.
+pub(crate) fn inflate(input: &[u8], output: &mut Output) -> Result<usize, DecompressErr> {
+    let mut bits = ForwardBits::new(input);
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored(&mut bits, output)?,
+            1 => {
+                let (literal_length, distance) = fixed_codes()?;
+                codes(&mut bits, output, &literal_length, &distance)?;
+            }
+            2 => {
+                let (literal_length, distance) = dynamic_codes(&mut bits)?;
+                codes(&mut bits, output, &literal_length, &distance)?;
+            }
+            _ => return Err(DecompressErr::Corrupted),
+        }
+        if last {
+            bits.align();
+            return Ok(bits.byte_position());
+        }
+    }
+}
+
+fn stored(bits: &mut ForwardBits, output: &mut Output) -> Result<(), DecompressErr> {
+    bits.align();
+    let len = bits.bits(16)?;
+    if bits.bits(16)? != !len & 0xFFFF {
+        return Err(DecompressErr::Corrupted);
+    }
+    output.extend(bits.bytes(len as usize)?)
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), DecompressErr> {
+    let mut lengths = [0u8; MAX_LITERAL_LENGTH_CODES];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((
+        Huffman::new(&lengths)?,
+        Huffman::new(&[5; MAX_DISTANCE_CODES])?,
+    ))
+}
+
+fn dynamic_codes(bits: &mut ForwardBits) -> Result<(Huffman, Huffman), DecompressErr> {
+    let literal_length_codes = bits.bits(5)? as usize + 257;
+    let distance_codes = bits.bits(5)? as usize + 1;
+    let code_length_codes = bits.bits(4)? as usize + 4;
+    if literal_length_codes > 286 || distance_codes > MAX_DISTANCE_CODES {
+        return Err(DecompressErr::Corrupted);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_codes] {
+        code_lengths[index] = bits.bits(3)? as u8;
+    }
+    let code_length = Huffman::new(&code_lengths)?;
+
+    let mut lengths = [0u8; 286 + MAX_DISTANCE_CODES];
+    let total = literal_length_codes + distance_codes;
+    let mut i = 0;
+    while i < total {
+        let symbol = code_length.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i].last().ok_or(DecompressErr::Corrupted)?;
+                (previous, 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            18 => (0, 11 + bits.bits(7)? as usize),
+            _ => return Err(DecompressErr::Corrupted),
+        };
+        if i + repeat > total {
+            return Err(DecompressErr::Corrupted);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(DecompressErr::Corrupted);
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_length_codes])?,
+        Huffman::new(&lengths[literal_length_codes..total])?,
+    ))
+}
+
+fn codes(
+    bits: &mut ForwardBits,
+    output: &mut Output,
+    literal_length: &Huffman,
+    distance: &Huffman,
+) -> Result<(), DecompressErr> {
+    loop {
+        let symbol = literal_length.decode(bits)?;
+        match symbol {
+            0..=255 => output.push(symbol as u8)?,
+            END_OF_BLOCK => return Ok(()),
+            _ => {
+                let index = (symbol - 257) as usize;
+                if index >= LENGTH_BASE.len() {
+                    return Err(DecompressErr::Corrupted);
+                }
+                let len =
+                    LENGTH_BASE[index] as usize + bits.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distance.decode(bits)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(DecompressErr::Corrupted);
+                }
+                let dist = DISTANCE_BASE[index] as usize
+                    + bits.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                output.copy_match(dist, len)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod bits;
+pub mod gzip;
+mod inflate;
+mod output;
+mod xxhash;
+pub mod zstd;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressErr {
+    /// the input ends in the middle of the stream
+    Truncated,
+    /// malformed stream
+    Corrupted,
+    /// `output` is too small, it holds the first `output.len()` bytes of the result
+    OutputTooSmall,
+    ChecksumMismatch,
+    /// a valid stream using a feature which is not implemented (zstd dictionaries)
+    Unsupported,
+    /// the stream does not record the decompressed size
+    UnknownSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zstd,
+}
+
+impl Format {
+    /// number of bytes `detect` looks at
+    pub const MAGIC_SIZE: usize = 4;
+
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if gzip::is_gzip(header) {
+            Some(Self::Gzip)
+        } else if zstd::is_zstd(header) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// size of the data `input` decompresses to
+    pub fn decompressed_size(self, input: &[u8]) -> Result<usize, DecompressErr> {
+        match self {
+            Self::Gzip => gzip::decompressed_size(input),
+            Self::Zstd => zstd::decompressed_size(input),
+        }
+    }
+
+    /// Decompress `input` into `output` and return the number of bytes written
+    pub fn decompress(self, input: &[u8], output: &mut [u8]) -> Result<usize, DecompressErr> {
+        match self {
+            Self::Gzip => gzip::decompress(input, output),
+            Self::Zstd => zstd::decompress(input, output),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    pub(crate) fn fixture(name: &str) -> Vec<u8> {
+        let mut path = PathBuf::from(env!("OUT_DIR"));
+        path.push(name);
+        std::fs::read(&path).unwrap_or_else(|_| panic!("failed to load {}", path.display()))
+    }
+
+    #[test]
+    fn detect_and_decompress() {
+        let sample = fixture("sample.bin");
+        for (name, format) in [("sample.gz", Format::Gzip), ("sample.zst", Format::Zstd)] {
+            let input = fixture(name);
+            assert_eq!(Format::detect(&input), Some(format));
+            let size = format.decompressed_size(&input).unwrap();
+            assert_eq!(size, sample.len());
+            let mut output = vec![0; size];
+            assert_eq!(format.decompress(&input, &mut output), Ok(size));
+            assert!(output == sample, "{} differs", name);
+        }
+        assert_eq!(Format::detect(&sample), None);
+    }
+
+    #[test]
+    fn partial_output() {
+        let sample = fixture("sample.bin");
+        for name in ["sample.gz", "sample.zst"] {
+            let input = fixture(name);
+            let format = Format::detect(&input).unwrap();
+            let mut header = [0u8; 64];
+            assert_eq!(
+                format.decompress(&input, &mut header),
+                Err(DecompressErr::OutputTooSmall)
+            );
+            assert_eq!(header, sample[..64]);
+        }
+    }
+}
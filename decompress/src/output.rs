@@ -0,0 +1,70 @@
+// output buffer of the decoders, which is also the LZ77 history
+
+use crate::DecompressErr;
+
+pub(crate) struct Output<'a> {
+    buffer: &'a mut [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Output<'a> {
+    pub(crate) fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, pos: 0 }
+    }
+
+    pub(crate) fn written(&self) -> &[u8] {
+        &self.buffer[..self.pos]
+    }
+
+    pub(crate) fn push(&mut self, byte: u8) -> Result<(), DecompressErr> {
+        let slot = self
+            .buffer
+            .get_mut(self.pos)
+            .ok_or(DecompressErr::OutputTooSmall)?;
+        *slot = byte;
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// copy as much of `bytes` as fits
+    pub(crate) fn extend(&mut self, bytes: &[u8]) -> Result<(), DecompressErr> {
+        let len = bytes.len().min(self.buffer.len() - self.pos);
+        self.buffer[self.pos..self.pos + len].copy_from_slice(&bytes[..len]);
+        self.pos += len;
+        if len < bytes.len() {
+            return Err(DecompressErr::OutputTooSmall);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn fill(&mut self, byte: u8, len: usize) -> Result<(), DecompressErr> {
+        let fit = len.min(self.buffer.len() - self.pos);
+        self.buffer[self.pos..self.pos + fit].fill(byte);
+        self.pos += fit;
+        if fit < len {
+            return Err(DecompressErr::OutputTooSmall);
+        }
+        Ok(())
+    }
+
+    /// copy `len` bytes from `distance` bytes back, the ranges may overlap
+    pub(crate) fn copy_match(&mut self, distance: usize, len: usize) -> Result<(), DecompressErr> {
+        if distance == 0 || distance > self.pos {
+            return Err(DecompressErr::Corrupted);
+        }
+        let fit = len.min(self.buffer.len() - self.pos);
+        let start = self.pos - distance;
+        if distance >= fit {
+            self.buffer.copy_within(start..start + fit, self.pos);
+        } else {
+            for i in 0..fit {
+                self.buffer[self.pos + i] = self.buffer[start + i];
+            }
+        }
+        self.pos += fit;
+        if fit < len {
+            return Err(DecompressErr::OutputTooSmall);
+        }
+        Ok(())
+    }
+}
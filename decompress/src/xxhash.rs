@@ -0,0 +1,85 @@
+// XXH64, the zstd content checksum is its low 32 bits
+
+const PRIME1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME1)
+}
+
+fn merge(acc: u64, value: u64) -> u64 {
+    (acc ^ round(0, value))
+        .wrapping_mul(PRIME1)
+        .wrapping_add(PRIME4)
+}
+
+pub(crate) fn xxh64(data: &[u8], seed: u64) -> u64 {
+    let (stripes, rest) = data.as_chunks::<32>();
+    let mut hash = if data.len() >= 32 {
+        let mut v = [
+            seed.wrapping_add(PRIME1).wrapping_add(PRIME2),
+            seed.wrapping_add(PRIME2),
+            seed,
+            seed.wrapping_sub(PRIME1),
+        ];
+        for stripe in stripes {
+            for (v, lane) in v.iter_mut().zip(stripe.as_chunks::<8>().0) {
+                *v = round(*v, u64::from_le_bytes(*lane));
+            }
+        }
+        let mut hash = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        for v in v {
+            hash = merge(hash, v);
+        }
+        hash
+    } else {
+        seed.wrapping_add(PRIME5)
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    let (lanes, mut rest) = rest.as_chunks::<8>();
+    for lane in lanes {
+        hash ^= round(0, u64::from_le_bytes(*lane));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(PRIME1)
+            .wrapping_add(PRIME4);
+    }
+    if let Some((word, tail)) = rest.split_first_chunk::<4>() {
+        hash ^= (u32::from_le_bytes(*word) as u64).wrapping_mul(PRIME1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(PRIME2)
+            .wrapping_add(PRIME3);
+        rest = tail;
+    }
+    for &byte in rest {
+        hash ^= (byte as u64).wrapping_mul(PRIME5);
+        hash = hash.rotate_left(11).wrapping_mul(PRIME1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME3);
+    hash ^ (hash >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(xxh64(b"", 0), 0xEF46_DB37_51D8_E999);
+    }
+}
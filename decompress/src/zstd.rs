@@ -0,0 +1,821 @@
+// Zstandard decoder (RFC 8878), frames without dictionaries
+
+use crate::DecompressErr;
+use crate::bits::BackwardBits;
+use crate::bits::ForwardBits;
+use crate::output::Output;
+use crate::xxhash::xxh64;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const MAGIC: u32 = 0xFD2F_B528;
+// skippable frames use 0x184D2A50..=0x184D2A5F
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A50;
+const SKIPPABLE_MAGIC_MASK: u32 = 0xFFFF_FFF0;
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+const BLOCK_HEADER_SIZE: usize = 3;
+const CHECKSUM_SIZE: usize = 4;
+
+const MAX_ACCURACY_LOG: u32 = 9;
+const MAX_HUFFMAN_BITS: u32 = 11;
+const MAX_WEIGHT_ACCURACY_LOG: u32 = 6;
+
+const LITERAL_LENGTH_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64,
+    128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LITERAL_LENGTH_BITS: [u8; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+const MATCH_LENGTH_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
+    28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027,
+    2051, 4099, 8195, 16387, 32771, 65539,
+];
+const MATCH_LENGTH_BITS: [u8; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+const MAX_OFFSET_CODE: usize = 31;
+
+// predefined distributions with their accuracy logs
+const LITERAL_LENGTH_DEFAULT: ([i16; 36], u32) = (
+    [
+        4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1,
+        1, 1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const MATCH_LENGTH_DEFAULT: ([i16; 53], u32) = (
+    [
+        1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    6,
+);
+const OFFSET_DEFAULT: ([i16; 29], u32) = (
+    [
+        1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+    ],
+    5,
+);
+
+pub fn is_zstd(header: &[u8]) -> bool {
+    header.len() >= 4 && u32::from_le_bytes(header[..4].try_into().unwrap()) == MAGIC
+}
+
+fn floor_log2(value: u32) -> u32 {
+    31 - value.leading_zeros()
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| (value << 8) | byte as u64)
+}
+
+fn get(input: &[u8], start: usize, len: usize) -> Result<&[u8], DecompressErr> {
+    input
+        .get(start..start + len)
+        .ok_or(DecompressErr::Truncated)
+}
+
+struct FrameHeader {
+    content_size: Option<u64>,
+    checksum: bool,
+    // including the magic number
+    size: usize,
+}
+
+impl FrameHeader {
+    fn parse(input: &[u8]) -> Result<Self, DecompressErr> {
+        let descriptor = *input.get(4).ok_or(DecompressErr::Truncated)?;
+        if descriptor & 0x08 != 0 {
+            return Err(DecompressErr::Corrupted);
+        }
+        let single_segment = descriptor & 0x20 != 0;
+        let mut pos = 5;
+        if !single_segment {
+            // window descriptor, the whole output is the window here
+            pos += 1;
+        }
+        let dictionary_id_size = [0, 1, 2, 4][(descriptor & 0b11) as usize];
+        if read_le(get(input, pos, dictionary_id_size)?) != 0 {
+            return Err(DecompressErr::Unsupported);
+        }
+        pos += dictionary_id_size;
+        let content_size_size = match descriptor >> 6 {
+            0 if single_segment => 1,
+            0 => 0,
+            1 => 2,
+            2 => 4,
+            _ => 8,
+        };
+        let content_size = match content_size_size {
+            0 => None,
+            2 => Some(read_le(get(input, pos, 2)?) + 256),
+            size => Some(read_le(get(input, pos, size)?)),
+        };
+        Ok(Self {
+            content_size,
+            checksum: descriptor & 0x04 != 0,
+            size: pos + content_size_size,
+        })
+    }
+}
+
+struct BlockHeader {
+    last: bool,
+    block_type: u8,
+    size: usize,
+}
+
+impl BlockHeader {
+    fn parse(input: &[u8], pos: usize) -> Result<Self, DecompressErr> {
+        let header = read_le(get(input, pos, BLOCK_HEADER_SIZE)?) as u32;
+        Ok(Self {
+            last: header & 1 != 0,
+            block_type: ((header >> 1) & 0b11) as u8,
+            size: (header >> 3) as usize,
+        })
+    }
+
+    // bytes of the block content
+    fn content_size(&self) -> Result<usize, DecompressErr> {
+        match self.block_type {
+            0 => Ok(self.size),
+            1 => Ok(1),
+            2 if self.size <= MAX_BLOCK_SIZE => Ok(self.size),
+            _ => Err(DecompressErr::Corrupted),
+        }
+    }
+}
+
+// returns the frame size in the input if it is a skippable frame
+fn skippable_frame_size(input: &[u8]) -> Result<Option<usize>, DecompressErr> {
+    let magic = read_le(get(input, 0, 4)?) as u32;
+    if magic & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC {
+        let size = read_le(get(input, 4, 4)?) as usize;
+        return Ok(Some(8 + size));
+    }
+    if magic != MAGIC {
+        return Err(DecompressErr::Corrupted);
+    }
+    Ok(None)
+}
+
+/// Sum of the content sizes of the frames. The zstd command records them unless told otherwise.
+pub fn decompressed_size(input: &[u8]) -> Result<usize, DecompressErr> {
+    let mut pos = 0;
+    let mut total = 0;
+    while pos < input.len() {
+        if let Some(size) = skippable_frame_size(&input[pos..])? {
+            pos += size;
+            continue;
+        }
+        let header = FrameHeader::parse(&input[pos..])?;
+        total += header.content_size.ok_or(DecompressErr::UnknownSize)? as usize;
+        pos += header.size;
+        loop {
+            let block = BlockHeader::parse(input, pos)?;
+            pos += BLOCK_HEADER_SIZE + block.content_size()?;
+            if block.last {
+                break;
+            }
+        }
+        if header.checksum {
+            pos += CHECKSUM_SIZE;
+        }
+    }
+    if pos > input.len() {
+        return Err(DecompressErr::Truncated);
+    }
+    Ok(total)
+}
+
+/// Decompress all frames of `input` into `output`
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, DecompressErr> {
+    let mut output = Output::new(output);
+    let mut pos = 0;
+    while pos < input.len() {
+        match skippable_frame_size(&input[pos..])? {
+            Some(size) => pos += size,
+            None => pos += decode_frame(&input[pos..], &mut output)?,
+        }
+    }
+    if pos > input.len() {
+        return Err(DecompressErr::Truncated);
+    }
+    Ok(output.pos)
+}
+
+// returns the number of input bytes of the frame
+fn decode_frame(input: &[u8], output: &mut Output) -> Result<usize, DecompressErr> {
+    let header = FrameHeader::parse(input)?;
+    let start = output.pos;
+    let mut state = State::new();
+    let mut pos = header.size;
+    loop {
+        let block = BlockHeader::parse(input, pos)?;
+        pos += BLOCK_HEADER_SIZE;
+        let content = get(input, pos, block.content_size()?)?;
+        match block.block_type {
+            0 => output.extend(content)?,
+            1 => output.fill(content[0], block.size)?,
+            _ => state.decode_block(content, output)?,
+        }
+        pos += content.len();
+        if block.last {
+            break;
+        }
+    }
+    if header.checksum {
+        let expected = read_le(get(input, pos, CHECKSUM_SIZE)?) as u32;
+        if xxh64(&output.written()[start..], 0) as u32 != expected {
+            return Err(DecompressErr::ChecksumMismatch);
+        }
+        pos += CHECKSUM_SIZE;
+    }
+    if header
+        .content_size
+        .is_some_and(|size| size != (output.pos - start) as u64)
+    {
+        return Err(DecompressErr::Corrupted);
+    }
+    Ok(pos)
+}
+
+#[derive(Clone, Copy, Default)]
+struct FseEntry {
+    symbol: u8,
+    bits: u8,
+    base: u16,
+}
+
+/// finite state entropy decoding table
+#[derive(Clone)]
+struct FseTable {
+    accuracy_log: u32,
+    entries: [FseEntry; 1 << MAX_ACCURACY_LOG],
+}
+
+impl FseTable {
+    fn new(counts: &[i16], accuracy_log: u32) -> Result<Self, DecompressErr> {
+        let size = 1usize << accuracy_log;
+        let mut table = Self {
+            accuracy_log,
+            entries: [FseEntry::default(); 1 << MAX_ACCURACY_LOG],
+        };
+        let mut next_state = [0u16; 256];
+        // "less than 1" probabilities take the last cells
+        let mut high = size;
+        for (symbol, &count) in counts.iter().enumerate() {
+            if count == -1 {
+                high -= 1;
+                table.entries[high].symbol = symbol as u8;
+                next_state[symbol] = 1;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut pos = 0;
+        for (symbol, &count) in counts.iter().enumerate() {
+            if count <= 0 {
+                continue;
+            }
+            for _ in 0..count {
+                table.entries[pos].symbol = symbol as u8;
+                loop {
+                    pos = (pos + step) & (size - 1);
+                    if pos < high {
+                        break;
+                    }
+                }
+            }
+            next_state[symbol] = count as u16;
+        }
+        if pos != 0 {
+            return Err(DecompressErr::Corrupted);
+        }
+        for entry in &mut table.entries[..size] {
+            let state = next_state[entry.symbol as usize];
+            next_state[entry.symbol as usize] += 1;
+            let bits = accuracy_log - floor_log2(state as u32);
+            entry.bits = bits as u8;
+            entry.base = ((state as u32) << bits) as u16 - size as u16;
+        }
+        Ok(table)
+    }
+
+    fn rle(symbol: u8) -> Self {
+        let mut table = Self {
+            accuracy_log: 0,
+            entries: [FseEntry::default(); 1 << MAX_ACCURACY_LOG],
+        };
+        table.entries[0].symbol = symbol;
+        table
+    }
+
+    /// read a table description, returns the table and the size of the description
+    fn read(
+        input: &[u8],
+        max_accuracy_log: u32,
+        max_symbol: usize,
+    ) -> Result<(Self, usize), DecompressErr> {
+        let mut bits = ForwardBits::new(input);
+        let accuracy_log = bits.bits(4)? + 5;
+        if accuracy_log > max_accuracy_log {
+            return Err(DecompressErr::Corrupted);
+        }
+        let mut counts = [0i16; 256];
+        let mut remaining: i32 = 1 << accuracy_log;
+        let mut symbol = 0;
+        while remaining > 0 {
+            if symbol > max_symbol {
+                return Err(DecompressErr::Corrupted);
+            }
+            // values below `threshold` take one bit less
+            let n = floor_log2((remaining + 1) as u32) + 1;
+            let value = bits.peek(n);
+            let lower_mask = (1 << (n - 1)) - 1;
+            let threshold = (1 << n) - 1 - (remaining as u32 + 1);
+            let value = if value & lower_mask < threshold {
+                bits.consume(n - 1)?;
+                value & lower_mask
+            } else {
+                bits.consume(n)?;
+                if value > lower_mask {
+                    value - threshold
+                } else {
+                    value
+                }
+            };
+            let count = value as i32 - 1;
+            remaining -= count.abs();
+            counts[symbol] = count as i16;
+            symbol += 1;
+            if count == 0 {
+                // the following zero counts are run length coded
+                loop {
+                    let repeat = bits.bits(2)? as usize;
+                    symbol += repeat;
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+        }
+        if remaining != 0 || symbol > max_symbol + 1 {
+            return Err(DecompressErr::Corrupted);
+        }
+        bits.align();
+        Ok((
+            Self::new(&counts[..symbol], accuracy_log)?,
+            bits.byte_position(),
+        ))
+    }
+
+    fn init(&self, bits: &mut BackwardBits) -> usize {
+        bits.bits(self.accuracy_log) as usize
+    }
+
+    fn symbol(&self, state: usize) -> u8 {
+        self.entries[state].symbol
+    }
+
+    fn update(&self, state: usize, bits: &mut BackwardBits) -> usize {
+        let entry = self.entries[state];
+        entry.base as usize + bits.bits(entry.bits as u32) as usize
+    }
+}
+
+#[derive(Clone)]
+struct HuffmanTable {
+    max_bits: u32,
+    // (symbol, code length) indexed by the next `max_bits` bits
+    entries: [(u8, u8); 1 << MAX_HUFFMAN_BITS],
+}
+
+impl HuffmanTable {
+    /// read a tree description, returns the table and the size of the description
+    fn read(input: &[u8]) -> Result<(Self, usize), DecompressErr> {
+        let header = *input.first().ok_or(DecompressErr::Truncated)? as usize;
+        let mut weights = [0u8; 256];
+        let (count, size) = if header < 128 {
+            // FSE compressed weights
+            let stream = get(input, 1, header)?;
+            (Self::decode_weights(stream, &mut weights)?, 1 + header)
+        } else {
+            // 4 bit weights
+            let count = header - 127;
+            let bytes = get(input, 1, count.div_ceil(2))?;
+            for (i, weight) in weights[..count].iter_mut().enumerate() {
+                let byte = bytes[i / 2];
+                *weight = if i % 2 == 0 { byte >> 4 } else { byte & 0xF };
+            }
+            (count, 1 + bytes.len())
+        };
+        Ok((Self::new(&mut weights, count)?, size))
+    }
+
+    fn decode_weights(stream: &[u8], weights: &mut [u8; 256]) -> Result<usize, DecompressErr> {
+        let (table, used) = FseTable::read(stream, MAX_WEIGHT_ACCURACY_LOG, 255)?;
+        let mut bits = BackwardBits::new(&stream[used..])?;
+        // two interleaved states
+        let mut states = [table.init(&mut bits), table.init(&mut bits)];
+        let mut count = 0;
+        loop {
+            for i in 0..2 {
+                if count >= 254 {
+                    return Err(DecompressErr::Corrupted);
+                }
+                weights[count] = table.symbol(states[i]);
+                count += 1;
+                states[i] = table.update(states[i], &mut bits);
+                if bits.remaining() < 0 {
+                    weights[count] = table.symbol(states[1 - i]);
+                    return Ok(count + 1);
+                }
+            }
+        }
+    }
+
+    // `count` weights are given, the weight of the last symbol is implied
+    fn new(weights: &mut [u8; 256], count: usize) -> Result<Self, DecompressErr> {
+        if count > 255 {
+            return Err(DecompressErr::Corrupted);
+        }
+        let mut sum = 0u32;
+        for &weight in &weights[..count] {
+            if weight as u32 > MAX_HUFFMAN_BITS {
+                return Err(DecompressErr::Corrupted);
+            }
+            if weight > 0 {
+                sum += 1 << (weight - 1);
+            }
+        }
+        if sum == 0 {
+            return Err(DecompressErr::Corrupted);
+        }
+        let max_bits = floor_log2(sum) + 1;
+        let left = (1 << max_bits) - sum;
+        if max_bits > MAX_HUFFMAN_BITS || !left.is_power_of_two() {
+            return Err(DecompressErr::Corrupted);
+        }
+        weights[count] = (floor_log2(left) + 1) as u8;
+        let weights = &weights[..count + 1];
+
+        let mut table = Self {
+            max_bits,
+            entries: [(0, 0); 1 << MAX_HUFFMAN_BITS],
+        };
+        let code_bits = |weight: u8| {
+            if weight == 0 {
+                0
+            } else {
+                max_bits + 1 - weight as u32
+            }
+        };
+        let mut rank_count = [0usize; MAX_HUFFMAN_BITS as usize + 2];
+        for &weight in weights {
+            rank_count[code_bits(weight) as usize] += 1;
+        }
+        // longer codes come first in the table
+        let mut rank_index = [0usize; MAX_HUFFMAN_BITS as usize + 2];
+        for bits in (1..=max_bits as usize).rev() {
+            rank_index[bits - 1] =
+                rank_index[bits] + rank_count[bits] * (1 << (max_bits as usize - bits));
+        }
+        for (symbol, &weight) in weights.iter().enumerate() {
+            let bits = code_bits(weight) as usize;
+            if bits == 0 {
+                continue;
+            }
+            let len = 1 << (max_bits as usize - bits);
+            table.entries[rank_index[bits]..rank_index[bits] + len]
+                .fill((symbol as u8, bits as u8));
+            rank_index[bits] += len;
+        }
+        Ok(table)
+    }
+
+    fn decode_stream(&self, stream: &[u8], output: &mut [u8]) -> Result<(), DecompressErr> {
+        let mut bits = BackwardBits::new(stream)?;
+        let mask = (1 << self.max_bits) - 1;
+        let mut state = bits.bits(self.max_bits) as usize;
+        for byte in output {
+            let (symbol, len) = self.entries[state];
+            *byte = symbol;
+            state = ((state << len) | bits.bits(len as u32) as usize) & mask;
+        }
+        // the last state reads `max_bits` past the start
+        if bits.remaining() != -(self.max_bits as i64) {
+            return Err(DecompressErr::Corrupted);
+        }
+        Ok(())
+    }
+}
+
+// decoding state carried between the blocks of a frame
+struct State {
+    huffman: Option<HuffmanTable>,
+    literal_length: Option<FseTable>,
+    offset: Option<FseTable>,
+    match_length: Option<FseTable>,
+    repeat_offsets: [usize; 3],
+    literals: Vec<u8>,
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            huffman: None,
+            literal_length: None,
+            offset: None,
+            match_length: None,
+            repeat_offsets: [1, 4, 8],
+            literals: vec![0; MAX_BLOCK_SIZE],
+        }
+    }
+
+    fn decode_block(&mut self, block: &[u8], output: &mut Output) -> Result<(), DecompressErr> {
+        let (size, literals) = self.decode_literals(block)?;
+        self.decode_sequences(&block[size..], literals, output)
+    }
+
+    // returns the size of the literals section and the number of literals
+    fn decode_literals(&mut self, block: &[u8]) -> Result<(usize, usize), DecompressErr> {
+        let first = *block.first().ok_or(DecompressErr::Truncated)?;
+        let literals_type = first & 0b11;
+        let size_format = (first >> 2) & 0b11;
+        if literals_type < 2 {
+            // raw or RLE
+            let (count, header_size) = match size_format {
+                0 | 2 => ((first >> 3) as usize, 1),
+                1 => ((read_le(get(block, 0, 2)?) >> 4) as usize, 2),
+                _ => ((read_le(get(block, 0, 3)?) >> 4) as usize, 3),
+            };
+            if count > MAX_BLOCK_SIZE {
+                return Err(DecompressErr::Corrupted);
+            }
+            return if literals_type == 0 {
+                self.literals[..count].copy_from_slice(get(block, header_size, count)?);
+                Ok((header_size + count, count))
+            } else {
+                let byte = get(block, header_size, 1)?[0];
+                self.literals[..count].fill(byte);
+                Ok((header_size + 1, count))
+            };
+        }
+
+        // Huffman coded, with a new tree or the one of the previous block
+        let (streams, header_size, field_bits) = match size_format {
+            0 => (1, 3, 10),
+            1 => (4, 3, 10),
+            2 => (4, 4, 14),
+            _ => (4, 5, 18),
+        };
+        let header = read_le(get(block, 0, header_size)?);
+        let mask = (1 << field_bits) - 1;
+        let count = ((header >> 4) & mask) as usize;
+        let compressed = ((header >> (4 + field_bits)) & mask) as usize;
+        if count > MAX_BLOCK_SIZE {
+            return Err(DecompressErr::Corrupted);
+        }
+        let mut data = get(block, header_size, compressed)?;
+        if literals_type == 2 {
+            let (table, used) = HuffmanTable::read(data)?;
+            self.huffman = Some(table);
+            data = &data[used..];
+        }
+        let huffman = self.huffman.as_ref().ok_or(DecompressErr::Corrupted)?;
+        let literals = &mut self.literals[..count];
+        if streams == 1 {
+            huffman.decode_stream(data, literals)?;
+        } else {
+            // jump table of the first three stream sizes
+            let jump = get(data, 0, 6)?;
+            let sizes = [0, 2, 4].map(|i| read_le(&jump[i..i + 2]) as usize);
+            let mut rest = &data[6..];
+            let segment = count.div_ceil(4);
+            if segment == 0 || segment * 3 > count {
+                return Err(DecompressErr::Corrupted);
+            }
+            let mut outputs = literals.chunks_mut(segment);
+            for size in sizes {
+                let stream = rest.get(..size).ok_or(DecompressErr::Truncated)?;
+                huffman.decode_stream(stream, outputs.next().unwrap())?;
+                rest = &rest[size..];
+            }
+            huffman.decode_stream(rest, outputs.next().unwrap_or(&mut []))?;
+        }
+        Ok((header_size + compressed, count))
+    }
+
+    fn decode_sequences(
+        &mut self,
+        section: &[u8],
+        literals: usize,
+        output: &mut Output,
+    ) -> Result<(), DecompressErr> {
+        let first = *section.first().ok_or(DecompressErr::Truncated)? as usize;
+        let (count, mut pos) = match first {
+            0..128 => (first, 1),
+            128..255 => (((first - 128) << 8) + get(section, 1, 1)?[0] as usize, 2),
+            _ => (read_le(get(section, 1, 2)?) as usize + 0x7F00, 3),
+        };
+        if count == 0 {
+            return output.extend(&self.literals[..literals]);
+        }
+
+        let modes = get(section, pos, 1)?[0];
+        pos += 1;
+        if modes & 0b11 != 0 {
+            return Err(DecompressErr::Corrupted);
+        }
+        pos += read_table(
+            &mut self.literal_length,
+            modes >> 6,
+            &section[pos..],
+            &LITERAL_LENGTH_DEFAULT.0,
+            LITERAL_LENGTH_DEFAULT.1,
+            MAX_ACCURACY_LOG,
+        )?;
+        pos += read_table(
+            &mut self.offset,
+            (modes >> 4) & 0b11,
+            &section[pos..],
+            &OFFSET_DEFAULT.0,
+            OFFSET_DEFAULT.1,
+            MAX_ACCURACY_LOG - 1,
+        )?;
+        pos += read_table(
+            &mut self.match_length,
+            (modes >> 2) & 0b11,
+            &section[pos..],
+            &MATCH_LENGTH_DEFAULT.0,
+            MATCH_LENGTH_DEFAULT.1,
+            MAX_ACCURACY_LOG,
+        )?;
+        let (Some(literal_length), Some(offset), Some(match_length)) =
+            (&self.literal_length, &self.offset, &self.match_length)
+        else {
+            return Err(DecompressErr::Corrupted);
+        };
+
+        let mut bits = BackwardBits::new(section.get(pos..).ok_or(DecompressErr::Truncated)?)?;
+        let mut literal_length_state = literal_length.init(&mut bits);
+        let mut offset_state = offset.init(&mut bits);
+        let mut match_length_state = match_length.init(&mut bits);
+        let mut literal = 0;
+        for i in 0..count {
+            let offset_code = offset.symbol(offset_state) as usize;
+            let literal_length_code = literal_length.symbol(literal_length_state) as usize;
+            let match_length_code = match_length.symbol(match_length_state) as usize;
+            if offset_code > MAX_OFFSET_CODE
+                || literal_length_code >= LITERAL_LENGTH_BASE.len()
+                || match_length_code >= MATCH_LENGTH_BASE.len()
+            {
+                return Err(DecompressErr::Corrupted);
+            }
+            let offset_value = (1usize << offset_code) + bits.bits(offset_code as u32) as usize;
+            let match_len = MATCH_LENGTH_BASE[match_length_code] as usize
+                + bits.bits(MATCH_LENGTH_BITS[match_length_code] as u32) as usize;
+            let literal_len = LITERAL_LENGTH_BASE[literal_length_code] as usize
+                + bits.bits(LITERAL_LENGTH_BITS[literal_length_code] as u32) as usize;
+            if i + 1 < count {
+                literal_length_state = literal_length.update(literal_length_state, &mut bits);
+                match_length_state = match_length.update(match_length_state, &mut bits);
+                offset_state = offset.update(offset_state, &mut bits);
+            }
+
+            let distance = resolve_offset(&mut self.repeat_offsets, offset_value, literal_len);
+            let bytes = self
+                .literals
+                .get(literal..literal + literal_len)
+                .filter(|_| literal + literal_len <= literals)
+                .ok_or(DecompressErr::Corrupted)?;
+            output.extend(bytes)?;
+            literal += literal_len;
+            output.copy_match(distance, match_len)?;
+        }
+        if bits.remaining() != 0 {
+            return Err(DecompressErr::Corrupted);
+        }
+        output.extend(&self.literals[literal..literals])
+    }
+}
+
+// values 1-3 select a repeat offset, shifted by one when there are no literals
+fn resolve_offset(repeat: &mut [usize; 3], offset_value: usize, literal_len: usize) -> usize {
+    if offset_value > 3 {
+        let offset = offset_value - 3;
+        *repeat = [offset, repeat[0], repeat[1]];
+        return offset;
+    }
+    let index = offset_value - 1 + usize::from(literal_len == 0);
+    if index == 0 {
+        return repeat[0];
+    }
+    let offset = if index == 3 {
+        repeat[0].wrapping_sub(1)
+    } else {
+        repeat[index]
+    };
+    if index > 1 {
+        repeat[2] = repeat[1];
+    }
+    repeat[1] = repeat[0];
+    repeat[0] = offset;
+    offset
+}
+
+// set `table` as selected by `mode`, returns the number of bytes used from `input`
+fn read_table(
+    table: &mut Option<FseTable>,
+    mode: u8,
+    input: &[u8],
+    default: &[i16],
+    default_accuracy_log: u32,
+    max_accuracy_log: u32,
+) -> Result<usize, DecompressErr> {
+    match mode {
+        0 => {
+            *table = Some(FseTable::new(default, default_accuracy_log)?);
+            Ok(0)
+        }
+        1 => {
+            let symbol = *input.first().ok_or(DecompressErr::Truncated)?;
+            if symbol as usize >= default.len() {
+                return Err(DecompressErr::Corrupted);
+            }
+            *table = Some(FseTable::rle(symbol));
+            Ok(1)
+        }
+        2 => {
+            let (new, size) = FseTable::read(input, max_accuracy_log, default.len() - 1)?;
+            *table = Some(new);
+            Ok(size)
+        }
+        // repeat the table of the previous block
+        _ => table.as_ref().map(|_| 0).ok_or(DecompressErr::Corrupted),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fixture;
+
+    #[test]
+    fn decompress_fixtures() {
+        let sample = fixture("sample.bin");
+        for name in ["sample.zst", "sample_19.zst", "sample_nocheck.zst"] {
+            let input = fixture(name);
+            assert_eq!(decompressed_size(&input), Ok(sample.len()), "{}", name);
+            let mut output = vec![0; sample.len()];
+            assert_eq!(
+                decompress(&input, &mut output),
+                Ok(sample.len()),
+                "{}",
+                name
+            );
+            assert!(output == sample, "{} differs", name);
+        }
+    }
+
+    #[test]
+    fn unknown_content_size() {
+        let sample = fixture("sample.bin");
+        let input = fixture("sample_nosize.zst");
+        assert_eq!(decompressed_size(&input), Err(DecompressErr::UnknownSize));
+        let mut output = vec![0; sample.len()];
+        assert_eq!(decompress(&input, &mut output), Ok(sample.len()));
+        assert!(output == sample);
+    }
+
+    #[test]
+    fn corrupted_input_detected() {
+        let input = fixture("sample.zst");
+        let mut output = vec![0; decompressed_size(&input).unwrap()];
+        assert!(decompress(&input[..input.len() / 2], &mut output).is_err());
+        let mut bad = input.clone();
+        let checksum = bad.len() - CHECKSUM_SIZE;
+        bad[checksum] ^= 1;
+        assert_eq!(
+            decompress(&bad, &mut output),
+            Err(DecompressErr::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn skippable_frame_ignored() {
+        let sample = fixture("sample.bin");
+        let mut input = vec![0x50, 0x2A, 0x4D, 0x18, 3, 0, 0, 0, 1, 2, 3];
+        input.extend(fixture("sample_nocheck.zst"));
+        assert_eq!(decompressed_size(&input), Ok(sample.len()));
+        let mut output = vec![0; sample.len()];
+        assert_eq!(decompress(&input, &mut output), Ok(sample.len()));
+    }
+}
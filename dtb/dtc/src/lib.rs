@@ -0,0 +1,153 @@
+// dtb/dtc/src/lib.rs
+//
+// The DTS fixtures of the dtb tests (dtb/test/dts) compiled to blobs. A build dependency of
+// dtb, which compiles them for its tests, and a dependency of `cargo xtask dtb`.
+//
+// `dtc` is the one named by the `DTC` environment variable, or `dtc` in PATH. Without it the
+// pre-compiled copies in dtb/test/dtb are used, so the tests build on hosts without dtc.
+// `DTB_FIXTURES=dtc` or `DTB_FIXTURES=vendored` forces one of the two.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+/// the fixtures, relative to the dtb crate
+pub const DTS_DIR: &str = "test/dts";
+/// the pre-compiled fixtures, relative to the dtb crate
+pub const VENDORED_DIR: &str = "test/dtb";
+pub const DTC_ENV: &str = "DTC";
+pub const SOURCE_ENV: &str = "DTB_FIXTURES";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// dtc when it is found, otherwise the vendored blobs
+    Auto,
+    Dtc,
+    Vendored,
+}
+
+impl Source {
+    /// the source selected by `DTB_FIXTURES`, `Auto` when unset
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var(SOURCE_ENV) {
+            Err(_) => Ok(Self::Auto),
+            Ok(value) => match value.as_str() {
+                "" | "auto" => Ok(Self::Auto),
+                "dtc" => Ok(Self::Dtc),
+                "vendored" => Ok(Self::Vendored),
+                _ => Err(format!(
+                    "{}={} is not one of auto, dtc or vendored",
+                    SOURCE_ENV, value
+                )),
+            },
+        }
+    }
+}
+
+/// The dtc to run, `None` when it cannot be started
+pub fn find_dtc() -> Option<PathBuf> {
+    let dtc = std::env::var_os(DTC_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("dtc"));
+    let status = Command::new(&dtc)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    matches!(status, Ok(s) if s.success()).then_some(dtc)
+}
+
+pub fn missing_dtc() -> String {
+    format!(
+        "dtc (the device tree compiler) was not found. Install it (`apt install \
+         device-tree-compiler`, `brew install dtc`) or set {} to its path, or set {}=vendored \
+         to use the pre-compiled blobs in dtb/{}",
+        DTC_ENV, SOURCE_ENV, VENDORED_DIR
+    )
+}
+
+/// The `.dts` files of `dts_dir`, sorted
+pub fn fixtures(dts_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dts_dir)
+        .map_err(|e| format!("failed to read {}: {}", dts_dir.display(), e))?;
+    let mut fixtures: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dts"))
+        .collect();
+    fixtures.sort();
+    Ok(fixtures)
+}
+
+/// `<stem>.dtb` for the fixture `dts`
+pub fn blob_name(dts: &Path) -> String {
+    format!(
+        "{}.dtb",
+        dts.file_stem().unwrap_or_default().to_string_lossy()
+    )
+}
+
+/// Compile `dts` to `out` with `dtc`
+pub fn compile(dtc: &Path, dts: &Path, out: &Path) -> Result<(), String> {
+    let output = Command::new(dtc)
+        .args(["-I", "dts", "-O", "dtb", "-o"])
+        .arg(out)
+        .arg(dts)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", dtc.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed on {} ({}):\n{}",
+            dtc.display(),
+            dts.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(())
+}
+
+/// Put `<stem>.dtb` of every fixture of the dtb crate at `dtb_crate` in `out_dir`.
+/// Returns where the blobs came from, `Dtc` or `Vendored`
+pub fn build_fixtures(dtb_crate: &Path, out_dir: &Path, source: Source) -> Result<Source, String> {
+    let fixtures = fixtures(&dtb_crate.join(DTS_DIR))?;
+    let dtc = match source {
+        Source::Vendored => None,
+        Source::Auto | Source::Dtc => find_dtc(),
+    };
+    fs::create_dir_all(out_dir)
+        .map_err(|e| format!("failed to create {}: {}", out_dir.display(), e))?;
+    if let Some(dtc) = dtc {
+        for dts in &fixtures {
+            compile(&dtc, dts, &out_dir.join(blob_name(dts)))?;
+        }
+        return Ok(Source::Dtc);
+    }
+    if source == Source::Dtc {
+        return Err(missing_dtc());
+    }
+
+    let vendored = dtb_crate.join(VENDORED_DIR);
+    for dts in &fixtures {
+        let name = blob_name(dts);
+        let blob = vendored.join(&name);
+        if !blob.exists() {
+            let hint = match source {
+                Source::Auto => format!("\n{}", missing_dtc()),
+                _ => String::new(),
+            };
+            return Err(format!(
+                "no pre-compiled {} for {}, run `cargo xtask dtb --update` on a host with \
+                 dtc{}",
+                blob.display(),
+                dts.display(),
+                hint
+            ));
+        }
+        fs::copy(&blob, out_dir.join(&name))
+            .map_err(|e| format!("failed to copy {}: {}", blob.display(), e))?;
+    }
+    Ok(Source::Vendored)
+}
@@ -1,11 +1,12 @@
 #![cfg_attr(not(test), no_std)]
 
 use core::ffi::CStr;
-use core::ffi::c_char;
+use core::fmt;
 use core::ops::ControlFlow;
 
 pub use dtb_parser::DtbGenerator;
 pub use dtb_parser::DtbParser;
+pub use dtb_parser::Property;
 
 mod dtb_parser {
     use super::*;
@@ -13,35 +14,45 @@ mod dtb_parser {
     use big_endian::Dtb;
     use big_endian::FdtProperty;
     use big_endian::FdtReserveEntry;
+    use big_endian::read_char_str;
+    use big_endian::read_regs;
+    use big_endian::read_u32;
+    use core::cell::OnceCell;
     use core::iter::once;
 
     use core::mem::size_of;
     use core::ptr;
 
     trait DtbStructData: Sized {
-        fn new(parent: Option<*const Self>) -> Self;
-    }
-
-    struct PropertyData {
-        head_addr: usize,
-        len: u32,
+        fn new(parent: Option<*const Self>, name: &'static str) -> Self;
     }
 
     struct SimpleDeviceNode {
         parent: Option<*const SimpleDeviceNode>,
+        // node name with the unit address, empty for the root
+        name: &'static str,
         address_cells: u32,
         size_cells: u32,
-        reg: Option<PropertyData>,
-        ranges: Option<usize>,
+        reg: Option<&'static [u8]>,
+        ranges: Option<&'static [u8]>,
+        // phandle of the interrupt parent, inherited from the ancestors
+        interrupt_parent: Option<u32>,
+        // `status` is neither "okay" nor "ok"
+        disabled: bool,
+        properties: [Option<&'static [u8]>; SimpleDeviceNode::MAX_PROPERTIES],
     }
 
     impl DtbStructData for SimpleDeviceNode {
-        fn new(parent: Option<*const Self>) -> Self {
+        fn new(parent: Option<*const Self>, name: &'static str) -> Self {
             Self {
+                name,
                 address_cells: 2,
                 size_cells: 1,
                 reg: None,
                 ranges: None,
+                interrupt_parent: parent.and_then(|p| unsafe { (*p).interrupt_parent }),
+                disabled: false,
+                properties: [None; Self::MAX_PROPERTIES],
                 parent,
             }
         }
@@ -49,12 +60,12 @@ mod dtb_parser {
 
     enum ReservedMemoryData {
         Static {
-            reg: PropertyData,
+            reg: &'static [u8],
         },
         Dynamic {
-            size: Option<PropertyData>, // require
-            alignment: Option<PropertyData>,
-            alloc_ranges: Option<PropertyData>,
+            size: Option<&'static [u8]>, // require
+            alignment: Option<&'static [u8]>,
+            alloc_ranges: Option<&'static [u8]>,
         },
     }
 
@@ -71,7 +82,7 @@ mod dtb_parser {
     }
 
     impl DtbStructData for ReservedMemoryNode {
-        fn new(parent: Option<*const Self>) -> Self {
+        fn new(parent: Option<*const Self>, _name: &'static str) -> Self {
             Self::Unused(parent)
         }
     }
@@ -100,106 +111,99 @@ mod dtb_parser {
         const PROP_SIZE: &'static str = "size";
         const PROP_ALIGNMENT: &'static str = "alignment";
         const PROP_ALLOC_RANGES: &'static str = "alloc-ranges";
+        const PROP_INTERRUPT_PARENT: &'static str = "interrupt-parent";
+        const PROP_INTERRUPTS: &'static str = "interrupts";
+        const PROP_INTERRUPT_CELLS: &'static str = "#interrupt-cells";
+        const PROP_STATUS: &'static str = "status";
+        // number of properties `find_node_properties` can look up at once
+        const MAX_PROPERTIES: usize = 8;
 
         fn parent_ref(&self) -> Option<&Self> {
             self.parent.map(|p| unsafe { &*p })
         }
 
-        // address is assumed to point to the FDT_PROP token
+        // whether this node is at the absolute `path`. A component without a unit address
+        // matches any unit address, like `/soc/serial` for `/soc/serial@1000`
+        fn matches_path(&self, path: &str) -> bool {
+            let mut node = Some(self);
+            for component in path.trim_end_matches('/').rsplit('/') {
+                let Some(current) = node else {
+                    return false;
+                };
+                if current.parent.is_none() {
+                    // the root, the path must start here
+                    return component.is_empty();
+                }
+                if !name_matches(current.name, component) {
+                    return false;
+                }
+                node = current.parent_ref();
+            }
+            false
+        }
+
+        // the property `name` = `value` of this node
         fn parse_prop(
             &mut self,
-            parser: &DtbParser,
-            address: &mut usize,
+            name: &'static str,
+            value: &'static [u8],
             device_name: Option<&str>,
             compatible_name: Option<&str>,
         ) -> Result<bool, &'static str> {
-            *address += DtbParser::SIZEOF_FDT_TOKEN;
-            let property = unsafe { &*(*address as *const FdtProperty) };
-            *address += size_of::<FdtProperty>();
-            let name = Dtb::read_char_str(
-                parser.dtb_header.get_string_start_address() + property.get_name_offset() as usize,
-            )?;
-            pr_debug!(
-                "FDT_PROP offset: {}, str: {}",
-                property.get_name_offset(),
-                name
-            );
+            pr_debug!("FDT_PROP str: {}", name);
             let mut result = false;
-            if let Some(_s) = match name {
+            match name {
                 Self::ADDRESS_CELLS => {
-                    self.address_cells = Dtb::read_u32_from_ptr(*address);
+                    self.address_cells = read_u32(value)?;
                     pr_debug!("address_cells: {}", self.address_cells);
-                    Some(size_of::<u32>())
                 }
                 Self::SIZE_CELLS => {
-                    self.size_cells = Dtb::read_u32_from_ptr(*address);
+                    self.size_cells = read_u32(value)?;
                     pr_debug!("size_cells: {}", self.size_cells);
-                    Some(size_of::<u32>())
+                }
+                Self::PROP_INTERRUPT_PARENT => {
+                    self.interrupt_parent = Some(read_u32(value)?);
+                }
+                Self::PROP_STATUS => {
+                    // like Linux, anything else such as "disabled" or "fail" is unavailable
+                    self.disabled = !matches!(property_str(value), Some("okay" | "ok"));
                 }
                 Self::PROP_COMPATIBLE => {
                     if let Some(compatible_name) = compatible_name {
-                        for str in CharStringIter::new(*address, property.get_property_len()) {
+                        for str in CharStringIter::new(value) {
                             if compatible_name == str? {
                                 result = true;
                             }
                         }
                     }
-                    None
                 }
                 Self::PROP_DEVICE_NAME => {
                     if let Some(device_name) = device_name
-                        && device_name == Dtb::read_char_str(*address)?
+                        && device_name == read_char_str(value)?
                     {
                         result = true;
                     }
-                    None
                 }
                 Self::PROP_REG => {
-                    if property.get_property_len() != 0 {
-                        self.reg = Some(PropertyData {
-                            head_addr: *address,
-                            len: property.get_property_len(),
-                        });
-                        Some(
-                            self.parent_ref()
-                                .ok_or("'reg' property should not be located at the root node")
-                                .map(|node| {
-                                    node.address_cells as usize * size_of::<u32>()
-                                        + node.size_cells as usize * size_of::<u32>()
-                                })?,
-                        )
-                    } else {
-                        None
+                    if !value.is_empty() {
+                        self.parent_ref()
+                            .ok_or("'reg' property should not be located at the root node")?;
+                        self.reg = Some(value);
                     }
                 }
                 Self::PROP_RANGES => {
-                    self.ranges = Some(*address);
-                    if property.get_property_len() != 0 {
+                    self.ranges = Some(value);
+                    if !value.is_empty() {
                         pr_debug!(
                             "parent address: {}, child address: {}, child_size: {}",
                             self.parent_ref().unwrap().address_cells,
                             self.address_cells,
                             self.size_cells
                         );
-                        None
-                    } else {
-                        Some(0)
                     }
                 }
-                _ => None,
-            } {
-                // if s > property.get_property_len() as usize {
-                //     pr_debug!(
-                //         "invalid size!!! expected: {} actually: {}",
-                //         property.get_property_len(),
-                //         s
-                //     );
-                //     return Err("invalid size");
-                // }
-            }
-            *address += property
-                .get_property_len()
-                .next_multiple_of(DtbParser::ALIGNMENT) as usize;
+                _ => {}
+            }
             Ok(result)
         }
 
@@ -214,23 +218,27 @@ mod dtb_parser {
                 {
                     return Err("address or size cells overflow usize");
                 }
-                let address = Dtb::read_regs(reg.head_addr + offset, address_cells)?;
-                let len = Dtb::read_regs(reg.head_addr + offset + address.1, size_cells)?;
+                let reg = reg.get(offset..).ok_or("reg: offset past the end")?;
+                let address = read_regs(reg, address_cells)?;
+                let len = read_regs(&reg[address.1..], size_cells)?;
                 pr_debug!("reg: address: {:#x}, size: {:#x}", address.0, len.0);
                 return Ok(Some((address.0, len.0)));
             }
             Ok(None)
         }
 
+        // the first entry of `ranges`, `None` without one: an empty `ranges` maps 1:1
         fn read_round_internal(&self) -> Result<Option<(usize, usize, usize)>, &'static str> {
-            if let Some(reg) = self.ranges {
-                let child_address = Dtb::read_regs(reg, self.address_cells)?;
-                let parent_address = Dtb::read_regs(
-                    reg + child_address.1,
-                    self.parent_ref().unwrap().address_cells,
+            if let Some(reg) = self.ranges.filter(|ranges| !ranges.is_empty()) {
+                let child_address = read_regs(reg, self.address_cells)?;
+                let parent_address = read_regs(
+                    &reg[child_address.1..],
+                    self.parent_ref()
+                        .ok_or("'ranges' property should not be located at the root node")?
+                        .address_cells,
                 )?;
                 let parent_len =
-                    Dtb::read_regs(reg + child_address.1 + parent_address.1, self.size_cells)?;
+                    read_regs(&reg[child_address.1 + parent_address.1..], self.size_cells)?;
                 #[cfg(test)]
                 assert_eq!(parent_len.1, self.size_cells as usize * size_of::<u32>());
                 return Ok(Some((child_address.0, parent_address.0, parent_len.0)));
@@ -243,8 +251,7 @@ mod dtb_parser {
             address: &(usize, usize),
         ) -> Result<(usize, usize), &'static str> {
             let address_child = {
-                if self.ranges.is_some() {
-                    let parent = self.read_round_internal()?.unwrap();
+                if let Some(parent) = self.read_round_internal()? {
                     if parent.0 + parent.2 < address.0 + address.1 {
                         return Err("ranges size overflow");
                     }
@@ -276,11 +283,8 @@ mod dtb_parser {
             Self { prop, remain: None }
         }
         fn next_internal(&mut self) -> Result<Option<(usize, usize)>, &'static str> {
-            let PropertyData {
-                head_addr: _address,
-                len: size,
-            } = self.prop.reg.as_ref().ok_or("reg property is none")?;
-            let remain = self.remain.get_or_insert(*size);
+            let size = self.prop.reg.ok_or("reg property is none")?.len() as u32;
+            let remain = self.remain.get_or_insert(size);
             if *remain == 0 {
                 return Ok(None);
             }
@@ -288,7 +292,7 @@ mod dtb_parser {
             let result = self.prop.calculate_address_internal(
                 &self
                     .prop
-                    .read_reg_internal(*size as usize - *remain as usize)?
+                    .read_reg_internal((size - *remain) as usize)?
                     .ok_or("reg property is none")?,
             )?;
             *remain -= self
@@ -308,8 +312,237 @@ mod dtb_parser {
         }
     }
 
+    // whether the node `name` is the path component `component`, which matches any unit
+    // address when it has none
+    fn name_matches(name: &str, component: &str) -> bool {
+        name == component || (!component.contains('@') && name.split('@').next() == Some(component))
+    }
+
+    // a string property value without its NUL terminator
+    fn property_str(value: &'static [u8]) -> Option<&'static str> {
+        CStr::from_bytes_until_nul(value).ok()?.to_str().ok()
+    }
+
+    // sort (address, size) regions and merge the overlapping or adjacent ones in place,
+    // dropping empty ones. returns the number of regions left
+    fn normalize_regions(regions: &mut [(usize, usize)]) -> usize {
+        regions.sort_unstable();
+        let mut count = 0;
+        for i in 0..regions.len() {
+            let (address, size) = regions[i];
+            if size == 0 {
+                continue;
+            }
+            let end = address.saturating_add(size);
+            if count != 0 {
+                let last = &mut regions[count - 1];
+                let last_end = last.0 + last.1;
+                if address <= last_end {
+                    last.1 = last_end.max(end) - last.0;
+                    continue;
+                }
+            }
+            regions[count] = (address, end - address);
+            count += 1;
+        }
+        count
+    }
+
+    /// The value of a property, as it is in the blob, with decoders for the standard
+    /// encodings
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Property(&'static [u8]);
+
+    impl Property {
+        const CELL: usize = size_of::<u32>();
+
+        pub fn new(value: &'static [u8]) -> Self {
+            Self(value)
+        }
+
+        pub fn bytes(&self) -> &'static [u8] {
+            self.0
+        }
+
+        /// A property without a value, like `dma-coherent`, is a flag which is set
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        /// The value as a single cell, like `clock-frequency`
+        pub fn u32(&self) -> Option<u32> {
+            self.0.try_into().ok().map(u32::from_be_bytes)
+        }
+
+        /// The value as one or two cells, like `cpu-release-addr`
+        pub fn u64(&self) -> Option<u64> {
+            match self.0.len() {
+                4 => self.u32().map(u64::from),
+                _ => self.0.try_into().ok().map(u64::from_be_bytes),
+            }
+        }
+
+        /// Every cell, like the entries of `interrupts`. `None` if the length is not a
+        /// multiple of a cell
+        pub fn u32_cells(&self) -> Option<impl Iterator<Item = u32> + use<>> {
+            let (cells, remainder) = self.0.as_chunks::<{ Self::CELL }>();
+            remainder
+                .is_empty()
+                .then(|| cells.iter().map(|cell| u32::from_be_bytes(*cell)))
+        }
+
+        /// Every pair of cells, like the entries of `reg` with `#address-cells = <2>` and
+        /// `#size-cells = <0>`. `None` if the length is not a multiple of two cells
+        pub fn u64_cells(&self) -> Option<impl Iterator<Item = u64> + use<>> {
+            let (cells, remainder) = self.0.as_chunks::<{ 2 * Self::CELL }>();
+            remainder
+                .is_empty()
+                .then(|| cells.iter().map(|cell| u64::from_be_bytes(*cell)))
+        }
+
+        /// The value as a single string, like `status`
+        pub fn str(&self) -> Option<&'static str> {
+            CStr::from_bytes_with_nul(self.0).ok()?.to_str().ok()
+        }
+
+        /// Every string of a string list, like `compatible`. `None` if the value is not a
+        /// list of NUL terminated UTF-8 strings
+        pub fn strings(&self) -> Option<impl Iterator<Item = &'static str> + use<>> {
+            let value = self.0.strip_suffix(&[0])?;
+            let value = core::str::from_utf8(value).ok()?;
+            Some(value.split('\0'))
+        }
+    }
+
+    // ` = <value>;` of a property for `DtbParser::dump`: a list of printable strings as
+    // strings, whole cells as cells and anything else as bytes
+    fn write_value<W: fmt::Write>(w: &mut W, value: &[u8]) -> fmt::Result {
+        if value.is_empty() {
+            return writeln!(w, ";");
+        }
+        let printable =
+            |s: &str| !s.is_empty() && s.chars().all(|c| c == ' ' || c.is_ascii_graphic());
+        let strings = value
+            .strip_suffix(&[0])
+            .and_then(|value| core::str::from_utf8(value).ok())
+            .filter(|value| value.split('\0').all(printable));
+        if let Some(strings) = strings {
+            for (i, string) in strings.split('\0').enumerate() {
+                let separator = if i == 0 { " = " } else { ", " };
+                write!(w, "{}\"{}\"", separator, string)?;
+            }
+        } else if let (cells, []) = value.as_chunks::<4>() {
+            write!(w, " = <")?;
+            for (i, cell) in cells.iter().enumerate() {
+                let separator = if i == 0 { "" } else { " " };
+                write!(w, "{}{:#x}", separator, u32::from_be_bytes(*cell))?;
+            }
+            write!(w, ">")?;
+        } else {
+            write!(w, " = [")?;
+            for (i, byte) in value.iter().enumerate() {
+                let separator = if i == 0 { "" } else { " " };
+                write!(w, "{}{:02x}", separator, byte)?;
+            }
+            write!(w, "]")?;
+        }
+        writeln!(w, ";")
+    }
+
+    // phandles and the offsets of their nodes, sorted by phandle
+    struct PhandleIndex {
+        entries: [(u32, u32); DtbParser::MAX_PHANDLES],
+        len: usize,
+        // every phandle of the dtb is in `entries`
+        complete: bool,
+    }
+
+    impl PhandleIndex {
+        fn get(&self, phandle: u32) -> Option<Option<usize>> {
+            let entries = &self.entries[..self.len];
+            match entries.binary_search_by_key(&phandle, |&(phandle, _)| phandle) {
+                Ok(i) => Some(Some(entries[i].1 as usize)),
+                Err(_) if self.complete => Some(None),
+                Err(_) => None,
+            }
+        }
+    }
+
+    // the properties of a node as their name and value, see `DtbParser::properties`
+    struct Properties<'p> {
+        parser: &'p DtbParser,
+        pointer: usize,
+        done: bool,
+    }
+
+    impl Iterator for Properties<'_> {
+        type Item = Result<(&'static str, &'static [u8]), &'static str>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while !self.done {
+                let Ok(token) = self.parser.get_types(self.pointer) else {
+                    self.done = true;
+                    return Some(Err("node runs past the structure block"));
+                };
+                match token {
+                    DtbParser::FDT_NOP => self.pointer += DtbParser::SIZEOF_FDT_TOKEN,
+                    DtbParser::FDT_PROP => {
+                        let property = self.parser.read_property(&mut self.pointer);
+                        self.done = property.is_err();
+                        return Some(property);
+                    }
+                    _ => self.done = true,
+                }
+            }
+            None
+        }
+    }
+
+    // the child nodes of a node as their offset and name, see `DtbParser::children`
+    struct Children<'p> {
+        parser: &'p DtbParser,
+        pointer: usize,
+        done: bool,
+    }
+
+    impl Iterator for Children<'_> {
+        type Item = Result<(usize, &'static str), &'static str>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while !self.done {
+                let Ok(token) = self.parser.get_types(self.pointer) else {
+                    self.done = true;
+                    return Some(Err("node runs past the structure block"));
+                };
+                match token {
+                    DtbParser::FDT_NOP => self.pointer += DtbParser::SIZEOF_FDT_TOKEN,
+                    DtbParser::FDT_PROP => {
+                        if let Err(e) = self.parser.read_property(&mut self.pointer) {
+                            self.done = true;
+                            return Some(Err(e));
+                        }
+                    }
+                    DtbParser::FDT_BEGIN_NODE => {
+                        let offset = self.pointer;
+                        let child = self.parser.node_name(offset).and_then(|name| {
+                            self.parser.skip_node(&mut self.pointer)?;
+                            Ok((offset, name))
+                        });
+                        self.done = child.is_err();
+                        return Some(child);
+                    }
+                    _ => self.done = true,
+                }
+            }
+            None
+        }
+    }
+
     pub struct DtbParser {
         dtb_header: Dtb,
+        // built by the first `resolve_phandle`, `init` only reads the header
+        phandles: OnceCell<PhandleIndex>,
+        include_disabled: bool,
     }
 
     impl DtbParser {
@@ -320,24 +553,82 @@ mod dtb_parser {
         const FDT_PROP: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x03];
         const FDT_NOP: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x04];
         const FDT_END: [u8; Self::SIZEOF_FDT_TOKEN] = [0x00, 0x00, 0x00, 0x09];
+        /// `reg` entries of all `memory` nodes `memory_regions` reads
+        pub const MAX_MEMORY_REGIONS: usize = 64;
+        // entries of `linux,usable-memory-range` `memory_regions` reads
+        const MAX_USABLE_RANGES: usize = 8;
+        /// phandles `resolve_phandle` indexes, the nodes of the others are searched for
+        pub const MAX_PHANDLES: usize = 256;
+        const PROP_PHANDLE: &'static str = "phandle";
+        const PROP_LINUX_PHANDLE: &'static str = "linux,phandle";
+        const PROP_COMPATIBLE: &'static str = "compatible";
+        const PROP_CLOCK_FREQUENCY: &'static str = "clock-frequency";
+        const FIXED_CLOCK: &'static str = "fixed-clock";
+        // depth of the nodes `node_path` follows
+        const MAX_PATH_DEPTH: usize = 32;
         pub fn init(dtb_address: usize) -> Result<Self, &'static str> {
             let dtb = Dtb::new(dtb_address)?;
-            let parser = Self { dtb_header: dtb };
+            let parser = Self {
+                dtb_header: dtb,
+                phandles: OnceCell::new(),
+                include_disabled: false,
+            };
             Ok(parser)
         }
+        /// Parse the blob at the start of `data`, such as a dtb read from a file.
+        /// Unlike `init`, a `totalsize` past the end of `data` is an error, so the parser
+        /// never reads outside of it. `data` must outlive the parser
+        pub fn from_slice(data: &[u8]) -> Result<Self, &'static str> {
+            Ok(Self {
+                dtb_header: Dtb::from_slice(data)?,
+                phandles: OnceCell::new(),
+                include_disabled: false,
+            })
+        }
+        /// Whether the searches by device type or compatible report nodes whose `status`
+        /// is not "okay", like `status = "disabled"`. They are left out by default.
+        /// Lookups by path and phandle always find them
+        pub fn set_include_disabled(&mut self, include: bool) {
+            self.include_disabled = include;
+        }
+        // whether a node matched by a search is left out for its `status`
+        fn skips(&self, node: &SimpleDeviceNode) -> bool {
+            node.disabled && !self.include_disabled
+        }
         pub fn get_size(&self) -> usize {
             self.dtb_header.get_total_size() as usize
         }
-        fn skip_nop(&self, address: &mut usize) {
-            while *address < self.dtb_header.get_struct_end_address()
-                && Self::get_types(address) == Self::FDT_NOP
-            {
-                *address += Self::SIZEOF_FDT_TOKEN;
+        fn skip_nop(&self, pointer: &mut usize) {
+            while self.get_types(*pointer) == Ok(Self::FDT_NOP) {
+                *pointer += Self::SIZEOF_FDT_TOKEN;
             }
         }
 
-        fn get_types(address: &usize) -> [u8; 4] {
-            unsafe { *(*address as *const [u8; Self::SIZEOF_FDT_TOKEN]) }
+        // the token at `pointer`, an offset in the structure block
+        fn get_types(&self, pointer: usize) -> Result<[u8; 4], &'static str> {
+            self.dtb_header.read_token(pointer)
+        }
+
+        // name of the FDT_BEGIN_NODE at `pointer`, which is moved past the name
+        fn read_begin_node(&self, pointer: &mut usize) -> Result<&'static str, &'static str> {
+            *pointer += Self::SIZEOF_FDT_TOKEN;
+            let node_name = self.dtb_header.read_node_name(*pointer)?;
+            *pointer += (node_name.len() + 1/* null terminator */)
+                .next_multiple_of(Self::ALIGNMENT as usize);
+            Ok(node_name)
+        }
+
+        // `pointer` must be at FDT_END after the root node
+        fn expect_end(&self, pointer: &mut usize) -> Result<(), &'static str> {
+            self.skip_nop(pointer);
+            if self.get_types(*pointer) != Ok(Self::FDT_END) {
+                pr_debug!(
+                    "failed to parse all of the dtb node: {:?}",
+                    self.get_types(*pointer)
+                );
+                return Err("struct block: did not end with FDT_END");
+            }
+            Ok(())
         }
 
         fn walk_struct<P, C, T>(
@@ -353,27 +644,25 @@ mod dtb_parser {
             P: FnMut(
                 &mut T,
                 &'static str,
-                &DtbParser,
-                &mut usize,
+                &'static str,
+                &'static [u8],
             ) -> Result<(bool, Option<u32>), &'static str>,
             C: FnMut(&mut T) -> Result<ControlFlow<()>, &'static str>,
         {
             let mut remaining_depth = remaining_depth;
-            if Self::get_types(pointer) != Self::FDT_BEGIN_NODE {
+            if self.get_types(*pointer)? != Self::FDT_BEGIN_NODE {
                 return Err("walk_struct: expected FDT_BEGIN_NODE");
             }
-            let mut prop = T::new(node_info.map(|p| p as *const T));
-            *pointer += Self::SIZEOF_FDT_TOKEN;
-            let node_name = Dtb::read_char_str(*pointer)?;
-            *pointer += (node_name.len() + 1/* null terminator */)
-                .next_multiple_of(Self::ALIGNMENT as usize);
+            let node_name = self.read_begin_node(pointer)?;
+            let mut prop = T::new(node_info.map(|p| p as *const T), node_name);
             pr_debug!("node name: {}", node_name);
             let mut find_in_this_node = false;
             loop {
-                match Self::get_types(pointer) {
+                match self.get_types(*pointer)? {
                     Self::FDT_NOP => *pointer += Self::SIZEOF_FDT_TOKEN,
                     Self::FDT_PROP => {
-                        let result = parse_property(&mut prop, node_name, self, pointer)?;
+                        let (name, value) = self.read_property(pointer)?;
+                        let result = parse_property(&mut prop, node_name, name, value)?;
                         remaining_depth = result.1;
                         if result.0 {
                             find_in_this_node = true;
@@ -389,7 +678,7 @@ mod dtb_parser {
             }
 
             loop {
-                match Self::get_types(pointer) {
+                match self.get_types(*pointer)? {
                     Self::FDT_NOP => *pointer += Self::SIZEOF_FDT_TOKEN,
                     // If depth limit exists and reached 0, skip this subtree
                     Self::FDT_BEGIN_NODE if remaining_depth == Some(0) => {
@@ -415,9 +704,9 @@ mod dtb_parser {
                     }
                     _ => {
                         pr_debug!(
-                            "find an unknown or unexpected token: {:?}, address: 0x{:#?}",
-                            Self::get_types(pointer),
-                            *pointer - self.dtb_header.get_struct_start_address()
+                            "find an unknown or unexpected token: {:?}, offset: {:#x}",
+                            self.get_types(*pointer),
+                            *pointer
                         );
                         return Err("walk_struct: unknown or unexpected token while parsing DTB");
                     }
@@ -426,31 +715,22 @@ mod dtb_parser {
         }
 
         fn skip_node(&self, pointer: &mut usize) -> Result<(), &'static str> {
-            if Self::get_types(pointer) != Self::FDT_BEGIN_NODE {
+            if self.get_types(*pointer)? != Self::FDT_BEGIN_NODE {
                 return Err("skip_node: expected FDT_BEGIN_NODE");
             }
             let mut nest = 0usize;
             loop {
-                match Self::get_types(pointer) {
+                match self.get_types(*pointer)? {
                     Self::FDT_NOP => {
                         *pointer += Self::SIZEOF_FDT_TOKEN;
                     }
                     Self::FDT_BEGIN_NODE => {
                         // consume token + node name (nul-terminated, 4-byte aligned)
-                        *pointer += Self::SIZEOF_FDT_TOKEN;
-                        let node_name = Dtb::read_char_str(*pointer)?;
-                        *pointer +=
-                            (node_name.len() + 1).next_multiple_of(Self::ALIGNMENT as usize);
+                        self.read_begin_node(pointer)?;
                         nest += 1;
                     }
                     Self::FDT_PROP => {
-                        *pointer += Self::SIZEOF_FDT_TOKEN;
-                        let property = unsafe { &*(*pointer as *const FdtProperty) };
-                        *pointer += size_of::<FdtProperty>()
-                            + property
-                                .get_property_len()
-                                .next_multiple_of(Self::ALIGNMENT)
-                                as usize;
+                        self.read_property(pointer)?;
                     }
                     Self::FDT_END_NODE => {
                         *pointer += Self::SIZEOF_FDT_TOKEN;
@@ -465,6 +745,155 @@ mod dtb_parser {
             Ok(())
         }
 
+        /// Call `f` with the depth (0 for the root) and the name of every node, in order
+        pub fn for_each_node<F>(&self, f: &mut F) -> Result<(), &'static str>
+        where
+            F: FnMut(usize, &'static str) -> ControlFlow<()>,
+        {
+            let mut pointer = 0;
+            let mut depth = 0usize;
+            loop {
+                let token = self
+                    .get_types(pointer)
+                    .map_err(|_| "struct block: did not end with FDT_END")?;
+                match token {
+                    Self::FDT_NOP => pointer += Self::SIZEOF_FDT_TOKEN,
+                    Self::FDT_BEGIN_NODE => {
+                        let node_name = self.read_begin_node(&mut pointer)?;
+                        if f(depth, node_name).is_break() {
+                            return Ok(());
+                        }
+                        depth += 1;
+                    }
+                    Self::FDT_PROP => {
+                        self.read_property(&mut pointer)?;
+                    }
+                    Self::FDT_END_NODE => {
+                        pointer += Self::SIZEOF_FDT_TOKEN;
+                        depth = depth
+                            .checked_sub(1)
+                            .ok_or("for_each_node: unbalanced FDT_END_NODE")?;
+                    }
+                    Self::FDT_END => return Ok(()),
+                    _ => return Err("for_each_node: unknown or unexpected token"),
+                }
+            }
+        }
+
+        /// Print the memory reservations and the tree to `w` in the dts syntax, strings
+        /// decoded and other values as hex cells or bytes, for debugging over a UART
+        pub fn dump<W: fmt::Write>(&self, w: &mut W) -> Result<(), &'static str> {
+            const INDENT: usize = 4;
+            const WRITE_FAILED: &str = "dump: write failed";
+            let mut result = Ok(());
+            self.find_memory_reservation_block(&mut |address, size| {
+                result = writeln!(w, "/memreserve/ {:#x} {:#x};", address, size);
+                if result.is_err() {
+                    return ControlFlow::Break(());
+                }
+                ControlFlow::Continue(())
+            });
+            result.map_err(|_| WRITE_FAILED)?;
+
+            let mut pointer = 0;
+            let mut depth = 0usize;
+            loop {
+                let token = self
+                    .get_types(pointer)
+                    .map_err(|_| "struct block: did not end with FDT_END")?;
+                let indent = depth * INDENT;
+                match token {
+                    Self::FDT_NOP => pointer += Self::SIZEOF_FDT_TOKEN,
+                    Self::FDT_BEGIN_NODE => {
+                        let node_name = match self.read_begin_node(&mut pointer)? {
+                            "" if depth == 0 => "/",
+                            node_name => node_name,
+                        };
+                        writeln!(w, "{:indent$}{} {{", "", node_name).map_err(|_| WRITE_FAILED)?;
+                        depth += 1;
+                    }
+                    Self::FDT_PROP => {
+                        let (name, value) = self.read_property(&mut pointer)?;
+                        write!(w, "{:indent$}{}", "", name)
+                            .and_then(|_| write_value(w, value))
+                            .map_err(|_| WRITE_FAILED)?;
+                    }
+                    Self::FDT_END_NODE => {
+                        pointer += Self::SIZEOF_FDT_TOKEN;
+                        depth = depth
+                            .checked_sub(1)
+                            .ok_or("dump: unbalanced FDT_END_NODE")?;
+                        writeln!(w, "{:1$}}};", "", depth * INDENT).map_err(|_| WRITE_FAILED)?;
+                    }
+                    Self::FDT_END => return Ok(()),
+                    _ => return Err("dump: unknown or unexpected token"),
+                }
+            }
+        }
+
+        /// Check that the memory reservation block is terminated, and the structure block is
+        /// a balanced tree ending with `FDT_END` whose names and property values stay within
+        /// their blocks. Every read is bounded by `totalsize` anyway, but `init` only reads
+        /// the header, so run this to reject a malformed dtb from a file up front.
+        pub fn validate(&self) -> Result<(), &'static str> {
+            let reservation_start = self.dtb_header.get_memory_reservation_offset();
+            if reservation_start < size_of::<big_endian::FtdHeader>()
+                || !reservation_start.is_multiple_of(size_of::<u64>())
+                || !self
+                    .dtb_header
+                    .get_struct_offset()
+                    .is_multiple_of(Self::ALIGNMENT as usize)
+            {
+                return Err("validate: misaligned or misplaced block");
+            }
+
+            // the reservation entries end with a zero entry
+            let mut entry = 0;
+            loop {
+                match self.dtb_header.read_reserve_entry(entry) {
+                    None => return Err("validate: memory reservation block not terminated"),
+                    Some((0, 0)) => break,
+                    Some(_) => entry += 1,
+                }
+            }
+
+            let mut pointer = 0;
+            let mut depth = 0usize;
+            let mut seen_root = false;
+            loop {
+                let token = self
+                    .get_types(pointer)
+                    .map_err(|_| "validate: structure block did not end with FDT_END")?;
+                match token {
+                    Self::FDT_NOP => pointer += Self::SIZEOF_FDT_TOKEN,
+                    Self::FDT_BEGIN_NODE => {
+                        if depth == 0 && seen_root {
+                            return Err("validate: node after the root node");
+                        }
+                        seen_root = true;
+                        self.read_begin_node(&mut pointer)
+                            .map_err(|_| "validate: node name not terminated")?;
+                        depth += 1;
+                    }
+                    Self::FDT_PROP => {
+                        if depth == 0 {
+                            return Err("validate: property outside a node");
+                        }
+                        self.read_property(&mut pointer)?;
+                    }
+                    Self::FDT_END_NODE => {
+                        pointer += Self::SIZEOF_FDT_TOKEN;
+                        depth = depth
+                            .checked_sub(1)
+                            .ok_or("validate: unbalanced FDT_END_NODE")?;
+                    }
+                    Self::FDT_END if depth == 0 && seen_root => return Ok(()),
+                    Self::FDT_END => return Err("validate: FDT_END inside a node"),
+                    _ => return Err("validate: unknown token"),
+                }
+            }
+        }
+
         pub fn find_node<F>(
             &self,
             device_name: Option<&str>,
@@ -481,22 +910,25 @@ mod dtb_parser {
                     "device name and compatible name cannot be searched for at the same time",
                 );
             }
-            let mut pointer = self.dtb_header.get_struct_start_address();
+            let mut pointer = 0;
             self.skip_nop(&mut pointer);
 
             // parse_property closure: parse props and indicate match
             let mut parse_property = |prop: &mut SimpleDeviceNode,
                                       _: &'static str,
-                                      parser: &DtbParser,
-                                      cursor: &mut usize|
+                                      name: &'static str,
+                                      value: &'static [u8]|
              -> Result<(bool, Option<u32>), &'static str> {
-                prop.parse_prop(parser, cursor, device_name, compatible_name)
+                prop.parse_prop(name, value, device_name, compatible_name)
                     .map(|b| (b, None))
             };
 
             // calcualte_property closure: emit addresses for matched node
             let mut calculate_property =
                 |prop: &mut SimpleDeviceNode| -> Result<ControlFlow<()>, &'static str> {
+                    if self.skips(prop) {
+                        return Ok(ControlFlow::Continue(()));
+                    }
                     for entry in DeviceAddressIter::new(prop) {
                         if f(entry?.0, entry?.1) == ControlFlow::Break(()) {
                             return Ok(ControlFlow::Break(()));
@@ -515,109 +947,828 @@ mod dtb_parser {
                 )?
                 .is_continue()
             {
-                self.skip_nop(&mut pointer);
-                if Self::get_types(&pointer) != Self::FDT_END {
-                    pr_debug!(
-                        "failed to parse all of the dtb node: {:?}",
-                        Self::get_types(&pointer)
-                    );
-                    return Err("struct block: did not end with FDT_END");
-                }
+                self.expect_end(&mut pointer)?;
             }
             Ok(())
         }
 
-        pub fn find_memory_reservation_block<F>(&self, f: &mut F)
+        /// Search nodes like `find_node` and call `f` with the raw value of
+        /// `property_name` of every matched node which has the property.
+        pub fn find_node_property<F>(
+            &self,
+            device_name: Option<&str>,
+            compatible_name: Option<&str>,
+            property_name: &str,
+            f: &mut F,
+        ) -> Result<(), &'static str>
         where
-            F: FnMut(usize, usize) -> ControlFlow<()>,
+            F: FnMut(&'static [u8]) -> ControlFlow<()>,
         {
-            let mut ptr = self.dtb_header.get_memory_reservation_start_address();
-            loop {
-                let addr = FdtReserveEntry::get_address(ptr);
-                let size = FdtReserveEntry::get_size(ptr);
-                if addr == 0 && size == 0 {
-                    return;
-                }
-                if f(addr as usize, size as usize) == ControlFlow::Break(()) {
-                    return;
-                }
-                ptr += size_of::<FdtReserveEntry>();
-            }
+            self.find_node_properties(
+                device_name,
+                compatible_name,
+                &[property_name],
+                &mut |values| match values[0] {
+                    Some(value) => f(value),
+                    None => ControlFlow::Continue(()),
+                },
+            )
         }
 
-        pub fn find_reserved_memory_node<F, D>(
+        /// Search nodes like `find_node` and call `f` with `property_name` of every matched
+        /// node which has the property
+        pub fn find_property<F>(
             &self,
+            device_name: Option<&str>,
+            compatible_name: Option<&str>,
+            property_name: &str,
             f: &mut F,
-            dynamic: &mut D,
         ) -> Result<(), &'static str>
         where
-            F: FnMut(usize, usize) -> ControlFlow<()>,
-            D: FnMut(usize, Option<usize>, Option<(usize, usize)>) -> Result<ControlFlow<()>, ()>,
+            F: FnMut(Property) -> ControlFlow<()>,
         {
-            let mut ptr = self.dtb_header.get_struct_start_address();
-            self.skip_nop(&mut ptr);
-            let mut parse_property = |prop: &mut ReservedMemoryNode,
-                                      node_name: &'static str,
-                                      parser: &DtbParser,
-                                      cursor: &mut usize|
+            self.find_node_property(device_name, compatible_name, property_name, &mut |value| {
+                f(Property::new(value))
+            })
+        }
+
+        /// Search nodes like `find_node` and call `f` with every entry of `interrupts` of the
+        /// matched nodes as `(type, number, flags)`, decoded with the `#interrupt-cells` of
+        /// the interrupt parent. On a GIC the type is 0 for an SPI and 1 for a PPI; a parent
+        /// with one or two cells has no type, which is then 0, and one cell has no flags.
+        pub fn find_interrupts<F>(
+            &self,
+            device_name: Option<&str>,
+            compatible_name: Option<&str>,
+            f: &mut F,
+        ) -> Result<(), &'static str>
+        where
+            F: FnMut(u32, u32, u32) -> ControlFlow<()>,
+        {
+            if (device_name.is_some() && compatible_name.is_some())
+                || (device_name.is_none() && compatible_name.is_none())
+            {
+                return Err(
+                    "device name and compatible name cannot be searched for at the same time",
+                );
+            }
+            let mut pointer = 0;
+            self.skip_nop(&mut pointer);
+
+            let mut parse_property = |prop: &mut SimpleDeviceNode,
+                                      _: &'static str,
+                                      name: &'static str,
+                                      value: &'static [u8]|
              -> Result<(bool, Option<u32>), &'static str> {
-                pr_debug!("property parse start");
-                *cursor += DtbParser::SIZEOF_FDT_TOKEN;
-                let property = unsafe { &*(*cursor as *const FdtProperty) };
-                *cursor += size_of::<FdtProperty>();
-                let name = Dtb::read_char_str(
-                    parser.dtb_header.get_string_start_address()
-                        + property.get_name_offset() as usize,
-                )?;
-                if let ReservedMemoryNode::Unused(parent) = prop {
-                    if let Some(parent) = parent
-                        && let ReservedMemoryNode::Parent {
-                            address_cells: _,
-                            size_cells: _,
-                        } = unsafe { &**parent }
-                    {
-                        *prop = ReservedMemoryNode::Child {
-                            parent: *parent,
-                            data: None,
+                if name == SimpleDeviceNode::PROP_INTERRUPTS {
+                    prop.properties[0] = Some(value);
+                }
+                prop.parse_prop(name, value, device_name, compatible_name)
+                    .map(|b| (b, None))
+            };
+
+            let mut calculate_property =
+                |prop: &mut SimpleDeviceNode| -> Result<ControlFlow<()>, &'static str> {
+                    if self.skips(prop) {
+                        return Ok(ControlFlow::Continue(()));
+                    }
+                    let Some(interrupts) = prop.properties[0] else {
+                        return Ok(ControlFlow::Continue(()));
+                    };
+                    let parent = prop
+                        .interrupt_parent
+                        .ok_or("interrupts: no interrupt-parent")?;
+                    let cells = self.interrupt_cells(parent)?;
+                    let entries = interrupts.chunks_exact(cells * size_of::<u32>());
+                    if !entries.remainder().is_empty() {
+                        return Err("interrupts: length not a multiple of #interrupt-cells");
+                    }
+                    for entry in entries {
+                        let cell = |i: usize| {
+                            u32::from_be_bytes(entry[i * 4..(i + 1) * 4].try_into().unwrap())
                         };
-                    } else if node_name == "reserved-memory" {
-                        *prop = ReservedMemoryNode::Parent {
-                            address_cells: 2,
-                            size_cells: 1,
+                        let (kind, number, flags) = match cells {
+                            1 => (0, cell(0), 0),
+                            2 => (0, cell(0), cell(1)),
+                            _ => (cell(0), cell(1), cell(2)),
                         };
-                    }
-                }
-                let mut matched_node = false;
-                match prop {
-                    ReservedMemoryNode::Parent {
-                        address_cells,
-                        size_cells,
-                    } => {
-                        pr_debug!("parent");
-                        match name {
-                            SimpleDeviceNode::ADDRESS_CELLS => {
-                                *address_cells = Dtb::read_u32_from_ptr(*cursor)
-                            }
-                            SimpleDeviceNode::SIZE_CELLS => {
-                                *size_cells = Dtb::read_u32_from_ptr(*cursor);
-                            }
-                            _ => {}
+                        if f(kind, number, flags).is_break() {
+                            return Ok(ControlFlow::Break(()));
                         }
                     }
-                    ReservedMemoryNode::Child { parent: _, data } => {
-                        pr_debug!("child");
-                        match name {
-                            SimpleDeviceNode::PROP_REG => {
+                    Ok(ControlFlow::Continue(()))
+                };
+
+            self.walk_struct(
+                &mut pointer,
+                None::<&SimpleDeviceNode>,
+                &mut parse_property,
+                &mut calculate_property,
+                None,
+            )
+            .map(|_| ())
+        }
+
+        // `#interrupt-cells` of the interrupt controller with `phandle`, 1 to 4 cells
+        fn interrupt_cells(&self, phandle: u32) -> Result<usize, &'static str> {
+            let controller = self
+                .resolve_phandle(phandle)?
+                .ok_or("interrupts: interrupt-parent does not resolve to a node")?;
+            let cells = self
+                .node_property(controller, SimpleDeviceNode::PROP_INTERRUPT_CELLS)?
+                .and_then(|cells| cells.u32())
+                .ok_or("interrupts: interrupt parent has no #interrupt-cells")?;
+            match cells {
+                1..=4 => Ok(cells as usize),
+                _ => Err("interrupts: unsupported #interrupt-cells"),
+            }
+        }
+
+        /// Search nodes like `find_node` and call `f` once per matched node with the raw
+        /// values of `property_names` (in the same order, `None` if the node lacks it).
+        pub fn find_node_properties<F>(
+            &self,
+            device_name: Option<&str>,
+            compatible_name: Option<&str>,
+            property_names: &[&str],
+            f: &mut F,
+        ) -> Result<(), &'static str>
+        where
+            F: FnMut(&[Option<&'static [u8]>]) -> ControlFlow<()>,
+        {
+            if (device_name.is_some() && compatible_name.is_some())
+                || (device_name.is_none() && compatible_name.is_none())
+            {
+                return Err(
+                    "device name and compatible name cannot be searched for at the same time",
+                );
+            }
+            if property_names.len() > SimpleDeviceNode::MAX_PROPERTIES {
+                return Err("too many property names");
+            }
+            let mut pointer = 0;
+            self.skip_nop(&mut pointer);
+
+            let mut parse_property = |prop: &mut SimpleDeviceNode,
+                                      _: &'static str,
+                                      name: &'static str,
+                                      value: &'static [u8]|
+             -> Result<(bool, Option<u32>), &'static str> {
+                if let Some(index) = property_names.iter().position(|n| *n == name) {
+                    prop.properties[index] = Some(value);
+                }
+                prop.parse_prop(name, value, device_name, compatible_name)
+                    .map(|b| (b, None))
+            };
+
+            let mut calculate_property =
+                |prop: &mut SimpleDeviceNode| -> Result<ControlFlow<()>, &'static str> {
+                    if self.skips(prop) {
+                        return Ok(ControlFlow::Continue(()));
+                    }
+                    Ok(f(&prop.properties[..property_names.len()]))
+                };
+
+            if self
+                .walk_struct(
+                    &mut pointer,
+                    None::<&SimpleDeviceNode>,
+                    &mut parse_property,
+                    &mut calculate_property,
+                    None,
+                )?
+                .is_continue()
+            {
+                self.expect_end(&mut pointer)?;
+            }
+            Ok(())
+        }
+
+        /// Call `f` with the raw value of `property_name` of the `/chosen` node
+        pub fn find_chosen_property<F>(
+            &self,
+            property_name: &str,
+            f: &mut F,
+        ) -> Result<(), &'static str>
+        where
+            F: FnMut(&'static [u8]),
+        {
+            let mut pointer = 0;
+            self.skip_nop(&mut pointer);
+
+            let mut parse_property = |prop: &mut SimpleDeviceNode,
+                                      node_name: &'static str,
+                                      name: &'static str,
+                                      value: &'static [u8]|
+             -> Result<(bool, Option<u32>), &'static str> {
+                let mut found = false;
+                // `/chosen` is a child of the root node, which has no parent
+                if node_name == DtbGenerator::CHOSEN_NODE_NAME
+                    && prop.parent_ref().is_some_and(|p| p.parent.is_none())
+                    && name == property_name
+                {
+                    prop.properties[0] = Some(value);
+                    found = true;
+                }
+                prop.parse_prop(name, value, None, None)?;
+                Ok((found, None))
+            };
+
+            let mut calculate_property =
+                |prop: &mut SimpleDeviceNode| -> Result<ControlFlow<()>, &'static str> {
+                    if let Some(property) = prop.properties[0] {
+                        f(property);
+                    }
+                    Ok(ControlFlow::Break(()))
+                };
+
+            self.walk_struct(
+                &mut pointer,
+                None::<&SimpleDeviceNode>,
+                &mut parse_property,
+                &mut calculate_property,
+                None,
+            )
+            .map(|_| ())
+        }
+
+        /// Call `f` with the first `reg` entry and the raw values of `property_names` (in the
+        /// same order, `None` if the node lacks it) of the node at the absolute `path`, such
+        /// as `/soc/serial@7e201000`. A node without any property is not found.
+        pub fn find_node_by_path<F>(
+            &self,
+            path: &str,
+            property_names: &[&str],
+            f: &mut F,
+        ) -> Result<(), &'static str>
+        where
+            F: FnMut(Option<(usize, usize)>, &[Option<&'static [u8]>]),
+        {
+            if !path.starts_with('/') {
+                return Err("node path must be absolute");
+            }
+            if property_names.len() > SimpleDeviceNode::MAX_PROPERTIES {
+                return Err("too many property names");
+            }
+            let mut pointer = 0;
+            self.skip_nop(&mut pointer);
+
+            let mut parse_property = |prop: &mut SimpleDeviceNode,
+                                      _: &'static str,
+                                      name: &'static str,
+                                      value: &'static [u8]|
+             -> Result<(bool, Option<u32>), &'static str> {
+                let found = prop.matches_path(path);
+                if found && let Some(index) = property_names.iter().position(|n| *n == name) {
+                    prop.properties[index] = Some(value);
+                }
+                prop.parse_prop(name, value, None, None)?;
+                Ok((found, None))
+            };
+
+            let mut calculate_property =
+                |prop: &mut SimpleDeviceNode| -> Result<ControlFlow<()>, &'static str> {
+                    let reg = match prop.reg {
+                        Some(_) => DeviceAddressIter::new(prop).next().transpose()?,
+                        None => None,
+                    };
+                    f(reg, &prop.properties[..property_names.len()]);
+                    Ok(ControlFlow::Break(()))
+                };
+
+            self.walk_struct(
+                &mut pointer,
+                None::<&SimpleDeviceNode>,
+                &mut parse_property,
+                &mut calculate_property,
+                None,
+            )
+            .map(|_| ())
+        }
+
+        /// `property_name` of the node at the absolute `path`, see `find_node_by_path`.
+        /// `Ok(None)` if the node or the property does not exist
+        pub fn property(
+            &self,
+            path: &str,
+            property_name: &str,
+        ) -> Result<Option<Property>, &'static str> {
+            let mut property = None;
+            self.find_node_by_path(path, &[property_name], &mut |_, values| {
+                property = values[0].map(Property::new);
+            })?;
+            Ok(property)
+        }
+
+        /// Offset in the structure block of the node whose `phandle` (or older
+        /// `linux,phandle`) is `phandle`, for `node_name` and `node_property`, so that
+        /// properties like `interrupt-parent` and `clocks` can be followed.
+        /// `Ok(None)` if no node has it. The first call indexes the phandles of every node.
+        pub fn resolve_phandle(&self, phandle: u32) -> Result<Option<usize>, &'static str> {
+            if phandle == 0 || phandle == u32::MAX {
+                return Ok(None);
+            }
+            let index = match self.phandles.get() {
+                Some(index) => index,
+                None => {
+                    let index = self.index_phandles()?;
+                    self.phandles.get_or_init(|| index)
+                }
+            };
+            if let Some(node) = index.get(phandle) {
+                return Ok(node);
+            }
+            let mut node = None;
+            self.for_each_phandle(&mut |p, offset| {
+                if p != phandle {
+                    return ControlFlow::Continue(());
+                }
+                node = Some(offset);
+                ControlFlow::Break(())
+            })?;
+            Ok(node)
+        }
+
+        /// Name, with the unit address, of the node at `node`, an offset returned by
+        /// `resolve_phandle`
+        pub fn node_name(&self, node: usize) -> Result<&'static str, &'static str> {
+            self.dtb_header
+                .read_node_name(self.node_address(node)? + Self::SIZEOF_FDT_TOKEN)
+        }
+
+        /// `property_name` of the node at `node`, an offset returned by `resolve_phandle`.
+        /// Only the node itself is searched, not its children. `Ok(None)` if it lacks it
+        pub fn node_property(
+            &self,
+            node: usize,
+            property_name: &str,
+        ) -> Result<Option<Property>, &'static str> {
+            for property in self.properties(node)? {
+                let (name, value) = property?;
+                if name == property_name {
+                    return Ok(Some(Property::new(value)));
+                }
+            }
+            Ok(None)
+        }
+
+        /// Rate in Hz of the first clock of `clocks`, the property of a device node, when it
+        /// is a `fixed-clock` node like the UART clock of most boards. `Ok(None)` if the
+        /// phandle is unknown or the clock is of another kind, whose rate a driver sets
+        pub fn clock_frequency(&self, clocks: Property) -> Result<Option<u32>, &'static str> {
+            let phandle = clocks
+                .bytes()
+                .first_chunk()
+                .map(|phandle| u32::from_be_bytes(*phandle))
+                .ok_or("clocks: no phandle")?;
+            let Some(clock) = self.resolve_phandle(phandle)? else {
+                return Ok(None);
+            };
+            let fixed = self
+                .node_property(clock, Self::PROP_COMPATIBLE)?
+                .and_then(|compatible| compatible.strings())
+                .is_some_and(|mut compatible| compatible.any(|c| c == Self::FIXED_CLOCK));
+            if !fixed {
+                return Ok(None);
+            }
+            self.node_property(clock, Self::PROP_CLOCK_FREQUENCY)?
+                .map(|frequency| frequency.u32().ok_or("clock-frequency: not a single cell"))
+                .transpose()
+        }
+
+        /// Offset of the node at the absolute `path`, matched like `find_node_by_path`, for
+        /// `node_name` and `node_property`. `Ok(None)` if there is no such node
+        pub fn node_offset(&self, path: &str) -> Result<Option<usize>, &'static str> {
+            if !path.starts_with('/') {
+                return Err("node path must be absolute");
+            }
+            let mut pointer = 0;
+            self.skip_nop(&mut pointer);
+            let mut node = pointer;
+            for component in path.split('/').filter(|c| !c.is_empty()) {
+                let mut next = None;
+                for child in self.children(node)? {
+                    let (offset, name) = child?;
+                    if name_matches(name, component) {
+                        next = Some(offset);
+                        break;
+                    }
+                }
+                let Some(next) = next else {
+                    return Ok(None);
+                };
+                node = next;
+            }
+            Ok(Some(node))
+        }
+
+        // absolute path of the node at `node`, written to `buffer`
+        fn node_path<'b>(
+            &self,
+            node: usize,
+            buffer: &'b mut [u8],
+        ) -> Result<&'b str, &'static str> {
+            self.node_address(node)?;
+            let mut pointer = 0;
+            // length of the path of the node at each depth
+            let mut lengths = [0usize; Self::MAX_PATH_DEPTH];
+            let mut depth = 0;
+            let mut len = 0;
+            loop {
+                let token = self
+                    .get_types(pointer)
+                    .map_err(|_| "struct block: did not end with FDT_END")?;
+                match token {
+                    Self::FDT_NOP => pointer += Self::SIZEOF_FDT_TOKEN,
+                    Self::FDT_BEGIN_NODE => {
+                        let offset = pointer;
+                        let node_name = self.read_begin_node(&mut pointer)?;
+                        // the root is the empty name
+                        if depth > 0 {
+                            let end = len + 1 + node_name.len();
+                            if let Some(component) = buffer.get_mut(len..end) {
+                                component[0] = b'/';
+                                component[1..].copy_from_slice(node_name.as_bytes());
+                            }
+                            len = end;
+                        }
+                        *lengths
+                            .get_mut(depth)
+                            .ok_or("node_path: nodes nested too deep")? = len;
+                        depth += 1;
+                        if offset == node {
+                            if len == 0 {
+                                *buffer.first_mut().ok_or("node_path: buffer too short")? = b'/';
+                                len = 1;
+                            }
+                            let path = buffer.get(..len).ok_or("node_path: buffer too short")?;
+                            return core::str::from_utf8(path).map_err(|_| "node_path: bad name");
+                        }
+                    }
+                    Self::FDT_END_NODE => {
+                        pointer += Self::SIZEOF_FDT_TOKEN;
+                        depth = depth
+                            .checked_sub(1)
+                            .ok_or("node_path: unbalanced FDT_END_NODE")?;
+                        len = depth.checked_sub(1).map_or(0, |parent| lengths[parent]);
+                    }
+                    Self::FDT_PROP => {
+                        self.read_property(&mut pointer)?;
+                    }
+                    _ => return Err("node_path: unknown or unexpected token"),
+                }
+            }
+        }
+
+        // the largest phandle of any node, 0 if there is none
+        fn max_phandle(&self) -> Result<u32, &'static str> {
+            let mut max = 0;
+            self.for_each_phandle(&mut |phandle, _| {
+                max = max.max(phandle);
+                ControlFlow::Continue(())
+            })?;
+            Ok(max)
+        }
+
+        fn index_phandles(&self) -> Result<PhandleIndex, &'static str> {
+            let mut index = PhandleIndex {
+                entries: [(0, 0); Self::MAX_PHANDLES],
+                len: 0,
+                complete: true,
+            };
+            self.for_each_phandle(&mut |phandle, node| {
+                let entry = (phandle, node as u32);
+                // dtc emits both `phandle` and `linux,phandle` for older kernels
+                if index.len > 0 && index.entries[index.len - 1] == entry {
+                    return ControlFlow::Continue(());
+                }
+                if index.len == Self::MAX_PHANDLES {
+                    index.complete = false;
+                    return ControlFlow::Break(());
+                }
+                index.entries[index.len] = entry;
+                index.len += 1;
+                ControlFlow::Continue(())
+            })?;
+            index.entries[..index.len].sort_unstable();
+            Ok(index)
+        }
+
+        // call `f` with every phandle and the offset of the node which has it
+        fn for_each_phandle<F>(&self, f: &mut F) -> Result<(), &'static str>
+        where
+            F: FnMut(u32, usize) -> ControlFlow<()>,
+        {
+            let mut pointer = 0;
+            let mut node = 0;
+            loop {
+                let token = self
+                    .get_types(pointer)
+                    .map_err(|_| "struct block: did not end with FDT_END")?;
+                match token {
+                    Self::FDT_NOP | Self::FDT_END_NODE => pointer += Self::SIZEOF_FDT_TOKEN,
+                    Self::FDT_BEGIN_NODE => {
+                        node = pointer;
+                        self.read_begin_node(&mut pointer)?;
+                    }
+                    Self::FDT_PROP => {
+                        let (name, value) = self.read_property(&mut pointer)?;
+                        if matches!(name, Self::PROP_PHANDLE | Self::PROP_LINUX_PHANDLE)
+                            && let Some(phandle) = Property::new(value).u32()
+                            && f(phandle, node).is_break()
+                        {
+                            return Ok(());
+                        }
+                    }
+                    Self::FDT_END => return Ok(()),
+                    _ => return Err("for_each_phandle: unknown or unexpected token"),
+                }
+            }
+        }
+
+        // the properties of the node at `node`, not those of its children
+        fn properties(&self, node: usize) -> Result<Properties<'_>, &'static str> {
+            Ok(Properties {
+                parser: self,
+                pointer: self.node_body(node)?,
+                done: false,
+            })
+        }
+
+        // the child nodes of the node at `node`
+        fn children(&self, node: usize) -> Result<Children<'_>, &'static str> {
+            Ok(Children {
+                parser: self,
+                pointer: self.node_body(node)?,
+                done: false,
+            })
+        }
+
+        // the child of the node at `node` named exactly `name`
+        fn child(&self, node: usize, name: &str) -> Result<Option<usize>, &'static str> {
+            for child in self.children(node)? {
+                let (offset, child_name) = child?;
+                if child_name == name {
+                    return Ok(Some(offset));
+                }
+            }
+            Ok(None)
+        }
+
+        // offset of the first token after the name of the node at `node`
+        fn node_body(&self, node: usize) -> Result<usize, &'static str> {
+            let mut pointer = self.node_address(node)?;
+            self.read_begin_node(&mut pointer)?;
+            Ok(pointer)
+        }
+
+        // name and value of the FDT_PROP at `pointer`, which is moved past it
+        fn read_property(
+            &self,
+            pointer: &mut usize,
+        ) -> Result<(&'static str, &'static [u8]), &'static str> {
+            *pointer += Self::SIZEOF_FDT_TOKEN;
+            let property = self.dtb_header.read_property(*pointer)?;
+            *pointer += size_of::<FdtProperty>();
+            let name = self.dtb_header.read_string(property.get_name_offset())?;
+            let len = property.get_property_len();
+            let value = self.dtb_header.read_bytes(*pointer, len as usize)?;
+            *pointer += len.next_multiple_of(Self::ALIGNMENT) as usize;
+            Ok((name, value))
+        }
+
+        // `node` checked to be the offset of a FDT_BEGIN_NODE token in the structure block
+        fn node_address(&self, node: usize) -> Result<usize, &'static str> {
+            if !node.is_multiple_of(Self::SIZEOF_FDT_TOKEN) {
+                return Err("node offset outside the structure block");
+            }
+            let token = self
+                .get_types(node)
+                .map_err(|_| "node offset outside the structure block")?;
+            if token != Self::FDT_BEGIN_NODE {
+                return Err("node offset does not point at a node");
+            }
+            Ok(node)
+        }
+
+        /// The console named by `stdout-path` (or the older `linux,stdout-path`) of `/chosen`,
+        /// as the absolute path of its node and the options after the `:`, like `115200n8`.
+        /// An alias such as `serial0` is resolved through `/aliases`.
+        /// `Ok(None)` if `/chosen` does not name a console.
+        pub fn stdout_path(
+            &self,
+        ) -> Result<Option<(&'static str, Option<&'static str>)>, &'static str> {
+            let mut value = None;
+            for property_name in ["stdout-path", "linux,stdout-path"] {
+                self.find_chosen_property(property_name, &mut |v| value = Some(v))?;
+                if value.is_some() {
+                    break;
+                }
+            }
+            let Some(value) = value else {
+                return Ok(None);
+            };
+            let value = property_str(value).ok_or("stdout-path: not a string")?;
+            let (path, options) = match value.split_once(':') {
+                Some((path, options)) => (path, Some(options)),
+                None => (value, None),
+            };
+            if path.starts_with('/') {
+                return Ok(Some((path, options)));
+            }
+            let mut alias = None;
+            self.find_node_by_path("/aliases", &[path], &mut |_, values| {
+                alias = values[0];
+            })?;
+            let alias = alias.ok_or("stdout-path: unknown alias")?;
+            let alias = property_str(alias).ok_or("stdout-path: alias is not a string")?;
+            Ok(Some((alias, options)))
+        }
+
+        /// The RAM of every `memory` node, sorted with overlapping and adjacent regions
+        /// merged, written to `regions`. Returns the number of regions written.
+        /// A node is a `memory` node by its `device_type`, or by its name when it is a
+        /// child of the root; all of its `reg` entries are read with the cells of its parent.
+        /// `linux,usable-memory-range` of `/chosen`, when present, limits the RAM to its ranges.
+        pub fn memory_regions(
+            &self,
+            regions: &mut [(usize, usize)],
+        ) -> Result<usize, &'static str> {
+            let mut memory = [(0, 0); Self::MAX_MEMORY_REGIONS];
+            let mut count = 0;
+            let mut root_cells = (2, 1);
+            let mut pointer = 0;
+            self.skip_nop(&mut pointer);
+
+            let mut parse_property = |prop: &mut SimpleDeviceNode,
+                                      node_name: &'static str,
+                                      name: &'static str,
+                                      value: &'static [u8]|
+             -> Result<(bool, Option<u32>), &'static str> {
+                let named = prop.parent_ref().is_some_and(|p| p.parent.is_none())
+                    && DtbGenerator::is_memory(node_name);
+                let found = prop.parse_prop(name, value, Some("memory"), None)?;
+                Ok((found || named, None))
+            };
+
+            let mut calculate_property =
+                |prop: &mut SimpleDeviceNode| -> Result<ControlFlow<()>, &'static str> {
+                    if prop.reg.is_none() || self.skips(prop) {
+                        return Ok(ControlFlow::Continue(()));
+                    }
+                    let mut root = &*prop;
+                    while let Some(parent) = root.parent_ref() {
+                        root = parent;
+                    }
+                    root_cells = (root.address_cells, root.size_cells);
+                    for entry in DeviceAddressIter::new(prop) {
+                        *memory.get_mut(count).ok_or("too many memory regions")? = entry?;
+                        count += 1;
+                    }
+                    Ok(ControlFlow::Continue(()))
+                };
+
+            if self
+                .walk_struct(
+                    &mut pointer,
+                    None::<&SimpleDeviceNode>,
+                    &mut parse_property,
+                    &mut calculate_property,
+                    None,
+                )?
+                .is_continue()
+            {
+                self.expect_end(&mut pointer)?;
+            }
+            let count = normalize_regions(&mut memory[..count]);
+
+            let mut value = None;
+            self.find_chosen_property("linux,usable-memory-range", &mut |v| value = Some(v))?;
+            let Some(value) = value else {
+                let regions = regions.get_mut(..count).ok_or("too many memory regions")?;
+                regions.copy_from_slice(&memory[..count]);
+                return Ok(count);
+            };
+
+            let (address_cells, size_cells) = root_cells;
+            if address_cells as usize > (size_of::<usize>() / size_of::<u32>())
+                || size_cells as usize > (size_of::<usize>() / size_of::<u32>())
+            {
+                return Err("address or size cells overflow usize");
+            }
+            let entry_size = (address_cells + size_cells) as usize * size_of::<u32>();
+            if entry_size == 0 || value.len() % entry_size != 0 {
+                return Err("linux,usable-memory-range: invalid length");
+            }
+            let mut usable = [(0, 0); Self::MAX_USABLE_RANGES];
+            let usable_count = value.len() / entry_size;
+            if usable_count > usable.len() {
+                return Err("linux,usable-memory-range: too many ranges");
+            }
+            for (range, entry) in usable[..usable_count]
+                .iter_mut()
+                .zip(value.chunks_exact(entry_size))
+            {
+                let address = read_regs(entry, address_cells)?;
+                let size = read_regs(&entry[address.1..], size_cells)?;
+                *range = (address.0, size.0);
+            }
+            let usable_count = normalize_regions(&mut usable[..usable_count]);
+
+            // both lists are sorted and disjoint, so are their intersections
+            let mut written = 0;
+            for &(address, size) in &memory[..count] {
+                for &(usable_address, usable_size) in &usable[..usable_count] {
+                    let start = address.max(usable_address);
+                    let end = (address + size).min(usable_address + usable_size);
+                    if start < end {
+                        *regions.get_mut(written).ok_or("too many memory regions")? =
+                            (start, end - start);
+                        written += 1;
+                    }
+                }
+            }
+            Ok(written)
+        }
+
+        pub fn find_memory_reservation_block<F>(&self, f: &mut F)
+        where
+            F: FnMut(usize, usize) -> ControlFlow<()>,
+        {
+            // an unterminated block ends with `totalsize`
+            let mut entry = 0;
+            while let Some((addr, size)) = self.dtb_header.read_reserve_entry(entry) {
+                if addr == 0 && size == 0 {
+                    return;
+                }
+                if f(addr as usize, size as usize) == ControlFlow::Break(()) {
+                    return;
+                }
+                entry += 1;
+            }
+        }
+
+        pub fn find_reserved_memory_node<F, D>(
+            &self,
+            f: &mut F,
+            dynamic: &mut D,
+        ) -> Result<(), &'static str>
+        where
+            F: FnMut(usize, usize) -> ControlFlow<()>,
+            D: FnMut(usize, Option<usize>, Option<(usize, usize)>) -> Result<ControlFlow<()>, ()>,
+        {
+            let mut ptr = 0;
+            self.skip_nop(&mut ptr);
+            let mut parse_property = |prop: &mut ReservedMemoryNode,
+                                      node_name: &'static str,
+                                      name: &'static str,
+                                      value: &'static [u8]|
+             -> Result<(bool, Option<u32>), &'static str> {
+                pr_debug!("property parse start");
+                if let ReservedMemoryNode::Unused(parent) = prop {
+                    if let Some(parent) = parent
+                        && let ReservedMemoryNode::Parent {
+                            address_cells: _,
+                            size_cells: _,
+                        } = unsafe { &**parent }
+                    {
+                        *prop = ReservedMemoryNode::Child {
+                            parent: *parent,
+                            data: None,
+                        };
+                    } else if node_name == "reserved-memory" {
+                        *prop = ReservedMemoryNode::Parent {
+                            address_cells: 2,
+                            size_cells: 1,
+                        };
+                    }
+                }
+                let mut matched_node = false;
+                match prop {
+                    ReservedMemoryNode::Parent {
+                        address_cells,
+                        size_cells,
+                    } => {
+                        pr_debug!("parent");
+                        match name {
+                            SimpleDeviceNode::ADDRESS_CELLS => {
+                                *address_cells = read_u32(value)?;
+                            }
+                            SimpleDeviceNode::SIZE_CELLS => {
+                                *size_cells = read_u32(value)?;
+                            }
+                            _ => {}
+                        }
+                    }
+                    ReservedMemoryNode::Child { parent: _, data } => {
+                        pr_debug!("child");
+                        match name {
+                            SimpleDeviceNode::PROP_REG => {
                                 if data.is_some() {
                                     return Err("reserved-memory child: duplicate 'reg' property");
                                 }
-                                if property.get_property_len() != 0 {
-                                    *data = Some(ReservedMemoryData::Static {
-                                        reg: PropertyData {
-                                            head_addr: *cursor,
-                                            len: property.get_property_len(),
-                                        },
-                                    });
+                                if !value.is_empty() {
+                                    *data = Some(ReservedMemoryData::Static { reg: value });
                                     matched_node = true;
                                 }
                             }
@@ -654,10 +1805,7 @@ mod dtb_parser {
                                         "reserved-memory child: duplicate dynamic property",
                                     );
                                 }
-                                *data = Some(PropertyData {
-                                    head_addr: *cursor,
-                                    len: property.get_property_len(),
-                                });
+                                *data = Some(value);
                                 matched_node = true;
                             }
                             _ => {}
@@ -666,11 +1814,6 @@ mod dtb_parser {
                     ReservedMemoryNode::Unused(_) => {}
                 }
 
-                // advance over property value (4-byte aligned)
-                *cursor += property
-                    .get_property_len()
-                    .next_multiple_of(DtbParser::ALIGNMENT) as usize;
-
                 // Only constrain depth when we are at reserved-memory
                 let is_reserved_memory = node_name == "reserved-memory";
                 if is_reserved_memory {
@@ -695,26 +1838,24 @@ mod dtb_parser {
                             ReservedMemoryData::Static { reg } => {
                                 let stride =
                                     (address_cells + size_cells) as usize * size_of::<u32>();
-                                if reg.len == 0 || !(reg.len as usize).is_multiple_of(stride) {
+                                if reg.is_empty() || !reg.len().is_multiple_of(stride) {
                                     return Err(
                                         "reserved-memory static: 'reg' length not multiple of stride",
                                     );
                                 }
                                 let mut consumed = 0;
                                 loop {
-                                    let addr =
-                                        Dtb::read_regs(reg.head_addr + consumed, address_cells)?;
+                                    let addr = read_regs(&reg[consumed..], address_cells)?;
                                     consumed += addr.1;
-                                    let size =
-                                        Dtb::read_regs(reg.head_addr + consumed, size_cells)?;
+                                    let size = read_regs(&reg[consumed..], size_cells)?;
                                     consumed += size.1;
                                     if f(addr.0, size.0).is_break() {
                                         return Ok(ControlFlow::Break(()));
                                     }
-                                    if consumed == reg.len as usize {
+                                    if consumed == reg.len() {
                                         return Ok(ControlFlow::Continue(()));
                                     }
-                                    if consumed > reg.len as usize {
+                                    if consumed > reg.len() {
                                         return Err(
                                             "reserved-memory static: overrun while reading 'reg' entries",
                                         );
@@ -732,55 +1873,46 @@ mod dtb_parser {
                                 if size.is_none() {
                                     return Err("reserved-memory dynamic: missing 'size' property");
                                 }
-                                if size.as_ref().is_some_and(|x| x.len as usize != sc_bytes) {
+                                if size.is_some_and(|x| x.len() != sc_bytes) {
                                     return Err("reserved-memory dynamic: 'size' length mismatch");
                                 }
-                                if alignment
-                                    .as_ref()
-                                    .is_some_and(|x| x.len as usize != sc_bytes)
-                                {
+                                if alignment.is_some_and(|x| x.len() != sc_bytes) {
                                     return Err(
                                         "reserved-memory dynamic: 'alignment' length mismatch",
                                     );
                                 }
-                                if alloc_ranges.as_ref().is_some_and(|x| {
+                                if alloc_ranges.is_some_and(|x| {
                                     let stride = ac_bytes + sc_bytes;
-                                    (x.len as usize) == 0
-                                        || !(x.len as usize).is_multiple_of(stride)
+                                    x.is_empty() || !x.len().is_multiple_of(stride)
                                 }) {
                                     return Err(
                                         "reserved-memory dynamic: 'alloc-ranges' length not multiple of stride",
                                     );
                                 }
-                                let size = size.as_ref().unwrap();
-                                let alloc_size = Dtb::read_regs(size.head_addr, size_cells)?.0;
+                                let alloc_size = read_regs(size.unwrap(), size_cells)?.0;
                                 let alignment = if let Some(alignment) = alignment {
-                                    Some(Dtb::read_regs(alignment.head_addr, size_cells)?.0)
+                                    Some(read_regs(alignment, size_cells)?.0)
                                 } else {
                                     None
                                 };
                                 if let Some(alloc_ranges) = alloc_ranges {
                                     let mut consumed = 0;
                                     loop {
-                                        let addr = Dtb::read_regs(
-                                            alloc_ranges.head_addr + consumed,
-                                            address_cells,
-                                        )?;
+                                        let addr =
+                                            read_regs(&alloc_ranges[consumed..], address_cells)?;
                                         consumed += addr.1;
-                                        let size = Dtb::read_regs(
-                                            alloc_ranges.head_addr + consumed,
-                                            size_cells,
-                                        )?;
+                                        let size =
+                                            read_regs(&alloc_ranges[consumed..], size_cells)?;
                                         consumed += size.1;
                                         if let Ok(result) =
                                             dynamic(alloc_size, alignment, Some((addr.0, size.0)))
                                         {
                                             return Ok(result);
                                         }
-                                        if consumed == alloc_ranges.len as usize {
+                                        if consumed == alloc_ranges.len() {
                                             return Ok(ControlFlow::Continue(()));
                                         }
-                                        if consumed > alloc_ranges.len as usize {
+                                        if consumed > alloc_ranges.len() {
                                             return Err(
                                                 "reserved-memory dynamic: overrun while reading 'alloc-ranges'",
                                             );
@@ -795,36 +1927,470 @@ mod dtb_parser {
                     }
                     Ok(ControlFlow::Continue(()))
                 };
-            if self
-                .walk_struct(
-                    &mut ptr,
-                    None::<&ReservedMemoryNode>,
-                    &mut parse_property,
-                    &mut calculate_property,
-                    Some(1),
-                )?
-                .is_continue()
-            {
-                self.skip_nop(&mut ptr);
-                if Self::get_types(&ptr) != Self::FDT_END {
-                    pr_debug!(
-                        "failed to parse all of the dtb node: {:?}",
-                        Self::get_types(&ptr)
-                    );
-                    return Err("struct block: did not end with FDT_END");
+            if self
+                .walk_struct(
+                    &mut ptr,
+                    None::<&ReservedMemoryNode>,
+                    &mut parse_property,
+                    &mut calculate_property,
+                    Some(1),
+                )?
+                .is_continue()
+            {
+                self.expect_end(&mut ptr)?;
+            }
+            Ok(())
+        }
+    }
+
+    // number of `/chosen` properties `DtbGenerator` can emit besides `bootargs`
+    const MAX_CHOSEN_PROPERTIES: usize = 8;
+    // number of unit addresses `DtbGenerator::disable_node` accepts
+    const MAX_DISABLED_NODES: usize = 8;
+    // number of nodes `DtbGenerator::add_reserved_memory` accepts
+    const MAX_RESERVED_NODES: usize = 4;
+    // hex digits of the widest unit address
+    const MAX_UNIT_ADDRESS_DIGITS: usize = 16;
+    // number of fragments `DtbGenerator::apply_overlay` merges
+    const MAX_OVERLAY_FRAGMENTS: usize = 8;
+    // depth of the base nodes an overlay is merged into
+    const MAX_OVERLAY_DEPTH: usize = 16;
+    // bytes of the path of an overlay node
+    const MAX_OVERLAY_PATH: usize = 256;
+
+    // a child node of `/reserved-memory` added by the generator
+    #[derive(Clone, Copy)]
+    struct ReservedNode<'a> {
+        name: &'a str,
+        address: usize,
+        size: usize,
+    }
+
+    #[derive(Clone, Copy)]
+    struct ChosenProperty<'a> {
+        name: &'a str,
+        value: &'a [u8],
+        // append a null terminator to `value`
+        is_string: bool,
+    }
+
+    impl ChosenProperty<'_> {
+        fn value_len(&self) -> usize {
+            self.value.len() + self.is_string as usize
+        }
+    }
+
+    // how the node being copied is edited
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum NodeEdit {
+        None,
+        // `/chosen`: the generator properties replace the old ones
+        Chosen,
+        // `status` is replaced with "disabled"
+        Disable,
+        // `reg` is replaced with the regions of `set_memory`
+        Memory,
+    }
+
+    // a fragment of an overlay: its `__overlay__` node and the base node it is merged into
+    #[derive(Clone, Copy)]
+    struct Fragment {
+        target: usize,
+        node: usize,
+    }
+
+    // overlay nodes merged into one node, by fragment
+    type OverlayNodes = [Option<usize>; MAX_OVERLAY_FRAGMENTS];
+
+    struct Overlay<'a> {
+        parser: &'a DtbParser,
+        fragments: [Option<Fragment>; MAX_OVERLAY_FRAGMENTS],
+        // added to the phandles of the overlay so they follow those of the base dtb
+        phandle_delta: u32,
+    }
+
+    impl Overlay<'_> {
+        const OVERLAY_NODE_NAME: &'static str = "__overlay__";
+        const FIXUPS_PATH: &'static str = "/__fixups__";
+        const LOCAL_FIXUPS_PATH: &'static str = "/__local_fixups__";
+
+        fn has_property(&self, nodes: &[Option<usize>], name: &str) -> Result<bool, &'static str> {
+            for &node in nodes.iter().flatten() {
+                if self.parser.node_property(node, name)?.is_some() {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+
+        fn has_child(&self, nodes: &[Option<usize>], name: &str) -> Result<bool, &'static str> {
+            for &node in nodes.iter().flatten() {
+                if self.parser.child(node, name)?.is_some() {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+
+        // call `f` with every offset in the property `name` of the node at `path` which
+        // `__local_fixups__` lists, a phandle of the overlay itself
+        fn for_each_local_fixup<F>(
+            &self,
+            path: &str,
+            name: &str,
+            f: &mut F,
+        ) -> Result<(), &'static str>
+        where
+            F: FnMut(usize) -> Result<(), &'static str>,
+        {
+            let mut buffer = [0u8; Self::LOCAL_FIXUPS_PATH.len() + MAX_OVERLAY_PATH];
+            let prefix = Self::LOCAL_FIXUPS_PATH.len();
+            buffer[..prefix].copy_from_slice(Self::LOCAL_FIXUPS_PATH.as_bytes());
+            buffer[prefix..prefix + path.len()].copy_from_slice(path.as_bytes());
+            let fixups = core::str::from_utf8(&buffer[..prefix + path.len()])
+                .map_err(|_| "overlay: bad node path")?;
+            let Some(node) = self.parser.node_offset(fixups)? else {
+                return Ok(());
+            };
+            let Some(offsets) = self.parser.node_property(node, name)? else {
+                return Ok(());
+            };
+            for offset in offsets
+                .u32_cells()
+                .ok_or("overlay: __local_fixups__ entry is not a list of cells")?
+            {
+                f(offset as usize)?;
+            }
+            Ok(())
+        }
+    }
+
+    pub struct DtbGenerator<'a> {
+        parser: &'a DtbParser,
+        bootargs: Option<&'a str>,
+        chosen_properties: [Option<ChosenProperty<'a>>; MAX_CHOSEN_PROPERTIES],
+        disabled_nodes: [Option<usize>; MAX_DISABLED_NODES],
+        memory: Option<&'a [(usize, usize)]>,
+        cpus: Option<&'a [u64]>,
+        reserved_nodes: [Option<ReservedNode<'a>>; MAX_RESERVED_NODES],
+        overlay: Option<Overlay<'a>>,
+    }
+
+    impl<'a> DtbGenerator<'a> {
+        const CHOSEN_NODE_NAME: &'static str = "chosen";
+        const MEMORY_NODE_NAME: &'static str = "memory";
+        const CPUS_NODE_NAME: &'static str = "cpus";
+        const CPU_NODE_NAME: &'static str = "cpu";
+        const CPU_MAP_NODE_NAME: &'static str = "cpu-map";
+        const RESERVED_MEMORY_NODE_NAME: &'static str = "reserved-memory";
+        const ADDRESS_CELLS_PROPERTY_NAME: &'static str = "#address-cells";
+        const SIZE_CELLS_PROPERTY_NAME: &'static str = "#size-cells";
+        const RANGES_PROPERTY_NAME: &'static str = "ranges";
+        const BOOTARGS_PROPERTY_NAME: &'static str = "bootargs";
+        const STATUS_PROPERTY_NAME: &'static str = "status";
+        const REG_PROPERTY_NAME: &'static str = "reg";
+        const STATUS_DISABLED: &'static [u8] = b"disabled\0";
+
+        pub fn new(parser: &'a DtbParser) -> Self {
+            Self {
+                parser,
+                bootargs: None,
+                chosen_properties: [None; MAX_CHOSEN_PROPERTIES],
+                disabled_nodes: [None; MAX_DISABLED_NODES],
+                memory: None,
+                cpus: None,
+                reserved_nodes: [None; MAX_RESERVED_NODES],
+                overlay: None,
+            }
+        }
+
+        /// Emit `bootargs` as `/chosen/bootargs` of the generated dtb.
+        /// An existing `bootargs` is replaced and `/chosen` is created if it is missing.
+        pub fn set_bootargs(&mut self, bootargs: &'a str) {
+            self.bootargs = Some(bootargs);
+        }
+
+        /// Emit the raw property `name` = `value` in `/chosen` like `set_bootargs`
+        pub fn set_chosen_property(
+            &mut self,
+            name: &'a str,
+            value: &'a [u8],
+        ) -> Result<(), &'static str> {
+            if name == Self::BOOTARGS_PROPERTY_NAME {
+                return Err("use set_bootargs for bootargs");
+            }
+            let slot = match self
+                .chosen_properties
+                .iter()
+                .position(|p| p.is_some_and(|p| p.name == name))
+            {
+                Some(index) => &mut self.chosen_properties[index],
+                None => self
+                    .chosen_properties
+                    .iter_mut()
+                    .find(|p| p.is_none())
+                    .ok_or("too many chosen properties")?,
+            };
+            *slot = Some(ChosenProperty {
+                name,
+                value,
+                is_string: false,
+            });
+            Ok(())
+        }
+
+        /// Set `status = "disabled"` in every node named `name@<unit_address>`,
+        /// e.g. a device the loader keeps for itself
+        pub fn disable_node(&mut self, unit_address: usize) -> Result<(), &'static str> {
+            if self.disabled_nodes.contains(&Some(unit_address)) {
+                return Ok(());
+            }
+            *self
+                .disabled_nodes
+                .iter_mut()
+                .find(|n| n.is_none())
+                .ok_or("too many disabled nodes")? = Some(unit_address);
+            Ok(())
+        }
+
+        /// Replace the `reg` of the first `/memory` node with `regions` (address, size)
+        /// and disable the other `/memory` nodes.
+        /// Addresses and sizes are encoded with the `#address-cells` and `#size-cells` of `/`
+        pub fn set_memory(&mut self, regions: &'a [(usize, usize)]) {
+            self.memory = Some(regions);
+        }
+
+        /// Remove the `/cpus` nodes of the CPUs whose `reg` is not in `cpus`, e.g. those a guest
+        /// cannot start. `/cpus/cpu-map` goes with them as it may name a removed CPU
+        pub fn keep_cpus(&mut self, cpus: &'a [u64]) {
+            self.cpus = Some(cpus);
+        }
+
+        /// Add the node `name@<address>` with `reg` = (address, size) to `/reserved-memory`,
+        /// which is created with the cells of `/` and an empty `ranges` if it is missing.
+        /// There is no `no-map`: the kernel keeps the region mapped but does not allocate it.
+        pub fn add_reserved_memory(
+            &mut self,
+            name: &'a str,
+            address: usize,
+            size: usize,
+        ) -> Result<(), &'static str> {
+            *self
+                .reserved_nodes
+                .iter_mut()
+                .find(|n| n.is_none())
+                .ok_or("too many reserved memory nodes")? = Some(ReservedNode {
+                name,
+                address,
+                size,
+            });
+            Ok(())
+        }
+
+        /// Merge `overlay`, a `.dtbo` built with `dtc -@`, into the generated dtb.
+        /// A fragment names the base node it applies to by `target-path`, or by a `target`
+        /// phandle which `__fixups__` sets from the base `__symbols__`, so the base dtb is
+        /// built with `-@` too. The properties of its `__overlay__` replace or are added to
+        /// those of the target and its nodes are merged by name, a later fragment winning.
+        /// The phandles of the overlay are moved past those of the base dtb. The labels of the
+        /// overlay are not added to `__symbols__`. `validate` an overlay read from a file first
+        pub fn apply_overlay(&mut self, overlay: &'a DtbParser) -> Result<(), &'static str> {
+            if self.overlay.is_some() {
+                return Err("overlay: only one overlay can be applied");
+            }
+            let mut applied = Overlay {
+                parser: overlay,
+                fragments: [None; MAX_OVERLAY_FRAGMENTS],
+                phandle_delta: self.parser.max_phandle()?,
+            };
+            let mut fragments = [None; MAX_OVERLAY_FRAGMENTS];
+            let mut count = 0;
+            let root = overlay.node_offset("/")?.ok_or("overlay: no root node")?;
+            for child in overlay.children(root)? {
+                let (fragment, _) = child?;
+                // `__symbols__` and the fixups have no `__overlay__`
+                let Some(node) = overlay.child(fragment, Overlay::OVERLAY_NODE_NAME)? else {
+                    continue;
+                };
+                let target = self.overlay_target(&applied, fragment)?;
+                *fragments
+                    .get_mut(count)
+                    .ok_or("overlay: too many fragments")? = Some(Fragment { target, node });
+                count += 1;
+            }
+            applied.fragments = fragments;
+            self.overlay = Some(applied);
+            Ok(())
+        }
+
+        // the base node the fragment at `fragment` applies to
+        fn overlay_target(
+            &self,
+            overlay: &Overlay,
+            fragment: usize,
+        ) -> Result<usize, &'static str> {
+            if let Some(path) = overlay.parser.node_property(fragment, "target-path")? {
+                let path = path.str().ok_or("overlay: target-path is not a string")?;
+                return self
+                    .parser
+                    .node_offset(path)?
+                    .ok_or("overlay: target-path is not in the base dtb");
+            }
+            let mut phandle = overlay
+                .parser
+                .node_property(fragment, "target")?
+                .and_then(|target| target.u32())
+                .ok_or("overlay: fragment without a target")?;
+            let mut buffer = [0u8; MAX_OVERLAY_PATH];
+            let path = overlay.parser.node_path(fragment, &mut buffer)?;
+            overlay.for_each_local_fixup(path, "target", &mut |_| {
+                Err("overlay: a target inside the overlay is not supported")
+            })?;
+            self.for_each_fixup(overlay, path, "target", &mut |_, label| {
+                phandle = label;
+                Ok(())
+            })?;
+            self.parser
+                .resolve_phandle(phandle)?
+                .ok_or("overlay: target is not in the base dtb")
+        }
+
+        // call `f` with the offset and the base phandle of the label of every `__fixups__`
+        // entry of the property `name` of the overlay node at `path`
+        fn for_each_fixup<F>(
+            &self,
+            overlay: &Overlay,
+            path: &str,
+            name: &str,
+            f: &mut F,
+        ) -> Result<(), &'static str>
+        where
+            F: FnMut(usize, u32) -> Result<(), &'static str>,
+        {
+            let Some(fixups) = overlay.parser.node_offset(Overlay::FIXUPS_PATH)? else {
+                return Ok(());
+            };
+            for property in overlay.parser.properties(fixups)? {
+                let (label, entries) = property?;
+                let entries = Property::new(entries)
+                    .strings()
+                    .ok_or("overlay: __fixups__ entry is not a string list")?;
+                // `path:property:offset`
+                for entry in entries {
+                    let mut fields = entry.rsplitn(3, ':');
+                    let (Some(offset), Some(property), Some(node)) =
+                        (fields.next(), fields.next(), fields.next())
+                    else {
+                        return Err("overlay: bad __fixups__ entry");
+                    };
+                    if node != path || property != name {
+                        continue;
+                    }
+                    let offset = offset
+                        .parse()
+                        .map_err(|_| "overlay: bad __fixups__ offset")?;
+                    f(offset, self.label_phandle(label)?)?;
                 }
             }
             Ok(())
         }
-    }
 
-    pub struct DtbGenerator<'a> {
-        parser: &'a DtbParser,
-    }
+        // phandle of the base node `label` names in `__symbols__`
+        fn label_phandle(&self, label: &str) -> Result<u32, &'static str> {
+            let symbols = self
+                .parser
+                .node_offset("/__symbols__")?
+                .ok_or("overlay: the base dtb has no __symbols__, build it with -@")?;
+            let path = self
+                .parser
+                .node_property(symbols, label)?
+                .and_then(|path| path.str())
+                .ok_or("overlay: label is not in the base __symbols__")?;
+            let node = self
+                .parser
+                .node_offset(path)?
+                .ok_or("overlay: the node of a label is not in the base dtb")?;
+            for name in [DtbParser::PROP_PHANDLE, DtbParser::PROP_LINUX_PHANDLE] {
+                if let Some(phandle) = self.parser.node_property(node, name)?.and_then(|p| p.u32())
+                {
+                    return Ok(phandle);
+                }
+            }
+            Err("overlay: the node of a label has no phandle")
+        }
 
-    impl<'a> DtbGenerator<'a> {
-        pub fn new(parser: &'a DtbParser) -> Self {
-            Self { parser }
+        // properties written to `/chosen`
+        fn chosen(&self) -> impl Iterator<Item = ChosenProperty<'a>> + '_ {
+            self.bootargs
+                .map(|bootargs| ChosenProperty {
+                    name: Self::BOOTARGS_PROPERTY_NAME,
+                    value: bootargs.as_bytes(),
+                    is_string: true,
+                })
+                .into_iter()
+                .chain(self.chosen_properties.iter().flatten().copied())
+        }
+
+        // whether `disable_node` or `set_memory` was called
+        fn has_node_edits(&self) -> bool {
+            self.disabled_nodes.iter().any(|n| n.is_some()) || self.memory.is_some()
+        }
+
+        fn has_reserved_nodes(&self) -> bool {
+            self.reserved_nodes.iter().any(|n| n.is_some())
+        }
+
+        // whether the struct block is copied as is
+        fn is_unmodified(&self) -> bool {
+            self.chosen().next().is_none()
+                && !self.has_node_edits()
+                && self.cpus.is_none()
+                && !self.has_reserved_nodes()
+                && self.overlay.is_none()
+        }
+
+        // names of the properties the generator may write, each once
+        fn property_names(&self) -> impl Iterator<Item = &'a str> + '_ {
+            self.edit_property_names().chain(
+                self.overlay_property_names()
+                    .filter(|name| self.edit_property_names().all(|n| n != *name)),
+            )
+        }
+
+        // names of the overlay properties, each once
+        fn overlay_property_names(&self) -> impl Iterator<Item = &'a str> + '_ {
+            let names = |overlay: &Overlay| {
+                CharStringIter::new(overlay.parser.dtb_header.string_block())
+                    .map_while(Result::ok)
+                    .filter(|name| !name.is_empty())
+            };
+            self.overlay.iter().flat_map(move |overlay| {
+                names(overlay)
+                    .enumerate()
+                    .filter(move |&(i, name)| names(overlay).take(i).all(|n| n != name))
+                    .map(|(_, name)| name)
+            })
+        }
+
+        // names of the properties the edits may write, each once
+        fn edit_property_names(&self) -> impl Iterator<Item = &'a str> + '_ {
+            let status = self.has_node_edits().then_some(Self::STATUS_PROPERTY_NAME);
+            let reg = (self.memory.is_some() || self.has_reserved_nodes())
+                .then_some(Self::REG_PROPERTY_NAME);
+            let reserved_memory = self
+                .has_reserved_nodes()
+                .then_some([
+                    Self::ADDRESS_CELLS_PROPERTY_NAME,
+                    Self::SIZE_CELLS_PROPERTY_NAME,
+                    Self::RANGES_PROPERTY_NAME,
+                ])
+                .into_iter()
+                .flatten();
+            self.chosen()
+                .map(|p| p.name)
+                .chain(status)
+                .chain(reg)
+                .chain(reserved_memory)
         }
 
         pub fn get_required_size(
@@ -833,11 +2399,301 @@ mod dtb_parser {
         ) -> (usize /* size */, usize /* alignment */) {
             (
                 self.parser.dtb_header.get_total_size() as usize
-                    + num_of_mem_reserved * size_of::<big_endian::FdtReserveEntry>(),
+                    + num_of_mem_reserved * size_of::<big_endian::FdtReserveEntry>()
+                    + self.chosen_extra_size()
+                    + self.node_edits_extra_size()
+                    + self.reserved_memory_extra_size()
+                    + self.overlay_extra_size(),
                 8,
             )
         }
 
+        fn property_size(value_len: usize) -> usize {
+            DtbParser::SIZEOF_FDT_TOKEN
+                + size_of::<FdtProperty>()
+                + value_len.next_multiple_of(DtbParser::ALIGNMENT as usize)
+        }
+
+        // upper bound of the bytes added by emitting the `/chosen` properties
+        fn chosen_extra_size(&self) -> usize {
+            if self.chosen().next().is_none() {
+                return 0;
+            }
+            let properties: usize = self
+                .chosen()
+                .map(|p| Self::property_size(p.value_len()) + p.name.len() + 1)
+                .sum();
+            let chosen_node = DtbParser::SIZEOF_FDT_TOKEN * 2
+                + (Self::CHOSEN_NODE_NAME.len() + 1)
+                    .next_multiple_of(DtbParser::ALIGNMENT as usize);
+            properties + chosen_node + DtbParser::ALIGNMENT as usize
+        }
+
+        // upper bound of the bytes added by `apply_overlay`: every node and name of the overlay
+        fn overlay_extra_size(&self) -> usize {
+            self.overlay.as_ref().map_or(0, |overlay| {
+                overlay.parser.dtb_header.get_struct_size()
+                    + overlay.parser.dtb_header.get_string_size()
+                    + DtbParser::ALIGNMENT as usize
+            })
+        }
+
+        // upper bound of the bytes added by `add_reserved_memory`, with a new `/reserved-memory`
+        fn reserved_memory_extra_size(&self) -> usize {
+            if !self.has_reserved_nodes() {
+                return 0;
+            }
+            let nodes: usize = self
+                .reserved_nodes
+                .iter()
+                .flatten()
+                .map(|node| {
+                    DtbParser::SIZEOF_FDT_TOKEN * 2
+                        + Self::unit_name_size(node.name)
+                        + Self::property_size(2 * size_of::<u64>())
+                })
+                .sum();
+            let parent = DtbParser::SIZEOF_FDT_TOKEN * 2
+                + (Self::RESERVED_MEMORY_NODE_NAME.len() + 1)
+                    .next_multiple_of(DtbParser::ALIGNMENT as usize)
+                + Self::property_size(size_of::<u32>()) * 2
+                + Self::property_size(0);
+            let names = Self::ADDRESS_CELLS_PROPERTY_NAME.len()
+                + Self::SIZE_CELLS_PROPERTY_NAME.len()
+                + Self::RANGES_PROPERTY_NAME.len()
+                + Self::REG_PROPERTY_NAME.len()
+                + 4;
+            nodes + parent + names + DtbParser::ALIGNMENT as usize
+        }
+
+        // upper bound of the aligned size of `name@<unit address>` and its terminator
+        fn unit_name_size(name: &str) -> usize {
+            (name.len() + 1 + MAX_UNIT_ADDRESS_DIGITS + 1)
+                .next_multiple_of(DtbParser::ALIGNMENT as usize)
+        }
+
+        // write the node name `name@<address in hex>`, zero filled to the alignment
+        fn write_unit_name(destination: &mut usize, name: &str, address: usize) {
+            let mut digits = [0u8; MAX_UNIT_ADDRESS_DIGITS];
+            let mut count = 0;
+            let mut value = address;
+            loop {
+                digits[MAX_UNIT_ADDRESS_DIGITS - 1 - count] = b"0123456789abcdef"[value & 0xF];
+                count += 1;
+                value >>= 4;
+                if value == 0 {
+                    break;
+                }
+            }
+            let len = name.len() + 1 + count + 1;
+            let start = *destination;
+            Self::write_bytes(
+                destination,
+                &[],
+                len.next_multiple_of(DtbParser::ALIGNMENT as usize),
+            );
+            let mut cursor = start;
+            Self::write_bytes(&mut cursor, name.as_bytes(), name.len());
+            Self::write_bytes(&mut cursor, b"@", 1);
+            Self::write_bytes(
+                &mut cursor,
+                &digits[MAX_UNIT_ADDRESS_DIGITS - count..],
+                count,
+            );
+        }
+
+        // the `add_reserved_memory` nodes, `reg` encoded with the cells of `/reserved-memory`
+        fn write_reserved_nodes(
+            &self,
+            destination: &mut usize,
+            address_cells: u32,
+            size_cells: u32,
+        ) -> Result<(), &'static str> {
+            for node in self.reserved_nodes.iter().flatten() {
+                Self::write_bytes(
+                    destination,
+                    &DtbParser::FDT_BEGIN_NODE,
+                    DtbParser::SIZEOF_FDT_TOKEN,
+                );
+                Self::write_unit_name(destination, node.name, node.address);
+                self.write_memory_reg(
+                    destination,
+                    &[(node.address, node.size)],
+                    address_cells,
+                    size_cells,
+                )?;
+                Self::write_bytes(
+                    destination,
+                    &DtbParser::FDT_END_NODE,
+                    DtbParser::SIZEOF_FDT_TOKEN,
+                );
+            }
+            Ok(())
+        }
+
+        // a new `/reserved-memory` with the cells of `/` holding the `add_reserved_memory` nodes
+        fn write_reserved_memory_node(
+            &self,
+            destination: &mut usize,
+            address_cells: u32,
+            size_cells: u32,
+        ) -> Result<(), &'static str> {
+            Self::write_bytes(
+                destination,
+                &DtbParser::FDT_BEGIN_NODE,
+                DtbParser::SIZEOF_FDT_TOKEN,
+            );
+            Self::write_bytes(
+                destination,
+                Self::RESERVED_MEMORY_NODE_NAME.as_bytes(),
+                (Self::RESERVED_MEMORY_NODE_NAME.len() + 1)
+                    .next_multiple_of(DtbParser::ALIGNMENT as usize),
+            );
+            self.write_property(
+                destination,
+                Self::ADDRESS_CELLS_PROPERTY_NAME,
+                &address_cells.to_be_bytes(),
+                size_of::<u32>(),
+            );
+            self.write_property(
+                destination,
+                Self::SIZE_CELLS_PROPERTY_NAME,
+                &size_cells.to_be_bytes(),
+                size_of::<u32>(),
+            );
+            self.write_property(destination, Self::RANGES_PROPERTY_NAME, &[], 0);
+            self.write_reserved_nodes(destination, address_cells, size_cells)?;
+            Self::write_bytes(
+                destination,
+                &DtbParser::FDT_END_NODE,
+                DtbParser::SIZEOF_FDT_TOKEN,
+            );
+            Ok(())
+        }
+
+        // upper bound of the bytes added by `disable_node` and `set_memory`
+        fn node_edits_extra_size(&self) -> usize {
+            if !self.has_node_edits() {
+                return 0;
+            }
+            let status = Self::property_size(Self::STATUS_DISABLED.len());
+            // a cell is at most 64 bits wide here
+            let reg = Self::property_size(
+                self.memory.map_or(0, |regions| regions.len()) * 2 * size_of::<u64>(),
+            );
+            let mut size = Self::STATUS_PROPERTY_NAME.len()
+                + Self::REG_PROPERTY_NAME.len()
+                + 2
+                + DtbParser::ALIGNMENT as usize;
+            let mut source = 0;
+            let mut depth = 0usize;
+            while let Ok(token) = self.parser.get_types(source) {
+                let Ok(len) = self.token_len(source) else {
+                    break;
+                };
+                match token {
+                    DtbParser::FDT_BEGIN_NODE => {
+                        depth += 1;
+                        if let Ok(name) = self.parser.node_name(source) {
+                            if self.is_disabled(name) {
+                                size += status;
+                            }
+                            if depth == 2 && self.memory.is_some() && Self::is_memory(name) {
+                                size += status + reg;
+                            }
+                        }
+                    }
+                    DtbParser::FDT_END_NODE => depth = depth.saturating_sub(1),
+                    DtbParser::FDT_END => break,
+                    _ => {}
+                }
+                source += len;
+            }
+            size
+        }
+
+        // size of the token at `source` in the source structure block including its payload,
+        // which is checked to be within the block
+        fn token_len(&self, source: usize) -> Result<usize, &'static str> {
+            let mut end = source;
+            match self.parser.get_types(source)? {
+                DtbParser::FDT_BEGIN_NODE => {
+                    self.parser.read_begin_node(&mut end)?;
+                    Ok(end - source)
+                }
+                DtbParser::FDT_PROP => {
+                    self.parser.read_property(&mut end)?;
+                    Ok(end - source)
+                }
+                DtbParser::FDT_END_NODE | DtbParser::FDT_NOP | DtbParser::FDT_END => {
+                    Ok(DtbParser::SIZEOF_FDT_TOKEN)
+                }
+                _ => Err("make_dtb: unknown token in struct block"),
+            }
+        }
+
+        // whether the unit address of the node `name` was passed to `disable_node`
+        fn is_disabled(&self, name: &str) -> bool {
+            name.split_once('@')
+                .and_then(|(_, unit_address)| usize::from_str_radix(unit_address, 16).ok())
+                .is_some_and(|address| self.disabled_nodes.contains(&Some(address)))
+        }
+
+        fn is_memory(name: &str) -> bool {
+            name.split('@').next() == Some(Self::MEMORY_NODE_NAME)
+        }
+
+        // whether the `/cpus` child at `node` is left out by `keep_cpus`
+        fn is_removed_cpu(&self, node: usize) -> Result<bool, &'static str> {
+            let Some(cpus) = self.cpus else {
+                return Ok(false);
+            };
+            let removed = |node: usize| -> Result<bool, &'static str> {
+                if self.parser.node_name(node)?.split('@').next() != Some(Self::CPU_NODE_NAME) {
+                    return Ok(false);
+                }
+                let reg = self
+                    .parser
+                    .node_property(node, Self::REG_PROPERTY_NAME)?
+                    .and_then(|p| p.u64());
+                Ok(reg.is_some_and(|reg| !cpus.contains(&reg)))
+            };
+            if self.parser.node_name(node)? != Self::CPU_MAP_NODE_NAME {
+                return removed(node);
+            }
+            let parent = self
+                .parser
+                .node_offset("/cpus")?
+                .ok_or("make_dtb: no /cpus")?;
+            for child in self.parser.children(parent)? {
+                if removed(child?.0)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+
+        // the offset after the end of the node at `node`
+        fn node_end(&self, node: usize) -> Result<usize, &'static str> {
+            let mut source = node;
+            let mut depth = 0usize;
+            loop {
+                let token = self.parser.get_types(source)?;
+                source += self.token_len(source)?;
+                match token {
+                    DtbParser::FDT_BEGIN_NODE => depth += 1,
+                    DtbParser::FDT_END_NODE => {
+                        depth = depth.checked_sub(1).ok_or("make_dtb: unbalanced node")?;
+                        if depth == 0 {
+                            return Ok(source);
+                        }
+                    }
+                    DtbParser::FDT_END => return Err("make_dtb: unbalanced node"),
+                    _ => {}
+                }
+            }
+        }
+
         pub fn make_dtb(
             &self,
             dtb: &mut [u8],
@@ -847,35 +2703,31 @@ mod dtb_parser {
                 return Err("dtb memory too short");
             }
             // copy header region
-            unsafe {
-                ptr::copy(
-                    self.parser.dtb_header.get_fdt_address() as *const _,
-                    dtb.as_mut_ptr(),
-                    self.parser.dtb_header.get_memory_reservation_offset(),
-                )
-            };
+            let header_size = self.parser.dtb_header.get_memory_reservation_offset();
+            let source = self.parser.dtb_header.as_bytes();
+            dtb[..header_size].copy_from_slice(
+                source
+                    .get(..header_size)
+                    .ok_or("make_dtb: memory reservation block outside totalsize")?,
+            );
 
             // copy and create mem reservation block
-            let mut source = self
-                .parser
-                .dtb_header
-                .get_memory_reservation_start_address();
-            let mut destination =
-                dtb.as_ptr() as usize + self.parser.dtb_header.get_memory_reservation_offset();
-            while {
-                let addr = FdtReserveEntry::get_address(source);
-                let size = FdtReserveEntry::get_size(source);
-                !(addr == 0 && size == 0)
-            } {
-                unsafe {
-                    ptr::copy(
-                        source as *const u8,
-                        destination as *mut u8,
-                        size_of::<FdtReserveEntry>(),
-                    )
-                };
-                source += size_of::<FdtReserveEntry>();
+            let mut destination = dtb.as_ptr() as usize + header_size;
+            let mut entry = 0;
+            loop {
+                let (addr, size) = self
+                    .parser
+                    .dtb_header
+                    .read_reserve_entry(entry)
+                    .ok_or("make_dtb: memory reservation block not terminated")?;
+                if addr == 0 && size == 0 {
+                    break;
+                }
+                let reserve = unsafe { &mut *(destination as *mut FdtReserveEntry) };
+                reserve.write_address(addr);
+                reserve.write_size(size);
                 destination += size_of::<FdtReserveEntry>();
+                entry += 1;
             }
 
             for (addr, size) in reserved_memory.iter().chain(once(&(0, 0))) {
@@ -887,35 +2739,492 @@ mod dtb_parser {
             }
 
             // copy struct
+            let struct_start_offset = destination - dtb.as_ptr() as usize;
+            let string_size = self.parser.dtb_header.get_string_size();
+            let (struct_size, appended_names) = if self.is_unmodified() {
+                let source = self.parser.dtb_header.struct_block();
+                unsafe { ptr::copy(source.as_ptr(), destination as *mut u8, source.len()) };
+                (source.len(), 0)
+            } else {
+                self.copy_struct(destination)?
+            };
+
+            destination = (destination + struct_size).next_multiple_of(4);
+            // copy string
             unsafe {
                 ptr::copy(
-                    self.parser.dtb_header.get_struct_start_address() as *const u8,
+                    self.parser.dtb_header.string_block().as_ptr(),
                     destination as *mut u8,
-                    self.parser.dtb_header.get_struct_size(),
+                    string_size,
                 );
             }
-            let struct_start_offset = destination - dtb.as_ptr() as usize;
+            let string_start_offset = destination - dtb.as_ptr() as usize;
+            // names which are not in the source strings block go after it, in order
+            let mut string_end = destination + string_size;
+            for name in self.property_names() {
+                if let (offset, true) = self.find_name_offset(name)
+                    && offset as usize == string_end - destination
+                {
+                    Self::write_bytes(&mut string_end, name.as_bytes(), name.len() + 1);
+                }
+            }
+            debug_assert_eq!(string_end - destination, string_size + appended_names);
+            let string_size = string_size + appended_names;
+
+            let header = unsafe { &mut *(dtb.as_mut_ptr() as *mut big_endian::FtdHeader) };
+            header.write_struct_offset(struct_start_offset as u32);
+            header.write_string_offset(string_start_offset as u32);
+            header.write_struct_size(struct_size as u32);
+            header.write_string_size(string_size as u32);
+            header.write_total_size((string_start_offset + string_size) as u32);
+
+            Ok(())
+        }
+
+        // returns the offset of `name` in the strings block and whether it has to be appended.
+        // names to be appended are placed after the source strings block in
+        // `property_names()` order
+        fn find_name_offset(&self, name: &str) -> (u32, bool) {
+            let strings = self.parser.dtb_header.string_block();
+            let find = |name: &str| {
+                let name = name.as_bytes();
+                strings
+                    .windows(name.len() + 1)
+                    .position(|w| &w[..name.len()] == name && w[name.len()] == 0)
+            };
+            if let Some(offset) = find(name) {
+                return (offset as u32, false);
+            }
+            let appended_before: usize = self
+                .property_names()
+                .take_while(|n| *n != name)
+                .filter(|n| find(n).is_none())
+                .map(|n| n.len() + 1)
+                .sum();
+            ((strings.len() + appended_before) as u32, true)
+        }
+
+        // copy `bytes` to `destination` and zero fill up to `len`
+        fn write_bytes(destination: &mut usize, bytes: &[u8], len: usize) {
+            unsafe {
+                ptr::copy(bytes.as_ptr(), *destination as *mut u8, bytes.len());
+                ptr::write_bytes(
+                    (*destination + bytes.len()) as *mut u8,
+                    0,
+                    len - bytes.len(),
+                );
+            }
+            *destination += len;
+        }
+
+        // FDT_PROP token and property header, the value of `len` bytes follows
+        fn write_property_header(&self, destination: &mut usize, name: &str, len: usize) {
+            let (name_offset, _) = self.find_name_offset(name);
+            Self::write_bytes(
+                destination,
+                &DtbParser::FDT_PROP,
+                DtbParser::SIZEOF_FDT_TOKEN,
+            );
+            Self::write_bytes(destination, &(len as u32).to_be_bytes(), size_of::<u32>());
+            Self::write_bytes(destination, &name_offset.to_be_bytes(), size_of::<u32>());
+        }
+
+        fn write_property(&self, destination: &mut usize, name: &str, value: &[u8], len: usize) {
+            self.write_property_header(destination, name, len);
+            Self::write_bytes(
+                destination,
+                value,
+                len.next_multiple_of(DtbParser::ALIGNMENT as usize),
+            );
+        }
+
+        fn write_chosen_properties(&self, destination: &mut usize) {
+            for property in self.chosen() {
+                self.write_property(
+                    destination,
+                    property.name,
+                    property.value,
+                    property.value_len(),
+                );
+            }
+        }
+
+        fn write_memory_reg(
+            &self,
+            destination: &mut usize,
+            regions: &[(usize, usize)],
+            address_cells: u32,
+            size_cells: u32,
+        ) -> Result<(), &'static str> {
+            if !(1..=2).contains(&address_cells) || !(1..=2).contains(&size_cells) {
+                return Err("make_dtb: unsupported cell size of a reg property");
+            }
+            let len = regions.len() * (address_cells + size_cells) as usize * size_of::<u32>();
+            self.write_property_header(destination, Self::REG_PROPERTY_NAME, len);
+            for &(address, size) in regions {
+                for (value, cells) in [(address, address_cells), (size, size_cells)] {
+                    let value = value as u64;
+                    if cells == 1 {
+                        let value = u32::try_from(value)
+                            .map_err(|_| "make_dtb: memory region does not fit in a cell")?;
+                        Self::write_bytes(destination, &value.to_be_bytes(), size_of::<u32>());
+                    } else {
+                        Self::write_bytes(destination, &value.to_be_bytes(), size_of::<u64>());
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        // whether `edit` writes the property `name` of the node being copied
+        fn edit_writes(&self, edit: NodeEdit, name: &str) -> bool {
+            match edit {
+                NodeEdit::None => false,
+                NodeEdit::Chosen => self.chosen().any(|p| p.name == name),
+                NodeEdit::Disable => name == Self::STATUS_PROPERTY_NAME,
+                NodeEdit::Memory => name == Self::REG_PROPERTY_NAME,
+            }
+        }
+
+        // the overlay nodes merged into the base node at `node` named `name`, whose parent
+        // has `parent` merged
+        fn overlay_nodes(
+            overlay: &Overlay,
+            node: usize,
+            name: &str,
+            parent: &OverlayNodes,
+        ) -> Result<OverlayNodes, &'static str> {
+            let mut nodes = [None; MAX_OVERLAY_FRAGMENTS];
+            for ((merged, fragment), parent) in nodes.iter_mut().zip(&overlay.fragments).zip(parent)
+            {
+                let Some(fragment) = fragment else {
+                    continue;
+                };
+                *merged = match parent {
+                    _ if fragment.target == node => Some(fragment.node),
+                    Some(parent) => overlay.parser.child(*parent, name)?,
+                    None => None,
+                };
+            }
+            Ok(nodes)
+        }
+
+        // the property `name` = `value` of the overlay node at `path`, with its phandles
+        // fixed up
+        fn write_overlay_property(
+            &self,
+            destination: &mut usize,
+            overlay: &Overlay,
+            path: &str,
+            name: &str,
+            value: &[u8],
+        ) -> Result<(), &'static str> {
+            let start = *destination + DtbParser::SIZEOF_FDT_TOKEN + size_of::<FdtProperty>();
+            self.write_property(destination, name, value, value.len());
+            let patch = |offset: usize, f: &dyn Fn(u32) -> u32| {
+                if offset
+                    .checked_add(size_of::<u32>())
+                    .is_none_or(|end| end > value.len())
+                {
+                    return Err("overlay: fixup past the end of a property");
+                }
+                let cell = (start + offset) as *mut u32;
+                unsafe {
+                    let old = u32::from_be(cell.read_unaligned());
+                    cell.write_unaligned(f(old).to_be());
+                }
+                Ok(())
+            };
+            let delta = |phandle: u32| phandle + overlay.phandle_delta;
+            if matches!(
+                name,
+                DtbParser::PROP_PHANDLE | DtbParser::PROP_LINUX_PHANDLE
+            ) {
+                patch(0, &delta)?;
+            }
+            overlay.for_each_local_fixup(path, name, &mut |offset| patch(offset, &delta))?;
+            self.for_each_fixup(overlay, path, name, &mut |offset, phandle| {
+                patch(offset, &|_| phandle)
+            })
+        }
+
+        // the properties of the overlay `nodes` merged into one node, a later node replacing
+        // those of an earlier one, without those `edit` writes
+        fn write_overlay_properties(
+            &self,
+            destination: &mut usize,
+            overlay: &Overlay,
+            nodes: &OverlayNodes,
+            edit: NodeEdit,
+        ) -> Result<(), &'static str> {
+            for (i, node) in nodes.iter().enumerate() {
+                let Some(node) = *node else {
+                    continue;
+                };
+                let mut buffer = [0u8; MAX_OVERLAY_PATH];
+                let path = overlay.parser.node_path(node, &mut buffer)?;
+                for property in overlay.parser.properties(node)? {
+                    let (name, value) = property?;
+                    if self.edit_writes(edit, name)
+                        || overlay.has_property(&nodes[i + 1..], name)?
+                    {
+                        continue;
+                    }
+                    self.write_overlay_property(destination, overlay, path, name, value)?;
+                }
+            }
+            Ok(())
+        }
+
+        // the children of the overlay `nodes` which the base node at `base` lacks,
+        // those of the same name merged
+        fn write_overlay_children(
+            &self,
+            destination: &mut usize,
+            overlay: &Overlay,
+            nodes: &OverlayNodes,
+            base: Option<usize>,
+        ) -> Result<(), &'static str> {
+            for (i, node) in nodes.iter().enumerate() {
+                let Some(node) = *node else {
+                    continue;
+                };
+                for child in overlay.parser.children(node)? {
+                    let (_, name) = child?;
+                    // written with the first node which has it
+                    if overlay.has_child(&nodes[..i], name)? {
+                        continue;
+                    }
+                    if let Some(base) = base
+                        && self.parser.child(base, name)?.is_some()
+                    {
+                        continue;
+                    }
+                    let mut children = [None; MAX_OVERLAY_FRAGMENTS];
+                    for (child, node) in children.iter_mut().zip(nodes).skip(i) {
+                        if let Some(node) = *node {
+                            *child = overlay.parser.child(node, name)?;
+                        }
+                    }
+                    Self::write_bytes(
+                        destination,
+                        &DtbParser::FDT_BEGIN_NODE,
+                        DtbParser::SIZEOF_FDT_TOKEN,
+                    );
+                    Self::write_bytes(
+                        destination,
+                        name.as_bytes(),
+                        (name.len() + 1).next_multiple_of(DtbParser::ALIGNMENT as usize),
+                    );
+                    self.write_overlay_properties(destination, overlay, &children, NodeEdit::None)?;
+                    self.write_overlay_children(destination, overlay, &children, None)?;
+                    Self::write_bytes(
+                        destination,
+                        &DtbParser::FDT_END_NODE,
+                        DtbParser::SIZEOF_FDT_TOKEN,
+                    );
+                }
+            }
+            Ok(())
+        }
+
+        // copy the struct block token by token, applying the edits.
+        // returns the size of the written struct block and the bytes of names to append
+        fn copy_struct(&self, destination: usize) -> Result<(usize, usize), &'static str> {
+            let structure = self.parser.dtb_header.struct_block();
+            let mut source = 0;
+            let mut cursor = destination;
+            let mut depth = 0usize;
+            let mut edit = NodeEdit::None;
+            let mut chosen_found = false;
+            let mut memory_found = false;
+            // cells of `/`, which come before its child nodes
+            let mut address_cells = 2;
+            let mut size_cells = 1;
+            // cells of `/reserved-memory` while it is being copied
+            let mut reserved_memory_cells = None;
+            let mut reserved_memory_found = false;
+            // overlay nodes merged into the node at each depth and the offset of that node
+            let mut overlay_nodes = [([None; MAX_OVERLAY_FRAGMENTS], 0); MAX_OVERLAY_DEPTH];
+            // whether `/cpus` is being copied
+            let mut in_cpus = false;
+            while source < structure.len() {
+                let token = self.parser.get_types(source)?;
+                let len = self.token_len(source)?;
+                if token == DtbParser::FDT_BEGIN_NODE
+                    && depth == 2
+                    && in_cpus
+                    && self.is_removed_cpu(source)?
+                {
+                    source = self.node_end(source)?;
+                    continue;
+                }
+
+                let mut copy = true;
+                match token {
+                    DtbParser::FDT_BEGIN_NODE => {
+                        depth += 1;
+                        edit = NodeEdit::None;
+                        if let Some(overlay) = &self.overlay {
+                            let node = source;
+                            let name = self.parser.node_name(node)?;
+                            let parent = match depth {
+                                1 => [None; MAX_OVERLAY_FRAGMENTS],
+                                _ => overlay_nodes[depth - 2].0,
+                            };
+                            *overlay_nodes
+                                .get_mut(depth - 1)
+                                .ok_or("make_dtb: nodes nested too deep to apply an overlay")? =
+                                (Self::overlay_nodes(overlay, node, name, &parent)?, node);
+                        }
+                    }
+                    DtbParser::FDT_PROP => {
+                        let mut property = source;
+                        let (name, value) = self.parser.read_property(&mut property)?;
+                        match (edit, name) {
+                            (NodeEdit::None, "#address-cells") if depth == 1 => {
+                                address_cells = read_u32(value)?;
+                            }
+                            (NodeEdit::None, "#size-cells") if depth == 1 => {
+                                size_cells = read_u32(value)?;
+                            }
+                            (NodeEdit::None, "#address-cells") if depth == 2 => {
+                                if let Some((cells, _)) = &mut reserved_memory_cells {
+                                    *cells = read_u32(value)?;
+                                }
+                            }
+                            (NodeEdit::None, "#size-cells") if depth == 2 => {
+                                if let Some((_, cells)) = &mut reserved_memory_cells {
+                                    *cells = read_u32(value)?;
+                                }
+                            }
+                            // drop the old values
+                            (NodeEdit::Chosen, name) => {
+                                copy = self.chosen().all(|p| p.name != name);
+                            }
+                            (NodeEdit::Disable, Self::STATUS_PROPERTY_NAME)
+                            | (NodeEdit::Memory, Self::REG_PROPERTY_NAME) => copy = false,
+                            _ => {}
+                        }
+                        // replaced by the overlay
+                        if copy
+                            && let Some(overlay) = &self.overlay
+                            && let Some((nodes, _)) =
+                                depth.checked_sub(1).map(|d| &overlay_nodes[d])
+                        {
+                            copy = !overlay.has_property(nodes, name)?;
+                        }
+                    }
+                    DtbParser::FDT_END_NODE => {
+                        if let Some(overlay) = &self.overlay
+                            && let Some((nodes, node)) =
+                                depth.checked_sub(1).map(|d| &overlay_nodes[d])
+                        {
+                            self.write_overlay_children(&mut cursor, overlay, nodes, Some(*node))?;
+                        }
+                        if depth == 1 && !chosen_found && self.chosen().next().is_some() {
+                            Self::write_bytes(
+                                &mut cursor,
+                                &DtbParser::FDT_BEGIN_NODE,
+                                DtbParser::SIZEOF_FDT_TOKEN,
+                            );
+                            Self::write_bytes(
+                                &mut cursor,
+                                Self::CHOSEN_NODE_NAME.as_bytes(),
+                                (Self::CHOSEN_NODE_NAME.len() + 1)
+                                    .next_multiple_of(DtbParser::ALIGNMENT as usize),
+                            );
+                            self.write_chosen_properties(&mut cursor);
+                            Self::write_bytes(
+                                &mut cursor,
+                                &DtbParser::FDT_END_NODE,
+                                DtbParser::SIZEOF_FDT_TOKEN,
+                            );
+                            chosen_found = true;
+                        }
+                        if depth == 1 && !reserved_memory_found && self.has_reserved_nodes() {
+                            self.write_reserved_memory_node(
+                                &mut cursor,
+                                address_cells,
+                                size_cells,
+                            )?;
+                            reserved_memory_found = true;
+                        }
+                        if depth == 2
+                            && let Some((node_address_cells, node_size_cells)) =
+                                reserved_memory_cells.take()
+                        {
+                            self.write_reserved_nodes(
+                                &mut cursor,
+                                node_address_cells,
+                                node_size_cells,
+                            )?;
+                        }
+                        if depth == 2 {
+                            in_cpus = false;
+                        }
+                        depth = depth.checked_sub(1).ok_or("make_dtb: unbalanced node")?;
+                        edit = NodeEdit::None;
+                    }
+                    _ => {}
+                }
+                if copy {
+                    // `token_len` checked the token is within the block
+                    Self::write_bytes(&mut cursor, &structure[source..source + len], len);
+                }
+                source += len;
 
-            destination =
-                (destination + self.parser.dtb_header.get_struct_size()).next_multiple_of(4);
-            // copy string
-            unsafe {
-                ptr::copy(
-                    self.parser.dtb_header.get_string_start_address() as *const u8,
-                    destination as *mut u8,
-                    self.parser.dtb_header.get_string_size(),
-                );
+                // new properties go first in the node
+                if token == DtbParser::FDT_BEGIN_NODE {
+                    let name = self.parser.node_name(source - len)?;
+                    if depth == 2
+                        && name == Self::RESERVED_MEMORY_NODE_NAME
+                        && self.has_reserved_nodes()
+                    {
+                        reserved_memory_cells = Some((address_cells, size_cells));
+                        reserved_memory_found = true;
+                    }
+                    if depth == 2 && name == Self::CPUS_NODE_NAME {
+                        in_cpus = true;
+                    }
+                    if depth == 2 && name == Self::CHOSEN_NODE_NAME {
+                        self.write_chosen_properties(&mut cursor);
+                        edit = NodeEdit::Chosen;
+                        chosen_found = true;
+                    } else if let Some(regions) = self.memory
+                        && depth == 2
+                        && Self::is_memory(name)
+                        && !memory_found
+                    {
+                        self.write_memory_reg(&mut cursor, regions, address_cells, size_cells)?;
+                        edit = NodeEdit::Memory;
+                        memory_found = true;
+                    } else if self.is_disabled(name)
+                        || (self.memory.is_some() && depth == 2 && Self::is_memory(name))
+                    {
+                        self.write_property(
+                            &mut cursor,
+                            Self::STATUS_PROPERTY_NAME,
+                            Self::STATUS_DISABLED,
+                            Self::STATUS_DISABLED.len(),
+                        );
+                        edit = NodeEdit::Disable;
+                    }
+                    if let Some(overlay) = &self.overlay {
+                        let (nodes, _) = &overlay_nodes[depth - 1];
+                        self.write_overlay_properties(&mut cursor, overlay, nodes, edit)?;
+                    }
+                }
+                if token == DtbParser::FDT_END {
+                    break;
+                }
             }
-            let string_start_offset = destination - dtb.as_ptr() as usize;
-
-            let header = unsafe { &mut *(dtb.as_mut_ptr() as *mut big_endian::FtdHeader) };
-            header.write_struct_offset(struct_start_offset as u32);
-            header.write_string_offset(string_start_offset as u32);
-            header.write_total_size(
-                (string_start_offset + self.parser.dtb_header.get_string_size()) as u32,
-            );
-
-            Ok(())
+            let appended_names = self
+                .property_names()
+                .filter(|name| self.find_name_offset(name).1)
+                .map(|name| name.len() + 1)
+                .sum();
+            Ok((cursor - destination, appended_names))
         }
     }
 
@@ -952,8 +3261,15 @@ mod dtb_parser {
             pub fn write_total_size(&mut self, size: u32) {
                 self.total_size = size.to_be();
             }
+            pub fn write_struct_size(&mut self, size: u32) {
+                self.size_dt_struct = size.to_be();
+            }
+            pub fn write_string_size(&mut self, size: u32) {
+                self.size_dt_strings = size.to_be();
+            }
         }
 
+        // read by `Dtb::read_property`, in native endianness
         #[repr(C)]
         pub struct FdtProperty {
             property_len: u32,
@@ -962,10 +3278,10 @@ mod dtb_parser {
 
         impl FdtProperty {
             pub fn get_property_len(&self) -> u32 {
-                u32::from_be(self.property_len)
+                self.property_len
             }
             pub fn get_name_offset(&self) -> u32 {
-                u32::from_be(self.name_offset)
+                self.name_offset
             }
         }
 
@@ -976,14 +3292,6 @@ mod dtb_parser {
         }
 
         impl FdtReserveEntry {
-            pub fn get_address(ptr: usize) -> u64 {
-                u64::from_be(unsafe { *(ptr as *const u64) })
-            }
-            pub fn get_size(ptr: usize) -> u64 {
-                u64::from_be(unsafe {
-                    *((ptr + 8/* address size */) as *const u64)
-                })
-            }
             pub fn write_address(&mut self, addr: u64) {
                 self.address = addr.to_be();
             }
@@ -992,105 +3300,218 @@ mod dtb_parser {
             }
         }
 
+        // the whole blob, `totalsize` bytes from the header. The blocks are sub-slices of
+        // it, so every read of the structure, strings and memory reservation blocks is
+        // checked against `totalsize`
         pub struct Dtb {
-            address: &'static FtdHeader,
+            blob: &'static [u8],
+            structure: &'static [u8],
+            strings: &'static [u8],
         }
 
         impl Dtb {
             const DTB_VERSION: u32 = 17;
             const DTB_HEADER_MAGIC: u32 = 0xd00d_feed;
+            // fields of `FtdHeader`, as indexes of big-endian cells
+            const MAGIC: usize = 0;
+            const TOTAL_SIZE: usize = 1;
+            const OFF_DT_STRUCT: usize = 2;
+            const OFF_DT_STRINGS: usize = 3;
+            const OFF_MEM_RSVMAP: usize = 4;
+            const LAST_COMP_VERSION: usize = 6;
+            const SIZE_DT_STRINGS: usize = 8;
+            const SIZE_DT_STRUCT: usize = 9;
+
             pub fn new(address: usize) -> Result<Dtb, &'static str> {
-                let ftb = Self {
-                    address: unsafe { &*(address as *const FtdHeader) },
+                let header = unsafe {
+                    core::slice::from_raw_parts(address as *const u8, size_of::<FtdHeader>())
                 };
-                let header = ftb.address;
-                pr_debug!("dtb version: {}", u32::from_be(header.last_comp_version));
-                if u32::from_be(header.magic) != Self::DTB_HEADER_MAGIC {
+                let total_size = Self::check_header(header)?;
+                Self::from_blob(unsafe {
+                    core::slice::from_raw_parts(address as *const u8, total_size)
+                })
+            }
+            // the blob at the start of `data`, `totalsize` must be within it
+            pub fn from_slice(data: &[u8]) -> Result<Dtb, &'static str> {
+                let header = data
+                    .get(..size_of::<FtdHeader>())
+                    .ok_or("shorter than the header")?;
+                let total_size = Self::check_header(header)?;
+                let blob = data
+                    .get(..total_size)
+                    .ok_or("totalsize past the end of the blob")?;
+                // the caller keeps `data` alive as long as the parser, like the memory at
+                // the address given to `new`
+                Self::from_blob(unsafe { &*(blob as *const [u8]) })
+            }
+            // the magic and version of `header`, returns `totalsize`
+            fn check_header(header: &[u8]) -> Result<usize, &'static str> {
+                let field = |index: usize| read_u32(&header[index * size_of::<u32>()..]);
+                pr_debug!("dtb version: {}", field(Self::LAST_COMP_VERSION)?);
+                if field(Self::MAGIC)? != Self::DTB_HEADER_MAGIC {
                     return Err("invalid magic");
                 }
-                if u32::from_be(header.last_comp_version) > Self::DTB_VERSION {
+                if field(Self::LAST_COMP_VERSION)? > Self::DTB_VERSION {
                     return Err("this dtb is not compatible with the version 17");
                 }
-                Ok(ftb)
+                let total_size = field(Self::TOTAL_SIZE)? as usize;
+                if total_size < header.len() {
+                    return Err("totalsize smaller than the header");
+                }
+                Ok(total_size)
+            }
+            // `blob` is `totalsize` bytes with a checked header
+            fn from_blob(blob: &'static [u8]) -> Result<Dtb, &'static str> {
+                let header_size = size_of::<FtdHeader>();
+                let field = |index: usize| read_u32(&blob[index * size_of::<u32>()..]);
+                let block = |offset: usize, size: usize| {
+                    offset
+                        .checked_add(size)
+                        .filter(|_| offset >= header_size)
+                        .and_then(|end| blob.get(offset..end))
+                        .ok_or("block outside totalsize")
+                };
+                let structure = block(
+                    field(Self::OFF_DT_STRUCT)? as usize,
+                    field(Self::SIZE_DT_STRUCT)? as usize,
+                )?;
+                let strings = block(
+                    field(Self::OFF_DT_STRINGS)? as usize,
+                    field(Self::SIZE_DT_STRINGS)? as usize,
+                )?;
+                Ok(Self {
+                    blob,
+                    structure,
+                    strings,
+                })
+            }
+            fn field(&self, index: usize) -> u32 {
+                // `new` checked that the header is within the blob
+                read_u32(&self.blob[index * size_of::<u32>()..]).unwrap()
             }
-            pub fn get_fdt_address(&self) -> usize {
-                self.address as *const _ as usize
+            pub fn as_bytes(&self) -> &'static [u8] {
+                self.blob
             }
             pub fn get_memory_reservation_offset(&self) -> usize {
-                u32::from_be(self.address.off_mem_rsvmap) as usize
+                self.field(Self::OFF_MEM_RSVMAP) as usize
             }
             pub fn get_total_size(&self) -> u32 {
-                u32::from_be(self.address.total_size)
+                self.field(Self::TOTAL_SIZE)
             }
-            pub fn get_struct_start_address(&self) -> usize {
-                self.address as *const _ as usize
-                    + u32::from_be(self.address.off_dt_struct) as usize
-            }
-            pub fn get_struct_end_address(&self) -> usize {
-                self.get_struct_start_address() + u32::from_be(self.address.size_dt_struct) as usize
+            pub fn get_struct_offset(&self) -> usize {
+                self.field(Self::OFF_DT_STRUCT) as usize
             }
             pub fn get_struct_size(&self) -> usize {
-                u32::from_be(self.address.size_dt_struct) as usize
+                self.structure.len()
+            }
+            pub fn get_string_size(&self) -> usize {
+                self.strings.len()
             }
-            pub fn get_string_start_address(&self) -> usize {
-                self.address as *const _ as usize
-                    + u32::from_be(self.address.off_dt_strings) as usize
+            pub fn struct_block(&self) -> &'static [u8] {
+                self.structure
             }
-            pub fn get_string_end_address(&self) -> usize {
-                self.get_string_start_address()
-                    + u32::from_be(self.address.size_dt_strings) as usize
+            pub fn string_block(&self) -> &'static [u8] {
+                self.strings
             }
-            pub fn get_string_size(&self) -> usize {
-                u32::from_be(self.address.size_dt_strings) as usize
+            // the token at `offset` in the structure block
+            pub fn read_token(&self, offset: usize) -> Result<[u8; 4], &'static str> {
+                self.structure
+                    .get(offset..)
+                    .and_then(|token| token.first_chunk().copied())
+                    .ok_or("token outside the structure block")
             }
-            pub fn get_memory_reservation_start_address(&self) -> usize {
-                self.address as *const _ as usize
-                    + u32::from_be(self.address.off_mem_rsvmap) as usize
+            // the NUL terminated name at `offset` in the structure block
+            pub fn read_node_name(&self, offset: usize) -> Result<&'static str, &'static str> {
+                read_char_str(self.structure.get(offset..).unwrap_or_default())
             }
-            pub fn read_u32_from_ptr(address: usize) -> u32 {
-                u32::from_be(unsafe { *(address as *const u32) })
+            // the property header at `offset` in the structure block
+            pub fn read_property(&self, offset: usize) -> Result<FdtProperty, &'static str> {
+                let header = self
+                    .structure
+                    .get(offset..)
+                    .and_then(|header| header.first_chunk::<{ size_of::<FdtProperty>() }>())
+                    .ok_or("property header outside the structure block")?;
+                Ok(FdtProperty {
+                    property_len: read_u32(header)?,
+                    name_offset: read_u32(&header[size_of::<u32>()..])?,
+                })
             }
-            // reads null-terminated strings and covert to &str
-            pub fn read_char_str(address: usize) -> Result<&'static str, &'static str> {
-                let str = unsafe { CStr::from_ptr(address as *const c_char) };
-                str.to_str().map_err(|_| "failed to convert &Cstr to &str")
+            // the `len` bytes at `offset` in the structure block
+            pub fn read_bytes(
+                &self,
+                offset: usize,
+                len: usize,
+            ) -> Result<&'static [u8], &'static str> {
+                offset
+                    .checked_add(len)
+                    .and_then(|end| self.structure.get(offset..end))
+                    .ok_or("property value outside the structure block")
             }
-            // read 'reg' property value
-            pub fn read_regs(address: usize, size: u32) -> Result<(usize, usize), &'static str> {
-                let mut address_result = 0;
-                let mut address_consumed = 0;
-                pr_debug!("read_reg: {}", size);
-                for _ in 0..size {
-                    address_result <<= 32;
-                    address_result +=
-                        u32::from_be(unsafe { *((address + address_consumed) as *const u32) })
-                            as usize;
-                    address_consumed += size_of::<u32>();
-                }
-                Ok((address_result, address_consumed))
+            // the name at `offset` in the strings block
+            pub fn read_string(&self, offset: u32) -> Result<&'static str, &'static str> {
+                let name = self
+                    .strings
+                    .get(offset as usize..)
+                    .ok_or("property name outside the strings block")?;
+                read_char_str(name)
             }
+            // the memory reservation entry `index` as (address, size), `None` past the blob
+            pub fn read_reserve_entry(&self, index: usize) -> Option<(u64, u64)> {
+                let entry = index
+                    .checked_mul(size_of::<FdtReserveEntry>())?
+                    .checked_add(self.get_memory_reservation_offset())?;
+                let entry = self
+                    .blob
+                    .get(entry..)?
+                    .first_chunk::<{ size_of::<FdtReserveEntry>() }>()?;
+                let (address, size) = entry.split_at(size_of::<u64>());
+                Some((
+                    u64::from_be_bytes(address.try_into().ok()?),
+                    u64::from_be_bytes(size.try_into().ok()?),
+                ))
+            }
+        }
+
+        // the first cell of `value`
+        pub fn read_u32(value: &[u8]) -> Result<u32, &'static str> {
+            value
+                .first_chunk()
+                .map(|cell| u32::from_be_bytes(*cell))
+                .ok_or("cell past the end of the value")
+        }
+        // the NUL terminated string at the start of `bytes`, which must contain its NUL
+        pub fn read_char_str(bytes: &'static [u8]) -> Result<&'static str, &'static str> {
+            let str = CStr::from_bytes_until_nul(bytes).map_err(|_| "string not terminated")?;
+            str.to_str().map_err(|_| "failed to convert &Cstr to &str")
+        }
+        // read `cells` cells of a 'reg' like property at the start of `value` as one number,
+        // returns it and the bytes read
+        pub fn read_regs(value: &[u8], cells: u32) -> Result<(usize, usize), &'static str> {
+            let mut address_result = 0;
+            let mut address_consumed = 0;
+            pr_debug!("read_reg: {}", cells);
+            for _ in 0..cells {
+                address_result <<= 32;
+                address_result += read_u32(value.get(address_consumed..).unwrap_or_default())
+                    .map_err(|_| "cells past the end of the property")?
+                    as usize;
+                address_consumed += size_of::<u32>();
+            }
+            Ok((address_result, address_consumed))
         }
         // an iterator over a list of null terminated strings within a property
         pub struct CharStringIter {
-            pointer: usize,
-            remain_size: u32,
+            remain: &'static [u8],
         }
         impl CharStringIter {
-            pub fn new(pointer: usize, remain_size: u32) -> Self {
-                Self {
-                    pointer,
-                    remain_size,
-                }
+            pub fn new(value: &'static [u8]) -> Self {
+                Self { remain: value }
             }
             fn next_internal(&mut self) -> Result<&'static str, &'static str> {
-                let str = Dtb::read_char_str(self.pointer);
-                if let Ok(s) = str {
-                    let str_size = s.len() + 1 /* null terminator */;
-                    self.pointer += str_size;
-                    self.remain_size = self
-                        .remain_size
-                        .checked_sub(str_size.try_into().map_err(|_| "over flow")?)
-                        .ok_or("too small")?;
+                let str = read_char_str(self.remain);
+                match str {
+                    Ok(s) => self.remain = &self.remain[s.len() + 1 /* null terminator */..],
+                    Err(_) => self.remain = &[],
                 }
                 str
             }
@@ -1098,143 +3519,951 @@ mod dtb_parser {
         impl Iterator for CharStringIter {
             type Item = Result<&'static str, &'static str>;
             fn next(&mut self) -> Option<Self::Item> {
-                if self.remain_size == 0 {
+                if self.remain.is_empty() {
                     return None;
                 }
                 Some(self.next_internal())
             }
         }
     }
-}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const PL011_DEBUG_UART_ADDRESS: usize = 0x10_7D00_1000;
+    const PL011_DEBUG_UART_SIZE: usize = 0x200;
+    const MEMORY_ADDRESS: usize = 0x0;
+    const MEMORY_SIZE: usize = 0x2800_0000;
+    #[test]
+    fn it_works() {
+        let mut path = PathBuf::from(env!("OUT_DIR"));
+        path.push("test.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load dtb files");
+        let test_data_addr = test_data.as_ptr() as usize;
+        let parser = DtbParser::init(test_data_addr).unwrap();
+
+        let mut counter = 0;
+        parser
+            .find_node(None, Some("arm,pl011"), &mut |address, size| {
+                pr_debug!("find pl011 node, address: {} size: {}", address, size);
+                assert_eq!(address, PL011_DEBUG_UART_ADDRESS);
+                assert_eq!(size, PL011_DEBUG_UART_SIZE);
+                counter += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(counter, 1);
+
+        counter = 0;
+        parser
+            .find_node(Some("memory"), None, &mut |address, size| {
+                pr_debug!("find memory node, address: {} size: {}", address, size);
+                assert_eq!(address, MEMORY_ADDRESS);
+                assert_eq!(size, MEMORY_SIZE);
+                counter += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(counter, 1);
+        counter = 0;
+        parser
+            .find_node(None, Some("arm,gic-400"), &mut |address, size| {
+                pr_debug!("find gic node, address: {} size: {}", address, size);
+                counter += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(counter, 4);
+    }
+
+    #[test]
+    fn reserved_memory_generated_dtb() {
+        // The build script places compiled DTBs in OUT_DIR
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("reserved_memory.dtb");
+        assert!(
+            path.exists(),
+            "{} not found. dtc is required to build DTS fixtures.",
+            path.display()
+        );
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let test_data_addr = test_data.as_ptr() as usize;
+        let parser = DtbParser::init(test_data_addr).unwrap();
+
+        let mut captured: Option<(usize, usize)> = None;
+        parser
+            .find_reserved_memory_node(
+                &mut |addr, size| {
+                    captured = Some((addr, size));
+                    ControlFlow::Break(())
+                },
+                &mut |_, _, _| -> Result<ControlFlow<()>, ()> { unreachable!() },
+            )
+            .unwrap();
+        let (addr, size) = captured.expect("no reserved-memory region found");
+        assert_eq!(addr, 0x20);
+        assert_eq!(size, 0x10);
+    }
+
+    #[test]
+    fn reserved_memory_dynamic_generated_dtb() {
+        // The build script places compiled DTBs in OUT_DIR
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("reserved_memory_dynamic.dtb");
+        assert!(
+            path.exists(),
+            "{} not found. dtc is required to build DTS fixtures.",
+            path.display()
+        );
+
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let test_data_addr = test_data.as_ptr() as usize;
+        let parser = DtbParser::init(test_data_addr).unwrap();
+
+        // Static regions should not be called in this DTS
+        let mut static_called = false;
+        // Capture dynamic result
+        let mut dynamic_captured: Option<(usize, Option<usize>, Option<(usize, usize)>)> = None;
+
+        parser
+            .find_reserved_memory_node(
+                &mut |addr, size| {
+                    static_called = true;
+                    pr_debug!(
+                        "unexpected static reserved-memory: {:#x}, {:#x}",
+                        addr,
+                        size
+                    );
+                    ControlFlow::Continue(())
+                },
+                &mut |alloc_size, alignment, alloc_range| {
+                    dynamic_captured = Some((alloc_size, alignment, alloc_range));
+                    Ok(ControlFlow::Break(()))
+                },
+            )
+            .expect("failed to parse reserved-memory node");
+
+        assert!(
+            !static_called,
+            "static reserved-memory entry unexpectedly called"
+        );
+        let (alloc_size, alignment, alloc_range) =
+            dynamic_captured.expect("no dynamic reserved-memory captured");
+
+        assert_eq!(alloc_size, 0x0001_0000);
+        assert_eq!(alignment, Some(0x0001_0000));
+        assert_eq!(alloc_range, Some((0x4000_0000, 0x1000_0000)));
+    }
+
+    #[test]
+    fn find_node_property_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("psci.dtb");
+        assert!(
+            path.exists(),
+            "{} not found. dtc is required to build DTS fixtures.",
+            path.display()
+        );
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let test_data_addr = test_data.as_ptr() as usize;
+        let parser = DtbParser::init(test_data_addr).unwrap();
+
+        let mut method = None;
+        parser
+            .find_node_property(None, Some("arm,psci-0.2"), "method", &mut |value| {
+                method = Some(value);
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert_eq!(method, Some(&b"smc\0"[..]));
+
+        let mut called = false;
+        parser
+            .find_node_property(None, Some("arm,psci-0.2"), "cpu_on", &mut |_| {
+                called = true;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert!(!called);
+
+        let mut cpus = Vec::new();
+        parser
+            .find_node_properties(
+                Some("cpu"),
+                None,
+                &["reg", "enable-method", "cpu-release-addr"],
+                &mut |values| {
+                    cpus.push((values[0], values[1], values[2]));
+                    ControlFlow::Continue(())
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            cpus,
+            [
+                (Some(&[0u8, 0, 0, 0][..]), Some(&b"psci\0"[..]), None),
+                (
+                    Some(&[0, 0, 0, 1][..]),
+                    Some(&b"spin-table\0"[..]),
+                    Some(&[0, 0, 0, 0, 0, 0, 0, 0xd8][..])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn property_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("property.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+
+        let property = |name| parser.property("/serial", name).unwrap().unwrap();
+        assert_eq!(property("clock-frequency").u32(), Some(24_000_000));
+        assert!(property("dma-coherent").is_empty());
+        assert_eq!(property("status").str(), Some("okay"));
+        assert_eq!(
+            property("compatible")
+                .strings()
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["arm,pl011", "arm,primecell"]
+        );
+        assert_eq!(property("compatible").str(), None);
+        assert_eq!(
+            property("interrupts")
+                .u32_cells()
+                .unwrap()
+                .collect::<Vec<_>>(),
+            [0, 1, 4]
+        );
+        assert!(property("interrupts").u64_cells().is_none());
+        assert_eq!(property("linux,initrd-start").u64(), Some(0x4800_0000));
+        assert_eq!(property("cpu-release-addr").u64(), Some(0xd8));
+        assert_eq!(
+            property("reg").u64_cells().unwrap().collect::<Vec<_>>(),
+            [0x900_0000, 0x1000]
+        );
+        assert_eq!(property("reg").u32(), None);
+        assert_eq!(parser.property("/serial", "clocks").unwrap(), None);
+        assert_eq!(parser.property("/uart", "status").unwrap(), None);
+
+        let mut frequencies = Vec::new();
+        parser
+            .find_property(None, Some("arm,pl011"), "clock-frequency", &mut |value| {
+                frequencies.push(value.u32());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(frequencies, [Some(24_000_000)]);
+    }
+
+    #[test]
+    fn dump_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("property.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+
+        let mut dump = String::new();
+        parser.dump(&mut dump).unwrap();
+        assert_eq!(
+            dump,
+            r#"/ {
+    #address-cells = <0x2>;
+    #size-cells = <0x2>;
+    serial@9000000 {
+        compatible = "arm,pl011", "arm,primecell";
+        reg = <0x0 0x9000000 0x0 0x1000>;
+        interrupts = <0x0 0x1 0x4>;
+        clock-frequency = <0x16e3600>;
+        dma-coherent;
+        status = "okay";
+        linux,initrd-start = <0x0 0x48000000>;
+        cpu-release-addr = <0xd8>;
+    };
+};
+"#
+        );
+    }
+
+    #[test]
+    fn resolve_phandle_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("phandle.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+
+        let follow = |phandle: u32| parser.resolve_phandle(phandle).unwrap().unwrap();
+        let interrupt_parent = parser
+            .property("/", "interrupt-parent")
+            .unwrap()
+            .and_then(|p| p.u32())
+            .unwrap();
+        let intc = follow(interrupt_parent);
+        assert_eq!(parser.node_name(intc), Ok("intc@8000000"));
+        assert_eq!(
+            parser
+                .node_property(intc, "#interrupt-cells")
+                .unwrap()
+                .and_then(|p| p.u32()),
+            Some(3)
+        );
+        assert!(
+            parser
+                .node_property(intc, "interrupt-controller")
+                .unwrap()
+                .unwrap()
+                .is_empty()
+        );
+
+        let serial = |name| parser.property("/serial", name).unwrap().unwrap();
+        let clock = follow(serial("clocks").u32().unwrap());
+        assert_eq!(parser.node_name(clock), Ok("apb-pclk"));
+        assert_eq!(
+            parser
+                .node_property(clock, "clock-frequency")
+                .unwrap()
+                .and_then(|p| p.u32()),
+            Some(24_000_000)
+        );
+        assert_eq!(
+            parser.clock_frequency(serial("clocks")),
+            Ok(Some(24_000_000))
+        );
+        // not a clock, or no node at all
+        assert_eq!(parser.clock_frequency(serial("iommus")), Ok(None));
+        assert_eq!(parser.clock_frequency(serial("interrupts")), Ok(None));
+        assert!(parser.clock_frequency(Property::new(&[])).is_err());
+        // the properties of the children are not the node's
+        assert_eq!(parser.node_property(clock, "reg"), Ok(None));
+        assert_eq!(parser.node_name(follow(3)), Ok("child"));
+
+        let mut iommus = serial("iommus").u32_cells().unwrap();
+        let smmu = follow(iommus.next().unwrap());
+        assert_eq!(parser.node_name(smmu), Ok("smmu@9050000"));
+        assert_eq!(iommus.next(), Some(0x10));
+
+        assert_eq!(parser.resolve_phandle(4), Ok(None));
+        assert_eq!(parser.resolve_phandle(0), Ok(None));
+        assert_eq!(parser.resolve_phandle(u32::MAX), Ok(None));
+        assert!(parser.node_name(intc + 4).is_err());
+        assert!(parser.node_name(parser.get_size()).is_err());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::path::PathBuf;
+    #[test]
+    fn find_interrupts_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("interrupts.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+
+        let interrupts = |compatible| {
+            let mut interrupts = Vec::new();
+            parser
+                .find_interrupts(None, Some(compatible), &mut |kind, number, flags| {
+                    interrupts.push((kind, number, flags));
+                    ControlFlow::Continue(())
+                })
+                .map(|_| interrupts)
+        };
+        // the interrupt parent of the root
+        assert_eq!(interrupts("arm,pl011"), Ok(vec![(0, 1, 4)]));
+        assert_eq!(interrupts("virtio,mmio"), Ok(vec![(0, 0x10, 1)]));
+        assert_eq!(
+            interrupts("arm,armv8-timer"),
+            Ok(vec![(1, 0xd, 4), (1, 0xe, 4), (1, 0xb, 4), (1, 0xa, 4)])
+        );
+        // an interrupt controller with two cells
+        assert_eq!(interrupts("gpio-keys"), Ok(vec![(0, 3, 1), (0, 5, 2)]));
+        assert_eq!(interrupts("arm,gic-v3"), Ok(vec![]));
+        assert!(interrupts("test,broken").is_err());
+
+        let mut first = None;
+        parser
+            .find_interrupts(None, Some("arm,primecell"), &mut |kind, number, flags| {
+                first = Some((kind, number, flags));
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert_eq!(first, Some((0, 1, 4)));
+    }
 
-    const PL011_DEBUG_UART_ADDRESS: usize = 0x10_7D00_1000;
-    const PL011_DEBUG_UART_SIZE: usize = 0x200;
-    const MEMORY_ADDRESS: usize = 0x0;
-    const MEMORY_SIZE: usize = 0x2800_0000;
     #[test]
-    fn it_works() {
-        let test_data = std::fs::read("test/test.dtb").expect("failed to load dtb files");
-        let test_data_addr = test_data.as_ptr() as usize;
-        let parser = DtbParser::init(test_data_addr).unwrap();
+    fn for_each_node_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("psci.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
 
-        let mut counter = 0;
+        let mut nodes = Vec::new();
         parser
-            .find_node(None, Some("arm,pl011"), &mut |address, size| {
-                pr_debug!("find pl011 node, address: {} size: {}", address, size);
-                assert_eq!(address, PL011_DEBUG_UART_ADDRESS);
-                assert_eq!(size, PL011_DEBUG_UART_SIZE);
-                counter += 1;
+            .for_each_node(&mut |depth, name| {
+                nodes.push((depth, name));
                 ControlFlow::Continue(())
             })
             .unwrap();
-        assert_eq!(counter, 1);
+        assert_eq!(
+            nodes,
+            [
+                (0, ""),
+                (1, "psci"),
+                (1, "cpus"),
+                (2, "cpu@0"),
+                (2, "cpu@1")
+            ]
+        );
 
-        counter = 0;
+        let mut count = 0;
+        parser
+            .for_each_node(&mut |_, _| {
+                count += 1;
+                if count == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn validate_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("psci.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let validate = |data: &[u8]| DtbParser::init(data.as_ptr() as usize)?.validate();
+        assert_eq!(validate(&test_data), Ok(()));
+
+        let read = |data: &[u8], offset: usize| {
+            u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+        };
+        let write = |data: &mut [u8], offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        };
+        // size_dt_struct cut short, FDT_END is not reached
+        let mut corrupted = test_data.clone();
+        let struct_size = read(&corrupted, 36);
+        write(&mut corrupted, 36, struct_size - 4);
+        assert!(validate(&corrupted).is_err());
+        // the strings block past totalsize
+        let mut corrupted = test_data.clone();
+        let total_size = read(&corrupted, 4);
+        write(&mut corrupted, 32, total_size);
+        assert!(validate(&corrupted).is_err());
+        // the first property name past the strings block
+        let mut corrupted = test_data.clone();
+        let struct_start = read(&corrupted, 8) as usize;
+        let property = (struct_start..)
+            .step_by(4)
+            .find(|&offset| read(&corrupted, offset) == 3)
+            .unwrap();
+        let strings_size = read(&corrupted, 32);
+        write(&mut corrupted, property + 8, strings_size);
+        assert!(validate(&corrupted).is_err());
+    }
+
+    #[test]
+    fn malformed_generated_dtb_is_an_error() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("psci.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        // the searches without `validate`, which must fail rather than read past the blob
+        let search = |data: &[u8]| {
+            let parser = DtbParser::from_slice(data)?;
+            parser.find_node(Some("cpu"), None, &mut |_, _| ControlFlow::Continue(()))?;
+            parser.for_each_node(&mut |_, _| ControlFlow::Continue(()))?;
+            parser.property("/psci", "method")
+        };
+        assert!(search(&test_data).unwrap().is_some());
+
+        let read = |data: &[u8], offset: usize| {
+            u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+        };
+        let write = |data: &mut [u8], offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        };
+        let struct_start = read(&test_data, 8) as usize;
+        let property = (struct_start..)
+            .step_by(4)
+            .find(|&offset| read(&test_data, offset) == 3)
+            .unwrap();
+        // the first property value runs past the structure block
+        let mut corrupted = test_data.clone();
+        write(&mut corrupted, property + 4, 0x1000_0000);
+        assert!(search(&corrupted).is_err());
+        // the first property name past the strings block
+        let mut corrupted = test_data.clone();
+        write(&mut corrupted, property + 8, 0x1000_0000);
+        assert!(search(&corrupted).is_err());
+        // the structure block ends inside the root node
+        let mut corrupted = test_data.clone();
+        write(&mut corrupted, 36, (property - struct_start) as u32);
+        assert!(search(&corrupted).is_err());
+        // the structure block past totalsize
+        let mut corrupted = test_data.clone();
+        write(&mut corrupted, 36, read(&test_data, 4));
+        assert!(DtbParser::init(corrupted.as_ptr() as usize).is_err());
+        // the slice ends before totalsize or inside the header
+        assert!(DtbParser::from_slice(&test_data[..test_data.len() - 1]).is_err());
+        assert!(DtbParser::from_slice(&test_data[..16]).is_err());
+    }
+
+    fn generate_with_bootargs(name: &str, bootargs: &str) -> Vec<u64> {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push(name);
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+        let mut generator = DtbGenerator::new(&parser);
+        generator.set_bootargs(bootargs);
+        let (size, _) = generator.get_required_size(0);
+        // u64 backing keeps the generated dtb 8 bytes aligned
+        let mut buffer = vec![0u64; size.div_ceil(8)];
+        let dtb = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size) };
+        generator.make_dtb(dtb, &[]).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn bootargs_replaced_in_generated_dtb() {
+        let dtb = generate_with_bootargs("chosen.dtb", "console=ttyAMA0 root=/dev/vda");
+        let parser = DtbParser::init(dtb.as_ptr() as usize).unwrap();
+
+        let mut bootargs = Vec::new();
+        parser
+            .find_chosen_property("bootargs", &mut |value| bootargs.push(value))
+            .unwrap();
+        assert_eq!(bootargs, [&b"console=ttyAMA0 root=/dev/vda\0"[..]]);
+
+        let mut stdout_path = None;
+        parser
+            .find_chosen_property("stdout-path", &mut |value| stdout_path = Some(value))
+            .unwrap();
+        assert_eq!(stdout_path, Some(&b"serial0:115200n8\0"[..]));
+
+        let mut memory = None;
         parser
             .find_node(Some("memory"), None, &mut |address, size| {
-                pr_debug!("find memory node, address: {} size: {}", address, size);
-                assert_eq!(address, MEMORY_ADDRESS);
-                assert_eq!(size, MEMORY_SIZE);
-                counter += 1;
-                ControlFlow::Continue(())
+                memory = Some((address, size));
+                ControlFlow::Break(())
             })
             .unwrap();
-        assert_eq!(counter, 1);
-        counter = 0;
+        assert_eq!(memory, Some((0, 0x1000_0000)));
+    }
+
+    #[test]
+    fn bootargs_chosen_created_in_generated_dtb() {
+        let dtb = generate_with_bootargs("psci.dtb", "quiet");
+        let parser = DtbParser::init(dtb.as_ptr() as usize).unwrap();
+
+        let mut bootargs = None;
         parser
-            .find_node(None, Some("arm,gic-400"), &mut |address, size| {
-                pr_debug!("find gic node, address: {} size: {}", address, size);
-                counter += 1;
-                ControlFlow::Continue(())
+            .find_chosen_property("bootargs", &mut |value| bootargs = Some(value))
+            .unwrap();
+        assert_eq!(bootargs, Some(&b"quiet\0"[..]));
+
+        let mut method = None;
+        parser
+            .find_node_property(None, Some("arm,psci-0.2"), "method", &mut |value| {
+                method = Some(value);
+                ControlFlow::Break(())
             })
             .unwrap();
-        assert_eq!(counter, 4);
+        assert_eq!(method, Some(&b"smc\0"[..]));
     }
 
     #[test]
-    fn reserved_memory_generated_dtb() {
-        // The build script places compiled DTBs in OUT_DIR
+    fn chosen_properties_in_generated_dtb() {
         let out_dir = env!("OUT_DIR");
         let mut path = PathBuf::from(out_dir);
-        path.push("reserved_memory.dtb");
-        assert!(
-            path.exists(),
-            "{} not found. dtc is required to build DTS fixtures.",
-            path.display()
+        path.push("chosen.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+        let mut generator = DtbGenerator::new(&parser);
+        generator.set_bootargs("quiet");
+        generator
+            .set_chosen_property("stdout-path", b"serial1\0")
+            .unwrap();
+        generator
+            .set_chosen_property("test,digest", &[1, 2, 3])
+            .unwrap();
+        generator
+            .set_chosen_property("test,digest", &[4, 5, 6, 7, 8])
+            .unwrap();
+        assert!(generator.set_chosen_property("bootargs", b"x\0").is_err());
+        let (size, _) = generator.get_required_size(0);
+        let mut buffer = vec![0u64; size.div_ceil(8)];
+        let dtb = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size) };
+        generator.make_dtb(dtb, &[]).unwrap();
+        let parser = DtbParser::init(buffer.as_ptr() as usize).unwrap();
+
+        let mut values = Vec::new();
+        for name in ["bootargs", "stdout-path", "test,digest"] {
+            parser
+                .find_chosen_property(name, &mut |value| values.push(value))
+                .unwrap();
+        }
+        assert_eq!(
+            values,
+            [&b"quiet\0"[..], &b"serial1\0"[..], &[4, 5, 6, 7, 8][..]]
         );
+    }
+
+    #[test]
+    fn stdout_path_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("stdout_path.dtb");
         let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
-        let test_data_addr = test_data.as_ptr() as usize;
-        let parser = DtbParser::init(test_data_addr).unwrap();
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
 
-        let mut captured: Option<(usize, usize)> = None;
+        assert_eq!(
+            parser.stdout_path().unwrap(),
+            Some(("/soc/serial@2000", Some("115200n8")))
+        );
+
+        let mut found = None;
         parser
-            .find_reserved_memory_node(
-                &mut |addr, size| {
-                    captured = Some((addr, size));
-                    ControlFlow::Break(())
-                },
-                &mut |_, _, _| -> Result<ControlFlow<()>, ()> { unreachable!() },
+            .find_node_by_path(
+                "/soc/serial@2000",
+                &["compatible", "reg-shift"],
+                &mut |reg, values| found = Some((reg, values[0], values[1])),
             )
             .unwrap();
-        let (addr, size) = captured.expect("no reserved-memory region found");
-        assert_eq!(addr, 0x20);
-        assert_eq!(size, 0x10);
+        assert_eq!(
+            found,
+            Some((
+                Some((0x4000_2000, 0x100)),
+                Some(&b"ns16550a\0"[..]),
+                Some(&[0u8, 0, 0, 2][..])
+            ))
+        );
+
+        // a component without the unit address
+        let mut reg = None;
+        parser
+            .find_node_by_path("/soc/serial", &[], &mut |r, _| reg = r)
+            .unwrap();
+        assert_eq!(reg, Some((0x4000_1000, 0x1000)));
+
+        let mut called = false;
+        parser
+            .find_node_by_path("/serial@1000", &[], &mut |_, _| called = true)
+            .unwrap();
+        assert!(!called);
+
+        // a dtb whose `/chosen` names no console
+        let mut path = PathBuf::from(out_dir);
+        path.push("psci.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+        assert_eq!(parser.stdout_path().unwrap(), None);
     }
 
     #[test]
-    fn reserved_memory_dynamic_generated_dtb() {
-        // The build script places compiled DTBs in OUT_DIR
+    fn disabled_nodes_and_memory_in_generated_dtb() {
         let out_dir = env!("OUT_DIR");
         let mut path = PathBuf::from(out_dir);
-        path.push("reserved_memory_dynamic.dtb");
-        assert!(
-            path.exists(),
-            "{} not found. dtc is required to build DTS fixtures.",
-            path.display()
+        path.push("sanitize.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+        let mut generator = DtbGenerator::new(&parser);
+        generator.disable_node(0x900_0000).unwrap();
+        generator.disable_node(0xa00_0200).unwrap();
+        let memory = [(0x4000_0000, 0x800_0000), (0x4c00_0000, 0x400_0000)];
+        generator.set_memory(&memory);
+        let (size, _) = generator.get_required_size(0);
+        let mut buffer = vec![0u64; size.div_ceil(8)];
+        let dtb = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size) };
+        generator.make_dtb(dtb, &[]).unwrap();
+        let mut parser = DtbParser::init(buffer.as_ptr() as usize).unwrap();
+
+        // disabled nodes are left out of searches by default
+        let mut addresses = Vec::new();
+        for compatible in ["arm,pl011", "virtio,mmio"] {
+            parser
+                .find_node(None, Some(compatible), &mut |address, _| {
+                    addresses.push(address);
+                    ControlFlow::Continue(())
+                })
+                .unwrap();
+        }
+        assert_eq!(addresses, [0xa00_0000]);
+        let mut regions = [(0, 0); DtbParser::MAX_MEMORY_REGIONS];
+        let count = parser.memory_regions(&mut regions).unwrap();
+        assert_eq!(regions[..count], memory);
+        assert_eq!(
+            parser
+                .property("/pl011@9000000", "status")
+                .unwrap()
+                .and_then(|p| p.str()),
+            Some("disabled")
+        );
+
+        parser.set_include_disabled(true);
+        let mut nodes = Vec::new();
+        for compatible in ["arm,pl011", "virtio,mmio"] {
+            parser
+                .find_node_properties(None, Some(compatible), &["reg", "status"], &mut |values| {
+                    nodes.push((values[0].unwrap()[..8].to_vec(), values[1]));
+                    ControlFlow::Continue(())
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            nodes,
+            [
+                (
+                    0x900_0000u64.to_be_bytes().to_vec(),
+                    Some(&b"disabled\0"[..])
+                ),
+                (0xa00_0000u64.to_be_bytes().to_vec(), None),
+                (
+                    0xa00_0200u64.to_be_bytes().to_vec(),
+                    Some(&b"disabled\0"[..])
+                ),
+            ]
         );
 
+        let mut memory_nodes = Vec::new();
+        parser
+            .find_node_properties(Some("memory"), None, &["reg", "status"], &mut |values| {
+                memory_nodes.push((values[0].unwrap().to_vec(), values[1]));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        let reg: Vec<u8> = memory
+            .iter()
+            .flat_map(|&(address, size)| [address as u64, size as u64])
+            .flat_map(u64::to_be_bytes)
+            .collect();
+        assert_eq!(memory_nodes.len(), 2);
+        assert_eq!(memory_nodes[0], (reg, None));
+        assert_eq!(memory_nodes[1].1, Some(&b"disabled\0"[..]));
+    }
+
+    #[test]
+    fn cpus_pruned_in_generated_dtb() {
+        let path = PathBuf::from(env!("OUT_DIR")).join("cpus.dtb");
         let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
-        let test_data_addr = test_data.as_ptr() as usize;
-        let parser = DtbParser::init(test_data_addr).unwrap();
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+        let generate = |cpus: &[u64]| {
+            let mut generator = DtbGenerator::new(&parser);
+            generator.keep_cpus(cpus);
+            let (size, _) = generator.get_required_size(0);
+            let mut buffer = vec![0u64; size.div_ceil(8)];
+            let dtb =
+                unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size) };
+            generator.make_dtb(dtb, &[]).unwrap();
+            buffer
+        };
+        let cpus = |buffer: &[u64]| {
+            let parser = DtbParser::init(buffer.as_ptr() as usize).unwrap();
+            parser.validate().unwrap();
+            let mut cpus = Vec::new();
+            parser
+                .find_node_properties(Some("cpu"), None, &["reg"], &mut |values| {
+                    cpus.push(u32::from_be_bytes(values[0].unwrap().try_into().unwrap()));
+                    ControlFlow::Continue(())
+                })
+                .unwrap();
+            let cpu_map = parser.node_offset("/cpus/cpu-map").unwrap().is_some();
+            let mut regions = [(0, 0); DtbParser::MAX_MEMORY_REGIONS];
+            let count = parser.memory_regions(&mut regions).unwrap();
+            assert_eq!(regions[..count], [(0x4000_0000, 0x1000_0000)]);
+            (cpus, cpu_map)
+        };
 
-        // Static regions should not be called in this DTS
-        let mut static_called = false;
-        // Capture dynamic result
-        let mut dynamic_captured: Option<(usize, Option<usize>, Option<(usize, usize)>)> = None;
+        assert_eq!(cpus(&generate(&[0x0, 0x100])), (vec![0x0, 0x100], false));
+        // the topology stays while every CPU does
+        assert_eq!(
+            cpus(&generate(&[0x0, 0x1, 0x100, 0x101])),
+            (vec![0x0, 0x1, 0x100, 0x101], true)
+        );
+    }
+
+    #[test]
+    fn overlay_applied_to_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let read = |name| std::fs::read(PathBuf::from(out_dir).join(name)).unwrap();
+        let base = read("overlay_base.dtb");
+        let overlay = read("overlay.dtb");
+        let parser = DtbParser::init(base.as_ptr() as usize).unwrap();
+        let overlay = DtbParser::init(overlay.as_ptr() as usize).unwrap();
+        let mut generator = DtbGenerator::new(&parser);
+        generator.apply_overlay(&overlay).unwrap();
+        assert!(generator.apply_overlay(&overlay).is_err());
+        generator.set_bootargs("console=ttyAMA0");
+        let (size, _) = generator.get_required_size(0);
+        let mut buffer = vec![0u64; size.div_ceil(8)];
+        let dtb = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size) };
+        generator.make_dtb(dtb, &[]).unwrap();
+        let parser = DtbParser::init(buffer.as_ptr() as usize).unwrap();
+        parser.validate().unwrap();
+
+        // the last value of a property, a duplicate would come after the overlay one
+        let property = |path, name| parser.property(path, name).unwrap().unwrap();
+        assert_eq!(property("/soc/serial", "status").str(), Some("okay"));
+        // fragment@3 comes after fragment@0
+        assert_eq!(property("/soc/serial", "current-speed").u32(), Some(9600));
+        assert_eq!(property("/soc/serial", "clocks").u32(), Some(1));
+        assert_eq!(
+            property("/apb-pclk", "clock-frequency").u32(),
+            Some(48_000_000)
+        );
+        assert_eq!(
+            property("/chosen", "bootargs").str(),
+            Some("console=ttyAMA0")
+        );
+
+        // both fragments add `bluetooth`, the phandles of the overlay follow those of the base
+        let bluetooth = |name| property("/soc/serial/bluetooth", name);
+        assert_eq!(bluetooth("compatible").str(), Some("test,bluetooth"));
+        assert_eq!(bluetooth("max-speed").u32(), Some(3_000_000));
+        let follow = |phandle: Property| {
+            let node = parser.resolve_phandle(phandle.u32().unwrap()).unwrap();
+            parser.node_name(node.unwrap()).unwrap()
+        };
+        assert_eq!(bluetooth("vdd-supply").u32(), Some(3));
+        assert_eq!(follow(bluetooth("vdd-supply")), "regulator-fixed");
+        assert_eq!(follow(bluetooth("clocks")), "apb-pclk");
+
+        let mut extra = Vec::new();
+        parser
+            .find_node(None, Some("test,extra"), &mut |address, size| {
+                extra.push((address, size));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(extra, [(0xa00_0000, 0x100)]);
+
+        let mut names = Vec::new();
+        parser
+            .for_each_node(&mut |_, name| {
+                names.push(name);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(
+            names,
+            [
+                "",
+                "apb-pclk",
+                "soc",
+                "serial@9000000",
+                "bluetooth",
+                "extra@a000000",
+                "__symbols__",
+                "regulator-fixed",
+                "chosen"
+            ]
+        );
+
+        // the labels of `__fixups__` need the `__symbols__` of the base
+        let out_dir = PathBuf::from(out_dir);
+        let base = std::fs::read(out_dir.join("property.dtb")).unwrap();
+        let parser = DtbParser::init(base.as_ptr() as usize).unwrap();
+        assert!(DtbGenerator::new(&parser).apply_overlay(&overlay).is_err());
+    }
+
+    fn reserved_regions_with_added_node(name: &str) -> Vec<(usize, usize)> {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push(name);
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+        let mut generator = DtbGenerator::new(&parser);
+        generator
+            .add_reserved_memory("crashkernel", 0x1000_0000, 0x200_0000)
+            .unwrap();
+        let (size, _) = generator.get_required_size(0);
+        let mut buffer = vec![0u64; size.div_ceil(8)];
+        let dtb = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size) };
+        generator.make_dtb(dtb, &[]).unwrap();
+        let parser = DtbParser::init(buffer.as_ptr() as usize).unwrap();
+        parser.validate().unwrap();
 
+        let mut names = Vec::new();
+        parser
+            .for_each_node(&mut |_, name| {
+                names.push(name.to_string());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert!(names.iter().any(|name| name == "crashkernel@10000000"));
+        let mut regions = Vec::new();
         parser
             .find_reserved_memory_node(
-                &mut |addr, size| {
-                    static_called = true;
-                    pr_debug!(
-                        "unexpected static reserved-memory: {:#x}, {:#x}",
-                        addr,
-                        size
-                    );
+                &mut |address, size| {
+                    regions.push((address, size));
                     ControlFlow::Continue(())
                 },
-                &mut |alloc_size, alignment, alloc_range| {
-                    dynamic_captured = Some((alloc_size, alignment, alloc_range));
-                    Ok(ControlFlow::Break(()))
-                },
+                &mut |_, _, _| -> Result<ControlFlow<()>, ()> { unreachable!() },
             )
-            .expect("failed to parse reserved-memory node");
+            .unwrap();
+        regions
+    }
 
-        assert!(
-            !static_called,
-            "static reserved-memory entry unexpectedly called"
+    #[test]
+    fn reserved_memory_node_added_to_generated_dtb() {
+        // `/reserved-memory` is created
+        assert_eq!(
+            reserved_regions_with_added_node("psci.dtb"),
+            [(0x1000_0000, 0x200_0000)]
         );
-        let (alloc_size, alignment, alloc_range) =
-            dynamic_captured.expect("no dynamic reserved-memory captured");
+        // the node goes after the existing ones
+        assert_eq!(
+            reserved_regions_with_added_node("reserved_memory.dtb"),
+            [(0x20, 0x10), (0x1000_0000, 0x200_0000)]
+        );
+    }
 
-        assert_eq!(alloc_size, 0x0001_0000);
-        assert_eq!(alignment, Some(0x0001_0000));
-        assert_eq!(alloc_range, Some((0x4000_0000, 0x1000_0000)));
+    #[test]
+    fn memory_regions_generated_dtb() {
+        let out_dir = env!("OUT_DIR");
+        let mut path = PathBuf::from(out_dir);
+        path.push("memory.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+
+        // adjacent nodes merged, a node found by name only, one behind `ranges`, then
+        // clipped to 0x4800_0000..0x1_4800_0000
+        let mut regions = [(0, 0); DtbParser::MAX_MEMORY_REGIONS];
+        let count = parser.memory_regions(&mut regions).unwrap();
+        assert_eq!(
+            &regions[..count],
+            &[
+                (0x4800_0000, 0x2800_0000),
+                (0x8000_0000, 0x100_0000),
+                (0xc000_0000, 0x200_0000),
+                (0x1_0000_0000, 0x4800_0000),
+            ]
+        );
+
+        let mut short = [(0, 0); 3];
+        assert!(parser.memory_regions(&mut short).is_err());
+
+        let mut path = PathBuf::from(out_dir);
+        path.push("chosen.dtb");
+        let test_data = std::fs::read(&path).expect("failed to load generated dtb file");
+        let parser = DtbParser::init(test_data.as_ptr() as usize).unwrap();
+        let count = parser.memory_regions(&mut regions).unwrap();
+        assert_eq!(&regions[..count], &[(0, 0x1000_0000)]);
     }
 }
 
@@ -1245,7 +4474,13 @@ macro_rules! pr_debug {
     ($fmt:expr, $($arg:tt)*) => (println!($fmt, $($arg)*));
 }
 
-#[cfg(not(test))]
+#[cfg(all(not(test), target_arch = "aarch64"))]
+#[macro_export]
+macro_rules! pr_debug {
+    ($($arg:tt)*) => (::arch_hal::log_debug!($($arg)*));
+}
+
+#[cfg(all(not(test), not(target_arch = "aarch64")))]
 #[macro_export]
 macro_rules! pr_debug {
     ($fmt:expr) => {};
@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(unused)]
 
 use core::cmp::min;
@@ -8,6 +8,8 @@ use typestate_macro::RawReg;
 #[allow(clippy::assertions_on_constants)]
 const _: () = assert!(size_of::<Elf64Header>() == 64);
 const _: () = assert!(size_of::<Elf64ProgramHeader>() == 56);
+const _: () = assert!(size_of::<Elf64Dyn>() == 16);
+const _: () = assert!(size_of::<Elf64Rela>() == 24);
 
 type Elf64Addr = u64;
 type Elf64Off = u64;
@@ -20,7 +22,7 @@ type Elf64Sxword = i64;
 #[repr(C)]
 struct Elf64Header {
     e_ident: ElfHeaderIdent,   // elf identification
-    e_type: Elf64Half,         // Object File Type
+    e_type: ElfType,           // Object File Type
     e_machine: ElfMachineType, // Machine Type
     e_version: Elf64Word,      // Object File Version
     e_entry: Elf64Addr,        // Entry Point Address
@@ -35,6 +37,15 @@ struct Elf64Header {
     e_shstrndx: Elf64Half,     // Section Name String Table Index
 }
 
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, RawReg, PartialEq, Eq)]
+struct ElfType(Elf64Half);
+
+impl ElfType {
+    const ET_EXEC: Self = Self(2);
+    const ET_DYN: Self = Self(3);
+}
+
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, RawReg, PartialEq, Eq)]
 struct ElfMachineType(Elf64Half);
@@ -44,6 +55,41 @@ impl ElfMachineType {
     const EM_AARCH64: Self = Self(183);
 }
 
+/// architecture an ELF is built for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Machine {
+    X86_64,
+    Aarch64,
+}
+
+impl Machine {
+    /// the architecture this code runs on, if it is supported
+    pub const fn current() -> Option<Self> {
+        if cfg!(target_arch = "x86_64") {
+            Some(Self::X86_64)
+        } else if cfg!(target_arch = "aarch64") {
+            Some(Self::Aarch64)
+        } else {
+            None
+        }
+    }
+
+    const fn raw(self) -> ElfMachineType {
+        match self {
+            Self::X86_64 => ElfMachineType::EM_X86_64,
+            Self::Aarch64 => ElfMachineType::EM_AARCH64,
+        }
+    }
+
+    // `r_type` of a relocation adding the load bias to its addend
+    const fn relative_relocation(self) -> u32 {
+        match self {
+            Self::X86_64 => 8,     // R_X86_64_RELATIVE
+            Self::Aarch64 => 1027, // R_AARCH64_RELATIVE
+        }
+    }
+}
+
 #[repr(C)]
 struct ElfHeaderIdent {
     magic: [u8; 4],  // File Identification
@@ -67,6 +113,29 @@ struct Elf64ProgramHeader {
     p_align: Elf64Xword,           // Alignment Of Segment
 }
 
+#[repr(C)]
+struct Elf64Dyn {
+    d_tag: Elf64Sxword, // Dynamic Entry Type
+    d_val: Elf64Xword,  // Integer Or Address Value
+}
+
+impl Elf64Dyn {
+    const DT_NULL: i64 = 0;
+    const DT_PLTRELSZ: i64 = 2;
+    const DT_RELA: i64 = 7;
+    const DT_RELASZ: i64 = 8;
+    const DT_RELAENT: i64 = 9;
+    const DT_REL: i64 = 17;
+    const DT_RELR: i64 = 36;
+}
+
+#[repr(C)]
+struct Elf64Rela {
+    r_offset: Elf64Addr,   // Address Of Reference
+    r_info: Elf64Xword,    // Symbol Index And Type Of Relocation
+    r_addend: Elf64Sxword, // Constant Part Of Expression
+}
+
 #[repr(transparent)]
 #[derive(Clone, Copy, RawReg, PartialEq)]
 struct ElfProgramHeaderTypes(Elf64Word);
@@ -116,8 +185,29 @@ pub struct ProgramHeaderData {
     align: u64,
 }
 
+impl ProgramHeaderData {
+    pub fn permission(&self) -> ElfPermissions {
+        self.permission
+    }
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+    pub fn mem_len(&self) -> u64 {
+        self.mem_len
+    }
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    pub fn align(&self) -> u64 {
+        self.align
+    }
+}
+
 #[repr(transparent)]
-#[derive(Clone, Copy, Debug, RawReg)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, RawReg)]
 pub struct ElfPermissions(u8);
 
 impl ElfPermissions {
@@ -131,6 +221,7 @@ impl ElfPermissions {
 pub struct Elf64<'a> {
     data: &'a [u8],
     endian: ElfEndian,
+    machine: Machine,
 }
 
 impl<'a> Elf64<'a> {
@@ -154,6 +245,15 @@ impl<'a> Elf64<'a> {
     ///  - slice size is lager than elf_header_size().0 /* size */
     ///  - slice head is algined elf_header_size().1 /* alignment */
     pub unsafe fn new(elf: &'a [u8]) -> Result<Self, ElfErr> {
+        let machine = Machine::current().ok_or(ElfErr::Unsupported)?;
+        unsafe { Self::new_for_machine(elf, machine) }
+    }
+
+    /// `new` for an ELF of `machine` instead of the running one, for host tools
+    ///
+    /// # Safety
+    ///  same as `new`
+    pub unsafe fn new_for_machine(elf: &'a [u8], machine: Machine) -> Result<Self, ElfErr> {
         if elf.len() < Self::elf_header_size().0 {
             return Err(ElfErr::TooShort);
         }
@@ -175,14 +275,11 @@ impl<'a> Elf64<'a> {
             return Err(ElfErr::Unsupported);
         }
         let e_type = read(header.e_type, endian);
-        if e_type != 2 {
-            // 2: Executable File
+        if e_type != ElfType::ET_EXEC && e_type != ElfType::ET_DYN {
             return Err(ElfErr::Unsupported);
         }
-        match read(header.e_machine, endian) {
-            ElfMachineType::EM_X86_64 if cfg!(target_arch = "x86_64") => {}
-            ElfMachineType::EM_AARCH64 if cfg!(target_arch = "aarch64") => {}
-            _ => return Err(ElfErr::Unsupported),
+        if read(header.e_machine, endian) != machine.raw() {
+            return Err(ElfErr::Unsupported);
         }
         if read(header.e_version, endian) != 1 {
             return Err(ElfErr::Unsupported);
@@ -193,26 +290,157 @@ impl<'a> Elf64<'a> {
         if read(header.e_phentsize, endian) != size_of::<Elf64ProgramHeader>() as u16 {
             return Err(ElfErr::Invalid);
         }
-        Ok(Self { data: elf, endian })
+        Ok(Self {
+            data: elf,
+            endian,
+            machine,
+        })
     }
 
-    pub fn iterate_program_header<F>(&self, mut f: F) -> Result<(), ElfErr>
-    where
-        F: FnMut(&ProgramHeaderData),
-    {
+    pub fn is_big_endian(&self) -> bool {
+        matches!(self.endian, ElfEndian::Big)
+    }
+
+    /// entry point address (`e_entry`)
+    pub fn entry(&self) -> u64 {
+        let header = unsafe { &*(self.data.as_ptr() as *const Elf64Header) };
+        read(header.e_entry, self.endian)
+    }
+
+    /// a position independent executable (`ET_DYN`), which runs at any address once its
+    /// relative relocations are applied
+    pub fn is_position_independent(&self) -> bool {
+        let header = unsafe { &*(self.data.as_ptr() as *const Elf64Header) };
+        read(header.e_type, self.endian) == ElfType::ET_DYN
+    }
+
+    fn program_headers(&self) -> Result<&[Elf64ProgramHeader], ElfErr> {
         if self.data.len() < self.elf_real_header_size().0 {
             return Err(ElfErr::TooShort);
         }
         let header = unsafe { &*(self.data.as_ptr() as *const Elf64Header) };
-        for i in 0..read(header.e_phnum, self.endian) as usize {
-            let program_header = unsafe {
-                &*((self.data.as_ptr() as usize
-                    + read(header.e_phoff, self.endian) as usize
-                    + i * size_of::<Elf64ProgramHeader>())
-                    as *const Elf64ProgramHeader)
-            };
+        let program_header =
+            self.data.as_ptr() as usize + read(header.e_phoff, self.endian) as usize;
+        if !program_header.is_multiple_of(align_of::<Elf64ProgramHeader>()) {
+            return Err(ElfErr::Invalid);
+        }
+        Ok(unsafe {
+            core::slice::from_raw_parts(
+                program_header as *const Elf64ProgramHeader,
+                read(header.e_phnum, self.endian) as usize,
+            )
+        })
+    }
+
+    // the `count` entries of `T` in the file at the virtual address `address`
+    fn table_at<T>(&self, address: u64, count: usize) -> Result<&[T], ElfErr> {
+        let size = count.checked_mul(size_of::<T>()).ok_or(ElfErr::Invalid)? as u64;
+        let segment = self
+            .program_headers()?
+            .iter()
+            .filter(|segment| read(segment.p_type, self.endian) == ElfProgramHeaderTypes::PT_LOAD)
+            .find(|segment| {
+                let start = read(segment.p_vaddr, self.endian);
+                address >= start
+                    && address.saturating_add(size)
+                        <= start.saturating_add(read(segment.p_filesz, self.endian))
+            })
+            .ok_or(ElfErr::Invalid)?;
+        let offset =
+            read(segment.p_offset, self.endian) + address - read(segment.p_vaddr, self.endian);
+        self.table(offset, size)
+    }
+
+    // the entries of `T` in the `size` bytes at file offset `offset`
+    fn table<T>(&self, offset: u64, size: u64) -> Result<&[T], ElfErr> {
+        let end = offset.checked_add(size).ok_or(ElfErr::Invalid)?;
+        if end > self.data.len() as u64 {
+            return Err(ElfErr::TooShort);
+        }
+        let table = self.data.as_ptr() as usize + offset as usize;
+        if !table.is_multiple_of(align_of::<T>()) {
+            return Err(ElfErr::Invalid);
+        }
+        Ok(unsafe {
+            core::slice::from_raw_parts(table as *const T, size as usize / size_of::<T>())
+        })
+    }
+
+    /// Call `f` with the (address, addend) of every relocation of a position independent
+    /// executable: moved by `bias` bytes, the ELF runs once `addend + bias` is stored as a
+    /// 64-bit word at `address + bias`. `address` is a virtual address, every segment must be
+    /// linked at the same physical address.
+    /// Relocations which need a symbol are `Unsupported`, a static PIE has none
+    pub fn iterate_relocations<F>(&self, mut f: F) -> Result<(), ElfErr>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut dynamic = None;
+        for segment in self.program_headers()? {
+            match read(segment.p_type, self.endian) {
+                ElfProgramHeaderTypes::PT_DYNAMIC => dynamic = Some(segment),
+                ElfProgramHeaderTypes::PT_LOAD
+                    if read(segment.p_vaddr, self.endian) != read(segment.p_paddr, self.endian) =>
+                {
+                    return Err(ElfErr::Unsupported);
+                }
+                _ => {}
+            }
+        }
+        let Some(dynamic) = dynamic else {
+            return Ok(());
+        };
+
+        let (mut rela, mut rela_size, mut rela_entry) = (None, 0, size_of::<Elf64Rela>() as u64);
+        let entries = self.table::<Elf64Dyn>(
+            read(dynamic.p_offset, self.endian),
+            read(dynamic.p_filesz, self.endian),
+        )?;
+        for entry in entries {
+            let value = read(entry.d_val, self.endian);
+            match read(entry.d_tag, self.endian) {
+                Elf64Dyn::DT_NULL => break,
+                Elf64Dyn::DT_RELA => rela = Some(value),
+                Elf64Dyn::DT_RELASZ => rela_size = value,
+                Elf64Dyn::DT_RELAENT => rela_entry = value,
+                Elf64Dyn::DT_PLTRELSZ | Elf64Dyn::DT_REL | Elf64Dyn::DT_RELR if value != 0 => {
+                    return Err(ElfErr::Unsupported);
+                }
+                _ => {}
+            }
+        }
+        let Some(rela) = rela else {
+            return Ok(());
+        };
+        if rela_entry != size_of::<Elf64Rela>() as u64 || !rela_size.is_multiple_of(rela_entry) {
+            return Err(ElfErr::Invalid);
+        }
+
+        let relative = self.machine.relative_relocation();
+        for relocation in self.table_at::<Elf64Rela>(rela, (rela_size / rela_entry) as usize)? {
+            let r_type = read(relocation.r_info, self.endian) as u32;
+            match r_type {
+                // R_*_NONE
+                0 => {}
+                _ if r_type == relative => f(
+                    read(relocation.r_offset, self.endian),
+                    read(relocation.r_addend, self.endian) as u64,
+                ),
+                _ => return Err(ElfErr::Unsupported),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn iterate_program_header<F>(&self, mut f: F) -> Result<(), ElfErr>
+    where
+        F: FnMut(&ProgramHeaderData),
+    {
+        for program_header in self.program_headers()? {
             match read(program_header.p_type, self.endian) {
                 ElfProgramHeaderTypes::PT_LOAD => {}
+                // the relocations of a PIE are applied by `iterate_relocations`
+                ElfProgramHeaderTypes::PT_DYNAMIC if self.is_position_independent() => continue,
                 ElfProgramHeaderTypes::PT_INTERP | ElfProgramHeaderTypes::PT_DYNAMIC => {
                     return Err(ElfErr::Invalid);
                 }
@@ -263,3 +491,122 @@ pub enum ElfErr {
     Unsupported,
     Invalid,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R_AARCH64_ABS64: u64 = 257;
+    const R_AARCH64_RELATIVE: u64 = 1027;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // a little endian aarch64 ELF of `e_type` linked at 0: one segment covering the file, and
+    // a dynamic section listing `relocations` as (r_offset, r_info, r_addend)
+    fn elf(e_type: u16, relocations: &[(u64, u64, i64)]) -> Vec<u64> {
+        const PHDRS: usize = 64;
+        const DYNAMIC: usize = PHDRS + 2 * 56;
+        const RELA: usize = DYNAMIC + 4 * 16;
+        let size = RELA + relocations.len() * 24;
+        let mut image = vec![0u8; size];
+        put(&mut image, 0, &[0x7F, b'E', b'L', b'F', 2, 1, 1]);
+        put(&mut image, 16, &e_type.to_le_bytes());
+        put(&mut image, 18, &183u16.to_le_bytes());
+        put(&mut image, 20, &1u32.to_le_bytes());
+        put(&mut image, 24, &0x40u64.to_le_bytes());
+        put(&mut image, 32, &(PHDRS as u64).to_le_bytes());
+        put(&mut image, 52, &64u16.to_le_bytes());
+        put(&mut image, 54, &56u16.to_le_bytes());
+        put(&mut image, 56, &2u16.to_le_bytes());
+        for (i, (p_type, offset, len)) in [(1u32, 0, size), (2, DYNAMIC, RELA - DYNAMIC)]
+            .into_iter()
+            .enumerate()
+        {
+            let phdr = PHDRS + i * 56;
+            put(&mut image, phdr, &p_type.to_le_bytes());
+            put(&mut image, phdr + 4, &0b110u32.to_le_bytes());
+            for (field, value) in [offset, offset, offset, len, len, 8]
+                .into_iter()
+                .enumerate()
+            {
+                put(
+                    &mut image,
+                    phdr + 8 + field * 8,
+                    &(value as u64).to_le_bytes(),
+                );
+            }
+        }
+        let dynamic = [
+            (7i64, RELA as u64),
+            (8, (relocations.len() * 24) as u64),
+            (9, 24),
+            (0, 0),
+        ];
+        for (i, (tag, value)) in dynamic.into_iter().enumerate() {
+            put(&mut image, DYNAMIC + i * 16, &tag.to_le_bytes());
+            put(&mut image, DYNAMIC + i * 16 + 8, &value.to_le_bytes());
+        }
+        for (i, &(r_offset, r_info, r_addend)) in relocations.iter().enumerate() {
+            put(&mut image, RELA + i * 24, &r_offset.to_le_bytes());
+            put(&mut image, RELA + i * 24 + 8, &r_info.to_le_bytes());
+            put(&mut image, RELA + i * 24 + 16, &r_addend.to_le_bytes());
+        }
+        // u64 backing for the alignment of the headers
+        image
+            .chunks(8)
+            .map(|word| {
+                let mut bytes = [0u8; 8];
+                bytes[..word.len()].copy_from_slice(word);
+                u64::from_le_bytes(bytes)
+            })
+            .collect()
+    }
+
+    fn bytes(words: &[u64], len: usize) -> &[u8] {
+        let bytes =
+            unsafe { core::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8) };
+        &bytes[..len]
+    }
+
+    fn relocations(elf: &Elf64) -> Result<Vec<(u64, u64)>, ElfErr> {
+        let mut relocations = Vec::new();
+        elf.iterate_relocations(|address, addend| relocations.push((address, addend)))?;
+        Ok(relocations)
+    }
+
+    #[test]
+    fn pie_lists_its_relative_relocations() {
+        let relocations_in = [
+            (0x10, R_AARCH64_RELATIVE, 0x40),
+            (0x18, 0, 0),
+            (0x20, R_AARCH64_RELATIVE, 0x1000),
+        ];
+        let words = elf(3, &relocations_in);
+        let data = bytes(&words, 240 + 3 * 24);
+        let elf = unsafe { Elf64::new_for_machine(data, Machine::Aarch64) }.unwrap();
+        assert!(elf.is_position_independent());
+        let mut segments = 0;
+        elf.iterate_program_header(|_| segments += 1).unwrap();
+        assert_eq!(segments, 1);
+        assert_eq!(relocations(&elf), Ok(vec![(0x10, 0x40), (0x20, 0x1000)]));
+    }
+
+    #[test]
+    fn relocation_with_a_symbol_is_unsupported() {
+        let words = elf(3, &[(0x10, (1 << 32) | R_AARCH64_ABS64, 0)]);
+        let data = bytes(&words, 240 + 24);
+        let elf = unsafe { Elf64::new_for_machine(data, Machine::Aarch64) }.unwrap();
+        assert_eq!(relocations(&elf), Err(ElfErr::Unsupported));
+    }
+
+    #[test]
+    fn executable_with_a_dynamic_section_is_invalid() {
+        let words = elf(2, &[(0x10, R_AARCH64_RELATIVE, 0x40)]);
+        let data = bytes(&words, 240 + 24);
+        let elf = unsafe { Elf64::new_for_machine(data, Machine::Aarch64) }.unwrap();
+        assert!(!elf.is_position_independent());
+        assert_eq!(elf.iterate_program_header(|_| {}), Err(ElfErr::Invalid));
+    }
+}
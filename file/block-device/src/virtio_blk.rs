@@ -1,10 +1,12 @@
 use core::cell::OnceCell;
 use core::mem::MaybeUninit;
 use core::mem::size_of;
+use core::time::Duration;
 
 use block_device_api::BlockDevice;
 use block_device_api::IoError;
 use block_device_api::Lba;
+use cpu::timer::with_timeout;
 use typestate::Le;
 use typestate::Readable;
 use virtio::VirtIoCore;
@@ -24,6 +26,9 @@ use crate::virtio_blk::operation::VirtioBlkReqType;
 use virtio::cache::clean_dcache_range;
 use virtio::cache::invalidate_dcache_range;
 
+// a device which has not completed a request by then is considered dead
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct VirtIoBlk {
     virtio: VirtIoCore<VirtIoMmio>,
     is_readonly: OnceCell<bool>,
@@ -105,7 +110,8 @@ impl BlockDevice for VirtIoBlk {
     fn init(&mut self) -> Result<(), IoError> {
         let adapter = VirtIoBlkAdapter::new();
         self.virtio.init(&adapter).map_err(error_from)?;
-        self.is_readonly.set(adapter.is_read_only()).unwrap();
+        // init runs again when the device is recovered
+        self.is_readonly = OnceCell::from(adapter.is_read_only());
         Ok(())
     }
 
@@ -236,15 +242,9 @@ impl BlockDevice for VirtIoBlk {
             self.virtio
                 .set_and_notify(0, first_desc_idx)
                 .map_err(error_from)?;
-            let (idx, _len) = loop {
-                match self.virtio.pop_used(0).map_err(error_from)? {
-                    Some(v) => break v,
-                    None => {
-                        core::hint::spin_loop();
-                        continue;
-                    }
-                }
-            };
+            let (idx, _len) = with_timeout(REQUEST_TIMEOUT, || self.virtio.pop_used(0).transpose())
+                .map_err(|_| IoError::Timeout)?
+                .map_err(error_from)?;
 
             if idx != first_desc_idx {
                 return Err(IoError::Io);
@@ -376,15 +376,9 @@ impl VirtIoBlk {
             self.virtio
                 .set_and_notify(0, first_desc_idx)
                 .map_err(error_from)?;
-            let (idx, _len) = loop {
-                match self.virtio.pop_used(0).map_err(error_from)? {
-                    Some(v) => break v,
-                    None => {
-                        core::hint::spin_loop();
-                        continue;
-                    }
-                }
-            };
+            let (idx, _len) = with_timeout(REQUEST_TIMEOUT, || self.virtio.pop_used(0).transpose())
+                .map_err(|_| IoError::Timeout)?
+                .map_err(error_from)?;
 
             if idx != first_desc_idx {
                 return Err(IoError::Io);
@@ -441,6 +435,6 @@ fn error_from(e: VirtioErr) -> IoError {
         VirtioErr::OutOfAvailableDesc => IoError::Busy,
 
         // 内部キュー破損＝一般化して Corrupted
-        VirtioErr::QueueCorrupted => IoError::Corrupted,
+        VirtioErr::QueueCorrupted(_) => IoError::Corrupted,
     }
 }
@@ -7,7 +7,9 @@ compile_error!("This test is intended to run on aarch64 targets only");
 use arch_hal::debug_uart;
 use arch_hal::exit_failure;
 use arch_hal::exit_success;
+use arch_hal::panic_report;
 use arch_hal::println;
+use arch_hal::uart::UartConfig;
 use block_device::VirtIoBlk;
 use block_device_api::BlockDevice;
 use core::mem::MaybeUninit;
@@ -17,7 +19,7 @@ const VIRTIO_MMIO_BASE: usize = 0x0a00_0000;
 
 #[unsafe(no_mangle)]
 extern "C" fn efi_main() -> ! {
-    debug_uart::init(0x900_0000);
+    debug_uart::init(0x900_0000, None);
     match run() {
         Ok(()) => {
             println!("virtio-blk modern interface test: PASS");
@@ -64,6 +66,7 @@ fn run() -> Result<(), &'static str> {
 
 #[panic_handler]
 fn panic_handler(info: &core::panic::PanicInfo<'_>) -> ! {
-    println!("PANIC: {}", info);
+    let mut console = panic_report::console(UartConfig::pl011(0x900_0000, None), 115200);
+    panic_report::report(&mut console, info);
     exit_failure()
 }
@@ -83,6 +83,20 @@ impl<T: BytePod> AlignedSliceBox<MaybeUninit<T>> {
     }
 }
 
+impl<T: BytePod> AlignedSliceBox<T> {
+    /// zero is a valid `BytePod`
+    pub fn new_zeroed_with_align(len: usize, align: usize) -> Result<Self, &'static str> {
+        let mut data = Self::new_uninit_with_align(len, align)?;
+        data.deref_uninit_u8_mut().fill(MaybeUninit::new(0));
+        Ok(unsafe { data.assume_init() })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        let byte_len = self.len * size_of::<T>();
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr() as *const u8, byte_len) }
+    }
+}
+
 impl<T> Deref for AlignedSliceBox<T> {
     type Target = [T];
     #[inline]
@@ -1,5 +1,10 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::bootsector::mbr::MasterBootRecordPartitionKind;
+use crate::partition::Guid;
 
+pub(crate) mod gpt;
 pub(crate) mod mbr;
 
 pub(crate) struct MBRPartition {
@@ -12,8 +17,21 @@ pub(crate) struct MBRConfig {
     pub(crate) partition: [MBRPartition; 4],
 }
 
+pub(crate) struct GPTPartition {
+    pub(crate) index: u8,
+    pub(crate) type_guid: Guid,
+    pub(crate) unique_guid: Guid,
+    pub(crate) first_sector: u64,
+    pub(crate) total_sector: u64,
+    pub(crate) name: String,
+}
+
+pub(crate) struct GPTConfig {
+    pub(crate) partition: Vec<GPTPartition>,
+}
+
 pub(crate) enum BootSector {
     MBR(MBRConfig),
-    GPT,
+    GPT(GPTConfig),
     Unknown,
 }
@@ -0,0 +1,126 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use block_device_api::BlockDevice;
+use core::mem::size_of;
+use core::ptr::addr_of;
+use typestate::Le;
+use typestate::Unaligned;
+use typestate::unalign_read;
+
+use crate::FileSystemErr;
+use crate::aligned_box::AlignedSliceBox;
+use crate::bootsector::GPTConfig;
+use crate::bootsector::GPTPartition;
+use crate::from_io_err;
+use crate::partition::Guid;
+
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(size_of::<GPTHeader>() == 92);
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(size_of::<GPTPartitionEntry>() == 128);
+
+// the partition index is a u8, and 128 is what every partitioning tool writes
+const MAX_ENTRIES: u32 = 128;
+
+/// The header at LBA 1. The CRCs are not checked
+#[repr(C, packed)]
+pub(crate) struct GPTHeader {
+    signature: [u8; 8],
+    _revision: Le<Unaligned<u32>>,
+    _header_size: Le<Unaligned<u32>>,
+    _header_crc32: Le<Unaligned<u32>>,
+    _reserved: Le<Unaligned<u32>>,
+    _my_lba: Le<Unaligned<u64>>,
+    _alternate_lba: Le<Unaligned<u64>>,
+    _first_usable_lba: Le<Unaligned<u64>>,
+    _last_usable_lba: Le<Unaligned<u64>>,
+    _disk_guid: [u8; 16],
+    partition_entry_lba: Le<Unaligned<u64>>,
+    num_of_partition_entries: Le<Unaligned<u32>>,
+    size_of_partition_entry: Le<Unaligned<u32>>,
+    _partition_entry_array_crc32: Le<Unaligned<u32>>,
+}
+
+impl GPTHeader {
+    const SIGNATURE: [u8; 8] = *b"EFI PART";
+}
+
+#[repr(C, packed)]
+pub(crate) struct GPTPartitionEntry {
+    type_guid: [u8; 16],
+    unique_guid: [u8; 16],
+    starting_lba: Le<Unaligned<u64>>,
+    ending_lba: Le<Unaligned<u64>>,
+    _attributes: Le<Unaligned<u64>>,
+    // UTF-16LE, NUL padded
+    name: [u8; 72],
+}
+
+/// Read the partition entries the GPT header names. Unused entries are skipped, the index
+/// of a partition is its position in the entry array
+pub(crate) fn read<D>(block_device: &D) -> Result<GPTConfig, FileSystemErr>
+where
+    D: BlockDevice,
+{
+    let block_size = block_device.block_size();
+    let mut buffer = AlignedSliceBox::<u8>::new_uninit_with_align(block_size, 1).unwrap();
+    block_device.read_at(1, &mut buffer).map_err(from_io_err)?;
+    let buffer = unsafe { buffer.assume_init() };
+    let header = buffer.as_ptr() as *const GPTHeader;
+    if unsafe { *addr_of!((*header).signature) } != GPTHeader::SIGNATURE {
+        return Err(FileSystemErr::Corrupted);
+    }
+    let entry_lba = unalign_read!((*header).partition_entry_lba => Le<Unaligned<u64>>);
+    let entry_count =
+        unalign_read!((*header).num_of_partition_entries => Le<Unaligned<u32>>).min(MAX_ENTRIES);
+    let entry_size = unalign_read!((*header).size_of_partition_entry => Le<Unaligned<u32>>);
+    if (entry_size as usize) < size_of::<GPTPartitionEntry>() {
+        return Err(FileSystemErr::Corrupted);
+    }
+
+    let len = (entry_count as usize * entry_size as usize).next_multiple_of(block_size);
+    let mut entries = AlignedSliceBox::<u8>::new_uninit_with_align(len, 1).unwrap();
+    if len != 0 {
+        block_device
+            .read_at(entry_lba, &mut entries)
+            .map_err(from_io_err)?;
+    }
+    let entries = unsafe { entries.assume_init() };
+    let mut partition = Vec::new();
+    for index in 0..entry_count {
+        let entry = unsafe { entries.as_ptr().add(index as usize * entry_size as usize) }
+            as *const GPTPartitionEntry;
+        let type_guid = Guid(unsafe { *addr_of!((*entry).type_guid) });
+        if type_guid == Guid::ZERO {
+            continue;
+        }
+        let first_sector = unalign_read!((*entry).starting_lba => Le<Unaligned<u64>>);
+        let last_sector = unalign_read!((*entry).ending_lba => Le<Unaligned<u64>>);
+        if last_sector < first_sector {
+            return Err(FileSystemErr::Corrupted);
+        }
+        let name = unsafe { *addr_of!((*entry).name) };
+        partition.push(GPTPartition {
+            index: index as u8,
+            type_guid,
+            unique_guid: Guid(unsafe { *addr_of!((*entry).unique_guid) }),
+            first_sector,
+            total_sector: last_sector - first_sector + 1,
+            name: decode_name(&name),
+        });
+    }
+    Ok(GPTConfig { partition })
+}
+
+// the UTF-16LE name up to the first NUL
+fn decode_name(raw: &[u8; 72]) -> String {
+    let units = raw
+        .as_chunks::<2>()
+        .0
+        .iter()
+        .map(|&unit| u16::from_le_bytes(unit))
+        .take_while(|&unit| unit != 0);
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
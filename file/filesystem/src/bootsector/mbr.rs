@@ -28,7 +28,7 @@ pub(crate) struct MasterBootRecordPartitionTable {
 
 #[repr(transparent)]
 #[derive(Clone, Copy, RawReg, PartialEq)]
-pub(crate) struct MasterBootRecordPartitionKind(u8);
+pub(crate) struct MasterBootRecordPartitionKind(pub(crate) u8);
 
 impl MasterBootRecordPartitionKind {
     pub(crate) const UNUSED: Self = Self(0);
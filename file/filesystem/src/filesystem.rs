@@ -1,10 +1,14 @@
 use core::mem::MaybeUninit;
+use core::ops::ControlFlow;
 use core::usize;
 
 use alloc::boxed::Box;
 use alloc::sync::Arc;
 use alloc::sync::Weak;
+use alloc::vec;
+use alloc::vec::Vec;
 use block_device_api::BlockDevice;
+use typestate::BytePod;
 
 use crate::FileSystemErr;
 use crate::PartitionIndex;
@@ -12,6 +16,7 @@ use crate::aligned_box::AlignedSliceBox;
 use crate::filesystem::fat32::FAT32FileSystem;
 use crate::filesystem::fat32::sector::FAT32BootSector;
 use crate::from_io_err;
+use crate::partition::FileSystemKind;
 
 pub(crate) mod fat32;
 
@@ -65,6 +70,7 @@ pub(crate) mod file_system {
         }
         // assume partition is fat32
         let fat32_filesystem = FAT32FileSystem::new(
+            block_device,
             block_device.block_size(),
             fat32_boot_sector,
             count_of_clusters,
@@ -81,6 +87,8 @@ pub enum OpenOptions {
 }
 
 pub(crate) trait FileSystemTrait {
+    fn kind(&self) -> FileSystemKind;
+
     // file
     fn open(
         &self,
@@ -89,13 +97,58 @@ pub(crate) trait FileSystemTrait {
         path: &str,
         opts: &OpenOptions,
     ) -> Result<FileHandle, FileSystemErr>;
-    fn remove_file(&self, path: &str) -> Result<(), FileSystemErr>;
-    fn copy(&self, from: &str, to: &str) -> Result<(), FileSystemErr>;
-    fn rename(&self, from: &str, to: &str) -> Result<(), FileSystemErr>;
+
+    /// The volume was not cleanly unmounted before this mount. It is read, but writes
+    /// fail with `Corrupted`
+    fn is_dirty(&self) -> bool;
+    /// Mark the volume dirty before its first write. Every operation changing the volume
+    /// calls it right before its first write, a volume marked dirty without a write is
+    /// left for a checker to repair
+    fn begin_write(&self, block_device: &Arc<dyn BlockDevice>) -> Result<(), FileSystemErr>;
+    /// Flush the writes and mark the volume clean again
+    fn sync(&self, block_device: &Arc<dyn BlockDevice>) -> Result<(), FileSystemErr>;
+
+    fn create_file(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr>;
+    fn remove_file(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr>;
+    fn copy(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        from: &str,
+        to: &str,
+    ) -> Result<(), FileSystemErr>;
+    fn rename(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        from: &str,
+        to: &str,
+    ) -> Result<(), FileSystemErr>;
 
     // dir
-    fn create_dir(&self, path: &str) -> Result<(), FileSystemErr>;
-    fn remove_dir(&self, path: &str) -> Result<(), FileSystemErr>;
+    fn create_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr>;
+    /// `Busy` when the directory is not empty
+    fn remove_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr>;
+    fn read_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+        f: &mut dyn FnMut(&DirEntry) -> ControlFlow<()>,
+    ) -> Result<(), FileSystemErr>;
 
     fn read(
         &self,
@@ -111,6 +164,25 @@ pub(crate) trait FileSystemTrait {
         buf: &mut [MaybeUninit<u8>],
         meta: &DirMeta,
     ) -> Result<u64, FileSystemErr>;
+
+    /// Write `buf` at `offset` of the file of `meta`, which is updated with the new size
+    fn write_at(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        offset: u64,
+        buf: &[u8],
+        meta: &mut DirMeta,
+    ) -> Result<u64, FileSystemErr>;
+}
+
+/// an entry of a directory listed by `read_dir`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirEntry<'a> {
+    /// long file name if present, otherwise the 8.3 name.
+    /// characters outside ASCII are replaced with '?'
+    pub name: &'a str,
+    pub is_dir: bool,
+    pub size: u32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -119,6 +191,9 @@ pub struct DirMeta {
     is_readonly: bool,
     first_cluster: u32,
     file_size: u32,
+    /// the sector holding the directory entry and the entry's offset in it, `None` for the
+    /// root directory
+    entry: Option<(u64, usize)>,
 }
 
 #[derive(Debug, Clone)]
@@ -150,18 +225,61 @@ impl FileHandle {
         file.read_at(&dev, offset, buf, &self.meta)
     }
 
-    pub fn write_at(&self, offset: u64, buf: &[u8]) -> Result<u64, FileSystemErr> {
-        todo!()
+    /// Fill `buf` from `offset`. `TooBigBuffer` when the file ends first
+    pub fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), FileSystemErr> {
+        // an initialized buffer is a valid uninitialized one, read_at only writes to it
+        let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        match self.read_at(offset, uninit)? {
+            read if read == buf.len() as u64 => Ok(()),
+            _ => Err(FileSystemErr::IncompleteRead),
+        }
+    }
+
+    /// The whole file
+    pub fn read_to_end(&self) -> Result<Vec<u8>, FileSystemErr> {
+        let mut data = vec![0u8; self.size()? as usize];
+        self.read_exact_at(0, &mut data)?;
+        Ok(data)
+    }
+
+    /// A `T` stored at `offset`. `TooBigBuffer` when the file ends first
+    pub fn read_struct<T: BytePod>(&self, offset: u64) -> Result<T, FileSystemErr> {
+        let mut value = MaybeUninit::<T>::uninit();
+        match self.read_at(offset, value.as_bytes_mut())? {
+            read if read == size_of::<T>() as u64 => {}
+            _ => return Err(FileSystemErr::IncompleteRead),
+        }
+        // any bytes are a valid `BytePod`
+        Ok(unsafe { value.assume_init() })
+    }
+
+    /// Write `buf` at `offset`, growing the file when it ends first. `offset` is at most the
+    /// file size. The first write of a mount marks the volume dirty until `flush`
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<u64, FileSystemErr> {
+        if self.opts != OpenOptions::Write {
+            return Err(FileSystemErr::ReadOnly);
+        }
+        let Some(dev) = self.dev_handle.upgrade() else {
+            return Err(FileSystemErr::Closed);
+        };
+        let Some(file) = self.file_handle.upgrade() else {
+            return Err(FileSystemErr::Closed);
+        };
+        file.write_at(&dev, offset, buf, &mut self.meta)
     }
 
     pub fn size(&self) -> Result<u64, FileSystemErr> {
         Ok(self.meta.file_size as u64)
     }
 
+    /// Flush the writes to the media. The volume is marked clean again
     pub fn flush(&self) -> Result<(), FileSystemErr> {
         let Some(dev) = self.dev_handle.upgrade() else {
             return Err(FileSystemErr::Closed);
         };
-        dev.flush().map_err(from_io_err)
+        match self.file_handle.upgrade() {
+            Some(file) => file.sync(&dev),
+            None => dev.flush().map_err(from_io_err),
+        }
     }
 }
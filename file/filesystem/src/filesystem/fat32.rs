@@ -1,23 +1,37 @@
 use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec;
 use block_device_api::BlockDevice;
 use core::mem::MaybeUninit;
+use core::ops::ControlFlow;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+use mutex::SpinLock;
 use typestate::Le;
 use typestate::Unaligned;
 use typestate::unalign_read;
 
 use crate::FileSystemErr;
 use crate::aligned_box::AlignedSliceBox;
+use crate::filesystem::DirEntry;
 use crate::filesystem::DirMeta;
 use crate::filesystem::FileHandle;
 use crate::filesystem::FileSystemTrait;
 use crate::filesystem::OpenOptions;
+use crate::filesystem::fat32::dir::Directory;
+use crate::filesystem::fat32::fat::FAT32FAT;
 use crate::filesystem::fat32::fat::FAT32FATIter;
+use crate::filesystem::fat32::fat::forget_free_count;
+use crate::filesystem::fat32::fat::read_volume_flags;
+use crate::filesystem::fat32::fat::update_volume_flags;
 use crate::filesystem::fat32::sector::FAT32BootSector;
 use crate::filesystem::fat32::sector::FAT32ByteDirectoryEntry;
 use crate::filesystem::fat32::sector::FAT32DirectoryEntryAttribute;
 use crate::filesystem::fat32::sector::FAT32LongDirectoryEntry;
 use crate::from_io_err;
+use crate::partition::FileSystemKind;
+mod dir;
 mod fat;
 pub(crate) mod sector;
 
@@ -54,16 +68,34 @@ pub(crate) struct FAT32FileSystem {
     /// Used for volume size calculations and sanity checks.
     total_sectors: u32,
 
+    /// Sector of the FSInfo structure (BPB_FSInfo), relative to the volume.
+    /// Its free cluster count is invalidated by the first write.
+    fs_info_sector: u16,
+
     /// Total number of clusters in the data region.
     /// Used to validate FAT type (e.g., FAT12, FAT16, FAT32) and for boundary checks.
     count_of_clusters: u32,
 
     /// first data sector
     first_data_sectors: u64,
+
+    /// The volume was not cleanly unmounted or met a disk error before this mount.
+    /// It is still read, but not written until a checker repaired it
+    dirty_on_mount: bool,
+
+    /// The dirty flag was set by the first write of this mount and is cleared on flush
+    marked_dirty: AtomicBool,
+
+    /// The cluster the search for a free one starts from
+    next_free: AtomicU32,
+
+    /// Held by every operation changing the volume
+    write_lock: SpinLock<()>,
 }
 
 impl FAT32FileSystem {
     pub(crate) fn new(
+        block_device: &Arc<dyn BlockDevice>,
         block_size: usize,
         boot_sector: &FAT32BootSector,
         count_of_clusters: u32,
@@ -89,7 +121,7 @@ impl FAT32FileSystem {
         if hidden_sector as u64 != first_sector || num_fats == 0 || reserved_sectors == 0 {
             return Err(FileSystemErr::Corrupted);
         }
-        Ok(Self {
+        let mut file_system = Self {
             bytes_per_sector,
             sectors_per_cluster,
             reserved_sectors,
@@ -98,10 +130,191 @@ impl FAT32FileSystem {
             root_dir_cluster: unalign_read!(boot_sector.bpb_root_clus => Le::<Unaligned<u32>>),
             hidden_sector,
             total_sectors: unalign_read!(boot_sector.bpb_tot_sec_32 => Le::<Unaligned<u32>>),
+            fs_info_sector: unalign_read!(boot_sector.bpb_fs_info => Le::<Unaligned<u16>>),
             count_of_clusters,
             first_data_sectors: reserved_sectors as u64
                 + (num_fats as u64 * sectors_per_fat as u64),
-        })
+            dirty_on_mount: false,
+            marked_dirty: AtomicBool::new(false),
+            next_free: AtomicU32::new(2),
+            write_lock: SpinLock::new(()),
+        };
+        let flags = read_volume_flags(block_device, &file_system)?;
+        file_system.dirty_on_mount =
+            flags & FAT32FAT::CLEAN_SHUTDOWN == 0 || flags & FAT32FAT::NO_HARD_ERROR == 0;
+        Ok(file_system)
+    }
+
+    /// the first sector of `cluster`
+    fn cluster_lba(&self, cluster: u32) -> u64 {
+        self.hidden_sector as u64
+            + self.first_data_sectors
+            + (cluster as u64 - 2) * self.sectors_per_cluster as u64
+    }
+
+    /// the cluster holding sector `lba`
+    fn lba_cluster(&self, lba: u64) -> u32 {
+        ((lba - self.cluster_lba(2)) / self.sectors_per_cluster as u64) as u32 + 2
+    }
+
+    fn bytes_per_cluster(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    fn entries_per_cluster(&self) -> usize {
+        self.bytes_per_cluster() / size_of::<FAT32ByteDirectoryEntry>()
+    }
+
+    /// Split an absolute `path` into the directory holding it and a file name which can be
+    /// created
+    fn split_path(path: &str) -> Result<(&str, &str), FileSystemErr> {
+        let Some((parent, name)) = path.rsplit_once('/') else {
+            return Err(FileSystemErr::NotRootDir);
+        };
+        // trailing dots and spaces are dropped by other drivers, the name would not be found
+        if name.is_empty()
+            || name.len() > 255
+            || name.ends_with(['.', ' '])
+            || Self::is_encode_83(name).is_err()
+        {
+            return Err(FileSystemErr::InvalidInput);
+        }
+        Ok((if parent.is_empty() { "/" } else { parent }, name))
+    }
+
+    /// The directory `path`, with the meta of the root directory for `/`
+    fn lookup_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<DirMeta, FileSystemErr> {
+        let meta = self.lookup(block_device, path)?;
+        if !meta.is_dir {
+            return Err(FileSystemErr::NotDir);
+        }
+        Ok(meta)
+    }
+
+    /// The `DirMeta` of the entry `index` of `dir`
+    fn entry_meta(&self, dir: &Directory, index: usize) -> DirMeta {
+        Self::calculate_next_dir(dir.entry(index), Some(dir.location(self, index)))
+    }
+
+    /// Add an entry for `path` and return its meta. The caller holds `write_lock`
+    fn create_entry(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+        entry: FAT32ByteDirectoryEntry,
+    ) -> Result<DirMeta, FileSystemErr> {
+        let (parent, name) = Self::split_path(path)?;
+        let parent = self.lookup_dir(block_device, parent)?;
+        let mut dir = Directory::load(block_device, self, parent.first_cluster)?;
+        if dir.find(name).is_some() {
+            return Err(FileSystemErr::AlreadyExists);
+        }
+        self.begin_write(block_device)?;
+        let index = dir.insert(block_device, self, name, entry)?;
+        dir.store(block_device, self)?;
+        Ok(self.entry_meta(&dir, index))
+    }
+
+    /// Write back the first cluster and size of `meta` to its directory entry
+    fn update_entry(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        meta: &DirMeta,
+    ) -> Result<(), FileSystemErr> {
+        let Some((lba, offset)) = meta.entry else {
+            return Err(FileSystemErr::InvalidInput);
+        };
+        let mut data = AlignedSliceBox::<u8>::new_uninit_with_align(
+            self.bytes_per_sector as usize,
+            align_of::<FAT32ByteDirectoryEntry>(),
+        )
+        .unwrap();
+        block_device.read_at(lba, &mut data).map_err(from_io_err)?;
+        let mut data = unsafe { data.assume_init() };
+        let entry =
+            unsafe { &mut *(data.as_mut_ptr().add(offset) as *mut FAT32ByteDirectoryEntry) };
+        entry.set_first_cluster(meta.first_cluster);
+        entry.dir_file_size.write(meta.file_size);
+        block_device.write_at(lba, &data).map_err(from_io_err)
+    }
+
+    /// `write_at` with `write_lock` held
+    fn write_locked(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        offset: u64,
+        buf: &[u8],
+        meta: &mut DirMeta,
+    ) -> Result<u64, FileSystemErr> {
+        if offset > meta.file_size as u64 {
+            return Err(FileSystemErr::InvalidInput);
+        }
+        // a FAT32 file is at most 4 GiB - 1
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end <= u32::MAX as u64)
+            .ok_or(FileSystemErr::NoSpace)?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.begin_write(block_device)?;
+
+        let bs = self.bytes_per_sector as usize;
+        let bpc = self.bytes_per_cluster() as u64;
+        let mut clusters = fat::chain(block_device, self, meta.first_cluster)?;
+        let needed = end.div_ceil(bpc) as usize;
+        if clusters.len() < needed {
+            let added = fat::allocate_clusters(
+                block_device,
+                self,
+                clusters.last().copied(),
+                needed - clusters.len(),
+            )?;
+            clusters.extend(added);
+        }
+
+        // cluster by cluster, the bytes around `buf` in its first and last sector are kept
+        let mut written = 0;
+        while written < buf.len() {
+            let position = offset + written as u64;
+            let in_cluster = (position % bpc) as usize;
+            let len = (bpc as usize - in_cluster).min(buf.len() - written);
+            let first_sector = in_cluster / bs;
+            let sectors = (in_cluster + len).div_ceil(bs) - first_sector;
+            let lba = self.cluster_lba(clusters[(position / bpc) as usize]) + first_sector as u64;
+            let mut data = vec![0u8; sectors * bs];
+            let start = in_cluster % bs;
+            let end = start + len;
+            if start != 0 {
+                Self::read_sectors(block_device, lba, &mut data[..bs])?;
+            }
+            if !end.is_multiple_of(bs) && (sectors > 1 || start == 0) {
+                let last = (sectors - 1) * bs;
+                Self::read_sectors(block_device, lba + sectors as u64 - 1, &mut data[last..])?;
+            }
+            data[start..end].copy_from_slice(&buf[written..written + len]);
+            block_device.write_at(lba, &data).map_err(from_io_err)?;
+            written += len;
+        }
+
+        meta.first_cluster = clusters[0];
+        meta.file_size = meta.file_size.max(end as u32);
+        self.update_entry(block_device, meta)?;
+        Ok(buf.len() as u64)
+    }
+
+    fn read_sectors(
+        block_device: &Arc<dyn BlockDevice>,
+        lba: u64,
+        buf: &mut [u8],
+    ) -> Result<(), FileSystemErr> {
+        // an initialized buffer is a valid uninitialized one, read_at only writes to it
+        let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        block_device.read_at(lba, uninit).map_err(from_io_err)
     }
 
     fn is_encode_83(name: &str) -> Result<Option<(&str, &str)>, FileSystemErr> {
@@ -143,7 +356,7 @@ impl FAT32FileSystem {
         Ok(Some(unsafe { (ret8.assume_init(), ret3) }))
     }
 
-    fn calculate_next_dir(sde: &FAT32ByteDirectoryEntry) -> DirMeta {
+    fn calculate_next_dir(sde: &FAT32ByteDirectoryEntry, entry: Option<(u64, usize)>) -> DirMeta {
         let cluster =
             ((sde.dir_fst_clus_hi.read() as u32) << 16) | sde.dir_fst_clus_lo.read() as u32;
         DirMeta {
@@ -153,6 +366,7 @@ impl FAT32FileSystem {
                 == FAT32DirectoryEntryAttribute::ATTR_READ_ONLY,
             first_cluster: cluster,
             file_size: sde.dir_file_size.read(),
+            entry,
         }
     }
 
@@ -200,6 +414,108 @@ impl FAT32FileSystem {
         Ok(true)
     }
 
+    /// resolve an absolute `path`. `/` resolves to the root directory
+    fn lookup(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<DirMeta, FileSystemErr> {
+        let mut path = path.chars();
+        match path.next() {
+            Some('/') => {}
+            Some(_) => return Err(FileSystemErr::NotRootDir),
+            None => return Err(FileSystemErr::InvalidInput),
+        }
+        let mut meta = DirMeta {
+            is_readonly: false,
+            is_dir: true,
+            first_cluster: self.root_dir_cluster,
+            file_size: 0,
+            entry: None,
+        };
+        if path.as_str().is_empty() {
+            return Ok(meta);
+        }
+        for dir_name in path.as_str().split('/') {
+            if dir_name.is_empty() {
+                return Err(FileSystemErr::InvalidInput);
+            }
+            if !meta.is_dir {
+                return Err(FileSystemErr::NotDir);
+            }
+            let Some(dir_meta) =
+                self.search_file_name_with_cluster_dir(block_device, meta.first_cluster, dir_name)?
+            else {
+                return Err(FileSystemErr::NotFound);
+            };
+            meta = dir_meta;
+        }
+        Ok(meta)
+    }
+
+    /// format the 8.3 name of `sde` as "NAME.EXT" into `buf`, returns the length
+    fn short_name(sde: &FAT32ByteDirectoryEntry, buf: &mut [u8; 12]) -> usize {
+        let base = sde.dir_name[..8].trim_ascii_end();
+        let extension = sde.dir_name[8..].trim_ascii_end();
+        let mut len = 0;
+        for (i, &c) in base.iter().enumerate() {
+            // 0x05 stands for a leading 0xE5 which marks deleted entries
+            let c = if i == 0 && c == 0x05 { 0xE5 } else { c };
+            buf[len] = if sde.dir_nt_res & FAT32ByteDirectoryEntry::LOWER_BASE != 0 {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
+            len += 1;
+        }
+        if !extension.is_empty() {
+            buf[len] = b'.';
+            len += 1;
+            for &c in extension {
+                buf[len] = if sde.dir_nt_res & FAT32ByteDirectoryEntry::LOWER_EXTENSION != 0 {
+                    c.to_ascii_lowercase()
+                } else {
+                    c
+                };
+                len += 1;
+            }
+        }
+        for c in &mut buf[..len] {
+            if !c.is_ascii() {
+                *c = b'?';
+            }
+        }
+        len
+    }
+
+    /// copy the 13 characters of `lde` into its slot of `buf`.
+    /// returns the name length if this entry holds the terminator or the last character
+    fn copy_long_name(lde: &FAT32LongDirectoryEntry, buf: &mut [u8; 255]) -> Option<usize> {
+        let seq = (lde.ldir_ord & 0x3F) as usize;
+        let start = (seq - 1) * 13;
+        let chars = lde
+            .ldir_name1
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .chain(lde.ldir_name2.as_chunks::<2>().0)
+            .chain(lde.ldir_name3.as_chunks::<2>().0)
+            .map(|&pair| u16::from_le_bytes(pair));
+        for (i, c) in chars.enumerate() {
+            if c == 0x0000 {
+                return Some(start + i);
+            }
+            if start + i >= buf.len() {
+                return Some(buf.len());
+            }
+            buf[start + i] = match c {
+                0x0001..=0x007F => c as u8,
+                _ => b'?',
+            };
+        }
+        (lde.ldir_ord & 0x40 != 0).then_some((start + 13).min(buf.len()))
+    }
+
     fn search_file_name_with_cluster_dir(
         &self,
         block_device: &Arc<dyn BlockDevice>,
@@ -238,6 +554,8 @@ impl FAT32FileSystem {
                     let sde = unsafe {
                         &*((data.as_ptr() as usize + i) as *const FAT32ByteDirectoryEntry)
                     };
+                    let bs = block_device.block_size();
+                    let entry = Some((lba + (i / bs) as u64, i % bs));
                     if sde.dir_attr & FAT32DirectoryEntryAttribute::ATTR_VOLUME_ID
                         == FAT32DirectoryEntryAttribute::ATTR_VOLUME_ID
                     {
@@ -262,7 +580,7 @@ impl FAT32FileSystem {
                                 continue 'outer;
                             }
                         }
-                        return Ok(Some(Self::calculate_next_dir(sde)));
+                        return Ok(Some(Self::calculate_next_dir(sde, entry)));
                     } else {
                         if lde == 0 {
                             continue;
@@ -307,7 +625,7 @@ impl FAT32FileSystem {
                                 || file_name.bytes().all(|c| c == b' ' || c == b'.')
                             {
                                 if j == lde - 1 {
-                                    return Ok(Some(Self::calculate_next_dir(sde)));
+                                    return Ok(Some(Self::calculate_next_dir(sde, entry)));
                                 } else {
                                     continue 'outer;
                                 }
@@ -320,7 +638,7 @@ impl FAT32FileSystem {
                                 || file_name.bytes().all(|c| c == b' ' || c == b'.')
                             {
                                 if j == lde - 1 {
-                                    return Ok(Some(Self::calculate_next_dir(sde)));
+                                    return Ok(Some(Self::calculate_next_dir(sde, entry)));
                                 } else {
                                     continue 'outer;
                                 }
@@ -333,7 +651,7 @@ impl FAT32FileSystem {
                                 || file_name.bytes().all(|c| c == b' ' || c == b'.')
                             {
                                 if j == lde - 1 {
-                                    return Ok(Some(Self::calculate_next_dir(sde)));
+                                    return Ok(Some(Self::calculate_next_dir(sde, entry)));
                                 } else {
                                     continue 'outer;
                                 }
@@ -350,6 +668,10 @@ impl FAT32FileSystem {
 }
 
 impl FileSystemTrait for FAT32FileSystem {
+    fn kind(&self) -> FileSystemKind {
+        FileSystemKind::Fat32
+    }
+
     fn open(
         &self,
         block_device: &Arc<dyn BlockDevice>,
@@ -357,31 +679,7 @@ impl FileSystemTrait for FAT32FileSystem {
         path: &str,
         opts: &super::OpenOptions,
     ) -> Result<FileHandle, FileSystemErr> {
-        let mut path = path.chars();
-        match path.next() {
-            Some('/') => {}
-            Some(_) => return Err(FileSystemErr::NotRootDir),
-            None => return Err(FileSystemErr::InvalidInput),
-        }
-        let mut dir_clusters = self.root_dir_cluster;
-        let mut meta = DirMeta {
-            is_readonly: false,
-            is_dir: false,
-            first_cluster: 0,
-            file_size: 0,
-        };
-        for dir_name in path.as_str().split('/') {
-            if dir_name.is_empty() {
-                return Err(FileSystemErr::InvalidInput);
-            }
-            let Some(dir_meta) =
-                self.search_file_name_with_cluster_dir(block_device, dir_clusters, dir_name)?
-            else {
-                return Err(FileSystemErr::NotFound);
-            };
-            dir_clusters = dir_meta.first_cluster;
-            meta = dir_meta;
-        }
+        let meta = self.lookup(block_device, path)?;
         if meta.is_dir {
             return Err(FileSystemErr::IsDir);
         }
@@ -390,6 +688,9 @@ impl FileSystemTrait for FAT32FileSystem {
         {
             return Err(FileSystemErr::ReadOnly);
         }
+        if *opts == OpenOptions::Write && self.dirty_on_mount {
+            return Err(FileSystemErr::Corrupted);
+        }
         Ok(FileHandle {
             dev_handle: Arc::downgrade(block_device),
             file_handle: Arc::downgrade(file_system),
@@ -398,24 +699,269 @@ impl FileSystemTrait for FAT32FileSystem {
         })
     }
 
-    fn remove_file(&self, path: &str) -> Result<(), FileSystemErr> {
-        todo!()
+    fn is_dirty(&self) -> bool {
+        self.dirty_on_mount
     }
 
-    fn copy(&self, from: &str, to: &str) -> Result<(), FileSystemErr> {
-        todo!()
+    fn begin_write(&self, block_device: &Arc<dyn BlockDevice>) -> Result<(), FileSystemErr> {
+        if self.dirty_on_mount {
+            return Err(FileSystemErr::Corrupted);
+        }
+        if self.marked_dirty.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        // the flag reaches the media before any data does
+        let result = update_volume_flags(block_device, self, FAT32FAT::CLEAN_SHUTDOWN, false)
+            .and_then(|()| forget_free_count(block_device, self))
+            .and_then(|()| block_device.flush().map_err(from_io_err));
+        if result.is_err() {
+            self.marked_dirty.store(false, Ordering::Release);
+        }
+        result
     }
 
-    fn rename(&self, from: &str, to: &str) -> Result<(), FileSystemErr> {
-        todo!()
+    fn sync(&self, block_device: &Arc<dyn BlockDevice>) -> Result<(), FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        if !self.marked_dirty.swap(false, Ordering::AcqRel) {
+            return block_device.flush().map_err(from_io_err);
+        }
+        // the data reaches the media before the volume is marked clean
+        let result = block_device
+            .flush()
+            .map_err(from_io_err)
+            .and_then(|()| update_volume_flags(block_device, self, FAT32FAT::CLEAN_SHUTDOWN, true))
+            .and_then(|()| block_device.flush().map_err(from_io_err));
+        if result.is_err() {
+            self.marked_dirty.store(true, Ordering::Release);
+        }
+        result
     }
 
-    fn create_dir(&self, path: &str) -> Result<(), FileSystemErr> {
-        todo!()
+    fn create_file(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        let entry = FAT32ByteDirectoryEntry::new(
+            [b' '; 11],
+            FAT32DirectoryEntryAttribute::ATTR_ARCHIVE,
+            0,
+            0,
+        );
+        self.create_entry(block_device, path, entry).map(|_| ())
     }
 
-    fn remove_dir(&self, path: &str) -> Result<(), FileSystemErr> {
-        todo!()
+    fn remove_file(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        let (parent, name) = Self::split_path(path)?;
+        let parent = self.lookup_dir(block_device, parent)?;
+        let mut dir = Directory::load(block_device, self, parent.first_cluster)?;
+        let slot = dir.find(name).ok_or(FileSystemErr::NotFound)?;
+        let meta = self.entry_meta(&dir, slot.short);
+        if meta.is_dir {
+            return Err(FileSystemErr::IsDir);
+        }
+        if meta.is_readonly {
+            return Err(FileSystemErr::ReadOnly);
+        }
+        self.begin_write(block_device)?;
+        // the entry goes first, a crash leaves lost clusters rather than a file whose
+        // clusters are reused
+        dir.remove(slot);
+        dir.store(block_device, self)?;
+        fat::free_chain(block_device, self, meta.first_cluster)
+    }
+
+    fn copy(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        from: &str,
+        to: &str,
+    ) -> Result<(), FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        let source = self.lookup(block_device, from)?;
+        if source.is_dir {
+            return Err(FileSystemErr::IsDir);
+        }
+        let mut data = vec![0u8; source.file_size as usize];
+        // an initialized buffer is a valid uninitialized one, read_at only writes to it
+        let uninit = unsafe { &mut *(data.as_mut_slice() as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        self.read_at(block_device, 0, uninit, &source)?;
+        let entry = FAT32ByteDirectoryEntry::new(
+            [b' '; 11],
+            FAT32DirectoryEntryAttribute::ATTR_ARCHIVE,
+            0,
+            0,
+        );
+        let mut meta = self.create_entry(block_device, to, entry)?;
+        self.write_locked(block_device, 0, &data, &mut meta)
+            .map(|_| ())
+    }
+
+    fn rename(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        from: &str,
+        to: &str,
+    ) -> Result<(), FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        let (from_parent, from_name) = Self::split_path(from)?;
+        let (to_parent, to_name) = Self::split_path(to)?;
+        let from_parent = self.lookup_dir(block_device, from_parent)?;
+        let to_parent = self.lookup_dir(block_device, to_parent)?;
+        let mut source = Directory::load(block_device, self, from_parent.first_cluster)?;
+        let slot = source.find(from_name).ok_or(FileSystemErr::NotFound)?;
+        let entry = *source.entry(slot.short);
+        let meta = self.entry_meta(&source, slot.short);
+        // a directory can not move into itself
+        if meta.is_dir
+            && to
+                .strip_prefix(from)
+                .is_some_and(|rest| rest.starts_with('/'))
+        {
+            return Err(FileSystemErr::InvalidInput);
+        }
+
+        if from_parent.first_cluster == to_parent.first_cluster {
+            // a new case of the same name finds the entry itself
+            if source.find(to_name).is_some_and(|found| found != slot) {
+                return Err(FileSystemErr::AlreadyExists);
+            }
+            self.begin_write(block_device)?;
+            source.remove(slot);
+            source.insert(block_device, self, to_name, entry)?;
+            return source.store(block_device, self);
+        }
+
+        let mut target = Directory::load(block_device, self, to_parent.first_cluster)?;
+        if target.find(to_name).is_some() {
+            return Err(FileSystemErr::AlreadyExists);
+        }
+        self.begin_write(block_device)?;
+        // the new entry goes first, a crash leaves the file in both directories rather than
+        // in none
+        target.insert(block_device, self, to_name, entry)?;
+        target.store(block_device, self)?;
+        source.remove(slot);
+        source.store(block_device, self)?;
+        if meta.is_dir {
+            // `..` of a directory in the root directory is 0
+            let parent_cluster = match to_parent.first_cluster {
+                cluster if cluster == self.root_dir_cluster => 0,
+                cluster => cluster,
+            };
+            let mut moved = Directory::load(block_device, self, meta.first_cluster)?;
+            moved.entry_mut(1).set_first_cluster(parent_cluster);
+            moved.store(block_device, self)?;
+        }
+        Ok(())
+    }
+
+    fn create_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        let (parent, name) = Self::split_path(path)?;
+        let parent = self.lookup_dir(block_device, parent)?;
+        let mut dir = Directory::load(block_device, self, parent.first_cluster)?;
+        if dir.find(name).is_some() {
+            return Err(FileSystemErr::AlreadyExists);
+        }
+        self.begin_write(block_device)?;
+        let cluster = fat::allocate_clusters(block_device, self, None, 1)?[0];
+        let mut entries = AlignedSliceBox::<FAT32ByteDirectoryEntry>::new_zeroed_with_align(
+            self.entries_per_cluster(),
+            align_of::<FAT32ByteDirectoryEntry>(),
+        )
+        .unwrap();
+        // `..` of a directory in the root directory is 0
+        let parent_cluster = match parent.first_cluster {
+            cluster if cluster == self.root_dir_cluster => 0,
+            cluster => cluster,
+        };
+        entries[0] = FAT32ByteDirectoryEntry::new(
+            *b".          ",
+            FAT32DirectoryEntryAttribute::ATTR_DIRECTORY,
+            cluster,
+            0,
+        );
+        entries[1] = FAT32ByteDirectoryEntry::new(
+            *b"..         ",
+            FAT32DirectoryEntryAttribute::ATTR_DIRECTORY,
+            parent_cluster,
+            0,
+        );
+        block_device
+            .write_at(self.cluster_lba(cluster), entries.as_bytes())
+            .map_err(from_io_err)?;
+        let entry = FAT32ByteDirectoryEntry::new(
+            [b' '; 11],
+            FAT32DirectoryEntryAttribute::ATTR_DIRECTORY,
+            cluster,
+            0,
+        );
+        dir.insert(block_device, self, name, entry)?;
+        dir.store(block_device, self)
+    }
+
+    fn remove_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+    ) -> Result<(), FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        let (parent, name) = Self::split_path(path)?;
+        let parent = self.lookup_dir(block_device, parent)?;
+        let mut dir = Directory::load(block_device, self, parent.first_cluster)?;
+        let slot = dir.find(name).ok_or(FileSystemErr::NotFound)?;
+        let meta = self.entry_meta(&dir, slot.short);
+        if !meta.is_dir {
+            return Err(FileSystemErr::NotDir);
+        }
+        if !Directory::load(block_device, self, meta.first_cluster)?.is_empty() {
+            return Err(FileSystemErr::Busy);
+        }
+        self.begin_write(block_device)?;
+        dir.remove(slot);
+        dir.store(block_device, self)?;
+        fat::free_chain(block_device, self, meta.first_cluster)
+    }
+
+    fn read_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        path: &str,
+        f: &mut dyn FnMut(&DirEntry) -> ControlFlow<()>,
+    ) -> Result<(), FileSystemErr> {
+        let meta = self.lookup_dir(block_device, path)?;
+        let dir = Directory::load(block_device, self, meta.first_cluster)?;
+        dir.for_each(&mut |name, slot| {
+            let meta = Self::calculate_next_dir(dir.entry(slot.short), None);
+            f(&DirEntry {
+                name,
+                is_dir: meta.is_dir,
+                size: meta.file_size,
+            })
+        });
+        Ok(())
+    }
+
+    fn write_at(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        offset: u64,
+        buf: &[u8],
+        meta: &mut DirMeta,
+    ) -> Result<u64, FileSystemErr> {
+        let _guard = self.write_lock.lock();
+        self.write_locked(block_device, offset, buf, meta)
     }
 
     fn read(
@@ -512,3 +1058,261 @@ impl FileSystemTrait for FAT32FileSystem {
         Ok(to_read as u64)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PartitionIndex;
+    use crate::tests::PARTITION_START;
+    use crate::tests::RESERVED_SECTORS;
+    use crate::tests::RamDisk;
+    use crate::tests::SECTOR_SIZE;
+    use crate::tests::SECTORS_PER_FAT;
+    use crate::tests::fat32_disk;
+    use alloc::string::String;
+    use alloc::string::ToString;
+
+    fn mount(disk: &Arc<RamDisk>) -> (Arc<dyn BlockDevice>, PartitionIndex) {
+        let dev: Arc<dyn BlockDevice> = disk.clone();
+        let partitions = PartitionIndex::new(disk.as_ref()).unwrap();
+        (dev, partitions)
+    }
+
+    // FAT[`index`] of FAT `fat` as stored on the disk
+    fn fat_entry(disk: &RamDisk, fat: usize, index: usize) -> u32 {
+        let offset =
+            (PARTITION_START + RESERVED_SECTORS + fat * SECTORS_PER_FAT) * SECTOR_SIZE + index * 4;
+        let data = disk.data.lock();
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read(partitions: &PartitionIndex, dev: &Arc<dyn BlockDevice>, path: &str) -> Vec<u8> {
+        partitions
+            .open(dev, 0, path, &OpenOptions::Read)
+            .unwrap()
+            .read_to_end()
+            .unwrap()
+    }
+
+    fn list(partitions: &PartitionIndex, dev: &Arc<dyn BlockDevice>, path: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        partitions
+            .read_dir(dev, 0, path, &mut |entry| {
+                names.push(entry.name.to_string());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        names
+    }
+
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(31) ^ seed)
+            .collect()
+    }
+
+    #[test]
+    fn write_grows_the_file_across_clusters() {
+        let disk = Arc::new(fat32_disk());
+        let (dev, partitions) = mount(&disk);
+        let mut file = partitions.create(&dev, 0, "/boot.log").unwrap();
+        assert_eq!(file.size(), Ok(0));
+
+        let first = pattern(1300, 1);
+        assert_eq!(file.write_at(0, &first), Ok(1300));
+        // unaligned, across the sector and cluster boundaries
+        let second = pattern(900, 2);
+        assert_eq!(file.write_at(1000, &second), Ok(900));
+        assert_eq!(file.size(), Ok(1900));
+        assert_eq!(
+            file.write_at(1901, b"hole"),
+            Err(FileSystemErr::InvalidInput)
+        );
+        file.flush().unwrap();
+
+        let mut expected = first[..1000].to_vec();
+        expected.extend_from_slice(&second);
+        assert_eq!(read(&partitions, &dev, "/boot.log"), expected);
+        // and after a new mount
+        let (dev, partitions) = mount(&disk);
+        assert_eq!(read(&partitions, &dev, "/BOOT.LOG"), expected);
+        assert_eq!(list(&partitions, &dev, "/"), ["boot.log"]);
+    }
+
+    #[test]
+    fn dirty_flag_is_set_by_the_first_write_and_cleared_on_flush() {
+        let disk = Arc::new(fat32_disk());
+        let (dev, partitions) = mount(&disk);
+        let mut file = partitions.create(&dev, 0, "/boot.log").unwrap();
+        file.flush().unwrap();
+        assert_ne!(fat_entry(&disk, 0, 1) & FAT32FAT::CLEAN_SHUTDOWN, 0);
+
+        file.write_at(0, b"booting").unwrap();
+        for fat in 0..2 {
+            assert_eq!(fat_entry(&disk, fat, 1) & FAT32FAT::CLEAN_SHUTDOWN, 0);
+        }
+        // a mount of the interrupted volume reads it but does not write it
+        let (dirty_dev, dirty) = mount(&disk);
+        assert!(dirty.partitions(&dirty_dev)[0].dirty);
+        assert_eq!(read(&dirty, &dirty_dev, "/boot.log"), b"booting");
+        assert_eq!(
+            dirty
+                .open(&dirty_dev, 0, "/boot.log", &OpenOptions::Write)
+                .err(),
+            Some(FileSystemErr::Corrupted)
+        );
+        assert_eq!(
+            dirty.create(&dirty_dev, 0, "/other.log").err(),
+            Some(FileSystemErr::Corrupted)
+        );
+
+        file.flush().unwrap();
+        for fat in 0..2 {
+            assert_ne!(fat_entry(&disk, fat, 1) & FAT32FAT::CLEAN_SHUTDOWN, 0);
+        }
+        let (dev, partitions) = mount(&disk);
+        assert!(!partitions.partitions(&dev)[0].dirty);
+    }
+
+    #[test]
+    fn directories_and_long_names() {
+        let disk = Arc::new(fat32_disk());
+        let (dev, partitions) = mount(&disk);
+        partitions.create_dir(&dev, 0, "/EFI").unwrap();
+        partitions.create_dir(&dev, 0, "/EFI/boot loader").unwrap();
+        let path = "/EFI/boot loader/A Rather Long Configuration File Name.conf";
+        let mut file = partitions.create(&dev, 0, path).unwrap();
+        file.write_at(0, b"timeout=3").unwrap();
+        partitions.create(&dev, 0, "/EFI/Mixed.Txt").unwrap();
+        partitions.create(&dev, 0, "/EFI/upper.TXT").unwrap();
+        assert_eq!(
+            partitions.create(&dev, 0, "/efi/MIXED.TXT").err(),
+            Some(FileSystemErr::AlreadyExists)
+        );
+        assert_eq!(
+            partitions.create(&dev, 0, "/EFI/bad|name").err(),
+            Some(FileSystemErr::InvalidInput)
+        );
+        assert_eq!(
+            partitions.create(&dev, 0, "/missing/file").err(),
+            Some(FileSystemErr::NotFound)
+        );
+        partitions.unmount(&dev).unwrap();
+
+        let (dev, partitions) = mount(&disk);
+        assert_eq!(read(&partitions, &dev, path), b"timeout=3");
+        assert_eq!(
+            list(&partitions, &dev, "/EFI"),
+            ["boot loader", "Mixed.Txt", "upper.TXT"]
+        );
+        assert_eq!(
+            list(&partitions, &dev, "/EFI/boot loader"),
+            ["A Rather Long Configuration File Name.conf"]
+        );
+        // the 8.3 alias of a long name
+        assert_eq!(
+            read(&partitions, &dev, "/EFI/BOOTLO~1/ARATHE~1.CON"),
+            b"timeout=3"
+        );
+    }
+
+    #[test]
+    fn directory_grows_by_a_cluster() {
+        let disk = Arc::new(fat32_disk());
+        let (dev, partitions) = mount(&disk);
+        // a 512 byte cluster holds 16 entries, each of these names takes 3
+        let names: Vec<String> = (0..12)
+            .map(|i| alloc::format!("log file {:02}", i))
+            .collect();
+        for (i, name) in names.iter().enumerate() {
+            let mut file = partitions
+                .create(&dev, 0, &alloc::format!("/{}", name))
+                .unwrap();
+            file.write_at(0, &[i as u8; 3]).unwrap();
+        }
+        assert_eq!(list(&partitions, &dev, "/"), names);
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(
+                read(&partitions, &dev, &alloc::format!("/{}", name)),
+                [i as u8; 3]
+            );
+        }
+    }
+
+    #[test]
+    fn remove_rename_and_copy() {
+        let disk = Arc::new(fat32_disk());
+        let (dev, partitions) = mount(&disk);
+        partitions.create_dir(&dev, 0, "/logs").unwrap();
+        let data = pattern(2000, 3);
+        let mut file = partitions.create(&dev, 0, "/logs/boot.log").unwrap();
+        file.write_at(0, &data).unwrap();
+        let first_cluster = file.meta.first_cluster;
+
+        partitions
+            .rename(&dev, 0, "/logs/boot.log", "/logs/boot.log.1")
+            .unwrap();
+        partitions
+            .rename(&dev, 0, "/logs/boot.log.1", "/old.log")
+            .unwrap();
+        assert_eq!(list(&partitions, &dev, "/logs"), Vec::<String>::new());
+        assert_eq!(read(&partitions, &dev, "/old.log"), data);
+        partitions
+            .copy(&dev, 0, "/old.log", "/logs/copy.log")
+            .unwrap();
+        assert_eq!(read(&partitions, &dev, "/logs/copy.log"), data);
+
+        // a moved directory points `..` at its new parent
+        partitions.create_dir(&dev, 0, "/archive").unwrap();
+        partitions
+            .rename(&dev, 0, "/logs", "/archive/logs")
+            .unwrap();
+        assert_eq!(
+            partitions
+                .rename(&dev, 0, "/archive", "/archive/logs/archive")
+                .err(),
+            Some(FileSystemErr::InvalidInput)
+        );
+        assert_eq!(read(&partitions, &dev, "/archive/logs/copy.log"), data);
+
+        assert_eq!(
+            partitions.remove_dir(&dev, 0, "/archive/logs").err(),
+            Some(FileSystemErr::Busy)
+        );
+        partitions.remove_file(&dev, 0, "/old.log").unwrap();
+        partitions
+            .remove_file(&dev, 0, "/archive/logs/copy.log")
+            .unwrap();
+        partitions.remove_dir(&dev, 0, "/archive/logs").unwrap();
+        assert_eq!(
+            partitions
+                .open(&dev, 0, "/old.log", &OpenOptions::Read)
+                .err(),
+            Some(FileSystemErr::NotFound)
+        );
+        assert_eq!(list(&partitions, &dev, "/"), ["archive"]);
+        // the clusters of the removed file are free again
+        for cluster in first_cluster..first_cluster + 4 {
+            assert_eq!(fat_entry(&disk, 0, cluster as usize), 0);
+            assert_eq!(fat_entry(&disk, 1, cluster as usize), 0);
+        }
+    }
+
+    #[test]
+    fn read_only_device_is_not_written() {
+        let mut disk = fat32_disk();
+        disk.read_only = true;
+        let disk = Arc::new(disk);
+        let (dev, partitions) = mount(&disk);
+        assert_eq!(
+            partitions.open(&dev, 0, "/", &OpenOptions::Write).err(),
+            Some(FileSystemErr::IsDir)
+        );
+        assert_eq!(
+            partitions.create(&dev, 0, "/boot.log").err(),
+            Some(FileSystemErr::BlockDeviceErr(
+                block_device_api::IoError::ReadOnly
+            ))
+        );
+    }
+}
@@ -0,0 +1,392 @@
+// A directory loaded whole, to be listed or edited
+
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use block_device_api::BlockDevice;
+use core::ops::ControlFlow;
+
+use crate::FileSystemErr;
+use crate::aligned_box::AlignedSliceBox;
+use crate::filesystem::fat32::FAT32FileSystem;
+use crate::filesystem::fat32::fat;
+use crate::filesystem::fat32::sector::FAT32ByteDirectoryEntry;
+use crate::filesystem::fat32::sector::FAT32DirectoryEntryAttribute;
+use crate::filesystem::fat32::sector::FAT32LongDirectoryEntry;
+use crate::from_io_err;
+
+/// The entries of a file found in a `Directory`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Slot {
+    /// the first long name entry, or `short` without a long name
+    pub(crate) first: usize,
+    pub(crate) short: usize,
+}
+
+pub(crate) struct Directory {
+    clusters: Vec<u32>,
+    /// the entries of each cluster
+    entries: Vec<AlignedSliceBox<FAT32ByteDirectoryEntry>>,
+    /// the clusters changed since the directory was loaded
+    modified: Vec<bool>,
+}
+
+impl Directory {
+    pub(crate) fn load(
+        block_device: &Arc<dyn BlockDevice>,
+        file_system: &FAT32FileSystem,
+        first_cluster: u32,
+    ) -> Result<Self, FileSystemErr> {
+        let clusters = fat::chain(block_device, file_system, first_cluster)?;
+        if clusters.is_empty() {
+            return Err(FileSystemErr::Corrupted);
+        }
+        let mut entries = Vec::with_capacity(clusters.len());
+        for &cluster in &clusters {
+            let mut data = AlignedSliceBox::<FAT32ByteDirectoryEntry>::new_uninit_with_align(
+                file_system.entries_per_cluster(),
+                align_of::<FAT32ByteDirectoryEntry>(),
+            )
+            .unwrap();
+            block_device
+                .read_at(file_system.cluster_lba(cluster), data.deref_uninit_u8_mut())
+                .map_err(from_io_err)?;
+            entries.push(unsafe { data.assume_init() });
+        }
+        Ok(Self {
+            modified: vec![false; clusters.len()],
+            clusters,
+            entries,
+        })
+    }
+
+    /// Write the changed clusters back
+    pub(crate) fn store(
+        &mut self,
+        block_device: &Arc<dyn BlockDevice>,
+        file_system: &FAT32FileSystem,
+    ) -> Result<(), FileSystemErr> {
+        for (i, modified) in self.modified.iter_mut().enumerate() {
+            if *modified {
+                block_device
+                    .write_at(
+                        file_system.cluster_lba(self.clusters[i]),
+                        self.entries[i].as_bytes(),
+                    )
+                    .map_err(from_io_err)?;
+                *modified = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.entries.iter().map(|cluster| cluster.len()).sum()
+    }
+
+    fn per_cluster(&self) -> usize {
+        self.entries[0].len()
+    }
+
+    pub(crate) fn entry(&self, index: usize) -> &FAT32ByteDirectoryEntry {
+        &self.entries[index / self.per_cluster()][index % self.per_cluster()]
+    }
+
+    pub(crate) fn entry_mut(&mut self, index: usize) -> &mut FAT32ByteDirectoryEntry {
+        let per_cluster = self.per_cluster();
+        self.modified[index / per_cluster] = true;
+        &mut self.entries[index / per_cluster][index % per_cluster]
+    }
+
+    /// The sector holding entry `index` and the entry's offset in it
+    pub(crate) fn location(&self, file_system: &FAT32FileSystem, index: usize) -> (u64, usize) {
+        let bytes_per_sector = file_system.bytes_per_sector as usize;
+        let offset = index % self.per_cluster() * size_of::<FAT32ByteDirectoryEntry>();
+        (
+            file_system.cluster_lba(self.clusters[index / self.per_cluster()])
+                + (offset / bytes_per_sector) as u64,
+            offset % bytes_per_sector,
+        )
+    }
+
+    /// Call `f` with the name of every file in order, except the volume label, `.` and
+    /// `..`. The name is the long file name if present, otherwise the 8.3 name.
+    /// characters outside ASCII are replaced with '?'
+    pub(crate) fn for_each(&self, f: &mut dyn FnMut(&str, Slot) -> ControlFlow<()>) {
+        let mut long_name = [0u8; 255];
+        // (name length, checksum, next expected sequence number, first entry) of the
+        // pending long name
+        let mut long_name_state: Option<(usize, u8, u8, usize)> = None;
+        let mut short_name = [0u8; 12];
+
+        for index in 0..self.len() {
+            let entry = self.entry(index);
+            let name0 = entry.dir_name[0];
+            if name0 == 0x00 {
+                return;
+            }
+            if name0 == FAT32ByteDirectoryEntry::DELETED {
+                long_name_state = None;
+                continue;
+            }
+
+            if !FAT32DirectoryEntryAttribute::is_sde(entry as *const _ as usize) {
+                let lde = unsafe {
+                    &*(entry as *const FAT32ByteDirectoryEntry as *const FAT32LongDirectoryEntry)
+                };
+                let last = lde.ldir_ord & FAT32LongDirectoryEntry::LAST != 0;
+                let seq = lde.ldir_ord & 0x3F;
+                let expected = match long_name_state {
+                    _ if last => seq,
+                    Some((_, check_sum, next, _)) if lde.ldir_chksum == check_sum => next,
+                    _ => 0,
+                };
+                // 255 characters take at most 20 entries
+                if seq == 0 || seq != expected || seq > 20 {
+                    long_name_state = None;
+                    continue;
+                }
+                let len = FAT32FileSystem::copy_long_name(lde, &mut long_name);
+                long_name_state = Some(match (last, long_name_state) {
+                    (true, _) => (len.unwrap_or(0), lde.ldir_chksum, seq - 1, index),
+                    (false, Some((len, check_sum, _, first))) => (len, check_sum, seq - 1, first),
+                    (false, None) => unreachable!(),
+                });
+                continue;
+            }
+
+            let long = long_name_state.take();
+            if entry.dir_attr & FAT32DirectoryEntryAttribute::ATTR_VOLUME_ID
+                == FAT32DirectoryEntryAttribute::ATTR_VOLUME_ID
+                || name0 == b'.'
+            {
+                continue;
+            }
+            let (name, first) = match long {
+                // every long entry down to sequence 1 has been seen
+                Some((len, sum, 0, first)) if sum == entry.check_sum() => {
+                    (&long_name[..len], first)
+                }
+                _ => {
+                    let len = FAT32FileSystem::short_name(entry, &mut short_name);
+                    (&short_name[..len], index)
+                }
+            };
+            let slot = Slot {
+                first,
+                short: index,
+            };
+            // only ASCII is written to the buffers
+            if f(core::str::from_utf8(name).unwrap(), slot).is_break() {
+                return;
+            }
+        }
+    }
+
+    /// The file `name`, matched without case against its long and its 8.3 name
+    pub(crate) fn find(&self, name: &str) -> Option<Slot> {
+        let mut found = None;
+        let mut short_name = [0u8; 12];
+        self.for_each(&mut |entry_name, slot| {
+            let len = FAT32FileSystem::short_name(self.entry(slot.short), &mut short_name);
+            if entry_name.eq_ignore_ascii_case(name)
+                || short_name[..len].eq_ignore_ascii_case(name.as_bytes())
+            {
+                found = Some(slot);
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        });
+        found
+    }
+
+    /// Whether the directory holds no file besides `.` and `..`
+    pub(crate) fn is_empty(&self) -> bool {
+        let mut empty = true;
+        self.for_each(&mut |_, _| {
+            empty = false;
+            ControlFlow::Break(())
+        });
+        empty
+    }
+
+    /// Mark the entries of `slot` deleted
+    pub(crate) fn remove(&mut self, slot: Slot) {
+        for index in slot.first..=slot.short {
+            self.entry_mut(index).dir_name[0] = FAT32ByteDirectoryEntry::DELETED;
+        }
+    }
+
+    /// Add `entry` as the file `name`, with long name entries when the 8.3 name can not
+    /// hold it. `name` is checked by `FAT32FileSystem::check_name`. Returns the index of the
+    /// short entry
+    pub(crate) fn insert(
+        &mut self,
+        block_device: &Arc<dyn BlockDevice>,
+        file_system: &FAT32FileSystem,
+        name: &str,
+        mut entry: FAT32ByteDirectoryEntry,
+    ) -> Result<usize, FileSystemErr> {
+        let long_entries = match Self::short_form(name)? {
+            Some((short, nt_res)) => {
+                entry.dir_name = short;
+                entry.dir_nt_res = nt_res;
+                0
+            }
+            None => {
+                entry.dir_name = self.alias(name)?;
+                entry.dir_nt_res = 0;
+                name.len().div_ceil(13)
+            }
+        };
+        let first = self.allocate(block_device, file_system, long_entries + 1)?;
+        let check_sum = entry.check_sum();
+        let name = name.as_bytes();
+        // the entry holding the end of the name comes first
+        for (i, seq) in (1..=long_entries).rev().enumerate() {
+            let mut chars = [0xFFFFu16; 13];
+            for (j, c) in chars.iter_mut().enumerate() {
+                let position = (seq - 1) * 13 + j;
+                if position < name.len() {
+                    *c = name[position] as u16;
+                } else if position == name.len() {
+                    *c = 0x0000;
+                }
+            }
+            let ord = seq as u8
+                | if i == 0 {
+                    FAT32LongDirectoryEntry::LAST
+                } else {
+                    0
+                };
+            let lde = FAT32LongDirectoryEntry::new(ord, check_sum, &chars);
+            let slot = self.entry_mut(first + i);
+            unsafe {
+                *(slot as *mut FAT32ByteDirectoryEntry as *mut FAT32LongDirectoryEntry) = lde
+            };
+        }
+        *self.entry_mut(first + long_entries) = entry;
+        Ok(first + long_entries)
+    }
+
+    /// The 8.3 name and NT lower case flags `name` is stored as without a long name
+    fn short_form(name: &str) -> Result<Option<([u8; 11], u8)>, FileSystemErr> {
+        let Some((base, extension)) = FAT32FileSystem::is_encode_83(name)? else {
+            return Ok(None);
+        };
+        // `Some(true)` for lower case, `None` for mixed case which only a long name keeps
+        let lower = |part: &str| match (
+            part.bytes().any(|c| c.is_ascii_lowercase()),
+            part.bytes().any(|c| c.is_ascii_uppercase()),
+        ) {
+            (true, true) => None,
+            (lower, _) => Some(lower),
+        };
+        let (Some(lower_base), Some(lower_extension)) = (lower(base), lower(extension)) else {
+            return Ok(None);
+        };
+        let mut short = [b' '; 11];
+        short[..base.len()].copy_from_slice(base.as_bytes());
+        short[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+        short.make_ascii_uppercase();
+        let mut nt_res = 0;
+        if lower_base {
+            nt_res |= FAT32ByteDirectoryEntry::LOWER_BASE;
+        }
+        if lower_extension {
+            nt_res |= FAT32ByteDirectoryEntry::LOWER_EXTENSION;
+        }
+        Ok(Some((short, nt_res)))
+    }
+
+    /// An 8.3 name for the long name `name` no other entry uses: the name itself when only
+    /// its mixed case needs the long name, otherwise `BASENA~N.EXT`
+    fn alias(&self, name: &str) -> Result<[u8; 11], FileSystemErr> {
+        if let Some((base, extension)) = FAT32FileSystem::is_encode_83(name)? {
+            let mut short = [b' '; 11];
+            short[..base.len()].copy_from_slice(base.as_bytes());
+            short[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+            short.make_ascii_uppercase();
+            if !self.short_name_used(&short) {
+                return Ok(short);
+            }
+        }
+        let name = name.trim_start_matches('.');
+        let (base, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+        let convert = |part: &str, len: usize| -> Vec<u8> {
+            part.bytes()
+                .filter(|&c| c != b' ' && c != b'.')
+                .map(|c| match c {
+                    b'+' | b',' | b';' | b'=' | b'[' | b']' => b'_',
+                    c => c.to_ascii_uppercase(),
+                })
+                .take(len)
+                .collect()
+        };
+        let base = convert(base, 8);
+        let extension = convert(extension, 3);
+        for n in 1..=999_999 {
+            let tail = format!("~{}", n);
+            let keep = base.len().min(8 - tail.len());
+            let mut short = [b' '; 11];
+            short[..keep].copy_from_slice(&base[..keep]);
+            short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+            short[8..8 + extension.len()].copy_from_slice(&extension);
+            if !self.short_name_used(&short) {
+                return Ok(short);
+            }
+        }
+        Err(FileSystemErr::AlreadyExists)
+    }
+
+    fn short_name_used(&self, short: &[u8; 11]) -> bool {
+        (0..self.len())
+            .map(|index| self.entry(index))
+            .take_while(|entry| entry.dir_name[0] != 0x00)
+            .any(|entry| {
+                FAT32DirectoryEntryAttribute::is_sde(entry as *const _ as usize)
+                    && entry.dir_name == *short
+            })
+    }
+
+    /// The first of `count` free entries in one cluster, the directory grows by a cluster
+    /// when it has none
+    fn allocate(
+        &mut self,
+        block_device: &Arc<dyn BlockDevice>,
+        file_system: &FAT32FileSystem,
+        count: usize,
+    ) -> Result<usize, FileSystemErr> {
+        let per_cluster = self.per_cluster();
+        // the lookup reads the long name entries from the cluster of their short entry
+        if count > per_cluster {
+            return Err(FileSystemErr::UnsupportedFileName);
+        }
+        let mut run = 0;
+        for index in 0..self.len() {
+            if index % per_cluster == 0 {
+                run = 0;
+            }
+            match self.entry(index).dir_name[0] {
+                0x00 | FAT32ByteDirectoryEntry::DELETED => run += 1,
+                _ => run = 0,
+            }
+            if run == count {
+                return Ok(index + 1 - count);
+            }
+        }
+        let cluster =
+            fat::allocate_clusters(block_device, file_system, self.clusters.last().copied(), 1)?[0];
+        self.clusters.push(cluster);
+        self.entries.push(
+            AlignedSliceBox::new_zeroed_with_align(
+                per_cluster,
+                align_of::<FAT32ByteDirectoryEntry>(),
+            )
+            .unwrap(),
+        );
+        self.modified.push(true);
+        Ok(self.len() - per_cluster)
+    }
+}
@@ -1,13 +1,19 @@
 // File Allocation Table
 
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use block_device_api::BlockDevice;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::Ordering;
 use typestate::Le;
+use typestate::Unaligned;
+use typestate::unalign_read;
 use typestate_macro::BytePod;
 
 use crate::FileSystemErr;
 use crate::aligned_box::AlignedSliceBox;
 use crate::filesystem::fat32::FAT32FileSystem;
+use crate::filesystem::fat32::sector::FAT32FSInfoSector;
 use crate::from_io_err;
 
 #[repr(transparent)]
@@ -16,6 +22,229 @@ pub(crate) struct FAT32FAT(Le<u32>);
 
 impl FAT32FAT {
     const MASK: u32 = 0x0FFF_FFFF;
+    /// the end of chain mark this driver writes
+    const END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+    /// FAT[1] bit which is cleared while the volume is written and set again on a clean
+    /// unmount
+    pub(crate) const CLEAN_SHUTDOWN: u32 = 0x0800_0000;
+    /// FAT[1] bit which is cleared by a driver that met a disk I/O error
+    pub(crate) const NO_HARD_ERROR: u32 = 0x0400_0000;
+}
+
+// the sector of FAT `fat_index` holding the entry of `cluster`, and the entry's index in it
+fn entry_position(file_system: &FAT32FileSystem, fat_index: u8, cluster: u32) -> (u64, usize) {
+    let bytes_per_sector = file_system.bytes_per_sector as u64;
+    let entry_byte = cluster as u64 * size_of::<FAT32FAT>() as u64;
+    let lba = file_system.hidden_sector as u64
+        + file_system.reserved_sectors as u64
+        + fat_index as u64 * file_system.sectors_per_fat as u64
+        + entry_byte / bytes_per_sector;
+    (
+        lba,
+        (entry_byte % bytes_per_sector) as usize / size_of::<FAT32FAT>(),
+    )
+}
+
+fn read_fat_sector(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+    lba: u64,
+) -> Result<AlignedSliceBox<FAT32FAT>, FileSystemErr> {
+    let mut data = AlignedSliceBox::<FAT32FAT>::new_uninit_with_align(
+        file_system.bytes_per_sector as usize / size_of::<FAT32FAT>(),
+        4,
+    )
+    .unwrap();
+    block_device
+        .read_at(lba, data.deref_uninit_u8_mut())
+        .map_err(from_io_err)?;
+    Ok(unsafe { data.assume_init() })
+}
+
+// the sector of FAT `fat_index` holding FAT[0] and FAT[1]
+fn read_first_fat_sector(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+    fat_index: u8,
+) -> Result<(AlignedSliceBox<FAT32FAT>, u64), FileSystemErr> {
+    let (lba, _) = entry_position(file_system, fat_index, 0);
+    Ok((read_fat_sector(block_device, file_system, lba)?, lba))
+}
+
+/// FAT[1] of the first FAT, which holds the `CLEAN_SHUTDOWN` and `NO_HARD_ERROR` flags
+pub(crate) fn read_volume_flags(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+) -> Result<u32, FileSystemErr> {
+    let (fat, _) = read_first_fat_sector(block_device, file_system, 0)?;
+    Ok(fat[1].0.read())
+}
+
+/// Set (`set == true`) or clear `flags` in FAT[1] of every FAT
+pub(crate) fn update_volume_flags(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+    flags: u32,
+    set: bool,
+) -> Result<(), FileSystemErr> {
+    for fat_index in 0..file_system.num_fats {
+        let (mut fat, lba) = read_first_fat_sector(block_device, file_system, fat_index)?;
+        let entry = fat[1].0.read();
+        fat[1]
+            .0
+            .write(if set { entry | flags } else { entry & !flags });
+        block_device
+            .write_at(lba, fat.as_bytes())
+            .map_err(from_io_err)?;
+    }
+    Ok(())
+}
+
+/// Mark the free cluster count of FSInfo unknown, it is recomputed by the next checker
+/// instead of trusting a count the writes made stale
+pub(crate) fn forget_free_count(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+) -> Result<(), FileSystemErr> {
+    // 0 and 0xFFFF mean the volume has no FSInfo sector
+    if file_system.fs_info_sector == 0 || file_system.fs_info_sector == 0xFFFF {
+        return Ok(());
+    }
+    let lba = file_system.hidden_sector as u64 + file_system.fs_info_sector as u64;
+    let mut data =
+        AlignedSliceBox::<u8>::new_uninit_with_align(file_system.bytes_per_sector as usize, 4)
+            .unwrap();
+    block_device.read_at(lba, &mut data).map_err(from_io_err)?;
+    let mut data = unsafe { data.assume_init() };
+    let fs_info = unsafe { &mut *(data.as_mut_ptr() as *mut FAT32FSInfoSector) };
+    if unalign_read!(fs_info.fsi_lead_sig => Le<Unaligned<u32>>) != FAT32FSInfoSector::LEAD_SIG
+        || unalign_read!(fs_info.fsi_struc_sig => Le<Unaligned<u32>>)
+            != FAT32FSInfoSector::STRUC_SIG
+    {
+        return Ok(());
+    }
+    unsafe {
+        Le::<Unaligned<u32>>::write(
+            addr_of_mut!(fs_info.fsi_free_count),
+            FAT32FSInfoSector::UNKNOWN,
+        )
+    };
+    block_device.write_at(lba, &data).map_err(from_io_err)
+}
+
+/// Point the FAT entry of each `(cluster, next)` at `next` in every FAT, 0 frees the
+/// cluster. A run of entries sharing a FAT sector is written with one read and one write
+pub(crate) fn write_entries(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+    entries: &[(u32, u32)],
+) -> Result<(), FileSystemErr> {
+    for fat_index in 0..file_system.num_fats {
+        let mut pending: Option<(u64, AlignedSliceBox<FAT32FAT>)> = None;
+        for &(cluster, next) in entries {
+            let (lba, index) = entry_position(file_system, fat_index, cluster);
+            if let Some((pending_lba, fat)) =
+                pending.take_if(|(pending_lba, _)| *pending_lba != lba)
+            {
+                block_device
+                    .write_at(pending_lba, fat.as_bytes())
+                    .map_err(from_io_err)?;
+            }
+            if pending.is_none() {
+                pending = Some((lba, read_fat_sector(block_device, file_system, lba)?));
+            }
+            let (_, fat) = pending.as_mut().unwrap();
+            // the top 4 bits are reserved and kept as they are
+            let entry = fat[index].0.read();
+            fat[index]
+                .0
+                .write((entry & !FAT32FAT::MASK) | (next & FAT32FAT::MASK));
+        }
+        if let Some((lba, fat)) = pending {
+            block_device
+                .write_at(lba, fat.as_bytes())
+                .map_err(from_io_err)?;
+        }
+    }
+    Ok(())
+}
+
+/// Take `count` free clusters, chain them in order after `previous` and end the chain.
+/// `NoSpace` when the volume has fewer free clusters
+pub(crate) fn allocate_clusters(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+    previous: Option<u32>,
+    count: usize,
+) -> Result<Vec<u32>, FileSystemErr> {
+    let last_cluster = file_system.count_of_clusters + 1;
+    let mut cluster = file_system
+        .next_free
+        .load(Ordering::Relaxed)
+        .clamp(2, last_cluster);
+    let mut clusters = Vec::with_capacity(count);
+    let mut cache: Option<(u64, AlignedSliceBox<FAT32FAT>)> = None;
+    // every cluster is looked at once, starting from the hint
+    for _ in 0..file_system.count_of_clusters {
+        if clusters.len() == count {
+            break;
+        }
+        let (lba, index) = entry_position(file_system, 0, cluster);
+        if cache.as_ref().is_none_or(|(cached, _)| *cached != lba) {
+            cache = Some((lba, read_fat_sector(block_device, file_system, lba)?));
+        }
+        let (_, fat) = cache.as_ref().unwrap();
+        if fat[index].0.read() & FAT32FAT::MASK == 0 {
+            clusters.push(cluster);
+        }
+        cluster = if cluster == last_cluster {
+            2
+        } else {
+            cluster + 1
+        };
+    }
+    if clusters.len() < count {
+        return Err(FileSystemErr::NoSpace);
+    }
+    // the new chain is complete before `previous` links to it
+    let mut entries: Vec<(u32, u32)> = clusters.windows(2).map(|w| (w[0], w[1])).collect();
+    if let Some(&last) = clusters.last() {
+        entries.push((last, FAT32FAT::END_OF_CHAIN));
+        if let Some(previous) = previous {
+            entries.push((previous, clusters[0]));
+        }
+    }
+    write_entries(block_device, file_system, &entries)?;
+    file_system.next_free.store(cluster, Ordering::Relaxed);
+    Ok(clusters)
+}
+
+/// The clusters of the chain starting at `first_cluster`, none for an empty file
+pub(crate) fn chain(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+    first_cluster: u32,
+) -> Result<Vec<u32>, FileSystemErr> {
+    FAT32FATIter::new(block_device, file_system, first_cluster)
+        .map(|lba| lba.map(|lba| file_system.lba_cluster(lba)))
+        .collect()
+}
+
+/// Free every cluster of the chain starting at `first_cluster`
+pub(crate) fn free_chain(
+    block_device: &Arc<dyn BlockDevice>,
+    file_system: &FAT32FileSystem,
+    first_cluster: u32,
+) -> Result<(), FileSystemErr> {
+    let entries: Vec<(u32, u32)> = chain(block_device, file_system, first_cluster)?
+        .into_iter()
+        .map(|cluster| (cluster, 0))
+        .collect();
+    write_entries(block_device, file_system, &entries)?;
+    if let Some(&(first, _)) = entries.first() {
+        file_system.next_free.fetch_min(first, Ordering::Relaxed);
+    }
+    Ok(())
 }
 
 pub(crate) struct FAT32FATIter<'a> {
@@ -134,11 +363,6 @@ impl<'a> Iterator for FAT32FATIter<'a> {
                 }
             }
         }
-        Some(Ok(self.file_system.hidden_sector as u64
-            + self.file_system.reserved_sectors as u64
-            + self.file_system.num_fats as u64
-                * self.file_system.sectors_per_fat as u64
-            + (cluster as u64 - 2)
-                * self.file_system.sectors_per_cluster as u64))
+        Some(Ok(self.file_system.cluster_lba(cluster)))
     }
 }
@@ -2,6 +2,7 @@
 
 use core::ffi::c_char;
 use core::mem::size_of;
+use typestate::BytePod;
 use typestate::Le;
 use typestate::Unaligned;
 use typestate_macro::RawReg;
@@ -32,7 +33,7 @@ pub(crate) struct FAT32BootSector {
     bpb_ext_flags: Le<Unaligned<u16>>,
     bpb_fs_ver: Le<Unaligned<u16>>,
     pub(crate) bpb_root_clus: Le<Unaligned<u32>>,
-    bpb_fs_info: Le<Unaligned<u16>>,
+    pub(crate) bpb_fs_info: Le<Unaligned<u16>>,
     bpb_bk_boot_sec: Le<Unaligned<u16>>,
     bpb_reserved: [u8; 12],
     bs_drv_num: u8,
@@ -46,23 +47,31 @@ pub(crate) struct FAT32BootSector {
 }
 
 #[repr(packed)]
-struct FAT32FSInfoSector {
-    fsi_lead_sig: Le<Unaligned<u32>>,
+pub(crate) struct FAT32FSInfoSector {
+    pub(crate) fsi_lead_sig: Le<Unaligned<u32>>,
     fsi_reserved1: [u8; 480],
-    fsi_struc_sig: Le<Unaligned<u32>>,
-    fsi_free_count: Le<Unaligned<u32>>,
+    pub(crate) fsi_struc_sig: Le<Unaligned<u32>>,
+    pub(crate) fsi_free_count: Le<Unaligned<u32>>,
     fsi_nxt_free: Le<Unaligned<u32>>,
     fsi_reserved2: [u8; 12],
     fsi_trail_sig: Le<Unaligned<u32>>,
 }
 
+impl FAT32FSInfoSector {
+    pub(crate) const LEAD_SIG: u32 = 0x4161_5252;
+    pub(crate) const STRUC_SIG: u32 = 0x6141_7272;
+    /// `fsi_free_count` and `fsi_nxt_free` are not known
+    pub(crate) const UNKNOWN: u32 = 0xFFFF_FFFF;
+}
+
 /// # Safety
 /// require 2byte alignment
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub(crate) struct FAT32ByteDirectoryEntry {
     pub(crate) dir_name: [u8; 11],
     pub(crate) dir_attr: FAT32DirectoryEntryAttribute,
-    dir_nt_res: u8,
+    pub(crate) dir_nt_res: u8,
     dir_crt_time_tenth: u8,
     dir_crt_time: Le<u16>,
     dir_ctr_data: Le<u16>,
@@ -74,6 +83,61 @@ pub(crate) struct FAT32ByteDirectoryEntry {
     pub(crate) dir_file_size: Le<u32>,
 }
 
+// bytes and little endian integers, any bit pattern is an entry
+unsafe impl BytePod for FAT32ByteDirectoryEntry {}
+
+impl FAT32ByteDirectoryEntry {
+    /// NT reserved flag: the base name is shown in lower case
+    pub(crate) const LOWER_BASE: u8 = 0x08;
+    /// NT reserved flag: the extension is shown in lower case
+    pub(crate) const LOWER_EXTENSION: u8 = 0x10;
+    /// the first name byte of a deleted entry
+    pub(crate) const DELETED: u8 = 0xE5;
+
+    /// An entry dated 1980-01-01, the FAT epoch: there is no wall clock at boot
+    pub(crate) fn new(
+        dir_name: [u8; 11],
+        dir_attr: FAT32DirectoryEntryAttribute,
+        first_cluster: u32,
+        file_size: u32,
+    ) -> Self {
+        // day 1 of month 1 of 1980
+        const EPOCH: u16 = 1 << 5 | 1;
+        let mut entry = Self {
+            dir_name,
+            dir_attr,
+            dir_nt_res: 0,
+            dir_crt_time_tenth: 0,
+            dir_crt_time: Le::new(0),
+            dir_ctr_data: Le::new(EPOCH),
+            dir_lst_acc_data: Le::new(EPOCH),
+            dir_fst_clus_hi: Le::new(0),
+            dir_wrt_time: Le::new(0),
+            dir_wrt_data: Le::new(EPOCH),
+            dir_fst_clus_lo: Le::new(0),
+            dir_file_size: Le::new(file_size),
+        };
+        entry.set_first_cluster(first_cluster);
+        entry
+    }
+
+    pub(crate) fn first_cluster(&self) -> u32 {
+        ((self.dir_fst_clus_hi.read() as u32) << 16) | self.dir_fst_clus_lo.read() as u32
+    }
+
+    pub(crate) fn set_first_cluster(&mut self, cluster: u32) {
+        self.dir_fst_clus_hi.write((cluster >> 16) as u16);
+        self.dir_fst_clus_lo.write(cluster as u16);
+    }
+
+    /// The checksum of the 8.3 name the long name entries of this entry carry
+    pub(crate) fn check_sum(&self) -> u8 {
+        self.dir_name
+            .iter()
+            .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+    }
+}
+
 /// # Safety
 /// require 2byte alignment
 #[repr(C)]
@@ -88,6 +152,37 @@ pub(crate) struct FAT32LongDirectoryEntry {
     pub(crate) ldir_name3: [u8; 4],
 }
 
+impl FAT32LongDirectoryEntry {
+    /// the `ldir_ord` flag of the entry holding the end of the name
+    pub(crate) const LAST: u8 = 0x40;
+
+    /// The entry holding the 13 UTF-16 characters `name`, number `ord` of the long name of
+    /// the short entry whose checksum is `check_sum`
+    pub(crate) fn new(ord: u8, check_sum: u8, name: &[u16; 13]) -> Self {
+        let mut entry = Self {
+            ldir_ord: ord,
+            ldir_name1: [0; 10],
+            ldir_attr: FAT32DirectoryEntryAttribute::ATTR_LONG_NAME,
+            ldir_type: 0,
+            ldir_chksum: check_sum,
+            ldir_name2: [0; 12],
+            ldir_fst_clus_lo: 0,
+            ldir_name3: [0; 4],
+        };
+        let slots = entry
+            .ldir_name1
+            .as_chunks_mut::<2>()
+            .0
+            .iter_mut()
+            .chain(entry.ldir_name2.as_chunks_mut::<2>().0)
+            .chain(entry.ldir_name3.as_chunks_mut::<2>().0);
+        for (slot, c) in slots.zip(name) {
+            *slot = c.to_le_bytes();
+        }
+        entry
+    }
+}
+
 #[repr(transparent)]
 #[derive(Clone, Copy, RawReg, PartialEq)]
 pub(crate) struct FAT32DirectoryEntryAttribute(u8);
@@ -99,7 +194,7 @@ impl FAT32DirectoryEntryAttribute {
     const ATTR_SYSTEM: Self = Self(0x04);
     pub(crate) const ATTR_VOLUME_ID: Self = Self(0x08);
     pub(crate) const ATTR_DIRECTORY: Self = Self(0x10);
-    const ATTR_ARCHIVE: Self = Self(0x20);
+    pub(crate) const ATTR_ARCHIVE: Self = Self(0x20);
     const ATTR_LONG_NAME: Self = Self(0x0F);
 
     #[inline]
@@ -1,9 +1,10 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(maybe_uninit_array_assume_init)]
 #![feature(maybe_uninit_as_bytes)]
 #![feature(maybe_uninit_slice)]
 
 extern crate alloc;
+use core::ops::ControlFlow;
 use core::ptr::addr_of;
 
 use alloc::sync::Arc;
@@ -19,16 +20,21 @@ mod bootsector;
 use bootsector::mbr::MasterBootRecord;
 pub mod aligned_box;
 pub mod filesystem;
+pub mod partition;
+pub mod recovery;
 
 use crate::aligned_box::AlignedSliceBox;
 use crate::bootsector::BootSector;
 use crate::bootsector::MBRConfig;
 use crate::bootsector::MBRPartition;
 use crate::bootsector::mbr::MasterBootRecordPartitionKind;
+use crate::filesystem::DirEntry;
 use crate::filesystem::FileHandle;
 use crate::filesystem::FileSystemTrait;
 use crate::filesystem::OpenOptions;
 use crate::filesystem::file_system;
+use crate::partition::PartitionInfo;
+use crate::partition::PartitionKind;
 
 pub struct PartitionIndex {
     sector_kind: BootSector,
@@ -81,10 +87,9 @@ impl PartitionIndex {
         };
         Ok(Self {
             sector_kind: match config.partition[0].kind {
+                // the protective MBR of a GPT disk
                 MasterBootRecordPartitionKind::TYPE_GPT => {
-                    // TODO GPT check
-                    todo!();
-                    BootSector::GPT
+                    BootSector::GPT(bootsector::gpt::read(block_device)?)
                 }
                 _ => BootSector::MBR(config),
             },
@@ -113,8 +118,13 @@ impl PartitionIndex {
                 let total = x.partition[partition_idx as usize].total_sector;
                 Ok((start as u64, total as u64))
             }
-            BootSector::GPT => {
-                todo!()
+            BootSector::GPT(x) => {
+                let partition = x
+                    .partition
+                    .iter()
+                    .find(|p| p.index == partition_idx)
+                    .ok_or(FileSystemErr::UnknownPartition)?;
+                Ok((partition.first_sector, partition.total_sector))
             }
             BootSector::Unknown => {
                 if partition_idx == 0 {
@@ -145,6 +155,73 @@ impl PartitionIndex {
         Ok(file_driver)
     }
 
+    /// The partitions of the disk, in index order. Every one is mounted to find its
+    /// filesystem
+    pub fn partitions(&self, block_device: &Arc<dyn BlockDevice>) -> Vec<PartitionInfo> {
+        let mut partitions = Vec::new();
+        match &self.sector_kind {
+            BootSector::MBR(x) => {
+                for (index, partition) in x.partition.iter().enumerate() {
+                    if partition.kind == MasterBootRecordPartitionKind::UNUSED {
+                        continue;
+                    }
+                    partitions.push((
+                        index as u8,
+                        PartitionKind::Mbr(partition.kind.0),
+                        partition.first_sector as u64,
+                        partition.total_sector as u64,
+                    ));
+                }
+            }
+            BootSector::GPT(x) => {
+                for partition in &x.partition {
+                    partitions.push((
+                        partition.index,
+                        PartitionKind::Gpt {
+                            type_guid: partition.type_guid,
+                            unique_guid: partition.unique_guid,
+                            name: partition.name.clone(),
+                        },
+                        partition.first_sector,
+                        partition.total_sector,
+                    ));
+                }
+            }
+            BootSector::Unknown => {
+                partitions.push((0, PartitionKind::Whole, 0, block_device.num_blocks()));
+            }
+        }
+        partitions
+            .into_iter()
+            .map(|(index, kind, start_sector, total_sector)| {
+                let driver = self.get_partition_driver(block_device, index).ok();
+                PartitionInfo {
+                    index,
+                    kind,
+                    start_sector,
+                    total_sector,
+                    sector_size: block_device.block_size(),
+                    filesystem: driver.as_ref().map(|driver| driver.kind()),
+                    dirty: driver.is_some_and(|driver| driver.is_dirty()),
+                }
+            })
+            .collect()
+    }
+
+    /// Flush every mounted filesystem and mark the written ones clean. They are mounted
+    /// again by the next access
+    pub fn unmount(&self, block_device: &Arc<dyn BlockDevice>) -> Result<(), FileSystemErr> {
+        let partitions = core::mem::take(&mut *self.partitions.lock());
+        let mut result = Ok(());
+        for (_, driver) in partitions {
+            // the other filesystems are still marked clean when one fails
+            if let Err(e) = driver.sync(block_device) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
     pub fn open(
         &self,
         block_device: &Arc<dyn BlockDevice>,
@@ -156,6 +233,18 @@ impl PartitionIndex {
         file_driver.open(block_device, &file_driver, path, opts)
     }
 
+    /// Create an empty file at `path` and open it for writing
+    pub fn create(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        partition_idx: u8,
+        path: &str,
+    ) -> Result<FileHandle, FileSystemErr> {
+        let file_driver = self.get_partition_driver(block_device, partition_idx)?;
+        file_driver.create_file(block_device, path)?;
+        file_driver.open(block_device, &file_driver, path, &OpenOptions::Write)
+    }
+
     pub fn remove_file(
         &self,
         block_device: &Arc<dyn BlockDevice>,
@@ -163,7 +252,7 @@ impl PartitionIndex {
         path: &str,
     ) -> Result<(), FileSystemErr> {
         let file_driver = self.get_partition_driver(block_device, partition_idx)?;
-        file_driver.remove_file(path)
+        file_driver.remove_file(block_device, path)
     }
 
     pub fn copy(
@@ -174,7 +263,7 @@ impl PartitionIndex {
         to: &str,
     ) -> Result<(), FileSystemErr> {
         let file_driver = self.get_partition_driver(block_device, partition_idx)?;
-        file_driver.copy(from, to)
+        file_driver.copy(block_device, from, to)
     }
 
     pub fn rename(
@@ -185,7 +274,7 @@ impl PartitionIndex {
         to: &str,
     ) -> Result<(), FileSystemErr> {
         let file_driver = self.get_partition_driver(block_device, partition_idx)?;
-        file_driver.rename(from, to)
+        file_driver.rename(block_device, from, to)
     }
 
     pub fn create_dir(
@@ -195,7 +284,7 @@ impl PartitionIndex {
         path: &str,
     ) -> Result<(), FileSystemErr> {
         let file_driver = self.get_partition_driver(block_device, partition_idx)?;
-        file_driver.create_dir(path)
+        file_driver.create_dir(block_device, path)
     }
 
     pub fn remove_dir(
@@ -205,7 +294,19 @@ impl PartitionIndex {
         path: &str,
     ) -> Result<(), FileSystemErr> {
         let file_driver = self.get_partition_driver(block_device, partition_idx)?;
-        file_driver.remove_dir(path)
+        file_driver.remove_dir(block_device, path)
+    }
+
+    /// Call `f` for every entry of the directory `path` except `.` and `..`
+    pub fn read_dir(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        partition_idx: u8,
+        path: &str,
+        f: &mut dyn FnMut(&DirEntry) -> ControlFlow<()>,
+    ) -> Result<(), FileSystemErr> {
+        let file_driver = self.get_partition_driver(block_device, partition_idx)?;
+        file_driver.read_dir(block_device, path, f)
     }
 }
 
@@ -229,9 +330,137 @@ pub enum FileSystemErr {
     UnsupportedFileName,
     TooBigBuffer,
     IncompleteRead,
+    /// The filesystem driver does not implement the operation yet
+    Unsupported,
 }
 
 pub(crate) fn from_io_err(err: IoError) -> FileSystemErr {
-    // TODO restart block device when IoError::Io returned
+    // a device wrapped in `recovery::Recovering` was already restarted and retried
     FileSystemErr::BlockDeviceErr(err)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::vec;
+    use core::mem::MaybeUninit;
+
+    pub(crate) const SECTOR_SIZE: usize = 512;
+    /// the first sector of the FAT32 partition of `fat32_disk`
+    pub(crate) const PARTITION_START: usize = 2048;
+    pub(crate) const RESERVED_SECTORS: usize = 32;
+    pub(crate) const SECTORS_PER_FAT: usize = 524;
+    // just above the 65525 clusters a FAT32 volume has at least
+    const VOLUME_SECTORS: usize = 68000;
+
+    /// block device kept in memory
+    pub(crate) struct RamDisk {
+        pub(crate) data: SpinLock<Vec<u8>>,
+        pub(crate) read_only: bool,
+    }
+
+    impl BlockDevice for RamDisk {
+        fn init(&mut self) -> Result<(), IoError> {
+            Ok(())
+        }
+
+        fn block_size(&self) -> usize {
+            SECTOR_SIZE
+        }
+
+        fn num_blocks(&self) -> u64 {
+            (self.data.lock().len() / SECTOR_SIZE) as u64
+        }
+
+        fn read_at(&self, lba: u64, buf: &mut [MaybeUninit<u8>]) -> Result<(), IoError> {
+            let data = self.data.lock();
+            let start = lba as usize * SECTOR_SIZE;
+            let source = data
+                .get(start..start + buf.len())
+                .ok_or(IoError::OutOfRange)?;
+            for (byte, &value) in buf.iter_mut().zip(source) {
+                byte.write(value);
+            }
+            Ok(())
+        }
+
+        fn write_at(&self, lba: u64, buf: &[u8]) -> Result<(), IoError> {
+            if self.read_only {
+                return Err(IoError::ReadOnly);
+            }
+            let mut data = self.data.lock();
+            let start = lba as usize * SECTOR_SIZE;
+            data.get_mut(start..start + buf.len())
+                .ok_or(IoError::OutOfRange)?
+                .copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn flush(&self) -> Result<(), IoError> {
+            Ok(())
+        }
+
+        fn max_io_bytes(&self) -> Result<Option<usize>, IoError> {
+            Ok(None)
+        }
+
+        fn is_read_only(&self) -> Result<bool, IoError> {
+            Ok(self.read_only)
+        }
+
+        fn uninstall(&self) {}
+    }
+
+    fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// An MBR disk whose first partition is an empty FAT32 volume with 512 byte clusters,
+    /// laid out like mkfs.fat does
+    pub(crate) fn fat32_disk() -> RamDisk {
+        let mut data = vec![0u8; (PARTITION_START + VOLUME_SECTORS) * SECTOR_SIZE];
+        // MBR: one FAT32 (LBA) partition
+        put(&mut data, 446 + 4, &[0x0C]);
+        put(&mut data, 446 + 8, &(PARTITION_START as u32).to_le_bytes());
+        put(&mut data, 446 + 12, &(VOLUME_SECTORS as u32).to_le_bytes());
+        put(&mut data, 510, &[0x55, 0xAA]);
+
+        let volume = &mut data[PARTITION_START * SECTOR_SIZE..];
+        put(volume, 0, &[0xEB, 0x58, 0x90]);
+        put(volume, 3, b"mkfs.fat");
+        put(volume, 11, &(SECTOR_SIZE as u16).to_le_bytes());
+        put(volume, 13, &[1]);
+        put(volume, 14, &(RESERVED_SECTORS as u16).to_le_bytes());
+        put(volume, 16, &[2]);
+        put(volume, 21, &[0xF8]);
+        put(volume, 28, &(PARTITION_START as u32).to_le_bytes());
+        put(volume, 32, &(VOLUME_SECTORS as u32).to_le_bytes());
+        put(volume, 36, &(SECTORS_PER_FAT as u32).to_le_bytes());
+        // root directory cluster, FSInfo sector, backup boot sector
+        put(volume, 44, &2u32.to_le_bytes());
+        put(volume, 48, &1u16.to_le_bytes());
+        put(volume, 50, &6u16.to_le_bytes());
+        put(volume, 64, &[0x80, 0, 0x29]);
+        put(volume, 71, b"NO NAME    FAT32   ");
+        put(volume, 510, &[0x55, 0xAA]);
+
+        let fs_info = &mut volume[SECTOR_SIZE..];
+        put(fs_info, 0, &0x4161_5252u32.to_le_bytes());
+        put(fs_info, 484, &0x6141_7272u32.to_le_bytes());
+        put(fs_info, 488, &0xFFFF_FFFFu32.to_le_bytes());
+        put(fs_info, 492, &3u32.to_le_bytes());
+        put(fs_info, 508, &0xAA55_0000u32.to_le_bytes());
+
+        for fat in 0..2 {
+            let fat = &mut volume[(RESERVED_SECTORS + fat * SECTORS_PER_FAT) * SECTOR_SIZE..];
+            // media descriptor, the clean flags, and the root directory chain
+            put(fat, 0, &0x0FFF_FFF8u32.to_le_bytes());
+            put(fat, 4, &0x0FFF_FFFFu32.to_le_bytes());
+            put(fat, 8, &0x0FFF_FFFFu32.to_le_bytes());
+        }
+        RamDisk {
+            data: SpinLock::new(data),
+            read_only: false,
+        }
+    }
+}
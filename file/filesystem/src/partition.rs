@@ -0,0 +1,115 @@
+use alloc::string::String;
+use core::fmt;
+
+/// A GUID in its on-disk layout: the first three fields little endian, the rest as is
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    pub const ZERO: Self = Self([0; 16]);
+
+    /// Parse the `C12A7328-F81F-11D2-BA4B-00A0C93EC93B` form, in either case
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.as_bytes();
+        if s.len() != 36 || [8, 13, 18, 23].iter().any(|&i| s[i] != b'-') {
+            return None;
+        }
+        let mut text = [0u8; 16];
+        let mut digits = s.iter().filter(|&&c| c != b'-');
+        for byte in text.iter_mut() {
+            let high = (*digits.next()? as char).to_digit(16)?;
+            let low = (*digits.next()? as char).to_digit(16)?;
+            *byte = (high << 4 | low) as u8;
+        }
+        let mut guid = text;
+        guid[0..4].reverse();
+        guid[4..6].reverse();
+        guid[6..8].reverse();
+        Some(Self(guid))
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+            b[8],
+            b[9]
+        )?;
+        for byte in &b[10..] {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// How the partition table describes a partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    /// the MBR partition type
+    Mbr(u8),
+    Gpt {
+        type_guid: Guid,
+        unique_guid: Guid,
+        name: String,
+    },
+    /// no partition table, the filesystem spans the disk
+    Whole,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemKind {
+    Fat32,
+}
+
+/// A partition listed by `PartitionIndex::partitions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    /// the `partition_idx` of `PartitionIndex::open` and friends
+    pub index: u8,
+    pub kind: PartitionKind,
+    pub start_sector: u64,
+    pub total_sector: u64,
+    pub sector_size: usize,
+    /// `None` when no supported filesystem was found
+    pub filesystem: Option<FileSystemKind>,
+    /// the filesystem was not cleanly unmounted, e.g. a boot was interrupted while writing.
+    /// It can be read, but is not written until a checker repaired it
+    pub dirty: bool,
+}
+
+impl PartitionInfo {
+    pub fn size(&self) -> u64 {
+        self.total_sector * self.sector_size as u64
+    }
+
+    /// The GPT partition name, if any
+    pub fn label(&self) -> Option<&str> {
+        match &self.kind {
+            PartitionKind::Gpt { name, .. } if !name.is_empty() => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Whether `name` refers to this partition: its index, GPT partition name or unique GUID
+    pub fn matches(&self, name: &str) -> bool {
+        if name.parse::<u8>() == Ok(self.index) || self.label() == Some(name) {
+            return true;
+        }
+        match &self.kind {
+            PartitionKind::Gpt { unique_guid, .. } => Guid::parse(name) == Some(*unique_guid),
+            _ => false,
+        }
+    }
+}
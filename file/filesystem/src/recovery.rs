@@ -0,0 +1,137 @@
+// recovery of a block device from transient errors
+//
+// A request failing with `NotReady` or `Io` reinitializes the device and is retried, so a
+// device which needs a reset does not abort the boot. The number of retries per request and
+// of re-inits over the life of a device are bounded by the `RetryPolicy`.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+use block_device_api::BlockDevice;
+use block_device_api::IoError;
+use block_device_api::Lba;
+use mutex::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// retries of a failed request, each after a re-init
+    pub retries: u32,
+    /// re-inits of a device over its lifetime
+    pub max_reinits: u32,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT: Self = Self {
+        retries: 1,
+        max_reinits: 4,
+    };
+    /// errors are returned as they are
+    pub const NONE: Self = Self {
+        retries: 0,
+        max_reinits: 0,
+    };
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static RETRIES: AtomicU32 = AtomicU32::new(RetryPolicy::DEFAULT.retries);
+static MAX_REINITS: AtomicU32 = AtomicU32::new(RetryPolicy::DEFAULT.max_reinits);
+
+/// Set the policy of every `Recovering` device, including the ones already created
+pub fn set_retry_policy(policy: RetryPolicy) {
+    RETRIES.store(policy.retries, Ordering::Relaxed);
+    MAX_REINITS.store(policy.max_reinits, Ordering::Relaxed);
+}
+
+pub fn retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        retries: RETRIES.load(Ordering::Relaxed),
+        max_reinits: MAX_REINITS.load(Ordering::Relaxed),
+    }
+}
+
+/// `D` which is reinitialized and retried on `NotReady` and `Io`
+pub struct Recovering<D> {
+    dev: RwLock<D>,
+    reinits: AtomicU32,
+}
+
+impl<D: BlockDevice> Recovering<D> {
+    /// `dev` is expected to be initialized already
+    pub fn new(dev: D) -> Self {
+        Self {
+            dev: RwLock::new(dev),
+            reinits: AtomicU32::new(0),
+        }
+    }
+
+    /// The re-inits done so far
+    pub fn reinits(&self) -> u32 {
+        self.reinits.load(Ordering::Relaxed)
+    }
+
+    fn retry<T>(&self, op: &mut dyn FnMut(&D) -> Result<T, IoError>) -> Result<T, IoError> {
+        let policy = retry_policy();
+        let mut result = op(&self.dev.read());
+        for _ in 0..policy.retries {
+            if !matches!(result, Err(IoError::NotReady | IoError::Io)) || !self.reinit(&policy) {
+                break;
+            }
+            result = op(&self.dev.read());
+        }
+        result
+    }
+
+    // false when the re-init budget is spent or the device does not come back
+    fn reinit(&self, policy: &RetryPolicy) -> bool {
+        if self.reinits.fetch_add(1, Ordering::Relaxed) >= policy.max_reinits {
+            return false;
+        }
+        let mut dev = self.dev.write();
+        dev.uninstall();
+        dev.init().is_ok()
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for Recovering<D> {
+    fn init(&mut self) -> Result<(), IoError> {
+        self.dev.write().init()
+    }
+
+    fn block_size(&self) -> usize {
+        self.dev.read().block_size()
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.dev.read().num_blocks()
+    }
+
+    fn read_at(&self, lba: Lba, buf: &mut [MaybeUninit<u8>]) -> Result<(), IoError> {
+        self.retry(&mut |dev| dev.read_at(lba, buf))
+    }
+
+    fn write_at(&self, lba: Lba, buf: &[u8]) -> Result<(), IoError> {
+        self.retry(&mut |dev| dev.write_at(lba, buf))
+    }
+
+    fn flush(&self) -> Result<(), IoError> {
+        self.retry(&mut |dev| dev.flush())
+    }
+
+    fn max_io_bytes(&self) -> Result<Option<usize>, IoError> {
+        self.dev.read().max_io_bytes()
+    }
+
+    fn is_read_only(&self) -> Result<bool, IoError> {
+        self.dev.read().is_read_only()
+    }
+
+    fn uninstall(&self) {
+        self.dev.read().uninstall()
+    }
+}
@@ -2,31 +2,109 @@
 
 extern crate alloc;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use block_device::VirtIoBlk;
 use block_device_api::BlockDevice;
 use block_device_api::IoError;
-use filesystem::FileSystemErr;
+use core::ops::ControlFlow;
+use dtb::DtbParser;
 use filesystem::PartitionIndex;
+use filesystem::recovery::Recovering;
 
+pub use filesystem::FileSystemErr;
+pub use filesystem::filesystem::DirEntry;
 pub use filesystem::filesystem::FileHandle;
 pub use filesystem::filesystem::OpenOptions;
+pub use filesystem::partition::FileSystemKind;
+pub use filesystem::partition::Guid;
+pub use filesystem::partition::PartitionInfo;
+pub use filesystem::partition::PartitionKind;
+pub use filesystem::recovery::RetryPolicy;
+pub use filesystem::recovery::set_retry_policy;
+// `FileHandle::read_struct` reads any `BytePod`
+pub use typestate::BytePod;
 
 pub struct StorageDevice {
     dev: Arc<dyn BlockDevice>,
     partition: PartitionIndex,
+    kind: &'static str,
+    address: usize,
 }
 
+/// A controller `StorageDevice::probe_all` looks for in the device tree
+struct Probe {
+    compatible: &'static str,
+    init: fn(usize) -> Result<StorageDevice, StorageDeviceErr>,
+}
+
+// SDHCI and NVMe controllers are added here once they have a driver
+const PROBES: &[Probe] = &[Probe {
+    compatible: "virtio,mmio",
+    init: StorageDevice::new_virtio,
+}];
+
 impl StorageDevice {
     pub fn new_virtio(mmio: usize) -> Result<Self, StorageDeviceErr> {
         let mut io = VirtIoBlk::new(mmio).map_err(error_from_ioerror)?;
         io.init().map_err(error_from_ioerror)?;
-        let dev = Arc::new(io);
+        Self::new(io, "virtio-blk", mmio)
+    }
+
+    fn new<D: BlockDevice + 'static>(
+        io: D,
+        kind: &'static str,
+        address: usize,
+    ) -> Result<Self, StorageDeviceErr> {
+        let dev = Arc::new(Recovering::new(io));
         Ok(Self {
             partition: PartitionIndex::new(dev.as_ref()).map_err(error_from_file_system_err)?,
             dev,
+            kind,
+            address,
         })
     }
 
+    /// Initialize every storage controller of `dtb`, in tree order. Nodes which are not
+    /// storage (e.g. a virtio-mmio slot of another device type) or fail to initialize are
+    /// skipped
+    pub fn probe_all(dtb: &DtbParser) -> Vec<StorageDevice> {
+        let mut devices = Vec::new();
+        for probe in PROBES {
+            // a malformed tree only ends the search early
+            let _ = dtb.find_node(None, Some(probe.compatible), &mut |addr, _size| {
+                if let Ok(device) = (probe.init)(addr) {
+                    devices.push(device);
+                }
+                ControlFlow::Continue(())
+            });
+        }
+        devices
+    }
+
+    /// The driver, e.g. `virtio-blk`
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    /// The base address of the controller
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// The partitions of the disk with the filesystem found on each
+    pub fn partitions(&self) -> Vec<PartitionInfo> {
+        self.partition.partitions(&self.dev)
+    }
+
+    /// The index of the partition `name` refers to: an index, a GPT partition name or a
+    /// GPT unique partition GUID
+    pub fn find_partition(&self, name: &str) -> Option<u8> {
+        self.partitions()
+            .iter()
+            .find(|partition| partition.matches(name))
+            .map(|partition| partition.index)
+    }
+
     pub fn open(
         &self,
         partition_idx: u8,
@@ -37,10 +115,88 @@ impl StorageDevice {
             .open(&self.dev, partition_idx, path, opts)
             .map_err(error_from_file_system_err)
     }
+
+    pub fn read_dir(
+        &self,
+        partition_idx: u8,
+        path: &str,
+        f: &mut dyn FnMut(&DirEntry) -> ControlFlow<()>,
+    ) -> Result<(), StorageDeviceErr> {
+        self.partition
+            .read_dir(&self.dev, partition_idx, path, f)
+            .map_err(error_from_file_system_err)
+    }
+
+    /// Flush every mounted partition and mark the written ones clean
+    pub fn unmount(&self) -> Result<(), StorageDeviceErr> {
+        self.partition
+            .unmount(&self.dev)
+            .map_err(error_from_file_system_err)
+    }
+
+    pub fn is_read_only(&self) -> Result<bool, StorageDeviceErr> {
+        self.dev.is_read_only().map_err(error_from_ioerror)
+    }
+
+    // the write operations fail with `ReadOnly` before reaching the filesystem
+    fn check_writable(&self) -> Result<(), StorageDeviceErr> {
+        if self.is_read_only()? {
+            return Err(StorageDeviceErr::FileSystemErr(FileSystemErr::ReadOnly));
+        }
+        Ok(())
+    }
+
+    /// Create an empty file at `path` and open it for writing
+    pub fn create(&self, partition_idx: u8, path: &str) -> Result<FileHandle, StorageDeviceErr> {
+        self.check_writable()?;
+        self.partition
+            .create(&self.dev, partition_idx, path)
+            .map_err(error_from_file_system_err)
+    }
+
+    /// Write `data` at `offset` of the existing file `path`. Returns the bytes written
+    pub fn write(
+        &self,
+        partition_idx: u8,
+        path: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<u64, StorageDeviceErr> {
+        self.check_writable()?;
+        let mut file = self.open(partition_idx, path, &OpenOptions::Write)?;
+        let written = file
+            .write_at(offset, data)
+            .map_err(error_from_file_system_err)?;
+        file.flush().map_err(error_from_file_system_err)?;
+        Ok(written)
+    }
+
+    pub fn remove_file(&self, partition_idx: u8, path: &str) -> Result<(), StorageDeviceErr> {
+        self.check_writable()?;
+        self.partition
+            .remove_file(&self.dev, partition_idx, path)
+            .map_err(error_from_file_system_err)
+    }
+
+    pub fn rename(&self, partition_idx: u8, from: &str, to: &str) -> Result<(), StorageDeviceErr> {
+        self.check_writable()?;
+        self.partition
+            .rename(&self.dev, partition_idx, from, to)
+            .map_err(error_from_file_system_err)
+    }
+
+    pub fn create_dir(&self, partition_idx: u8, path: &str) -> Result<(), StorageDeviceErr> {
+        self.check_writable()?;
+        self.partition
+            .create_dir(&self.dev, partition_idx, path)
+            .map_err(error_from_file_system_err)
+    }
 }
 
 impl Drop for StorageDevice {
     fn drop(&mut self) {
+        // a volume which can not be marked clean is left dirty for the next checker
+        let _ = self.partition.unmount(&self.dev);
         self.dev.uninstall();
     }
 }
@@ -7,7 +7,9 @@ compile_error!("This test is intended to run on aarch64 targets only");
 use arch_hal::debug_uart;
 use arch_hal::exit_failure;
 use arch_hal::exit_success;
+use arch_hal::panic_report;
 use arch_hal::println;
+use arch_hal::uart::UartConfig;
 use file::StorageDevice;
 use file::StorageDeviceErr;
 use filesystem::FileSystemErr;
@@ -16,7 +18,7 @@ const VIRTIO_MMIO_BASE: usize = 0x0a00_0000;
 
 #[unsafe(no_mangle)]
 extern "C" fn efi_main() -> ! {
-    debug_uart::init(0x900_0000);
+    debug_uart::init(0x900_0000, None);
     match run() {
         Ok(()) => {
             println!("fat32_virtio test: PASS");
@@ -33,6 +35,7 @@ fn run() -> Result<(), &'static str> {
     println!("Starting fat32_virtio test");
     let device = StorageDevice::new_virtio(VIRTIO_MMIO_BASE).unwrap();
     println!("fat32_virtio init success");
+    assert!(!device.partitions()[0].dirty);
     let handle = device
         .open(0, "/hello.txt", &file::OpenOptions::Read)
         .unwrap();
@@ -71,6 +74,7 @@ fn run() -> Result<(), &'static str> {
 
 #[panic_handler]
 fn panic_handler(info: &core::panic::PanicInfo<'_>) -> ! {
-    println!("PANIC: {}", info);
+    let mut console = panic_report::console(UartConfig::pl011(0x900_0000, None), 115200);
+    panic_report::report(&mut console, info);
     exit_failure()
 }
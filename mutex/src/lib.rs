@@ -203,6 +203,101 @@ impl<T> DerefMut for RwLockWriteGuard<'_, T> {
     }
 }
 
+// sleep until an event, e.g. the `sev` of `send_event`. an event sent since the last
+// wait ends it at once, so a condition checked before waiting is not missed
+#[inline]
+fn wait_for_event() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("wfe", options(nomem, nostack, preserves_flags))
+    };
+    #[cfg(not(target_arch = "aarch64"))]
+    core::hint::spin_loop();
+}
+
+// wake every CPU in `wait_for_event`, after the preceding stores are visible to them
+#[inline]
+fn send_event() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("dsb ish", "sev", options(nostack, preserves_flags))
+    };
+}
+
+/// Rendezvous of a fixed number of CPUs, reusable once all of them passed.
+/// Waiting CPUs sleep in `wfe`. The CPUs must run with the MMU and caches on, exclusive
+/// accesses to non-cacheable memory may never succeed.
+pub struct Barrier {
+    count: usize,
+    arrived: AtomicUsize,
+    generation: AtomicUsize,
+}
+
+impl Barrier {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count,
+            arrived: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait until `count` CPUs called `wait`. Returns true on the last CPU to arrive only.
+    pub fn wait(&self) -> bool {
+        let generation = self.generation.load(Ordering::Acquire);
+        if self.arrived.fetch_add(1, Ordering::AcqRel) + 1 >= self.count {
+            // no CPU leaves before the generation changes, so none arrives for the next
+            // round before the reset
+            self.arrived.store(0, Ordering::Relaxed);
+            self.generation
+                .store(generation.wrapping_add(1), Ordering::Release);
+            send_event();
+            return true;
+        }
+        while self.generation.load(Ordering::Acquire) == generation {
+            wait_for_event();
+        }
+        false
+    }
+}
+
+/// Wait of one or more CPUs until others counted down to zero, e.g. until every secondary
+/// CPU is ready. Single use. Waiting CPUs sleep in `wfe` like with `Barrier`.
+pub struct CountDownLatch {
+    count: AtomicUsize,
+}
+
+impl CountDownLatch {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+        }
+    }
+
+    /// Decrement the count, nothing once it is zero. Wakes the waiting CPUs at zero.
+    pub fn count_down(&self) {
+        let previous = self
+            .count
+            .try_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_sub(1)
+            });
+        if previous == Ok(1) {
+            send_event();
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Wait until the count is zero
+    pub fn wait(&self) {
+        while self.count() != 0 {
+            wait_for_event();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,4 +364,62 @@ mod tests {
         // After all threads are done, the final value should be 100.
         assert_eq!(*lock.read(), 100);
     }
+
+    #[test]
+    fn barrier_test() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 100;
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let arrived = Arc::new(AtomicUsize::new(0));
+        let mut handles = vec![];
+
+        for _ in 0..THREADS {
+            let barrier = Arc::clone(&barrier);
+            let arrived = Arc::clone(&arrived);
+            handles.push(thread::spawn(move || {
+                let mut leaders = 0;
+                for round in 0..ROUNDS {
+                    arrived.fetch_add(1, Ordering::Relaxed);
+                    if barrier.wait() {
+                        leaders += 1;
+                    }
+                    // every thread of this round arrived, none of the next one yet
+                    let count = arrived.load(Ordering::Relaxed);
+                    assert!(count >= (round + 1) * THREADS);
+                    assert!(count <= (round + 2) * THREADS);
+                    barrier.wait();
+                }
+                leaders
+            }));
+        }
+
+        let leaders: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(leaders, ROUNDS);
+    }
+
+    #[test]
+    fn count_down_latch_test() {
+        let latch = Arc::new(CountDownLatch::new(4));
+        let ready = Arc::new(AtomicUsize::new(0));
+        let mut handles = vec![];
+
+        for _ in 0..4 {
+            let latch = Arc::clone(&latch);
+            let ready = Arc::clone(&ready);
+            handles.push(thread::spawn(move || {
+                ready.fetch_add(1, Ordering::Relaxed);
+                latch.count_down();
+            }));
+        }
+
+        latch.wait();
+        assert_eq!(ready.load(Ordering::Relaxed), 4);
+        // stays at zero
+        latch.count_down();
+        assert_eq!(latch.count(), 0);
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
 }
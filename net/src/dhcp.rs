@@ -0,0 +1,322 @@
+// DHCP client (RFC 2131): DISCOVER, OFFER, REQUEST, ACK
+//
+// The server is asked to broadcast its replies since the interface has no address yet.
+// Leases are not renewed, the kernel brings up its own network.
+
+use crate::Interface;
+use crate::Ipv4Addr;
+use crate::NetDevice;
+use crate::NetErr;
+use alloc::string::String;
+
+const CLIENT_PORT: u16 = 68;
+const SERVER_PORT: u16 = 67;
+const TIMEOUT_MS: u64 = 2000;
+const RETRIES: usize = 4;
+
+const OP_REQUEST: u8 = 1;
+const OP_REPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+const FLAG_BROADCAST: u16 = 0x8000;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+// fixed fields before the magic cookie
+const FIXED_SIZE: usize = 236;
+// messages are padded to the minimum size a server must accept
+const MESSAGE_SIZE: usize = 300;
+const FILE_OFFSET: usize = 108;
+const FILE_SIZE: usize = 128;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_PARAMETERS: u8 = 55;
+const OPTION_TFTP_SERVER: u8 = 66;
+const OPTION_BOOT_FILE: u8 = 67;
+const OPTION_END: u8 = 255;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+/// configuration received from the DHCP server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub address: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+    /// the DHCP server
+    pub server: Ipv4Addr,
+    /// TFTP server: option 66 when it is an address, else `siaddr`, else the DHCP server
+    pub boot_server: Ipv4Addr,
+    /// option 67 or the `file` field
+    pub boot_file: Option<String>,
+}
+
+/// Obtain a lease and configure `interface` with it
+pub fn request<D: NetDevice>(interface: &mut Interface<D>) -> Result<Lease, NetErr> {
+    let mac = interface.mac().0;
+    let xid = u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]]) ^ interface.now() as u32;
+    let mut buf = [0u8; crate::MAX_UDP_PAYLOAD];
+
+    let offer = exchange(interface, xid, &mut buf, DHCPDISCOVER, None, DHCPOFFER)?;
+    let requested = Some((offer.address, offer.server));
+    let lease = exchange(interface, xid, &mut buf, DHCPREQUEST, requested, DHCPACK)?;
+    interface.configure(lease.address, lease.netmask, lease.gateway);
+    Ok(lease)
+}
+
+// send `message_type` until a reply of `expected` type arrives
+fn exchange<D: NetDevice>(
+    interface: &mut Interface<D>,
+    xid: u32,
+    buf: &mut [u8],
+    message_type: u8,
+    requested: Option<(Ipv4Addr, Ipv4Addr)>,
+    expected: u8,
+) -> Result<Lease, NetErr> {
+    let mut message = [0u8; MESSAGE_SIZE];
+    write_message(
+        &mut message,
+        xid,
+        interface.mac().0,
+        message_type,
+        requested,
+    );
+    for _ in 0..RETRIES {
+        interface.send_udp(Ipv4Addr::BROADCAST, CLIENT_PORT, SERVER_PORT, &message)?;
+        let deadline = interface.now() + TIMEOUT_MS;
+        while let Some(timeout) = deadline.checked_sub(interface.now()) {
+            let Some((_, _, len)) = interface.receive_udp(CLIENT_PORT, buf, timeout)? else {
+                break;
+            };
+            match parse_reply(&buf[..len], xid) {
+                Some((reply_type, lease)) if reply_type == expected => return Ok(lease),
+                Some((DHCPNAK, _)) => return Err(NetErr::DhcpNak),
+                _ => continue,
+            }
+        }
+    }
+    Err(NetErr::Timeout)
+}
+
+fn write_message(
+    message: &mut [u8; MESSAGE_SIZE],
+    xid: u32,
+    mac: [u8; 6],
+    message_type: u8,
+    requested: Option<(Ipv4Addr, Ipv4Addr)>,
+) {
+    message[0] = OP_REQUEST;
+    message[1] = HTYPE_ETHERNET;
+    message[2] = mac.len() as u8;
+    message[4..8].copy_from_slice(&xid.to_be_bytes());
+    message[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+    message[28..34].copy_from_slice(&mac);
+    message[FIXED_SIZE..FIXED_SIZE + 4].copy_from_slice(&MAGIC_COOKIE);
+    let mut options = FIXED_SIZE + 4;
+    let mut push = |code: u8, value: &[u8]| {
+        message[options] = code;
+        message[options + 1] = value.len() as u8;
+        message[options + 2..options + 2 + value.len()].copy_from_slice(value);
+        options += 2 + value.len();
+    };
+    push(OPTION_MESSAGE_TYPE, &[message_type]);
+    if let Some((address, server)) = requested {
+        push(OPTION_REQUESTED_ADDRESS, &address.0);
+        push(OPTION_SERVER_ID, &server.0);
+    }
+    push(
+        OPTION_PARAMETERS,
+        &[
+            OPTION_SUBNET_MASK,
+            OPTION_ROUTER,
+            OPTION_TFTP_SERVER,
+            OPTION_BOOT_FILE,
+        ],
+    );
+    message[options] = OPTION_END;
+}
+
+// (message type, lease) of a reply to `xid`
+fn parse_reply(message: &[u8], xid: u32) -> Option<(u8, Lease)> {
+    if message.len() < FIXED_SIZE + MAGIC_COOKIE.len()
+        || message[0] != OP_REPLY
+        || message[4..8] != xid.to_be_bytes()
+        || message[FIXED_SIZE..FIXED_SIZE + 4] != MAGIC_COOKIE
+    {
+        return None;
+    }
+    let address = |offset: usize| Ipv4Addr(message[offset..offset + 4].try_into().unwrap());
+    let next_server = address(20);
+    let mut message_type = None;
+    let mut lease = Lease {
+        address: address(16),
+        netmask: Ipv4Addr([255, 255, 255, 0]),
+        gateway: None,
+        server: next_server,
+        boot_server: next_server,
+        // option overload (options in `file` and `sname`) is not supported
+        boot_file: c_string(&message[FILE_OFFSET..FILE_OFFSET + FILE_SIZE]),
+    };
+    let mut tftp_server = None;
+    let mut options = &message[FIXED_SIZE + 4..];
+    loop {
+        match *options {
+            [OPTION_END, ..] | [] => break,
+            [OPTION_PAD, ref rest @ ..] => options = rest,
+            [code, len, ref rest @ ..] => {
+                let value = rest.get(..usize::from(len))?;
+                let ipv4 = value.get(..4).map(|a| Ipv4Addr(a.try_into().unwrap()));
+                match code {
+                    OPTION_MESSAGE_TYPE => message_type = value.first().copied(),
+                    OPTION_SUBNET_MASK => lease.netmask = ipv4?,
+                    OPTION_ROUTER => lease.gateway = ipv4,
+                    OPTION_SERVER_ID => lease.server = ipv4?,
+                    OPTION_TFTP_SERVER => {
+                        tftp_server = c_string(value).as_deref().and_then(Ipv4Addr::parse)
+                    }
+                    OPTION_BOOT_FILE => lease.boot_file = c_string(value),
+                    _ => {}
+                }
+                options = &rest[usize::from(len)..];
+            }
+            [_] => return None,
+        }
+    }
+    if let Some(server) = tftp_server {
+        lease.boot_server = server;
+    } else if next_server == Ipv4Addr::UNSPECIFIED {
+        lease.boot_server = lease.server;
+    }
+    Some((message_type?, lease))
+}
+
+// NUL terminated or full-length text, `None` if empty
+fn c_string(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let text = core::str::from_utf8(&bytes[..end]).ok()?;
+    (!text.is_empty()).then(|| String::from(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MacAddr;
+    use crate::ethernet;
+    use crate::ipv4;
+    use crate::tests::Loopback;
+    use crate::tests::ticking_clock;
+    use alloc::collections::VecDeque;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    const CLIENT_MAC: MacAddr = MacAddr([0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+    const SERVER_IP: Ipv4Addr = Ipv4Addr([10, 0, 2, 2]);
+    const OFFERED: Ipv4Addr = Ipv4Addr([10, 0, 2, 15]);
+
+    // a server offering `OFFERED`, answering the request with `answer`
+    fn server(answer: u8) -> impl FnMut(&[u8]) -> Vec<Vec<u8>> {
+        move |frame| {
+            let (_, _, packet) = ethernet::parse(frame).unwrap();
+            let datagram = ipv4::parse_udp(packet).unwrap();
+            assert_eq!(datagram.destination_port, SERVER_PORT);
+            let request = datagram.payload;
+            assert_eq!(request.len(), MESSAGE_SIZE);
+            let options = &request[FIXED_SIZE + 4..];
+            let reply_type = match options[..3] {
+                [OPTION_MESSAGE_TYPE, 1, DHCPDISCOVER] => DHCPOFFER,
+                [OPTION_MESSAGE_TYPE, 1, DHCPREQUEST] => {
+                    // requested address and server id follow
+                    assert_eq!(options[3..9], [OPTION_REQUESTED_ADDRESS, 4, 10, 0, 2, 15]);
+                    answer
+                }
+                _ => panic!("unexpected request"),
+            };
+            let mut reply = vec![0u8; FIXED_SIZE + 4];
+            reply[0] = OP_REPLY;
+            reply[4..8].copy_from_slice(&request[4..8]);
+            reply[16..20].copy_from_slice(&OFFERED.0);
+            reply[FILE_OFFSET..FILE_OFFSET + 5].copy_from_slice(b"Image");
+            reply[FIXED_SIZE..].copy_from_slice(&MAGIC_COOKIE);
+            reply.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, reply_type]);
+            reply.extend_from_slice(&[OPTION_SERVER_ID, 4, 10, 0, 2, 2]);
+            reply.extend_from_slice(&[OPTION_SUBNET_MASK, 4, 255, 255, 0, 0, OPTION_PAD]);
+            reply.extend_from_slice(&[OPTION_ROUTER, 4, 10, 0, 2, 1]);
+            reply.extend_from_slice(&[OPTION_TFTP_SERVER, 9]);
+            reply.extend_from_slice(b"10.0.2.3\0");
+            reply.push(OPTION_END);
+
+            let mut frame = vec![0u8; ethernet::HEADER_SIZE + 28 + reply.len()];
+            ethernet::write_header(
+                &mut frame,
+                MacAddr::BROADCAST,
+                MacAddr([2; 6]),
+                ethernet::ETHERTYPE_IPV4,
+            );
+            frame[ethernet::HEADER_SIZE + 28..].copy_from_slice(&reply);
+            ipv4::write_udp(
+                &mut frame[ethernet::HEADER_SIZE..],
+                SERVER_IP,
+                Ipv4Addr::BROADCAST,
+                SERVER_PORT,
+                CLIENT_PORT,
+                reply.len(),
+                0,
+            );
+            vec![frame]
+        }
+    }
+
+    #[test]
+    fn obtain_lease() {
+        let clock = ticking_clock();
+        let device = Loopback {
+            mac: CLIENT_MAC,
+            peer: server(DHCPACK),
+            pending: VecDeque::new(),
+        };
+        let mut interface = Interface::new(device, &clock);
+        let lease = request(&mut interface).unwrap();
+        assert_eq!(
+            lease,
+            Lease {
+                address: OFFERED,
+                netmask: Ipv4Addr([255, 255, 0, 0]),
+                gateway: Some(Ipv4Addr([10, 0, 2, 1])),
+                server: SERVER_IP,
+                boot_server: Ipv4Addr([10, 0, 2, 3]),
+                boot_file: Some(String::from("Image")),
+            }
+        );
+        assert_eq!(interface.address(), OFFERED);
+    }
+
+    #[test]
+    fn refused_request() {
+        let clock = ticking_clock();
+        let device = Loopback {
+            mac: CLIENT_MAC,
+            peer: server(DHCPNAK),
+            pending: VecDeque::new(),
+        };
+        let mut interface = Interface::new(device, &clock);
+        assert_eq!(request(&mut interface), Err(NetErr::DhcpNak));
+    }
+
+    #[test]
+    fn no_server() {
+        let clock = ticking_clock();
+        let device = Loopback {
+            mac: CLIENT_MAC,
+            peer: |_: &[u8]| Vec::new(),
+            pending: VecDeque::new(),
+        };
+        let mut interface = Interface::new(device, &clock);
+        assert_eq!(request(&mut interface), Err(NetErr::Timeout));
+    }
+}
@@ -0,0 +1,73 @@
+// Ethernet II framing and ARP for IPv4 over Ethernet
+
+use crate::Ipv4Addr;
+use crate::MacAddr;
+
+pub(crate) const HEADER_SIZE: usize = 14;
+pub(crate) const ETHERTYPE_IPV4: u16 = 0x0800;
+pub(crate) const ETHERTYPE_ARP: u16 = 0x0806;
+
+pub(crate) const ARP_PACKET_SIZE: usize = 28;
+const ARP_HTYPE_ETHERNET: u16 = 1;
+pub(crate) const ARP_REQUEST: u16 = 1;
+pub(crate) const ARP_REPLY: u16 = 2;
+
+/// write the header for a frame from `source` to `destination` at the start of `frame`
+pub(crate) fn write_header(
+    frame: &mut [u8],
+    destination: MacAddr,
+    source: MacAddr,
+    ethertype: u16,
+) {
+    frame[0..6].copy_from_slice(&destination.0);
+    frame[6..12].copy_from_slice(&source.0);
+    frame[12..14].copy_from_slice(&ethertype.to_be_bytes());
+}
+
+/// (source, ethertype, payload) of `frame`
+pub(crate) fn parse(frame: &[u8]) -> Option<(MacAddr, u16, &[u8])> {
+    if frame.len() < HEADER_SIZE {
+        return None;
+    }
+    let source = MacAddr(frame[6..12].try_into().unwrap());
+    let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    Some((source, ethertype, &frame[HEADER_SIZE..]))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ArpPacket {
+    pub(crate) operation: u16,
+    pub(crate) sender_mac: MacAddr,
+    pub(crate) sender_ip: Ipv4Addr,
+    pub(crate) target_mac: MacAddr,
+    pub(crate) target_ip: Ipv4Addr,
+}
+
+impl ArpPacket {
+    pub(crate) fn parse(payload: &[u8]) -> Option<Self> {
+        let packet = payload.get(..ARP_PACKET_SIZE)?;
+        // hardware type, protocol type, their address lengths
+        if packet[0..6] != [0, 1, 0x08, 0x00, 6, 4] {
+            return None;
+        }
+        Some(Self {
+            operation: u16::from_be_bytes([packet[6], packet[7]]),
+            sender_mac: MacAddr(packet[8..14].try_into().unwrap()),
+            sender_ip: Ipv4Addr(packet[14..18].try_into().unwrap()),
+            target_mac: MacAddr(packet[18..24].try_into().unwrap()),
+            target_ip: Ipv4Addr(packet[24..28].try_into().unwrap()),
+        })
+    }
+
+    pub(crate) fn write(&self, payload: &mut [u8]) {
+        payload[0..2].copy_from_slice(&ARP_HTYPE_ETHERNET.to_be_bytes());
+        payload[2..4].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        payload[4] = 6;
+        payload[5] = 4;
+        payload[6..8].copy_from_slice(&self.operation.to_be_bytes());
+        payload[8..14].copy_from_slice(&self.sender_mac.0);
+        payload[14..18].copy_from_slice(&self.sender_ip.0);
+        payload[18..24].copy_from_slice(&self.target_mac.0);
+        payload[24..28].copy_from_slice(&self.target_ip.0);
+    }
+}
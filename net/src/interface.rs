@@ -0,0 +1,313 @@
+// IPv4 interface over a `NetDevice`: addressing, ARP and UDP send/receive
+
+use crate::Clock;
+use crate::Ipv4Addr;
+use crate::MAX_FRAME_SIZE;
+use crate::MacAddr;
+use crate::NetDevice;
+use crate::NetErr;
+use crate::ethernet;
+use crate::ethernet::ArpPacket;
+use crate::ipv4;
+
+const ARP_TIMEOUT_MS: u64 = 1000;
+const ARP_RETRIES: usize = 3;
+const NEIGHBOUR_CACHE_SIZE: usize = 4;
+
+/// largest UDP payload sent or received
+pub const MAX_UDP_PAYLOAD: usize =
+    MAX_FRAME_SIZE - ethernet::HEADER_SIZE - ipv4::HEADER_SIZE - ipv4::UDP_HEADER_SIZE;
+
+pub struct Interface<'a, D: NetDevice> {
+    device: D,
+    mac: MacAddr,
+    clock: Clock<'a>,
+    address: Ipv4Addr,
+    netmask: Ipv4Addr,
+    gateway: Option<Ipv4Addr>,
+    // resolved neighbours, replaced round-robin
+    neighbours: [Option<(Ipv4Addr, MacAddr)>; NEIGHBOUR_CACHE_SIZE],
+    next_neighbour: usize,
+    identification: u16,
+}
+
+impl<'a, D: NetDevice> Interface<'a, D> {
+    /// an interface without an address, only broadcasts can be sent until `configure`
+    pub fn new(device: D, clock: Clock<'a>) -> Self {
+        Self {
+            mac: device.mac(),
+            device,
+            clock,
+            address: Ipv4Addr::UNSPECIFIED,
+            netmask: Ipv4Addr::UNSPECIFIED,
+            gateway: None,
+            neighbours: [None; NEIGHBOUR_CACHE_SIZE],
+            next_neighbour: 0,
+            identification: 0,
+        }
+    }
+
+    pub fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    pub fn address(&self) -> Ipv4Addr {
+        self.address
+    }
+
+    pub fn configure(&mut self, address: Ipv4Addr, netmask: Ipv4Addr, gateway: Option<Ipv4Addr>) {
+        self.address = address;
+        self.netmask = netmask;
+        self.gateway = gateway;
+    }
+
+    pub(crate) fn now(&self) -> u64 {
+        (self.clock)()
+    }
+
+    /// send `payload` to `destination`, resolving the next hop first
+    pub fn send_udp(
+        &mut self,
+        destination: Ipv4Addr,
+        source_port: u16,
+        destination_port: u16,
+        payload: &[u8],
+    ) -> Result<(), NetErr> {
+        if payload.len() > MAX_UDP_PAYLOAD {
+            return Err(NetErr::TooLarge);
+        }
+        let next_hop = self.resolve(destination)?;
+        let mut frame = [0u8; MAX_FRAME_SIZE];
+        ethernet::write_header(&mut frame, next_hop, self.mac, ethernet::ETHERTYPE_IPV4);
+        let packet = &mut frame[ethernet::HEADER_SIZE..];
+        packet[ipv4::HEADER_SIZE + ipv4::UDP_HEADER_SIZE..][..payload.len()]
+            .copy_from_slice(payload);
+        self.identification = self.identification.wrapping_add(1);
+        let len = ipv4::write_udp(
+            packet,
+            self.address,
+            destination,
+            source_port,
+            destination_port,
+            payload.len(),
+            self.identification,
+        );
+        self.device.send(&frame[..ethernet::HEADER_SIZE + len])
+    }
+
+    /// Wait up to `timeout_ms` for a datagram to `port` and copy its payload into `buf`.
+    /// Returns (source, source port, length), `None` on timeout.
+    pub fn receive_udp(
+        &mut self,
+        port: u16,
+        buf: &mut [u8],
+        timeout_ms: u64,
+    ) -> Result<Option<(Ipv4Addr, u16, usize)>, NetErr> {
+        let deadline = self.now() + timeout_ms;
+        let mut frame = [0u8; MAX_FRAME_SIZE];
+        while self.now() < deadline {
+            let Some(len) = self.poll(&mut frame)? else {
+                core::hint::spin_loop();
+                continue;
+            };
+            let Some(datagram) = ipv4::parse_udp(&frame[ethernet::HEADER_SIZE..len]) else {
+                continue;
+            };
+            let for_us = self.address == Ipv4Addr::UNSPECIFIED
+                || datagram.destination == self.address
+                || datagram.destination == Ipv4Addr::BROADCAST;
+            if !for_us || datagram.destination_port != port {
+                continue;
+            }
+            let len = datagram.payload.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram.payload[..len]);
+            return Ok(Some((datagram.source, datagram.source_port, len)));
+        }
+        Ok(None)
+    }
+
+    // Receive one frame into `frame`. ARP is handled here, `Some` is an IPv4 frame.
+    fn poll(&mut self, frame: &mut [u8; MAX_FRAME_SIZE]) -> Result<Option<usize>, NetErr> {
+        let Some(len) = self.device.receive(frame)? else {
+            return Ok(None);
+        };
+        let Some((_, ethertype, payload)) = ethernet::parse(&frame[..len]) else {
+            return Ok(None);
+        };
+        match ethertype {
+            ethernet::ETHERTYPE_IPV4 => Ok(Some(len)),
+            ethernet::ETHERTYPE_ARP => {
+                if let Some(arp) = ArpPacket::parse(payload) {
+                    self.handle_arp(arp)?;
+                }
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn handle_arp(&mut self, arp: ArpPacket) -> Result<(), NetErr> {
+        if self.address == Ipv4Addr::UNSPECIFIED || arp.target_ip != self.address {
+            return Ok(());
+        }
+        self.learn(arp.sender_ip, arp.sender_mac);
+        if arp.operation == ethernet::ARP_REQUEST {
+            self.send_arp(ethernet::ARP_REPLY, arp.sender_mac, arp.sender_ip)?;
+        }
+        Ok(())
+    }
+
+    fn send_arp(
+        &mut self,
+        operation: u16,
+        target_mac: MacAddr,
+        target_ip: Ipv4Addr,
+    ) -> Result<(), NetErr> {
+        let mut frame = [0u8; ethernet::HEADER_SIZE + ethernet::ARP_PACKET_SIZE];
+        let destination = match operation {
+            ethernet::ARP_REQUEST => MacAddr::BROADCAST,
+            _ => target_mac,
+        };
+        ethernet::write_header(&mut frame, destination, self.mac, ethernet::ETHERTYPE_ARP);
+        ArpPacket {
+            operation,
+            sender_mac: self.mac,
+            sender_ip: self.address,
+            target_mac,
+            target_ip,
+        }
+        .write(&mut frame[ethernet::HEADER_SIZE..]);
+        self.device.send(&frame)
+    }
+
+    fn learn(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        if let Some(entry) = self.neighbours.iter_mut().flatten().find(|(i, _)| *i == ip) {
+            entry.1 = mac;
+            return;
+        }
+        self.neighbours[self.next_neighbour] = Some((ip, mac));
+        self.next_neighbour = (self.next_neighbour + 1) % NEIGHBOUR_CACHE_SIZE;
+    }
+
+    fn neighbour(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.neighbours
+            .iter()
+            .flatten()
+            .find(|(i, _)| *i == ip)
+            .map(|(_, mac)| *mac)
+    }
+
+    // MAC address of the next hop to `destination`
+    fn resolve(&mut self, destination: Ipv4Addr) -> Result<MacAddr, NetErr> {
+        if destination == Ipv4Addr::BROADCAST {
+            return Ok(MacAddr::BROADCAST);
+        }
+        let mask = self.netmask.to_u32();
+        let next_hop = if destination.to_u32() & mask == self.address.to_u32() & mask {
+            destination
+        } else {
+            self.gateway.ok_or(NetErr::Unreachable)?
+        };
+        if let Some(mac) = self.neighbour(next_hop) {
+            return Ok(mac);
+        }
+        let mut frame = [0u8; MAX_FRAME_SIZE];
+        for _ in 0..ARP_RETRIES {
+            self.send_arp(ethernet::ARP_REQUEST, MacAddr([0; 6]), next_hop)?;
+            let deadline = self.now() + ARP_TIMEOUT_MS;
+            while self.now() < deadline {
+                // other traffic is dropped while resolving
+                self.poll(&mut frame)?;
+                if let Some(mac) = self.neighbour(next_hop) {
+                    return Ok(mac);
+                }
+            }
+        }
+        Err(NetErr::Timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Loopback;
+    use crate::tests::ticking_clock;
+    use alloc::collections::VecDeque;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    const SERVER_MAC: MacAddr = MacAddr([2, 0, 0, 0, 0, 2]);
+    const SERVER_IP: Ipv4Addr = Ipv4Addr([10, 0, 0, 2]);
+
+    // a host answering ARP requests and echoing UDP datagrams
+    fn echo_server(frame: &[u8]) -> Vec<Vec<u8>> {
+        let (source, ethertype, payload) = ethernet::parse(frame).unwrap();
+        match ethertype {
+            ethernet::ETHERTYPE_ARP => {
+                let request = ArpPacket::parse(payload).unwrap();
+                assert_eq!(request.operation, ethernet::ARP_REQUEST);
+                if request.target_ip != SERVER_IP {
+                    return vec![];
+                }
+                let mut reply = vec![0; ethernet::HEADER_SIZE + ethernet::ARP_PACKET_SIZE];
+                ethernet::write_header(&mut reply, source, SERVER_MAC, ethernet::ETHERTYPE_ARP);
+                ArpPacket {
+                    operation: ethernet::ARP_REPLY,
+                    sender_mac: SERVER_MAC,
+                    sender_ip: SERVER_IP,
+                    target_mac: request.sender_mac,
+                    target_ip: request.sender_ip,
+                }
+                .write(&mut reply[ethernet::HEADER_SIZE..]);
+                vec![reply]
+            }
+            ethernet::ETHERTYPE_IPV4 => {
+                let datagram = ipv4::parse_udp(payload).unwrap();
+                let mut reply = vec![0; MAX_FRAME_SIZE];
+                ethernet::write_header(&mut reply, source, SERVER_MAC, ethernet::ETHERTYPE_IPV4);
+                let packet = &mut reply[ethernet::HEADER_SIZE..];
+                packet[ipv4::HEADER_SIZE + ipv4::UDP_HEADER_SIZE..][..datagram.payload.len()]
+                    .copy_from_slice(datagram.payload);
+                let len = ipv4::write_udp(
+                    packet,
+                    datagram.destination,
+                    datagram.source,
+                    datagram.destination_port,
+                    datagram.source_port,
+                    datagram.payload.len(),
+                    0,
+                );
+                reply.truncate(ethernet::HEADER_SIZE + len);
+                vec![reply]
+            }
+            _ => panic!("unexpected ethertype {:#x}", ethertype),
+        }
+    }
+
+    #[test]
+    fn resolve_and_exchange_datagram() {
+        let clock = ticking_clock();
+        let device = Loopback {
+            mac: MacAddr([2, 0, 0, 0, 0, 1]),
+            peer: echo_server,
+            pending: VecDeque::new(),
+        };
+        let mut interface = Interface::new(device, &clock);
+        interface.configure(Ipv4Addr([10, 0, 0, 1]), Ipv4Addr([255, 255, 255, 0]), None);
+        interface.send_udp(SERVER_IP, 4000, 7, b"ping").unwrap();
+        assert_eq!(interface.neighbour(SERVER_IP), Some(SERVER_MAC));
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            interface.receive_udp(4000, &mut buf, 100),
+            Ok(Some((SERVER_IP, 7, 4)))
+        );
+        assert_eq!(&buf[..4], b"ping");
+        // nothing else arrives
+        assert_eq!(interface.receive_udp(4000, &mut buf, 100), Ok(None));
+        // no gateway for other subnets
+        assert_eq!(
+            interface.send_udp(Ipv4Addr([10, 0, 1, 2]), 4000, 7, b"ping"),
+            Err(NetErr::Unreachable)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+// IPv4 and UDP headers
+//
+// Packets are sent with the don't-fragment bit and a zero UDP checksum, which IPv4
+// allows. Fragments are dropped on receive.
+
+use crate::Ipv4Addr;
+
+pub(crate) const HEADER_SIZE: usize = 20;
+pub(crate) const UDP_HEADER_SIZE: usize = 8;
+const PROTOCOL_UDP: u8 = 17;
+const DEFAULT_TTL: u8 = 64;
+const FLAG_DONT_FRAGMENT: u16 = 0x4000;
+const FLAG_MORE_FRAGMENTS: u16 = 0x2000;
+const FRAGMENT_OFFSET_MASK: u16 = 0x1FFF;
+
+/// a received UDP datagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Datagram<'a> {
+    pub(crate) source: Ipv4Addr,
+    pub(crate) destination: Ipv4Addr,
+    pub(crate) source_port: u16,
+    pub(crate) destination_port: u16,
+    pub(crate) payload: &'a [u8],
+}
+
+// ones' complement sum of 16-bit words, folded
+pub(crate) fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Write the IPv4 and UDP headers in front of the `payload_len` bytes at
+/// `packet[HEADER_SIZE + UDP_HEADER_SIZE..]`. Returns the packet length.
+pub(crate) fn write_udp(
+    packet: &mut [u8],
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    source_port: u16,
+    destination_port: u16,
+    payload_len: usize,
+    identification: u16,
+) -> usize {
+    let udp_len = UDP_HEADER_SIZE + payload_len;
+    let total_len = HEADER_SIZE + udp_len;
+    let header = &mut packet[..HEADER_SIZE];
+    header[0] = 0x45; // version 4, 5 words
+    header[1] = 0;
+    header[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+    header[4..6].copy_from_slice(&identification.to_be_bytes());
+    header[6..8].copy_from_slice(&FLAG_DONT_FRAGMENT.to_be_bytes());
+    header[8] = DEFAULT_TTL;
+    header[9] = PROTOCOL_UDP;
+    header[10..12].fill(0);
+    header[12..16].copy_from_slice(&source.0);
+    header[16..20].copy_from_slice(&destination.0);
+    let sum = checksum(header);
+    header[10..12].copy_from_slice(&sum.to_be_bytes());
+
+    let udp = &mut packet[HEADER_SIZE..HEADER_SIZE + UDP_HEADER_SIZE];
+    udp[0..2].copy_from_slice(&source_port.to_be_bytes());
+    udp[2..4].copy_from_slice(&destination_port.to_be_bytes());
+    udp[4..6].copy_from_slice(&(udp_len as u16).to_be_bytes());
+    udp[6..8].fill(0);
+    total_len
+}
+
+/// the UDP datagram in `packet`, `None` for other protocols, fragments and malformed packets
+pub(crate) fn parse_udp(packet: &[u8]) -> Option<Datagram<'_>> {
+    let header = packet.get(..HEADER_SIZE)?;
+    let header_len = usize::from(header[0] & 0x0F) * 4;
+    if header[0] >> 4 != 4 || header_len < HEADER_SIZE || header[9] != PROTOCOL_UDP {
+        return None;
+    }
+    let total_len = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    let fragment = u16::from_be_bytes([header[6], header[7]]);
+    if fragment & (FLAG_MORE_FRAGMENTS | FRAGMENT_OFFSET_MASK) != 0
+        || total_len > packet.len()
+        || checksum(packet.get(..header_len)?) != 0
+    {
+        return None;
+    }
+    let udp = packet.get(header_len..total_len)?;
+    let udp_len = usize::from(u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]));
+    if udp_len < UDP_HEADER_SIZE || udp_len > udp.len() {
+        return None;
+    }
+    Some(Datagram {
+        source: Ipv4Addr(header[12..16].try_into().unwrap()),
+        destination: Ipv4Addr(header[16..20].try_into().unwrap()),
+        source_port: u16::from_be_bytes([udp[0], udp[1]]),
+        destination_port: u16::from_be_bytes([udp[2], udp[3]]),
+        payload: &udp[UDP_HEADER_SIZE..udp_len],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn udp_round_trip() {
+        let mut packet = [0u8; 64];
+        let payload = b"hello";
+        packet[HEADER_SIZE + UDP_HEADER_SIZE..][..payload.len()].copy_from_slice(payload);
+        let len = write_udp(
+            &mut packet,
+            Ipv4Addr([10, 0, 2, 15]),
+            Ipv4Addr([10, 0, 2, 2]),
+            1234,
+            69,
+            payload.len(),
+            7,
+        );
+        assert_eq!(len, HEADER_SIZE + UDP_HEADER_SIZE + payload.len());
+        assert_eq!(checksum(&packet[..HEADER_SIZE]), 0);
+        let datagram = parse_udp(&packet[..len]).unwrap();
+        assert_eq!(datagram.source, Ipv4Addr([10, 0, 2, 15]));
+        assert_eq!(datagram.destination, Ipv4Addr([10, 0, 2, 2]));
+        assert_eq!(
+            (datagram.source_port, datagram.destination_port),
+            (1234, 69)
+        );
+        assert_eq!(datagram.payload, payload);
+
+        // a corrupted header is dropped
+        packet[8] ^= 1;
+        assert_eq!(parse_udp(&packet[..len]), None);
+    }
+
+    #[test]
+    fn checksum_of_odd_length() {
+        // the last byte is padded with zero
+        assert_eq!(checksum(&[0x12, 0x34, 0x56]), !0x6834);
+    }
+}
@@ -0,0 +1,157 @@
+#![cfg_attr(not(test), no_std)]
+
+// minimal IPv4 network boot: ARP, UDP, a DHCP client and a TFTP client
+//
+// Everything is polled from the boot CPU, one request in flight at a time. Only what a boot
+// server exchange needs is implemented: no fragmentation, no routing beyond the default
+// gateway, no TCP.
+
+extern crate alloc;
+
+pub mod dhcp;
+mod ethernet;
+mod interface;
+mod ipv4;
+pub mod tftp;
+#[cfg(target_arch = "aarch64")]
+pub mod virtio_net;
+
+use core::fmt;
+
+pub use interface::Interface;
+pub use interface::MAX_UDP_PAYLOAD;
+
+/// largest Ethernet frame without the FCS
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetErr {
+    /// the network device failed or is not a supported device
+    Device,
+    /// no answer before the retries ran out
+    Timeout,
+    /// the DHCP server refused the offered address
+    DhcpNak,
+    /// the TFTP server sent an error packet with this code
+    Tftp(u16),
+    /// malformed or unexpected packet from the peer
+    Protocol,
+    /// the packet does not fit in a frame
+    TooLarge,
+    /// the destination is outside the subnet and there is no gateway
+    Unreachable,
+}
+
+/// Ethernet frame I/O, implemented by the NIC driver
+pub trait NetDevice {
+    fn mac(&self) -> MacAddr;
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetErr>;
+    /// copy the next received frame into `buf`, `None` if there is none
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, NetErr>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: Self = Self([0xFF; 6]);
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Self = Self([0; 4]);
+    pub const BROADCAST: Self = Self([0xFF; 4]);
+
+    fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// parse the dotted form `a.b.c.d`
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut address = [0; 4];
+        let mut parts = s.split('.');
+        for byte in &mut address {
+            *byte = parts.next()?.parse().ok()?;
+        }
+        parts.next().is_none().then_some(Self(address))
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+/// millisecond clock the timeouts are measured with
+pub type Clock<'a> = &'a dyn Fn() -> u64;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+    use alloc::vec::Vec;
+    use core::cell::Cell;
+
+    /// device whose peer is a closure answering each sent frame with any number of frames
+    pub(crate) struct Loopback<F: FnMut(&[u8]) -> Vec<Vec<u8>>> {
+        pub(crate) mac: MacAddr,
+        pub(crate) peer: F,
+        pub(crate) pending: VecDeque<Vec<u8>>,
+    }
+
+    impl<F: FnMut(&[u8]) -> Vec<Vec<u8>>> NetDevice for Loopback<F> {
+        fn mac(&self) -> MacAddr {
+            self.mac
+        }
+
+        fn send(&mut self, frame: &[u8]) -> Result<(), NetErr> {
+            let replies = (self.peer)(frame);
+            self.pending.extend(replies);
+            Ok(())
+        }
+
+        fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, NetErr> {
+            Ok(self.pending.pop_front().map(|frame| {
+                buf[..frame.len()].copy_from_slice(&frame);
+                frame.len()
+            }))
+        }
+    }
+
+    /// clock advancing by one millisecond per reading
+    pub(crate) fn ticking_clock() -> impl Fn() -> u64 {
+        let now = Cell::new(0);
+        move || {
+            now.set(now.get() + 1);
+            now.get()
+        }
+    }
+
+    #[test]
+    fn parse_and_print_addresses() {
+        assert_eq!(Ipv4Addr::parse("10.0.2.2"), Some(Ipv4Addr([10, 0, 2, 2])));
+        assert_eq!(Ipv4Addr::parse("10.0.2"), None);
+        assert_eq!(Ipv4Addr::parse("10.0.2.256"), None);
+        assert_eq!(Ipv4Addr::parse("10.0.2.2.1"), None);
+        assert_eq!(alloc::format!("{}", Ipv4Addr([192, 168, 0, 1])), "192.168.0.1");
+        assert_eq!(
+            alloc::format!("{}", MacAddr([0x52, 0x54, 0, 0x12, 0x34, 0x56])),
+            "52:54:00:12:34:56"
+        );
+    }
+}
@@ -0,0 +1,367 @@
+// TFTP client (RFC 1350), read requests only, with the blksize and tsize options
+// (RFC 2348, RFC 2349)
+//
+// Servers without option support answer the request with the first DATA packet and
+// the transfer falls back to 512-byte blocks.
+
+use crate::Interface;
+use crate::Ipv4Addr;
+use crate::MAX_UDP_PAYLOAD;
+use crate::NetDevice;
+use crate::NetErr;
+use alloc::format;
+use alloc::vec::Vec;
+
+const SERVER_PORT: u16 = 69;
+const TIMEOUT_MS: u64 = 1000;
+const RETRIES: usize = 5;
+// first port of the dynamic range, the local port is picked above it
+const EPHEMERAL_PORT_BASE: u16 = 49152;
+
+const OPCODE_RRQ: u16 = 1;
+const OPCODE_DATA: u16 = 3;
+const OPCODE_ACK: u16 = 4;
+const OPCODE_ERROR: u16 = 5;
+const OPCODE_OACK: u16 = 6;
+const ERROR_UNKNOWN_TID: u16 = 5;
+
+const DEFAULT_BLOCK_SIZE: usize = 512;
+// largest block which fits in an Ethernet frame
+const BLOCK_SIZE: usize = MAX_UDP_PAYLOAD - 4;
+
+/// Read `path` from `server`. `progress` is called with the number of bytes received so far.
+pub fn read<D: NetDevice>(
+    interface: &mut Interface<D>,
+    server: Ipv4Addr,
+    path: &str,
+    progress: &mut dyn FnMut(usize),
+) -> Result<Vec<u8>, NetErr> {
+    let local_port = EPHEMERAL_PORT_BASE | (interface.now() as u16 & 0x3FFF);
+    let mut request = Vec::with_capacity(path.len() + 32);
+    request.extend_from_slice(&OPCODE_RRQ.to_be_bytes());
+    let block_size_option = format!("{}", BLOCK_SIZE);
+    for field in [path, "octet", "blksize", &block_size_option, "tsize", "0"] {
+        request.extend_from_slice(field.as_bytes());
+        request.push(0);
+    }
+    if request.len() > MAX_UDP_PAYLOAD {
+        return Err(NetErr::TooLarge);
+    }
+
+    let mut data = Vec::new();
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    // the server answers from its own port, the transfer ID
+    let mut server_port = None;
+    let mut expected: u16 = 1;
+    // the packet resent on timeout: the request, then the last ACK
+    let mut last = request;
+    let mut buf = [0u8; MAX_UDP_PAYLOAD];
+    let mut retries = 0;
+    interface.send_udp(server, local_port, SERVER_PORT, &last)?;
+    loop {
+        let Some((source, port, len)) = interface.receive_udp(local_port, &mut buf, TIMEOUT_MS)?
+        else {
+            retries += 1;
+            if retries > RETRIES {
+                return Err(NetErr::Timeout);
+            }
+            interface.send_udp(
+                server,
+                local_port,
+                server_port.unwrap_or(SERVER_PORT),
+                &last,
+            )?;
+            continue;
+        };
+        if source != server {
+            continue;
+        }
+        if server_port.is_some_and(|p| p != port) {
+            let error = error_packet(ERROR_UNKNOWN_TID, "unknown transfer ID");
+            interface.send_udp(source, local_port, port, &error)?;
+            continue;
+        }
+        let packet = &buf[..len];
+        let Some((opcode, body)) = packet
+            .split_first_chunk::<2>()
+            .map(|(opcode, body)| (u16::from_be_bytes(*opcode), body))
+        else {
+            continue;
+        };
+        match opcode {
+            // a repeated OACK means the ACK was lost and is sent again
+            OPCODE_OACK if expected == 1 => {
+                if server_port.is_none() {
+                    let (size, total) = parse_options(body).ok_or(NetErr::Protocol)?;
+                    block_size = size.unwrap_or(DEFAULT_BLOCK_SIZE);
+                    // the size is only a hint, the transfer goes on if it cannot be reserved
+                    if let Some(total) = total {
+                        let _ = data.try_reserve_exact(total);
+                    }
+                    server_port = Some(port);
+                    last = ack(0).to_vec();
+                }
+            }
+            OPCODE_DATA if body.len() >= 2 => {
+                let block = u16::from_be_bytes([body[0], body[1]]);
+                let payload = &body[2..];
+                if block == expected {
+                    if payload.len() > block_size {
+                        return Err(NetErr::Protocol);
+                    }
+                    server_port = Some(port);
+                    data.extend_from_slice(payload);
+                    progress(data.len());
+                    last = ack(block).to_vec();
+                    expected = expected.wrapping_add(1);
+                    if payload.len() < block_size {
+                        // the final ACK is not retransmitted, the server resends on loss
+                        interface.send_udp(server, local_port, port, &last)?;
+                        return Ok(data);
+                    }
+                } else if block != expected.wrapping_sub(1) || server_port.is_none() {
+                    continue;
+                }
+                // a duplicate is acknowledged again below
+            }
+            OPCODE_ERROR => {
+                let code = body
+                    .get(..2)
+                    .map_or(0, |c| u16::from_be_bytes([c[0], c[1]]));
+                return Err(NetErr::Tftp(code));
+            }
+            _ => continue,
+        }
+        retries = 0;
+        interface.send_udp(server, local_port, server_port.unwrap(), &last)?;
+    }
+}
+
+fn ack(block: u16) -> [u8; 4] {
+    let [high, low] = block.to_be_bytes();
+    let [op_high, op_low] = OPCODE_ACK.to_be_bytes();
+    [op_high, op_low, high, low]
+}
+
+fn error_packet(code: u16, message: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5 + message.len());
+    packet.extend_from_slice(&OPCODE_ERROR.to_be_bytes());
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+    packet
+}
+
+// (blksize, tsize) acknowledged in an OACK, `None` if it is malformed
+fn parse_options(body: &[u8]) -> Option<(Option<usize>, Option<usize>)> {
+    let mut fields = body
+        .split(|&b| b == 0)
+        .map(|field| core::str::from_utf8(field).ok());
+    let (mut block_size, mut total_size) = (None, None);
+    while let Some(name) = fields.next() {
+        let name = name?;
+        if name.is_empty() {
+            // the NUL ending the last value
+            break;
+        }
+        let value: usize = fields.next()??.parse().ok()?;
+        if name.eq_ignore_ascii_case("blksize") {
+            // a server may lower the block size, never raise it
+            if value == 0 || value > BLOCK_SIZE {
+                return None;
+            }
+            block_size = Some(value);
+        } else if name.eq_ignore_ascii_case("tsize") {
+            total_size = Some(value);
+        }
+    }
+    Some((block_size, total_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_FRAME_SIZE;
+    use crate::MacAddr;
+    use crate::ethernet;
+    use crate::ipv4;
+    use crate::tests::Loopback;
+    use crate::tests::ticking_clock;
+    use alloc::collections::VecDeque;
+    use alloc::vec;
+
+    const CLIENT_IP: Ipv4Addr = Ipv4Addr([10, 0, 2, 15]);
+    const SERVER_IP: Ipv4Addr = Ipv4Addr([10, 0, 2, 2]);
+    const SERVER_MAC: MacAddr = MacAddr([2; 6]);
+    const TRANSFER_PORT: u16 = 3000;
+
+    fn frame_to_client(source_port: u16, destination_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; MAX_FRAME_SIZE];
+        ethernet::write_header(
+            &mut frame,
+            MacAddr([1; 6]),
+            SERVER_MAC,
+            ethernet::ETHERTYPE_IPV4,
+        );
+        let packet = &mut frame[ethernet::HEADER_SIZE..];
+        packet[ipv4::HEADER_SIZE + ipv4::UDP_HEADER_SIZE..][..payload.len()]
+            .copy_from_slice(payload);
+        let len = ipv4::write_udp(
+            packet,
+            SERVER_IP,
+            CLIENT_IP,
+            source_port,
+            destination_port,
+            payload.len(),
+            0,
+        );
+        frame.truncate(ethernet::HEADER_SIZE + len);
+        frame
+    }
+
+    fn arp_reply(request: &[u8]) -> Vec<u8> {
+        let request = ethernet::ArpPacket::parse(request).unwrap();
+        let mut reply = vec![0; ethernet::HEADER_SIZE + ethernet::ARP_PACKET_SIZE];
+        ethernet::write_header(
+            &mut reply,
+            request.sender_mac,
+            SERVER_MAC,
+            ethernet::ETHERTYPE_ARP,
+        );
+        ethernet::ArpPacket {
+            operation: ethernet::ARP_REPLY,
+            sender_mac: SERVER_MAC,
+            sender_ip: request.target_ip,
+            target_mac: request.sender_mac,
+            target_ip: request.sender_ip,
+        }
+        .write(&mut reply[ethernet::HEADER_SIZE..]);
+        reply
+    }
+
+    // Serves `file`. With `options` the request options are acknowledged, otherwise the
+    // server behaves like one without option support. The first DATA of each block is lost
+    // when `lossy` is set.
+    fn server(file: Vec<u8>, options: bool, lossy: bool) -> impl FnMut(&[u8]) -> Vec<Vec<u8>> {
+        let mut block_size = DEFAULT_BLOCK_SIZE;
+        let mut dropped = Vec::new();
+        move |frame| {
+            let (_, ethertype, packet) = ethernet::parse(frame).unwrap();
+            if ethertype == ethernet::ETHERTYPE_ARP {
+                return vec![arp_reply(packet)];
+            }
+            let datagram = ipv4::parse_udp(packet).unwrap();
+            let client_port = datagram.source_port;
+            let opcode = u16::from_be_bytes([datagram.payload[0], datagram.payload[1]]);
+            let block = match opcode {
+                OPCODE_RRQ => {
+                    assert_eq!(datagram.destination_port, SERVER_PORT);
+                    let fields: Vec<&[u8]> = datagram.payload[2..].split(|&b| b == 0).collect();
+                    assert_eq!(fields[0], b"/boot/Image");
+                    assert_eq!(fields[1], b"octet");
+                    if options {
+                        block_size = 1024;
+                        let mut oack = OPCODE_OACK.to_be_bytes().to_vec();
+                        let tsize = alloc::format!("tsize\0{}\0", file.len());
+                        oack.extend_from_slice(b"blksize\x001024\0");
+                        oack.extend_from_slice(tsize.as_bytes());
+                        return vec![frame_to_client(TRANSFER_PORT, client_port, &oack)];
+                    }
+                    1
+                }
+                OPCODE_ACK => {
+                    assert_eq!(datagram.destination_port, TRANSFER_PORT);
+                    u16::from_be_bytes([datagram.payload[2], datagram.payload[3]]) + 1
+                }
+                _ => panic!("unexpected opcode {}", opcode),
+            };
+            let start = (usize::from(block) - 1) * block_size;
+            if start > file.len() {
+                return vec![];
+            }
+            if lossy && !dropped.contains(&block) {
+                dropped.push(block);
+                return vec![];
+            }
+            let end = (start + block_size).min(file.len());
+            let mut data = OPCODE_DATA.to_be_bytes().to_vec();
+            data.extend_from_slice(&block.to_be_bytes());
+            data.extend_from_slice(&file[start..end]);
+            vec![frame_to_client(TRANSFER_PORT, client_port, &data)]
+        }
+    }
+
+    fn fetch(peer: impl FnMut(&[u8]) -> Vec<Vec<u8>>) -> Result<Vec<u8>, NetErr> {
+        let clock = ticking_clock();
+        let device = Loopback {
+            mac: MacAddr([1; 6]),
+            peer,
+            pending: VecDeque::new(),
+        };
+        let mut interface = Interface::new(device, &clock);
+        interface.configure(CLIENT_IP, Ipv4Addr([255, 255, 255, 0]), None);
+        let mut received = 0;
+        let result = read(&mut interface, SERVER_IP, "/boot/Image", &mut |n| {
+            received = n
+        });
+        if let Ok(data) = &result {
+            assert_eq!(received, data.len());
+        }
+        result
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn read_with_options() {
+        // the last block is short
+        let file = sample(1024 * 5 + 100);
+        assert_eq!(fetch(server(file.clone(), true, false)), Ok(file));
+        // an exact multiple of the block size ends with an empty block
+        let file = sample(1024 * 3);
+        assert_eq!(fetch(server(file.clone(), true, false)), Ok(file));
+    }
+
+    #[test]
+    fn read_without_options() {
+        let file = sample(512 * 4 + 1);
+        assert_eq!(fetch(server(file.clone(), false, false)), Ok(file));
+    }
+
+    #[test]
+    fn read_with_lost_packets() {
+        let file = sample(512 * 3 + 17);
+        assert_eq!(fetch(server(file.clone(), false, true)), Ok(file));
+    }
+
+    #[test]
+    fn server_error() {
+        let error = |frame: &[u8]| {
+            let (_, ethertype, packet) = ethernet::parse(frame).unwrap();
+            if ethertype == ethernet::ETHERTYPE_ARP {
+                return vec![arp_reply(packet)];
+            }
+            let datagram = ipv4::parse_udp(packet).unwrap();
+            vec![frame_to_client(
+                TRANSFER_PORT,
+                datagram.source_port,
+                &error_packet(1, "file not found"),
+            )]
+        };
+        assert_eq!(fetch(error), Err(NetErr::Tftp(1)));
+    }
+
+    #[test]
+    fn oack_options() {
+        assert_eq!(
+            parse_options(b"blksize\x001024\0tsize\x00300\0"),
+            Some((Some(1024), Some(300)))
+        );
+        assert_eq!(parse_options(b"TSIZE\x0042\0"), Some((None, Some(42))));
+        // larger than requested
+        assert_eq!(parse_options(b"blksize\x0065464\0"), None);
+        assert_eq!(parse_options(b"blksize\0"), None);
+    }
+}
@@ -0,0 +1,214 @@
+// virtio-net driver: one receive and one transmit queue, polled
+//
+// Receive buffers are posted once and posted again after their frame is copied out.
+// Only VIRTIO_NET_F_MAC is negotiated: no checksum offload, no merged receive buffers.
+
+use crate::MAX_FRAME_SIZE;
+use crate::MacAddr;
+use crate::NetDevice;
+use crate::NetErr;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::mem::size_of;
+use typestate::Le;
+use typestate::RawReg;
+use virtio::VirtIoCore;
+use virtio::VirtIoDevice;
+use virtio::VirtioErr;
+use virtio::VirtioFeatures;
+use virtio::cache::clean_dcache_range;
+use virtio::cache::clean_invalidate_dcache_range;
+use virtio::cache::invalidate_dcache_range;
+use virtio::device_type::VirtIoDeviceTypes;
+use virtio::mmio::VirtIoMmio;
+use virtio::queue::VirtqDesc;
+use virtio::queue::VirtqDescFlags;
+
+const RECEIVE_QUEUE: u16 = 0;
+const TRANSMIT_QUEUE: u16 = 1;
+const RECEIVE_BUFFERS: usize = 16;
+// struct virtio_net_hdr with VIRTIO_F_VERSION_1, zero for plain frames
+const HEADER_SIZE: usize = 12;
+// MAC used when the device does not provide one, locally administered
+const FALLBACK_MAC: MacAddr = MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+
+// whole cache lines, so invalidating a buffer cannot drop a neighbour's writes
+#[repr(C, align(64))]
+struct Buffer([u8; 1536]);
+
+const _: () = assert!(HEADER_SIZE + MAX_FRAME_SIZE <= size_of::<Buffer>());
+
+pub struct VirtIoNet {
+    virtio: VirtIoCore<VirtIoMmio>,
+    mac: MacAddr,
+    receive_buffers: Box<[Buffer]>,
+    // descriptor posted for each receive buffer
+    receive_descriptors: Vec<u16>,
+    transmit_buffer: Box<Buffer>,
+}
+
+unsafe impl Sync for VirtIoNet {}
+unsafe impl Send for VirtIoNet {}
+
+impl VirtIoNet {
+    const VIRTIO_NET_F_MAC: VirtioFeatures = VirtioFeatures(1 << 5);
+}
+
+struct VirtIoNetAdapter {
+    has_mac: Cell<bool>,
+}
+
+impl VirtIoDevice for VirtIoNetAdapter {
+    fn driver_features(
+        &self,
+        select: u32,
+        device_feature: VirtioFeatures,
+    ) -> Result<VirtioFeatures, VirtioErr> {
+        if select == 0 {
+            self.has_mac
+                .set(device_feature & VirtIoNet::VIRTIO_NET_F_MAC != VirtioFeatures(0));
+            return Ok(VirtIoNet::VIRTIO_NET_F_MAC);
+        }
+        Ok(VirtioFeatures(0))
+    }
+
+    fn num_of_queue(&self) -> Result<u32, VirtioErr> {
+        // receiveq1 and transmitq1
+        Ok(2)
+    }
+}
+
+impl VirtIoNet {
+    /// Probe and initialize the virtio-mmio device at `addr`
+    pub fn new(addr: usize) -> Result<Self, NetErr> {
+        let mut virtio = VirtIoCore::new_mmio(addr).map_err(error_from)?;
+        if virtio.get_device() != VirtIoDeviceTypes::NetworkDevice {
+            return Err(NetErr::Device);
+        }
+        let adapter = VirtIoNetAdapter {
+            has_mac: Cell::new(false),
+        };
+        virtio.init(&adapter).map_err(error_from)?;
+        let mac = if adapter.has_mac.get() {
+            // the configuration space starts with the 6-byte MAC
+            let config = virtio.get_configuration_addr() as *const u8;
+            MacAddr(core::array::from_fn(|i| unsafe {
+                core::ptr::read_volatile(config.add(i))
+            }))
+        } else {
+            FALLBACK_MAC
+        };
+        let mut net = Self {
+            virtio,
+            mac,
+            receive_buffers: (0..RECEIVE_BUFFERS).map(|_| Buffer([0; 1536])).collect(),
+            receive_descriptors: Vec::with_capacity(RECEIVE_BUFFERS),
+            transmit_buffer: Box::new(Buffer([0; 1536])),
+        };
+        for i in 0..RECEIVE_BUFFERS {
+            let buffer = net.receive_buffers[i].0.as_ptr();
+            // the zeroing may still sit dirty in the cache and be written back over a frame
+            clean_invalidate_dcache_range(buffer, size_of::<Buffer>());
+            let (index, descriptor) = net
+                .virtio
+                .allocate_descriptor(RECEIVE_QUEUE)
+                .map_err(error_from)?;
+            fill_descriptor(
+                descriptor,
+                buffer as u64,
+                size_of::<Buffer>() as u32,
+                VirtqDescFlags::VIRTQ_DESC_F_WRITE,
+            );
+            net.virtio
+                .set_and_notify(RECEIVE_QUEUE, index)
+                .map_err(error_from)?;
+            net.receive_descriptors.push(index);
+        }
+        Ok(net)
+    }
+}
+
+fn fill_descriptor(descriptor: &mut VirtqDesc, addr: u64, len: u32, flags: VirtqDescFlags) {
+    descriptor.addr = Le::new(addr);
+    descriptor.len = Le::new(len);
+    descriptor.flags = Le::new(flags);
+    descriptor.next = Le::new(0);
+    clean_dcache_range(descriptor as *const _ as *const u8, size_of::<VirtqDesc>());
+}
+
+impl NetDevice for VirtIoNet {
+    fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), NetErr> {
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(NetErr::TooLarge);
+        }
+        let buffer = &mut self.transmit_buffer.0;
+        buffer[..HEADER_SIZE].fill(0);
+        buffer[HEADER_SIZE..HEADER_SIZE + frame.len()].copy_from_slice(frame);
+        let len = HEADER_SIZE + frame.len();
+        clean_dcache_range(buffer.as_ptr(), len);
+        let (index, descriptor) = self
+            .virtio
+            .allocate_descriptor(TRANSMIT_QUEUE)
+            .map_err(error_from)?;
+        // device read-only, no flags
+        fill_descriptor(
+            descriptor,
+            buffer.as_ptr() as u64,
+            len as u32,
+            VirtqDescFlags::from_raw(0),
+        );
+        let result = (|| {
+            self.virtio
+                .set_and_notify(TRANSMIT_QUEUE, index)
+                .map_err(error_from)?;
+            // the buffer is reused, so wait until the device has read it
+            loop {
+                match self.virtio.pop_used(TRANSMIT_QUEUE).map_err(error_from)? {
+                    Some((used, _)) if used == index => return Ok(()),
+                    Some(_) => return Err(NetErr::Device),
+                    None => core::hint::spin_loop(),
+                }
+            }
+        })();
+        let _ = self.virtio.dequeue_used(TRANSMIT_QUEUE, index);
+        result
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, NetErr> {
+        let Some((index, len)) = self.virtio.pop_used(RECEIVE_QUEUE).map_err(error_from)? else {
+            return Ok(None);
+        };
+        let slot = self
+            .receive_descriptors
+            .iter()
+            .position(|&d| d == index)
+            .ok_or(NetErr::Device)?;
+        let buffer = &self.receive_buffers[slot].0;
+        let len = (len as usize).clamp(HEADER_SIZE, buffer.len());
+        invalidate_dcache_range(buffer.as_ptr(), len);
+        let frame = &buffer[HEADER_SIZE..len];
+        let copied = frame.len().min(buf.len());
+        buf[..copied].copy_from_slice(&frame[..copied]);
+        // the descriptor still describes the buffer, hand it back as it is
+        self.virtio
+            .set_and_notify(RECEIVE_QUEUE, index)
+            .map_err(error_from)?;
+        Ok(Some(copied))
+    }
+}
+
+impl Drop for VirtIoNet {
+    fn drop(&mut self) {
+        // stop DMA into the buffers before they are freed
+        self.virtio.reset();
+    }
+}
+
+fn error_from(_: VirtioErr) -> NetErr {
+    NetErr::Device
+}
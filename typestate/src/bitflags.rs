@@ -1,5 +1,8 @@
 use core::marker::PhantomData;
 
+#[doc(hidden)]
+pub use typestate_macro::bitregs_field_consts;
+
 pub trait FieldSpec<Reg> {
     const OFF: u32;
     const SZ: u32;
@@ -35,9 +38,12 @@ impl<Reg, const OFF: u32, const SZ: u32> Field<Reg, OFF, SZ> {
 ///   * Enum values fit the declared width
 /// - `bits()` **applies res0/res1 policy** (encode integrated)
 /// - `new()`/`Default` start with res1 bits set, res0 cleared
+/// - Every field also gets `<FIELD>_OFFSET: u32` and `<FIELD>_MASK: $ty`, the field bits in
+///   place, for `asm!` `const` operands and generated linker scripts
 ///
 /// Usage:
 /// 'Foo::new().set(Foo::bar1, 0b1).set_enum(Foo::bar2, Bar2::baz1).bits();'
+/// 'asm!("orr {0}, {0}, {mask}", inout(reg) x, mask = const Foo::BAR1_MASK);'
 ///
 /// Example:
 /// ```rust
@@ -276,6 +282,7 @@ macro_rules! bitregs {
                 $crate::bitflags::Field<$Name, { ($off as u32) }, { ($sz as u32) }> =
                 $crate::bitflags::Field::<$Name, { ($off as u32) }, { ($sz as u32) }>::new();
         }
+        $crate::bitflags::bitregs_field_consts!{ $Name, $ty, $fvis $Field, $off, $sz }
         const _: () = {
             let bits = (core::mem::size_of::<$ty>() as u32) * 8;
             let off = $off as u32; let sz = $sz as u32;
@@ -314,6 +321,7 @@ macro_rules! bitregs {
                 $crate::bitflags::Field<$Name, { ($off as u32) }, { ($sz as u32) }> =
                 $crate::bitflags::Field::<$Name, { ($off as u32) }, { ($sz as u32) }>::new();
         }
+        $crate::bitflags::bitregs_field_consts!{ $Name, $ty, $fvis $Field, $off, $sz }
         const _: () = {
             let bits = (core::mem::size_of::<$ty>() as u32) * 8;
             let off = $off as u32; let sz = $sz as u32;
@@ -349,6 +357,7 @@ macro_rules! bitregs {
                 $crate::bitflags::Field<$Name, { ($off as u32) }, { ($sz as u32) }> =
                 $crate::bitflags::Field::<$Name, { ($off as u32) }, { ($sz as u32) }>::new();
         }
+        $crate::bitflags::bitregs_field_consts!{ $Name, $ty, $fvis $Field, $off, $sz }
         const _: () = {
             let bits = (core::mem::size_of::<$ty>() as u32) * 8;
             let off = $off as u32; let sz = $sz as u32;
@@ -556,6 +565,23 @@ mod test {
         assert_eq!(reg.bits() & 0x00F8, 0);
     }
 
+    #[test]
+    fn bitregs_exports_offsets_and_masks() {
+        assert_eq!(Timer::PERIOD_OFFSET, 0);
+        assert_eq!(Timer::PERIOD_MASK, 0xFF);
+        assert_eq!(Timer::ENABLE_OFFSET, 16);
+        assert_eq!(Timer::ENABLE_MASK, 1 << 16);
+        assert_eq!(Status::STATE_OFFSET, 0);
+        assert_eq!(Status::STATE_MASK, 0b111);
+        assert_eq!(Status::ERROR_MASK, 1 << 8);
+
+        let reg = Timer::new().set(Timer::period, 0x5A);
+        assert_eq!(
+            (reg.bits() & Timer::PERIOD_MASK) >> Timer::PERIOD_OFFSET,
+            reg.get(Timer::period)
+        );
+    }
+
     #[test]
     fn bitregs_enum_invalid_pattern_returns_none() {
         let reg = Status::new().set(Status::state, 0b111);
@@ -75,4 +75,5 @@ impl_raw!(
 );
 
 unsafe impl<T: BytePod> BytePod for Le<T> {}
+unsafe impl<T: BytePod, const N: usize> BytePod for [T; N] {}
 unsafe impl<T: BytePod> BytePod for Be<T> {}
@@ -196,3 +196,74 @@ pub fn derive_rawreg(input: TokenStream) -> TokenStream {
     };
     expanded.into()
 }
+
+// `Name, ty, vis field, offset, size` of a `bitregs!` field
+struct BitregsField {
+    name: syn::Ident,
+    ty: syn::Type,
+    vis: syn::Visibility,
+    field: syn::Ident,
+    offset: syn::Expr,
+    size: syn::Expr,
+}
+
+impl syn::parse::Parse for BitregsField {
+    fn parse(input: syn::parse::ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let ty = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let vis = input.parse()?;
+        let field = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let offset = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let size = input.parse()?;
+        Ok(Self {
+            name,
+            ty,
+            vis,
+            field,
+            offset,
+            size,
+        })
+    }
+}
+
+/// `<FIELD>_OFFSET` and the in-place `<FIELD>_MASK` of a field, emitted by `bitregs!`
+#[doc(hidden)]
+#[proc_macro]
+pub fn bitregs_field_consts(input: TokenStream) -> TokenStream {
+    let BitregsField {
+        name,
+        ty,
+        vis,
+        field,
+        offset,
+        size,
+    } = parse_macro_input!(input as BitregsField);
+    let upper = field.to_string().to_uppercase();
+    let offset_ident = syn::Ident::new(&format!("{}_OFFSET", upper), field.span());
+    let mask_ident = syn::Ident::new(&format!("{}_MASK", upper), field.span());
+    let offset_doc = format!("Bit offset of `{}`", field);
+    let mask_doc = format!("Bits of `{}` in the register", field);
+
+    let expanded = quote! {
+        impl #name {
+            #[doc = #offset_doc]
+            #vis const #offset_ident: u32 = (#offset) as u32;
+            #[doc = #mask_doc]
+            #vis const #mask_ident: #ty = {
+                let bits = (::core::mem::size_of::<#ty>() as u32) * 8;
+                let size = (#size) as u32;
+                let value: #ty = if size >= bits {
+                    !(0 as #ty)
+                } else {
+                    ((1 as #ty) << size) - (1 as #ty)
+                };
+                value << ((#offset) as u32)
+            };
+        }
+    };
+    expanded.into()
+}
@@ -14,6 +14,8 @@ use typestate_macro::RawReg;
 
 use crate::device_type::VirtIoDeviceTypes;
 use crate::mmio::VirtIoMmio;
+use crate::queue::QueueAudit;
+use crate::queue::QueueCorruption;
 use crate::queue::VirtQueue;
 use crate::queue::VirtqDesc;
 
@@ -42,6 +44,33 @@ pub trait VirtioTransport {
     fn queue_set_used(&self, paddr: usize);
 
     fn queue_notify(&self, index: u16);
+
+    // interrupt
+    /// pending interrupt causes: InterruptStatus for MMIO, the ISR status (or the MSI-X
+    /// vector which fired) for PCI
+    fn read_interrupt_status(&self) -> InterruptStatus;
+    /// acknowledge the causes in `mask`: InterruptACK for MMIO, a no-op for PCI where
+    /// reading the ISR status acknowledges it
+    fn ack_interrupt(&self, mask: InterruptStatus);
+}
+
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, RawReg)]
+pub struct InterruptStatus(pub u32);
+
+impl InterruptStatus {
+    /// the device used a buffer of one of the queues
+    pub const USED_BUFFER: Self = Self(1 << 0);
+    /// the device configuration changed
+    pub const CONFIGURATION_CHANGE: Self = Self(1 << 1);
+
+    pub fn contains(self, other: Self) -> bool {
+        self & other == other
+    }
+
+    pub fn is_empty(self) -> bool {
+        self == Self(0)
+    }
 }
 
 #[repr(transparent)]
@@ -55,6 +84,7 @@ impl VirtioFeatures {
     pub const F_EVENT_IDX: Self = Self(1u32 << 29);
 
     // --- upper 32 bits (select = 1) ---
+    #[allow(clippy::eq_op)] // written as `bit - 32` like the others
     pub const F_VERSION_1: Self = Self(1u32 << (32 - 32)); // bit 0 of high
     pub const F_ACCESS_PLATFORM: Self = Self(1u32 << (33 - 32));
     pub const F_RING_PACKED: Self = Self(1u32 << (34 - 32));
@@ -298,6 +328,31 @@ impl<T: VirtioTransport> VirtIoCore<T> {
         queue[queue_idx as usize].dequeue_used(desc_idx)
     }
 
+    /// descriptors of the queue which are not free
+    pub fn outstanding(&self, queue_idx: u16) -> Result<u32, VirtioErr> {
+        let Some(queue) = &self.queues else {
+            return Err(VirtioErr::DeviceUninitialized);
+        };
+        Ok(queue[queue_idx as usize].outstanding())
+    }
+
+    /// check the descriptor accounting of the queue while no request is in progress
+    pub fn audit(&self, queue_idx: u16) -> Result<QueueAudit, VirtioErr> {
+        let Some(queue) = &self.queues else {
+            return Err(VirtioErr::DeviceUninitialized);
+        };
+        queue[queue_idx as usize].audit()
+    }
+
+    /// read the pending interrupt causes and acknowledge them
+    pub fn ack_interrupt(&self) -> InterruptStatus {
+        let status = self.transport.read_interrupt_status();
+        if !status.is_empty() {
+            self.transport.ack_interrupt(status);
+        }
+        status
+    }
+
     pub fn reset(&self) {
         // reset virtio
         self.transport.set_status(DeviceStatus::RESET);
@@ -315,5 +370,5 @@ pub enum VirtioErr {
     DeviceNeedsReset,
     DeviceUninitialized,
     OutOfAvailableDesc,
-    QueueCorrupted,
+    QueueCorrupted(QueueCorruption),
 }
@@ -7,6 +7,7 @@ use typestate::Writable;
 use typestate::WriteOnly;
 
 use crate::DeviceStatus;
+use crate::InterruptStatus;
 use crate::VirtioErr;
 use crate::VirtioFeatures;
 use crate::VirtioTransport;
@@ -35,8 +36,8 @@ struct MmioDeviceRegister {
     _reserved4: [u32; 2],
     queue_notify: WriteOnly<u32>,
     _reserved5: [u32; 3],
-    interrupt_status: ReadPure<u32>,
-    interrupt_ack: WriteOnly<u32>,
+    interrupt_status: ReadPure<InterruptStatus>,
+    interrupt_ack: WriteOnly<InterruptStatus>,
     _reserved6: [u32; 2],
     status: ReadWrite<DeviceStatus>,
     _reserved7: [u32; 3],
@@ -174,4 +175,12 @@ impl VirtioTransport for VirtIoMmio {
     fn queue_notify(&self, index: u16) {
         self.registers.queue_notify.write(index as u32);
     }
+
+    fn read_interrupt_status(&self) -> InterruptStatus {
+        self.registers.interrupt_status.read()
+    }
+
+    fn ack_interrupt(&self, mask: InterruptStatus) {
+        self.registers.interrupt_ack.write(mask);
+    }
 }
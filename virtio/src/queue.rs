@@ -1,3 +1,5 @@
+use alloc::boxed::Box;
+use alloc::vec;
 use core::mem::size_of;
 use core::sync::atomic::Ordering;
 
@@ -29,6 +31,85 @@ struct VirtQueueIdx {
     free_list: IntrusiveLinkedList,
     avail_idx: u16,
     used_idx: u16,
+    // one bit per descriptor
+    free: Bitmap,
+    // heads put on the available ring and not returned on the used ring yet
+    in_device: Bitmap,
+    // heads returned by `pop_used` and neither dequeued nor put on the available ring again
+    popped: Bitmap,
+}
+
+#[derive(Debug)]
+struct Bitmap(Box<[u64]>);
+
+impl Bitmap {
+    fn new(bits: u32, set: bool) -> Self {
+        let mut words = vec![0u64; (bits as usize).div_ceil(64)].into_boxed_slice();
+        if set {
+            for bit in 0..bits as usize {
+                words[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        Self(words)
+    }
+
+    fn get(&self, bit: u16) -> bool {
+        self.0[bit as usize / 64] & (1 << (bit % 64)) != 0
+    }
+
+    fn set(&mut self, bit: u16, value: bool) {
+        let word = &mut self.0[bit as usize / 64];
+        if value {
+            *word |= 1 << (bit % 64);
+        } else {
+            *word &= !(1 << (bit % 64));
+        }
+    }
+
+    fn count(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    fn first(&self) -> Option<u16> {
+        self.0
+            .iter()
+            .enumerate()
+            .find(|(_, word)| **word != 0)
+            .map(|(i, word)| (i * 64 + word.trailing_zeros() as usize) as u16)
+    }
+}
+
+/// What is inconsistent in a queue, returned with `VirtioErr::QueueCorrupted`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueCorruption {
+    /// the used index of the device moved by more than the queue size
+    UsedIndexJump { used_idx: u16, device_used_idx: u16 },
+    /// the device returned a descriptor which is not on the available ring
+    UnexpectedUsed { desc_idx: u32 },
+    /// a descriptor on the free list is not free
+    AllocatedTwice { desc_idx: u16 },
+    /// a free descriptor, or one the device owns, was put on the available ring
+    NotOwned { desc_idx: u16 },
+    /// a free descriptor was dequeued
+    DoubleFree { desc_idx: u16 },
+    /// a descriptor the device owns was dequeued before `pop_used` returned it
+    FreedInDevice { desc_idx: u16 },
+    /// `popped` descriptors, the first is `desc_idx`, were returned by `pop_used` and
+    /// never dequeued
+    Leaked { popped: u32, desc_idx: u16 },
+    /// the free list and the free descriptors differ
+    FreeListMismatch { listed: u32, free: u32 },
+}
+
+/// Descriptor accounting of a queue, see `VirtQueue::audit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueAudit {
+    pub size: u32,
+    pub free: u32,
+    /// heads on the available ring, the device owns their chains
+    pub in_device: u32,
+    /// allocated and not given to the device, including the rest of the chains
+    pub driver: u32,
 }
 
 #[repr(C)]
@@ -103,6 +184,9 @@ impl VirtQueue {
                 avail_idx: 0,
                 used_idx: 0,
                 free_list,
+                free: Bitmap::new(size, true),
+                in_device: Bitmap::new(size, false),
+                popped: Bitmap::new(size, false),
             }),
         }
     }
@@ -139,7 +223,14 @@ impl VirtQueue {
         let Some(ptr) = lock.free_list.pop() else {
             return Err(VirtioErr::OutOfAvailableDesc);
         };
-        Ok(self.get_desc_queue(ptr))
+        let (desc_idx, desc) = self.get_desc_queue(ptr);
+        if !lock.free.get(desc_idx) {
+            return Err(VirtioErr::QueueCorrupted(QueueCorruption::AllocatedTwice {
+                desc_idx,
+            }));
+        }
+        lock.free.set(desc_idx, false);
+        Ok((desc_idx, desc))
     }
 
     pub(crate) fn set_available_ring(&self, desc_idx: u16) -> Result<(), VirtioErr> {
@@ -149,6 +240,13 @@ impl VirtQueue {
         if in_flight >= self.size {
             return Err(VirtioErr::OutOfAvailableDesc);
         }
+        if desc_idx as u32 >= self.size || idx.free.get(desc_idx) || idx.in_device.get(desc_idx) {
+            return Err(VirtioErr::QueueCorrupted(QueueCorruption::NotOwned {
+                desc_idx,
+            }));
+        }
+        idx.in_device.set(desc_idx, true);
+        idx.popped.set(desc_idx, false);
 
         let ring_slot = idx.avail_idx & (self.size as u16 - 1);
         self.set_avail_queue_idx(ring_slot, desc_idx);
@@ -175,7 +273,10 @@ impl VirtQueue {
             return Ok(None);
         }
         if delta as u32 > self.size {
-            return Err(VirtioErr::QueueCorrupted);
+            return Err(VirtioErr::QueueCorrupted(QueueCorruption::UsedIndexJump {
+                used_idx,
+                device_used_idx: used.idx.read(),
+            }));
         }
         // used_idx % qsize = used_idx & (qsize - 1)
         let ring_idx = used_idx & (self.size as u16 - 1);
@@ -184,19 +285,68 @@ impl VirtQueue {
         let elem_ptr = (ring_start + ring_idx as usize * size_of::<VirtqUsedElem>()) as *const u8;
         invalidate_dcache_range(elem_ptr, size_of::<VirtqUsedElem>());
         let virt_queue_elem = self.get_used_queue_idx(ring_idx);
+        let id = virt_queue_elem.id.read();
+        if id >= self.size || !idx.in_device.get(id as u16) {
+            return Err(VirtioErr::QueueCorrupted(QueueCorruption::UnexpectedUsed {
+                desc_idx: id,
+            }));
+        }
+        idx.in_device.set(id as u16, false);
+        idx.popped.set(id as u16, true);
         idx.used_idx = used_idx.wrapping_add(1);
-        Ok(Some((
-            virt_queue_elem.id.read() as u16,
-            virt_queue_elem.len.read(),
-        )))
+        Ok(Some((id as u16, virt_queue_elem.len.read())))
     }
 
+    /// free `desc_idx`, which must not be free or owned by the device
     pub(crate) fn dequeue_used(&self, desc_idx: u16) -> Result<(), VirtioErr> {
         let mut lock = self.idx.lock();
+        if desc_idx as u32 >= self.size || lock.free.get(desc_idx) {
+            return Err(VirtioErr::QueueCorrupted(QueueCorruption::DoubleFree {
+                desc_idx,
+            }));
+        }
+        if lock.in_device.get(desc_idx) {
+            return Err(VirtioErr::QueueCorrupted(QueueCorruption::FreedInDevice {
+                desc_idx,
+            }));
+        }
+        lock.free.set(desc_idx, true);
+        lock.popped.set(desc_idx, false);
         unsafe {
             lock.free_list
                 .push(self.descriptor_paddr as usize + desc_idx as usize * size_of::<VirtqDesc>())
         };
         Ok(())
     }
+
+    /// descriptors which are not free: owned by the device or allocated by the driver
+    pub(crate) fn outstanding(&self) -> u32 {
+        self.size - self.idx.lock().free.count()
+    }
+
+    /// Check the descriptor accounting. Call it while no request is in progress: a
+    /// descriptor returned by `pop_used` and not dequeued yet is reported as leaked
+    pub(crate) fn audit(&self) -> Result<QueueAudit, VirtioErr> {
+        let lock = self.idx.lock();
+        let free = lock.free.count();
+        let listed = lock.free_list.size() as u32;
+        if listed != free {
+            return Err(VirtioErr::QueueCorrupted(
+                QueueCorruption::FreeListMismatch { listed, free },
+            ));
+        }
+        if let Some(desc_idx) = lock.popped.first() {
+            return Err(VirtioErr::QueueCorrupted(QueueCorruption::Leaked {
+                popped: lock.popped.count(),
+                desc_idx,
+            }));
+        }
+        let in_device = lock.in_device.count();
+        Ok(QueueAudit {
+            size: self.size,
+            free,
+            in_device,
+            driver: self.size - free - in_device,
+        })
+    }
 }
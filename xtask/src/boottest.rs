@@ -0,0 +1,201 @@
+// xtask/src/boottest.rs
+//
+// `cargo xtask boottest`: boot the disk of `cargo xtask image` in QEMU and watch the UART
+// for the markers of a good boot, in order. The test passes once the last one is printed and
+// fails on a failure marker (a panic), when QEMU exits first or when the timeout expires.
+// The console is echoed and saved to bin/boottest-serial.log.
+//
+// The markers and the timeout come from the `[boottest]` table of xtask.toml, or the flags:
+//
+//   --expect TEXT      expect = ["allocator setup success", "jumping linux"], repeatable
+//   --fail TEXT        fail = ["PANIC on cpu", "@@event error"], repeatable
+//   --timeout SECS     timeout = 120
+//
+// The `--expect`/`--fail` flags replace the markers of the file. The QEMU flags of `run`
+// and the image arguments are accepted too.
+
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::config;
+use crate::image;
+use crate::qemu;
+
+const SERIAL_LOG: &str = "bin/boottest-serial.log";
+const DEFAULT_EXPECT: &[&str] = &["allocator setup success", "jumping linux"];
+// the panic report of the loader, and its structured form
+const DEFAULT_FAIL: &[&str] = &["PANIC on cpu", "@@event error"];
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+struct Markers {
+    expect: Vec<String>,
+    fail: Vec<String>,
+    timeout: Duration,
+}
+
+impl Markers {
+    // the defaults with the `[boottest]` table of xtask.toml applied
+    fn load() -> Result<Self, String> {
+        let mut markers = Self {
+            expect: DEFAULT_EXPECT.iter().map(|s| s.to_string()).collect(),
+            fail: DEFAULT_FAIL.iter().map(|s| s.to_string()).collect(),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+        };
+        for (key, value) in config::table("boottest")? {
+            match key.as_str() {
+                "expect" => markers.expect = value.into_array(&key)?,
+                "fail" => markers.fail = value.into_array(&key)?,
+                "timeout" => {
+                    let secs = u64::try_from(value.into_integer(&key)?)
+                        .map_err(|_| format!("{}: timeout out of range", config::FILE_NAME))?;
+                    markers.timeout = Duration::from_secs(secs);
+                }
+                _ => {
+                    return Err(format!(
+                        "{}: unknown key '{}' in [boottest]",
+                        config::FILE_NAME,
+                        key
+                    ));
+                }
+            }
+        }
+        Ok(markers)
+    }
+
+    // apply the marker flags of `args`, returns the other arguments
+    fn parse_args(&mut self, args: &[String]) -> Result<Vec<String>, String> {
+        let mut expect = Vec::new();
+        let mut fail = Vec::new();
+        let mut rest = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", name))
+            };
+            match arg.as_str() {
+                "--expect" => expect.push(value(arg)?),
+                "--fail" => fail.push(value(arg)?),
+                "--timeout" => {
+                    let secs = value(arg)?
+                        .parse()
+                        .map_err(|_| "--timeout expects seconds".to_string())?;
+                    self.timeout = Duration::from_secs(secs);
+                }
+                _ => rest.push(arg.clone()),
+            }
+        }
+        if !expect.is_empty() {
+            self.expect = expect;
+        }
+        if !fail.is_empty() {
+            self.fail = fail;
+        }
+        Ok(rest)
+    }
+}
+
+/// `cargo xtask boottest [--expect TEXT]... [--fail TEXT]... [--timeout SECS] [run args...]`
+pub fn boottest(args: &[String]) -> Result<(), String> {
+    let mut markers = Markers::load()?;
+    let args = markers.parse_args(args)?;
+    if markers.expect.is_empty() {
+        return Err("no markers to expect".to_string());
+    }
+    let mut qemu = qemu::Qemu::load()?;
+    let build_args = qemu.parse_args(&args)?;
+    let disk = match &qemu.disk {
+        Some(disk) if build_args.is_empty() => disk.clone(),
+        Some(_) => {
+            return Err(format!(
+                "--disk boots a built disk, {:?} unused",
+                build_args
+            ));
+        }
+        None => image::image(&build_args)?.1,
+    };
+
+    let mut cmd = qemu.command(&disk);
+    cmd.args(["-display", "none", "-monitor", "none", "-serial", "stdio"])
+        .arg("-no-reboot")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+    eprintln!("\n--- Boot test ---");
+    eprintln!("Running: {:?}", cmd);
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {}", qemu::QEMU, e))?;
+    let stdout = child.stdout.take().ok_or("no QEMU stdout")?;
+    let _ = std::fs::create_dir_all("bin");
+    let mut log =
+        File::create(SERIAL_LOG).map_err(|e| format!("failed to create {}: {}", SERIAL_LOG, e))?;
+
+    // the console lines, the channel closes when QEMU exits
+    let (sender, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).split(b'\n') {
+            let Ok(line) = line else {
+                break;
+            };
+            let line = String::from_utf8_lossy(&line)
+                .trim_end_matches('\r')
+                .to_string();
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = watch(&lines, &markers, &mut log);
+    let _ = child.kill();
+    let _ = child.wait();
+    match &result {
+        Ok(()) => eprintln!("\n--- Boot test passed ---"),
+        Err(e) => eprintln!("\n--- Boot test failed: {} ---", e),
+    }
+    eprintln!("console output: {}", SERIAL_LOG);
+    result
+}
+
+// match the lines against the markers until the last expected one, a failure or the timeout
+fn watch(lines: &mpsc::Receiver<String>, markers: &Markers, log: &mut File) -> Result<(), String> {
+    let deadline = Instant::now() + markers.timeout;
+    let mut pending = markers.expect.iter().peekable();
+    while let Some(expected) = pending.peek() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let line = match lines.recv_timeout(remaining) {
+            Ok(line) => line,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Err(format!(
+                    "timed out after {}s waiting for '{}'",
+                    markers.timeout.as_secs(),
+                    expected
+                ));
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(format!("QEMU exited before '{}'", expected));
+            }
+        };
+        println!("{}", line);
+        let _ = writeln!(log, "{}", line);
+        if let Some(fail) = markers
+            .fail
+            .iter()
+            .find(|fail| line.contains(fail.as_str()))
+        {
+            return Err(format!("failure marker '{}'", fail));
+        }
+        if line.contains(expected.as_str()) {
+            eprintln!("[boottest] matched '{}'", expected);
+            pending.next();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+// xtask/src/check.rs
+//
+// `cargo xtask check` runs clippy on every crate for the targets it is built for. A check of
+// the whole workspace does not work: the crates are built for different targets, and cargo
+// unifies the features of all members, so e.g. the `uefi-test` allocator of `aarch64_test`
+// ends up next to the one of `allocator`. Each crate is checked on its own instead:
+//
+// - the bare-metal crates for aarch64-unknown-none, the target of the loader
+// - the loader for aarch64-unknown-uefi too, the target of `cargo xtask build --uefi`
+// - the `std` crates of xtest.txt, xtask and the proc macros on the host, with their tests
+// - the `uefi` tests of xtest.txt for aarch64-unknown-uefi
+
+use std::process::Command;
+use std::process::Stdio;
+
+const LOADER_TARGET: &str = "aarch64-unknown-none";
+const UEFI_TARGET: &str = "aarch64-unknown-uefi";
+
+// every crate linked into the loader or one of the UEFI tests
+const BARE_METAL: &[&str] = &[
+    "elf-hypervisor",
+    "allocator",
+    "intrusive_linked_list",
+    "mutex",
+    "typestate",
+    "arch_hal",
+    "aarch64_hal",
+    "aarch64_test",
+    "cpu",
+    "dma",
+    "gic",
+    "gpio",
+    "ns16550",
+    "paging",
+    "pl011",
+    "sbsa_gwdt",
+    "crypto",
+    "decompress",
+    "dtb",
+    "elf",
+    "file",
+    "block-device",
+    "block-device-api",
+    "filesystem",
+    "virtio",
+    "net",
+];
+
+// crates only built for the host, besides the `std` entries of xtest.txt
+const HOST_ONLY: &[&str] = &["xtask", "typestate_macro", "dtc"];
+
+enum Target {
+    Host,
+    Loader,
+    // the loader built as a UEFI application
+    UefiLoader,
+    // the integration test of the package
+    Uefi(String),
+}
+
+/// Run `cargo clippy`, or `cargo check` with `--no-clippy`, on every crate. Arguments after
+/// `--` are passed to clippy or rustc, e.g. `-- -D warnings`, the others to cargo.
+pub fn check(args: &[String]) -> Result<(), String> {
+    let (cargo_args, tool_args) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[][..]),
+    };
+    let clippy = !cargo_args.iter().any(|a| a == "--no-clippy");
+    let cargo_args: Vec<&String> = cargo_args.iter().filter(|a| *a != "--no-clippy").collect();
+
+    let mut failed = Vec::new();
+    let mut count = 0;
+    for (package, target) in plan()? {
+        let mut cmd = Command::new("cargo");
+        cmd.arg(if clippy { "clippy" } else { "check" })
+            .arg("-p")
+            .arg(&package);
+        let label = match &target {
+            Target::Host => {
+                cmd.arg("--all-targets");
+                format!("host:{}", package)
+            }
+            Target::Loader => {
+                cmd.arg("--target").arg(LOADER_TARGET);
+                format!("{}:{}", LOADER_TARGET, package)
+            }
+            Target::UefiLoader => {
+                cmd.arg("--target").arg(UEFI_TARGET);
+                format!("{}:{}", UEFI_TARGET, package)
+            }
+            Target::Uefi(test) => {
+                cmd.arg("--target").arg(UEFI_TARGET).arg("--test").arg(test);
+                format!("{}:{}::{}", UEFI_TARGET, package, test)
+            }
+        };
+        cmd.args(&cargo_args);
+        if !tool_args.is_empty() {
+            cmd.arg("--").args(tool_args);
+        }
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit());
+
+        eprintln!("\n--- Checking {} ---", label);
+        eprintln!("Running: {:?}", cmd);
+        let status = cmd
+            .status()
+            .map_err(|e| format!("failed to run cargo for {}: {}", package, e))?;
+        count += 1;
+        if !status.success() {
+            failed.push(label);
+        }
+    }
+
+    eprintln!("\n===== Check Summary =====");
+    eprintln!("Passed: {}/{}", count - failed.len(), count);
+    if failed.is_empty() {
+        return Ok(());
+    }
+    eprintln!("Failed ({}):", failed.len());
+    for label in &failed {
+        eprintln!("  - {}", label);
+    }
+    Err(format!("{} check(s) failed", failed.len()))
+}
+
+// the crates and targets to check, the host and UEFI ones come from xtest.txt
+fn plan() -> Result<Vec<(String, Target)>, String> {
+    let repo_root = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../");
+    let plan_path = repo_root.join("xtest.txt");
+    let text = std::fs::read_to_string(&plan_path)
+        .map_err(|e| format!("failed to read {}: {}", plan_path.display(), e))?;
+
+    let mut plan: Vec<(String, Target)> = BARE_METAL
+        .iter()
+        .map(|package| (package.to_string(), Target::Loader))
+        .collect();
+    plan.push(("elf-hypervisor".to_string(), Target::UefiLoader));
+    plan.extend(
+        HOST_ONLY
+            .iter()
+            .map(|package| (package.to_string(), Target::Host)),
+    );
+    for line in text.lines() {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("std"), Some(package), _) => plan.push((package.to_string(), Target::Host)),
+            (Some("uefi"), Some(package), Some(test)) => {
+                plan.push((package.to_string(), Target::Uefi(test.to_string())))
+            }
+            // comments, `xtask test` reports malformed lines
+            _ => {}
+        }
+    }
+    Ok(plan)
+}
@@ -0,0 +1,148 @@
+// xtask/src/config.rs
+//
+// xtask.toml at the workspace root holds the per-developer defaults of the subcommands, one
+// table each, e.g. `[qemu]` for `cargo xtask run`. xtask has no dependencies, so only the
+// part of TOML the file needs is read: tables, and keys with a string, integer, boolean or
+// one-line array of strings as the value.
+
+use std::path::PathBuf;
+
+pub const FILE_NAME: &str = "xtask.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+impl Value {
+    pub fn into_string(self, key: &str) -> Result<String, String> {
+        match self {
+            Self::String(s) => Ok(s),
+            _ => Err(format!("{}: {} expects a string", FILE_NAME, key)),
+        }
+    }
+
+    pub fn into_integer(self, key: &str) -> Result<i64, String> {
+        match self {
+            Self::Integer(n) => Ok(n),
+            _ => Err(format!("{}: {} expects an integer", FILE_NAME, key)),
+        }
+    }
+
+    pub fn into_boolean(self, key: &str) -> Result<bool, String> {
+        match self {
+            Self::Boolean(b) => Ok(b),
+            _ => Err(format!("{}: {} expects true or false", FILE_NAME, key)),
+        }
+    }
+
+    pub fn into_array(self, key: &str) -> Result<Vec<String>, String> {
+        match self {
+            Self::Array(items) => Ok(items),
+            _ => Err(format!(
+                "{}: {} expects an array of strings",
+                FILE_NAME, key
+            )),
+        }
+    }
+}
+
+pub fn path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join(FILE_NAME)
+}
+
+/// The keys of `[table]` in xtask.toml, in file order. Empty when there is no xtask.toml
+pub fn table(table: &str) -> Result<Vec<(String, Value)>, String> {
+    match std::fs::read_to_string(path()) {
+        Ok(text) => parse_table(&text, table),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("failed to read {}: {}", FILE_NAME, e)),
+    }
+}
+
+fn parse_table(text: &str, table: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut current = String::new();
+    let mut entries = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("{}:{}: {}", FILE_NAME, lineno + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| error("expected ']'"))?;
+            current = name.trim().to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected key = value"))?;
+        if current != table {
+            continue;
+        }
+        let value = parse_value(value.trim()).ok_or_else(|| error("unsupported value"))?;
+        entries.push((key.trim().to_string(), value));
+    }
+    Ok(entries)
+}
+
+// the line up to a `#` which is not in a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    match value {
+        "true" => return Some(Value::Boolean(true)),
+        "false" => return Some(Value::Boolean(false)),
+        _ => {}
+    }
+    if let Some(items) = value.strip_prefix('[') {
+        return parse_array(items.strip_suffix(']')?).map(Value::Array);
+    }
+    if let Some(s) = parse_string(value) {
+        return Some(Value::String(s));
+    }
+    value.replace('_', "").parse().ok().map(Value::Integer)
+}
+
+// `"a", "b,c",` without the brackets, the strings may contain commas
+fn parse_array(mut rest: &str) -> Option<Vec<String>> {
+    let mut items = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Some(items);
+        }
+        let (item, tail) = rest.strip_prefix('"')?.split_once('"')?;
+        if item.contains('\\') {
+            return None;
+        }
+        items.push(item.to_string());
+        rest = tail.trim_start();
+        if !rest.is_empty() {
+            rest = rest.strip_prefix(',')?;
+        }
+    }
+}
+
+// a basic string without escapes
+fn parse_string(value: &str) -> Option<String> {
+    let s = value.strip_prefix('"')?.strip_suffix('"')?;
+    (!s.contains(['"', '\\'])).then(|| s.to_string())
+}
@@ -0,0 +1,120 @@
+// xtask/src/dtb.rs
+//
+// `cargo xtask dtb`: compile the DTS fixtures of the dtb tests the way its build script does,
+// to bin/dtb, and keep the pre-compiled copies in dtb/test/dtb in sync with them. See `dtc`
+// for how dtc is found.
+//
+//   (no flag)     compile to bin/dtb with dtc, or copy the vendored blobs without it
+//   --dtc         require dtc
+//   --vendored    use the vendored blobs
+//   --update      recompile the vendored blobs with dtc, after a fixture changed
+//   --check       fail when a vendored blob differs from what dtc compiles
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use dtc::Source;
+
+const OUT_DIR: &str = "bin/dtb";
+
+enum Mode {
+    Build(Source),
+    Update,
+    Check,
+}
+
+/// `cargo xtask dtb [--dtc|--vendored|--update|--check]`
+pub fn dtb(args: &[String]) -> Result<(), String> {
+    let mut mode = Mode::Build(dtc::Source::from_env()?);
+    for arg in args {
+        mode = match arg.as_str() {
+            "--dtc" => Mode::Build(Source::Dtc),
+            "--vendored" => Mode::Build(Source::Vendored),
+            "--update" => Mode::Update,
+            "--check" => Mode::Check,
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        };
+    }
+    let dtb_crate = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../dtb");
+    match mode {
+        Mode::Build(source) => {
+            let source = dtc::build_fixtures(&dtb_crate, Path::new(OUT_DIR), source)?;
+            let from = match source {
+                Source::Dtc => "compiled with dtc",
+                _ => "copied from dtb/test/dtb",
+            };
+            for dts in dtc::fixtures(&dtb_crate.join(dtc::DTS_DIR))? {
+                eprintln!("{}/{} ({})", OUT_DIR, dtc::blob_name(&dts), from);
+            }
+            Ok(())
+        }
+        Mode::Update => update(&dtb_crate),
+        Mode::Check => check(&dtb_crate),
+    }
+}
+
+// recompile every vendored blob and drop the ones without a fixture
+fn update(dtb_crate: &Path) -> Result<(), String> {
+    let dtc = dtc::find_dtc().ok_or_else(dtc::missing_dtc)?;
+    let vendored = dtb_crate.join(dtc::VENDORED_DIR);
+    fs::create_dir_all(&vendored)
+        .map_err(|e| format!("failed to create {}: {}", vendored.display(), e))?;
+    let fixtures = dtc::fixtures(&dtb_crate.join(dtc::DTS_DIR))?;
+    for dts in &fixtures {
+        let name = dtc::blob_name(dts);
+        dtc::compile(&dtc, dts, &vendored.join(&name))?;
+        eprintln!("updated dtb/{}/{}", dtc::VENDORED_DIR, name);
+    }
+    for name in orphans(&vendored, &fixtures) {
+        fs::remove_file(vendored.join(&name))
+            .map_err(|e| format!("failed to remove {}: {}", name, e))?;
+        eprintln!("removed dtb/{}/{}", dtc::VENDORED_DIR, name);
+    }
+    Ok(())
+}
+
+// compare the vendored blobs with a fresh dtc build
+fn check(dtb_crate: &Path) -> Result<(), String> {
+    dtc::build_fixtures(dtb_crate, Path::new(OUT_DIR), Source::Dtc)?;
+    let vendored = dtb_crate.join(dtc::VENDORED_DIR);
+    let fixtures = dtc::fixtures(&dtb_crate.join(dtc::DTS_DIR))?;
+    let mut stale = Vec::new();
+    for dts in &fixtures {
+        let name = dtc::blob_name(dts);
+        let compiled = fs::read(Path::new(OUT_DIR).join(&name))
+            .map_err(|e| format!("failed to read {}: {}", name, e))?;
+        match fs::read(vendored.join(&name)) {
+            Ok(blob) if blob == compiled => {}
+            Ok(_) => stale.push(format!("{} is out of date", name)),
+            Err(_) => stale.push(format!("{} is missing", name)),
+        }
+    }
+    for name in orphans(&vendored, &fixtures) {
+        stale.push(format!("{} has no fixture", name));
+    }
+    if !stale.is_empty() {
+        return Err(format!(
+            "dtb/{}: {}, run `cargo xtask dtb --update`",
+            dtc::VENDORED_DIR,
+            stale.join(", ")
+        ));
+    }
+    eprintln!("dtb/{} is up to date", dtc::VENDORED_DIR);
+    Ok(())
+}
+
+// the vendored blobs no fixture compiles to
+fn orphans(vendored: &Path, fixtures: &[PathBuf]) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(vendored) else {
+        return Vec::new();
+    };
+    let mut orphans: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".dtb"))
+        .filter(|name| !fixtures.iter().any(|dts| dtc::blob_name(dts) == *name))
+        .collect();
+    orphans.sort();
+    orphans
+}
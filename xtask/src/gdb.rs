@@ -0,0 +1,75 @@
+// xtask/src/gdb.rs
+//
+// `cargo xtask gdb`: boot the disk of `cargo xtask image` under QEMU halted at the first
+// instruction and attach gdb-multiarch to it, with the loader symbols loaded and breakpoints
+// on `main` and the panic handler. The console goes to bin/gdb-serial.log, since gdb owns
+// the terminal.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+use crate::image;
+use crate::qemu;
+
+const GDB: &str = "gdb-multiarch";
+const SCRIPT_PATH: &str = "bin/gdb-script";
+const SERIAL_LOG: &str = "bin/gdb-serial.log";
+// the symbol of the `#[panic_handler]`
+const PANIC_SYMBOL: &str = "rust_begin_unwind";
+
+/// `cargo xtask gdb [QEMU flags of run...] [image args...]`
+pub fn gdb(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|a| a == "--uefi") {
+        return Err("gdb needs the ELF build, drop --uefi".to_string());
+    }
+    let mut qemu = qemu::Qemu::load()?;
+    let mut args = qemu.parse_args(args)?;
+    // keep the debug info in release builds too
+    args.extend(["--config", "profile.release.debug=true"].map(String::from));
+    // a given disk is booted as is, the build only provides the symbols
+    let (loader, disk) = match &qemu.disk {
+        Some(disk) => (PathBuf::from(crate::build(&args)?), disk.clone()),
+        None => image::image(&args)?,
+    };
+    write_script(&loader)?;
+
+    let mut qemu = qemu.command(&disk);
+    qemu.args(["-display", "none", "-monitor", "none"])
+        .arg("-serial")
+        .arg(format!("file:{}", SERIAL_LOG))
+        .arg("-gdb")
+        .arg(format!("tcp::{}", qemu::GDB_PORT))
+        .arg("-S")
+        .stdin(Stdio::null());
+    eprintln!("Running: {:?}", qemu);
+    let mut qemu = qemu
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {}", qemu::QEMU, e))?;
+    eprintln!("console output: {}", SERIAL_LOG);
+
+    let status = Command::new(GDB).arg("-x").arg(SCRIPT_PATH).status();
+    let _ = qemu.kill();
+    let _ = qemu.wait();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} exited with {}", GDB, status)),
+        Err(e) => Err(format!("failed to start {}: {}", GDB, e)),
+    }
+}
+
+fn write_script(loader: &Path) -> Result<(), String> {
+    let script = format!(
+        "set architecture aarch64\n\
+         file {}\n\
+         target remote :{}\n\
+         break main\n\
+         break {}\n",
+        loader.display(),
+        qemu::GDB_PORT,
+        PANIC_SYMBOL
+    );
+    fs::write(SCRIPT_PATH, script).map_err(|e| format!("{}: {}", SCRIPT_PATH, e))
+}
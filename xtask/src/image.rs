@@ -0,0 +1,523 @@
+// xtask/src/image.rs
+//
+// `cargo xtask image`: a bootable disk image built without sfdisk, mkfs.vfat or mtools.
+//
+// The layout is the one the old run.sh made with sfdisk: an MBR with a bootable FAT32 partition at
+// 1 MiB holding the loader and the files it boots, then a Linux partition with the root file
+// system image when there is one. Every field which would differ between runs (volume ID,
+// disk signature, timestamps) is fixed, so the same inputs give the same image.
+
+use std::fs;
+use std::fs::File;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+const SECTOR_SIZE: u64 = 512;
+// first partition start, and the alignment of the second one
+const PARTITION_ALIGN: u64 = 1 << 20;
+const DEFAULT_FAT_SIZE_MIB: u64 = 512;
+// FAT32 needs at least 65525 clusters
+const MIN_CLUSTERS: u64 = 65525;
+
+const RESERVED_SECTORS: u32 = 32;
+const NUM_FATS: u32 = 2;
+const ROOT_CLUSTER: u32 = 2;
+const FSINFO_SECTOR: u16 = 1;
+const BACKUP_BOOT_SECTOR: u16 = 6;
+const VOLUME_ID: u32 = 0x454C_4631;
+const VOLUME_LABEL: &[u8; 11] = b"ELFBOOT    ";
+const DISK_SIGNATURE: u32 = 0x454C_4644;
+// 1980-01-01, the FAT epoch
+const FAT_DATE: u16 = (1 << 5) | 1;
+
+const END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+const DIR_ENTRY_SIZE: usize = 32;
+// UTF-16 units in a long name entry
+const LFN_CHARS: usize = 13;
+
+const PARTITION_TYPE_FAT32_LBA: u8 = 0x0C;
+const PARTITION_TYPE_LINUX: u8 = 0x83;
+
+/// a file copied to the root directory of the FAT partition
+pub struct ImageFile {
+    pub source: PathBuf,
+    pub name: String,
+}
+
+pub struct ImageSpec {
+    pub output: PathBuf,
+    /// size of the FAT partition in bytes
+    pub fat_size: u64,
+    pub files: Vec<ImageFile>,
+    /// written to the second partition as is
+    pub rootfs: Option<PathBuf>,
+}
+
+/// `cargo xtask image [--out PATH] [--size MIB] [--rootfs PATH] [--add SRC[:NAME]]...`,
+/// the other arguments are passed to `build`. Returns the paths of the loader and the image.
+pub fn image(args: &[String]) -> Result<(PathBuf, PathBuf), String> {
+    let mut output = PathBuf::from("bin/disk.img");
+    let mut fat_size = DEFAULT_FAT_SIZE_MIB << 20;
+    let mut rootfs = None;
+    let mut extra = Vec::new();
+    let mut build_args = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "--out" => output = PathBuf::from(value("--out")?),
+            "--size" => {
+                let mib: u64 = value("--size")?
+                    .parse()
+                    .map_err(|_| "--size expects MiB".to_string())?;
+                fat_size = mib << 20;
+            }
+            "--rootfs" => rootfs = Some(PathBuf::from(value("--rootfs")?)),
+            "--add" => extra.push(parse_file(&value("--add")?)),
+            _ => build_args.push(arg.clone()),
+        }
+    }
+
+    let loader = PathBuf::from(crate::build(&build_args)?);
+    let loader_name = loader
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or("loader path has no file name")?
+        .to_string();
+    let mut files = vec![ImageFile {
+        source: loader.clone(),
+        name: loader_name,
+    }];
+    // boot.scr boots the `Image` with `booti`, which passes the dtb in x0
+    if loader
+        .extension()
+        .is_some_and(|extension| extension == "elf")
+    {
+        let image = crate::package::booti_image(&loader)?;
+        files.push(ImageFile {
+            name: image
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or("loader path has no file name")?
+                .to_string(),
+            source: image,
+        });
+    }
+    // the files U-Boot and the loader boot, the optional ones only when present
+    for (source, name, required) in [
+        ("bin/Image", "image", true),
+        ("bin/qemu_mod.dtb", "qemu.dtb", true),
+        ("bin/boot.cfg", "boot.cfg", false),
+        ("bin/boot.scr", "boot.scr", false),
+        ("bin/u-boot.bin", "u-boot.bin", false),
+    ] {
+        let source = PathBuf::from(source);
+        if source.exists() {
+            files.push(ImageFile {
+                source,
+                name: name.to_string(),
+            });
+        } else if required {
+            return Err(format!("{} not found", source.display()));
+        }
+    }
+    files.extend(extra);
+    if rootfs.is_none() && Path::new("bin/DISK0").exists() {
+        rootfs = Some(PathBuf::from("bin/DISK0"));
+    }
+
+    let spec = ImageSpec {
+        output,
+        fat_size,
+        files,
+        rootfs,
+    };
+    write_image(&spec)?;
+    eprintln!("\n--- Disk image written to {} ---", spec.output.display());
+    Ok((loader, spec.output))
+}
+
+// `SRC[:NAME]`, the name defaults to the file name of SRC
+fn parse_file(arg: &str) -> ImageFile {
+    let (source, name) = match arg.split_once(':') {
+        Some((source, name)) => (source, name.trim_start_matches('/').to_string()),
+        None => (
+            arg,
+            Path::new(arg)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        ),
+    };
+    ImageFile {
+        source: PathBuf::from(source),
+        name,
+    }
+}
+
+/// Write the disk image of `spec`
+pub fn write_image(spec: &ImageSpec) -> Result<(), String> {
+    let fat_start = PARTITION_ALIGN;
+    let fat_sectors = spec.fat_size / SECTOR_SIZE;
+    let rootfs_size = match &spec.rootfs {
+        Some(path) => fs::metadata(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .len(),
+        None => 0,
+    };
+    let rootfs_start = (fat_start + spec.fat_size).next_multiple_of(PARTITION_ALIGN);
+    let total_size = if spec.rootfs.is_some() {
+        rootfs_start + rootfs_size.next_multiple_of(SECTOR_SIZE)
+    } else {
+        fat_start + spec.fat_size
+    };
+
+    if let Some(parent) = spec.output.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    let mut disk =
+        File::create(&spec.output).map_err(|e| format!("{}: {}", spec.output.display(), e))?;
+    let io = |e: std::io::Error| format!("{}: {}", spec.output.display(), e);
+    disk.set_len(total_size).map_err(io)?;
+
+    let mut partitions = vec![(
+        true,
+        PARTITION_TYPE_FAT32_LBA,
+        fat_start / SECTOR_SIZE,
+        fat_sectors,
+    )];
+    if spec.rootfs.is_some() {
+        partitions.push((
+            false,
+            PARTITION_TYPE_LINUX,
+            rootfs_start / SECTOR_SIZE,
+            rootfs_size.div_ceil(SECTOR_SIZE),
+        ));
+    }
+    disk.write_all(&mbr(&partitions)?).map_err(io)?;
+
+    format_fat32(&mut disk, fat_start, fat_sectors, &spec.files, &spec.output)?;
+
+    if let Some(path) = &spec.rootfs {
+        let mut source = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        disk.seek(SeekFrom::Start(rootfs_start)).map_err(io)?;
+        std::io::copy(&mut source, &mut disk).map_err(io)?;
+    }
+    Ok(())
+}
+
+// (bootable, type, first LBA, sectors)
+fn mbr(partitions: &[(bool, u8, u64, u64)]) -> Result<[u8; SECTOR_SIZE as usize], String> {
+    let mut sector = [0u8; SECTOR_SIZE as usize];
+    sector[440..444].copy_from_slice(&DISK_SIGNATURE.to_le_bytes());
+    for (i, &(bootable, kind, start, sectors)) in partitions.iter().enumerate() {
+        let start = u32::try_from(start).map_err(|_| "partition past 2 TiB")?;
+        let sectors = u32::try_from(sectors).map_err(|_| "partition larger than 2 TiB")?;
+        let entry = &mut sector[446 + i * 16..446 + (i + 1) * 16];
+        entry[0] = if bootable { 0x80 } else { 0 };
+        // CHS of an LBA-only partition
+        entry[1..4].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        entry[4] = kind;
+        entry[5..8].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+    }
+    sector[510] = 0x55;
+    sector[511] = 0xAA;
+    Ok(sector)
+}
+
+// sectors per cluster of a FAT32 volume of `sectors`, as mkfs.vfat picks it
+fn sectors_per_cluster(sectors: u64) -> u32 {
+    match sectors * SECTOR_SIZE {
+        size if size < 260 << 20 => 1,
+        size if size <= 8 << 30 => 8,
+        size if size <= 16 << 30 => 16,
+        _ => 32,
+    }
+}
+
+// format the partition at byte `start` of `sectors` and copy `files` to its root directory
+fn format_fat32(
+    disk: &mut File,
+    start: u64,
+    sectors: u64,
+    files: &[ImageFile],
+    output: &Path,
+) -> Result<(), String> {
+    let io = |e: std::io::Error| format!("{}: {}", output.display(), e);
+    let total_sectors = u32::try_from(sectors).map_err(|_| "FAT partition too large")?;
+    let spc = sectors_per_cluster(sectors);
+    // the FAT size formula of the FAT specification
+    let fat_sectors = {
+        let tmp1 = total_sectors - RESERVED_SECTORS;
+        let tmp2 = (256 * spc + NUM_FATS) / 2;
+        tmp1.div_ceil(tmp2)
+    };
+    let data_start = RESERVED_SECTORS + NUM_FATS * fat_sectors;
+    let clusters = u64::from((total_sectors - data_start) / spc);
+    if clusters < MIN_CLUSTERS {
+        return Err(format!(
+            "FAT partition of {} MiB is too small for FAT32",
+            (sectors * SECTOR_SIZE) >> 20
+        ));
+    }
+    let cluster_size = u64::from(spc) * SECTOR_SIZE;
+    let cluster_offset = |cluster: u32| {
+        start + (u64::from(data_start) + u64::from(cluster - 2) * u64::from(spc)) * SECTOR_SIZE
+    };
+
+    // root directory: the volume label, then each file
+    let mut root = Vec::new();
+    root.extend_from_slice(&dir_entry(VOLUME_LABEL, ATTR_VOLUME_ID, 0, 0));
+    let mut fat = vec![0x0FFF_FFF8, END_OF_CHAIN];
+    let mut short_names = Vec::new();
+    let mut layout = Vec::new();
+    // the root directory cluster chain is linked once its size is known
+    let mut next_cluster = ROOT_CLUSTER;
+    let mut contents = Vec::new();
+    for file in files {
+        let data =
+            fs::read(&file.source).map_err(|e| format!("{}: {}", file.source.display(), e))?;
+        if file.name.is_empty() || file.name.contains('/') {
+            return Err(format!(
+                "{}: only root directory names are supported",
+                file.name
+            ));
+        }
+        let size = u32::try_from(data.len())
+            .map_err(|_| format!("{}: larger than 4 GiB", file.source.display()))?;
+        contents.push((file, data, size));
+    }
+    for (file, data, size) in &contents {
+        let short = short_name(&file.name, &short_names);
+        short_names.push(short);
+        if !is_exact_short_name(&file.name) {
+            for entry in long_name_entries(&file.name, checksum(&short)) {
+                root.extend_from_slice(&entry);
+            }
+        }
+        layout.push((root.len(), data, *size));
+        root.extend_from_slice(&dir_entry(&short, ATTR_ARCHIVE, 0, *size));
+    }
+    let root_clusters = (root.len() as u64).div_ceil(cluster_size).max(1) as u32;
+    next_cluster += root_clusters;
+    link_chain(&mut fat, ROOT_CLUSTER, root_clusters);
+
+    for (entry_offset, data, size) in &layout {
+        let count = u64::from(*size).div_ceil(cluster_size) as u32;
+        if count == 0 {
+            continue;
+        }
+        let first = next_cluster;
+        link_chain(&mut fat, first, count);
+        next_cluster += count;
+        let entry = &mut root[*entry_offset..*entry_offset + DIR_ENTRY_SIZE];
+        entry[20..22].copy_from_slice(&((first >> 16) as u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(first as u16).to_le_bytes());
+        disk.seek(SeekFrom::Start(cluster_offset(first)))
+            .map_err(io)?;
+        disk.write_all(data).map_err(io)?;
+    }
+    if u64::from(next_cluster - 2) > clusters {
+        return Err("the files do not fit in the FAT partition, raise --size".to_string());
+    }
+    disk.seek(SeekFrom::Start(cluster_offset(ROOT_CLUSTER)))
+        .map_err(io)?;
+    disk.write_all(&root).map_err(io)?;
+
+    let boot = boot_sector(start / SECTOR_SIZE, total_sectors, spc, fat_sectors)?;
+    let used = u64::from(next_cluster - 2);
+    let fsinfo = fsinfo_sector((clusters - used) as u32, next_cluster);
+    for copy in [0, u64::from(BACKUP_BOOT_SECTOR)] {
+        disk.seek(SeekFrom::Start(start + copy * SECTOR_SIZE))
+            .map_err(io)?;
+        disk.write_all(&boot).map_err(io)?;
+        disk.seek(SeekFrom::Start(
+            start + (copy + u64::from(FSINFO_SECTOR)) * SECTOR_SIZE,
+        ))
+        .map_err(io)?;
+        disk.write_all(&fsinfo).map_err(io)?;
+    }
+
+    let fat_bytes: Vec<u8> = fat.iter().flat_map(|entry| entry.to_le_bytes()).collect();
+    for i in 0..NUM_FATS {
+        let offset = u64::from(RESERVED_SECTORS + i * fat_sectors) * SECTOR_SIZE;
+        disk.seek(SeekFrom::Start(start + offset)).map_err(io)?;
+        disk.write_all(&fat_bytes).map_err(io)?;
+    }
+    Ok(())
+}
+
+// chain `count` clusters from `first` in `fat`, growing it as needed
+fn link_chain(fat: &mut Vec<u32>, first: u32, count: u32) {
+    let last = (first + count) as usize;
+    if fat.len() < last {
+        fat.resize(last, 0);
+    }
+    for cluster in first..first + count {
+        fat[cluster as usize] = if cluster + 1 == first + count {
+            END_OF_CHAIN
+        } else {
+            cluster + 1
+        };
+    }
+}
+
+fn boot_sector(
+    hidden_sectors: u64,
+    total_sectors: u32,
+    spc: u32,
+    fat_sectors: u32,
+) -> Result<[u8; SECTOR_SIZE as usize], String> {
+    let hidden_sectors = u32::try_from(hidden_sectors).map_err(|_| "partition past 2 TiB")?;
+    let mut sector = [0u8; SECTOR_SIZE as usize];
+    sector[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+    sector[3..11].copy_from_slice(b"ELFBOOT ");
+    sector[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    sector[13] = spc as u8;
+    sector[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+    sector[16] = NUM_FATS as u8;
+    // media: fixed disk
+    sector[21] = 0xF8;
+    sector[24..26].copy_from_slice(&63u16.to_le_bytes());
+    sector[26..28].copy_from_slice(&255u16.to_le_bytes());
+    sector[28..32].copy_from_slice(&hidden_sectors.to_le_bytes());
+    sector[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+    sector[36..40].copy_from_slice(&fat_sectors.to_le_bytes());
+    sector[44..48].copy_from_slice(&ROOT_CLUSTER.to_le_bytes());
+    sector[48..50].copy_from_slice(&FSINFO_SECTOR.to_le_bytes());
+    sector[50..52].copy_from_slice(&BACKUP_BOOT_SECTOR.to_le_bytes());
+    sector[64] = 0x80;
+    sector[66] = 0x29;
+    sector[67..71].copy_from_slice(&VOLUME_ID.to_le_bytes());
+    sector[71..82].copy_from_slice(VOLUME_LABEL);
+    sector[82..90].copy_from_slice(b"FAT32   ");
+    sector[510] = 0x55;
+    sector[511] = 0xAA;
+    Ok(sector)
+}
+
+fn fsinfo_sector(free_clusters: u32, next_free: u32) -> [u8; SECTOR_SIZE as usize] {
+    let mut sector = [0u8; SECTOR_SIZE as usize];
+    sector[0..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+    sector[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+    sector[488..492].copy_from_slice(&free_clusters.to_le_bytes());
+    sector[492..496].copy_from_slice(&next_free.to_le_bytes());
+    sector[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+    sector
+}
+
+fn dir_entry(name: &[u8; 11], attr: u8, cluster: u32, size: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0u8; DIR_ENTRY_SIZE];
+    entry[0..11].copy_from_slice(name);
+    entry[11] = attr;
+    // creation, access and write dates
+    for offset in [16, 18, 24] {
+        entry[offset..offset + 2].copy_from_slice(&FAT_DATE.to_le_bytes());
+    }
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+    entry
+}
+
+fn is_short_name_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || b"$%'-_@~`!(){}^#&".contains(&c)
+}
+
+// whether `name` is stored as is in an 8.3 entry, without a long name
+fn is_exact_short_name(name: &str) -> bool {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    !base.is_empty()
+        && base.len() <= 8
+        && ext.len() <= 3
+        && base.bytes().chain(ext.bytes()).all(is_short_name_char)
+}
+
+// the 8.3 name of `name`, with a `~N` tail when it is shortened or clashes with `taken`
+fn short_name(name: &str, taken: &[[u8; 11]]) -> [u8; 11] {
+    let pack = |base: &[u8], ext: &[u8]| {
+        let mut short = [b' '; 11];
+        short[..base.len()].copy_from_slice(base);
+        short[8..8 + ext.len()].copy_from_slice(ext);
+        short
+    };
+    let clean = |s: &str| -> Vec<u8> {
+        s.bytes()
+            .map(|c| c.to_ascii_uppercase())
+            .filter(|&c| c != b' ' && c != b'.')
+            .map(|c| if is_short_name_char(c) { c } else { b'_' })
+            .collect()
+    };
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (clean(base), clean(ext)),
+        _ => (clean(name), Vec::new()),
+    };
+    let ext = &ext[..ext.len().min(3)];
+    let lossy = base.len() > 8
+        || name.matches('.').count() > 1
+        || !name
+            .to_ascii_uppercase()
+            .bytes()
+            .all(|c| c == b'.' || is_short_name_char(c));
+    if !lossy && !base.is_empty() {
+        let short = pack(&base, ext);
+        if !taken.contains(&short) {
+            return short;
+        }
+    }
+    for n in 1.. {
+        let tail = format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+        let mut numbered = base[..keep].to_vec();
+        numbered.extend_from_slice(tail.as_bytes());
+        let short = pack(&numbered, ext);
+        if !taken.contains(&short) {
+            return short;
+        }
+    }
+    unreachable!()
+}
+
+fn checksum(short: &[u8; 11]) -> u8 {
+    short
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+// the long name entries of `name`, in the order they are stored: the last part first
+fn long_name_entries(name: &str, checksum: u8) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    if !units.len().is_multiple_of(LFN_CHARS) {
+        units.push(0);
+        units.resize(units.len().next_multiple_of(LFN_CHARS), 0xFFFF);
+    }
+    let count = units.len() / LFN_CHARS;
+    let mut entries = Vec::new();
+    for (i, chunk) in units.chunks(LFN_CHARS).enumerate().rev() {
+        let mut entry = [0u8; DIR_ENTRY_SIZE];
+        entry[0] = (i + 1) as u8 | if i + 1 == count { 0x40 } else { 0 };
+        entry[11] = ATTR_LONG_NAME;
+        entry[13] = checksum;
+        let offsets = (1..11)
+            .step_by(2)
+            .chain((14..26).step_by(2))
+            .chain((28..32).step_by(2));
+        for (offset, unit) in offsets.zip(chunk) {
+            entry[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        entries.push(entry);
+    }
+    entries
+}
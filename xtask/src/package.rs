@@ -0,0 +1,388 @@
+// xtask/src/package.rs
+//
+// `cargo xtask package`: the loader as a raw binary for firmware which cannot load an ELF,
+// optionally wrapped in a U-Boot legacy image (`bootm`) or a FIT image.
+//
+// The raw binary is what `objcopy -O binary` makes: the PT_LOAD segments copied to their
+// physical addresses, from the lowest one to the end of the last file-backed byte. The first
+// segment starts with the ELF header, which nothing reads once the loader runs, so its first
+// 64 bytes are replaced by an arm64 Linux `Image` header branching to the entry. U-Boot then
+// boots the binary with `booti` like a kernel, and `bootm` of the wrapped images does the
+// same checks on it. `text_offset` is the offset of the link address from the RAM base, as
+// `booti` places the image there.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use elf::Elf64;
+use elf::Machine;
+
+// RAM base of the QEMU virt board
+const DEFAULT_RAM_BASE: u64 = 0x4000_0000;
+// `booti` places images at a 2 MiB aligned base plus `text_offset`
+const IMAGE_ALIGN: u64 = 2 << 20;
+const IMAGE_HEADER_SIZE: usize = 64;
+const IMAGE_MAGIC: u32 = 0x644d_5241; // "ARM\x64"
+// `b` with a zero offset
+const BRANCH: u32 = 0x1400_0000;
+
+const UIMAGE_MAGIC: u32 = 0x2705_1956;
+const UIMAGE_HEADER_SIZE: usize = 64;
+const UIMAGE_NAME_LEN: usize = 32;
+const IH_OS_LINUX: u8 = 5;
+const IH_ARCH_ARM64: u8 = 22;
+const IH_TYPE_KERNEL: u8 = 2;
+const IH_COMP_NONE: u8 = 0;
+
+const IMAGE_NAME: &str = "elf-hypervisor";
+
+/// the loader as one block of memory
+pub struct RawImage {
+    /// physical address of the first byte
+    pub load: u64,
+    pub entry: u64,
+    pub data: Vec<u8>,
+    /// bytes taken in memory, with the .bss after `data`
+    pub mem_size: u64,
+}
+
+/// `cargo xtask package [--uimage] [--fit] [--ram-base ADDR] [build args...]`
+pub fn package(args: &[String]) -> Result<(), String> {
+    let mut uimage = false;
+    let mut fit = false;
+    let mut ram_base = DEFAULT_RAM_BASE;
+    let mut build_args = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--uimage" => uimage = true,
+            "--fit" => fit = true,
+            "--ram-base" => {
+                let value = iter.next().ok_or("--ram-base needs a value")?;
+                ram_base = parse_address(value)
+                    .ok_or_else(|| format!("--ram-base: invalid address '{}'", value))?;
+            }
+            "--uefi" => return Err("a UEFI application is already a PE image".to_string()),
+            _ => build_args.push(arg.clone()),
+        }
+    }
+
+    let loader = PathBuf::from(crate::build(&build_args)?);
+    let mut image = raw_image(&loader)?;
+    if let Err(e) = add_image_header(&mut image, ram_base) {
+        eprintln!(
+            "Warning: no Image header, booti cannot boot the binary: {}",
+            e
+        );
+    }
+    eprintln!(
+        "\n--- Loader at {:#x}, entry {:#x}, {} bytes ({} in memory) ---",
+        image.load,
+        image.entry,
+        image.data.len(),
+        image.mem_size
+    );
+    write(&loader.with_extension("bin"), &image.data)?;
+    if uimage {
+        write(&loader.with_extension("uimg"), &legacy_image(&image)?)?;
+    }
+    if fit {
+        write(&loader.with_extension("itb"), &fit_image(&image))?;
+    }
+    Ok(())
+}
+
+/// The loader at `loader` as an `Image` for the `booti` of the U-Boot boot script, written
+/// next to it like the `.bin` of `package`
+pub fn booti_image(loader: &Path) -> Result<PathBuf, String> {
+    let mut image = raw_image(loader)?;
+    add_image_header(&mut image, DEFAULT_RAM_BASE)
+        .map_err(|e| format!("no Image header, booti cannot boot the loader: {}", e))?;
+    let path = loader.with_extension("bin");
+    write(&path, &image.data)?;
+    Ok(path)
+}
+
+// `0x` prefixed hex or decimal
+fn parse_address(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), String> {
+    fs::write(path, data).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    eprintln!("written {}", path.display());
+    Ok(())
+}
+
+/// `objcopy -O binary` of `path`
+pub fn raw_image(path: &Path) -> Result<RawImage, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    // `Elf64` wants the header aligned
+    let mut words = vec![0u64; bytes.len().div_ceil(size_of::<u64>())];
+    let aligned = &mut as_bytes_mut(&mut words)[..bytes.len()];
+    aligned.copy_from_slice(&bytes);
+    let aligned = &*aligned;
+
+    let elf = unsafe { Elf64::new_for_machine(aligned, Machine::Aarch64) }
+        .map_err(|e| format!("{}: not an aarch64 executable: {:?}", path.display(), e))?;
+    let mut segments = Vec::new();
+    elf.iterate_program_header(|segment| {
+        segments.push((
+            segment.address(),
+            segment.offset() as usize,
+            segment.file_len() as usize,
+            segment.mem_len(),
+        ))
+    })
+    .map_err(|e| format!("{}: {:?}", path.display(), e))?;
+
+    let load = segments
+        .iter()
+        .map(|&(address, ..)| address)
+        .min()
+        .ok_or("no loadable segment")?;
+    let file_end = segments
+        .iter()
+        .map(|&(address, _, file_len, _)| address + file_len as u64)
+        .max()
+        .unwrap_or(load);
+    let mem_end = segments
+        .iter()
+        .map(|&(address, _, _, mem_len)| address + mem_len)
+        .max()
+        .unwrap_or(load);
+    let mut data = vec![0u8; (file_end - load) as usize];
+    for (address, offset, file_len, _) in segments {
+        let at = (address - load) as usize;
+        data[at..at + file_len].copy_from_slice(&aligned[offset..offset + file_len]);
+    }
+    Ok(RawImage {
+        load,
+        entry: elf.entry(),
+        data,
+        mem_size: mem_end - load,
+    })
+}
+
+fn as_bytes_mut(words: &mut [u64]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, size_of_val(words)) }
+}
+
+// the arm64 `Image` header over the ELF header at the start of the binary
+fn add_image_header(image: &mut RawImage, ram_base: u64) -> Result<(), String> {
+    if image.data.get(..4) != Some(b"\x7fELF") {
+        return Err("the binary does not start with the ELF header".to_string());
+    }
+    let branch = image
+        .entry
+        .checked_sub(image.load)
+        .filter(|&offset| offset >= IMAGE_HEADER_SIZE as u64 && offset < 1 << 27)
+        .ok_or("the entry is not reachable from the header")?;
+    if !ram_base.is_multiple_of(IMAGE_ALIGN) {
+        return Err(format!("RAM base {:#x} is not 2 MiB aligned", ram_base));
+    }
+    let text_offset = image
+        .load
+        .checked_sub(ram_base)
+        .ok_or_else(|| format!("the loader is linked below the RAM base {:#x}", ram_base))?;
+
+    let mut header = [0u8; IMAGE_HEADER_SIZE];
+    // code0 branches to the entry, code1 is never reached
+    header[0..4].copy_from_slice(&(BRANCH | (branch / 4) as u32).to_le_bytes());
+    header[8..16].copy_from_slice(&text_offset.to_le_bytes());
+    header[16..24].copy_from_slice(&image.mem_size.to_le_bytes());
+    // flags: little endian, page size unspecified, placed relative to the RAM base
+    header[24..32].copy_from_slice(&0u64.to_le_bytes());
+    header[56..60].copy_from_slice(&IMAGE_MAGIC.to_le_bytes());
+    image.data[..IMAGE_HEADER_SIZE].copy_from_slice(&header);
+    Ok(())
+}
+
+// U-Boot legacy image, the header is big endian
+fn legacy_image(image: &RawImage) -> Result<Vec<u8>, String> {
+    let address = |value: u64| {
+        u32::try_from(value).map_err(|_| format!("{:#x} does not fit a legacy image", value))
+    };
+    let mut header = [0u8; UIMAGE_HEADER_SIZE];
+    header[0..4].copy_from_slice(&UIMAGE_MAGIC.to_be_bytes());
+    // 4..8 header CRC, 8..12 time, 0 so the same inputs give the same image
+    header[12..16].copy_from_slice(&(image.data.len() as u32).to_be_bytes());
+    header[16..20].copy_from_slice(&address(image.load)?.to_be_bytes());
+    header[20..24].copy_from_slice(&address(image.entry)?.to_be_bytes());
+    header[24..28].copy_from_slice(&crc32(&image.data).to_be_bytes());
+    header[28] = IH_OS_LINUX;
+    header[29] = IH_ARCH_ARM64;
+    header[30] = IH_TYPE_KERNEL;
+    header[31] = IH_COMP_NONE;
+    let name = &IMAGE_NAME.as_bytes()[..IMAGE_NAME.len().min(UIMAGE_NAME_LEN - 1)];
+    header[32..32 + name.len()].copy_from_slice(name);
+    let header_crc = crc32(&header);
+    header[4..8].copy_from_slice(&header_crc.to_be_bytes());
+
+    let mut out = header.to_vec();
+    out.extend_from_slice(&image.data);
+    Ok(out)
+}
+
+// FIT image with the binary as its only kernel and configuration
+fn fit_image(image: &RawImage) -> Vec<u8> {
+    let mut fdt = FdtWriter::new();
+    fdt.begin_node("");
+    fdt.property_str("description", IMAGE_NAME);
+    fdt.property_u32("#address-cells", 2);
+    fdt.begin_node("images");
+    fdt.begin_node("kernel");
+    fdt.property_str("description", IMAGE_NAME);
+    fdt.property("data", &image.data);
+    fdt.property_str("type", "kernel");
+    fdt.property_str("arch", "arm64");
+    fdt.property_str("os", "linux");
+    fdt.property_str("compression", "none");
+    fdt.property_u64("load", image.load);
+    fdt.property_u64("entry", image.entry);
+    fdt.begin_node("hash-1");
+    fdt.property_str("algo", "crc32");
+    fdt.property_u32("value", crc32(&image.data));
+    fdt.end_node();
+    fdt.end_node();
+    fdt.end_node();
+    fdt.begin_node("configurations");
+    fdt.property_str("default", "conf-1");
+    fdt.begin_node("conf-1");
+    fdt.property_str("description", IMAGE_NAME);
+    fdt.property_str("kernel", "kernel");
+    fdt.end_node();
+    fdt.end_node();
+    fdt.end_node();
+    fdt.finish()
+}
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_VERSION: u32 = 17;
+const FDT_LAST_COMPATIBLE_VERSION: u32 = 16;
+const FDT_HEADER_SIZE: usize = 40;
+// the empty memory reservation map, its terminating entry
+const FDT_RESERVE_MAP_SIZE: usize = 16;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_END: u32 = 9;
+
+// flattened device tree blob, nodes and properties in the order they are written
+struct FdtWriter {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+}
+
+impl FdtWriter {
+    fn new() -> Self {
+        Self {
+            structure: Vec::new(),
+            strings: Vec::new(),
+        }
+    }
+
+    fn begin_node(&mut self, name: &str) {
+        self.structure
+            .extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.pad();
+    }
+
+    fn end_node(&mut self) {
+        self.structure
+            .extend_from_slice(&FDT_END_NODE.to_be_bytes());
+    }
+
+    fn property(&mut self, name: &str, value: &[u8]) {
+        let name_offset = self.string_offset(name);
+        self.structure.extend_from_slice(&FDT_PROP.to_be_bytes());
+        self.structure
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.structure.extend_from_slice(&name_offset.to_be_bytes());
+        self.structure.extend_from_slice(value);
+        self.pad();
+    }
+
+    fn property_str(&mut self, name: &str, value: &str) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.property(name, &bytes);
+    }
+
+    fn property_u32(&mut self, name: &str, value: u32) {
+        self.property(name, &value.to_be_bytes());
+    }
+
+    fn property_u64(&mut self, name: &str, value: u64) {
+        self.property(name, &value.to_be_bytes());
+    }
+
+    fn string_offset(&mut self, name: &str) -> u32 {
+        let mut at = 0;
+        while let Some(len) = self.strings[at..].iter().position(|&b| b == 0) {
+            if &self.strings[at..at + len] == name.as_bytes() {
+                return at as u32;
+            }
+            at += len + 1;
+        }
+        let offset = self.strings.len();
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        offset as u32
+    }
+
+    fn pad(&mut self) {
+        let len = self.structure.len().next_multiple_of(4);
+        self.structure.resize(len, 0);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.structure.extend_from_slice(&FDT_END.to_be_bytes());
+        let reserve_map = FDT_HEADER_SIZE.next_multiple_of(8);
+        let structure = reserve_map + FDT_RESERVE_MAP_SIZE;
+        let strings = structure + self.structure.len();
+        let total = strings + self.strings.len();
+
+        let mut out = Vec::with_capacity(total);
+        for field in [
+            FDT_MAGIC,
+            total as u32,
+            structure as u32,
+            strings as u32,
+            reserve_map as u32,
+            FDT_VERSION,
+            FDT_LAST_COMPATIBLE_VERSION,
+            // boot_cpuid_phys
+            0,
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ] {
+            out.extend_from_slice(&field.to_be_bytes());
+        }
+        out.resize(structure, 0);
+        out.extend_from_slice(&self.structure);
+        out.extend_from_slice(&self.strings);
+        out
+    }
+}
+
+// CRC-32 of zlib, used by both U-Boot image formats
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |mut crc, &byte| {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+        crc
+    })
+}
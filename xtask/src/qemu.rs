@@ -0,0 +1,176 @@
+// xtask/src/qemu.rs
+//
+// The QEMU machine the loader is run on. The defaults are the virt board with GICv3 and
+// virtualization, U-Boot as the firmware and the boot disk as a virtio-blk device. They can
+// be changed in the `[qemu]` table of xtask.toml, and for one run with the `cargo xtask run`
+// flags:
+//
+//   --machine M        machine = "virt,gic-version=3,secure=off,virtualization=on"
+//   --cpu MODEL        cpu = "cortex-a55"
+//   --cpus N           cpus = 4
+//   --memory SIZE      memory = "4G"
+//   --firmware PATH    firmware = "bin/u-boot.bin"
+//   --disk PATH        disk = "bin/disk.img", boot this disk instead of building one
+//   --device DEV       devices = ["virtio-rng-device"], an extra `-device`, repeatable
+//   -d ITEMS           debug = "int,guest_errors", QEMU's `-d` log items
+//   -D FILE            debug_log = "bin/qemu.log", where the `-d` log goes
+//   --trace PATTERN    trace = ["virtio_*"], a `-trace` event pattern, repeatable
+//   --qemu-arg ARG     args = ["-netdev", "user,id=net0"], passed to QEMU as is, repeatable
+//   --no-gdb           gdb = false, do not listen for gdb on tcp::1234
+//
+// The console options are left to the caller.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::config;
+use crate::config::Value;
+
+pub const QEMU: &str = "qemu-system-aarch64";
+pub const GDB_PORT: u16 = 1234;
+
+#[derive(Debug, Clone)]
+pub struct Qemu {
+    pub machine: String,
+    pub cpu: String,
+    pub cpus: u32,
+    pub memory: String,
+    // U-Boot loads the loader from the boot disk, see `image`
+    pub firmware: PathBuf,
+    /// boot this disk instead of the one `cargo xtask image` builds
+    pub disk: Option<PathBuf>,
+    pub devices: Vec<String>,
+    pub debug: Option<String>,
+    pub debug_log: Option<PathBuf>,
+    pub trace: Vec<String>,
+    pub args: Vec<String>,
+    pub gdb: bool,
+}
+
+impl Default for Qemu {
+    fn default() -> Self {
+        Self {
+            machine: "virt,gic-version=3,secure=off,virtualization=on".to_string(),
+            cpu: "cortex-a55".to_string(),
+            cpus: 4,
+            memory: "4G".to_string(),
+            firmware: PathBuf::from("bin/u-boot.bin"),
+            disk: None,
+            devices: Vec::new(),
+            debug: None,
+            debug_log: None,
+            trace: Vec::new(),
+            args: Vec::new(),
+            gdb: true,
+        }
+    }
+}
+
+impl Qemu {
+    /// the defaults with the `[qemu]` table of xtask.toml applied
+    pub fn load() -> Result<Self, String> {
+        let mut qemu = Self::default();
+        for (key, value) in config::table("qemu")? {
+            qemu.set(&key, value)?;
+        }
+        Ok(qemu)
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match key {
+            "machine" => self.machine = value.into_string(key)?,
+            "cpu" => self.cpu = value.into_string(key)?,
+            "cpus" => {
+                self.cpus = u32::try_from(value.into_integer(key)?)
+                    .map_err(|_| format!("{}: cpus out of range", config::FILE_NAME))?
+            }
+            "memory" => self.memory = value.into_string(key)?,
+            "firmware" => self.firmware = PathBuf::from(value.into_string(key)?),
+            "disk" => self.disk = Some(PathBuf::from(value.into_string(key)?)),
+            "devices" => self.devices = value.into_array(key)?,
+            "debug" => self.debug = Some(value.into_string(key)?),
+            "debug_log" => self.debug_log = Some(PathBuf::from(value.into_string(key)?)),
+            "trace" => self.trace = value.into_array(key)?,
+            "args" => self.args = value.into_array(key)?,
+            "gdb" => self.gdb = value.into_boolean(key)?,
+            _ => {
+                return Err(format!(
+                    "{}: unknown key '{}' in [qemu]",
+                    config::FILE_NAME,
+                    key
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the QEMU flags of `args`. Returns the other arguments
+    pub fn parse_args(&mut self, args: &[String]) -> Result<Vec<String>, String> {
+        let mut rest = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", name))
+            };
+            match arg.as_str() {
+                "--machine" => self.machine = value(arg)?,
+                "--cpu" => self.cpu = value(arg)?,
+                "--cpus" => {
+                    self.cpus = value(arg)?
+                        .parse()
+                        .map_err(|_| "--cpus expects a number".to_string())?
+                }
+                "--memory" => self.memory = value(arg)?,
+                "--firmware" => self.firmware = PathBuf::from(value(arg)?),
+                "--disk" => self.disk = Some(PathBuf::from(value(arg)?)),
+                "--device" => self.devices.push(value(arg)?),
+                "-d" => self.debug = Some(value(arg)?),
+                "-D" => self.debug_log = Some(PathBuf::from(value(arg)?)),
+                "--trace" => self.trace.push(value(arg)?),
+                "--qemu-arg" => self.args.push(value(arg)?),
+                "--no-gdb" => self.gdb = false,
+                _ => rest.push(arg.clone()),
+            }
+        }
+        Ok(rest)
+    }
+
+    /// QEMU booting `disk`
+    pub fn command(&self, disk: &Path) -> Command {
+        let mut cmd = Command::new(QEMU);
+        cmd.arg("-M")
+            .arg(&self.machine)
+            .args(["-global", "virtio-mmio.force-legacy=off"])
+            .arg("-smp")
+            .arg(self.cpus.to_string())
+            .arg("-cpu")
+            .arg(&self.cpu)
+            .arg("-m")
+            .arg(&self.memory)
+            .arg("-bios")
+            .arg(&self.firmware)
+            .args(["-device", "virtio-blk-device,drive=disk"])
+            .arg("-drive")
+            .arg(format!(
+                "file={},format=raw,if=none,media=disk,id=disk",
+                disk.display()
+            ));
+        for device in &self.devices {
+            cmd.arg("-device").arg(device);
+        }
+        if let Some(debug) = &self.debug {
+            cmd.arg("-d").arg(debug);
+        }
+        if let Some(log) = &self.debug_log {
+            cmd.arg("-D").arg(log);
+        }
+        for pattern in &self.trace {
+            cmd.arg("-trace").arg(pattern);
+        }
+        cmd.args(&self.args);
+        cmd
+    }
+}
@@ -0,0 +1,258 @@
+// xtask/src/size.rs
+//
+// Size report of a release build: the size of each allocated section of the loader ELF and
+// of the symbols of each crate, read from the symbol table. The report of the previous
+// release build is kept in `bin/size-report.txt` and the new one is printed as a diff
+// against it, so a change that grows the loader past the budget of a board is noticed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const REPORT: &str = "bin/size-report.txt";
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+// symbols which are not mangled Rust paths, e.g. `_start` or `memcpy`
+const UNMANGLED: &str = "[unmangled]";
+
+/// sizes by entry name, e.g. `section .text` or `crate core`
+type Report = BTreeMap<String, u64>;
+
+struct Section<'a> {
+    name: &'a str,
+    kind: u32,
+    flags: u64,
+    offset: usize,
+    size: u64,
+    link: u32,
+}
+
+/// Print the size report of `elf` with the difference to the previous one and save it
+pub fn report(elf: &Path) -> Result<(), String> {
+    let data = fs::read(elf).map_err(|e| format!("failed to read {}: {}", elf.display(), e))?;
+    let report = analyze(&data)?;
+    let previous = fs::read_to_string(REPORT)
+        .map(|text| parse(&text))
+        .unwrap_or_default();
+
+    eprintln!("\n--- Size of {} ---", elf.display());
+    if previous.is_empty() {
+        eprintln!("(no previous report in {})", REPORT);
+    }
+    eprintln!("{:<40}{:>12}{:>12}", "", "bytes", "diff");
+    let mut names: Vec<&String> = report.keys().chain(previous.keys()).collect();
+    names.sort_by_key(|name| (group(name), name.as_str()));
+    names.dedup();
+    for name in names {
+        let new = report.get(name).copied().unwrap_or(0);
+        let old = previous.get(name).copied();
+        let diff = match old {
+            Some(old) if old == new => String::new(),
+            Some(old) => format!("{:+}", new as i64 - old as i64),
+            None if previous.is_empty() => String::new(),
+            None => "new".to_string(),
+        };
+        eprintln!("{:<40}{:>12}{:>12}", name, new, diff);
+    }
+
+    let text: String = report
+        .iter()
+        .map(|(name, size)| format!("{}\t{}\n", name, size))
+        .collect();
+    if let Some(dir) = Path::new(REPORT).parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    fs::write(REPORT, text).map_err(|e| format!("failed to write {}: {}", REPORT, e))
+}
+
+// sections first, then the crates, then the total
+fn group(name: &str) -> u8 {
+    match name.split_whitespace().next() {
+        Some("section") => 0,
+        Some("crate") => 1,
+        _ => 2,
+    }
+}
+
+fn parse(text: &str) -> Report {
+    text.lines()
+        .filter_map(|line| {
+            let (name, size) = line.rsplit_once('\t')?;
+            Some((name.to_string(), size.parse().ok()?))
+        })
+        .collect()
+}
+
+fn analyze(data: &[u8]) -> Result<Report, String> {
+    if data.get(..4) != Some(b"\x7fELF") || data.get(4) != Some(&2) || data.get(5) != Some(&1) {
+        return Err("not a little-endian ELF64 file".to_string());
+    }
+    let shoff = read_u64(data, 0x28)? as usize;
+    let shnum = read_u16(data, 0x3c)? as usize;
+    let shstrndx = read_u16(data, 0x3e)? as usize;
+    let headers = (0..shnum)
+        .map(|i| section_header(data, shoff + i * SECTION_HEADER_SIZE))
+        .collect::<Result<Vec<_>, _>>()?;
+    let names = headers
+        .get(shstrndx)
+        .ok_or("section name table out of range")?;
+    let sections = headers
+        .iter()
+        .map(|h| {
+            Ok(Section {
+                name: string(data, names.offset + h.name as usize)?,
+                kind: h.kind,
+                flags: h.flags,
+                offset: h.offset,
+                size: h.size,
+                link: h.link,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut report = Report::new();
+    let mut total = 0;
+    for section in sections.iter().filter(|s| s.flags & SHF_ALLOC != 0) {
+        *report
+            .entry(format!("section {}", section.name))
+            .or_default() += section.size;
+        // .bss takes memory but no space in the image
+        if section.kind != SHT_NOBITS {
+            total += section.size;
+        }
+    }
+    report.insert("total".to_string(), total);
+
+    let Some(symtab) = sections.iter().find(|s| s.kind == SHT_SYMTAB) else {
+        eprintln!("no symbol table, the per-crate sizes are skipped");
+        return Ok(report);
+    };
+    let strtab = sections
+        .get(symtab.link as usize)
+        .ok_or("symbol string table out of range")?;
+    for i in 0..symtab.size as usize / SYMBOL_SIZE {
+        let at = symtab.offset + i * SYMBOL_SIZE;
+        let info = *data.get(at + 4).ok_or("symbol table out of range")?;
+        let shndx = read_u16(data, at + 6)? as usize;
+        let size = read_u64(data, at + 16)?;
+        let in_image = sections
+            .get(shndx)
+            .is_some_and(|s| s.flags & SHF_ALLOC != 0);
+        if size == 0 || !in_image || !matches!(info & 0xf, STT_FUNC | STT_OBJECT) {
+            continue;
+        }
+        let name = string(data, strtab.offset + read_u32(data, at)? as usize)?;
+        *report
+            .entry(format!("crate {}", crate_name(name)))
+            .or_default() += size;
+    }
+    Ok(report)
+}
+
+// name offset, type, flags, offset, size and link of a section header
+struct Header {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: usize,
+    size: u64,
+    link: u32,
+}
+
+fn section_header(data: &[u8], at: usize) -> Result<Header, String> {
+    Ok(Header {
+        name: read_u32(data, at)?,
+        kind: read_u32(data, at + 4)?,
+        flags: read_u64(data, at + 8)?,
+        offset: read_u64(data, at + 24)? as usize,
+        size: read_u64(data, at + 32)?,
+        link: read_u32(data, at + 40)?,
+    })
+}
+
+// The crate of a mangled symbol, `[unmangled]` if it is not a Rust symbol.
+fn crate_name(symbol: &str) -> &str {
+    let name = if let Some(rest) = symbol.strip_prefix("_R") {
+        v0_crate(rest)
+    } else if let Some(rest) = symbol.strip_prefix("_ZN") {
+        legacy_crate(rest)
+    } else {
+        None
+    };
+    name.unwrap_or(UNMANGLED)
+}
+
+// v0 mangling, `_RNvNtCs1234_11aarch64_hal6logging4_log` is in `aarch64_hal`. The crate root
+// `C` is reached through the nested paths `N`, generic instances `I` and the impl paths of
+// `M` and `X`, so impls are counted to the crate they are written in.
+fn v0_crate(mut rest: &str) -> Option<&str> {
+    rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    loop {
+        let (tag, tail) = rest.split_at_checked(1)?;
+        rest = match tag {
+            // the namespace follows
+            "N" => tail.get(1..)?,
+            "I" => tail,
+            "M" | "X" => skip_disambiguator(tail),
+            "C" => break,
+            _ => return None,
+        };
+    }
+    let rest = skip_disambiguator(&rest[1..]);
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let len: usize = rest[..digits].parse().ok()?;
+    let rest = &rest[digits..];
+    // `_` separates the length from a name starting with a digit or `_`
+    let rest = rest.strip_prefix('_').unwrap_or(rest);
+    rest.get(..len)
+}
+
+// `s<base-62>_`
+fn skip_disambiguator(rest: &str) -> &str {
+    match rest.strip_prefix('s') {
+        Some(tail) => tail.split_once('_').map_or(rest, |(_, tail)| tail),
+        None => rest,
+    }
+}
+
+// legacy mangling, `_ZN4core3fmt5write17h..E` is in `core`. For trait impls,
+// `_ZN<len>_$LT$alloc..vec..Vec$LT$T$GT$$u20$as$u20$..` the crate of the self type is used,
+// `T$u20$as$u20$core..` falls back to the crate of the trait.
+fn legacy_crate(rest: &str) -> Option<&str> {
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let len: usize = rest[..digits].parse().ok()?;
+    let first = rest.get(digits..digits + len)?;
+    let path = first.trim_start_matches('_').trim_start_matches("$LT$");
+    let head = path.split("..").next().unwrap_or(path);
+    head.rsplit('$').next()
+}
+
+fn string(data: &[u8], at: usize) -> Result<&str, String> {
+    let bytes = data.get(at..).ok_or("string out of range")?;
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).map_err(|_| "string is not UTF-8".to_string())
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, String> {
+    read(data, at).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, String> {
+    read(data, at).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], at: usize) -> Result<u64, String> {
+    read(data, at).map(u64::from_le_bytes)
+}
+
+fn read<const N: usize>(data: &[u8], at: usize) -> Result<[u8; N], String> {
+    data.get(at..at + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("ELF truncated at {:#x}", at))
+}
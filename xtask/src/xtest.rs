@@ -0,0 +1,527 @@
+// xtask/src/xtest.rs
+//
+// `cargo xtask test` runs the entries of xtest.txt: host tests of the `std` crates and the
+// `uefi` integration tests, which boot in QEMU through their runner script.
+//
+// Every entry is built first, one after the other since cargo locks the build directory
+// anyway, then the tests run in parallel. Each one has a timeout, counted from the start of
+// the run so a slow build does not eat it, and its own log in bin/test-logs. The results are
+// written as JSON and JUnit XML for CI, the log of a failed test is printed to the console.
+
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+const PLAN_FILE: &str = "xtest.txt";
+const LOG_DIR: &str = "bin/test-logs";
+const JSON_RESULTS: &str = "bin/test-results.json";
+const JUNIT_RESULTS: &str = "bin/test-results.xml";
+const UEFI_TARGET: &str = "aarch64-unknown-uefi";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+enum Kind {
+    Std,
+    // the integration test and the runner script of it
+    Uefi { test: String, runner: PathBuf },
+}
+
+struct Entry {
+    package: String,
+    kind: Kind,
+    timeout: Duration,
+}
+
+impl Entry {
+    fn label(&self) -> String {
+        match &self.kind {
+            Kind::Std => format!("std:{}", self.package),
+            Kind::Uefi { test, .. } => format!("uefi:{}::{}", self.package, test),
+        }
+    }
+
+    fn log_path(&self) -> PathBuf {
+        let name: String = self
+            .label()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        Path::new(LOG_DIR).join(format!("{}.log", name))
+    }
+
+    fn command(&self, host_tuple: &str, cargo_args: &[String]) -> Command {
+        let mut cmd = Command::new("cargo");
+        cmd.arg("test").arg("-p").arg(&self.package);
+        match &self.kind {
+            Kind::Std => {
+                cmd.arg("--target").arg(host_tuple);
+            }
+            Kind::Uefi { test, runner } => {
+                cmd.arg("--target")
+                    .arg(UEFI_TARGET)
+                    .arg("--test")
+                    .arg(test)
+                    .env("CARGO_TARGET_AARCH64_UNKNOWN_UEFI_RUNNER", runner);
+            }
+        }
+        cmd.args(cargo_args);
+        cmd
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Passed,
+    Failed(i32),
+    BuildFailed(i32),
+    TimedOut,
+}
+
+struct Outcome {
+    label: String,
+    status: Status,
+    duration: Duration,
+    log: PathBuf,
+}
+
+struct Options {
+    parallel: usize,
+    timeout: Option<Duration>,
+    json: PathBuf,
+    junit: PathBuf,
+    cargo_args: Vec<String>,
+}
+
+/// `cargo xtask test [--parallel N] [--timeout SECS] [--json PATH] [--junit PATH] [cargo args...]`
+pub fn test(args: &[String]) -> Result<(), String> {
+    let options = parse_args(args)?;
+    let host_tuple = host_tuple()?;
+    eprintln!("Detected host target: {}", host_tuple);
+    let entries = read_plan(options.timeout)?;
+    fs::create_dir_all(LOG_DIR).map_err(|e| format!("{}: {}", LOG_DIR, e))?;
+
+    // build everything first, the build directory lock would serialize the builds anyway
+    let mut runnable = Vec::new();
+    let mut outcomes = Vec::new();
+    for entry in entries {
+        eprintln!("--- Building {} ---", entry.label());
+        let log = entry.log_path();
+        // the build and the run of the test share the log
+        let _ = fs::remove_file(&log);
+        let mut cmd = entry.command(&host_tuple, &options.cargo_args);
+        cmd.arg("--no-run");
+        let status = match spawn(cmd, &log) {
+            Ok(mut child) => child.wait().ok(),
+            Err(e) => {
+                let _ = fs::write(&log, format!("{}\n", e));
+                None
+            }
+        };
+        match status {
+            Some(status) if status.success() => runnable.push(entry),
+            status => {
+                let code = status.and_then(|s| s.code()).unwrap_or(1);
+                let outcome = Outcome {
+                    label: entry.label(),
+                    status: Status::BuildFailed(code),
+                    duration: Duration::ZERO,
+                    log,
+                };
+                report(&outcome);
+                outcomes.push(outcome);
+            }
+        }
+    }
+
+    eprintln!(
+        "\n--- Running {} test(s), {} at a time ---",
+        runnable.len(),
+        options.parallel
+    );
+    let queue = Mutex::new(runnable.into_iter());
+    let finished = Mutex::new(outcomes);
+    std::thread::scope(|scope| {
+        for _ in 0..options.parallel {
+            scope.spawn(|| {
+                loop {
+                    let Some(entry) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let outcome = run_entry(&entry, &host_tuple, &options.cargo_args);
+                    report(&outcome);
+                    finished.lock().unwrap().push(outcome);
+                }
+            });
+        }
+    });
+    let outcomes = finished.into_inner().unwrap();
+
+    write_json(&options.json, &outcomes)?;
+    write_junit(&options.junit, &outcomes)?;
+    summary(&outcomes, &options)
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        parallel: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        timeout: None,
+        json: PathBuf::from(JSON_RESULTS),
+        junit: PathBuf::from(JUNIT_RESULTS),
+        cargo_args: Vec::new(),
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "--parallel" => {
+                options.parallel = value(arg)?
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or("--parallel expects a positive number")?
+            }
+            "--timeout" => {
+                let secs = value(arg)?
+                    .parse()
+                    .map_err(|_| "--timeout expects seconds".to_string())?;
+                options.timeout = Some(Duration::from_secs(secs));
+            }
+            "--json" => options.json = PathBuf::from(value(arg)?),
+            "--junit" => options.junit = PathBuf::from(value(arg)?),
+            _ => options.cargo_args.push(arg.clone()),
+        }
+    }
+    Ok(options)
+}
+
+fn host_tuple() -> Result<String, String> {
+    let output = Command::new("rustc")
+        .arg("--print")
+        .arg("host-tuple")
+        .output()
+        .map_err(|e| format!("failed to run rustc --print host-tuple: {}", e))?;
+    String::from_utf8(output.stdout)
+        .map(|s| s.trim().to_string())
+        .map_err(|_| "invalid UTF-8 from rustc --print host-tuple".to_string())
+}
+
+// The entries of xtest.txt, `timeout` overrides the `timeout=SECS` of the entries.
+//   std  <package> [timeout=SECS]
+//   uefi <package> <testname> <testscript> [timeout=SECS]
+fn read_plan(timeout: Option<Duration>) -> Result<Vec<Entry>, String> {
+    let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../");
+    let plan_path = repo_root.join(PLAN_FILE);
+    let text = fs::read_to_string(&plan_path)
+        .map_err(|e| format!("require {}: {}", plan_path.display(), e))?;
+
+    let mut entries = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let mut entry_timeout = Duration::from_secs(DEFAULT_TIMEOUT_SECS);
+        if let Some(secs) = words.last().and_then(|w| w.strip_prefix("timeout=")) {
+            let secs = secs
+                .parse()
+                .map_err(|_| format!("{}:{}: timeout= expects seconds", PLAN_FILE, lineno + 1))?;
+            entry_timeout = Duration::from_secs(secs);
+            words.pop();
+        }
+        let kind = match words.as_slice() {
+            ["std", package] => (package, Kind::Std),
+            ["uefi", package, test, script] => (
+                package,
+                Kind::Uefi {
+                    test: test.to_string(),
+                    runner: repo_root.join(script),
+                },
+            ),
+            ["std", ..] => {
+                eprintln!("{}:{}: expected: std <package>", PLAN_FILE, lineno + 1);
+                continue;
+            }
+            ["uefi", ..] => {
+                eprintln!(
+                    "{}:{}: expected: uefi <package> <testname> <testscript>",
+                    PLAN_FILE,
+                    lineno + 1
+                );
+                continue;
+            }
+            [other, ..] => {
+                eprintln!(
+                    "{}:{}: unknown kind '{}'; expected 'std' or 'uefi'",
+                    PLAN_FILE,
+                    lineno + 1,
+                    other
+                );
+                continue;
+            }
+            [] => continue,
+        };
+        entries.push(Entry {
+            package: kind.0.to_string(),
+            kind: kind.1,
+            timeout: timeout.unwrap_or(entry_timeout),
+        });
+    }
+    Ok(entries)
+}
+
+fn run_entry(entry: &Entry, host_tuple: &str, cargo_args: &[String]) -> Outcome {
+    let log = entry.log_path();
+    let start = Instant::now();
+    let status = match spawn(entry.command(host_tuple, cargo_args), &log) {
+        Ok(child) => wait(child, entry.timeout),
+        Err(e) => {
+            let _ = fs::write(&log, format!("{}\n", e));
+            Status::Failed(1)
+        }
+    };
+    Outcome {
+        label: entry.label(),
+        status,
+        duration: start.elapsed(),
+        log,
+    }
+}
+
+// `cmd` with its output appended to `log`
+fn spawn(mut cmd: Command, log: &Path) -> Result<Child, String> {
+    use std::os::unix::process::CommandExt;
+    let stdout = File::options()
+        .create(true)
+        .append(true)
+        .open(log)
+        .map_err(|e| format!("{}: {}", log.display(), e))?;
+    let stderr = stdout
+        .try_clone()
+        .map_err(|e| format!("{}: {}", log.display(), e))?;
+    // a group of its own, so a timeout also stops the QEMU started by the runner
+    cmd.process_group(0)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    cmd.spawn()
+        .map_err(|e| format!("failed to spawn cargo test: {}", e))
+}
+
+fn wait(mut child: Child, timeout: Duration) -> Status {
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Status::Passed,
+            Ok(Some(status)) => return Status::Failed(status.code().unwrap_or(1)),
+            Ok(None) if start.elapsed() >= timeout => {
+                let _ = Command::new("kill")
+                    .arg("-KILL")
+                    .arg("--")
+                    .arg(format!("-{}", child.id()))
+                    .status();
+                let _ = child.wait();
+                return Status::TimedOut;
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(_) => return Status::Failed(1),
+        }
+    }
+}
+
+// one line per test, with the log of a failure
+fn report(outcome: &Outcome) {
+    let secs = outcome.duration.as_secs_f64();
+    let mut text = match outcome.status {
+        Status::Passed => format!("[ok]      {} ({:.1}s)\n", outcome.label, secs),
+        Status::TimedOut => format!("[timeout] {} ({:.1}s)\n", outcome.label, secs),
+        Status::Failed(code) => {
+            format!(
+                "[failed]  {} (code {}, {:.1}s)\n",
+                outcome.label, code, secs
+            )
+        }
+        Status::BuildFailed(code) => format!("[build]   {} (code {})\n", outcome.label, code),
+    };
+    if outcome.status != Status::Passed {
+        text += &format!("---- log of {} ----\n", outcome.label);
+        text += &fs::read_to_string(&outcome.log).unwrap_or_default();
+        text += &format!("---- end of {} ----\n", outcome.label);
+    }
+    // a single write, so the logs of tests finishing together do not interleave
+    eprint!("{}", text);
+}
+
+fn summary(outcomes: &[Outcome], options: &Options) -> Result<(), String> {
+    eprintln!("\n===== Test Summary =====");
+    let passed: Vec<&Outcome> = outcomes
+        .iter()
+        .filter(|o| o.status == Status::Passed)
+        .collect();
+    let failed: Vec<&Outcome> = outcomes
+        .iter()
+        .filter(|o| o.status != Status::Passed)
+        .collect();
+    eprintln!("Passed ({}):", passed.len());
+    for outcome in &passed {
+        eprintln!("  - {}", outcome.label);
+    }
+    eprintln!(
+        "Results: {}, {}",
+        options.json.display(),
+        options.junit.display()
+    );
+    if failed.is_empty() {
+        eprintln!("All tests passed (host + UEFI)");
+        return Ok(());
+    }
+    eprintln!("Failed ({}):", failed.len());
+    for outcome in &failed {
+        let reason = match outcome.status {
+            Status::Failed(code) => format!("code {}", code),
+            Status::BuildFailed(code) => format!("build failed, code {}", code),
+            Status::TimedOut => "timed out".to_string(),
+            Status::Passed => unreachable!(),
+        };
+        eprintln!(
+            "  - {} ({}), log: {}",
+            outcome.label,
+            reason,
+            outcome.log.display()
+        );
+    }
+    Err(format!("{} test(s) failed", failed.len()))
+}
+
+fn status_name(status: Status) -> &'static str {
+    match status {
+        Status::Passed => "passed",
+        Status::Failed(_) => "failed",
+        Status::BuildFailed(_) => "build-failed",
+        Status::TimedOut => "timeout",
+    }
+}
+
+fn write_json(path: &Path, outcomes: &[Outcome]) -> Result<(), String> {
+    let tests: Vec<String> = outcomes
+        .iter()
+        .map(|o| {
+            let code = match o.status {
+                Status::Failed(code) | Status::BuildFailed(code) => code.to_string(),
+                _ => "null".to_string(),
+            };
+            format!(
+                "    {{\"name\": \"{}\", \"status\": \"{}\", \"code\": {}, \"duration\": {:.3}, \"log\": \"{}\"}}",
+                json_escape(&o.label),
+                status_name(o.status),
+                code,
+                o.duration.as_secs_f64(),
+                json_escape(&o.log.to_string_lossy())
+            )
+        })
+        .collect();
+    let passed = outcomes
+        .iter()
+        .filter(|o| o.status == Status::Passed)
+        .count();
+    let text = format!(
+        "{{\n  \"passed\": {},\n  \"failed\": {},\n  \"tests\": [\n{}\n  ]\n}}\n",
+        passed,
+        outcomes.len() - passed,
+        tests.join(",\n")
+    );
+    write_results(path, &text)
+}
+
+fn write_junit(path: &Path, outcomes: &[Outcome]) -> Result<(), String> {
+    let failures = outcomes
+        .iter()
+        .filter(|o| o.status != Status::Passed)
+        .count();
+    let total: f64 = outcomes.iter().map(|o| o.duration.as_secs_f64()).sum();
+    let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    text += &format!(
+        "<testsuites>\n  <testsuite name=\"xtask\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        outcomes.len(),
+        failures,
+        total
+    );
+    for outcome in outcomes {
+        let (classname, name) = outcome
+            .label
+            .split_once(':')
+            .unwrap_or(("", &outcome.label));
+        text += &format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            xml_escape(classname),
+            xml_escape(name),
+            outcome.duration.as_secs_f64()
+        );
+        if outcome.status == Status::Passed {
+            text += "/>\n";
+            continue;
+        }
+        let message = match outcome.status {
+            Status::Failed(code) => format!("exit code {}", code),
+            Status::BuildFailed(code) => format!("build failed with exit code {}", code),
+            _ => "timed out".to_string(),
+        };
+        let log = fs::read_to_string(&outcome.log).unwrap_or_default();
+        text += &format!(
+            ">\n      <failure message=\"{}\"/>\n      <system-out>{}</system-out>\n    </testcase>\n",
+            xml_escape(&message),
+            xml_escape(&log)
+        );
+    }
+    text += "  </testsuite>\n</testsuites>\n";
+    write_results(path, &text)
+}
+
+fn write_results(path: &Path, text: &str) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    fs::write(path, text).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// also drops the control characters XML 1.0 does not allow, test logs have ANSI colors
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}